@@ -0,0 +1,234 @@
+use crate::{Error, Result};
+
+/// The first sample entry of a trak inside a raw moov atom.
+///
+/// The mp4 crate skips the codec configuration of some sample entries (hvcC)
+/// or the entries altogether (hvc1), so they are read from the raw atom instead.
+pub(crate) struct SampleEntry<'a> {
+	pub kind: [u8; 4],
+	body: &'a [u8],
+}
+
+impl<'a> SampleEntry<'a> {
+	/// find the sample entry of the trak with `track_id` in `moov`, the full atom or the init segment holding it
+	pub fn track(moov: &'a [u8], track_id: u32) -> Option<Self> {
+		let (_, moov) = Boxes::new(moov).find(|(kind, _)| kind == b"moov")?;
+		let (_, trak) = Boxes::new(moov)
+			.filter(|(kind, _)| kind == b"trak")
+			.find(|(_, trak)| trak_id(trak) == Some(track_id))?;
+
+		Self::from_trak(trak)
+	}
+
+	fn from_trak(trak: &'a [u8]) -> Option<Self> {
+		let stsd = find(trak, &[b"mdia", b"minf", b"stbl", b"stsd"])?;
+
+		// skip version, flags and entry count
+		let (kind, body) = Boxes::new(stsd.get(8..)?).next()?;
+
+		Some(Self { kind, body })
+	}
+
+	pub fn is_hevc(&self) -> bool {
+		&self.kind == b"hev1" || &self.kind == b"hvc1"
+	}
+
+	pub fn is_av1(&self) -> bool {
+		&self.kind == b"av01"
+	}
+
+	/// encoded width and height of a visual sample entry
+	pub fn dimensions(&self) -> Option<(u16, u16)> {
+		let width = u16::from_be_bytes(self.body.get(24..26)?.try_into().ok()?);
+		let height = u16::from_be_bytes(self.body.get(26..28)?.try_into().ok()?);
+		Some((width, height))
+	}
+
+	/// child box of a visual sample entry, ex. the codec configuration
+	pub fn child(&self, kind: &[u8; 4]) -> Option<&'a [u8]> {
+		Boxes::new(self.body.get(VISUAL_SAMPLE_ENTRY_SIZE..)?)
+			.find(|(k, _)| k == kind)
+			.map(|(_, body)| body)
+	}
+
+	/// RFC 6381 codec string of a hev1/hvc1 sample entry
+	pub fn hevc_codec(&self) -> Result<String> {
+		let Some(hvcc) = self.child(b"hvcC") else {
+			return Err(Error::InvalidInitSegment("missing hvcC box".to_string()));
+		};
+		hevc_codec(&String::from_utf8_lossy(&self.kind), hvcc)
+	}
+
+	/// RFC 6381 codec string of an av01 sample entry
+	pub fn av1_codec(&self) -> Result<String> {
+		let Some(av1c) = self.child(b"av1C") else {
+			return Err(Error::InvalidInitSegment("missing av1C box".to_string()));
+		};
+		av1_codec(av1c)
+	}
+}
+
+/// size of the fields of a VisualSampleEntry before its child boxes
+const VISUAL_SAMPLE_ENTRY_SIZE: usize = 78;
+
+/// build the RFC 6381 codec string from a hvcC body
+///
+/// Source: ISO/IEC 14496-15 Annex E.3
+pub(crate) fn hevc_codec(kind: &str, hvcc: &[u8]) -> Result<String> {
+	if hvcc.len() < 13 {
+		return Err(Error::InvalidInitSegment(format!(
+			"hvcC box too short, expected at least 13 bytes, got {}",
+			hvcc.len()
+		)));
+	}
+
+	if hvcc[0] != 1 {
+		return Err(Error::InvalidInitSegment(format!(
+			"unsupported hvcC configuration version {}",
+			hvcc[0]
+		)));
+	}
+
+	let profile_space = ["", "A", "B", "C"][(hvcc[1] >> 6) as usize];
+	let tier = match (hvcc[1] >> 5) & 0x1 {
+		0 => 'L',
+		_ => 'H',
+	};
+	let profile = hvcc[1] & 0x1f;
+	// the compatibility flags are signaled in reverse bit order
+	let compatibility = u32::from_be_bytes([hvcc[2], hvcc[3], hvcc[4], hvcc[5]]).reverse_bits();
+	let constraints = &hvcc[6..12];
+	let level = hvcc[12];
+
+	let mut codec = format!("{kind}.{profile_space}{profile}.{compatibility:X}.{tier}{level}");
+
+	// trailing zero bytes of the constraint flags are omitted
+	let len = constraints.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+	for byte in &constraints[..len] {
+		codec += &format!(".{byte:X}");
+	}
+
+	Ok(codec)
+}
+
+/// build the `av01.P.LLT.DD` codec string from an av1C body
+///
+/// Source: [AV1 Codec ISO Media File Format Binding](https://aomediacodec.github.io/av1-isobmff/#codecsparam)
+pub(crate) fn av1_codec(av1c: &[u8]) -> Result<String> {
+	if av1c.len() < 4 {
+		return Err(Error::InvalidInitSegment(format!(
+			"av1C box too short, expected at least 4 bytes, got {}",
+			av1c.len()
+		)));
+	}
+
+	// marker bit and version 1
+	if av1c[0] != 0x81 {
+		return Err(Error::InvalidInitSegment(format!(
+			"unsupported av1C marker/version byte {:#04x}",
+			av1c[0]
+		)));
+	}
+
+	let profile = av1c[1] >> 5;
+	let level = av1c[1] & 0x1f;
+	let tier = match av1c[2] >> 7 {
+		0 => 'M',
+		_ => 'H',
+	};
+	let bit_depth = match ((av1c[2] >> 6) & 0x1, (av1c[2] >> 5) & 0x1) {
+		(0, _) => 8,
+		(_, 0) => 10,
+		_ => 12,
+	};
+
+	Ok(format!("av01.{profile}.{level:02}{tier}.{bit_depth:02}"))
+}
+
+/// track_id from the tkhd of a trak body
+fn trak_id(trak: &[u8]) -> Option<u32> {
+	let tkhd = find(trak, &[b"tkhd"])?;
+
+	// creation and modification time are 64 bit in version 1
+	let offset = match tkhd.first()? {
+		1 => 20,
+		_ => 12,
+	};
+	Some(u32::from_be_bytes(tkhd.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+/// descend into nested boxes following `path`, returning the body of the last one
+fn find<'a>(buf: &'a [u8], path: &[&[u8; 4]]) -> Option<&'a [u8]> {
+	let mut buf = buf;
+	for kind in path {
+		let (_, body) = Boxes::new(buf).find(|(k, _)| k == *kind)?;
+		buf = body;
+	}
+	Some(buf)
+}
+
+/// iterator over the (type, body) of consecutive boxes
+struct Boxes<'a> {
+	buf: &'a [u8],
+}
+
+impl<'a> Boxes<'a> {
+	fn new(buf: &'a [u8]) -> Self {
+		Self { buf }
+	}
+}
+
+impl<'a> Iterator for Boxes<'a> {
+	type Item = ([u8; 4], &'a [u8]);
+
+	fn next(&mut self) -> Option<Self::Item> {
+		let size = u32::from_be_bytes(self.buf.get(0..4)?.try_into().ok()?) as usize;
+		let kind: [u8; 4] = self.buf.get(4..8)?.try_into().ok()?;
+
+		let (header, size) = match size {
+			// runs until the end of the buffer
+			0 => (8, self.buf.len()),
+			// extended size
+			1 => (16, u64::from_be_bytes(self.buf.get(8..16)?.try_into().ok()?) as usize),
+			size => (8, size),
+		};
+
+		if size < header {
+			return None;
+		}
+
+		let body = self.buf.get(header..size)?;
+		self.buf = &self.buf[size..];
+
+		Some((kind, body))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_hevc_codec() {
+		let hvcc = [1, 0x01, 0x60, 0, 0, 0, 0xb0, 0, 0, 0, 0, 0, 93];
+		assert_eq!(hevc_codec("hev1", &hvcc).unwrap(), "hev1.1.6.L93.B0");
+
+		// main 10, high tier, no constraint flags
+		let hvcc = [1, 0x22, 0x20, 0, 0, 0, 0, 0, 0, 0, 0, 0, 150];
+		assert_eq!(hevc_codec("hvc1", &hvcc).unwrap(), "hvc1.2.4.H150");
+
+		assert!(hevc_codec("hev1", &hvcc[..8]).is_err());
+		assert!(hevc_codec("hev1", &[0; 13]).is_err());
+	}
+
+	#[test]
+	fn test_av1_codec() {
+		// main profile, level 4.0, 8 bit
+		assert_eq!(av1_codec(&[0x81, 0x08, 0x0c, 0x00]).unwrap(), "av01.0.08M.08");
+		// high profile, level 5.1, high tier, 10 bit
+		assert_eq!(av1_codec(&[0x81, 0x2d, 0xcc, 0x00]).unwrap(), "av01.1.13H.10");
+
+		assert!(av1_codec(&[0x81, 0x08]).is_err());
+		assert!(av1_codec(&[0x01, 0x08, 0x0c, 0x00]).is_err());
+	}
+}
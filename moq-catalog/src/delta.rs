@@ -0,0 +1,253 @@
+use crate::{Error, MoqCatalog, Result};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Delta Update
+///
+/// A delta update is a JSON Patch (RFC 6902) which, applied to the previous
+/// catalog, results in the current catalog. Tracks are matched by their name,
+/// new tracks are appended, removed tracks deleted and changed tracks replaced.
+///
+/// Source: [draft-ietf-moq-catalogformat-01](https://www.ietf.org/archive/id/draft-ietf-moq-catalogformat-01.html#name-delta-updates)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct CatalogDelta(Vec<Operation>);
+
+/// a single JSON Patch operation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Operation {
+	Add { path: String, value: Value },
+	Remove { path: String },
+	Replace { path: String, value: Value },
+}
+
+impl CatalogDelta {
+	/// operations turning `previous` into `current`
+	pub(crate) fn new(previous: &MoqCatalog, current: &MoqCatalog) -> Result<Self> {
+		let (Value::Object(previous), Value::Object(current)) = (to_value(previous)?, to_value(current)?) else {
+			return Err(Error::Delta("catalog is not an object".to_string()));
+		};
+
+		let mut ops = Vec::new();
+
+		for (key, value) in &current {
+			let path = format!("/{}", escape(key));
+			match previous.get(key) {
+				Some(Value::Array(before)) if key == "tracks" => {
+					let Value::Array(after) = value else {
+						ops.push(Operation::Replace {
+							path,
+							value: value.clone(),
+						});
+						continue;
+					};
+					diff_tracks(&path, before, after, &mut ops);
+				}
+				Some(before) if before != value => ops.push(Operation::Replace {
+					path,
+					value: value.clone(),
+				}),
+				Some(_) => (),
+				None => ops.push(Operation::Add {
+					path,
+					value: value.clone(),
+				}),
+			}
+		}
+
+		for key in previous.keys().filter(|key| !current.contains_key(*key)) {
+			ops.push(Operation::Remove {
+				path: format!("/{}", escape(key)),
+			});
+		}
+
+		Ok(Self(ops))
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.0.is_empty()
+	}
+
+	pub fn operations(&self) -> &[Operation] {
+		&self.0
+	}
+
+	/// apply the delta to `catalog`, returning the updated catalog
+	pub fn apply(&self, catalog: &MoqCatalog) -> Result<MoqCatalog> {
+		let mut value = to_value(catalog)?;
+
+		for op in &self.0 {
+			apply(&mut value, op)?;
+		}
+
+		match serde_json::from_value(value) {
+			Ok(v) => Ok(v),
+			Err(err) => Err(Error::Delta(err.to_string())),
+		}
+	}
+
+	pub fn encode(&self) -> Result<Vec<u8>> {
+		match serde_json::to_vec(&self) {
+			Ok(v) => Ok(v),
+			Err(err) => {
+				log::error!("encode [CatalogDelta]: {}", err);
+				Err(Error::External {
+					krayt: "serde_json".to_string(),
+					error: err.to_string(),
+				})
+			}
+		}
+	}
+
+	pub fn decode(buf: &[u8]) -> Result<Self> {
+		match serde_json::from_slice(buf) {
+			Ok(v) => Ok(v),
+			Err(err) => {
+				log::error!("decode [CatalogDelta]: {}", err);
+				Err(Error::External {
+					krayt: "serde_json".to_string(),
+					error: err.to_string(),
+				})
+			}
+		}
+	}
+}
+
+fn to_value(catalog: &MoqCatalog) -> Result<Value> {
+	match serde_json::to_value(catalog) {
+		Ok(v) => Ok(v),
+		Err(err) => Err(Error::External {
+			krayt: "serde_json".to_string(),
+			error: err.to_string(),
+		}),
+	}
+}
+
+/// removals first, from the back so the indices stay valid, then replacements and additions
+fn diff_tracks(path: &str, before: &[Value], after: &[Value], ops: &mut Vec<Operation>) {
+	let name = |track: &Value| track.get("name").cloned();
+
+	let mut remaining = before.to_vec();
+	for (i, track) in before.iter().enumerate().rev() {
+		if !after.iter().any(|t| name(t) == name(track)) {
+			ops.push(Operation::Remove {
+				path: format!("{path}/{i}"),
+			});
+			remaining.remove(i);
+		}
+	}
+
+	for track in after {
+		match remaining.iter().position(|t| name(t) == name(track)) {
+			Some(i) if &remaining[i] != track => ops.push(Operation::Replace {
+				path: format!("{path}/{i}"),
+				value: track.clone(),
+			}),
+			Some(_) => (),
+			None => ops.push(Operation::Add {
+				path: format!("{path}/-"),
+				value: track.clone(),
+			}),
+		}
+	}
+}
+
+fn apply(value: &mut Value, op: &Operation) -> Result<()> {
+	let path = match op {
+		Operation::Add { path, .. } | Operation::Remove { path } | Operation::Replace { path, .. } => path,
+	};
+
+	let Some((parent, token)) = path.rsplit_once('/') else {
+		return Err(Error::Delta(format!("invalid path {path}")));
+	};
+	let token = unescape(token);
+
+	let Some(parent) = value.pointer_mut(parent) else {
+		return Err(Error::Delta(format!("missing parent of {path}")));
+	};
+
+	match (parent, op) {
+		(Value::Object(map), Operation::Add { value, .. }) => {
+			map.insert(token, value.clone());
+		}
+		(Value::Object(map), Operation::Replace { value, .. }) if map.contains_key(&token) => {
+			map.insert(token, value.clone());
+		}
+		(Value::Object(map), Operation::Remove { .. }) if map.contains_key(&token) => {
+			map.remove(&token);
+		}
+		(Value::Array(array), Operation::Add { value, .. }) if token == "-" => array.push(value.clone()),
+		(Value::Array(array), op) => {
+			let Some(i) = token.parse::<usize>().ok().filter(|i| *i <= array.len()) else {
+				return Err(Error::Delta(format!("invalid index in {path}")));
+			};
+			match op {
+				Operation::Add { value, .. } => array.insert(i, value.clone()),
+				Operation::Remove { .. } if i < array.len() => {
+					array.remove(i);
+				}
+				Operation::Replace { value, .. } if i < array.len() => array[i] = value.clone(),
+				_ => return Err(Error::Delta(format!("index out of bounds in {path}"))),
+			}
+		}
+		_ => return Err(Error::Delta(format!("cannot apply to {path}"))),
+	}
+
+	Ok(())
+}
+
+/// JSON Pointer escaping, Source: RFC 6901 Section 4
+fn escape(token: &str) -> String {
+	token.replace('~', "~0").replace('/', "~1")
+}
+
+fn unescape(token: &str) -> String {
+	token.replace("~1", "/").replace("~0", "~")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{Packaging, SelectionParams, Track};
+
+	fn track(name: &str, bitrate: u64) -> Track {
+		let mut params = SelectionParams::new();
+		params.set_codec("avc1.64001F").set_bitrate(bitrate);
+
+		let mut track = Track::new(name, Packaging::CMAF);
+		track.set_selection_params(params);
+		track
+	}
+
+	#[test]
+	fn test_delta() {
+		let mut previous = MoqCatalog::new();
+		previous.enable_delta_updates();
+		previous.insert_track(track("a", 1)).unwrap();
+		previous.insert_track(track("b", 2)).unwrap();
+		previous.insert_track(track("c", 3)).unwrap();
+
+		let mut current = MoqCatalog::new();
+		current.enable_delta_updates();
+		current.set_tracks(&[track("c", 4), track("d", 5)]).unwrap();
+
+		let delta = current.diff(&previous).unwrap();
+		assert_eq!(
+			serde_json::to_value(&delta).unwrap(),
+			serde_json::json!([
+				{ "op": "remove", "path": "/tracks/1" },
+				{ "op": "remove", "path": "/tracks/0" },
+				{ "op": "replace", "path": "/tracks/0", "value": serde_json::to_value(track("c", 4)).unwrap() },
+				{ "op": "add", "path": "/tracks/-", "value": serde_json::to_value(track("d", 5)).unwrap() },
+			])
+		);
+
+		let decoded = CatalogDelta::decode(&delta.encode().unwrap()).unwrap();
+		let updated = decoded.apply(&previous).unwrap();
+		assert_eq!(updated.encode().unwrap(), current.encode().unwrap());
+
+		assert!(current.diff(&current).unwrap().is_empty());
+	}
+}
@@ -1,5 +1,7 @@
 use thiserror::Error;
 
+use crate::VERSION;
+
 #[derive(Error, Debug)]
 pub enum Error {
 	#[error("crate={krayt} err={error}")]
@@ -10,4 +12,37 @@ pub enum Error {
 
 	#[error("cannot add catalog, because tracks are already present")]
 	TracksAlreadySet,
+
+	#[error("cannot apply delta update: {0}")]
+	Delta(String),
+
+	#[error("unsupported catalog version {0}, expected {VERSION}")]
+	UnknownVersion(String),
+
+	#[error("catalog contains both tracks and catalogs")]
+	BothTracksAndCatalogs,
+
+	#[error("catalog {0} is listed more than once")]
+	DuplicateCatalog(String),
+
+	#[error("empty selectionParams in {0}")]
+	EmptySelectionParams(String),
+
+	#[error("unknown packaging {0}, expected cmaf or loc")]
+	UnknownPackaging(String),
+
+	#[error("unknown catalog format {0}, expected json, json-compact or cbor")]
+	UnknownFormat(String),
+
+	#[error("inconsistent selectionParams in {0}: {1}")]
+	InconsistentSelectionParams(String, String),
+
+	#[error("invalid initData in {0}: {1}")]
+	InvalidInitData(String, String),
+
+	#[error("invalid init segment: {0}")]
+	InvalidInitSegment(String),
+
+	#[error("unsupported codec: {0}")]
+	UnsupportedCodec(String),
 }
@@ -0,0 +1,217 @@
+use std::io::{Cursor, Seek, SeekFrom};
+
+use mp4::ReadBox;
+
+use crate::codec::SampleEntry;
+use crate::{Error, Packaging, Result, SelectionParams, Track};
+
+/// sampling rates supported by AAC by their index, Source: ISO/IEC 14496-3 Table 1.18
+pub const AAC_SAMPLING_RATES: [u32; 13] = [
+	96000, 88200, 64000, 48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025, 8000, 7350,
+];
+
+impl Track {
+	/// a CMAF track described by the single trak of the ftyp+moov `init`, which becomes its initData
+	///
+	/// The codec, mime type and the coded and display size or the sample rate and channels are filled in,
+	/// the framerate if the stts holds samples and the bitrate if the esds declares one.
+	pub fn from_init_segment(name: &str, init: &[u8]) -> Result<Self> {
+		let moov = read_moov(init)?;
+		let [trak] = moov.traks.as_slice() else {
+			return Err(Error::InvalidInitSegment(format!(
+				"expected a single trak, found {}",
+				moov.traks.len()
+			)));
+		};
+
+		from_trak(name, init, &moov, trak.tkhd.track_id)
+	}
+
+	/// like [Self::from_init_segment], for the trak with `track_id` of an init segment with several traks
+	pub fn from_init_segment_track(name: &str, init: &[u8], track_id: u32) -> Result<Self> {
+		let moov = read_moov(init)?;
+		from_trak(name, init, &moov, track_id)
+	}
+}
+
+fn from_trak(name: &str, init: &[u8], moov: &mp4::MoovBox, track_id: u32) -> Result<Track> {
+	let Some(trak) = moov.traks.iter().find(|trak| trak.tkhd.track_id == track_id) else {
+		return Err(Error::InvalidInitSegment(format!("missing trak {track_id}")));
+	};
+
+	let mut params = SelectionParams::new();
+	let stsd = &trak.mdia.minf.stbl.stsd;
+	// the tkhd holds 16.16 fixed point values, 0 if not set
+	let display = (trak.tkhd.width.value(), trak.tkhd.height.value());
+
+	let coded = if let Some(avc1) = &stsd.avc1 {
+		let avcc = &avc1.avcc;
+		let codec = format!(
+			"avc1.{:02x}{:02x}{:02x}",
+			avcc.avc_profile_indication, avcc.profile_compatibility, avcc.avc_level_indication
+		);
+		params.set_codec(&codec);
+		Some((avc1.width, avc1.height))
+	} else if let Some(entry) = SampleEntry::track(init, track_id).filter(|e| e.is_hevc()) {
+		// hvc1 entries are skipped by the mp4 crate, so always read them from the raw moov
+		params.set_codec(&entry.hevc_codec()?);
+		Some(dimensions(&entry, "HEVC")?)
+	} else if let Some(entry) = SampleEntry::track(init, track_id).filter(|e| e.is_av1()) {
+		// av01 entries are skipped by the mp4 crate
+		params.set_codec(&entry.av1_codec()?);
+		Some(dimensions(&entry, "AV1")?)
+	} else if let Some(vp09) = &stsd.vp09 {
+		// https://www.webmproject.org/vp9/mp4/#codecs-parameter-string
+		let vpcc = &vp09.vpcc;
+		params.set_codec(&format!(
+			"vp09.{:02}.{:02}.{:02}",
+			vpcc.profile, vpcc.level, vpcc.bit_depth
+		));
+		Some((vp09.width, vp09.height))
+	} else if let Some(mp4a) = &stsd.mp4a {
+		let Some(esds) = &mp4a.esds else {
+			return Err(Error::InvalidInitSegment("missing esds box for mp4a".to_string()));
+		};
+		let desc = &esds.es_desc.dec_config;
+
+		// the sample entry only holds the integer part of a 16.16 value, the AudioSpecificConfig is authoritative
+		let sample_rate = AAC_SAMPLING_RATES
+			.get(desc.dec_specific.freq_index as usize)
+			.map_or(mp4a.samplerate.value() as u32, |rate| *rate);
+
+		// channel configuration 0 is signaled in the stream, fall back to the sample entry
+		let channels = match desc.dec_specific.chan_conf {
+			0 => u8::try_from(mp4a.channelcount).unwrap_or(u8::MAX),
+			c => c,
+		};
+
+		params
+			.set_codec(&format!(
+				"mp4a.{:02x}.{}",
+				desc.object_type_indication, desc.dec_specific.profile
+			))
+			.set_sample_rate(sample_rate)
+			.set_channel_count(channels);
+
+		let bitrate = desc.max_bitrate.max(desc.avg_bitrate);
+		if bitrate > 0 {
+			params.set_bitrate(bitrate as u64);
+		}
+		None
+	} else {
+		return Err(Error::UnsupportedCodec(format!(
+			"no supported sample entry in trak {track_id}"
+		)));
+	};
+
+	match coded {
+		Some((width, height)) => {
+			params.set_width(width).set_height(height).set_mime_type("video/mp4")?;
+			if display != (0, 0) && display != (width, height) {
+				params.set_display_width(display.0).set_display_height(display.1);
+			}
+			if let Some(framerate) = stts_framerate(moov, track_id) {
+				params.set_framerate(framerate);
+			}
+		}
+		None => {
+			params.set_mime_type("audio/mp4")?;
+		}
+	}
+
+	let mut track = Track::new(name, Packaging::CMAF);
+	track.set_selection_params(params).set_init_data(init);
+	Ok(track)
+}
+
+/// encoded width and height of a sample entry the mp4 crate skips
+fn dimensions(entry: &SampleEntry, codec: &str) -> Result<(u16, u16)> {
+	entry
+		.dimensions()
+		.ok_or_else(|| Error::InvalidInitSegment(format!("missing {codec} dimensions")))
+}
+
+/// average framerate from the sample durations in the stts, empty for fragmented files
+fn stts_framerate(moov: &mp4::MoovBox, track_id: u32) -> Option<u64> {
+	let trak = moov.traks.iter().find(|trak| trak.tkhd.track_id == track_id)?;
+	let entries = &trak.mdia.minf.stbl.stts.entries;
+	let samples: u64 = entries.iter().map(|e| e.sample_count as u64).sum();
+	let duration: u64 = entries
+		.iter()
+		.map(|e| e.sample_count as u64 * e.sample_delta as u64)
+		.sum();
+	if duration == 0 {
+		return None;
+	}
+
+	let timescale = trak.mdia.mdhd.timescale as f64;
+	Some((samples as f64 * timescale / duration as f64).round() as u64)
+}
+
+/// the parsed moov of `init`, skipping the other top level boxes
+fn read_moov(init: &[u8]) -> Result<mp4::MoovBox> {
+	let invalid = |e: mp4::Error| Error::InvalidInitSegment(e.to_string());
+
+	let mut reader = Cursor::new(init);
+	while (reader.position() as usize) < init.len() {
+		let start = reader.position();
+		let header = mp4::BoxHeader::read(&mut reader).map_err(invalid)?;
+		if header.name == mp4::BoxType::MoovBox {
+			return mp4::MoovBox::read_box(&mut reader, header.size).map_err(invalid);
+		}
+		if header.size == 0 {
+			break;
+		}
+
+		reader
+			.seek(SeekFrom::Start(start + header.size))
+			.map_err(|e| Error::InvalidInitSegment(e.to_string()))?;
+	}
+
+	Err(Error::InvalidInitSegment("missing moov".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_avc() {
+		let track = Track::from_init_segment("video", include_bytes!("../tests/fixtures/avc_init.m4s")).unwrap();
+		let params = track.selection_params().unwrap();
+		assert_eq!(params.codec(), Some("avc1.64001f"));
+		assert_eq!(params.mime_type(), Some("video/mp4"));
+		assert!(params.width().is_some() && params.height().is_some());
+		assert!(params.sample_rate().is_none());
+		assert_eq!(
+			track.init_data().unwrap().unwrap(),
+			include_bytes!("../tests/fixtures/avc_init.m4s")
+		);
+	}
+
+	#[test]
+	fn test_aac() {
+		let track = Track::from_init_segment("audio", include_bytes!("../tests/fixtures/aac_init.m4s")).unwrap();
+		let params = track.selection_params().unwrap();
+		assert_eq!(params.codec(), Some("mp4a.40.2"));
+		assert_eq!(params.mime_type(), Some("audio/mp4"));
+		assert!(params
+			.sample_rate()
+			.is_some_and(|rate| AAC_SAMPLING_RATES.contains(&rate)));
+		assert!(params.channel_count().is_some());
+		assert!(params.width().is_none());
+	}
+
+	#[test]
+	fn test_invalid() {
+		let init = include_bytes!("../tests/fixtures/avc_init.m4s");
+		assert!(matches!(
+			Track::from_init_segment("video", &init[..init.len() / 2]),
+			Err(Error::InvalidInitSegment(_))
+		));
+		assert!(matches!(
+			Track::from_init_segment_track("video", init, 7),
+			Err(Error::InvalidInitSegment(_))
+		));
+	}
+}
@@ -5,6 +5,10 @@ use std::str::FromStr;
 use base64::prelude::*;
 use serde::{Deserialize, Serialize};
 
+// mixin replaces the name of a mixin anywhere in its impls, ex. the R of `Result`,
+// so the impls of R and S return `Chained`, and S writes `S` and `Option::from` for `Self` and `Some`
+type Chained<'a, C> = std::result::Result<&'a mut C, Error>;
+
 #[mixin::declare]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct R {
@@ -67,7 +71,7 @@ impl R {
 		self.common_track_fields.as_mut()
 	}
 
-	pub fn set_tracks(&mut self, tracks: &[Track]) -> Result<&mut Self, Error> {
+	pub fn set_tracks(&mut self, tracks: &[Track]) -> Chained<'_, Self> {
 		if self.catalogs.is_some() {
 			return Err(Error::CatalogsAlreadySet);
 		}
@@ -76,7 +80,7 @@ impl R {
 		Ok(self)
 	}
 
-	pub fn insert_track(&mut self, track: Track) -> Result<&mut Self, Error> {
+	pub fn insert_track(&mut self, track: Track) -> Chained<'_, Self> {
 		if self.catalogs.is_some() {
 			return Err(Error::CatalogsAlreadySet);
 		}
@@ -88,7 +92,7 @@ impl R {
 		Ok(self)
 	}
 
-	pub fn set_catalog(&mut self, catalog: &[Catalog]) -> Result<&mut Self, Error> {
+	pub fn set_catalog(&mut self, catalog: &[Catalog]) -> Chained<'_, Self> {
 		if self.tracks.is_some() {
 			return Err(Error::TracksAlreadySet);
 		}
@@ -97,7 +101,7 @@ impl R {
 		Ok(self)
 	}
 
-	pub fn insert_catalog(&mut self, catalog: Catalog) -> Result<&mut Self, Error> {
+	pub fn insert_catalog(&mut self, catalog: Catalog) -> Chained<'_, Self> {
 		if self.tracks.is_some() {
 			return Err(Error::TracksAlreadySet);
 		}
@@ -110,52 +114,40 @@ impl R {
 	}
 
 	pub fn get_track(&self, name: &str) -> Option<Track> {
-		self.tracks?
-			.iter()
-			.find(|track| track.name == name.to_string())
-			.cloned()
+		self.get_track_ref(name).cloned()
 	}
 
 	pub fn get_track_ref(&self, name: &str) -> Option<&Track> {
-		self.tracks?.iter().find(|track| track.name == name.to_string())
+		self.tracks.as_ref()?.iter().find(|track| track.name == name)
 	}
 
 	pub fn get_track_mut(&mut self, name: &str) -> Option<&mut Track> {
-		self.tracks?
-			.iter_mut()
-			.find(|track| track.name == name.to_string())
-			.as_deref_mut()
+		self.tracks.as_mut()?.iter_mut().find(|track| track.name == name)
 	}
 
 	pub fn remove_track(&mut self, name: &str) -> &mut Self {
-		// TODO replace self.tracks
-		self.tracks
-			.map(|tracks| tracks.iter().filter(|track| track.name != name.to_string()));
+		if let Some(tracks) = self.tracks.as_mut() {
+			tracks.retain(|track| track.name != name);
+		}
 		self
 	}
 
 	pub fn get_catalog(&self, name: &str) -> Option<Catalog> {
-		self.catalogs?
-			.iter()
-			.find(|catalog| catalog.name == name.to_string())
-			.cloned()
+		self.get_catalog_ref(name).cloned()
 	}
 
 	pub fn get_catalog_ref(&self, name: &str) -> Option<&Catalog> {
-		self.catalogs?.iter().find(|catalog| catalog.name == name.to_string())
+		self.catalogs.as_ref()?.iter().find(|catalog| catalog.name == name)
 	}
 
 	pub fn get_catalog_mut(&mut self, name: &str) -> Option<&mut Catalog> {
-		self.catalogs?
-			.iter_mut()
-			.find(|catalog| catalog.name == name.to_string())
-			.as_deref_mut()
+		self.catalogs.as_mut()?.iter_mut().find(|catalog| catalog.name == name)
 	}
 
 	pub fn remove_catalog(&mut self, name: &str) -> &mut Self {
-		// TODO replace self.catalogs
-		self.catalogs
-			.map(|catalogs| catalogs.iter().filter(|catalog| catalog.name != name.to_string()));
+		if let Some(catalogs) = self.catalogs.as_mut() {
+			catalogs.retain(|catalog| catalog.name != name);
+		}
 		self
 	}
 
@@ -168,7 +160,7 @@ impl R {
 	}
 
 	pub fn tracks_len(&self) -> Option<usize> {
-		Some(self.tracks?.len())
+		self.tracks.as_ref().map(|tracks| tracks.len())
 	}
 
 	pub fn catalogs(&self) -> Option<&[Catalog]> {
@@ -180,7 +172,7 @@ impl R {
 	}
 
 	pub fn catalogs_len(&self) -> Option<usize> {
-		Some(self.catalogs?.len())
+		self.catalogs.as_ref().map(|catalogs| catalogs.len())
 	}
 }
 
@@ -283,7 +275,7 @@ impl TFC {
 	}
 
 	pub fn namespace(&self) -> Option<&str> {
-		self.namespace.as_ref().map(|x| x.as_str())
+		self.namespace.as_deref()
 	}
 
 	pub fn name(&self) -> &str {
@@ -387,7 +379,7 @@ impl TF {
 	}
 
 	pub fn label(&self) -> Option<&str> {
-		self.label.as_ref().map(|x| x.as_str())
+		self.label.as_deref()
 	}
 
 	pub fn set_label(&mut self, label: &str) -> &mut Self {
@@ -414,7 +406,7 @@ impl TF {
 	}
 
 	pub fn init_data(&self) -> Option<&str> {
-		self.init_data.as_ref().map(|x| x.as_str())
+		self.init_data.as_deref()
 	}
 
 	pub fn set_init_data(&mut self, init_data: &str) -> &mut Self {
@@ -424,7 +416,7 @@ impl TF {
 	}
 
 	pub fn init_track(&self) -> Option<&str> {
-		self.init_track.as_ref().map(|x| x.as_str())
+		self.init_track.as_deref()
 	}
 
 	pub fn set_init_track(&mut self, track: &str) -> &mut Self {
@@ -488,11 +480,7 @@ impl T {
 	}
 
 	pub fn insert_depends(&mut self, depends: &str) -> &mut Self {
-		if self.depends.is_none() {
-			self.depends = Some(Vec::new());
-		}
-
-		self.depends.unwrap().push(depends.to_string());
+		self.depends.get_or_insert_with(Vec::new).push(depends.to_string());
 		self
 	}
 
@@ -575,7 +563,7 @@ pub struct S {
 	///
 	/// Source: [draft-ietf-moq-catalogformat-01](https://www.ietf.org/archive/id/draft-ietf-moq-catalogformat-01.html#name-audio-sample-rate)
 	#[serde(rename = "samplerate")]
-	sample_rate: Option<u16>,
+	sample_rate: Option<u32>,
 
 	/// Channel Config
 	///
@@ -619,21 +607,21 @@ pub struct S {
 
 #[mixin::expand]
 impl S {
-	pub fn codec(&mut self) -> Option<&str> {
-		self.codec.as_ref().map(|x| x.as_str())
+	pub fn codec(&self) -> Option<&str> {
+		self.codec.as_deref()
 	}
 
-	pub fn set_codec(&mut self, codec: &str) -> &mut Self {
+	pub fn set_codec(&mut self, codec: &str) -> &mut S {
 		// TODO: force only values from webcodec registry?
-		self.codec = Some(codec.to_string());
+		self.codec = Option::from(codec.to_string());
 		self
 	}
 
-	pub fn mime_type(&mut self) -> Option<&str> {
-		self.mime_type.as_ref().map(|x| x.as_str())
+	pub fn mime_type(&self) -> Option<&str> {
+		self.mime_type.as_deref()
 	}
 
-	pub fn set_mime_type(&mut self, mime: &str) -> Result<&mut Self, Error> {
+	pub fn set_mime_type(&mut self, mime: &str) -> Chained<'_, S> {
 		let mime = match mime::Mime::from_str(mime) {
 			core::result::Result::Ok(v) => v,
 			core::result::Result::Err(err) => {
@@ -645,7 +633,7 @@ impl S {
 			}
 		};
 
-		self.mime_type = Some(mime.to_string());
+		self.mime_type = Option::from(mime.to_string());
 		Ok(self)
 	}
 
@@ -653,8 +641,8 @@ impl S {
 		self.framerate
 	}
 
-	pub fn set_framerate(&mut self, framerate: u64) -> &mut Self {
-		self.framerate = Some(framerate);
+	pub fn set_framerate(&mut self, framerate: u64) -> &mut S {
+		self.framerate = Option::from(framerate);
 		self
 	}
 
@@ -662,8 +650,8 @@ impl S {
 		self.bitrate
 	}
 
-	pub fn set_bitrate(&mut self, bitrate: u64) -> &mut Self {
-		self.bitrate = Some(bitrate);
+	pub fn set_bitrate(&mut self, bitrate: u64) -> &mut S {
+		self.bitrate = Option::from(bitrate);
 		self
 	}
 
@@ -671,8 +659,8 @@ impl S {
 		self.width
 	}
 
-	pub fn set_width(&mut self, width: u16) -> &mut Self {
-		self.width = Some(width);
+	pub fn set_width(&mut self, width: u16) -> &mut S {
+		self.width = Option::from(width);
 		self
 	}
 
@@ -680,8 +668,8 @@ impl S {
 		self.height
 	}
 
-	pub fn set_height(&mut self, height: u16) -> &mut Self {
-		self.height = Some(height);
+	pub fn set_height(&mut self, height: u16) -> &mut S {
+		self.height = Option::from(height);
 		self
 	}
 
@@ -689,8 +677,8 @@ impl S {
 		self.display_width
 	}
 
-	pub fn set_display_width(&mut self, width: u16) -> &mut Self {
-		self.display_width = Some(width);
+	pub fn set_display_width(&mut self, width: u16) -> &mut S {
+		self.display_width = Option::from(width);
 		self
 	}
 
@@ -698,26 +686,46 @@ impl S {
 		self.display_height
 	}
 
-	pub fn set_display_height(&mut self, height: u16) -> &mut Self {
-		self.display_height = Some(height);
+	pub fn set_display_height(&mut self, height: u16) -> &mut S {
+		self.display_height = Option::from(height);
 		self
 	}
 
-	pub fn sample_rate(&self) -> Option<u16> {
+	pub fn sample_rate(&self) -> Option<u32> {
 		self.sample_rate
 	}
 
-	pub fn set_sample_rate(&mut self, sample_rate: u16) -> &mut Self {
+	pub fn set_sample_rate(&mut self, sample_rate: u32) -> &mut S {
 		// TODO make sure self.codec is audio codec
-		self.sample_rate = Some(sample_rate);
+		self.sample_rate = Option::from(sample_rate);
+		self
+	}
+
+	pub fn channel_config(&self) -> Option<&str> {
+		self.channel_config.as_deref()
+	}
+
+	pub fn set_channel_config(&mut self, config: &str) -> &mut S {
+		self.channel_config = Option::from(config.to_string());
+		self
+	}
+
+	/// number of channels, if the channel configuration is a plain number
+	pub fn channel_count(&self) -> Option<u8> {
+		self.channel_config.as_deref()?.parse().ok()
+	}
+
+	/// set the channel configuration to a plain number of channels
+	pub fn set_channel_count(&mut self, channels: u8) -> &mut S {
+		self.channel_config = Option::from(channels.to_string());
 		self
 	}
 
 	pub fn language(&self) -> Option<&str> {
-		self.language.as_ref().map(|x| x.as_str())
+		self.language.as_deref()
 	}
 
-	pub fn set_language(&mut self, lang: &str) -> Result<&mut Self, Error> {
+	pub fn set_language(&mut self, lang: &str) -> Chained<'_, S> {
 		let tag = match language_tags::LanguageTag::parse(lang) {
 			core::result::Result::Ok(v) => v,
 			core::result::Result::Err(err) => {
@@ -729,7 +737,7 @@ impl S {
 			}
 		};
 
-		self.language = Some(tag.to_string());
+		self.language = Option::from(tag.to_string());
 		Ok(self)
 	}
 }
@@ -828,3 +836,84 @@ impl SelectionParams {
 		Self::default()
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_tracks() {
+		let mut catalog = MoqCatalog::new();
+		assert!(catalog.get_track("video").is_none());
+		assert!(catalog.get_track_mut("video").is_none());
+		assert_eq!(catalog.tracks_len(), None);
+		catalog.remove_track("video");
+
+		catalog
+			.insert_track(Track::new("video", Packaging::CMAF))
+			.unwrap()
+			.insert_track(Track::new("audio", Packaging::CMAF))
+			.unwrap();
+		assert_eq!(catalog.get_track("video").unwrap().name(), "video");
+		assert!(catalog.get_track_ref("subtitles").is_none());
+
+		catalog
+			.get_track_mut("audio")
+			.unwrap()
+			.set_label("English")
+			.insert_depends("video");
+		let audio = catalog.get_track_ref("audio").unwrap();
+		assert_eq!(audio.label(), Some("English"));
+		assert_eq!(audio.depends(), Some(&["video".to_string()][..]));
+
+		catalog.remove_track("subtitles");
+		assert_eq!(catalog.tracks_len(), Some(2));
+
+		catalog.remove_track("video");
+		assert_eq!(catalog.tracks_len(), Some(1));
+		assert!(catalog.get_track("video").is_none());
+
+		assert!(matches!(
+			catalog.insert_catalog(Catalog::new("sports")),
+			Err(Error::TracksAlreadySet)
+		));
+	}
+
+	#[test]
+	fn test_catalogs() {
+		let mut catalog = MoqCatalog::new();
+		assert!(catalog.get_catalog("sports").is_none());
+		assert!(catalog.get_catalog_mut("sports").is_none());
+		assert_eq!(catalog.catalogs_len(), None);
+		catalog.remove_catalog("sports");
+
+		catalog
+			.insert_catalog(Catalog::new("sports"))
+			.unwrap()
+			.insert_catalog(Catalog::new("news"))
+			.unwrap();
+		assert_eq!(catalog.get_catalog("sports").unwrap().name(), "sports");
+		assert!(catalog.get_catalog_ref("movies").is_none());
+
+		catalog
+			.get_catalog_mut("news")
+			.unwrap()
+			.set_namespace("example.com/news");
+		assert_eq!(
+			catalog.get_catalog_ref("news").unwrap().namespace(),
+			Some("example.com/news")
+		);
+
+		catalog.remove_catalog("movies");
+		assert_eq!(catalog.catalogs_len(), Some(2));
+
+		catalog.remove_catalog("sports");
+		assert_eq!(catalog.catalogs_len(), Some(1));
+		assert!(catalog.get_catalog("sports").is_none());
+
+		assert!(matches!(
+			catalog.insert_track(Track::new("video", Packaging::CMAF)),
+			Err(Error::CatalogsAlreadySet)
+		));
+	}
+}
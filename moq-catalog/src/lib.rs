@@ -1,13 +1,25 @@
+#[cfg(feature = "mp4")]
+mod codec;
 mod error;
-// mod internal;
+#[cfg(feature = "mp4")]
+mod init;
+// only built and tested until it replaces the catalog of `old`
+#[allow(dead_code)]
+pub(crate) mod internal;
 
 // pub use internal::{Catalog, CommonStructFields, MoqCatalog, SelectionParams, Track};
 
+mod delta;
 mod old;
+mod watcher;
 
-pub use old::{Catalog, CommonStructFields, MoqCatalog, SelectionParams, Track};
+pub use delta::{CatalogDelta, Operation};
+pub use old::{Catalog, CatalogSummary, CommonStructFields, MoqCatalog, SelectionParams, Track};
+pub use watcher::{CatalogEvent, CatalogEvents, CatalogWatcher};
 
 pub use error::Error;
+#[cfg(feature = "mp4")]
+pub use init::AAC_SAMPLING_RATES;
 
 use serde::{Deserialize, Serialize};
 
@@ -17,7 +29,7 @@ const STREAMING_FORMAT_VERSION: &str = "1";
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
 pub enum Packaging {
 	#[serde(rename = "cmaf")]
 	#[default]
@@ -26,3 +38,54 @@ pub enum Packaging {
 	#[serde(rename = "loc")]
 	LOC,
 }
+
+impl std::str::FromStr for Packaging {
+	type Err = Error;
+
+	/// the names used in the catalog, ex. `cmaf` or `loc`
+	fn from_str(s: &str) -> Result<Self> {
+		match s {
+			"cmaf" => Ok(Self::CMAF),
+			"loc" => Ok(Self::LOC),
+			_ => Err(Error::UnknownPackaging(s.to_string())),
+		}
+	}
+}
+
+/// The serialization of an encoded catalog.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CatalogFormat {
+	/// pretty-printed JSON
+	Json,
+	/// JSON without whitespace, written by [MoqCatalog::encode]
+	#[default]
+	JsonCompact,
+	/// CBOR, the smallest
+	Cbor,
+}
+
+impl CatalogFormat {
+	/// the format of the encoded catalog `buf`: JSON starts with an object, CBOR with a map
+	pub fn sniff(buf: &[u8]) -> Option<Self> {
+		match buf.iter().find(|b| !b.is_ascii_whitespace())? {
+			b'{' => Some(Self::Json),
+			// major type 5, a map
+			b if b >> 5 == 5 => Some(Self::Cbor),
+			_ => None,
+		}
+	}
+}
+
+impl std::str::FromStr for CatalogFormat {
+	type Err = Error;
+
+	/// `json`, `json-compact` or `cbor`
+	fn from_str(s: &str) -> Result<Self> {
+		match s {
+			"json" => Ok(Self::Json),
+			"json-compact" => Ok(Self::JsonCompact),
+			"cbor" => Ok(Self::Cbor),
+			_ => Err(Error::UnknownFormat(s.to_string())),
+		}
+	}
+}
@@ -1,11 +1,68 @@
 use std::str::FromStr;
 
-use crate::{Error, Packaging, Result, STREAMING_FORMAT, STREAMING_FORMAT_VERSION, VERSION};
+use crate::{
+	CatalogDelta, CatalogFormat, Error, Packaging, Result, STREAMING_FORMAT, STREAMING_FORMAT_VERSION, VERSION,
+};
 
 use base64::prelude::*;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// consuming variants of setters returning `&mut Self`, to build a value in a single expression
+macro_rules! builders {
+	($($with:ident => $set:ident($($arg:ident: $ty:ty),*);)*) => {
+		$(
+			#[doc = concat!("like [Self::", stringify!($set), "], consuming `self` for chaining")]
+			pub fn $with(mut self, $($arg: $ty),*) -> Self {
+				self.$set($($arg),*);
+				self
+			}
+		)*
+	};
+}
+
+/// consuming variants of setters returning `Result<&mut Self>`
+macro_rules! try_builders {
+	($($with:ident => $set:ident($($arg:ident: $ty:ty),*);)*) => {
+		$(
+			#[doc = concat!("like [Self::", stringify!($set), "], consuming `self` for chaining")]
+			pub fn $with(mut self, $($arg: $ty),*) -> Result<Self> {
+				self.$set($($arg),*)?;
+				Ok(self)
+			}
+		)*
+	};
+}
+
+/// A catalog of tracks or of other catalogs.
+///
+/// Either mutated in place with the `set_*` and `insert_*` methods,
+/// or built in a single expression with their consuming `with_*` variants:
+///
+/// ```
+/// use moq_catalog::{CommonStructFields, MoqCatalog, Packaging, SelectionParams, Track};
+///
+/// let catalog = MoqCatalog::new()
+///     .with_delta_updates()
+///     .with_common_track_fields(CommonStructFields::new("", Packaging::CMAF).with_namespace("live").with_render_group(1))
+///     .with_track(
+///         Track::new("720p", Packaging::CMAF).with_label("720p").with_selection_params(
+///             SelectionParams::new()
+///                 .with_codec("avc1.64001f")
+///                 .with_width(1280)
+///                 .with_height(720)
+///                 .with_bitrate(3_000_000),
+///         ),
+///     )?
+///     .with_track(
+///         Track::new("audio", Packaging::CMAF)
+///             .with_selection_params(SelectionParams::new().with_codec("mp4a.40.2").with_sample_rate(48000)),
+///     )?;
+///
+/// assert_eq!(catalog.video_tracks().len(), 1);
+/// assert_eq!(MoqCatalog::decode(&catalog.encode()?)?.tracks().len(), 2);
+/// # Ok::<(), moq_catalog::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MoqCatalog {
 	/// Catalog Version
 	///
@@ -116,6 +173,28 @@ impl MoqCatalog {
 		self.common_track_fields.as_mut()
 	}
 
+	/// the tracks of the catalog, empty if it lists catalogs instead
+	pub fn tracks(&self) -> &[Track] {
+		self.tracks.as_deref().unwrap_or_default()
+	}
+
+	/// the track named `name`, if the catalog lists tracks
+	pub fn track(&self, name: &str) -> Option<&Track> {
+		self.tracks().iter().find(|track| track.name == name)
+	}
+
+	/// the track named `name`, if the catalog lists tracks
+	pub fn track_mut(&mut self, name: &str) -> Option<&mut Track> {
+		self.tracks.as_mut()?.iter_mut().find(|track| track.name == name)
+	}
+
+	/// remove the track named `name`, None if the catalog does not list it
+	pub fn remove_track(&mut self, name: &str) -> Option<Track> {
+		let tracks = self.tracks.as_mut()?;
+		let index = tracks.iter().position(|track| track.name == name)?;
+		Some(tracks.remove(index))
+	}
+
 	pub fn set_tracks(&mut self, tracks: &[Track]) -> Result<&mut Self> {
 		if self.catalogs.is_some() {
 			return Err(Error::CatalogsAlreadySet);
@@ -137,19 +216,37 @@ impl MoqCatalog {
 		Ok(self)
 	}
 
+	/// the child catalogs, empty if the catalog lists tracks instead
+	pub fn catalogs(&self) -> &[Catalog] {
+		self.catalogs.as_deref().unwrap_or_default()
+	}
+
+	/// the child catalog named `name`, if the catalog lists catalogs
+	pub fn catalog(&self, name: &str) -> Option<&Catalog> {
+		self.catalogs().iter().find(|catalog| catalog.name == name)
+	}
+
+	/// list the child catalogs `catalog` instead of tracks, their names must be unique
 	pub fn set_catalog(&mut self, catalog: &[Catalog]) -> Result<&mut Self> {
 		if self.tracks.is_some() {
 			return Err(Error::TracksAlreadySet);
 		}
+		if let Some(duplicate) = duplicate_catalog(catalog) {
+			return Err(Error::DuplicateCatalog(duplicate.to_string()));
+		}
 
 		self.catalogs = Some(catalog.to_vec());
 		Ok(self)
 	}
 
+	/// list the child catalog `catalog` instead of tracks, fails if one of the same name is listed already
 	pub fn insert_catalog(&mut self, catalog: Catalog) -> Result<&mut Self> {
 		if self.tracks.is_some() {
 			return Err(Error::TracksAlreadySet);
 		}
+		if self.catalog(&catalog.name).is_some() {
+			return Err(Error::DuplicateCatalog(catalog.name));
+		}
 
 		match &mut self.catalogs {
 			Some(catalogs) => catalogs.push(catalog),
@@ -158,107 +255,427 @@ impl MoqCatalog {
 		Ok(self)
 	}
 
+	/// remove the child catalog named `name`, None if the catalog does not list it
+	pub fn remove_catalog(&mut self, name: &str) -> Option<Catalog> {
+		let catalogs = self.catalogs.as_mut()?;
+		let index = catalogs.iter().position(|catalog| catalog.name == name)?;
+		Some(catalogs.remove(index))
+	}
+
+	/// encode the catalog as compact JSON, failing on empty selection parameters, which the draft forbids
 	pub fn encode(&self) -> Result<Vec<u8>> {
-		match serde_json::to_vec(&self) {
+		self.encode_with(CatalogFormat::JsonCompact)
+	}
+
+	/// like [encode](MoqCatalog::encode), in the serialization `format`
+	pub fn encode_with(&self, format: CatalogFormat) -> Result<Vec<u8>> {
+		let mut violations = Vec::new();
+		if let Some(csf) = &self.common_track_fields {
+			validate_params("commonTrackFields", &csf.selection_params, &mut violations);
+		}
+		for track in self.tracks.iter().flatten() {
+			validate_params(&track.name, &track.selection_params, &mut violations);
+		}
+		if let Some(err) = violations.into_iter().next() {
+			log::error!("encode [MoqCatalog]: {}", err);
+			return Err(err);
+		}
+
+		let res = match format {
+			CatalogFormat::Json => serde_json::to_vec_pretty(&self).map_err(|err| ("serde_json", err.to_string())),
+			CatalogFormat::JsonCompact => serde_json::to_vec(&self).map_err(|err| ("serde_json", err.to_string())),
+			CatalogFormat::Cbor => {
+				let mut buf = Vec::new();
+				ciborium::into_writer(&self, &mut buf)
+					.map(|_| buf)
+					.map_err(|err| ("ciborium", err.to_string()))
+			}
+		};
+		match res {
 			Ok(v) => Ok(v),
-			Err(err) => {
-				log::error!("encode [MoqCatalog]: {}", err);
+			Err((krayt, error)) => {
+				log::error!("encode [MoqCatalog]: {}", error);
 				Err(Error::External {
-					krayt: "serde_json".to_string(),
-					error: err.to_string(),
+					krayt: krayt.to_string(),
+					error,
 				})
 			}
 		}
 	}
+
+	/// decode and [validate](MoqCatalog::validate) a JSON catalog, failing on the first violation
+	pub fn decode(buf: &[u8]) -> Result<Self> {
+		Self::decode_with(buf, CatalogFormat::Json)
+	}
+
+	/// like [decode](MoqCatalog::decode), for a catalog in the serialization `format`
+	///
+	/// Both JSON formats are decoded alike, see [CatalogFormat::sniff] for a catalog of unknown format.
+	pub fn decode_with(buf: &[u8], format: CatalogFormat) -> Result<Self> {
+		let res = match format {
+			CatalogFormat::Json | CatalogFormat::JsonCompact => {
+				serde_json::from_slice(buf).map_err(|err| ("serde_json", err.to_string()))
+			}
+			CatalogFormat::Cbor => ciborium::from_reader(buf).map_err(|err| ("ciborium", err.to_string())),
+		};
+		let catalog: Self = match res {
+			Ok(v) => v,
+			Err((krayt, error)) => {
+				log::error!("decode [MoqCatalog]: {}", error);
+				return Err(Error::External {
+					krayt: krayt.to_string(),
+					error,
+				});
+			}
+		};
+
+		match catalog.validate().into_iter().next() {
+			Some(err) => {
+				log::error!("decode [MoqCatalog]: {}", err);
+				Err(err)
+			}
+			None => Ok(catalog),
+		}
+	}
+
+	/// all violations of the draft rules, empty if the catalog is valid
+	pub fn validate(&self) -> Vec<Error> {
+		let mut violations = Vec::new();
+
+		if self.version != VERSION {
+			violations.push(Error::UnknownVersion(self.version.clone()));
+		}
+
+		if self.tracks.is_some() && self.catalogs.is_some() {
+			violations.push(Error::BothTracksAndCatalogs);
+		}
+
+		if let Some(duplicate) = duplicate_catalog(self.catalogs()) {
+			violations.push(Error::DuplicateCatalog(duplicate.to_string()));
+		}
+
+		if let Some(csf) = &self.common_track_fields {
+			validate_fields(
+				"commonTrackFields",
+				&csf.selection_params,
+				&csf.init_data,
+				&mut violations,
+			);
+		}
+
+		for track in self.tracks.iter().flatten() {
+			validate_fields(&track.name, &track.selection_params, &track.init_data, &mut violations);
+		}
+
+		violations
+	}
+
+	/// delta update turning `previous` into this catalog
+	pub fn diff(&self, previous: &MoqCatalog) -> Result<CatalogDelta> {
+		CatalogDelta::new(previous, self)
+	}
+
+	/// encoded delta update turning `previous` into this catalog
+	pub fn encode_delta(&self, previous: &MoqCatalog) -> Result<Vec<u8>> {
+		self.diff(previous)?.encode()
+	}
+
+	/// the video tracks, by the mime type or else the codec, inherited from the common track fields
+	pub fn video_tracks(&self) -> Vec<&Track> {
+		self.tracks_of_kind(MediaKind::Video)
+	}
+
+	/// the audio tracks, by the mime type or else the codec, inherited from the common track fields
+	pub fn audio_tracks(&self) -> Vec<&Track> {
+		self.tracks_of_kind(MediaKind::Audio)
+	}
+
+	/// the tracks which are alternatives of each other in `alt_group`
+	pub fn tracks_in_alt_group(&self, alt_group: usize) -> Vec<&Track> {
+		let inherited = self.common_track_fields.as_ref().and_then(|csf| csf.alt_group);
+		self.tracks()
+			.iter()
+			.filter(|track| track.alt_group.or(inherited) == Some(alt_group))
+			.collect()
+	}
+
+	/// the video track with the highest bitrate of at most `bitrate` bits per second
+	///
+	/// Tracks without a bitrate are never picked, of equal bitrates the first listed wins.
+	pub fn best_video_under_bitrate(&self, bitrate: u64) -> Option<&Track> {
+		self.video_bitrates()
+			.filter(|(_, b)| *b <= bitrate)
+			.rev()
+			.max_by_key(|(_, b)| *b)
+			.map(|(track, _)| track)
+	}
+
+	/// the video track with the lowest bitrate, of equal bitrates the first listed wins
+	pub fn lowest_bitrate_video(&self) -> Option<&Track> {
+		self.video_bitrates().min_by_key(|(_, b)| *b).map(|(track, _)| track)
+	}
+
+	/// the number of tracks by kind and their total declared bitrate, inherited from the common track fields
+	pub fn summary(&self) -> CatalogSummary {
+		CatalogSummary {
+			tracks: self.tracks().len(),
+			video_tracks: self.video_tracks().len(),
+			audio_tracks: self.audio_tracks().len(),
+			bandwidth: self
+				.tracks()
+				.iter()
+				.filter_map(|track| self.param(track, SelectionParams::bitrate))
+				.sum(),
+		}
+	}
+
+	/// the video tracks with a bitrate, in catalog order
+	fn video_bitrates(&self) -> impl DoubleEndedIterator<Item = (&Track, u64)> {
+		self.video_tracks()
+			.into_iter()
+			.filter_map(|track| Some((track, self.param(track, SelectionParams::bitrate)?)))
+	}
+
+	fn tracks_of_kind(&self, kind: MediaKind) -> Vec<&Track> {
+		self.tracks()
+			.iter()
+			.filter(|track| self.kind(track) == Some(kind))
+			.collect()
+	}
+
+	/// by the own selection parameters first, then by the inherited ones
+	fn kind(&self, track: &Track) -> Option<MediaKind> {
+		let inherited = self
+			.common_track_fields
+			.as_ref()
+			.and_then(|csf| csf.selection_params.as_ref());
+		track
+			.selection_params
+			.as_ref()
+			.and_then(SelectionParams::kind)
+			.or_else(|| inherited.and_then(SelectionParams::kind))
+	}
+
+	/// a selection parameter of `track`, inherited from the common track fields if not set
+	fn param<T>(&self, track: &Track, get: impl Fn(&SelectionParams) -> Option<T>) -> Option<T> {
+		let inherited = self
+			.common_track_fields
+			.as_ref()
+			.and_then(|csf| csf.selection_params.as_ref());
+		track
+			.selection_params
+			.as_ref()
+			.and_then(&get)
+			.or_else(|| inherited.and_then(&get))
+	}
 }
 
-impl std::fmt::Display for MoqCatalog {
+impl MoqCatalog {
+	builders! {
+		with_delta_updates => enable_delta_updates();
+		with_common_track_fields => set_common_track_fields(csf: CommonStructFields);
+	}
+
+	try_builders! {
+		with_tracks => set_tracks(tracks: &[Track]);
+		with_track => insert_track(track: Track);
+		with_catalogs => set_catalog(catalogs: &[Catalog]);
+		with_catalog => insert_catalog(catalog: Catalog);
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MediaKind {
+	Video,
+	Audio,
+}
+
+impl MediaKind {
+	fn from_mime_type(mime: &str) -> Option<Self> {
+		match mime.split('/').next() {
+			Some("video") => Some(Self::Video),
+			Some("audio") => Some(Self::Audio),
+			_ => None,
+		}
+	}
+
+	/// by the WebCodecs codec string
+	fn from_codec(codec: &str) -> Option<Self> {
+		match codec.split('.').next()? {
+			"avc1" | "avc3" | "hev1" | "hvc1" | "vp8" | "vp09" | "av01" => Some(Self::Video),
+			"mp4a" | "opus" | "flac" | "vorbis" | "mp3" | "ac-3" | "ec-3" | "alaw" | "ulaw" | "pcm-s16" => {
+				Some(Self::Audio)
+			}
+			_ => None,
+		}
+	}
+}
+
+/// the name of the first catalog listed twice
+fn duplicate_catalog(catalogs: &[Catalog]) -> Option<&str> {
+	catalogs
+		.iter()
+		.enumerate()
+		.find(|(index, catalog)| catalogs[..*index].iter().any(|other| other.name == catalog.name))
+		.map(|(_, catalog)| catalog.name.as_str())
+}
+
+fn validate_fields(
+	name: &str,
+	params: &Option<SelectionParams>,
+	init_data: &Option<String>,
+	violations: &mut Vec<Error>,
+) {
+	validate_params(name, params, violations);
+
+	if let Some(Err(err)) = init_data.as_ref().map(|init| BASE64_STANDARD.decode(init)) {
+		violations.push(Error::InvalidInitData(name.to_string(), err.to_string()));
+	}
+}
+
+fn validate_params(name: &str, params: &Option<SelectionParams>, violations: &mut Vec<Error>) {
+	if params.as_ref().is_some_and(SelectionParams::is_empty) {
+		violations.push(Error::EmptySelectionParams(name.to_string()));
+	}
+}
+
+fn decode_init_data(name: &str, init_data: &Option<String>) -> Result<Option<Vec<u8>>> {
+	match init_data.as_ref().map(|init| BASE64_STANDARD.decode(init)) {
+		Some(Ok(init)) => Ok(Some(init)),
+		Some(Err(err)) => Err(Error::InvalidInitData(name.to_string(), err.to_string())),
+		None => Ok(None),
+	}
+}
+
+/// Counts of a [MoqCatalog], see [MoqCatalog::summary].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct CatalogSummary {
+	pub tracks: usize,
+	pub video_tracks: usize,
+	pub audio_tracks: usize,
+	/// the sum of the declared bitrates in bits per second, tracks without one are not counted
+	pub bandwidth: u64,
+}
+
+impl std::fmt::Display for CatalogSummary {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		let mut out = format!(
-			"MoqCatalog v{}, format: {} Version {}\n",
-			self.version, self.streaming_format, self.streaming_format_version
-		);
-		if self.tracks.is_some() {
-			out += &format!("containing {} tracks:\n", self.tracks.as_ref().unwrap().len());
-			let (mut res, mut bitrate, mut mime, mut codec, mut name) = (0, 0, 0, 0, 0);
-			for track in self.tracks.as_ref().unwrap().iter() {
-				if let Some(params) = track.selection_params() {
-					let width = params.width.unwrap_or_default();
-					let height = params.height.unwrap_or_default();
-
-					let res_len = width.checked_ilog10().unwrap_or(1) + height.checked_ilog10().unwrap_or(1) + 3;
-					if res_len > res {
-						res = res_len;
-					}
+		write!(
+			f,
+			"{} tracks ({} video, {} audio), {} declared",
+			self.tracks,
+			self.video_tracks,
+			self.audio_tracks,
+			Kbps(self.bandwidth)
+		)
+	}
+}
 
-					if let Some(sample) = params.sample_rate {
-						let sample = sample / 1_000;
-						let sample = sample.checked_ilog10().unwrap_or_default() + 5;
-						if sample > res {
-							res = sample;
-						}
-					}
+/// a bitrate in bits per second, written in kbps
+struct Kbps(u64);
 
-					let br = params.bitrate.unwrap_or_default() / 1_000;
-					let bitrate_len = br.checked_ilog10().unwrap_or(1) + 1;
-					if bitrate_len > bitrate {
-						bitrate = bitrate_len;
-					}
+impl std::fmt::Display for Kbps {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{} kbps", self.0 / 1_000)
+	}
+}
 
-					let mim = params.mime_type.clone().unwrap_or("no mime".to_string());
-					let mime_len = mim.len();
-					if mime_len > mime {
-						mime = mime_len;
-					}
+/// The columns of a track in the table of a [MoqCatalog].
+struct Row {
+	name: String,
+	codec: String,
+	media: String,
+	bitrate: String,
+	groups: String,
+}
 
-					let code = params.codec.clone().unwrap_or("no codec".to_string());
-					let codec_len = code.len();
-					if codec_len > codec {
-						codec = codec_len;
-					}
-				}
+impl Row {
+	const HEADER: [&'static str; 5] = ["name", "codec", "media", "bitrate", "groups"];
 
-				let name_len = track.name.len();
-				if name_len > name {
-					name = name_len;
-				}
-			}
-			for (i, track) in self.tracks.as_ref().unwrap().iter().enumerate() {
-				let (res_str, mime_str, codec_str, br) = if let Some(params) = track.selection_params() {
-					let res_str = match (params.width, params.height, params.sample_rate) {
-						(Some(w), Some(h), None) => format!("{}x{}", w, h),
-						(None, None, Some(s)) => format!("{} kbps", s / 1_000),
-						_ => "-".to_string(),
-					};
-					let mime_str = params.mime_type.clone().unwrap_or("no mime".to_string());
-					let codec_str = params.codec.clone().unwrap_or("no codec".to_string());
-					let br = params.bitrate.unwrap_or(0) / 1_000;
-					(res_str, mime_str, codec_str, br)
-				} else {
-					("0x0".to_string(), "no_mime".to_string(), "no codec".to_string(), 0)
-				};
-				out += &format!(
-					"{i:>3}: {name:>name_width$}, {bitrate:>bitrate_width$} kbps {resolution:>resolution_width$} {codec:>codec_width$} {mime:>mime_width$}\n",
-					name = track.name,
-					name_width = name,
-					bitrate = br,
-					bitrate_width = bitrate as usize,
-					resolution = res_str,
-					resolution_width = res as usize,
-					codec = codec_str,
-					codec_width = codec,
-					mime = mime_str,
-					mime_width = mime,
-				);
+	fn new(
+		name: &str,
+		params: Option<&SelectionParams>,
+		alt_group: Option<usize>,
+		render_group: Option<usize>,
+	) -> Self {
+		let cell = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+
+		let groups = [("alt", alt_group), ("render", render_group)]
+			.into_iter()
+			.filter_map(|(kind, group)| Some(format!("{kind}={}", group?)))
+			.collect::<Vec<_>>();
+
+		Self {
+			name: name.to_string(),
+			codec: cell(params.and_then(|p| p.codec.clone())),
+			media: cell(params.and_then(SelectionParams::media)),
+			bitrate: cell(params.and_then(|p| p.bitrate).map(|b| Kbps(b).to_string())),
+			groups: match groups.is_empty() {
+				true => "-".to_string(),
+				false => groups.join(" "),
+			},
+		}
+	}
+
+	fn cells(&self) -> [&str; 5] {
+		[&self.name, &self.codec, &self.media, &self.bitrate, &self.groups]
+	}
+
+	/// the rows with their columns aligned, the bitrates to the right
+	fn write_table(f: &mut std::fmt::Formatter<'_>, rows: &[Row]) -> std::fmt::Result {
+		let mut widths = Self::HEADER.map(str::len);
+		for row in rows {
+			for (width, cell) in widths.iter_mut().zip(row.cells()) {
+				*width = (*width).max(cell.len());
 			}
 		}
-		if self.catalogs.is_some() {
-			out += &format!("containing {} catalogs:\n", self.catalogs.as_ref().unwrap().len());
-			for (i, catalog) in self.catalogs.as_ref().unwrap().iter().enumerate() {
-				out += &format!("{i:3}: {}", catalog.name);
+
+		let header = Self::HEADER;
+		for cells in std::iter::once(header).chain(rows.iter().map(Row::cells)) {
+			let [name, codec, media, bitrate, groups] = cells;
+			let [w0, w1, w2, w3, _] = widths;
+			writeln!(f, "  {name:<w0$}  {codec:<w1$}  {media:<w2$}  {bitrate:>w3$}  {groups}")?;
+		}
+		Ok(())
+	}
+}
+
+impl std::fmt::Display for MoqCatalog {
+	/// a line per track with its name, codec, size or sample rate, bitrate and groups, inherited values included
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(
+			f,
+			"catalog v{}, streaming format {} v{}",
+			self.version, self.streaming_format, self.streaming_format_version
+		)?;
+
+		if let Some(catalogs) = &self.catalogs {
+			writeln!(f, ", {} catalogs", catalogs.len())?;
+			for catalog in catalogs {
+				writeln!(f, "  {}", catalog.name)?;
 			}
+			return Ok(());
 		}
-		write!(f, "{}", out)
+
+		writeln!(f, ", {}", self.summary())?;
+		let csf = self.common_track_fields.as_ref();
+		let inherited = csf.and_then(|csf| csf.selection_params.as_ref());
+		let rows: Vec<_> = self
+			.tracks()
+			.iter()
+			.map(|track| {
+				let params = match (&track.selection_params, inherited) {
+					(Some(own), Some(inherited)) => Some(own.inherit(inherited)),
+					(own, inherited) => own.clone().or_else(|| inherited.cloned()),
+				};
+				Row::new(
+					&track.name,
+					params.as_ref(),
+					track.alt_group.or(csf.and_then(|csf| csf.alt_group)),
+					track.render_group.or(csf.and_then(|csf| csf.render_group)),
+				)
+			})
+			.collect();
+		Row::write_table(f, &rows)
 	}
 }
 
@@ -276,7 +693,7 @@ impl std::default::Default for MoqCatalog {
 	}
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Catalog {
 	/// Streaming Format
 	///
@@ -376,7 +793,14 @@ impl Catalog {
 	}
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl Catalog {
+	builders! {
+		with_delta_updates => enable_delta_updates();
+		with_namespace => set_namespace(name: &str);
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CommonStructFields {
 	/// Track Namespace
 	///
@@ -517,6 +941,11 @@ impl CommonStructFields {
 		self
 	}
 
+	pub fn set_render_group(&mut self, render: usize) -> &mut Self {
+		self.render_group = Some(render);
+		self
+	}
+
 	pub fn set_alt_group(&mut self, alt: usize) -> &mut Self {
 		self.alt_group = Some(alt);
 		self
@@ -528,13 +957,29 @@ impl CommonStructFields {
 		self
 	}
 
+	/// the decoded init segment
+	pub fn init_data(&self) -> Result<Option<Vec<u8>>> {
+		decode_init_data("commonTrackFields", &self.init_data)
+	}
+
 	pub fn set_selection_params(&mut self, params: SelectionParams) -> &mut Self {
 		self.selection_params = Some(params);
 		self
 	}
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl CommonStructFields {
+	builders! {
+		with_namespace => set_namespace(name: &str);
+		with_label => set_label(label: &str);
+		with_render_group => set_render_group(render: usize);
+		with_alt_group => set_alt_group(alt: usize);
+		with_init_data => set_init_data(init: &[u8]);
+		with_selection_params => set_selection_params(params: SelectionParams);
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Track {
 	/// Track Namespace
 	///
@@ -695,6 +1140,10 @@ impl Track {
 		}
 	}
 
+	pub fn name(&self) -> &str {
+		&self.name
+	}
+
 	pub fn set_namespace(&mut self, name: &str) -> &mut Self {
 		self.namespace = Some(name.to_string());
 		self
@@ -709,17 +1158,64 @@ impl Track {
 		self
 	}
 
+	pub fn set_render_group(&mut self, render: usize) -> &mut Self {
+		self.render_group = Some(render);
+		self
+	}
+
 	pub fn set_alt_group(&mut self, alt: usize) -> &mut Self {
 		self.alt_group = Some(alt);
 		self
 	}
 
+	pub fn packaging(&self) -> Packaging {
+		self.packaging
+	}
+
+	pub fn set_packaging(&mut self, packaging: Packaging) -> &mut Self {
+		self.packaging = packaging;
+		self
+	}
+
+	pub fn label(&self) -> Option<&str> {
+		self.label.as_deref()
+	}
+
+	pub fn render_group(&self) -> Option<usize> {
+		self.render_group
+	}
+
+	pub fn alt_group(&self) -> Option<usize> {
+		self.alt_group
+	}
+
 	pub fn set_init_data(&mut self, init: &[u8]) -> &mut Self {
 		let b64 = BASE64_STANDARD.encode(init);
 		self.init_data = Some(b64);
 		self
 	}
 
+	/// the decoded init segment
+	pub fn init_data(&self) -> Result<Option<Vec<u8>>> {
+		decode_init_data(&self.name, &self.init_data)
+	}
+
+	/// drop the inline init segment, ex. once it is sent on an [init track](Self::set_init_track)
+	pub fn clear_init_data(&mut self) -> &mut Self {
+		self.init_data = None;
+		self
+	}
+
+	/// the name of the track holding the init segment, which must not be in the tracks array itself
+	pub fn set_init_track(&mut self, name: &str) -> &mut Self {
+		self.init_track = Some(name.to_string());
+		self
+	}
+
+	pub fn init_track(&self) -> Option<&str> {
+		self.init_track.as_deref()
+	}
+
 	pub fn set_selection_params(&mut self, params: SelectionParams) -> &mut Self {
 		self.selection_params = Some(params);
 		self
@@ -728,9 +1224,46 @@ impl Track {
 	pub fn selection_params(&self) -> Option<&SelectionParams> {
 		self.selection_params.as_ref()
 	}
+
+	pub fn clear_selection_params(&mut self) -> &mut Self {
+		self.selection_params = None;
+		self
+	}
+
+	/// selection parameters which contradict each other, ex. an audio codec with a width, empty if consistent
+	///
+	/// Only the parameters of the track itself are checked, not the inherited ones.
+	pub fn validate(&self) -> Vec<Error> {
+		let Some(params) = &self.selection_params else {
+			return Vec::new();
+		};
+
+		params
+			.inconsistencies()
+			.into_iter()
+			.map(|inconsistency| Error::InconsistentSelectionParams(self.name.clone(), inconsistency))
+			.collect()
+	}
+
+	pub fn selection_params_mut(&mut self) -> Option<&mut SelectionParams> {
+		self.selection_params.as_mut()
+	}
+}
+
+impl Track {
+	builders! {
+		with_namespace => set_namespace(name: &str);
+		with_label => set_label(label: &str);
+		with_render_group => set_render_group(render: usize);
+		with_alt_group => set_alt_group(alt: usize);
+		with_packaging => set_packaging(packaging: Packaging);
+		with_init_data => set_init_data(init: &[u8]);
+		with_init_track => set_init_track(name: &str);
+		with_selection_params => set_selection_params(params: SelectionParams);
+	}
 }
 
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct SelectionParams {
 	/// Codec
 	///
@@ -794,7 +1327,7 @@ pub struct SelectionParams {
 	///
 	/// Source: [draft-ietf-moq-catalogformat-01](https://www.ietf.org/archive/id/draft-ietf-moq-catalogformat-01.html#name-audio-sample-rate)
 	#[serde(rename = "samplerate", skip_serializing_if = "Option::is_none")]
-	sample_rate: Option<u16>,
+	sample_rate: Option<u32>,
 
 	/// Channel Config
 	///
@@ -834,6 +1367,14 @@ pub struct SelectionParams {
 	/// Source: [draft-ietf-moq-catalogformat-01](https://www.ietf.org/archive/id/draft-ietf-moq-catalogformat-01.html#name-language)
 	#[serde(rename = "lang", skip_serializing_if = "Option::is_none")]
 	language: Option<String>,
+
+	/// Priority
+	///
+	/// A number hinting the priority of the groups of the track relative to the
+	/// other tracks, lower values are sent first.  Not part of the catalog format,
+	/// ex. the audio tracks come before the video tracks.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	priority: Option<u8>,
 }
 
 impl SelectionParams {
@@ -841,6 +1382,99 @@ impl SelectionParams {
 		Self::default()
 	}
 
+	/// no parameter is set, such params must not be in a catalog
+	pub fn is_empty(&self) -> bool {
+		*self == Self::default()
+	}
+
+	pub fn codec(&self) -> Option<&str> {
+		self.codec.as_deref()
+	}
+
+	/// video or audio by the mime type, or else the codec
+	fn kind(&self) -> Option<MediaKind> {
+		match self.mime_type() {
+			Some(mime) => MediaKind::from_mime_type(mime),
+			None => self.codec().and_then(MediaKind::from_codec),
+		}
+	}
+
+	/// the parameters which do not fit the kind of media, which is taken from the mime type or else the codec
+	fn inconsistencies(&self) -> Vec<String> {
+		let mut inconsistencies = Vec::new();
+
+		if let (Some(codec), Some(mime)) = (self.codec(), self.mime_type()) {
+			let codec_kind = MediaKind::from_codec(codec);
+			let mime_kind = MediaKind::from_mime_type(mime);
+			if codec_kind.is_some() && mime_kind.is_some() && codec_kind != mime_kind {
+				inconsistencies.push(format!("codec {codec} with mime type {mime}"));
+			}
+		}
+
+		match self.kind() {
+			Some(MediaKind::Audio) => {
+				let sized = [self.width, self.height, self.display_width, self.display_height];
+				if sized.iter().any(Option::is_some) {
+					inconsistencies.push("audio with width or height".to_string());
+				}
+				if self.framerate.is_some() {
+					inconsistencies.push("audio with framerate".to_string());
+				}
+			}
+			Some(MediaKind::Video) => {
+				if self.sample_rate.is_some() {
+					inconsistencies.push("video with samplerate".to_string());
+				}
+				if self.channel_config.is_some() {
+					inconsistencies.push("video with channelConfig".to_string());
+				}
+			}
+			None => (),
+		}
+
+		inconsistencies
+	}
+
+	/// `WxH@fps` of a video track, the sample rate and channels of an audio track
+	fn media(&self) -> Option<String> {
+		if self.width.is_some() || self.height.is_some() {
+			let size = |value: Option<u16>| value.map_or("?".to_string(), |v| v.to_string());
+			let mut media = format!("{}x{}", size(self.width), size(self.height));
+			if let Some(framerate) = self.framerate {
+				media += &format!("@{framerate}");
+			}
+			return Some(media);
+		}
+
+		let rate = self.sample_rate?;
+		let mut media = match rate % 1_000 {
+			0 => format!("{}kHz", rate / 1_000),
+			_ => format!("{:.1}kHz", rate as f64 / 1_000.0),
+		};
+		if let Some(channels) = self.channel_count() {
+			media += &format!(" {channels}ch");
+		}
+		Some(media)
+	}
+
+	/// these parameters, the unset ones taken from `inherited`
+	fn inherit(&self, inherited: &Self) -> Self {
+		Self {
+			codec: self.codec.clone().or_else(|| inherited.codec.clone()),
+			mime_type: self.mime_type.clone().or_else(|| inherited.mime_type.clone()),
+			framerate: self.framerate.or(inherited.framerate),
+			bitrate: self.bitrate.or(inherited.bitrate),
+			width: self.width.or(inherited.width),
+			height: self.height.or(inherited.height),
+			sample_rate: self.sample_rate.or(inherited.sample_rate),
+			channel_config: self.channel_config.clone().or_else(|| inherited.channel_config.clone()),
+			display_width: self.display_width.or(inherited.display_width),
+			display_height: self.display_height.or(inherited.display_height),
+			language: self.language.clone().or_else(|| inherited.language.clone()),
+			priority: self.priority.or(inherited.priority),
+		}
+	}
+
 	pub fn set_codec(&mut self, codec: &str) -> &mut Self {
 		// TODO: force only values from webcodec registry?
 		self.codec = Some(codec.to_string());
@@ -863,32 +1497,104 @@ impl SelectionParams {
 		Ok(self)
 	}
 
+	pub fn framerate(&self) -> Option<u64> {
+		self.framerate
+	}
+
 	pub fn set_framerate(&mut self, framerate: u64) -> &mut Self {
 		self.framerate = Some(framerate);
 		self
 	}
 
+	pub fn bitrate(&self) -> Option<u64> {
+		self.bitrate
+	}
+
+	pub fn mime_type(&self) -> Option<&str> {
+		self.mime_type.as_deref()
+	}
+
+	/// drop the mime type, ex. for packagings without a container
+	pub fn clear_mime_type(&mut self) -> &mut Self {
+		self.mime_type = None;
+		self
+	}
+
 	pub fn set_bitrate(&mut self, bitrate: u64) -> &mut Self {
 		self.bitrate = Some(bitrate);
 		self
 	}
 
+	pub fn width(&self) -> Option<u16> {
+		self.width
+	}
+
 	pub fn set_width(&mut self, width: u16) -> &mut Self {
 		self.width = Some(width);
 		self
 	}
 
+	pub fn height(&self) -> Option<u16> {
+		self.height
+	}
+
 	pub fn set_height(&mut self, height: u16) -> &mut Self {
 		self.height = Some(height);
 		self
 	}
 
-	pub fn set_sample_rate(&mut self, sample_rate: u16) -> &mut Self {
+	pub fn display_width(&self) -> Option<u16> {
+		self.display_width
+	}
+
+	pub fn set_display_width(&mut self, width: u16) -> &mut Self {
+		self.display_width = Some(width);
+		self
+	}
+
+	pub fn display_height(&self) -> Option<u16> {
+		self.display_height
+	}
+
+	pub fn set_display_height(&mut self, height: u16) -> &mut Self {
+		self.display_height = Some(height);
+		self
+	}
+
+	pub fn sample_rate(&self) -> Option<u32> {
+		self.sample_rate
+	}
+
+	pub fn set_sample_rate(&mut self, sample_rate: u32) -> &mut Self {
 		// TODO make sure self.codec is audio codec
 		self.sample_rate = Some(sample_rate);
 		self
 	}
 
+	pub fn channel_config(&self) -> Option<&str> {
+		self.channel_config.as_deref()
+	}
+
+	pub fn set_channel_config(&mut self, config: &str) -> &mut Self {
+		self.channel_config = Some(config.to_string());
+		self
+	}
+
+	/// number of channels, if the channel configuration is a plain number
+	pub fn channel_count(&self) -> Option<u8> {
+		self.channel_config.as_deref()?.parse().ok()
+	}
+
+	/// set the channel configuration to a plain number of channels
+	pub fn set_channel_count(&mut self, channels: u8) -> &mut Self {
+		self.channel_config = Some(channels.to_string());
+		self
+	}
+
+	pub fn language(&self) -> Option<&str> {
+		self.language.as_deref()
+	}
+
 	pub fn set_language(&mut self, lang: &str) -> Result<&mut Self> {
 		let tag = match language_tags::LanguageTag::parse(lang) {
 			Ok(v) => v,
@@ -904,4 +1610,465 @@ impl SelectionParams {
 		self.language = Some(tag.to_string());
 		Ok(self)
 	}
+
+	pub fn priority(&self) -> Option<u8> {
+		self.priority
+	}
+
+	pub fn set_priority(&mut self, priority: u8) -> &mut Self {
+		self.priority = Some(priority);
+		self
+	}
+}
+
+impl SelectionParams {
+	builders! {
+		with_codec => set_codec(codec: &str);
+		with_framerate => set_framerate(framerate: u64);
+		with_bitrate => set_bitrate(bitrate: u64);
+		with_width => set_width(width: u16);
+		with_height => set_height(height: u16);
+		with_display_width => set_display_width(width: u16);
+		with_display_height => set_display_height(height: u16);
+		with_sample_rate => set_sample_rate(sample_rate: u32);
+		with_channel_config => set_channel_config(config: &str);
+		with_channel_count => set_channel_count(channels: u8);
+		with_priority => set_priority(priority: u8);
+	}
+
+	try_builders! {
+		with_mime_type => set_mime_type(mime: &str);
+		with_language => set_language(lang: &str);
+	}
+}
+
+impl std::fmt::Display for Track {
+	/// the name, codec, size or sample rate, bitrate and groups on a single line, without inherited values
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let row = Row::new(
+			&self.name,
+			self.selection_params.as_ref(),
+			self.alt_group,
+			self.render_group,
+		);
+		write!(f, "{}", row.cells().join(" "))
+	}
+}
+
+impl std::fmt::Display for SelectionParams {
+	/// the codec, size or sample rate and bitrate
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let row = Row::new("", Some(self), None, None);
+		write!(f, "{} {} {}", row.codec, row.media, row.bitrate)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const CATALOG: &str = r#"{
+		"version": "1",
+		"streamingFormat": "1",
+		"streamingFormatVersion": "1",
+		"supportsDeltaUpdates": true,
+		"commonTrackFields": {
+			"namespace": "conference.example.com/conference123/alice",
+			"name": "",
+			"packaging": "cmaf",
+			"label": "Dash MoQ",
+			"renderGroup": 1,
+			"altGroup": 1,
+			"initData": "AAAAGGZ0eXBpc282",
+			"initTrack": "init",
+			"selectionParams": { "mimeType": "video/mp4" }
+		},
+		"tracks": [
+			{
+				"namespace": "conference.example.com/conference123/alice",
+				"name": "video",
+				"packaging": "cmaf",
+				"label": "1080p",
+				"renderGroup": 1,
+				"altGroup": 1,
+				"initData": "AAAAGGZ0eXBpc282",
+				"initTrack": "init",
+				"selectionParams": {
+					"codec": "avc1.64001F",
+					"mimeType": "video/mp4",
+					"framerate": 30,
+					"bitrate": 6000000,
+					"width": 1920,
+					"height": 1080,
+					"displayWidth": 1920,
+					"displayHeight": 1080
+				},
+				"depends": ["audio"],
+				"temporalId": 0,
+				"spatialId": 1
+			},
+			{
+				"name": "audio",
+				"packaging": "loc",
+				"selectionParams": {
+					"codec": "opus",
+					"samplerate": 48000,
+					"channelConfig": "2",
+					"lang": "en"
+				}
+			}
+		]
+	}"#;
+
+	#[test]
+	fn test_round_trip() {
+		let catalog = MoqCatalog::decode(CATALOG.as_bytes()).unwrap();
+		let encoded = catalog.encode().unwrap();
+
+		let expected: serde_json::Value = serde_json::from_str(CATALOG).unwrap();
+		let actual: serde_json::Value = serde_json::from_slice(&encoded).unwrap();
+		assert_eq!(actual, expected);
+
+		let decoded = MoqCatalog::decode(&encoded).unwrap();
+		assert_eq!(decoded.encode().unwrap(), encoded);
+
+		let mut catalog = MoqCatalog::new();
+		catalog.insert_catalog(Catalog::new("sports")).unwrap();
+		let encoded = catalog.encode().unwrap();
+		assert_eq!(MoqCatalog::decode(&encoded).unwrap().encode().unwrap(), encoded);
+	}
+
+	#[test]
+	fn test_formats() {
+		let catalog = MoqCatalog::decode(CATALOG.as_bytes()).unwrap();
+		let expected = catalog.encode().unwrap();
+
+		let mut sizes = Vec::new();
+		for format in [CatalogFormat::Json, CatalogFormat::JsonCompact, CatalogFormat::Cbor] {
+			let encoded = catalog.encode_with(format).unwrap();
+			let sniffed = match format {
+				CatalogFormat::Cbor => CatalogFormat::Cbor,
+				_ => CatalogFormat::Json,
+			};
+			assert_eq!(CatalogFormat::sniff(&encoded), Some(sniffed));
+
+			let decoded = MoqCatalog::decode_with(&encoded, format).unwrap();
+			assert_eq!(decoded.encode().unwrap(), expected);
+			sizes.push(encoded.len());
+		}
+		assert_eq!(catalog.encode_with(CatalogFormat::JsonCompact).unwrap(), expected);
+
+		// pretty JSON, compact JSON, CBOR
+		assert!(sizes[2] < sizes[1] && sizes[1] < sizes[0], "{sizes:?}");
+	}
+
+	#[test]
+	fn test_encode_empty_params() {
+		let mut catalog: MoqCatalog = serde_json::from_str(CATALOG).unwrap();
+		assert!(!catalog.tracks()[1].selection_params().unwrap().is_empty());
+		let encoded = catalog.encode().unwrap();
+		assert_eq!(MoqCatalog::decode(&encoded).unwrap().encode().unwrap(), encoded);
+
+		catalog.tracks.as_mut().unwrap()[1].set_selection_params(SelectionParams::new());
+		let err = catalog.encode().unwrap_err();
+		assert!(matches!(err, Error::EmptySelectionParams(ref name) if name == "audio"));
+		assert_eq!(err.to_string(), "empty selectionParams in audio");
+
+		catalog.tracks.as_mut().unwrap()[1].clear_selection_params();
+		catalog.encode().unwrap();
+	}
+
+	#[test]
+	fn test_catalogs() {
+		let mut root = MoqCatalog::new();
+		root.insert_catalog(Catalog::new(".catalog.video"))
+			.unwrap()
+			.insert_catalog(Catalog::new(".catalog.audio"))
+			.unwrap();
+		assert!(matches!(
+			root.insert_catalog(Catalog::new(".catalog.video")),
+			Err(Error::DuplicateCatalog(ref name)) if name == ".catalog.video"
+		));
+		assert!(matches!(
+			root.insert_track(Track::new("video", Packaging::CMAF)),
+			Err(Error::CatalogsAlreadySet)
+		));
+
+		let names: Vec<_> = root.catalogs().iter().map(Catalog::name).collect();
+		assert_eq!(names, [".catalog.video", ".catalog.audio"]);
+		assert!(root.tracks().is_empty());
+
+		let decoded = MoqCatalog::decode(&root.encode().unwrap()).unwrap();
+		assert_eq!(decoded.catalogs().len(), 2);
+		assert!(decoded.catalog(".catalog.audio").is_some());
+
+		assert_eq!(root.remove_catalog(".catalog.audio").unwrap().name(), ".catalog.audio");
+		assert!(root.remove_catalog(".catalog.audio").is_none());
+
+		// the tracks exclude the catalogs, and a catalog is listed once
+		let mut leaf = MoqCatalog::new();
+		leaf.insert_track(Track::new("video", Packaging::CMAF)).unwrap();
+		assert!(matches!(
+			leaf.set_catalog(&[Catalog::new("sports")]),
+			Err(Error::TracksAlreadySet)
+		));
+		let twice = [Catalog::new("sports"), Catalog::new("sports")];
+		assert!(matches!(
+			MoqCatalog::new().set_catalog(&twice),
+			Err(Error::DuplicateCatalog(_))
+		));
+		root.catalogs = Some(twice.to_vec());
+		assert!(matches!(root.validate()[..], [Error::DuplicateCatalog(_)]));
+	}
+
+	#[test]
+	fn test_validate() {
+		let catalog = CATALOG.replace(r#""version": "1""#, r#""version": "2""#);
+		let err = MoqCatalog::decode(catalog.as_bytes()).unwrap_err();
+		assert!(matches!(err, Error::UnknownVersion(ref version) if version == "2"));
+		assert_eq!(err.to_string(), "unsupported catalog version 2, expected 1");
+
+		let mut catalog: MoqCatalog = serde_json::from_str(CATALOG).unwrap();
+		catalog.catalogs = Some(vec![Catalog::new("sports")]);
+		let tracks = catalog.tracks.as_mut().unwrap();
+		tracks[0].init_data = Some("not base64!".to_string());
+		tracks[1].selection_params = Some(SelectionParams::new());
+
+		let violations = catalog.validate();
+		assert_eq!(violations.len(), 3);
+		assert!(matches!(violations[0], Error::BothTracksAndCatalogs));
+		assert!(matches!(violations[1], Error::InvalidInitData(ref name, _) if name == "video"));
+		assert!(matches!(violations[2], Error::EmptySelectionParams(ref name) if name == "audio"));
+	}
+
+	#[test]
+	fn test_track_validate() {
+		let inconsistencies = |params: &mut SelectionParams| {
+			let mut track = Track::new("track", Packaging::CMAF);
+			track.set_selection_params(params.clone());
+			track
+				.validate()
+				.iter()
+				.map(|err| match err {
+					Error::InconsistentSelectionParams(name, inconsistency) => {
+						assert_eq!(name, "track");
+						inconsistency.clone()
+					}
+					err => panic!("unexpected {err}"),
+				})
+				.collect::<Vec<_>>()
+		};
+
+		let mut video = SelectionParams::new();
+		video.set_codec("avc1.64001f").set_mime_type("video/mp4").unwrap();
+		video.set_width(1280).set_height(720).set_framerate(30);
+		assert!(inconsistencies(&mut video).is_empty());
+		assert!(Track::new("empty", Packaging::CMAF).validate().is_empty());
+
+		let mut audio = SelectionParams::new();
+		audio.set_codec("mp4a.40.2").set_mime_type("audio/mp4").unwrap();
+		audio.set_sample_rate(48000).set_channel_count(2);
+		assert!(inconsistencies(&mut audio).is_empty());
+
+		assert_eq!(
+			inconsistencies(video.clone().set_mime_type("audio/mp4").unwrap()),
+			[
+				"codec avc1.64001f with mime type audio/mp4",
+				"audio with width or height",
+				"audio with framerate"
+			]
+		);
+		assert_eq!(
+			inconsistencies(audio.clone().set_height(720)),
+			["audio with width or height"]
+		);
+		assert_eq!(
+			inconsistencies(audio.clone().set_framerate(25)),
+			["audio with framerate"]
+		);
+		assert_eq!(
+			inconsistencies(video.clone().set_sample_rate(48000)),
+			["video with samplerate"]
+		);
+		assert_eq!(
+			inconsistencies(video.clone().set_channel_count(2)),
+			["video with channelConfig"]
+		);
+
+		// without a mime type, the kind is taken from the codec
+		let mut params = SelectionParams::new();
+		params.set_codec("opus").set_width(640);
+		assert_eq!(inconsistencies(&mut params), ["audio with width or height"]);
+	}
+
+	/// a rung of the video ladder, with `bitrate` if given
+	fn rung(name: &str, height: u16, bitrate: Option<u64>) -> Track {
+		let mut params = SelectionParams::new();
+		params.set_codec("avc1.64001F").set_height(height);
+		if let Some(bitrate) = bitrate {
+			params.set_bitrate(bitrate);
+		}
+
+		let mut track = Track::new(name, Packaging::CMAF);
+		track.set_alt_group(1).set_selection_params(params);
+		track
+	}
+
+	#[test]
+	fn test_ladder() {
+		let mut audio = Track::new("audio", Packaging::CMAF);
+		let mut params = SelectionParams::new();
+		params.set_codec("mp4a.40.2").set_bitrate(128_000);
+		audio.set_alt_group(2).set_selection_params(params);
+
+		let mut catalog = MoqCatalog::new();
+		catalog
+			.set_tracks(&[
+				rung("1080p", 1080, Some(6_000_000)),
+				audio,
+				rung("720p", 720, Some(3_000_000)),
+				rung("360p", 360, Some(1_000_000)),
+				// ties with 720p, listed later
+				rung("720p-alt", 720, Some(3_000_000)),
+				// no bitrate, never picked by bitrate
+				rung("unknown", 480, None),
+			])
+			.unwrap();
+
+		// a mime type wins over the codec, own parameters over the inherited ones
+		let mut csf = CommonStructFields::new("", Packaging::CMAF);
+		let mut params = SelectionParams::new();
+		params.set_mime_type("video/mp4").unwrap();
+		csf.set_selection_params(params);
+		let mut text = Track::new("subtitles", Packaging::CMAF);
+		text.set_selection_params(SelectionParams {
+			codec: Some("wvtt".to_string()),
+			..Default::default()
+		});
+		catalog.insert_track(text).unwrap();
+
+		fn names(tracks: Vec<&Track>) -> Vec<&str> {
+			tracks.into_iter().map(Track::name).collect()
+		}
+		assert_eq!(
+			names(catalog.video_tracks()),
+			["1080p", "720p", "360p", "720p-alt", "unknown"]
+		);
+		assert_eq!(names(catalog.audio_tracks()), ["audio"]);
+		assert_eq!(names(catalog.tracks_in_alt_group(2)), ["audio"]);
+		assert_eq!(catalog.tracks_in_alt_group(1).len(), 5);
+		assert!(catalog.tracks_in_alt_group(3).is_empty());
+
+		let best = |bitrate| catalog.best_video_under_bitrate(bitrate).map(Track::name);
+		assert_eq!(best(10_000_000), Some("1080p"));
+		assert_eq!(best(6_000_000), Some("1080p"));
+		assert_eq!(best(5_999_999), Some("720p"));
+		assert_eq!(best(2_000_000), Some("360p"));
+		assert_eq!(best(999_999), None);
+		assert_eq!(catalog.lowest_bitrate_video().map(Track::name), Some("360p"));
+
+		catalog.set_common_track_fields(csf);
+		assert_eq!(catalog.video_tracks().len(), 6);
+		assert_eq!(names(catalog.audio_tracks()), ["audio"]);
+
+		let params = catalog.track("720p").unwrap().selection_params().unwrap();
+		assert_eq!(params.codec(), Some("avc1.64001F"));
+		assert_eq!(params.height(), Some(720));
+		assert_eq!(params.width(), None);
+	}
+
+	#[test]
+	fn test_sample_rate() {
+		// written while the sample rate was a u16
+		let params: SelectionParams =
+			serde_json::from_str(r#"{ "codec": "opus", "samplerate": 48000, "channelConfig": "2" }"#).unwrap();
+		assert_eq!(params.sample_rate(), Some(48000));
+		assert_eq!(params.channel_count(), Some(2));
+
+		let mut params = SelectionParams::new();
+		params
+			.set_codec("mp4a.40.2")
+			.set_sample_rate(96000)
+			.set_channel_count(6);
+
+		let encoded = serde_json::to_value(&params).unwrap();
+		assert_eq!(encoded["samplerate"], 96000);
+		assert_eq!(encoded["channelConfig"], "6");
+
+		let decoded: SelectionParams = serde_json::from_value(encoded).unwrap();
+		assert_eq!(decoded, params);
+		assert_eq!(decoded.sample_rate(), Some(96000));
+
+		params.set_channel_config("5.1");
+		assert_eq!(params.channel_count(), None);
+	}
+
+	#[test]
+	fn test_display() {
+		let video = |name: &str, width, height, bitrate| {
+			let mut params = SelectionParams::new();
+			params
+				.set_codec("avc1.64001f")
+				.set_width(width)
+				.set_height(height)
+				.set_framerate(30)
+				.set_bitrate(bitrate);
+			let mut track = Track::new(name, Packaging::CMAF);
+			track.set_alt_group(1).set_selection_params(params);
+			track
+		};
+		let mut audio = Track::new("audio", Packaging::CMAF);
+		let mut params = SelectionParams::new();
+		params
+			.set_codec("mp4a.40.2")
+			.set_sample_rate(44100)
+			.set_channel_count(2)
+			.set_bitrate(128_000);
+		audio.set_alt_group(2).set_selection_params(params);
+
+		let mut catalog = MoqCatalog::new();
+		catalog
+			.set_tracks(&[
+				video("1080p", 1920, 1080, 6_000_000),
+				video("720p", 1280, 720, 3_000_000),
+				video("360p", 640, 360, 800_000),
+				audio,
+				Track::new("subtitles", Packaging::CMAF),
+			])
+			.unwrap();
+		let mut csf = CommonStructFields::new("", Packaging::CMAF);
+		csf.set_render_group(1);
+		catalog.set_common_track_fields(csf);
+
+		assert_eq!(
+			catalog.to_string(),
+			concat!(
+				"catalog v1, streaming format 1 v1, 5 tracks (3 video, 1 audio), 9928 kbps declared\n",
+				"  name       codec        media           bitrate  groups\n",
+				"  1080p      avc1.64001f  1920x1080@30  6000 kbps  alt=1 render=1\n",
+				"  720p       avc1.64001f  1280x720@30   3000 kbps  alt=1 render=1\n",
+				"  360p       avc1.64001f  640x360@30     800 kbps  alt=1 render=1\n",
+				"  audio      mp4a.40.2    44.1kHz 2ch    128 kbps  alt=2 render=1\n",
+				"  subtitles  -            -                     -  render=1\n",
+			)
+		);
+
+		let track = catalog.track("720p").unwrap();
+		assert_eq!(track.to_string(), "720p avc1.64001f 1280x720@30 3000 kbps alt=1");
+		assert_eq!(
+			track.selection_params().unwrap().to_string(),
+			"avc1.64001f 1280x720@30 3000 kbps"
+		);
+
+		let summary = catalog.summary();
+		assert_eq!((summary.video_tracks, summary.audio_tracks), (3, 1));
+		assert_eq!(summary.bandwidth, 9_928_000);
+
+		let mut catalog = MoqCatalog::new();
+		catalog.insert_catalog(Catalog::new("sports")).unwrap();
+		assert_eq!(
+			catalog.to_string(),
+			"catalog v1, streaming format 1 v1, 1 catalogs\n  sports\n"
+		);
+	}
 }
@@ -0,0 +1,308 @@
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::{ready, Stream};
+
+use crate::{CatalogDelta, CatalogFormat, CommonStructFields, MoqCatalog, Result, Track};
+
+/// A change between two versions of a catalog.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CatalogEvent {
+	TrackAdded(Track),
+	TrackRemoved(String),
+	TrackChanged {
+		name: String,
+		before: Track,
+		after: Track,
+	},
+	CommonFieldsChanged {
+		before: Option<CommonStructFields>,
+		after: Option<CommonStructFields>,
+	},
+}
+
+/// Follows the updates of a catalog track and reports what changed.
+///
+/// Every update is either a full catalog in any [CatalogFormat], replacing the current one,
+/// or a [CatalogDelta] applied to it. Updates without any change report no events.
+/// An update identical to the previous one, ex. a catalog re-published for late subscribers, is not applied again.
+/// The catalogs listed by a root catalog are not followed, see [Self::catalog].
+#[derive(Debug, Default)]
+pub struct CatalogWatcher {
+	catalog: Option<MoqCatalog>,
+	/// the encoded update applied last
+	last: Vec<u8>,
+}
+
+impl CatalogWatcher {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// the catalog after the last update, None before the first full catalog
+	pub fn catalog(&self) -> Option<&MoqCatalog> {
+		self.catalog.as_ref()
+	}
+
+	/// apply the encoded catalog or delta `buf`, returning the changes in the order
+	/// common fields, removed tracks, then added or changed tracks in the order of the catalog
+	///
+	/// A delta before the first full catalog is skipped.
+	pub fn update(&mut self, buf: &[u8]) -> Result<Vec<CatalogEvent>> {
+		if self.catalog.is_some() && self.last == buf {
+			return Ok(Vec::new());
+		}
+
+		let is_delta = buf.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'[');
+		let catalog = match (is_delta, &self.catalog) {
+			(true, Some(previous)) => CatalogDelta::decode(buf)?.apply(previous)?,
+			(true, None) => {
+				log::warn!("skipping catalog delta without a full catalog");
+				return Ok(Vec::new());
+			}
+			(false, _) => MoqCatalog::decode_with(buf, CatalogFormat::sniff(buf).unwrap_or_default())?,
+		};
+
+		let events = diff(self.catalog.as_ref(), &catalog);
+		self.catalog = Some(catalog);
+		self.last = buf.to_vec();
+		Ok(events)
+	}
+}
+
+fn diff(before: Option<&MoqCatalog>, after: &MoqCatalog) -> Vec<CatalogEvent> {
+	let mut events = Vec::new();
+
+	let common = before.and_then(MoqCatalog::common_track_fields);
+	if common != after.common_track_fields() {
+		events.push(CatalogEvent::CommonFieldsChanged {
+			before: common.cloned(),
+			after: after.common_track_fields().cloned(),
+		});
+	}
+
+	let previous = before.map(MoqCatalog::tracks).unwrap_or_default();
+	for track in previous {
+		if after.track(track.name()).is_none() {
+			events.push(CatalogEvent::TrackRemoved(track.name().to_string()));
+		}
+	}
+
+	for track in after.tracks() {
+		match previous.iter().find(|previous| previous.name() == track.name()) {
+			None => events.push(CatalogEvent::TrackAdded(track.clone())),
+			Some(previous) if previous != track => events.push(CatalogEvent::TrackChanged {
+				name: track.name().to_string(),
+				before: previous.clone(),
+				after: track.clone(),
+			}),
+			Some(_) => (),
+		}
+	}
+
+	events
+}
+
+/// The events of a [CatalogWatcher] following a stream of catalog updates, ex. the objects of a catalog track.
+///
+/// Ends with the updates, a malformed update is returned as an error and the stream continues.
+pub struct CatalogEvents<S> {
+	updates: S,
+	watcher: CatalogWatcher,
+	pending: VecDeque<CatalogEvent>,
+}
+
+impl<S> CatalogEvents<S> {
+	pub fn new(updates: S) -> Self {
+		Self {
+			updates,
+			watcher: CatalogWatcher::new(),
+			pending: VecDeque::new(),
+		}
+	}
+
+	/// the catalog after the update of the last event
+	pub fn catalog(&self) -> Option<&MoqCatalog> {
+		self.watcher.catalog()
+	}
+}
+
+impl<S, B> Stream for CatalogEvents<S>
+where
+	S: Stream<Item = B> + Unpin,
+	B: AsRef<[u8]>,
+{
+	type Item = Result<CatalogEvent>;
+
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+		let this = &mut *self;
+
+		loop {
+			if let Some(event) = this.pending.pop_front() {
+				return Poll::Ready(Some(Ok(event)));
+			}
+
+			let Some(update) = ready!(Pin::new(&mut this.updates).poll_next(cx)) else {
+				return Poll::Ready(None);
+			};
+			match this.watcher.update(update.as_ref()) {
+				Ok(events) => this.pending.extend(events),
+				Err(err) => return Poll::Ready(Some(Err(err))),
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{Packaging, SelectionParams};
+
+	use futures::StreamExt;
+
+	fn track(name: &str, bitrate: u64) -> Track {
+		Track::new(name, Packaging::CMAF).with_selection_params(SelectionParams::new().with_bitrate(bitrate))
+	}
+
+	fn catalog(tracks: &[Track]) -> MoqCatalog {
+		MoqCatalog::new().with_tracks(tracks).unwrap()
+	}
+
+	fn names(events: &[CatalogEvent]) -> Vec<String> {
+		events
+			.iter()
+			.map(|event| match event {
+				CatalogEvent::TrackAdded(track) => format!("+{}", track.name()),
+				CatalogEvent::TrackRemoved(name) => format!("-{name}"),
+				CatalogEvent::TrackChanged { name, .. } => format!("~{name}"),
+				CatalogEvent::CommonFieldsChanged { .. } => "common".to_string(),
+			})
+			.collect()
+	}
+
+	#[test]
+	fn test_snapshots() {
+		let mut watcher = CatalogWatcher::new();
+		assert!(watcher.catalog().is_none());
+
+		// everything is new in the first catalog
+		let first = catalog(&[track("720p", 3_000_000), track("audio", 128_000)]);
+		let events = watcher.update(&first.encode().unwrap()).unwrap();
+		assert_eq!(names(&events), ["+720p", "+audio"]);
+		assert_eq!(events[0], CatalogEvent::TrackAdded(track("720p", 3_000_000)));
+		assert_eq!(watcher.catalog(), Some(&first));
+
+		// the same catalog again, in another format
+		assert!(watcher
+			.update(&first.encode_with(CatalogFormat::Cbor).unwrap())
+			.unwrap()
+			.is_empty());
+
+		// removed before added or changed, those in the order of the catalog
+		let second = catalog(&[track("1080p", 6_000_000), track("audio", 96_000)]);
+		let events = watcher.update(&second.encode().unwrap()).unwrap();
+		assert_eq!(names(&events), ["-720p", "+1080p", "~audio"]);
+		assert_eq!(
+			events[2],
+			CatalogEvent::TrackChanged {
+				name: "audio".to_string(),
+				before: track("audio", 128_000),
+				after: track("audio", 96_000),
+			}
+		);
+
+		// a catalog without tracks, ex. at the end of the broadcast
+		let events = watcher.update(&MoqCatalog::new().encode().unwrap()).unwrap();
+		assert_eq!(names(&events), ["-1080p", "-audio"]);
+	}
+
+	#[test]
+	fn test_common_fields() {
+		let mut watcher = CatalogWatcher::new();
+		let csf = CommonStructFields::new("", Packaging::CMAF).with_namespace("live");
+
+		let first = catalog(&[track("audio", 128_000)]).with_common_track_fields(csf.clone());
+		let events = watcher.update(&first.encode().unwrap()).unwrap();
+		assert_eq!(names(&events), ["common", "+audio"]);
+
+		let moved = csf.clone().with_namespace("moved");
+		let second = catalog(&[track("audio", 128_000)]).with_common_track_fields(moved.clone());
+		let events = watcher.update(&second.encode().unwrap()).unwrap();
+		assert_eq!(
+			events,
+			[CatalogEvent::CommonFieldsChanged {
+				before: Some(csf),
+				after: Some(moved.clone()),
+			}]
+		);
+
+		let events = watcher
+			.update(&catalog(&[track("audio", 128_000)]).encode().unwrap())
+			.unwrap();
+		assert_eq!(
+			events,
+			[CatalogEvent::CommonFieldsChanged {
+				before: Some(moved),
+				after: None,
+			}]
+		);
+	}
+
+	#[test]
+	fn test_deltas() {
+		let mut watcher = CatalogWatcher::new();
+		let first = catalog(&[track("720p", 3_000_000)]);
+		let second = catalog(&[track("720p", 2_500_000), track("audio", 128_000)]);
+		let delta = second.encode_delta(&first).unwrap();
+
+		// nothing to apply the delta to yet
+		assert!(watcher.update(&delta).unwrap().is_empty());
+		assert!(watcher.catalog().is_none());
+
+		watcher.update(&first.encode().unwrap()).unwrap();
+		let events = watcher.update(&delta).unwrap();
+		assert_eq!(names(&events), ["~720p", "+audio"]);
+		assert_eq!(watcher.catalog(), Some(&second));
+
+		// an empty delta changes nothing
+		assert!(watcher.update(b"[]").unwrap().is_empty());
+
+		// a delta that does not apply keeps the catalog
+		let remove = br#"[{"op": "remove", "path": "/tracks/7"}]"#;
+		assert!(watcher.update(remove).is_err());
+		assert_eq!(watcher.catalog(), Some(&second));
+
+		// a re-published delta is not applied twice
+		let add = br#"[{"op": "add", "path": "/tracks/-", "value": {"name": "1080p", "packaging": "cmaf"}}]"#;
+		assert_eq!(names(&watcher.update(add).unwrap()), ["+1080p"]);
+		assert!(watcher.update(add).unwrap().is_empty());
+		assert_eq!(watcher.catalog().unwrap().tracks().len(), 3);
+	}
+
+	#[test]
+	fn test_stream() {
+		let first = catalog(&[track("720p", 3_000_000)]);
+		let second = catalog(&[track("1080p", 6_000_000)]);
+		let updates = futures::stream::iter([
+			first.encode().unwrap(),
+			first.encode().unwrap(),
+			b"not a catalog".to_vec(),
+			second.encode().unwrap(),
+		]);
+
+		let mut events = CatalogEvents::new(updates);
+		let mut seen = Vec::new();
+		let mut errors = 0;
+		while let Some(event) = futures::executor::block_on(events.next()) {
+			match event {
+				Ok(event) => seen.push(event),
+				Err(_) => errors += 1,
+			}
+		}
+
+		assert_eq!(names(&seen), ["+720p", "-720p", "+1080p"]);
+		assert_eq!(errors, 1);
+		assert_eq!(events.catalog(), Some(&second));
+	}
+}
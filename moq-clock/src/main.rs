@@ -52,7 +52,11 @@ async fn main() -> anyhow::Result<()> {
 	let config = Cli::parse();
 	let tls = config.tls.load()?;
 
-	let quic = quic::Endpoint::new(quic::Config { bind: config.bind, tls })?;
+	let quic = quic::Endpoint::new(quic::Config {
+		bind: config.bind,
+		tls,
+		transport: Default::default(),
+	})?;
 
 	log::info!("connecting to server: url={}", config.url);
 
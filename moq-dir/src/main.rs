@@ -48,7 +48,11 @@ async fn main() -> anyhow::Result<()> {
 	let cli = Cli::parse();
 	let tls = cli.tls.load()?;
 
-	let quic = quic::Endpoint::new(quic::Config { bind: cli.bind, tls })?;
+	let quic = quic::Endpoint::new(quic::Config {
+		bind: cli.bind,
+		tls,
+		transport: Default::default(),
+	})?;
 	let mut quic = quic.server.context("missing server certificate")?;
 
 	let listings = Listings::new(cli.namespace);
@@ -32,13 +32,92 @@ impl Default for Args {
 impl Args {
 	pub fn load(&self) -> anyhow::Result<Config> {
 		let tls = self.tls.load()?;
-		Ok(Config { bind: self.bind, tls })
+		Ok(Config {
+			bind: self.bind,
+			tls,
+			transport: Default::default(),
+		})
 	}
 }
 
 pub struct Config {
 	pub bind: net::SocketAddr,
 	pub tls: tls::Config,
+	pub transport: Transport,
+}
+
+/// The congestion controller of the QUIC connections.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum CongestionControl {
+	#[default]
+	Bbr,
+	Cubic,
+	NewReno,
+}
+
+/// QUIC transport parameters of the client and server connections.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transport {
+	/// close the connection after this long without traffic, None to never time out
+	pub idle_timeout: Option<time::Duration>,
+	/// send a keep-alive after this long without traffic, None to disable
+	pub keep_alive_interval: Option<time::Duration>,
+	pub congestion_control: CongestionControl,
+	/// initial congestion window in bytes, None for the default of the congestion controller
+	pub initial_window: Option<u64>,
+}
+
+impl Default for Transport {
+	fn default() -> Self {
+		Self {
+			idle_timeout: Some(time::Duration::from_secs(10)),
+			keep_alive_interval: Some(time::Duration::from_secs(4)), // TODO make this smarter
+			congestion_control: CongestionControl::Bbr,
+			initial_window: None,
+		}
+	}
+}
+
+impl Transport {
+	/// fails if the idle timeout is too long to be encoded
+	pub fn config(&self) -> anyhow::Result<quinn::TransportConfig> {
+		let mut transport = quinn::TransportConfig::default();
+
+		let idle_timeout = self
+			.idle_timeout
+			.map(quinn::IdleTimeout::try_from)
+			.transpose()
+			.context("idle timeout too long")?;
+		transport.max_idle_timeout(idle_timeout);
+		transport.keep_alive_interval(self.keep_alive_interval);
+		// TODO validate the BBR implementation
+		match self.congestion_control {
+			CongestionControl::Bbr => {
+				let mut config = quinn::congestion::BbrConfig::default();
+				if let Some(window) = self.initial_window {
+					config.initial_window(window);
+				}
+				transport.congestion_controller_factory(Arc::new(config))
+			}
+			CongestionControl::Cubic => {
+				let mut config = quinn::congestion::CubicConfig::default();
+				if let Some(window) = self.initial_window {
+					config.initial_window(window);
+				}
+				transport.congestion_controller_factory(Arc::new(config))
+			}
+			CongestionControl::NewReno => {
+				let mut config = quinn::congestion::NewRenoConfig::default();
+				if let Some(window) = self.initial_window {
+					config.initial_window(window);
+				}
+				transport.congestion_controller_factory(Arc::new(config))
+			}
+		};
+		transport.mtu_discovery_config(None); // Disable MTU discovery
+
+		Ok(transport)
+	}
 }
 
 pub struct Endpoint {
@@ -48,14 +127,7 @@ pub struct Endpoint {
 
 impl Endpoint {
 	pub fn new(config: Config) -> anyhow::Result<Self> {
-		// Enable BBR congestion control
-		// TODO validate the implementation
-		let mut transport = quinn::TransportConfig::default();
-		transport.max_idle_timeout(Some(time::Duration::from_secs(10).try_into().unwrap()));
-		transport.keep_alive_interval(Some(time::Duration::from_secs(4))); // TODO make this smarter
-		transport.congestion_controller_factory(Arc::new(quinn::congestion::BbrConfig::default()));
-		transport.mtu_discovery_config(None); // Disable MTU discovery
-		let transport = Arc::new(transport);
+		let transport = Arc::new(config.transport.config()?);
 
 		let server_config = config.tls.server.map(|mut server| {
 			server.alpn_protocols = vec![web_transport_quinn::ALPN.to_vec(), moq_transport::setup::ALPN.to_vec()];
@@ -91,11 +163,16 @@ impl Endpoint {
 
 pub struct Server {
 	quic: quinn::Endpoint,
-	accept: FuturesUnordered<BoxFuture<'static, anyhow::Result<web_transport::Session>>>,
+	accept: FuturesUnordered<BoxFuture<'static, anyhow::Result<(web_transport::Session, quinn::Connection)>>>,
 }
 
 impl Server {
 	pub async fn accept(&mut self) -> Option<web_transport::Session> {
+		self.accept_connection().await.map(|(session, _)| session)
+	}
+
+	/// like [Self::accept], with the underlying QUIC connection, ex. for its stats
+	pub async fn accept_connection(&mut self) -> Option<(web_transport::Session, quinn::Connection)> {
 		loop {
 			tokio::select! {
 				res = self.quic.accept() => {
@@ -112,7 +189,9 @@ impl Server {
 		}
 	}
 
-	async fn accept_session(mut conn: quinn::Connecting) -> anyhow::Result<web_transport::Session> {
+	async fn accept_session(
+		mut conn: quinn::Connecting,
+	) -> anyhow::Result<(web_transport::Session, quinn::Connection)> {
 		let handshake = conn
 			.handshake_data()
 			.await?
@@ -141,6 +220,8 @@ impl Server {
 			server_name,
 		);
 
+		let connection = conn.clone();
+
 		let session = match alpn.as_bytes() {
 			web_transport_quinn::ALPN => {
 				// Wait for the CONNECT request.
@@ -159,7 +240,7 @@ impl Server {
 			_ => anyhow::bail!("unsupported ALPN: {}", alpn),
 		};
 
-		Ok(session.into())
+		Ok((session.into(), connection))
 	}
 
 	pub fn local_addr(&self) -> anyhow::Result<net::SocketAddr> {
@@ -209,3 +290,58 @@ impl Client {
 		Ok(session.into())
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_transport() {
+		let transport = format!("{:?}", Transport::default().config().unwrap());
+		assert!(transport.contains("max_idle_timeout: Some(10000)"));
+		assert!(transport.contains("keep_alive_interval: Some(4s)"));
+
+		let transport = Transport {
+			idle_timeout: None,
+			keep_alive_interval: Some(time::Duration::from_millis(500)),
+			congestion_control: CongestionControl::Cubic,
+			initial_window: Some(1 << 20),
+		};
+		let config = format!("{:?}", transport.config().unwrap());
+		assert!(config.contains("max_idle_timeout: None"));
+		assert!(config.contains("keep_alive_interval: Some(500ms)"));
+
+		let idle_timeout = Some(time::Duration::from_secs(u64::MAX));
+		assert!(Transport {
+			idle_timeout,
+			..transport
+		}
+		.config()
+		.is_err());
+	}
+
+	/// the client connections use the transport parameters of the config
+	#[test]
+	fn test_endpoint() {
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		let _guard = runtime.enter();
+
+		let tls = tls::Args {
+			disable_verify: true,
+			..Default::default()
+		};
+		let transport = Transport {
+			keep_alive_interval: None,
+			..Default::default()
+		};
+		let endpoint = Endpoint::new(Config {
+			bind: "127.0.0.1:0".parse().unwrap(),
+			tls: tls.load().unwrap(),
+			transport,
+		})
+		.unwrap();
+
+		let config = format!("{:?}", endpoint.client.transport);
+		assert!(config.contains("keep_alive_interval: None"));
+	}
+}
@@ -45,7 +45,16 @@ pub struct Args {
 pub struct Config {
 	pub client: rustls::ClientConfig,
 	pub server: Option<rustls::ServerConfig>,
-	pub fingerprints: Vec<String>,
+	pub fingerprints: Vec<Fingerprint>,
+}
+
+/// The SHA256 fingerprint of a served certificate.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fingerprint {
+	/// signature algorithm of the certificate key, ex. `ecdsa` or `rsa`
+	pub algorithm: String,
+	/// hex encoded
+	pub sha256: String,
 }
 
 impl Args {
@@ -165,13 +174,15 @@ impl ServeCerts {
 	}
 
 	// Return the SHA256 fingerprint of our certificates.
-	pub fn fingerprints(&self) -> Vec<String> {
+	pub fn fingerprints(&self) -> Vec<Fingerprint> {
 		self.list
 			.iter()
 			.map(|ck| {
 				let fingerprint = digest(&SHA256, ck.cert[0].as_ref());
-				let fingerprint = hex::encode(fingerprint.as_ref());
-				fingerprint
+				Fingerprint {
+					algorithm: format!("{:?}", ck.key.algorithm()).to_lowercase(),
+					sha256: hex::encode(fingerprint.as_ref()),
+				}
 			})
 			.collect()
 	}
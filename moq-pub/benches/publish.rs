@@ -0,0 +1,145 @@
+//! Publishes a recorded segment stream through [Publisher::publish], as handed over by the watcher,
+//! for a single rep and for a ladder of 6 reps.
+//!
+//! Run with `cargo bench -p moq-pub`, add `-- --profile-time 10` to profile the atom parsing with an external profiler.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use moq_pub::dash::{Publisher, Settings};
+
+const SETTINGS: &str = "gop_num=1
+fps=25
+target_segment_duration=2.0
+===AUDIO===
+name,sampling,bitrate
+===VIDEO===
+name,resolution,bitrate,max_rate,buffer_size
+2160p,3840x2160,20000000,20000000,40000000
+";
+
+/// the rungs of a ladder, each published from the same segment stream
+const LADDER: [&str; 6] = ["2160p", "1440p", "1080p", "720p", "480p", "360p"];
+
+/// a second of a 20 Mbps rung: 25 fragments of prft, moof and a 100 kB mdat
+fn fragments() -> Vec<u8> {
+	let chunk = include_bytes!("../tests/fixtures/chunk_1.m4s");
+	let (moof, _) = chunk.split_at(96);
+	let mdat = [&(100_008u32.to_be_bytes())[..], b"mdat", &[0; 100_000]].concat();
+	let prft = [
+		&[0, 0, 0, 32][..],
+		b"prft",
+		&[1, 0, 0, 0, 0, 0, 0, 1],
+		&[0xe0; 4],
+		&[0; 12],
+	]
+	.concat();
+
+	[prft, moof.to_vec(), mdat].concat().repeat(25)
+}
+
+/// the settings of the first `reps` rungs of the [LADDER]
+fn settings(reps: usize) -> Settings<std::path::PathBuf> {
+	let ladder: String = LADDER[..reps]
+		.iter()
+		.map(|name| format!("{name},3840x2160,20000000,20000000,40000000\n"))
+		.collect();
+	let settings = SETTINGS.replace("2160p,3840x2160,20000000,20000000,40000000\n", &ladder);
+	Settings::from_bytes(settings.into_bytes(), "input".into(), "output".into(), true, false).unwrap()
+}
+
+fn publisher(settings: Settings<std::path::PathBuf>) -> (Publisher, moq_transport::serve::TracksReader) {
+	let (writer, _, reader) = moq_transport::serve::Tracks::new("bench".to_string()).produce();
+	let publisher = Publisher::new(
+		writer,
+		settings,
+		Default::default(),
+		Default::default(),
+		Default::default(),
+		Default::default(),
+	)
+	.unwrap();
+
+	(publisher, reader)
+}
+
+fn publish(c: &mut Criterion) {
+	let runtime = tokio::runtime::Builder::new_current_thread()
+		.enable_all()
+		.build()
+		.unwrap();
+	let settings = settings(1);
+	let stream = fragments();
+
+	let mut group = c.benchmark_group("publish");
+	group.throughput(Throughput::Bytes(stream.len() as u64));
+
+	// whole fragments, and reads cutting through the mdats
+	for read in [stream.len(), 64 * 1024] {
+		group.bench_with_input(BenchmarkId::from_parameter(read), &read, |b, &read| {
+			b.iter_batched(
+				|| {
+					let chunks: Vec<_> = stream.chunks(read).map(bytes::Bytes::copy_from_slice).collect();
+					(chunks, settings.clone())
+				},
+				|(chunks, settings)| {
+					runtime.block_on(async {
+						let (mut publisher, _reader) = publisher(settings);
+
+						let init = include_bytes!("../tests/fixtures/avc_init.m4s");
+						publisher.publish(0, bytes::Bytes::from_static(init)).unwrap();
+						for chunk in chunks {
+							publisher.publish(0, chunk).unwrap();
+						}
+						publisher.flush().await.unwrap();
+					})
+				},
+				criterion::BatchSize::SmallInput,
+			)
+		});
+	}
+
+	group.finish();
+}
+
+/// a second of every rung of the [LADDER], read in turns like the watcher does
+fn publish_reps(c: &mut Criterion) {
+	let runtime = tokio::runtime::Builder::new_multi_thread()
+		.enable_all()
+		.build()
+		.unwrap();
+	let settings = settings(LADDER.len());
+	let stream = fragments();
+
+	let mut group = c.benchmark_group("publish_reps");
+	group.throughput(Throughput::Bytes((stream.len() * LADDER.len()) as u64));
+
+	group.bench_function(BenchmarkId::from_parameter(LADDER.len()), |b| {
+		b.iter_batched(
+			|| {
+				let chunks: Vec<_> = stream.chunks(64 * 1024).map(bytes::Bytes::copy_from_slice).collect();
+				(chunks, settings.clone())
+			},
+			|(chunks, settings)| {
+				runtime.block_on(async {
+					let (mut publisher, _reader) = publisher(settings);
+
+					let init = include_bytes!("../tests/fixtures/avc_init.m4s");
+					for rep_id in 0..LADDER.len() {
+						publisher.publish(rep_id, bytes::Bytes::from_static(init)).unwrap();
+					}
+					for chunk in chunks {
+						for rep_id in 0..LADDER.len() {
+							publisher.publish(rep_id, chunk.clone()).unwrap();
+						}
+					}
+					publisher.flush().await.unwrap();
+				})
+			},
+			criterion::BatchSize::SmallInput,
+		)
+	});
+
+	group.finish();
+}
+
+criterion_group!(benches, publish, publish_reps);
+criterion_main!(benches);
@@ -0,0 +1,344 @@
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::{path, time};
+
+use moq_transport::serve::{self, ServeError};
+use tokio::sync::{mpsc, oneshot};
+
+/// Writes every object of the tracks below a directory, as `<track>/<group>/<object>.bin`.
+///
+/// Each group gets an `index.json` with its priority and the wallclock times it and its objects were written,
+/// once the group ends. The files are written on a separate task, publishing never waits for the disk.
+/// The first failure, ex. a full disk, is logged and disables the archive.
+#[derive(Clone)]
+pub struct Archive {
+	records: mpsc::UnboundedSender<Record>,
+}
+
+enum Record {
+	Group {
+		track: String,
+		group: u64,
+		priority: u64,
+		created: u64,
+	},
+	Object {
+		track: String,
+		group: u64,
+		index: u64,
+		payload: bytes::Bytes,
+		created: u64,
+	},
+	End {
+		track: String,
+		group: u64,
+	},
+	Flush(oneshot::Sender<()>),
+}
+
+/// the `index.json` of a group
+#[derive(serde::Serialize)]
+struct Index {
+	track: String,
+	group: u64,
+	priority: u64,
+	/// milliseconds since the unix epoch
+	created: u64,
+	objects: Vec<ObjectIndex>,
+}
+
+#[derive(serde::Serialize)]
+struct ObjectIndex {
+	size: usize,
+	/// milliseconds since the unix epoch
+	created: u64,
+}
+
+impl Archive {
+	/// archive below `dir`, which is created if missing; must be called within a tokio runtime
+	pub fn new<P>(dir: P) -> Self
+	where
+		P: AsRef<path::Path>,
+	{
+		let dir = dir.as_ref().to_path_buf();
+		let (records, rx) = mpsc::unbounded_channel();
+
+		tokio::spawn(async move {
+			if let Err(e) = run(&dir, rx).await {
+				log::error!("archiving to {} failed, disabling it: {e}", dir.display());
+			}
+		});
+
+		Self { records }
+	}
+
+	/// wait until everything recorded so far is on disk, returns immediately if the archive is disabled
+	pub async fn flush(&self) {
+		let (tx, rx) = oneshot::channel();
+		self.record(Record::Flush(tx));
+		let _ = rx.await;
+	}
+
+	/// archive the objects of the group `group` of `track`, its index is written once the result is dropped
+	pub(crate) fn group(&self, track: &str, group: u64, priority: u64) -> GroupArchive {
+		self.record(Record::Group {
+			track: track.to_string(),
+			group,
+			priority,
+			created: now(),
+		});
+
+		GroupArchive {
+			archive: self.clone(),
+			track: track.to_string(),
+			group,
+			next: 0,
+		}
+	}
+
+	/// a disabled archive dropped the receiver, the records are discarded
+	fn record(&self, record: Record) {
+		let _ = self.records.send(record);
+	}
+}
+
+/// The objects of a single group written to an [Archive].
+pub(crate) struct GroupArchive {
+	archive: Archive,
+	track: String,
+	group: u64,
+	next: u64,
+}
+
+impl GroupArchive {
+	/// archive `payload` as the next object of the group
+	pub(crate) fn object(&mut self, payload: bytes::Bytes) {
+		self.archive.record(Record::Object {
+			track: self.track.clone(),
+			group: self.group,
+			index: self.next,
+			payload,
+			created: now(),
+		});
+		self.next += 1;
+	}
+}
+
+impl Drop for GroupArchive {
+	fn drop(&mut self) {
+		self.archive.record(Record::End {
+			track: self.track.clone(),
+			group: self.group,
+		});
+	}
+}
+
+async fn run(dir: &path::Path, mut records: mpsc::UnboundedReceiver<Record>) -> std::io::Result<()> {
+	let mut groups: HashMap<(String, u64), Index> = HashMap::new();
+
+	while let Some(record) = records.recv().await {
+		match record {
+			Record::Group {
+				track,
+				group,
+				priority,
+				created,
+			} => {
+				tokio::fs::create_dir_all(group_dir(dir, &track, group)).await?;
+				let index = Index {
+					track: track.clone(),
+					group,
+					priority,
+					created,
+					objects: Vec::new(),
+				};
+				groups.insert((track, group), index);
+			}
+			Record::Object {
+				track,
+				group,
+				index,
+				payload,
+				created,
+			} => {
+				let path = group_dir(dir, &track, group).join(format!("{index}.bin"));
+				tokio::fs::write(path, &payload).await?;
+
+				if let Some(index) = groups.get_mut(&(track, group)) {
+					index.objects.push(ObjectIndex {
+						size: payload.len(),
+						created,
+					});
+				}
+			}
+			Record::End { track, group } => {
+				if let Some(index) = groups.remove(&(track, group)) {
+					write_index(dir, &index).await?;
+				}
+			}
+			Record::Flush(done) => {
+				let _ = done.send(());
+			}
+		}
+	}
+
+	// the groups still open when the publisher went away
+	for index in groups.values() {
+		write_index(dir, index).await?;
+	}
+
+	Ok(())
+}
+
+fn group_dir(dir: &path::Path, track: &str, group: u64) -> path::PathBuf {
+	dir.join(track).join(group.to_string())
+}
+
+async fn write_index(dir: &path::Path, index: &Index) -> std::io::Result<()> {
+	let path = group_dir(dir, &index.track, index.group).join("index.json");
+	let json = serde_json::to_vec_pretty(index).map_err(std::io::Error::other)?;
+	tokio::fs::write(path, json).await
+}
+
+/// milliseconds since the unix epoch
+fn now() -> u64 {
+	time::SystemTime::now()
+		.duration_since(time::UNIX_EPOCH)
+		.map(|d| d.as_millis() as u64)
+		.unwrap_or_default()
+}
+
+/// A [serve::GroupsWriter] writing the objects of its groups to an [Archive] as well.
+pub struct ArchivingGroupsWriter {
+	inner: serve::GroupsWriter,
+	archive: Option<Archive>,
+}
+
+impl ArchivingGroupsWriter {
+	/// archive the groups created from now on, None only publishes them
+	pub fn new(inner: serve::GroupsWriter, archive: Option<Archive>) -> Self {
+		Self { inner, archive }
+	}
+
+	/// archive the groups created from now on, None to stop
+	pub fn set_archive(&mut self, archive: Option<Archive>) {
+		self.archive = archive;
+	}
+
+	pub fn archive(&self) -> Option<&Archive> {
+		self.archive.as_ref()
+	}
+
+	pub fn append(&mut self, priority: u64) -> Result<ArchivingGroupWriter, ServeError> {
+		let group = self.inner.append(priority)?;
+		Ok(self.archived(group))
+	}
+
+	pub fn create(&mut self, group: serve::Group) -> Result<ArchivingGroupWriter, ServeError> {
+		let group = self.inner.create(group)?;
+		Ok(self.archived(group))
+	}
+
+	pub fn close(self, err: ServeError) -> Result<(), ServeError> {
+		self.inner.close(err)
+	}
+
+	fn archived(&self, inner: serve::GroupWriter) -> ArchivingGroupWriter {
+		let archive = self
+			.archive
+			.as_ref()
+			.map(|archive| archive.group(&inner.name, inner.group_id, inner.priority));
+
+		ArchivingGroupWriter { inner, archive }
+	}
+}
+
+impl Deref for ArchivingGroupsWriter {
+	type Target = serve::GroupsWriter;
+
+	fn deref(&self) -> &Self::Target {
+		&self.inner
+	}
+}
+
+/// A [serve::GroupWriter] writing its objects to an [Archive] as well, the group index is written once it is dropped.
+pub struct ArchivingGroupWriter {
+	inner: serve::GroupWriter,
+	archive: Option<GroupArchive>,
+}
+
+impl ArchivingGroupWriter {
+	pub fn write(&mut self, payload: bytes::Bytes) -> Result<(), ServeError> {
+		self.inner.write(payload.clone())?;
+
+		if let Some(archive) = &mut self.archive {
+			archive.object(payload);
+		}
+
+		Ok(())
+	}
+
+	/// write an object made of `parts` without copying them, only the archive gets them copied together
+	pub fn write_parts(&mut self, parts: &[bytes::Bytes]) -> Result<(), ServeError> {
+		let mut object = self.inner.create(parts.iter().map(bytes::Bytes::len).sum())?;
+		for part in parts {
+			object.write(part.clone())?;
+		}
+
+		if let Some(archive) = &mut self.archive {
+			archive.object(crate::atom::concat(parts));
+		}
+
+		Ok(())
+	}
+}
+
+impl Deref for ArchivingGroupWriter {
+	type Target = serve::GroupWriter;
+
+	fn deref(&self) -> &Self::Target {
+		&self.inner
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[tokio::test]
+	async fn test_archive() {
+		let dir = crate::testing::temp_dir("archive");
+
+		let archive = Archive::new(&dir);
+		let (writer, _reader) = serve::Track::new("test".to_string(), "video".to_string()).produce();
+		let mut groups = ArchivingGroupsWriter::new(writer.groups().unwrap(), Some(archive.clone()));
+
+		let mut group = groups.append(7).unwrap();
+		group.write("moof".into()).unwrap();
+		group.write("mdat".into()).unwrap();
+		drop(group);
+		// still open
+		groups.append(6).unwrap().write("next".into()).unwrap();
+
+		archive.flush().await;
+		assert_eq!(std::fs::read(dir.join("video/0/1.bin")).unwrap(), b"mdat");
+		assert_eq!(std::fs::read(dir.join("video/1/0.bin")).unwrap(), b"next");
+
+		let index: serde_json::Value =
+			serde_json::from_slice(&std::fs::read(dir.join("video/0/index.json")).unwrap()).unwrap();
+		assert_eq!(index["priority"], 7);
+		assert_eq!(index["objects"][0]["size"], 4);
+		assert_eq!(index["objects"].as_array().unwrap().len(), 2);
+
+		// a failing archive is disabled, publishing goes on
+		std::fs::remove_dir_all(&dir).unwrap();
+		std::fs::write(&dir, b"not a directory").unwrap();
+		let mut group = groups.append(5).unwrap();
+		group.write("moof".into()).unwrap();
+		archive.flush().await;
+		group.write("mdat".into()).unwrap();
+		assert_eq!(group.len(), 2);
+
+		let _ = std::fs::remove_file(&dir);
+	}
+}
@@ -0,0 +1,823 @@
+// most of the parsing helpers are only used by the dash pipeline
+#![cfg_attr(not(feature = "dash"), allow(dead_code))]
+
+use std::collections::VecDeque;
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+/// size of an atom header with a 64-bit extended size
+const MAX_HEADER: usize = 16;
+
+/// how many chunks of a buffer are looked at to find a header
+const MAX_SLICES: usize = 64;
+
+/// Take the next full atom from the buffer, None if more data is needed.
+///
+/// An atom with size 0 extends to the end of the input,
+/// it is only returned once `eof` signals that no more data follows.
+pub(crate) fn next_atom<B: Buf>(buf: &mut B, eof: bool) -> anyhow::Result<Option<Bytes>> {
+	match peek_atom(buf, 0, eof)? {
+		Some((size, _)) => Ok(Some(buf.copy_to_bytes(size))),
+		None => Ok(None),
+	}
+}
+
+/// The size and type of the atom `offset` bytes into the buffer, None if it is not complete yet.
+///
+/// Headers beyond the first [MAX_SLICES] chunks of the buffer are not found.
+pub(crate) fn peek_atom<B: Buf>(buf: &B, offset: usize, eof: bool) -> anyhow::Result<Option<(usize, [u8; 4])>> {
+	let mut header = [0; MAX_HEADER];
+	let peeked = peek(buf, offset, &mut header);
+	let remaining = buf.remaining().saturating_sub(offset);
+	let kind = header[4..8].try_into()?;
+
+	if peeked < 8 {
+		anyhow::ensure!(!eof || peeked == 0, "truncated atom header at end of input");
+		return Ok(None);
+	}
+
+	let size = u32::from_be_bytes(header[..4].try_into()?);
+
+	let size = match size {
+		// Runs until the end of the input.
+		0 => match eof {
+			true => remaining,
+			false => return Ok(None),
+		},
+
+		// The next 8 bytes are the extended size to be used instead.
+		1 => {
+			if peeked < MAX_HEADER {
+				anyhow::ensure!(!eof, "truncated extended atom header at end of input");
+				return Ok(None);
+			}
+
+			let size_ext = u64::from_be_bytes(header[8..].try_into()?);
+			anyhow::ensure!(size_ext >= 16, "impossible extended box size: {}", size_ext);
+			usize::try_from(size_ext)?
+		}
+
+		2..=7 => anyhow::bail!("impossible box size: {}", size),
+
+		size => size as usize,
+	};
+
+	if remaining < size {
+		anyhow::ensure!(!eof, "truncated atom at end of input");
+		return Ok(None);
+	}
+
+	Ok(Some((size, kind)))
+}
+
+/// Fails if the header at the start of the buffer cannot be that of an atom of at most `max` bytes, ex. of corrupt data.
+///
+/// The type has to be printable, the size 0 of an atom to the end of the input is rejected.
+/// A header that is not complete yet passes.
+pub(crate) fn check_header<B: Buf>(buf: &B, max: usize) -> anyhow::Result<()> {
+	let mut header = [0; MAX_HEADER];
+	let peeked = peek(buf, 0, &mut header);
+	if peeked < 8 {
+		return Ok(());
+	}
+
+	// ex. the © of the iTunes metadata atoms
+	let kind = &header[4..8];
+	anyhow::ensure!(
+		kind.iter().all(|b| b.is_ascii_graphic() || *b == b' ' || *b == 0xa9),
+		"implausible atom type {kind:02x?}"
+	);
+
+	let size = match u32::from_be_bytes(header[..4].try_into()?) {
+		1 if peeked < MAX_HEADER => return Ok(()),
+		1 => u64::from_be_bytes(header[8..].try_into()?),
+		size => size as u64,
+	};
+	anyhow::ensure!((8..=max as u64).contains(&size), "implausible atom size {size}");
+
+	Ok(())
+}
+
+/// The offset of the first header in `buf` of an atom of `kinds` with at most `max` bytes, ex. after corrupt data.
+///
+/// Only the offsets aligned to 4 bytes are candidates.
+/// Otherwise the aligned offset before which no header starts, the bytes after it may hold one once more data arrived.
+pub(crate) fn find_atom(buf: &[u8], kinds: &[[u8; 4]], max: usize) -> Result<usize, usize> {
+	let candidates = (0..buf.len().saturating_sub(7)).step_by(4);
+	for offset in candidates.clone() {
+		let header = &buf[offset..offset + 8];
+		let size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+		if kinds.iter().any(|kind| header[4..] == kind[..]) && (8..=max).contains(&size) {
+			return Ok(offset);
+		}
+	}
+
+	Err(candidates.len() * 4)
+}
+
+/// Fails if the full moof atom `moof` is corrupt in a way its parser does not catch.
+///
+/// The parser loops forever on an atom of size 0 within it, and reads the samples of a trun one by one,
+/// so those with more than `max_samples` are rejected.
+pub(crate) fn check_moof(moof: &[u8], max_samples: usize) -> anyhow::Result<()> {
+	for traf in children(moof)?.into_iter().filter(|traf| &traf[4..8] == b"traf") {
+		for trun in children(traf)?.into_iter().filter(|trun| &trun[4..8] == b"trun") {
+			let count = trun.get(12..16).ok_or_else(|| anyhow::anyhow!("trun too short"))?;
+			let count = u32::from_be_bytes([count[0], count[1], count[2], count[3]]);
+			anyhow::ensure!(count as usize <= max_samples, "implausible sample count {count}");
+		}
+	}
+	Ok(())
+}
+
+/// the atoms within the full atom `atom`, failing unless they fill it exactly
+fn children(atom: &[u8]) -> anyhow::Result<Vec<&[u8]>> {
+	let mut buf = atom.get(8..).unwrap_or_default();
+	let mut children = Vec::new();
+
+	while !buf.is_empty() {
+		anyhow::ensure!(buf.len() >= 8, "truncated atom header");
+		let (size, min) = match u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) {
+			1 => {
+				let ext = buf
+					.get(8..16)
+					.ok_or_else(|| anyhow::anyhow!("truncated extended atom header"))?;
+				(u64::from_be_bytes(ext.try_into()?), 16)
+			}
+			size => (size as u64, 8),
+		};
+		anyhow::ensure!((min..=buf.len() as u64).contains(&size), "impossible atom size {size}");
+
+		let (child, rest) = buf.split_at(size as usize);
+		children.push(child);
+		buf = rest;
+	}
+
+	Ok(children)
+}
+
+/// Cut `buf` into pieces of at most `max` bytes, at the atom boundaries where possible.
+///
+/// Consecutive atoms share a piece while they fit, a larger atom is cut into pieces of its own,
+/// as are the trailing bytes that do not parse as an atom.
+pub(crate) fn split(buf: Bytes, max: usize) -> Vec<Bytes> {
+	split_rope(buf.into(), max).iter().map(Rope::to_bytes).collect()
+}
+
+/// [split] for a [Rope], the pieces are slices of its parts without copying them.
+pub(crate) fn split_rope(rope: Rope, max: usize) -> Vec<Rope> {
+	let max = max.max(1);
+	let mut pieces = Vec::new();
+	let mut piece = Rope::default();
+	let mut chunks = Chunks::from(rope);
+
+	while chunks.has_remaining() {
+		let size = match peek_atom(&chunks, 0, true) {
+			Ok(Some((size, _))) => size,
+			_ => chunks.remaining(),
+		};
+
+		// the atom does not fit next to the previous ones
+		if !piece.is_empty() && piece.len() + size > max {
+			pieces.push(std::mem::take(&mut piece));
+		}
+		piece.append(chunks.take_rope(size));
+
+		// the payload is opaque, ex. of an mdat
+		if piece.len() > max {
+			pieces.extend(std::mem::take(&mut piece).split(max));
+		}
+	}
+
+	if !piece.is_empty() {
+		pieces.push(piece);
+	}
+	pieces
+}
+
+/// Chunks appended without copying them, read as a single [Buf].
+///
+/// An atom within a single chunk is taken without copying, only atoms spanning several chunks are copied together.
+#[derive(Debug, Default)]
+pub(crate) struct Chunks {
+	chunks: VecDeque<Bytes>,
+	remaining: usize,
+}
+
+impl Chunks {
+	pub fn push(&mut self, chunk: Bytes) {
+		if !chunk.is_empty() {
+			self.remaining += chunk.len();
+			self.chunks.push_back(chunk);
+		}
+	}
+
+	pub fn clear(&mut self) {
+		self.chunks.clear();
+		self.remaining = 0;
+	}
+
+	/// take the next `len` bytes without copying them, as the slices of the chunks they span
+	pub fn take_rope(&mut self, len: usize) -> Rope {
+		assert!(len <= self.remaining, "take past the end of the chunks");
+		self.remaining -= len;
+
+		let mut rope = Rope::default();
+		while rope.len() < len {
+			let Some(front) = self.chunks.front_mut() else {
+				break;
+			};
+			let taken = (len - rope.len()).min(front.len());
+			rope.push(front.split_to(taken));
+			if front.is_empty() {
+				self.chunks.pop_front();
+			}
+		}
+		rope
+	}
+}
+
+/// Bytes taken from [Chunks] without copying them, the slices of the chunks they span in order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Rope {
+	parts: Vec<Bytes>,
+	len: usize,
+}
+
+impl Rope {
+	pub fn push(&mut self, part: Bytes) {
+		if !part.is_empty() {
+			self.len += part.len();
+			self.parts.push(part);
+		}
+	}
+
+	pub fn append(&mut self, other: Rope) {
+		self.len += other.len;
+		self.parts.extend(other.parts);
+	}
+
+	pub fn len(&self) -> usize {
+		self.len
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len == 0
+	}
+
+	pub fn parts(&self) -> &[Bytes] {
+		&self.parts
+	}
+
+	/// the bytes as a single slice, only copied together if they span several chunks
+	pub fn to_bytes(&self) -> Bytes {
+		concat(&self.parts)
+	}
+
+	/// cut into ropes of at most `max` bytes, without copying the slices
+	pub fn split(self, max: usize) -> Vec<Rope> {
+		let max = max.max(1);
+		let mut pieces = Vec::new();
+		let mut piece = Rope::default();
+
+		for mut part in self.parts {
+			while !part.is_empty() {
+				let taken = (max - piece.len()).min(part.len());
+				piece.push(part.split_to(taken));
+				if piece.len() == max {
+					pieces.push(std::mem::take(&mut piece));
+				}
+			}
+		}
+
+		if !piece.is_empty() {
+			pieces.push(piece);
+		}
+		pieces
+	}
+}
+
+impl From<Bytes> for Rope {
+	fn from(bytes: Bytes) -> Self {
+		let mut rope = Self::default();
+		rope.push(bytes);
+		rope
+	}
+}
+
+impl From<Rope> for Chunks {
+	fn from(rope: Rope) -> Self {
+		Self {
+			remaining: rope.len,
+			chunks: rope.parts.into(),
+		}
+	}
+}
+
+/// `parts` as a single slice, a single part is not copied
+pub(crate) fn concat(parts: &[Bytes]) -> Bytes {
+	match parts {
+		[] => Bytes::new(),
+		[part] => part.clone(),
+		parts => {
+			let mut bytes = BytesMut::with_capacity(parts.iter().map(Bytes::len).sum());
+			for part in parts {
+				bytes.extend_from_slice(part);
+			}
+			bytes.freeze()
+		}
+	}
+}
+
+impl Buf for Chunks {
+	fn remaining(&self) -> usize {
+		self.remaining
+	}
+
+	fn chunk(&self) -> &[u8] {
+		self.chunks.front().map_or(&[], |chunk| chunk)
+	}
+
+	fn chunks_vectored<'a>(&'a self, dst: &mut [std::io::IoSlice<'a>]) -> usize {
+		let mut count = 0;
+		for (slice, chunk) in dst.iter_mut().zip(&self.chunks) {
+			*slice = std::io::IoSlice::new(chunk);
+			count += 1;
+		}
+		count
+	}
+
+	fn advance(&mut self, mut cnt: usize) {
+		assert!(cnt <= self.remaining, "advance past the end of the chunks");
+		self.remaining -= cnt;
+
+		while let Some(front) = self.chunks.front_mut() {
+			if cnt < front.len() {
+				front.advance(cnt);
+				return;
+			}
+			cnt -= front.len();
+			self.chunks.pop_front();
+		}
+	}
+
+	fn copy_to_bytes(&mut self, len: usize) -> Bytes {
+		assert!(len <= self.remaining, "copy past the end of the chunks");
+
+		match self.chunks.front_mut() {
+			Some(front) if len <= front.len() => {
+				let bytes = front.split_to(len);
+				if front.is_empty() {
+					self.chunks.pop_front();
+				}
+				self.remaining -= len;
+				bytes
+			}
+			_ => {
+				let mut bytes = BytesMut::with_capacity(len);
+				bytes.put((&mut *self).take(len));
+				bytes.freeze()
+			}
+		}
+	}
+}
+
+/// seconds between the NTP epoch (1900) and the UNIX epoch (1970)
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+/// The producer reference time box, the wall clock at which a sample was produced.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Prft {
+	pub track_id: u32,
+	/// when the sample was produced
+	pub wallclock: std::time::SystemTime,
+	/// media time of the sample, in timescale units of the track
+	pub media_time: u64,
+}
+
+impl Prft {
+	/// parse the full prft atom, including its header
+	pub fn parse(atom: &[u8]) -> anyhow::Result<Self> {
+		let mut buf = atom;
+		anyhow::ensure!(buf.remaining() >= 24, "prft too short");
+		buf.advance(4);
+		anyhow::ensure!(buf.get_u32().to_be_bytes() == *b"prft", "not a prft atom");
+
+		let version = buf.get_u8();
+		buf.advance(3);
+		let track_id = buf.get_u32();
+
+		// NTP timestamps are 32.32 fixed point seconds
+		let ntp = buf.get_u64();
+		let secs = (ntp >> 32)
+			.checked_sub(NTP_UNIX_OFFSET)
+			.ok_or_else(|| anyhow::anyhow!("prft before 1970"))?;
+		let nanos = ((ntp & 0xffff_ffff) * 1_000_000_000) >> 32;
+		let wallclock = std::time::UNIX_EPOCH + std::time::Duration::new(secs, nanos as u32);
+
+		let media_time = match version {
+			0 => {
+				anyhow::ensure!(buf.remaining() >= 4, "prft too short");
+				buf.get_u32() as u64
+			}
+			_ => {
+				anyhow::ensure!(buf.remaining() >= 8, "prft too short");
+				buf.get_u64()
+			}
+		};
+
+		Ok(Self {
+			track_id,
+			wallclock,
+			media_time,
+		})
+	}
+}
+
+/// The event message box, timed metadata like SCTE-35 markers, carried within the media segments.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Emsg {
+	pub scheme_id_uri: String,
+	pub value: String,
+	pub timescale: u32,
+	/// media time of the event with version 1, otherwise relative to the earliest presentation time of the segment
+	pub presentation_time: u64,
+	pub relative: bool,
+	pub event_duration: u32,
+	pub id: u32,
+}
+
+impl Emsg {
+	/// parse the full emsg atom, including its header
+	pub fn parse(atom: &[u8]) -> anyhow::Result<Self> {
+		let mut buf = atom;
+		anyhow::ensure!(buf.remaining() >= 12, "emsg too short");
+		buf.advance(4);
+		anyhow::ensure!(buf.get_u32().to_be_bytes() == *b"emsg", "not an emsg atom");
+
+		let version = buf.get_u8();
+		buf.advance(3);
+
+		match version {
+			0 => {
+				let scheme_id_uri = cstring(&mut buf)?;
+				let value = cstring(&mut buf)?;
+				anyhow::ensure!(buf.remaining() >= 16, "emsg too short");
+				Ok(Self {
+					scheme_id_uri,
+					value,
+					timescale: buf.get_u32(),
+					presentation_time: buf.get_u32() as u64,
+					relative: true,
+					event_duration: buf.get_u32(),
+					id: buf.get_u32(),
+				})
+			}
+			1 => {
+				anyhow::ensure!(buf.remaining() >= 20, "emsg too short");
+				let timescale = buf.get_u32();
+				let presentation_time = buf.get_u64();
+				let event_duration = buf.get_u32();
+				let id = buf.get_u32();
+				Ok(Self {
+					scheme_id_uri: cstring(&mut buf)?,
+					value: cstring(&mut buf)?,
+					timescale,
+					presentation_time,
+					relative: false,
+					event_duration,
+					id,
+				})
+			}
+			version => anyhow::bail!("unsupported emsg version {version}"),
+		}
+	}
+
+	/// the presentation time in seconds
+	pub fn time(&self) -> std::time::Duration {
+		std::time::Duration::from_secs_f64(self.presentation_time as f64 / self.timescale.max(1) as f64)
+	}
+}
+
+/// take a null-terminated UTF-8 string
+fn cstring(buf: &mut &[u8]) -> anyhow::Result<String> {
+	let len = buf
+		.iter()
+		.position(|b| *b == 0)
+		.ok_or_else(|| anyhow::anyhow!("unterminated string"))?;
+	let string = std::str::from_utf8(&buf[..len])?.to_string();
+	buf.advance(len + 1);
+	Ok(string)
+}
+
+/// copy the bytes `offset` into `buf` without consuming them, even if the header is split across chunks
+fn peek<B: Buf>(buf: &B, mut offset: usize, header: &mut [u8; MAX_HEADER]) -> usize {
+	let mut slices = [std::io::IoSlice::new(&[]); MAX_SLICES];
+	let count = buf.chunks_vectored(&mut slices);
+
+	let mut peeked = 0;
+	for slice in &slices[..count] {
+		if offset >= slice.len() {
+			offset -= slice.len();
+			continue;
+		}
+		let slice = &slice[offset..];
+		offset = 0;
+
+		let len = slice.len().min(MAX_HEADER - peeked);
+		header[peeked..peeked + len].copy_from_slice(&slice[..len]);
+		peeked += len;
+
+		if peeked == MAX_HEADER {
+			break;
+		}
+	}
+
+	peeked
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn atom(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+		let mut atom = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+		atom.extend_from_slice(kind);
+		atom.extend_from_slice(body);
+		atom
+	}
+
+	#[test]
+	fn test_split_header() {
+		let moof = atom(b"moof", &[1; 20]);
+		let mdat = atom(b"mdat", &[2; 10]);
+		let data = [moof.clone(), mdat.clone()].concat();
+
+		// split inside the size and inside the type of the second atom
+		for split in [2, 6, moof.len() + 3, moof.len() + 5] {
+			let mut buf = Bytes::copy_from_slice(&data[..split]).chain(Bytes::copy_from_slice(&data[split..]));
+
+			assert_eq!(next_atom(&mut buf, false).unwrap().unwrap(), moof);
+			assert_eq!(next_atom(&mut buf, false).unwrap().unwrap(), mdat);
+			assert_eq!(next_atom(&mut buf, false).unwrap(), None);
+		}
+	}
+
+	#[test]
+	fn test_split() {
+		let (moof, mdat) = (atom(b"moof", &[1; 92]), atom(b"mdat", &[2; 3 << 20]));
+		let buf = Bytes::from([moof.clone(), mdat.clone(), moof.clone()].concat());
+
+		let pieces = split(buf.clone(), 1 << 20);
+		let sizes: Vec<_> = pieces.iter().map(Bytes::len).collect();
+		assert_eq!(sizes, [100, 1 << 20, 1 << 20, 1 << 20, 8, 100]);
+		assert_eq!(pieces.concat(), buf);
+
+		// the atoms fitting together stay in one piece
+		let buf = Bytes::from([moof.clone(), moof.clone()].concat());
+		assert_eq!(split(buf.clone(), 1 << 20), [buf]);
+		assert_eq!(split(Bytes::from(moof.clone()), 100), [Bytes::from(moof)]);
+
+		// not atoms, ex. a truncated one
+		let pieces = split(Bytes::from_static(&[0, 0, 1, 0, b'm', b'd']), 4);
+		assert_eq!(pieces, [&[0, 0, 1, 0][..], &[b'm', b'd'][..]]);
+	}
+
+	#[test]
+	fn test_chunks() {
+		let moof = atom(b"moof", &[1; 20]);
+		let mdat = atom(b"mdat", &[2; 10]);
+		let data = [moof.clone(), mdat.clone()].concat();
+
+		let mut chunks = Chunks::default();
+		let first = Bytes::copy_from_slice(&data[..moof.len() + 6]);
+		chunks.push(first.clone());
+		chunks.push(Bytes::new());
+		assert_eq!(peek_atom(&chunks, moof.len(), false).unwrap(), None);
+		chunks.push(Bytes::copy_from_slice(&data[moof.len() + 6..]));
+		assert_eq!(
+			peek_atom(&chunks, moof.len(), false).unwrap(),
+			Some((mdat.len(), *b"mdat"))
+		);
+
+		// within the first chunk, taken without copying
+		let atom = next_atom(&mut chunks, false).unwrap().unwrap();
+		assert_eq!(atom, moof);
+		assert_eq!(atom.as_ptr(), first.as_ptr());
+
+		// spanning both chunks
+		assert_eq!(next_atom(&mut chunks, false).unwrap().unwrap(), mdat);
+		assert_eq!(chunks.remaining(), 0);
+		assert_eq!(next_atom(&mut chunks, false).unwrap(), None);
+	}
+
+	#[test]
+	fn test_rope() {
+		let (moof, mdat) = (atom(b"moof", &[1; 92]), atom(b"mdat", &[2; 3 << 20]));
+		let data = Bytes::from([moof.clone(), mdat, moof].concat());
+		let reads: Vec<_> = data.chunks(1000).map(Bytes::copy_from_slice).collect();
+
+		let mut chunks = Chunks::default();
+		for read in &reads {
+			chunks.push(read.clone());
+		}
+		let rope = chunks.take_rope(data.len() - 50);
+		assert_eq!(chunks.remaining(), 50);
+		assert_eq!(rope.to_bytes(), data[..data.len() - 50]);
+		// slices of the reads
+		assert_eq!(rope.parts()[1].as_ptr(), reads[1].as_ptr());
+
+		// cut like the copied bytes, without copying them
+		let mut rope = rope;
+		rope.append(chunks.take_rope(50));
+		let pieces = split_rope(rope, 1 << 20);
+		let copied = split(data.clone(), 1 << 20);
+		assert_eq!(pieces.iter().map(Rope::to_bytes).collect::<Vec<_>>(), copied);
+		assert_eq!(pieces[1].parts()[0].as_ptr(), reads[0][100..].as_ptr());
+	}
+
+	#[test]
+	fn test_extended_size() {
+		let mut data = 1u32.to_be_bytes().to_vec();
+		data.extend_from_slice(b"mdat");
+		data.extend_from_slice(&24u64.to_be_bytes());
+		data.extend_from_slice(&[3; 8]);
+
+		// the extended size is split from the rest of the header
+		let mut buf = Bytes::copy_from_slice(&data[..10]).chain(Bytes::copy_from_slice(&data[10..]));
+		assert_eq!(next_atom(&mut buf, false).unwrap().unwrap(), data);
+
+		let mut truncated = Bytes::copy_from_slice(&data[..12]);
+		assert_eq!(next_atom(&mut truncated, false).unwrap(), None);
+		assert_eq!(truncated.remaining(), 12);
+	}
+
+	#[test]
+	fn test_eof_size() {
+		let mut data = 0u32.to_be_bytes().to_vec();
+		data.extend_from_slice(b"mdat");
+		data.extend_from_slice(&[4; 30]);
+
+		// more data may follow
+		let mut buf = Bytes::from(data.clone());
+		assert_eq!(next_atom(&mut buf, false).unwrap(), None);
+
+		assert_eq!(next_atom(&mut buf, true).unwrap().unwrap(), data);
+		assert_eq!(next_atom(&mut buf, true).unwrap(), None);
+	}
+
+	#[test]
+	fn test_prft() {
+		// 2024-01-01T00:00:00.5Z, media time 25600 of track 1
+		let secs = NTP_UNIX_OFFSET + 1_704_067_200;
+		let body = [
+			&[1, 0, 0, 0, 0, 0, 0, 1][..],
+			&secs.to_be_bytes()[4..],
+			&[0x80, 0, 0, 0],
+			&25600u64.to_be_bytes(),
+		]
+		.concat();
+		let prft = Prft::parse(&atom(b"prft", &body)).unwrap();
+
+		assert_eq!(prft.track_id, 1);
+		assert_eq!(prft.media_time, 25600);
+		assert_eq!(
+			prft.wallclock,
+			std::time::UNIX_EPOCH + std::time::Duration::from_millis(1_704_067_200_500)
+		);
+
+		// version 0 has a 32 bit media time
+		let body = [
+			&[0; 4][..],
+			&[0, 0, 0, 2],
+			&secs.to_be_bytes()[4..],
+			&[0; 4],
+			&7u32.to_be_bytes(),
+		]
+		.concat();
+		assert_eq!(Prft::parse(&atom(b"prft", &body)).unwrap().media_time, 7);
+
+		assert!(Prft::parse(&atom(b"moof", &body)).is_err());
+		assert!(Prft::parse(&atom(b"prft", &body[..10])).is_err());
+	}
+
+	#[test]
+	fn test_emsg() {
+		let body = [
+			&[1, 0, 0, 0][..],
+			&90000u32.to_be_bytes(),
+			&180000u64.to_be_bytes(),
+			&450u32.to_be_bytes(),
+			&7u32.to_be_bytes(),
+			b"urn:scte:scte35:2013:bin\0\0",
+			&[0xfc, 0x30],
+		]
+		.concat();
+		let emsg = Emsg::parse(&atom(b"emsg", &body)).unwrap();
+		assert_eq!(
+			emsg,
+			Emsg {
+				scheme_id_uri: "urn:scte:scte35:2013:bin".to_string(),
+				value: String::new(),
+				timescale: 90000,
+				presentation_time: 180000,
+				relative: false,
+				event_duration: 450,
+				id: 7,
+			}
+		);
+		assert_eq!(emsg.time(), std::time::Duration::from_secs(2));
+
+		// version 0 starts with the strings, the time is a delta
+		let body = [
+			&[0; 4][..],
+			b"urn:example\0v\0",
+			&1000u32.to_be_bytes(),
+			&500u32.to_be_bytes(),
+			&0u32.to_be_bytes(),
+			&1u32.to_be_bytes(),
+		]
+		.concat();
+		let emsg = Emsg::parse(&atom(b"emsg", &body)).unwrap();
+		assert_eq!((emsg.scheme_id_uri.as_str(), emsg.value.as_str()), ("urn:example", "v"));
+		assert!(emsg.relative);
+		assert_eq!(emsg.time(), std::time::Duration::from_millis(500));
+
+		assert!(Emsg::parse(&atom(b"emsg", &body[..10])).is_err());
+		assert!(Emsg::parse(&atom(b"prft", &body)).is_err());
+	}
+
+	#[test]
+	fn test_find_atom() {
+		let moof = atom(b"moof", &[1; 20]);
+		let kinds = [*b"styp", *b"moof"];
+
+		let data = [&[7; 4][..], &atom(b"mdat", &[2; 4]), &moof].concat();
+		assert_eq!(find_atom(&data, &kinds, 1 << 20), Ok(16));
+		assert_eq!(find_atom(&moof, &kinds, 1 << 20), Ok(0));
+
+		// only at the aligned offsets
+		let data = [&[7; 5][..], &atom(b"mdat", &[2; 4]), &moof].concat();
+		assert_eq!(find_atom(&data, &kinds, 1 << 20), Err(40));
+
+		// the size has to be plausible
+		assert_eq!(find_atom(&moof, &kinds, 16), Err(24));
+		assert_eq!(
+			find_atom(&[0, 0, 0, 4, b'm', b'o', b'o', b'f'], &kinds, 1 << 20),
+			Err(4)
+		);
+
+		// the header may be cut off
+		let data = [&[7; 4][..], &atom(b"mdat", &[2; 4]), &moof].concat();
+		assert_eq!(find_atom(&data[..20], &kinds, 1 << 20), Err(16));
+		assert_eq!(find_atom(&[0; 3], &kinds, 1 << 20), Err(0));
+	}
+
+	#[test]
+	fn test_check_moof() {
+		let trun = atom(b"trun", &[&[0; 4][..], &7u32.to_be_bytes()].concat());
+		let traf = atom(b"traf", &[atom(b"tfhd", &[0; 8]), trun].concat());
+		let moof = atom(b"moof", &[atom(b"mfhd", &[0; 8]), traf.clone()].concat());
+		assert!(check_moof(&moof, 7).is_ok());
+		assert!(check_moof(&moof, 6).is_err());
+
+		// the atoms within have to fill it
+		assert!(check_moof(&atom(b"moof", &[&traf[..], &[0; 8]].concat()), 7).is_err());
+		assert!(check_moof(&atom(b"moof", &traf[..20]), 7).is_err());
+		let traf = atom(b"traf", &atom(b"trun", &[0; 4]));
+		assert!(check_moof(&atom(b"moof", &traf), 7).is_err());
+	}
+
+	#[test]
+	fn test_check_header() {
+		let moof = atom(b"moof", &[1; 20]);
+		assert!(check_header(&Bytes::from(moof.clone()), 28).is_ok());
+		assert!(check_header(&Bytes::from(moof.clone()), 27).is_err());
+		assert!(check_header(&Bytes::copy_from_slice(&moof[..6]), 0).is_ok());
+
+		assert!(check_header(&Bytes::from(atom(b"\xa9too", &[])), 8).is_ok());
+		assert!(check_header(&Bytes::from(atom(&[0, 1, 2, 3], &[])), 8).is_err());
+		assert!(check_header(&Bytes::from_static(&[0, 0, 0, 0, b'm', b'd', b'a', b't']), 8).is_err());
+
+		let mut extended = 1u32.to_be_bytes().to_vec();
+		extended.extend_from_slice(b"mdat");
+		assert!(check_header(&Bytes::from(extended.clone()), 16).is_ok());
+		extended.extend_from_slice(&(1u64 << 40).to_be_bytes());
+		assert!(check_header(&Bytes::from(extended), 1 << 30).is_err());
+	}
+
+	#[test]
+	fn test_truncated() {
+		let moof = atom(b"moof", &[1; 20]);
+
+		for len in [0, 4, 8, moof.len() - 1] {
+			let mut buf = Bytes::copy_from_slice(&moof[..len]);
+			assert_eq!(next_atom(&mut buf, false).unwrap(), None);
+			assert_eq!(buf.remaining(), len);
+		}
+
+		let mut buf = Bytes::copy_from_slice(&moof[..4]);
+		assert!(next_atom(&mut buf, true).is_err());
+		assert!(next_atom(&mut Bytes::copy_from_slice(&[0, 0, 0, 4, 0, 0, 0, 0]), false).is_err());
+	}
+}
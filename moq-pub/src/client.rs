@@ -0,0 +1,37 @@
+use anyhow::Context;
+use moq_native::{quic, tls};
+use moq_transport::session::{Publisher, Session, Subscriber};
+
+/// connect to the relay at `url` and setup a MoQ publisher session
+///
+/// The session has to be run next to the publisher, ex. with [Session::run] in a `tokio::select!`.
+pub async fn connect_publisher(
+	url: &url::Url,
+	bind: std::net::SocketAddr,
+	tls: &tls::Args,
+	transport: quic::Transport,
+) -> anyhow::Result<(Session, Publisher)> {
+	log::info!("connecting to relay: url={}", url);
+	let session = client(bind, tls, transport)?.connect(url).await?;
+	// a failed setup stays a SessionError to downcast to
+	Ok(Publisher::connect(session).await?)
+}
+
+/// connect to the relay at `url` and setup a MoQ subscriber session
+pub async fn connect_subscriber(
+	url: &url::Url,
+	bind: std::net::SocketAddr,
+	tls: &tls::Args,
+	transport: quic::Transport,
+) -> anyhow::Result<(Session, Subscriber)> {
+	log::info!("connecting to relay: url={}", url);
+	let session = client(bind, tls, transport)?.connect(url).await?;
+	Subscriber::connect(session)
+		.await
+		.context("failed to create MoQ Transport subscriber")
+}
+
+fn client(bind: std::net::SocketAddr, tls: &tls::Args, transport: quic::Transport) -> anyhow::Result<quic::Client> {
+	let tls = tls.load()?;
+	Ok(quic::Endpoint::new(quic::Config { bind, tls, transport })?.client)
+}
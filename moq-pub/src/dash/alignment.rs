@@ -0,0 +1,250 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time;
+
+use super::{publisher::RepID, Error};
+
+/// group starts kept per rep, older ones are never judged
+const HISTORY: usize = 32;
+
+/// Checks that the video reps start their groups at the same media timestamps, as ABR switching requires.
+///
+/// A group start is judged once every other rep started a group within the tolerance of it, which aligns it,
+/// or moved past it without doing so. Reps that started publishing after it are ignored.
+/// Cloning is cheap, all clones share the same group starts.
+#[derive(Clone)]
+pub struct Alignment {
+	tolerance: time::Duration,
+	strict: bool,
+	reps: Arc<Mutex<BTreeMap<RepID, Starts>>>,
+}
+
+struct Starts {
+	/// media timestamp of the recent group starts and whether they were judged yet
+	starts: VecDeque<(time::Duration, bool)>,
+	metrics: crate::metrics::Recorder,
+}
+
+impl Starts {
+	fn first(&self) -> Option<time::Duration> {
+		self.starts.front().map(|(start, _)| *start)
+	}
+
+	fn last(&self) -> Option<time::Duration> {
+		self.starts.back().map(|(start, _)| *start)
+	}
+}
+
+impl Alignment {
+	/// group starts closer than `tolerance` are aligned, ex. one frame duration
+	pub fn new(tolerance: time::Duration) -> Self {
+		Self {
+			tolerance,
+			strict: false,
+			reps: Default::default(),
+		}
+	}
+
+	/// fail the rep recording a misaligned group instead of only warning
+	pub fn set_strict(&mut self, strict: bool) {
+		self.strict = strict;
+	}
+
+	/// forget the group starts of all reps, ex. once the encoder restarted with other settings
+	///
+	/// The reps have to register again with [Self::rep], the recorders handed out so far are ignored.
+	pub fn restart(&mut self, tolerance: time::Duration) {
+		self.tolerance = tolerance;
+		self.reps = Default::default();
+	}
+
+	/// recorder of the group starts of `rep_id`, misaligned groups are counted in `metrics`
+	pub fn rep(&self, rep_id: RepID, metrics: crate::metrics::Recorder) -> Rep {
+		self.lock().insert(
+			rep_id,
+			Starts {
+				starts: VecDeque::new(),
+				metrics,
+			},
+		);
+
+		Rep {
+			rep_id,
+			alignment: self.clone(),
+		}
+	}
+
+	/// record a group start of `rep_id`, returns the group starts found misaligned since
+	fn record(&self, rep_id: RepID, timestamp: time::Duration) -> Vec<(RepID, time::Duration)> {
+		let mut reps = self.lock();
+
+		let Some(rep) = reps.get_mut(&rep_id) else {
+			return Vec::new();
+		};
+		if rep.starts.len() == HISTORY {
+			rep.starts.pop_front();
+		}
+		rep.starts.push_back((timestamp, false));
+
+		let mut judged = Vec::new();
+		for (id, rep) in reps.iter() {
+			for (index, (start, _)) in rep.starts.iter().enumerate().filter(|(_, (_, judged))| !judged) {
+				let mut others = reps
+					.iter()
+					.filter(|(other, starts)| {
+						*other != id && starts.first().is_some_and(|first| first <= *start + self.tolerance)
+					})
+					.map(|(_, starts)| starts)
+					.peekable();
+				if others.peek().is_none() {
+					continue;
+				}
+
+				let (mut aligned, mut passed) = (false, true);
+				for other in others {
+					aligned |= other.starts.iter().any(|(s, _)| s.abs_diff(*start) < self.tolerance);
+					passed &= other.last().is_some_and(|last| last >= *start + self.tolerance);
+				}
+
+				if aligned || passed {
+					judged.push((*id, index, *start, aligned));
+				}
+			}
+		}
+
+		let mut misaligned = Vec::new();
+		for (id, index, start, aligned) in judged {
+			let Some(rep) = reps.get_mut(&id) else {
+				continue;
+			};
+			rep.starts[index].1 = true;
+
+			if !aligned {
+				rep.metrics.misaligned();
+				misaligned.push((id, start));
+			}
+		}
+
+		misaligned
+	}
+
+	fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<RepID, Starts>> {
+		self.reps.lock().unwrap_or_else(|e| e.into_inner())
+	}
+}
+
+/// Records the group starts of a single video rep.
+pub struct Rep {
+	rep_id: RepID,
+	alignment: Alignment,
+}
+
+impl Rep {
+	/// a new group starts at the media `timestamp`, fails in strict mode if a misaligned group was found
+	pub fn group(&self, timestamp: time::Duration) -> Result<(), Error> {
+		let misaligned = self.alignment.record(self.rep_id, timestamp);
+
+		for (rep_id, timestamp) in &misaligned {
+			tracing::warn!(
+				rep_id,
+				?timestamp,
+				"group starts at a timestamp no other rep starts one at"
+			);
+		}
+
+		match misaligned.first() {
+			Some((rep_id, timestamp)) if self.alignment.strict => {
+				tracing::error!(rep_id, ?timestamp, "misaligned group");
+				Err(Error::Misaligned(*rep_id, *timestamp))
+			}
+			_ => Ok(()),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn starts(millis: &[u64]) -> Vec<time::Duration> {
+		millis.iter().map(|m| time::Duration::from_millis(*m)).collect()
+	}
+
+	/// record the group starts of both reps interleaved
+	fn feed(alignment: &Alignment, a: &[u64], b: &[u64]) -> Vec<Result<(), Error>> {
+		let metrics = crate::metrics::Metrics::default();
+		let reps = [
+			alignment.rep(0, metrics.track("a")),
+			alignment.rep(1, metrics.track("b")),
+		];
+
+		let mut results = Vec::new();
+		for (a, b) in starts(a).into_iter().zip(starts(b)) {
+			results.push(reps[0].group(a));
+			results.push(reps[1].group(b));
+		}
+		results
+	}
+
+	#[test]
+	fn test_aligned() {
+		let alignment = Alignment::new(time::Duration::from_millis(40));
+		// one rep running ahead, and a difference below one frame
+		let a = [0, 2000, 4000, 6000, 8000];
+		let b = [0, 2000, 4020, 6000, 8000];
+		let results = feed(&alignment, &a, &b);
+		assert!(results.iter().all(|r| r.is_ok()));
+	}
+
+	#[test]
+	fn test_misaligned() {
+		let mut alignment = Alignment::new(time::Duration::from_millis(40));
+		alignment.set_strict(true);
+
+		let metrics = crate::metrics::Metrics::default();
+		let a = alignment.rep(0, metrics.track("a"));
+		let b = alignment.rep(1, metrics.track("b"));
+
+		// b cuts a group one frame late
+		a.group(time::Duration::from_millis(0)).unwrap();
+		b.group(time::Duration::from_millis(0)).unwrap();
+		a.group(time::Duration::from_millis(2000)).unwrap();
+		a.group(time::Duration::from_millis(4000)).unwrap();
+		b.group(time::Duration::from_millis(2000)).unwrap();
+
+		// judged once the other rep moved past it
+		let err = b.group(time::Duration::from_millis(4040)).unwrap_err();
+		assert!(matches!(err, Error::Misaligned(0, t) if t == time::Duration::from_millis(4000)));
+		let err = a.group(time::Duration::from_millis(6000)).unwrap_err();
+		assert!(matches!(err, Error::Misaligned(1, t) if t == time::Duration::from_millis(4040)));
+		b.group(time::Duration::from_millis(6000)).unwrap();
+
+		let encoded = metrics.encode();
+		assert!(encoded.contains("moq_pub_misaligned_groups_total{track=\"a\"} 1"));
+		assert!(encoded.contains("moq_pub_misaligned_groups_total{track=\"b\"} 1"));
+
+		// warnings only by default
+		let alignment = Alignment::new(time::Duration::from_millis(40));
+		let results = feed(&alignment, &[0, 2000, 4000, 6000], &[0, 2000, 4040, 6000]);
+		assert!(results.iter().all(|r| r.is_ok()));
+	}
+
+	#[test]
+	fn test_late_rep() {
+		let mut alignment = Alignment::new(time::Duration::from_millis(40));
+		alignment.set_strict(true);
+
+		let metrics = crate::metrics::Metrics::default();
+		let a = alignment.rep(0, metrics.track("a"));
+		let b = alignment.rep(1, metrics.track("b"));
+
+		// the groups of a before b started are not judged against it
+		for start in starts(&[0, 2000, 4000]) {
+			a.group(start).unwrap();
+		}
+		for start in starts(&[4000, 6000]) {
+			b.group(start).unwrap();
+		}
+		a.group(time::Duration::from_millis(6000)).unwrap();
+	}
+}
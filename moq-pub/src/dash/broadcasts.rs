@@ -0,0 +1,339 @@
+use futures::{future::FusedFuture, FutureExt};
+use std::path;
+use tracing::Instrument;
+
+use super::{announce_all, close, relay, ConnectOptions, Dash, Error, Input, Reconnect};
+
+/// A broadcast of a [broadcasts file](BroadcastConfig::load), the options of the command line apply to all of them.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BroadcastConfig {
+	/// the namespace it is announced as
+	pub name: String,
+	/// the ffmpeg input, like `--input`
+	#[serde(deserialize_with = "input")]
+	pub input: Input,
+	/// the settings file, like `--settings`
+	pub settings: path::PathBuf,
+	/// the output directory, like `--output`, removed once the broadcast ends
+	pub output: path::PathBuf,
+	#[serde(default)]
+	pub no_audio: bool,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BroadcastsFile {
+	broadcasts: Vec<BroadcastConfig>,
+}
+
+impl BroadcastConfig {
+	/// read the `broadcasts` list of a YAML or JSON file
+	///
+	/// Relative paths are resolved against the directory of the file.
+	/// Fails if a name is used twice or an output directory is shared, as it is removed at the end.
+	pub fn load<P: AsRef<path::Path>>(path: P) -> Result<Vec<Self>, Error> {
+		let path = path.as_ref();
+		let buf = match std::fs::read(path) {
+			Ok(b) => b,
+			Err(e) => {
+				tracing::error!(error = %e);
+				return Err(Error::Io(e).context("reading the broadcasts file"));
+			}
+		};
+
+		// JSON is valid YAML
+		let file: BroadcastsFile = match serde_yaml::from_slice(&buf) {
+			Ok(f) => f,
+			Err(e) => {
+				tracing::error!(error = %e);
+				return Err(Error::Yaml(e).context("parsing the broadcasts file"));
+			}
+		};
+
+		let dir = path.parent().unwrap_or(path::Path::new(""));
+		let broadcasts: Vec<_> = file
+			.broadcasts
+			.into_iter()
+			.map(|mut broadcast| {
+				broadcast.settings = dir.join(&broadcast.settings);
+				broadcast.output = dir.join(&broadcast.output);
+				broadcast
+			})
+			.collect();
+
+		validate(&broadcasts)?;
+
+		Ok(broadcasts)
+	}
+}
+
+fn input<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Input, D::Error> {
+	<String as serde::Deserialize>::deserialize(deserializer).map(Input::from)
+}
+
+fn validate(broadcasts: &[BroadcastConfig]) -> Result<(), Error> {
+	let invalid = |error: String| {
+		tracing::error!(error);
+		Err(Error::InvalidBroadcasts(error))
+	};
+
+	if broadcasts.is_empty() {
+		return invalid("no broadcasts".to_string());
+	}
+
+	for (i, broadcast) in broadcasts.iter().enumerate() {
+		for other in &broadcasts[..i] {
+			if broadcast.name == other.name {
+				return invalid(format!("{} is used twice", broadcast.name));
+			}
+			if broadcast.output.starts_with(&other.output) || other.output.starts_with(&broadcast.output) {
+				return invalid(format!("{} and {} share their output", other.name, broadcast.name));
+			}
+		}
+	}
+
+	Ok(())
+}
+
+/// Runs several [Dash] pipelines, announced on a single session with the relay.
+///
+/// Every broadcast runs as its own task, one ending or failing leaves the others running,
+/// unless [Self::fail_fast] is set. Its tracks are ended, its namespace stays announced until the session ends.
+pub struct Broadcasts {
+	broadcasts: Vec<Dash>,
+	options: ConnectOptions,
+	reconnect: Reconnect,
+	fail_fast: bool,
+}
+
+impl Broadcasts {
+	/// connect to the relay of `options`, its namespace is not used as every broadcast has its own
+	pub fn new(options: ConnectOptions) -> Self {
+		Self {
+			broadcasts: Vec::new(),
+			options,
+			reconnect: Default::default(),
+			fail_fast: false,
+		}
+	}
+
+	/// add a broadcast, announced under the namespace of its connect options
+	pub fn broadcast(mut self, dash: Dash) -> Self {
+		self.broadcasts.push(dash);
+		self
+	}
+
+	/// how the relay is re-dialed when the session drops, the broadcasts keep running meanwhile
+	pub fn reconnect(mut self, reconnect: Reconnect) -> Self {
+		self.reconnect = reconnect;
+		self
+	}
+
+	/// stop all broadcasts once one fails, instead of leaving the others running
+	pub fn fail_fast(mut self, fail_fast: bool) -> Self {
+		self.fail_fast = fail_fast;
+		self
+	}
+
+	/// until all broadcasts ended, the relay is lost for good or a signal arrives
+	///
+	/// Fails if a broadcast cannot be started, or with the first failure if [Self::fail_fast] is set.
+	pub async fn run(self) -> Result<(), Error> {
+		let mut started = Vec::new();
+		for dash in &self.broadcasts {
+			started.push(dash.start()?);
+		}
+
+		let (stop_tx, stop) = tokio::sync::watch::channel(false);
+		let mut tasks = tokio::task::JoinSet::new();
+		let mut readers = Vec::new();
+		for (mut broadcast, reader) in started {
+			let namespace = reader.namespace.clone();
+			readers.push(reader);
+
+			let mut stop = stop.clone();
+			let task = async move {
+				let res = tokio::select! {
+					res = broadcast.run() => res,
+					_ = stop.wait_for(|stop| *stop) => Ok(false),
+				};
+				match res {
+					Ok(true) => {
+						let linger = broadcast.linger;
+						broadcast.finish().await?;
+						// the namespace stays announced meanwhile, unless all broadcasts ended
+						tokio::select! {
+							_ = tokio::time::sleep(linger) => (),
+							_ = stop.wait_for(|stop| *stop) => (),
+						}
+						Ok(())
+					}
+					res => {
+						let closed = broadcast.close().await;
+						res.and(closed)
+					}
+				}
+			};
+			let span = tracing::info_span!("broadcast", namespace);
+			tasks.spawn(async move { (namespace, task.await) }.instrument(span));
+		}
+
+		// kept alive past the loop for the teardown, reconnects are handled inside
+		let shutdown = tokio::sync::Notify::new();
+		let mut relay = Box::pin(
+			announce_all(&self.options, readers, &self.reconnect, &shutdown)
+				.instrument(tracing::info_span!("relay"))
+				.fuse(),
+		);
+		let mut signal = Box::pin(close().fuse());
+
+		let mut failure = None;
+		while failure.is_none() {
+			tokio::select! {
+				res = &mut relay => {
+					tracing::info!(?res, "relay ended");
+					break;
+				}
+				res = &mut signal => {
+					tracing::info!(?res, "closed by signal");
+					break;
+				}
+				ended = tasks.join_next() => match ended {
+					Some(ended) => failure = self.ended(ended),
+					None => break,
+				},
+			}
+		}
+
+		log::info!("termination initiated, cleaning up");
+
+		let _ = stop_tx.send(true);
+		while let Some(ended) = tasks.join_next().await {
+			self.ended(ended);
+		}
+
+		if !relay.is_terminated() {
+			shutdown.notify_one();
+			let _ = tokio::time::timeout(relay::GRACE_PERIOD, &mut relay).await;
+		}
+
+		match failure {
+			Some(e) => Err(e),
+			None => Ok(()),
+		}
+	}
+
+	/// log the end of a broadcast task, its error if all broadcasts should stop
+	fn ended(&self, ended: Result<(String, Result<(), Error>), tokio::task::JoinError>) -> Option<Error> {
+		let (namespace, res) = match ended {
+			Ok(ended) => ended,
+			Err(e) => {
+				tracing::error!(error = %e, "broadcast task failed");
+				return self.fail_fast.then_some(Error::Other);
+			}
+		};
+
+		match res {
+			Ok(()) => {
+				tracing::info!(namespace, "broadcast ended");
+				None
+			}
+			Err(e) => {
+				tracing::error!(namespace, error = %e, "broadcast failed");
+				self.fail_fast.then_some(e)
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// load `yaml` from a file of its own, the paths relative to its directory
+	fn load(yaml: &str) -> Result<Vec<BroadcastConfig>, Error> {
+		static FILES: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+		let file = FILES.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+		let dir = crate::testing::temp_dir(&format!("broadcasts-{file}"));
+		let path = dir.join("broadcasts.yaml");
+		std::fs::write(&path, yaml).unwrap();
+
+		let res = BroadcastConfig::load(&path);
+		std::fs::remove_file(path).unwrap();
+		res.map(|broadcasts| {
+			broadcasts
+				.into_iter()
+				.map(|mut broadcast| {
+					broadcast.settings = broadcast.settings.strip_prefix(&dir).unwrap().to_path_buf();
+					broadcast.output = broadcast.output.strip_prefix(&dir).unwrap().to_path_buf();
+					broadcast
+				})
+				.collect()
+		})
+	}
+
+	#[test]
+	fn test_load() {
+		let broadcasts = load(
+			"broadcasts:
+  - name: camera1
+    input: /dev/video0
+    settings: camera1.csv
+    output: camera1
+  - name: camera2
+    input: srt://:9000?mode=listener
+    settings: settings/camera2.yaml
+    output: camera2
+    no_audio: true
+",
+		)
+		.unwrap();
+		assert_eq!(
+			broadcasts,
+			[
+				BroadcastConfig {
+					name: "camera1".to_string(),
+					input: Input::Webcam,
+					settings: "camera1.csv".into(),
+					output: "camera1".into(),
+					no_audio: false,
+				},
+				BroadcastConfig {
+					name: "camera2".to_string(),
+					input: Input::Network("srt://:9000?mode=listener".to_string()),
+					settings: "settings/camera2.yaml".into(),
+					output: "camera2".into(),
+					no_audio: true,
+				}
+			]
+		);
+
+		// JSON works as well
+		let json = r#"{"broadcasts": [{"name": "a", "input": "a.mp4", "settings": "a.csv", "output": "a"}]}"#;
+		assert_eq!(load(json).unwrap()[0].name, "a");
+	}
+
+	#[test]
+	fn test_invalid() {
+		let broadcast = |name: &str, output: &str| {
+			format!("  - {{ name: {name}, input: in.mp4, settings: s.csv, output: {output} }}\n")
+		};
+		let invalid = |broadcasts: &[String]| match load(&format!("broadcasts:\n{}", broadcasts.concat())) {
+			Err(Error::InvalidBroadcasts(error)) => error,
+			res => panic!("unexpected {res:?}"),
+		};
+
+		assert!(matches!(load("broadcasts: []"), Err(Error::InvalidBroadcasts(error)) if error == "no broadcasts"));
+		assert_eq!(
+			invalid(&[broadcast("a", "out/a"), broadcast("a", "out/b")]),
+			"a is used twice"
+		);
+		assert_eq!(
+			invalid(&[broadcast("a", "out"), broadcast("b", "out/b")]),
+			"a and b share their output"
+		);
+		assert!(matches!(load("broadcasts:\n  - name: a\n"), Err(Error::Context { .. })));
+	}
+}
@@ -1,5 +1,7 @@
 use thiserror::Error;
 
+use super::settings::Violation;
+
 #[derive(Debug, Error)]
 pub enum Error {
 	#[error("invalid number of paths given. expected {0}, got {1}")]
@@ -8,8 +10,86 @@ pub enum Error {
 	#[error("failed to convert")]
 	FailedToConvert,
 
-	#[error("Crate {0} Error: {1}")]
-	Crate(String, String),
+	#[error("io error: {0}")]
+	Io(#[from] std::io::Error),
+
+	#[error("mp4 error: {0}")]
+	Mp4(#[from] mp4::Error),
+
+	#[error("notify error: {0}")]
+	Notify(#[from] notify::Error),
+
+	#[error("csv error: {0}")]
+	Csv(#[from] csv::Error),
+
+	#[error("json error: {0}")]
+	Json(#[from] serde_json::Error),
+
+	#[error("yaml error: {0}")]
+	Yaml(#[from] serde_yaml::Error),
+
+	#[error("regex error: {0}")]
+	Regex(#[from] regex::Error),
+
+	#[error("transport error: {0}")]
+	Transport(#[from] moq_transport::serve::ServeError),
+
+	#[error("session error: {0}")]
+	Session(#[from] moq_transport::session::SessionError),
+
+	#[error("encode error: {0}")]
+	Encode(#[from] moq_transport::coding::EncodeError),
+
+	#[error("decode error: {0}")]
+	Decode(#[from] moq_transport::coding::DecodeError),
+
+	#[error("catalog error: {0}")]
+	Catalog(#[from] moq_catalog::Error),
+
+	#[error("failed to connect: {0}")]
+	Connect(#[source] anyhow::Error),
+
+	#[error("malformed {0}: {1}")]
+	Malformed(&'static str, String),
+
+	#[error("unknown {0}: {1}")]
+	Unknown(&'static str, String),
+
+	#[error("ffmpeg exited with {0}")]
+	Ffmpeg(std::process::ExitStatus),
+
+	/// `stderr` holds the last lines ffmpeg wrote, ex. naming the missing device or encoder
+	#[error("ffmpeg failed to start, {reason}:\n{stderr}")]
+	FfmpegStartup { reason: String, stderr: String },
+
+	#[error("fragment of rep {rep} is of trak {found}, the init segment describes trak {expected}")]
+	UnexpectedTrak { rep: usize, expected: u32, found: u32 },
+
+	#[error("group of rep {0} starts at {1:?}, no other rep starts one there")]
+	Misaligned(usize, std::time::Duration),
+
+	#[error("task of rep {0} ended")]
+	RepEnded(usize),
+
+	/// what was being done when `source` failed, ex. for which rep
+	#[error("{what}: {source}")]
+	Context {
+		what: &'static str,
+		#[source]
+		source: Box<Error>,
+	},
+
+	#[error("invalid setting {field} in line {line}: {error}")]
+	InvalidSetting { line: usize, field: String, error: String },
+
+	#[error("invalid settings: {0}")]
+	InvalidSettings(String),
+
+	#[error("invalid broadcasts: {0}")]
+	InvalidBroadcasts(String),
+
+	#[error("invalid settings: {}", .0.iter().map(|v| v.to_string()).collect::<Vec<_>>().join("; "))]
+	Validation(Vec<Violation>),
 
 	#[error("missing key")]
 	Missing,
@@ -17,3 +97,89 @@ pub enum Error {
 	#[error("check previous logs")]
 	Other,
 }
+
+impl Error {
+	/// wrap the error with what was being done when it happened
+	pub fn context(self, what: &'static str) -> Self {
+		Self::Context {
+			what,
+			source: Box::new(self),
+		}
+	}
+
+	/// the error below all [Error::Context] wrappers, to match on the cause
+	pub fn root(&self) -> &Self {
+		match self {
+			Self::Context { source, .. } => source.root(),
+			e => e,
+		}
+	}
+}
+
+/// [Error::context] for results of errors that convert into an [Error]
+pub(crate) trait ResultExt<T> {
+	fn context(self, what: &'static str) -> Result<T, Error>;
+}
+
+impl<T, E: Into<Error>> ResultExt<T> for Result<T, E> {
+	fn context(self, what: &'static str) -> Result<T, Error> {
+		self.map_err(|e| e.into().context(what))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use moq_transport::serve::ServeError;
+
+	#[test]
+	fn test_from() {
+		let err: Error = std::io::Error::new(std::io::ErrorKind::NotFound, "gone").into();
+		assert!(matches!(err, Error::Io(ref e) if e.kind() == std::io::ErrorKind::NotFound));
+		assert_eq!(err.to_string(), "io error: gone");
+
+		let err: Error = ServeError::Closed(3).into();
+		assert!(matches!(err, Error::Transport(ServeError::Closed(3))));
+		assert_eq!(err.to_string(), "transport error: closed, code=3");
+
+		let err: Error = mp4::Error::InvalidData("bad box").into();
+		assert!(matches!(err, Error::Mp4(_)));
+		assert_eq!(err.to_string(), "mp4 error: bad box");
+
+		let err: Error = moq_catalog::Error::UnknownPackaging("raw".to_string()).into();
+		assert!(matches!(err, Error::Catalog(_)));
+		assert_eq!(
+			err.to_string(),
+			"catalog error: unknown packaging raw, expected cmaf or loc"
+		);
+
+		let err: Error = notify::Error::generic("no inotify").into();
+		assert!(matches!(err, Error::Notify(_)));
+
+		let err: Error = csv::Reader::from_reader(&b"a,b\n\xff,1\n"[..])
+			.records()
+			.next()
+			.unwrap()
+			.unwrap_err()
+			.into();
+		assert!(matches!(err, Error::Csv(_)));
+	}
+
+	#[test]
+	fn test_context() {
+		let res: Result<(), ServeError> = Err(ServeError::Closed(0));
+		let err = res
+			.context("publishing rep 2")
+			.unwrap_err()
+			.context("running the publisher");
+
+		assert_eq!(
+			err.to_string(),
+			"running the publisher: publishing rep 2: transport error: closed, code=0"
+		);
+		assert!(matches!(err.root(), Error::Transport(ServeError::Closed(_))));
+
+		let source = std::error::Error::source(&err).unwrap();
+		assert_eq!(source.to_string(), "publishing rep 2: transport error: closed, code=0");
+	}
+}
@@ -0,0 +1,420 @@
+use tokio::io::AsyncBufReadExt;
+
+use super::{Error, Settings};
+
+/// how much of the stderr of ffmpeg is kept, its errors on startup are within the first lines
+const STDERR_CAPTURE: usize = 8 << 10;
+
+/// the last lines of the kept stderr that are reported
+const STDERR_TAIL: usize = 10;
+
+/// an ffmpeg exiting with an error this soon failed to start, ex. a missing device, and is not restarted
+pub const STARTUP_GRACE: std::time::Duration = std::time::Duration::from_secs(3);
+
+/// how long ffmpeg may take to write its first segment by default
+pub const STARTUP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(15);
+
+/// how often the output is checked for the first segment
+const STARTUP_POLL: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// ffmpeg process producing the DASH output for a [Settings]
+pub struct Ffmpeg {
+	child: tokio::process::Child,
+	stderr: Option<tokio::io::BufReader<tokio::process::ChildStderr>>,
+	/// the first [STDERR_CAPTURE] bytes of stderr
+	captured: Vec<u8>,
+}
+
+impl Ffmpeg {
+	/// spawn `program`, usually ffmpeg, with the arguments generated from `settings`
+	pub fn spawn(program: &std::path::Path, settings: &Settings<std::path::PathBuf>) -> Result<Self, Error> {
+		let args = settings.to_args()?;
+		let mut child = match tokio::process::Command::new(program)
+			.args(args)
+			.stdout(std::process::Stdio::null())
+			.stderr(std::process::Stdio::piped())
+			.kill_on_drop(true)
+			.spawn()
+		{
+			Ok(c) => c,
+			Err(e) => {
+				tracing::error!(error = %e);
+				return Err(Error::Io(e));
+			}
+		};
+
+		let Some(stderr) = child.stderr.take() else {
+			tracing::error!("failed to take the ffmpeg stderr");
+			return Err(Error::Io(std::io::Error::other("failed to take the ffmpeg stderr")));
+		};
+
+		Ok(Self {
+			child,
+			stderr: Some(tokio::io::BufReader::new(stderr)),
+			captured: Vec::new(),
+		})
+	}
+
+	/// wait for ffmpeg to exit while displaying its progress
+	pub async fn run(&mut self) -> Result<std::process::ExitStatus, Error> {
+		let captured = &mut self.captured;
+		let stderr = self.stderr.take();
+		let output = async {
+			let Some(stderr) = stderr else {
+				return;
+			};
+			if let Err(e) = read_output(stderr, captured).await {
+				log::warn!("failed to read ffmpeg output: {}", e);
+			}
+		};
+
+		let (status, _) = tokio::join!(self.child.wait(), output);
+		match status {
+			Ok(s) => Ok(s),
+			Err(e) => {
+				tracing::error!(error = %e);
+				Err(Error::Io(e))
+			}
+		}
+	}
+
+	/// terminate the ffmpeg process and reap it
+	pub async fn kill(&mut self) -> Result<(), Error> {
+		if let Err(e) = self.child.kill().await {
+			tracing::error!(error = %e);
+			return Err(Error::Io(e));
+		}
+		Ok(())
+	}
+
+	/// the last lines of the stderr kept so far
+	pub fn stderr_tail(&self) -> String {
+		tail(&self.captured, STDERR_TAIL)
+	}
+}
+
+/// the last `lines` non-empty lines of `captured`
+fn tail(captured: &[u8], lines: usize) -> String {
+	let captured = String::from_utf8_lossy(captured);
+	let mut tail: Vec<_> = captured
+		.lines()
+		.map(str::trim)
+		.filter(|line| !line.is_empty())
+		.rev()
+		.take(lines)
+		.collect();
+	tail.reverse();
+
+	match tail.is_empty() {
+		true => "(no output)".to_string(),
+		false => tail.join("\n"),
+	}
+}
+
+/// display the ffmpeg progress as a spinner and log its errors, runs until stderr is closed
+///
+/// The first [STDERR_CAPTURE] bytes are kept in `captured`.
+async fn read_output(
+	mut stderr: tokio::io::BufReader<tokio::process::ChildStderr>,
+	captured: &mut Vec<u8>,
+) -> anyhow::Result<()> {
+	let re = regex::Regex::new(r"(?<key>fps|bitrate|speed)=\s*(?<value>\S+)")?;
+	let errors = regex::Regex::new(r"Error|No such|Invalid")?;
+	let pb = indicatif::ProgressBar::new_spinner();
+	pb.enable_steady_tick(std::time::Duration::from_millis(100));
+	pb.set_style(
+		indicatif::ProgressStyle::with_template("{spinner} {msg}")?
+			.tick_strings(&["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"]),
+	);
+
+	let mut line = Vec::new();
+	while next_line(&mut stderr, &mut line).await? {
+		if captured.len() < STDERR_CAPTURE {
+			let kept = line.len().min(STDERR_CAPTURE - captured.len());
+			captured.extend_from_slice(&line[..kept]);
+			captured.push(b'\n');
+		}
+
+		let line = String::from_utf8_lossy(&line);
+		if let Some(msg) = progress(&re, &line) {
+			pb.set_message(msg);
+		} else if errors.is_match(&line) {
+			pb.suspend(|| log::warn!("ffmpeg: {}", line.trim()));
+		}
+	}
+
+	pb.finish_and_clear();
+
+	Ok(())
+}
+
+/// read the next line into `line`, false at the end
+///
+/// Progress lines are terminated by \r, log lines by \n, a line is handled as soon as it is complete.
+async fn next_line<R>(reader: &mut R, line: &mut Vec<u8>) -> std::io::Result<bool>
+where
+	R: tokio::io::AsyncBufRead + Unpin,
+{
+	line.clear();
+	loop {
+		let buf = reader.fill_buf().await?;
+		if buf.is_empty() {
+			return Ok(!line.is_empty());
+		}
+
+		match buf.iter().position(|b| *b == b'\r' || *b == b'\n') {
+			Some(end) => {
+				line.extend_from_slice(&buf[..end]);
+				reader.consume(end + 1);
+				return Ok(true);
+			}
+			None => {
+				let read = buf.len();
+				line.extend_from_slice(buf);
+				reader.consume(read);
+			}
+		}
+	}
+}
+
+/// "fps: 25 bitrate: 1000.0kbits/s speed: 1.00x" from a `frame=... fps=... bitrate=... speed=...` line
+fn progress(re: &regex::Regex, line: &str) -> Option<String> {
+	let fields: Vec<String> = re
+		.captures_iter(line)
+		.map(|c| format!("{}: {}", &c["key"], &c["value"]))
+		.collect();
+
+	match fields.is_empty() {
+		true => None,
+		false => Some(fields.join(" ")),
+	}
+}
+
+/// how often and how fast a crashed ffmpeg is restarted
+#[derive(Debug, Clone)]
+pub struct Restart {
+	pub max_restarts: u32,
+	pub backoff: std::time::Duration,
+}
+
+impl Default for Restart {
+	fn default() -> Self {
+		Self {
+			max_restarts: 3,
+			backoff: std::time::Duration::from_secs(1),
+		}
+	}
+}
+
+/// runs ffmpeg, restarting it with the same arguments after unexpected exits
+///
+/// A start failing, exiting within the [STARTUP_GRACE] or not writing a segment within the startup timeout,
+/// is not restarted but fails with the last lines of its stderr.
+pub struct Supervisor {
+	settings: Settings<std::path::PathBuf>,
+	restart: Restart,
+	current: Option<Ffmpeg>,
+	program: std::path::PathBuf,
+	startup_timeout: std::time::Duration,
+}
+
+impl Supervisor {
+	pub fn new(settings: Settings<std::path::PathBuf>, restart: Restart) -> Self {
+		Self {
+			settings,
+			restart,
+			current: None,
+			program: "ffmpeg".into(),
+			startup_timeout: STARTUP_TIMEOUT,
+		}
+	}
+
+	/// run `program` instead of the ffmpeg found in the PATH
+	pub fn program(mut self, program: std::path::PathBuf) -> Self {
+		self.program = program;
+		self
+	}
+
+	/// fail if a started ffmpeg writes no segment within `timeout`, [STARTUP_TIMEOUT] by default
+	pub fn startup_timeout(mut self, timeout: std::time::Duration) -> Self {
+		self.startup_timeout = timeout;
+		self
+	}
+
+	/// returns once ffmpeg exited successfully, or with an error once `max_restarts` is exceeded or it failed to start
+	pub async fn run(&mut self) -> Result<(), Error> {
+		let mut restarts = 0;
+
+		loop {
+			let (spawned, started) = (std::time::SystemTime::now(), tokio::time::Instant::now());
+			let (output, timeout) = (self.settings.output().to_path_buf(), self.startup_timeout);
+			let ffmpeg = self.current.insert(Ffmpeg::spawn(&self.program, &self.settings)?);
+
+			let status = {
+				let run = ffmpeg.run();
+				let startup = tokio::time::timeout(timeout, first_segment(&output, spawned));
+				tokio::pin!(run, startup);
+
+				let mut writing = false;
+				loop {
+					tokio::select! {
+						res = &mut run => break Ok(res?),
+						res = &mut startup, if !writing => match res {
+							Ok(()) => writing = true,
+							Err(_) => break Err(format!("no segment written within {timeout:?}")),
+						},
+					}
+				}
+			};
+
+			let stderr = ffmpeg.stderr_tail();
+			let status = match status {
+				Ok(status) => status,
+				Err(reason) => {
+					self.kill().await?;
+					tracing::error!(reason, stderr, "ffmpeg failed to start");
+					return Err(Error::FfmpegStartup { reason, stderr });
+				}
+			};
+			self.current = None;
+
+			if status.success() {
+				log::info!("ffmpeg finished");
+				return Ok(());
+			}
+
+			if started.elapsed() < STARTUP_GRACE {
+				let reason = format!("exited with {status} within {STARTUP_GRACE:?}");
+				tracing::error!(reason, stderr, "ffmpeg failed to start");
+				return Err(Error::FfmpegStartup { reason, stderr });
+			}
+
+			if restarts >= self.restart.max_restarts {
+				tracing::error!(%status, restarts, "ffmpeg exited, giving up");
+				return Err(Error::Ffmpeg(status));
+			}
+			restarts += 1;
+
+			log::warn!(
+				"ffmpeg exited with {status}, restarting ({restarts}/{}) in {:?}",
+				self.restart.max_restarts,
+				self.restart.backoff
+			);
+			tokio::time::sleep(self.restart.backoff).await;
+		}
+	}
+
+	/// the settings of the next ffmpeg started by [Self::run]
+	pub fn set_settings(&mut self, settings: Settings<std::path::PathBuf>) {
+		self.settings = settings;
+	}
+
+	/// terminate and reap the running ffmpeg, if any
+	pub async fn kill(&mut self) -> Result<(), Error> {
+		match self.current.take() {
+			Some(mut ffmpeg) => ffmpeg.kill().await,
+			None => Ok(()),
+		}
+	}
+}
+
+/// resolves once a segment was written to `output` since `since`, also a temporary one
+async fn first_segment(output: &std::path::Path, since: std::time::SystemTime) {
+	let written = || -> std::io::Result<bool> {
+		for entry in std::fs::read_dir(output)? {
+			let entry = entry?;
+			if entry.file_name().to_string_lossy().contains(".m4s") && entry.metadata()?.modified()? >= since {
+				return Ok(true);
+			}
+		}
+		Ok(false)
+	};
+
+	// the output may not exist yet
+	while !written().unwrap_or(false) {
+		tokio::time::sleep(STARTUP_POLL).await;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_progress() {
+		let re = regex::Regex::new(r"(?<key>fps|bitrate|speed)=\s*(?<value>\S+)").unwrap();
+
+		let line = "frame=  250 fps= 25 q=28.0 size=    1024kB time=00:00:10.00 bitrate= 838.9kbits/s speed=1.01x";
+		assert_eq!(
+			progress(&re, line).unwrap(),
+			"fps: 25 bitrate: 838.9kbits/s speed: 1.01x"
+		);
+		assert_eq!(
+			progress(&re, "Input #0, mov,mp4,m4a,3gp,3g2,mj2, from 'input.mp4':"),
+			None
+		);
+	}
+
+	#[test]
+	fn test_tail() {
+		let captured =
+			b"ffmpeg version 6.1\n  built with gcc\n\n[video4linux2] /dev/video0: No such file or directory\n";
+		assert_eq!(
+			tail(captured, 2),
+			"built with gcc\n[video4linux2] /dev/video0: No such file or directory"
+		);
+		assert_eq!(tail(b"\n", 2), "(no output)");
+	}
+
+	/// a supervisor of the shell script `script` faking ffmpeg, writing to its own output directory
+	#[cfg(unix)]
+	fn fake(name: &str, script: &str) -> Supervisor {
+		use std::os::unix::fs::PermissionsExt;
+
+		let dir = crate::testing::temp_dir(&format!("ffmpeg-{name}"));
+		std::fs::create_dir_all(dir.join("output")).unwrap();
+		let program = dir.join("ffmpeg");
+		std::fs::write(&program, format!("#!/bin/sh\n{script}\n")).unwrap();
+		std::fs::set_permissions(&program, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+		let settings = "gop_num=1\nfps=25\ntarget_segment_duration=2.0\n===AUDIO===\nname,sampling,bitrate\n\
+			===VIDEO===\nname,resolution,bitrate,max_rate,buffer_size\nvideo,1280x720,3000000,3000000,6000000\n";
+		let settings = Settings::from_bytes(settings.into(), "input".into(), dir.join("output"), true, false).unwrap();
+		Supervisor::new(settings, Default::default()).program(program)
+	}
+
+	#[cfg(unix)]
+	#[tokio::test]
+	async fn test_startup_exit() {
+		let mut ffmpeg = fake("exit", "echo 'Unknown encoder libx264' >&2\nexit 1");
+
+		// not restarted
+		let err = ffmpeg.run().await.unwrap_err();
+		let Error::FfmpegStartup { reason, stderr } = &err else {
+			panic!("{err}");
+		};
+		assert!(reason.starts_with("exited with exit status: 1"), "{reason}");
+		assert_eq!(stderr, "Unknown encoder libx264");
+		assert!(err.to_string().ends_with(":\nUnknown encoder libx264"), "{err}");
+	}
+
+	#[cfg(unix)]
+	#[tokio::test]
+	async fn test_startup_timeout() {
+		let mut ffmpeg = fake("timeout", "echo 'Invalid argument' >&2\nexec sleep 10")
+			.startup_timeout(std::time::Duration::from_millis(500));
+
+		let err = ffmpeg.run().await.unwrap_err();
+		let Error::FfmpegStartup { reason, stderr } = &err else {
+			panic!("{err}");
+		};
+		assert_eq!(reason, "no segment written within 500ms");
+		assert_eq!(stderr, "Invalid argument");
+		assert!(ffmpeg.current.is_none());
+
+		// a segment in time
+		let mut ffmpeg = fake("segment", "touch \"$(dirname \"$0\")/output/init.m4s\"\nexit 0")
+			.startup_timeout(std::time::Duration::from_secs(5));
+		ffmpeg.run().await.unwrap();
+	}
+}
@@ -1,15 +1,84 @@
-use std::{fs, path};
+use notify::Watcher;
+use std::{fs, path, time};
 
 use super::Error;
 
+/// events of a watched path, kept alive next to the watcher reporting them
+pub type Events = tokio::sync::mpsc::UnboundedReceiver<notify::Result<notify::Event>>;
+
+/// watch the directory of the file `path`, polling every `poll_interval` instead of using inotify
+///
+/// The directory is watched, as editors often replace the file instead of writing to it.
+pub fn watch_file(
+	path: &path::Path,
+	poll_interval: Option<time::Duration>,
+) -> Result<(Box<dyn Watcher + Send>, Events), Error> {
+	let (tx, events) = tokio::sync::mpsc::unbounded_channel();
+
+	let handler = move |event| {
+		let _ = tx.send(event);
+	};
+	let watcher: notify::Result<Box<dyn Watcher + Send>> = match poll_interval {
+		Some(interval) => {
+			let config = notify::Config::default().with_poll_interval(interval);
+			notify::PollWatcher::new(handler, config).map(|w| Box::new(w) as _)
+		}
+		None => notify::recommended_watcher(handler).map(|w| Box::new(w) as _),
+	};
+	let mut watcher = match watcher {
+		Ok(w) => w,
+		Err(e) => {
+			tracing::error!(error = %e);
+			return Err(Error::Notify(e));
+		}
+	};
+
+	let dir = match path.parent() {
+		Some(dir) if !dir.as_os_str().is_empty() => dir,
+		_ => path::Path::new("."),
+	};
+	if let Err(e) = watcher.watch(dir, notify::RecursiveMode::NonRecursive) {
+		tracing::error!(error = %e);
+		return Err(Error::Notify(e));
+	}
+
+	Ok((watcher, events))
+}
+
+/// wait for an event changing the file `path` of [watch_file]
+pub async fn changed(events: &mut Events, path: &path::Path) -> Result<(), Error> {
+	loop {
+		let event = match events.recv().await {
+			Some(Ok(event)) => event,
+			Some(Err(e)) => {
+				tracing::error!(error = %e);
+				return Err(Error::Notify(e));
+			}
+			// the sender lives in the watcher kept next to the events
+			None => return std::future::pending().await,
+		};
+
+		if event.kind.is_access() {
+			continue;
+		}
+		if event
+			.paths
+			.iter()
+			.any(|changed| changed.file_name() == path.file_name())
+		{
+			return Ok(());
+		}
+	}
+}
+
 /// create full directory path
 pub fn init_output<P>(output: P) -> Result<(), Error>
 where
 	P: AsRef<path::Path>,
 {
 	if let Err(e) = fs::create_dir_all(output) {
-		println!("Error: {}", e);
-		return Err(Error::Crate("fs".to_string(), e.to_string()));
+		tracing::error!(error = %e);
+		return Err(Error::Io(e));
 	}
 	Ok(())
 }
@@ -20,8 +89,8 @@ where
 	P: AsRef<path::Path>,
 {
 	if let Err(e) = fs::remove_dir_all(output) {
-		println!("Error: {}", e);
-		return Err(Error::Crate("fs".to_string(), e.to_string()));
+		tracing::error!(error = %e);
+		return Err(Error::Io(e));
 	}
 	Ok(())
 }
@@ -52,6 +121,18 @@ pub fn split_vec_once(vec: Vec<u8>, sep: &[u8]) -> (Vec<u8>, Vec<u8>) {
 	(first, second)
 }
 
+/// inode of a file, detects a file replaced under the same name
+#[cfg(unix)]
+pub fn inode(metadata: &fs::Metadata) -> Option<u64> {
+	use std::os::unix::fs::MetadataExt;
+	Some(metadata.ino())
+}
+
+#[cfg(not(unix))]
+pub fn inode(_metadata: &fs::Metadata) -> Option<u64> {
+	None
+}
+
 /// attempts to convert `path` to a String
 pub fn path_to_string<P>(path: P) -> Option<String>
 where
@@ -60,29 +141,26 @@ where
 	Some(path.as_ref().as_os_str().to_str()?.to_string())
 }
 
-/// removes possible trailing ".tmp"
-pub fn clean_path<P>(path: P) -> Result<String, Error>
-where
-	P: AsRef<path::Path>,
-{
-	let Some(path) = path_to_string(path) else {
-		return Err(Error::FailedToConvert);
-	};
-
-	let path = path.replace(".tmp", "");
+/// the name of a segment, the same for the tmp file ffmpeg writes and the file it is renamed to once complete
+pub fn clean_path(path: &str) -> &str {
+	path.strip_suffix(".tmp").unwrap_or(path)
+}
 
-	Ok(path)
+/// the tmp file of a segment, or the segment its tmp file is renamed to
+pub fn counterpart(path: &str) -> String {
+	match path.strip_suffix(".tmp") {
+		Some(path) => path.to_string(),
+		None => format!("{path}.tmp"),
+	}
 }
 
-pub fn append_shell(buf: &mut Vec<u8>, slice: &[String]) {
-	let slice = if slice[0] == "-adaptation_sets" {
-		vec![slice[0].clone(), format!("\"{}\"", slice[1])]
-	} else {
-		slice.to_vec()
-	};
-	let mut b = format!(" \\\n\t{}", slice.join(" "))
-		.replace('$', "\\$")
-		.as_bytes()
-		.to_vec();
-	buf.append(&mut b);
+/// single quote `arg` unless it only holds characters the shell never splits or expands
+pub fn shell_quote(arg: &str) -> String {
+	let safe = |c: char| c.is_ascii_alphanumeric() || "+-=_./:,%@^".contains(c);
+	if !arg.is_empty() && arg.chars().all(safe) {
+		return arg.to_string();
+	}
+
+	// a single quote ends the quoting, is escaped and starts it again
+	format!("'{}'", arg.replace('\'', r"'\''"))
 }
@@ -0,0 +1,84 @@
+use moq_transport::coding::{Decode, Encode};
+
+use super::Error;
+
+/// flag of [Frame] headers whose payload can be decoded on its own
+const KEYFRAME: u8 = 0x1;
+
+/// A single encoded frame with a LOC-style header, the object payload of tracks with [moq_catalog::Packaging::LOC].
+///
+/// The header holds the presentation timestamp and the duration in microseconds as QUIC varints,
+/// followed by a flags byte, the rest of the object is the frame as written by the encoder.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+	pub timestamp: std::time::Duration,
+	pub duration: std::time::Duration,
+	pub keyframe: bool,
+	pub payload: bytes::Bytes,
+}
+
+impl Frame {
+	pub fn encode(&self) -> Result<bytes::Bytes, Error> {
+		let mut buf = bytes::BytesMut::with_capacity(self.payload.len() + 17);
+
+		for value in [self.timestamp, self.duration] {
+			let micros: u64 = value.as_micros().try_into().unwrap_or(u64::MAX);
+			if let Err(e) = micros.encode(&mut buf) {
+				tracing::error!(error = %e);
+				return Err(Error::Encode(e));
+			}
+		}
+
+		buf.extend_from_slice(&[if self.keyframe { KEYFRAME } else { 0 }]);
+		buf.extend_from_slice(&self.payload);
+
+		Ok(buf.freeze())
+	}
+
+	pub fn decode(mut buf: bytes::Bytes) -> Result<Self, Error> {
+		let mut header = [0; 2];
+		for value in header.iter_mut() {
+			*value = match u64::decode(&mut buf) {
+				Ok(v) => v,
+				Err(e) => {
+					tracing::error!(error = %e);
+					return Err(Error::Decode(e));
+				}
+			};
+		}
+
+		if buf.is_empty() {
+			tracing::error!("missing LOC flags");
+			return Err(Error::Malformed("loc", "missing flags".to_string()));
+		}
+		let flags = buf.split_to(1)[0];
+
+		Ok(Self {
+			timestamp: std::time::Duration::from_micros(header[0]),
+			duration: std::time::Duration::from_micros(header[1]),
+			keyframe: flags & KEYFRAME != 0,
+			payload: buf,
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_roundtrip() {
+		let frame = Frame {
+			timestamp: std::time::Duration::from_millis(2040),
+			duration: std::time::Duration::from_millis(40),
+			keyframe: true,
+			payload: bytes::Bytes::from_static(b"frame"),
+		};
+
+		let encoded = frame.encode().unwrap();
+		assert_eq!(encoded.len(), 4 + 4 + 1 + 5);
+		assert_eq!(Frame::decode(encoded).unwrap(), frame);
+
+		assert!(Frame::decode(bytes::Bytes::from_static(&[0x00, 0x00])).is_err());
+	}
+}
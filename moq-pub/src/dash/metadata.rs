@@ -0,0 +1,203 @@
+use notify::Watcher;
+use std::{path, time};
+use tokio::sync::watch;
+
+use super::{helper, Error};
+
+/// track of the timed metadata, if published
+pub const METADATA_TRACK: &str = ".metadata";
+
+/// the mime type of the payloads on the [METADATA_TRACK]
+pub const METADATA_MIME_TYPE: &str = "application/json";
+
+/// A line of the metadata file, published once the media reaches `at_ms`.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+pub struct Marker {
+	pub at_ms: u64,
+	pub payload: serde_json::Value,
+}
+
+impl Marker {
+	pub fn at(&self) -> time::Duration {
+		time::Duration::from_millis(self.at_ms)
+	}
+}
+
+/// The markers not published yet, in the order of their media time.
+#[derive(Debug, Default)]
+pub struct Schedule {
+	pending: Vec<Marker>,
+}
+
+impl Schedule {
+	/// add `markers`, the ones at the same media time keep the order of the file
+	pub fn add(&mut self, markers: Vec<Marker>) {
+		self.pending.extend(markers);
+		self.pending.sort_by_key(|marker| marker.at_ms);
+	}
+
+	/// take the markers the media time `now` passed, in order
+	pub fn due(&mut self, now: time::Duration) -> Vec<Marker> {
+		let due = self.pending.partition_point(|marker| marker.at() <= now);
+		self.pending.drain(..due).collect()
+	}
+}
+
+/// Reads the markers appended to a JSONL file, one `{ "at_ms": .., "payload": .. }` per line.
+pub struct MetadataFile {
+	path: path::PathBuf,
+	/// the bytes of the complete lines read so far
+	offset: usize,
+	events: helper::Events,
+	_watcher: Box<dyn Watcher + Send>,
+}
+
+impl MetadataFile {
+	/// watch `path`, polling every `poll_interval` instead of using inotify
+	pub fn new(path: path::PathBuf, poll_interval: Option<time::Duration>) -> Result<Self, Error> {
+		let (watcher, events) = helper::watch_file(&path, poll_interval)?;
+
+		Ok(Self {
+			path,
+			offset: 0,
+			events,
+			_watcher: watcher,
+		})
+	}
+
+	/// the markers of the lines completed since the last read
+	///
+	/// A line still being written is read once its newline arrived, invalid lines are logged and skipped.
+	pub async fn read(&mut self) -> Result<Vec<Marker>, Error> {
+		let buf = match tokio::fs::read(&self.path).await {
+			Ok(b) => b,
+			Err(e) => {
+				tracing::error!(path = %self.path.display(), error = %e);
+				return Err(Error::Io(e));
+			}
+		};
+
+		if buf.len() < self.offset {
+			log::warn!("{} was truncated, reading it from the start", self.path.display());
+			self.offset = 0;
+		}
+		let Some(end) = buf[self.offset..].iter().rposition(|&b| b == b'\n') else {
+			return Ok(Vec::new());
+		};
+		let lines = &buf[self.offset..self.offset + end + 1];
+		self.offset += end + 1;
+
+		Ok(parse(lines, &self.path))
+	}
+
+	/// wait for an event changing the file
+	pub async fn changed(&mut self) -> Result<(), Error> {
+		helper::changed(&mut self.events, &self.path).await
+	}
+}
+
+/// the markers of the complete lines `buf` of the file `path`, blank lines skipped
+fn parse(buf: &[u8], path: &path::Path) -> Vec<Marker> {
+	let mut markers = Vec::new();
+	for line in buf.split(|&b| b == b'\n') {
+		if line.iter().all(u8::is_ascii_whitespace) {
+			continue;
+		}
+
+		match serde_json::from_slice(line) {
+			Ok(marker) => markers.push(marker),
+			Err(e) => log::warn!("skipping an invalid line of {}: {e}", path.display()),
+		}
+	}
+	markers
+}
+
+/// hand the markers of `file` to `publish` once the media time of `timeline` passed them
+///
+/// Markers already passed are handed over right away, in order. Runs until the sender of `timeline` is gone.
+pub async fn run<F>(
+	mut file: MetadataFile,
+	mut timeline: watch::Receiver<time::Duration>,
+	mut publish: F,
+) -> Result<(), Error>
+where
+	F: FnMut(Marker) -> Result<(), Error>,
+{
+	let mut schedule = Schedule::default();
+	schedule.add(file.read().await?);
+
+	loop {
+		let now = *timeline.borrow_and_update();
+		for marker in schedule.due(now) {
+			log::info!("publishing the metadata at {}ms", marker.at_ms);
+			publish(marker)?;
+		}
+
+		tokio::select! {
+			res = timeline.changed() => if res.is_err() {
+				return Ok(());
+			},
+			res = file.changed() => {
+				res?;
+				schedule.add(file.read().await?);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn marker(at_ms: u64, title: &str) -> Marker {
+		Marker {
+			at_ms,
+			payload: serde_json::json!({ "title": title }),
+		}
+	}
+
+	#[test]
+	fn test_schedule() {
+		let mut schedule = Schedule::default();
+		schedule.add(vec![marker(4000, "ad"), marker(0, "intro"), marker(2000, "chapter 1")]);
+
+		// the synthetic timeline of the media
+		assert_eq!(schedule.due(time::Duration::ZERO), [marker(0, "intro")]);
+		assert!(schedule.due(time::Duration::from_millis(1999)).is_empty());
+		assert_eq!(
+			schedule.due(time::Duration::from_millis(2000)),
+			[marker(2000, "chapter 1")]
+		);
+
+		// appended later, in the past and at the same time as a pending one
+		schedule.add(vec![marker(1000, "late"), marker(4000, "ad end")]);
+		assert_eq!(
+			schedule.due(time::Duration::from_millis(5000)),
+			[marker(1000, "late"), marker(4000, "ad"), marker(4000, "ad end")]
+		);
+		assert!(schedule.due(time::Duration::MAX).is_empty());
+	}
+
+	#[tokio::test]
+	async fn test_read() {
+		let dir = crate::testing::temp_dir("metadata");
+		let path = dir.join("markers.jsonl");
+
+		std::fs::write(
+			&path,
+			"{\"at_ms\": 0, \"payload\": {\"title\": \"intro\"}}\n\nnot json\n{\"at_ms\": 2000, ",
+		)
+		.unwrap();
+		let mut file = MetadataFile::new(path.clone(), None).unwrap();
+		assert_eq!(file.read().await.unwrap(), [marker(0, "intro")]);
+
+		// the incomplete line once finished
+		let mut appended = std::fs::read(&path).unwrap();
+		appended.extend_from_slice(b"\"payload\": {\"title\": \"chapter 1\"}}\n");
+		std::fs::write(&path, appended).unwrap();
+		assert_eq!(file.read().await.unwrap(), [marker(2000, "chapter 1")]);
+		assert!(file.read().await.unwrap().is_empty());
+
+		let _ = std::fs::remove_dir_all(&dir);
+	}
+}
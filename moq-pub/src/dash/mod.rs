@@ -1,92 +1,631 @@
-use futures::StreamExt;
-use signal_hook::consts::signal::{SIGHUP, SIGINT, SIGQUIT, SIGTERM};
-use std::io::Read;
-use std::path;
+use futures::{future::FusedFuture, FutureExt};
+use std::{path, time};
+use tracing::Instrument;
 
+mod alignment;
+mod broadcasts;
 mod error;
+mod ffmpeg;
 mod helper;
+mod loc;
+mod metadata;
 mod publisher;
+mod relay;
+mod reload;
 mod settings;
 mod watcher;
 
-use error::Error;
-use publisher::Publisher;
-use settings::Settings;
+pub use broadcasts::{BroadcastConfig, Broadcasts};
+pub use error::Error;
+pub use ffmpeg::{Ffmpeg, Restart, Supervisor, STARTUP_GRACE, STARTUP_TIMEOUT};
+pub use loc::Frame as LocFrame;
+pub use metadata::{Marker, METADATA_TRACK};
+pub use relay::{announce, announce_all, Reconnect};
+pub use reload::{Reloader, SettingsWatcher};
+pub use settings::{
+	AudioSetting, ExtraArgs, FfmpegArgs, Format, Input, Ladder, Setting, Settings, SettingsFile, TrackNameTemplate,
+	VideoSetting, Violation,
+};
+pub use watcher::QUIESCENCE;
 
-pub struct PubInfo {
+pub use publisher::{
+	GroupOrder, ObjectMode, Publisher, TimelineRecord, AUDIO_PRIORITY, CATALOG_INITIAL_TIMEOUT, CATALOG_TRACK,
+	DISCONTINUITY_THRESHOLD, INIT_DATA_LIMIT, INIT_TRACK_SUFFIX, MAX_ATOM_SIZE, MAX_OBJECT_SIZE,
+	TIMELINE_GROUP_DURATION, TIMELINE_MIME_TYPE, TIMELINE_TRACK, VIDEO_PRIORITY,
+};
+
+/// how long the events of the segments written last are still handled once ffmpeg finished
+const DRAIN: time::Duration = time::Duration::from_millis(200);
+
+/// where the broadcast is published and how the relay is connected to
+pub struct ConnectOptions {
 	pub tls: moq_native::tls::Args,
 	pub url: url::Url,
 	pub bind: std::net::SocketAddr,
 	pub namespace: String,
+	/// QUIC tuning, ex. the idle timeout and the congestion controller
+	pub transport: moq_native::quic::Transport,
 }
 
+/// Runs the full DASH pipeline: ffmpeg, the MoQ session and the [DashPublisher]
 pub struct Dash {
-	settings: settings::Settings<std::path::PathBuf>,
+	settings: Settings<path::PathBuf>,
 	output: path::PathBuf,
-	info: PubInfo,
+	options: ConnectOptions,
+	restart: Restart,
+	ffmpeg: Option<path::PathBuf>,
+	startup_timeout: Option<time::Duration>,
+	reconnect: Reconnect,
+	poll_interval: Option<time::Duration>,
+	debounce: Option<time::Duration>,
+	quiescence: Option<time::Duration>,
+	max_read: Option<usize>,
+	metrics: crate::metrics::Metrics,
+	packaging: moq_catalog::Packaging,
+	group_order: GroupOrder,
+	object_mode: ObjectMode,
+	publish_mpd: bool,
+	emsg_track: bool,
+	metadata: Option<path::PathBuf>,
+	timeline_track: Option<time::Duration>,
+	init_tracks: bool,
+	audio_group_duration: Option<time::Duration>,
+	group_duration: Option<time::Duration>,
+	discontinuity_threshold: Option<time::Duration>,
+	max_group_age: Option<time::Duration>,
+	catalog_initial_timeout: Option<time::Duration>,
+	republish_interval: Option<time::Duration>,
+	catalog_track: Option<String>,
+	hierarchical_catalog: bool,
+	catalog_format: moq_catalog::CatalogFormat,
+	track_name_template: Option<TrackNameTemplate>,
+	strict_alignment: bool,
+	strict_traks: bool,
+	lazy_tracks: bool,
+	max_object_size: Option<usize>,
+	max_atom_size: Option<usize>,
+	priorities: Option<(u8, u8)>,
+	settings_file: Option<path::PathBuf>,
+	archive: Option<crate::archive::Archive>,
+	stats: Option<crate::stats::Stats>,
+	linger: time::Duration,
+	start: Option<time::SystemTime>,
 }
 
 impl Dash {
-	pub fn new(cli: super::Dash) -> Result<Self, Error> {
-		let settings = settings::Settings::new(
-			cli.settings_file,
-			cli.input,
-			cli.output.clone(),
-			cli.no_audio,
-			cli.looping,
-		)?;
-
-		settings.save(cli.output.with_file_name("dash.sh"))?;
+	/// fails with [Error::Validation] if the settings cannot be encoded
+	pub fn new(
+		settings: Settings<path::PathBuf>,
+		output: path::PathBuf,
+		options: ConnectOptions,
+		restart: Restart,
+	) -> Result<Self, Error> {
+		settings.validate()?;
 
 		Ok(Self {
 			settings,
-			output: cli.output,
-			info: PubInfo {
-				tls: cli.tls,
-				url: cli.url,
-				bind: cli.bind,
-				namespace: cli.name,
-			},
+			output,
+			options,
+			restart,
+			ffmpeg: None,
+			startup_timeout: None,
+			reconnect: Default::default(),
+			poll_interval: None,
+			debounce: None,
+			quiescence: None,
+			max_read: None,
+			metrics: Default::default(),
+			packaging: Default::default(),
+			group_order: Default::default(),
+			object_mode: Default::default(),
+			publish_mpd: false,
+			emsg_track: false,
+			metadata: None,
+			timeline_track: None,
+			init_tracks: false,
+			audio_group_duration: None,
+			group_duration: None,
+			discontinuity_threshold: None,
+			max_group_age: None,
+			catalog_initial_timeout: None,
+			republish_interval: None,
+			catalog_track: None,
+			hierarchical_catalog: false,
+			catalog_format: Default::default(),
+			track_name_template: None,
+			strict_alignment: false,
+			strict_traks: false,
+			lazy_tracks: false,
+			max_object_size: None,
+			max_atom_size: None,
+			priorities: None,
+			settings_file: None,
+			archive: None,
+			stats: None,
+			linger: time::Duration::ZERO,
+			start: None,
 		})
 	}
 
+	/// poll the output directory instead of using inotify, ex. on network filesystems
+	pub fn poll_interval(mut self, interval: time::Duration) -> Self {
+		self.poll_interval = Some(interval);
+		self
+	}
+
+	/// coalesce the writes to a segment within `debounce` into a single read, zero reads on every write
+	pub fn debounce(mut self, debounce: time::Duration) -> Self {
+		self.debounce = Some(debounce);
+		self
+	}
+
+	/// a segment without writes for `quiescence` is complete, zero waits for its close or rename
+	pub fn quiescence(mut self, quiescence: time::Duration) -> Self {
+		self.quiescence = Some(quiescence);
+		self
+	}
+
+	/// read at most `max_read` bytes of a segment at once
+	pub fn max_read(mut self, max_read: usize) -> Self {
+		self.max_read = Some(max_read);
+		self
+	}
+
+	/// how the relay is re-dialed when the session drops, ffmpeg keeps running meanwhile
+	pub fn reconnect(mut self, reconnect: Reconnect) -> Self {
+		self.reconnect = reconnect;
+		self
+	}
+
+	/// run `program` instead of the ffmpeg found in the PATH
+	pub fn ffmpeg(mut self, program: path::PathBuf) -> Self {
+		self.ffmpeg = Some(program);
+		self
+	}
+
+	/// fail the run if ffmpeg writes no segment within `timeout` of its start, [STARTUP_TIMEOUT] by default
+	pub fn startup_timeout(mut self, timeout: time::Duration) -> Self {
+		self.startup_timeout = Some(timeout);
+		self
+	}
+
+	/// record the published tracks in `metrics`
+	pub fn metrics(mut self, metrics: crate::metrics::Metrics) -> Self {
+		self.metrics = metrics;
+		self
+	}
+
+	/// how the media is packaged into objects, CMAF fragments by default
+	pub fn packaging(mut self, packaging: moq_catalog::Packaging) -> Self {
+		self.packaging = packaging;
+		self
+	}
+
+	/// which groups the relay sends first, the newest by default
+	pub fn group_order(mut self, group_order: GroupOrder) -> Self {
+		self.group_order = group_order;
+		self
+	}
+
+	/// how the CMAF atoms are cut into objects, one per atom by default
+	pub fn object_mode(mut self, object_mode: ObjectMode) -> Self {
+		self.object_mode = object_mode;
+		self
+	}
+
+	/// publish the manifest ffmpeg writes on the `.mpd` track
+	pub fn publish_mpd(mut self, publish_mpd: bool) -> Self {
+		self.publish_mpd = publish_mpd;
+		self
+	}
+
+	/// publish the event messages on the `.emsg` track instead of in front of the next moof
+	pub fn emsg_track(mut self, emsg_track: bool) -> Self {
+		self.emsg_track = emsg_track;
+		self
+	}
+
+	/// publish the markers of the JSONL file `path` on the `.metadata` track, once the media reaches them
+	pub fn metadata(mut self, path: path::PathBuf) -> Self {
+		self.metadata = Some(path);
+		self
+	}
+
+	/// publish the wall clock and media time of every prft on the `.timeline` track, a new group every `group_duration`
+	pub fn timeline_track(mut self, group_duration: time::Duration) -> Self {
+		self.timeline_track = Some(group_duration);
+		self
+	}
+
+	/// send the init segments on `<track>_init` tracks referenced by initTrack, instead of inlined as initData
+	pub fn init_tracks(mut self, init_tracks: bool) -> Self {
+		self.init_tracks = init_tracks;
+		self
+	}
+
+	/// end the audio groups every `duration` of media, by default only audio-only broadcasts do every segment
+	pub fn audio_group_duration(mut self, duration: time::Duration) -> Self {
+		self.audio_group_duration = Some(duration);
+		self
+	}
+
+	/// end the groups once they cover `duration` of media, at the next keyframe for video, by default every GOP
+	pub fn group_duration(mut self, duration: time::Duration) -> Self {
+		self.group_duration = Some(duration);
+		self
+	}
+
+	/// start a new group at gaps in the timestamps larger than `threshold`, by default [DISCONTINUITY_THRESHOLD]
+	pub fn discontinuity_threshold(mut self, threshold: time::Duration) -> Self {
+		self.discontinuity_threshold = Some(threshold);
+		self
+	}
+
+	/// abandon groups whose writes block past `age` of wall clock, skipping to the next keyframe, by default never
+	pub fn max_group_age(mut self, age: time::Duration) -> Self {
+		self.max_group_age = Some(age);
+		self
+	}
+
+	/// wait at most `timeout` for all reps before the first catalog, by default [CATALOG_INITIAL_TIMEOUT]
+	pub fn catalog_initial_timeout(mut self, timeout: time::Duration) -> Self {
+		self.catalog_initial_timeout = Some(timeout);
+		self
+	}
+
+	/// write the newest catalog and init segments again every `interval` as the newest groups, by default never
+	pub fn republish_interval(mut self, interval: time::Duration) -> Self {
+		self.republish_interval = Some(interval);
+		self
+	}
+
+	/// publish the catalog on the track `name`, `.catalog` by default
+	pub fn catalog_track(mut self, name: &str) -> Self {
+		self.catalog_track = Some(name.to_string());
+		self
+	}
+
+	/// publish a catalog per media type, listed by the root catalog on the catalog track
+	pub fn hierarchical_catalog(mut self, hierarchical: bool) -> Self {
+		self.hierarchical_catalog = hierarchical;
+		self
+	}
+
+	/// publish the catalogs as pretty or compact JSON or as CBOR, compact JSON by default
+	pub fn catalog_format(mut self, format: moq_catalog::CatalogFormat) -> Self {
+		self.catalog_format = format;
+		self
+	}
+
+	/// derive the track names from the rep settings, checked for duplicates before ffmpeg starts
+	pub fn track_name_template(mut self, template: TrackNameTemplate) -> Self {
+		self.track_name_template = Some(template);
+		self
+	}
+
+	/// stop publishing once the video reps cut their groups at different timestamps, only warns by default
+	pub fn strict_alignment(mut self, strict: bool) -> Self {
+		self.strict_alignment = strict;
+		self
+	}
+
+	/// fail on a fragment of a trak the init segment does not describe, it is skipped with a warning by default
+	pub fn strict_traks(mut self, strict: bool) -> Self {
+		self.strict_traks = strict;
+		self
+	}
+
+	/// stop writing the groups of media tracks nobody is subscribed to, they are all written by default
+	pub fn lazy_tracks(mut self, lazy: bool) -> Self {
+		self.lazy_tracks = lazy;
+		self
+	}
+
+	/// split the objects larger than `size` bytes, [MAX_OBJECT_SIZE] by default
+	pub fn max_object_size(mut self, size: usize) -> Self {
+		self.max_object_size = Some(size);
+		self
+	}
+
+	/// take atoms declaring more than `size` bytes for corrupt data, [MAX_ATOM_SIZE] by default
+	pub fn max_atom_size(mut self, size: usize) -> Self {
+		self.max_atom_size = Some(size);
+		self
+	}
+
+	/// the base priorities of the audio and the video tracks, [AUDIO_PRIORITY] and [VIDEO_PRIORITY] by default
+	pub fn priorities(mut self, audio: u8, video: u8) -> Self {
+		self.priorities = Some((audio, video));
+		self
+	}
+
+	/// write every published object to `archive` as well, flushed before [Self::run] returns
+	pub fn archive(mut self, archive: crate::archive::Archive) -> Self {
+		self.archive = Some(archive);
+		self
+	}
+
+	/// record every published group and object to `stats`, flushed before [Self::run] returns
+	pub fn stats(mut self, stats: crate::stats::Stats) -> Self {
+		self.stats = Some(stats);
+		self
+	}
+
+	/// stay connected for `linger` once ffmpeg finished the input, late subscribers still get the last groups
+	pub fn linger(mut self, linger: time::Duration) -> Self {
+		self.linger = linger;
+		self
+	}
+
+	/// announce right away but start ffmpeg at the wall-clock time `at`, ex. in sync with other publishers
+	pub fn start_at(mut self, at: time::SystemTime) -> Self {
+		self.start = Some(at);
+		self
+	}
+
+	/// apply the changes to `settings_file` while running, ffmpeg is restarted and the catalog updated
+	pub fn watch_settings(mut self, settings_file: path::PathBuf) -> Self {
+		self.settings_file = Some(settings_file);
+		self
+	}
+
 	pub async fn run(self) -> Result<(), Error> {
-		helper::init_output(&self.output)?;
+		let (mut broadcast, reader) = self.start()?;
+
+		// kept alive past the select for the teardown, reconnects are handled inside
+		let shutdown = tokio::sync::Notify::new();
+		let namespace = reader.namespace.clone();
+		let mut relay = Box::pin(
+			announce(&self.options, reader, &self.reconnect, &shutdown)
+				.instrument(tracing::info_span!("relay", namespace))
+				.fuse(),
+		);
 
-		let args = self.settings.to_args()?;
-		let mut ffmpeg = match std::process::Command::new("ffmpeg")
-			.args(args)
-			.stdout(std::process::Stdio::null())
-			.stderr(std::process::Stdio::piped())
-			.spawn()
-		{
-			Ok(c) => c,
-			Err(e) => {
-				println!("Error: {}", e);
-				return Err(Error::Crate("process".to_string(), e.to_string()));
+		// restarts are handled inside, the session is only torn down once ffmpeg is done for good
+		let mut failed = None;
+		let finished = tokio::select! {
+			res = &mut relay => {
+				tracing::info!(?res, "relay ended");
+				false
+			}
+			res = broadcast.run() => match res {
+				Ok(finished) => finished,
+				// returned once cleaned up, ex. ffmpeg failing to start
+				Err(e) => {
+					failed = Some(e);
+					false
+				}
+			},
+			res = close() => {
+				tracing::info!(?res, "closed by signal");
+				false
 			}
 		};
 
-		let Some(output) = ffmpeg.stderr.take() else {
-			println!("Error: failed to take FFmpeg stderr");
-			return Err(Error::Crate("process".to_string(), "failed to take stderr".to_string()));
+		tracing::info!("termination initiated, cleaning up");
+
+		match finished {
+			true => {
+				broadcast.finish().await?;
+				if !relay.is_terminated() && !self.linger.is_zero() {
+					tracing::info!(linger = ?self.linger, "lingering");
+					tokio::select! {
+						_ = tokio::time::sleep(self.linger) => (),
+						res = &mut relay => tracing::info!(?res, "relay ended"),
+						res = close() => tracing::info!(?res, "closed by signal"),
+					}
+				}
+			}
+			false => broadcast.close().await?,
+		}
+
+		if !relay.is_terminated() {
+			shutdown.notify_one();
+			let _ = tokio::time::timeout(relay::GRACE_PERIOD, &mut relay).await;
+		}
+
+		match failed {
+			Some(e) => Err(e),
+			None => Ok(()),
+		}
+	}
+
+	/// create the output directory and the publisher of its segments, [Broadcast::run] starts ffmpeg
+	fn start(&self) -> Result<(Broadcast, moq_transport::serve::TracksReader), Error> {
+		helper::init_output(&self.output)?;
+
+		let changes = match &self.settings_file {
+			Some(path) => Some(SettingsWatcher::new(
+				self.settings.clone(),
+				path.clone(),
+				self.poll_interval,
+			)?),
+			None => None,
+		};
+		let template = self.track_name_template.clone().unwrap_or_default();
+
+		let mut builder = DashPublisher::builder()
+			.output(&self.output)
+			.settings(self.settings.clone())
+			.namespace(&self.options.namespace)
+			.metrics(self.metrics.clone())
+			.packaging(self.packaging)
+			.group_order(self.group_order)
+			.object_mode(self.object_mode)
+			.publish_mpd(self.publish_mpd)
+			.emsg_track(self.emsg_track)
+			.init_tracks(self.init_tracks)
+			.strict_alignment(self.strict_alignment)
+			.strict_traks(self.strict_traks)
+			.lazy_tracks(self.lazy_tracks);
+		if let Some(size) = self.max_object_size {
+			builder = builder.max_object_size(size);
+		}
+		if let Some(size) = self.max_atom_size {
+			builder = builder.max_atom_size(size);
+		}
+		if let Some((audio, video)) = self.priorities {
+			builder = builder.priorities(audio, video);
+		}
+		if let Some(interval) = self.poll_interval {
+			builder = builder.poll_interval(interval);
+		}
+		if let Some(debounce) = self.debounce {
+			builder = builder.debounce(debounce);
+		}
+		if let Some(quiescence) = self.quiescence {
+			builder = builder.quiescence(quiescence);
+		}
+		if let Some(max_read) = self.max_read {
+			builder = builder.max_read(max_read);
+		}
+		if let Some(duration) = self.audio_group_duration {
+			builder = builder.audio_group_duration(duration);
+		}
+		if let Some(duration) = self.group_duration {
+			builder = builder.group_duration(duration);
+		}
+		if let Some(threshold) = self.discontinuity_threshold {
+			builder = builder.discontinuity_threshold(threshold);
+		}
+		if let Some(age) = self.max_group_age {
+			builder = builder.max_group_age(age);
+		}
+		if let Some(timeout) = self.catalog_initial_timeout {
+			builder = builder.catalog_initial_timeout(timeout);
+		}
+		if let Some(interval) = self.republish_interval {
+			builder = builder.republish_interval(interval);
+		}
+		if let Some(name) = &self.catalog_track {
+			builder = builder.catalog_track(name);
+		}
+		builder = builder
+			.hierarchical_catalog(self.hierarchical_catalog)
+			.catalog_format(self.catalog_format);
+		if let Some(template) = self.track_name_template.clone() {
+			builder = builder.track_name_template(template);
+		}
+		if let Some(archive) = self.archive.clone() {
+			builder = builder.archive(archive);
+		}
+		if let Some(stats) = self.stats.clone() {
+			builder = builder.stats(stats);
+		}
+		if let Some(path) = self.metadata.clone() {
+			builder = builder.metadata(path);
+		}
+		if let Some(duration) = self.timeline_track {
+			builder = builder.timeline_track(duration);
+		}
+		let (publisher, reader) = builder.build()?;
+		let reloader = publisher.reloader();
+
+		let mut ffmpeg = Supervisor::new(self.settings.clone(), self.restart.clone());
+		if let Some(program) = self.ffmpeg.clone() {
+			ffmpeg = ffmpeg.program(program);
+		}
+		if let Some(timeout) = self.startup_timeout {
+			ffmpeg = ffmpeg.startup_timeout(timeout);
+		}
+
+		let broadcast = Broadcast {
+			publisher,
+			ffmpeg,
+			changes,
+			reloader,
+			template,
+			output: self.output.clone(),
+			archive: self.archive.clone(),
+			stats: self.stats.clone(),
+			linger: self.linger,
+			start: self.start,
 		};
 
-		let (session, mut publisher, writer, reader) = create(self.info).await?;
+		Ok((broadcast, reader))
+	}
+}
+
+/// ffmpeg and the [DashPublisher] of its output, the relay session is run next to it
+struct Broadcast {
+	publisher: DashPublisher,
+	ffmpeg: Supervisor,
+	changes: Option<SettingsWatcher>,
+	reloader: Reloader,
+	template: TrackNameTemplate,
+	output: path::PathBuf,
+	archive: Option<crate::archive::Archive>,
+	stats: Option<crate::stats::Stats>,
+	/// how long the session is kept once the input is finished
+	linger: time::Duration,
+	/// ffmpeg is started at this wall-clock time
+	start: Option<time::SystemTime>,
+}
+
+impl Broadcast {
+	/// until the publisher or ffmpeg is done for good, restarts are handled inside
+	///
+	/// True once ffmpeg finished the input, the tracks are then ended by [Self::finish] instead of [Self::close].
+	async fn run(&mut self) -> Result<bool, Error> {
+		let start = self.start.take();
+		let supervise = supervise(&mut self.ffmpeg, self.changes.as_mut(), &self.reloader, &self.template);
+		let supervise = async {
+			if let Some(at) = start {
+				crate::schedule::start(at).await;
+			}
+			supervise.await
+		};
 
 		tokio::select! {
-			res = session.run() => println!("Session: {:#?}", res),
-			res = run(&self.output, writer, self.settings) => println!("run: {:#?}", res),
-			res = publisher.announce(reader) => println!("Publisher: {:#?}", res),
-			res = close() => println!("close: {:#?}", res),
-			res = read_output(output) => println!("output: {:#?}", res),
+			res = self.publisher.run().instrument(tracing::info_span!("publisher")) => match res {
+				Err(e) if matches!(e.root(), Error::Transport(moq_transport::serve::ServeError::Closed(_))) => {
+					tracing::warn!(error = %e, "relay closed the tracks");
+					Ok(false)
+				}
+				res => {
+					tracing::info!(?res, "publisher ended");
+					res.map(|_| false)
+				}
+			},
+			res = supervise.instrument(tracing::info_span!("ffmpeg")) => {
+				tracing::info!(?res, "ffmpeg ended");
+				res.map(|_| true)
+			}
+		}
+	}
+
+	/// publish the last segments ffmpeg wrote, then end the tracks with a catalog not listing them anymore
+	async fn finish(mut self) -> Result<(), Error> {
+		// the events of the last writes may still be queued
+		if let Ok(Err(e)) = tokio::time::timeout(DRAIN, self.publisher.run()).await {
+			tracing::warn!(error = %e, "publisher failed while draining");
 		}
 
-		log::info!("termination initiated, cleaning up");
+		let finished = self.publisher.finish().await;
+
+		if let Some(archive) = &self.archive {
+			archive.flush().await;
+		}
+		if let Some(stats) = &self.stats {
+			stats.flush().await;
+		}
+
+		helper::clear_output(&self.output)?;
+
+		finished
+	}
+
+	/// stop ffmpeg and end the tracks, the relay should still be connected to receive their end
+	async fn close(mut self) -> Result<(), Error> {
+		self.ffmpeg.kill().await?;
 
-		if let Err(e) = ffmpeg.kill() {
-			println!("Error: {}", e);
-			return Err(Error::Crate("process".to_string(), e.to_string()));
+		self.publisher.close().await;
+
+		if let Some(archive) = &self.archive {
+			archive.flush().await;
+		}
+		if let Some(stats) = &self.stats {
+			stats.flush().await;
 		}
 
 		helper::clear_output(&self.output)?;
@@ -95,74 +634,481 @@ impl Dash {
 	}
 }
 
-pub async fn create(
-	info: PubInfo,
-) -> Result<
-	(
-		moq_transport::session::Session,
-		moq_transport::session::Publisher,
-		moq_transport::serve::TracksWriter,
-		moq_transport::serve::TracksReader,
-	),
-	Error,
-> {
-	let (writer, _, reader) = moq_transport::serve::Tracks::new(info.namespace.clone()).produce();
-
-	let tls = match info.tls.load() {
-		Ok(t) => t,
-		Err(e) => {
-			println!("Error: {}", e);
-			return Err(Error::Crate("tls".to_string(), e.to_string()));
-		}
-	};
+/// Publishes the DASH segments written to a directory as MoQ tracks.
+///
+/// The publisher does not spawn an encoder, anything producing
+/// `*_rep_<id>.m4s` files into the output directory can be used.
+pub struct DashPublisher {
+	output: path::PathBuf,
+	watcher: watcher::MoqWatcher,
+}
 
-	let quic = match moq_native::quic::Endpoint::new(moq_native::quic::Config {
-		bind: info.bind,
-		tls: tls.clone(),
-	}) {
-		Ok(q) => q,
-		Err(e) => {
-			println!("Error: {}", e);
-			return Err(Error::Crate("moq_native".to_string(), e.to_string()));
-		}
-	};
+impl DashPublisher {
+	pub fn builder() -> DashPublisherBuilder {
+		DashPublisherBuilder::default()
+	}
 
-	log::info!("connecting to relay: url={}", info.url);
-	let session = match quic.client.connect(&info.url).await {
-		Ok(s) => s,
-		Err(e) => {
-			println!("Error: {}", e);
-			return Err(Error::Crate("moq_native".to_string(), e.to_string()));
+	/// watch the output directory and publish every segment, runs until the watcher fails
+	pub async fn run(&mut self) -> Result<(), Error> {
+		self.watcher.run(&self.output).await?;
+
+		Ok(())
+	}
+
+	/// hands changed settings to [Self::run]
+	pub fn reloader(&self) -> Reloader {
+		self.watcher.reloader()
+	}
+
+	/// the encoded catalog of the newest version, None until the first init segment was published
+	pub fn catalog_snapshot(&self) -> Option<bytes::Bytes> {
+		self.watcher.catalog_snapshot()
+	}
+
+	/// end every published track, including the catalog
+	pub async fn close(self) {
+		self.watcher.close().await;
+	}
+
+	/// publish what is left of the segments, then end every track with a last catalog not listing them
+	///
+	/// Call once the encoder finished, the catalog tells the subscribers that nothing follows.
+	pub async fn finish(self) -> Result<(), Error> {
+		self.watcher.finish().await
+	}
+}
+
+#[derive(Default)]
+pub struct DashPublisherBuilder {
+	output: Option<path::PathBuf>,
+	settings: Option<Settings<path::PathBuf>>,
+	namespace: Option<String>,
+	poll_interval: Option<time::Duration>,
+	debounce: Option<time::Duration>,
+	quiescence: Option<time::Duration>,
+	max_read: Option<usize>,
+	metrics: crate::metrics::Metrics,
+	packaging: moq_catalog::Packaging,
+	group_order: GroupOrder,
+	object_mode: ObjectMode,
+	publish_mpd: bool,
+	emsg_track: bool,
+	metadata: Option<path::PathBuf>,
+	timeline_track: Option<time::Duration>,
+	init_tracks: bool,
+	audio_group_duration: Option<time::Duration>,
+	group_duration: Option<time::Duration>,
+	discontinuity_threshold: Option<time::Duration>,
+	max_group_age: Option<time::Duration>,
+	catalog_initial_timeout: Option<time::Duration>,
+	republish_interval: Option<time::Duration>,
+	catalog_track: Option<String>,
+	hierarchical_catalog: bool,
+	catalog_format: moq_catalog::CatalogFormat,
+	track_name_template: Option<TrackNameTemplate>,
+	strict_alignment: bool,
+	strict_traks: bool,
+	lazy_tracks: bool,
+	max_object_size: Option<usize>,
+	max_atom_size: Option<usize>,
+	priorities: Option<(u8, u8)>,
+	archive: Option<crate::archive::Archive>,
+	stats: Option<crate::stats::Stats>,
+}
+
+impl DashPublisherBuilder {
+	/// directory the DASH segments are written to
+	pub fn output<P>(mut self, output: P) -> Self
+	where
+		P: AsRef<path::Path>,
+	{
+		self.output = Some(output.as_ref().to_path_buf());
+		self
+	}
+
+	/// settings describing the representations, rep IDs are mapped to the settings in order (audio first)
+	pub fn settings(mut self, settings: Settings<path::PathBuf>) -> Self {
+		self.settings = Some(settings);
+		self
+	}
+
+	/// namespace of the broadcast
+	pub fn namespace(mut self, namespace: &str) -> Self {
+		self.namespace = Some(namespace.to_string());
+		self
+	}
+
+	/// poll the output directory every `interval` instead of using inotify
+	pub fn poll_interval(mut self, interval: time::Duration) -> Self {
+		self.poll_interval = Some(interval);
+		self
+	}
+
+	/// coalesce the data events of a segment within `debounce` into a single read, 10ms by default
+	///
+	/// Zero reads the segment on every event.
+	pub fn debounce(mut self, debounce: time::Duration) -> Self {
+		self.debounce = Some(debounce);
+		self
+	}
+
+	/// a segment without data events for `quiescence` is complete, for platforms not reporting closes
+	///
+	/// Zero waits for the close or the rename of the segment.
+	/// Defaults to [QUIESCENCE] on macOS and Windows, and to zero on Linux, where inotify reports the closes.
+	pub fn quiescence(mut self, quiescence: time::Duration) -> Self {
+		self.quiescence = Some(quiescence);
+		self
+	}
+
+	/// read at most `max_read` bytes of a segment at once, 8 MiB by default
+	pub fn max_read(mut self, max_read: usize) -> Self {
+		self.max_read = Some(max_read);
+		self
+	}
+
+	/// record the published tracks in `metrics`
+	pub fn metrics(mut self, metrics: crate::metrics::Metrics) -> Self {
+		self.metrics = metrics;
+		self
+	}
+
+	/// write CMAF fragments (default) or a [LocFrame] per sample
+	pub fn packaging(mut self, packaging: moq_catalog::Packaging) -> Self {
+		self.packaging = packaging;
+		self
+	}
+
+	/// how the groups of the media tracks are prioritized, [GroupOrder::NewestFirst] by default
+	pub fn group_order(mut self, group_order: GroupOrder) -> Self {
+		self.group_order = group_order;
+		self
+	}
+
+	/// whether atoms, chunks or segments are written as objects, [ObjectMode::PerAtom] by default
+	pub fn object_mode(mut self, object_mode: ObjectMode) -> Self {
+		self.object_mode = object_mode;
+		self
+	}
+
+	/// publish every version of the manifest as a single-object group of the `.mpd` track, skipped by default
+	pub fn publish_mpd(mut self, publish_mpd: bool) -> Self {
+		self.publish_mpd = publish_mpd;
+		self
+	}
+
+	/// publish every event message of the segments as a single-object group of the `.emsg` track
+	///
+	/// By default they are sent in-band, in the object of the next moof.
+	pub fn emsg_track(mut self, emsg_track: bool) -> Self {
+		self.emsg_track = emsg_track;
+		self
+	}
+
+	/// publish every marker of the JSONL file `path` as a single-object group of the `.metadata` track
+	///
+	/// A marker is published once the media time of the video reaches its `at_ms`, appended lines are picked up.
+	pub fn metadata(mut self, path: path::PathBuf) -> Self {
+		self.metadata = Some(path);
+		self
+	}
+
+	/// publish a [TimelineRecord] of every prft of the segments as an object of the `.timeline` track
+	///
+	/// A new group starts every `group_duration` of wall clock, so late joiners only get the recent records.
+	pub fn timeline_track(mut self, group_duration: time::Duration) -> Self {
+		self.timeline_track = Some(group_duration);
+		self
+	}
+
+	/// send the init segment of every rep on its own track, referenced by initTrack in the catalog
+	///
+	/// By default the init segments are inlined as initData.
+	pub fn init_tracks(mut self, init_tracks: bool) -> Self {
+		self.init_tracks = init_tracks;
+		self
+	}
+
+	/// end the groups of the audio tracks every `duration` of media, as there are no keyframes to split on
+	///
+	/// Audio-only broadcasts default to the target segment duration, otherwise an audio track is a single group.
+	pub fn audio_group_duration(mut self, duration: time::Duration) -> Self {
+		self.audio_group_duration = Some(duration);
+		self
+	}
+
+	/// end the groups once they cover `duration` of media, independent of the ffmpeg segment duration
+	///
+	/// Video groups still start at keyframes, so they cover whole GOPs. The audio groups follow,
+	/// unless [Self::audio_group_duration] is set. By default every keyframe starts a group.
+	pub fn group_duration(mut self, duration: time::Duration) -> Self {
+		self.group_duration = Some(duration);
+		self
+	}
+
+	/// end the current group once the timestamps jump ahead by more than `threshold`, ex. at dropped frames
+	///
+	/// The next fragment starts a new group, even without a keyframe. Defaults to [DISCONTINUITY_THRESHOLD].
+	pub fn discontinuity_threshold(mut self, threshold: time::Duration) -> Self {
+		self.discontinuity_threshold = Some(threshold);
+		self
+	}
+
+	/// abandon a group once a write to it blocks past `age` of wall clock, ex. when the relay falls behind
+	///
+	/// The fragments until the next keyframe are skipped, which bounds the latency behind the live edge.
+	/// By default a write waits as long as it takes.
+	pub fn max_group_age(mut self, age: time::Duration) -> Self {
+		self.max_group_age = Some(age);
+		self
+	}
+
+	/// hold the first catalog back until every rep of the settings set up its track, at most `timeout`
+	///
+	/// Subscribers get a single catalog of the full ladder, later reps update it.
+	/// Defaults to [CATALOG_INITIAL_TIMEOUT], zero publishes a catalog with every new track.
+	pub fn catalog_initial_timeout(mut self, timeout: time::Duration) -> Self {
+		self.catalog_initial_timeout = Some(timeout);
+		self
+	}
+
+	/// write the newest catalog, and the init segments with [Self::init_tracks], again every `interval`
+	///
+	/// A subscriber joining through a relay that keeps only the newest groups finds them within an interval.
+	/// The re-published groups are byte-identical, a [moq_catalog::CatalogWatcher] reports no change.
+	/// By default nothing is re-published.
+	pub fn republish_interval(mut self, interval: time::Duration) -> Self {
+		self.republish_interval = Some(interval);
+		self
+	}
+
+	/// publish the catalog on the track `name`, `.catalog` by default
+	pub fn catalog_track(mut self, name: &str) -> Self {
+		self.catalog_track = Some(name.to_string());
+		self
+	}
+
+	/// publish a catalog per media type, listed by the root catalog on the catalog track
+	pub fn hierarchical_catalog(mut self, hierarchical: bool) -> Self {
+		self.hierarchical_catalog = hierarchical;
+		self
+	}
+
+	/// publish the catalogs as pretty or compact JSON or as CBOR, compact JSON by default
+	pub fn catalog_format(mut self, format: moq_catalog::CatalogFormat) -> Self {
+		self.catalog_format = format;
+		self
+	}
+
+	/// name the tracks after a [TrackNameTemplate] of the rep settings instead of the rep names
+	pub fn track_name_template(mut self, template: TrackNameTemplate) -> Self {
+		self.track_name_template = Some(template);
+		self
+	}
+
+	/// fail once a video rep starts a group at a timestamp no other video rep starts one at
+	///
+	/// ABR switching needs the groups of all video reps to start at the same media timestamps,
+	/// misaligned groups are logged and counted in the metrics either way.
+	pub fn strict_alignment(mut self, strict: bool) -> Self {
+		self.strict_alignment = strict;
+		self
+	}
+
+	/// fail on a fragment of a trak the init segment does not describe, ex. after the encoder switched inputs,
+	/// instead of skipping it with a warning
+	pub fn strict_traks(mut self, strict: bool) -> Self {
+		self.strict_traks = strict;
+		self
+	}
+
+	/// skip the groups of a media track while nobody is subscribed to it, written regardless by default
+	///
+	/// The fragments are still parsed, a new subscriber gets the next group, starting at a keyframe.
+	pub fn lazy_tracks(mut self, lazy: bool) -> Self {
+		self.lazy_tracks = lazy;
+		self
+	}
+
+	/// split the objects larger than `size` bytes into several objects of the same group
+	///
+	/// The atoms stay whole where possible, [MAX_OBJECT_SIZE] by default.
+	/// Init segments whose initData would exceed [INIT_DATA_LIMIT] are sent on their init track regardless.
+	pub fn max_object_size(mut self, size: usize) -> Self {
+		self.max_object_size = Some(size);
+		self
+	}
+
+	/// take atoms declaring more than `size` bytes for corrupt data
+	///
+	/// The corrupt data is skipped up to the next fragment, [MAX_ATOM_SIZE] by default.
+	pub fn max_atom_size(mut self, size: usize) -> Self {
+		self.max_atom_size = Some(size);
+		self
+	}
+
+	/// the base priorities of the audio and the video tracks, lower is sent first
+	///
+	/// They take precedence over the group order, by default [AUDIO_PRIORITY] puts the audio before the video.
+	pub fn priorities(mut self, audio: u8, video: u8) -> Self {
+		self.priorities = Some((audio, video));
+		self
+	}
+
+	/// write every published object to `archive` as well, including the catalog and the manifest
+	pub fn archive(mut self, archive: crate::archive::Archive) -> Self {
+		self.archive = Some(archive);
+		self
+	}
+
+	/// record every published group and object to `stats`, including the catalog
+	pub fn stats(mut self, stats: crate::stats::Stats) -> Self {
+		self.stats = Some(stats);
+		self
+	}
+
+	/// create the publisher and the [moq_transport::serve::TracksReader] to announce
+	///
+	/// The `.catalog` track is created immediately, the media tracks once their init segment is written.
+	pub fn build(self) -> Result<(DashPublisher, moq_transport::serve::TracksReader), Error> {
+		let (Some(output), Some(settings), Some(namespace)) = (self.output, self.settings, self.namespace) else {
+			tracing::error!("output, settings and namespace are required");
+			return Err(Error::Missing);
+		};
+
+		let (broadcast, _, reader) = moq_transport::serve::Tracks::new(namespace).produce();
+
+		let mut watcher = watcher::MoqWatcher::new(
+			broadcast,
+			settings,
+			self.poll_interval,
+			self.metrics,
+			self.packaging,
+			self.group_order,
+			self.object_mode,
+		)?;
+		if let Some(archive) = self.archive {
+			watcher.set_archive(archive);
+		}
+		if let Some(stats) = self.stats {
+			watcher.set_stats(stats);
+		}
+		if self.publish_mpd {
+			watcher.publish_mpd()?;
+		}
+		if self.emsg_track {
+			watcher.publish_emsg_track()?;
+		}
+		watcher.set_init_tracks(self.init_tracks);
+		watcher.set_strict_alignment(self.strict_alignment);
+		watcher.set_strict_traks(self.strict_traks);
+		watcher.set_lazy_tracks(self.lazy_tracks);
+		if let Some(size) = self.max_object_size {
+			watcher.set_max_object_size(size);
+		}
+		if let Some(size) = self.max_atom_size {
+			watcher.set_max_atom_size(size);
+		}
+		if let Some((audio, video)) = self.priorities {
+			watcher.set_priorities(audio, video);
+		}
+		if let Some(debounce) = self.debounce {
+			watcher.set_debounce(debounce);
+		}
+		if let Some(quiescence) = self.quiescence {
+			watcher.set_quiescence((!quiescence.is_zero()).then_some(quiescence));
+		}
+		if let Some(max_read) = self.max_read {
+			watcher.set_max_read(max_read);
+		}
+		if let Some(duration) = self.audio_group_duration {
+			watcher.set_audio_group_duration(duration);
+		}
+		if let Some(duration) = self.group_duration {
+			watcher.set_group_duration(duration);
+		}
+		if let Some(threshold) = self.discontinuity_threshold {
+			watcher.set_discontinuity_threshold(threshold);
+		}
+		if let Some(age) = self.max_group_age {
+			watcher.set_max_group_age(age);
+		}
+		if let Some(timeout) = self.catalog_initial_timeout {
+			watcher.set_catalog_initial_timeout(timeout);
+		}
+		if let Some(interval) = self.republish_interval {
+			watcher.set_republish_interval(interval);
+		}
+		if let Some(name) = &self.catalog_track {
+			watcher.set_catalog_track(name)?;
+		}
+		watcher.set_hierarchical_catalog(self.hierarchical_catalog);
+		watcher.set_catalog_format(self.catalog_format);
+		if let Some(template) = self.track_name_template {
+			watcher.set_track_name_template(template)?;
+		}
+		if let Some(path) = self.metadata {
+			watcher.publish_metadata(path, self.poll_interval)?;
+		}
+		if let Some(duration) = self.timeline_track {
+			watcher.publish_timeline_track(duration)?;
 		}
-	};
 
-	let (session, publisher) = match moq_transport::session::Publisher::connect(session).await {
-		Ok(v) => v,
+		Ok((DashPublisher { output, watcher }, reader))
+	}
+}
+
+/// connect to the relay and setup a MoQ publisher session
+pub async fn connect(
+	options: &ConnectOptions,
+) -> Result<(moq_transport::session::Session, moq_transport::session::Publisher), Error> {
+	let connected = crate::connect_publisher(&options.url, options.bind, &options.tls, options.transport.clone()).await;
+	match connected {
+		Ok(v) => Ok(v),
 		Err(e) => {
-			println!("Error: {}", e);
-			return Err(Error::Crate("moq_transport".to_string(), e.to_string()));
+			tracing::error!(error = %e);
+			match e.downcast::<moq_transport::session::SessionError>() {
+				Ok(e) => Err(Error::Session(e)),
+				Err(e) => Err(Error::Connect(e)),
+			}
 		}
-	};
-
-	Ok((session, publisher, writer, reader))
+	}
 }
 
-pub async fn run<P>(
-	target: P,
-	writer: moq_transport::serve::TracksWriter,
-	settings: Settings<std::path::PathBuf>,
-) -> Result<(), Error>
-where
-	P: AsRef<std::path::Path>,
-{
-	let mut watcher = watcher::MoqWatcher::new(writer, settings)?;
+/// run ffmpeg, restarting it with the changed settings the publisher continues with
+async fn supervise(
+	ffmpeg: &mut Supervisor,
+	mut changes: Option<&mut SettingsWatcher>,
+	reloader: &Reloader,
+	template: &TrackNameTemplate,
+) -> Result<(), Error> {
+	loop {
+		let settings = match changes.as_deref_mut() {
+			Some(changes) => tokio::select! {
+				res = ffmpeg.run() => return res,
+				res = changes.next() => res?,
+			},
+			None => return ffmpeg.run().await,
+		};
 
-	watcher.run(target).await?;
+		if let Err(e) = settings.track_names(template) {
+			tracing::warn!(error = %e, "ignoring the changed settings");
+			continue;
+		}
 
-	Ok(())
+		tracing::info!("restarting ffmpeg with the changed settings");
+		ffmpeg.kill().await?;
+		reloader.reload(settings.clone()).await?;
+		ffmpeg.set_settings(settings);
+	}
 }
 
+/// resolves on SIGHUP, SIGTERM, SIGINT or SIGQUIT
+#[cfg(unix)]
 async fn close() -> anyhow::Result<()> {
+	use futures::StreamExt;
+	use signal_hook::consts::signal::{SIGHUP, SIGINT, SIGQUIT, SIGTERM};
+
 	let mut signals = signal_hook_tokio::Signals::new([SIGHUP, SIGTERM, SIGINT, SIGQUIT])?;
 	let handle = signals.handle();
 
@@ -178,31 +1124,17 @@ async fn close() -> anyhow::Result<()> {
 	Ok(())
 }
 
-async fn read_output(mut stderr: std::process::ChildStderr) -> anyhow::Result<()> {
-	let re = regex::Regex::new(r"speed=(?<speed>(?:0|1)\.\d{3}x)")?;
-	let pb = indicatif::ProgressBar::new_spinner();
-	pb.enable_steady_tick(std::time::Duration::from_millis(100));
-	pb.set_style(
-		indicatif::ProgressStyle::with_template("{spinner} {msg}")?
-			.tick_strings(&["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"]),
-	);
-
-	loop {
-		let mut buf = [0; 1024];
-		let read = stderr.read(&mut buf)?;
-
-		let text = match String::from_utf8(buf[..read].to_vec()) {
-			Ok(v) => v,
-			Err(_) => continue,
-		};
-
-		let matches = match re.captures(&text) {
-			Some(v) => v,
-			None => continue,
-		};
-
-		pb.set_message(format!("Speed: {}", &matches["speed"]));
+/// resolves on Ctrl+C, Ctrl+Break or the console window being closed
+#[cfg(windows)]
+async fn close() -> anyhow::Result<()> {
+	let mut ctrl_break = tokio::signal::windows::ctrl_break()?;
+	let mut ctrl_close = tokio::signal::windows::ctrl_close()?;
 
-		tokio::time::sleep(tokio::time::Duration::from_millis(1_000)).await;
+	tokio::select! {
+		res = tokio::signal::ctrl_c() => res?,
+		_ = ctrl_break.recv() => {},
+		_ = ctrl_close.recv() => {},
 	}
+
+	Ok(())
 }
@@ -1,563 +1,5087 @@
 use bytes::Buf;
 use mp4::ReadBox;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc, oneshot, watch};
 
-use crate::dash::settings::Setting;
+use crate::archive::{Archive, ArchivingGroupsWriter};
+use crate::atom::Rope;
+use crate::dash::settings::{Setting, TrackNameTemplate};
+use crate::mode::{Backpressure, ModeGroupWriter, ModeWriter, StreamMode};
+use crate::stats::Stats;
+pub use crate::CATALOG_TRACK;
 
-use super::Error;
+use super::{alignment::Alignment, loc, metadata, Error};
 
 const LABEL: &str = "Dash MoQ";
 
+/// track of the DASH manifest, if published
+const MPD_TRACK: &str = ".mpd";
+
+/// track of the event messages, if published
+const EMSG_TRACK: &str = ".emsg";
+
+/// track of the wall clock to media time mapping of the prft atoms, if published
+pub const TIMELINE_TRACK: &str = ".timeline";
+
+/// the mime type of the [TimelineRecord]s on the [TIMELINE_TRACK]
+pub const TIMELINE_MIME_TYPE: &str = "application/json";
+
+/// the timeline track starts a new group this often, by default
+pub const TIMELINE_GROUP_DURATION: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// appended to the track name of a rep to name the track of its init segment, if published
+pub const INIT_TRACK_SUFFIX: &str = "_init";
+
+/// larger objects are split into several objects of the same group, by default
+pub const MAX_OBJECT_SIZE: usize = 1 << 20;
+
+/// atoms declaring a larger size are taken for corrupt data, by default
+pub const MAX_ATOM_SIZE: usize = 64 << 20;
+
+/// the atoms a rep resynchronizes on after corrupt data, each starts a fragment or an init segment
+const RESYNC_ATOMS: [[u8; 4]; 4] = [*b"styp", *b"moof", *b"prft", *b"ftyp"];
+
+/// a moof with a trun of more samples is taken for corrupt data, ex. minutes of audio frames
+const MAX_FRAGMENT_SAMPLES: usize = 1 << 16;
+
+/// init segments whose base64 initData would be longer are published on their init track instead
+pub const INIT_DATA_LIMIT: usize = 64 << 10;
+
+/// event messages remembered to skip the copies the other reps carry
+const EMSG_SEEN: usize = 64;
+
+/// audio and video are rendered together
+const RENDER_GROUP: usize = 1;
+/// the video representations are alternatives of each other, as are the audio ones
+const VIDEO_ALT_GROUP: usize = 1;
+const AUDIO_ALT_GROUP: usize = 2;
+
 pub type RepID = usize;
 
-// TODO see catalog print, something is off with 4k
+/// the bits of a group priority below the base priority of its track
+///
+/// The transport sets the priority as that of a QUIC stream, an i32 sending the higher ones first: the base priority and
+/// the order fit the 31 bits of a positive i32.
+const BASE_PRIORITY_SHIFT: u32 = 23;
+/// largest part of a group priority set by the [GroupOrder]
+const MAX_ORDER_PRIORITY: u64 = (1 << BASE_PRIORITY_SHIFT) - 1;
 
-pub struct Publisher {
-	buf: HashMap<RepID, bytes::BytesMut>,
+/// base priority of the audio tracks by default, lower is sent first
+pub const AUDIO_PRIORITY: u8 = 0;
+/// base priority of the video tracks by default
+pub const VIDEO_PRIORITY: u8 = 1;
 
-	settings: super::Settings<std::path::PathBuf>,
-	tracks: HashMap<RepID, Track>,
-	broadcast: moq_transport::serve::TracksWriter,
+/// how long the first catalog waits for the tracks of all reps, by default
+pub const CATALOG_INITIAL_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
 
-	catalog_broadcast: moq_transport::serve::GroupsWriter,
-	catalog: moq_catalog::MoqCatalog,
+/// gaps in the timestamps larger than this start a new group, by default
+pub const DISCONTINUITY_THRESHOLD: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Which groups of a track the relay should send first, higher priorities are sent first.
+///
+/// The groups of a track with a lower base priority come first, ex. the audio before the video.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GroupOrder {
+	/// the latest media first, derived from the group timestamp
+	#[default]
+	NewestFirst,
+	/// the earliest media first, derived from the group timestamp
+	OldestFirst,
+	/// groups in the order they were created, independent of the timestamps
+	Sequential,
+}
+
+impl GroupOrder {
+	/// priority of the `sequence`th group of a track with the priority `base`, starting at `timestamp`
+	///
+	/// The order within the track wraps, every 2.3 hours of media or 8M groups, the groups in flight at once stay ordered
+	/// but for the moment of the wrap.
+	fn priority(&self, timestamp: std::time::Duration, sequence: u64, base: u8) -> u64 {
+		let millis = (timestamp.as_millis() & MAX_ORDER_PRIORITY as u128) as u64;
+		let order = match self {
+			Self::NewestFirst => millis,
+			Self::OldestFirst => MAX_ORDER_PRIORITY - millis,
+			Self::Sequential => MAX_ORDER_PRIORITY - (sequence & MAX_ORDER_PRIORITY),
+		};
+		(((u8::MAX - base) as u64) << BASE_PRIORITY_SHIFT) | order
+	}
+}
+
+impl std::str::FromStr for GroupOrder {
+	type Err = Error;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"newest-first" => Ok(Self::NewestFirst),
+			"oldest-first" => Ok(Self::OldestFirst),
+			"sequential" => Ok(Self::Sequential),
+			_ => {
+				tracing::error!(group_order = s, "unknown group order");
+				Err(Error::Unknown("group order", s.to_string()))
+			}
+		}
+	}
+}
+
+/// How the atoms of the media tracks are cut into objects, only [moq_catalog::Packaging::CMAF] is affected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ObjectMode {
+	/// every prft+moof and every mdat is its own object
+	#[default]
+	PerAtom,
+	/// a whole CMAF chunk, prft+moof+mdat, in a single object
+	PerChunk,
+	/// all chunks of a segment file in a single object, written once the file is complete
+	PerSegment,
+}
+
+impl std::str::FromStr for ObjectMode {
+	type Err = Error;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"per-atom" => Ok(Self::PerAtom),
+			"per-chunk" => Ok(Self::PerChunk),
+			"per-segment" => Ok(Self::PerSegment),
+			_ => {
+				tracing::error!(object_mode = s, "unknown object mode");
+				Err(Error::Unknown("object mode", s.to_string()))
+			}
+		}
+	}
+}
 
-	ftyp: HashMap<RepID, bytes::Bytes>,
-	moov: HashMap<RepID, mp4::MoovBox>,
+/// Routes the chunks of every rep to its own [Representation] task.
+///
+/// The reps are parsed in parallel, a slow rep never delays the others,
+/// while the chunks of a single rep keep their order.
+pub struct Publisher {
+	settings: super::Settings<std::path::PathBuf>,
+	broadcast: Arc<Mutex<Broadcast>>,
+	metrics: crate::metrics::Metrics,
+	packaging: moq_catalog::Packaging,
+	group_order: GroupOrder,
+	object_mode: ObjectMode,
+	/// set explicitly, otherwise the group duration or for audio-only broadcasts the segment duration
+	audio_group: Option<std::time::Duration>,
+	group_duration: Option<std::time::Duration>,
+	discontinuity: std::time::Duration,
+	max_group_age: Option<std::time::Duration>,
+	backpressure: Option<Arc<dyn Backpressure>>,
+	track_names: TrackNameTemplate,
+	alignment: Alignment,
+	archive: Option<Archive>,
+	stats: Option<Stats>,
+	lazy_tracks: bool,
+	strict_traks: bool,
+	init_tracks: bool,
+	max_object_size: usize,
+	max_atom_size: usize,
+	/// the base priorities of the audio and the video tracks
+	priorities: (u8, u8),
+	catalog_timeout: std::time::Duration,
+	republish_interval: Option<std::time::Duration>,
 
-	prft: HashMap<RepID, bytes::Bytes>,
+	reps: HashMap<RepID, mpsc::UnboundedSender<Message>>,
+	tasks: Vec<tokio::task::JoinHandle<()>>,
+	/// publishes the first catalog once the timeout elapsed, started with the first rep
+	startup: Option<tokio::task::JoinHandle<()>>,
+	/// re-publishes the catalog and the init segments every interval, started with the first rep
+	republish: Option<tokio::task::JoinHandle<()>>,
+	/// the media time of the newest video fragment, of any fragment if there is no video
+	timeline: Arc<watch::Sender<std::time::Duration>>,
+	/// publishes the markers of the metadata file as the timeline passes them
+	metadata: Option<tokio::task::JoinHandle<()>>,
+
+	errors: mpsc::UnboundedReceiver<Error>,
+	errors_tx: mpsc::UnboundedSender<Error>,
 }
 
 impl Publisher {
 	pub fn new(
 		mut broadcast: moq_transport::serve::TracksWriter,
 		settings: super::Settings<std::path::PathBuf>,
+		metrics: crate::metrics::Metrics,
+		packaging: moq_catalog::Packaging,
+		group_order: GroupOrder,
+		object_mode: ObjectMode,
 	) -> Result<Self, Error> {
-		let Some(catalog_broadcast) = broadcast.create(".catalog") else {
-			println!("Error: failed to create catalog track");
-			return Err(Error::Crate(
-				"moq_transport".to_string(),
-				"broadcast closed".to_string(),
-			));
+		let Some(catalog_broadcast) = broadcast.create(CATALOG_TRACK) else {
+			tracing::error!("failed to create the catalog track");
+			return Err(Error::Transport(moq_transport::serve::ServeError::Cancel));
 		};
 		let catalog_broadcast = match catalog_broadcast.groups() {
-			Ok(c) => c,
+			Ok(c) => ArchivingGroupsWriter::new(c, None),
 			Err(e) => {
-				println!("Error: {}", e);
-				return Err(Error::Crate("moq_transport".to_string(), e.to_string()));
+				tracing::error!(error = %e);
+				return Err(Error::Transport(e));
 			}
 		};
-		let mut catalog = moq_catalog::MoqCatalog::new();
+		let csf = moq_catalog::CommonStructFields::new("", packaging)
+			.with_render_group(RENDER_GROUP)
+			.with_label(LABEL)
+			.with_namespace(&broadcast.namespace);
+		let catalog = moq_catalog::MoqCatalog::new()
+			.with_delta_updates()
+			.with_common_track_fields(csf);
 
-		let mut csf = moq_catalog::CommonStructFields::new("", moq_catalog::Packaging::CMAF);
-		csf.set_alt_group(1)
-			.set_label(LABEL)
-			.set_namespace(&broadcast.namespace);
+		let (errors_tx, errors) = mpsc::unbounded_channel();
 
-		catalog.enable_delta_updates().set_common_track_fields(csf);
+		// keyframes of aligned reps are at most a frame apart
+		let alignment = Alignment::new(std::time::Duration::from_secs_f64(1.0 / settings.fps as f64));
+		let startup = (settings.rep_len() > 0).then(|| settings.rep_len());
 
 		Ok(Self {
-			buf: HashMap::new(),
 			settings,
-			tracks: HashMap::new(),
-			broadcast,
-			catalog_broadcast,
-			catalog,
-			ftyp: HashMap::new(),
-			moov: HashMap::new(),
-			prft: HashMap::new(),
+			broadcast: Arc::new(Mutex::new(Broadcast {
+				tracks: broadcast,
+				catalog_name: CATALOG_TRACK.to_string(),
+				catalog_broadcast,
+				catalog,
+				children: None,
+				format: Default::default(),
+				catalog_version: 0,
+				catalog_group: 0,
+				snapshot: None,
+				startup,
+				manifest: None,
+				emsg: None,
+				metadata: None,
+				timeline_track: None,
+				inits: HashMap::new(),
+				stats: None,
+			})),
+			metrics,
+			packaging,
+			group_order,
+			object_mode,
+			audio_group: None,
+			group_duration: None,
+			discontinuity: DISCONTINUITY_THRESHOLD,
+			max_group_age: None,
+			backpressure: None,
+			track_names: Default::default(),
+			alignment,
+			archive: None,
+			stats: None,
+			lazy_tracks: false,
+			strict_traks: false,
+			init_tracks: false,
+			max_object_size: MAX_OBJECT_SIZE,
+			max_atom_size: MAX_ATOM_SIZE,
+			priorities: (AUDIO_PRIORITY, VIDEO_PRIORITY),
+			catalog_timeout: CATALOG_INITIAL_TIMEOUT,
+			republish_interval: None,
+			reps: HashMap::new(),
+			tasks: Vec::new(),
+			startup: None,
+			republish: None,
+			timeline: Arc::new(watch::channel(std::time::Duration::ZERO).0),
+			metadata: None,
+			errors,
+			errors_tx,
 		})
 	}
 
-	pub fn publish(&mut self, rep_id: RepID, data: &[u8]) -> Result<(), Error> {
-		let buf = self.get_mut(rep_id);
-		buf.extend_from_slice(data);
-
-		self.parse(rep_id)?;
+	/// queue `data` for the task of `rep_id`, fails if the task already failed
+	///
+	/// The atoms within `data` are published without copying them.
+	pub fn publish(&mut self, rep_id: RepID, data: bytes::Bytes) -> Result<(), Error> {
+		if self.rep(rep_id).send(Message::Data(data)).is_err() {
+			return Err(self.ended(rep_id));
+		}
 
 		Ok(())
 	}
 
-	fn parse(&mut self, rep_id: RepID) -> Result<(), Error> {
-		while self.parse_atom(rep_id)? {}
+	/// drop the unparsed data of a rep, ex. a fragment left incomplete by a crashed encoder
+	pub fn reset(&mut self, rep_id: RepID) {
+		let _ = self.rep(rep_id).send(Message::Reset);
+	}
+
+	/// the segment file of `rep_id` is complete, flushes the segment with [ObjectMode::PerSegment]
+	pub fn end_segment(&mut self, rep_id: RepID) -> Result<(), Error> {
+		if self.rep(rep_id).send(Message::EndSegment).is_err() {
+			return Err(self.ended(rep_id));
+		}
+
 		Ok(())
 	}
 
-	fn parse_atom(&mut self, rep_id: RepID) -> Result<bool, Error> {
-		let buf = self.get_mut(rep_id);
-		let Some(atom) = next_atom(buf)? else {
-			return Ok(false);
-		};
+	/// end the groups of the audio tracks every `duration` of media, as there are no keyframes to split on
+	///
+	/// Audio-only broadcasts default to the target segment duration, otherwise an audio track is a single group.
+	pub fn set_audio_group_duration(&mut self, duration: std::time::Duration) {
+		self.audio_group = Some(duration);
+	}
 
-		let mut reader = std::io::Cursor::new(&atom);
-		let header = match mp4::BoxHeader::read(&mut reader) {
-			Ok(h) => h,
-			Err(e) => {
-				println!("Error: {}", e);
-				return Err(Error::Crate("mp4".to_string(), e.to_string()));
-			}
-		};
+	/// end the groups once they cover `duration` of media, at the next keyframe for video
+	///
+	/// A group may span several segments, by default every keyframe starts a new group.
+	/// The audio groups follow as well, unless their duration is set on its own.
+	pub fn set_group_duration(&mut self, duration: std::time::Duration) {
+		self.group_duration = Some(duration);
+	}
 
-		match header.name {
-			n if n.to_string() == "prft" => {
-				self.prft.insert(rep_id, atom);
-			}
-			mp4::BoxType::FtypBox => {
-				if self.ftyp.get(&rep_id).is_some() {
-					println!("Error: multiple ftyp on track {rep_id}");
-					return Err(Error::Crate("mp4".to_string(), "multiple ftyp on track".to_string()));
-				}
+	/// start a new group once the timestamps jump ahead of the previous fragment by more than `threshold`
+	///
+	/// Defaults to [DISCONTINUITY_THRESHOLD], the new group may start without a keyframe.
+	pub fn set_discontinuity_threshold(&mut self, threshold: std::time::Duration) {
+		self.discontinuity = threshold;
+	}
 
-				self.ftyp.insert(rep_id, atom);
-			}
-			mp4::BoxType::MoovBox => {
-				if self.moov.get(&rep_id).is_some() {
-					println!("Error: multiple moov on track {rep_id}");
-					return Err(Error::Crate("mp4".to_string(), "multiple moov on track".to_string()));
-				}
+	/// abandon a group once a write to it is still blocked `age` of wall clock after it started
+	///
+	/// The fragments until the next keyframe are skipped, which bounds the latency behind the live edge
+	/// when the writes fall behind, at the cost of a gap. By default a write waits as long as it takes.
+	pub fn set_max_group_age(&mut self, age: std::time::Duration) {
+		self.max_group_age = Some(age);
+	}
 
-				let moov = match mp4::MoovBox::read_box(&mut reader, header.size) {
-					Ok(m) => m,
-					Err(e) => {
-						println!("Error: {}", e);
-						return Err(Error::Crate("mp4".to_string(), e.to_string()));
-					}
-				};
+	/// wait for `backpressure` before every object of the media tracks, bounded by the max group age
+	pub fn set_backpressure(&mut self, backpressure: Arc<dyn Backpressure>) {
+		self.backpressure = Some(backpressure);
+	}
 
-				self.setup(&moov, atom, rep_id)?;
-				self.moov.insert(rep_id, moov);
-			}
-			mp4::BoxType::MoofBox => {
-				let moof = match mp4::MoofBox::read_box(&mut reader, header.size) {
-					Ok(m) => m,
-					Err(e) => {
-						println!("Error: {}", e);
-						return Err(Error::Crate("mp4".to_string(), e.to_string()));
-					}
-				};
+	/// wait for the tracks of all reps of the settings before the first catalog, at most `timeout` after the first chunk
+	///
+	/// Defaults to [CATALOG_INITIAL_TIMEOUT], a zero timeout publishes the catalog with every new track.
+	pub fn set_catalog_initial_timeout(&mut self, timeout: std::time::Duration) {
+		self.catalog_timeout = timeout;
+		if timeout.is_zero() {
+			self.broadcast.lock().unwrap_or_else(|e| e.into_inner()).startup = None;
+		}
+	}
 
-				let fragment = Fragment::new(moof)?;
+	/// write the newest catalog and init segments again every `interval`, unchanged as the newest group of their track
+	///
+	/// A subscriber joining through a relay that keeps only the newest groups finds them within an interval.
+	/// Nothing is re-published by default.
+	pub fn set_republish_interval(&mut self, interval: std::time::Duration) {
+		self.republish_interval = Some(interval);
+	}
 
-				let Some(track) = self.tracks.get_mut(&rep_id) else {
-					println!("Error: track {rep_id} not available");
-					return Err(Error::Missing);
-				};
+	/// how much media an audio group covers, None for a single group
+	fn audio_group(&self) -> Option<std::time::Duration> {
+		// without video, the audio groups are cut like the segments
+		let segment = self
+			.settings
+			.video
+			.is_empty()
+			.then(|| std::time::Duration::from_secs_f64(self.settings.effective_segment_duration()));
 
-				if fragment.keyframe && track.handler == mp4::TrackType::Video {
-					track.end_group();
-				}
+		self.audio_group.or(self.group_duration).or(segment)
+	}
 
-				if let Err(e) = track.header(atom, fragment) {
-					println!("Error: {}", e);
-					return Err(Error::Crate("moq".to_string(), e.to_string()));
-				}
-			}
-			mp4::BoxType::MdatBox => {
-				let Some(track) = self.tracks.get_mut(&rep_id) else {
-					println!("Error: track {rep_id} not available");
-					return Err(Error::Missing);
-				};
+	/// derive the track names from the rep settings, the rep name by default
+	pub fn set_track_name_template(&mut self, template: TrackNameTemplate) -> Result<(), Error> {
+		self.settings.track_names(&template)?;
+		self.track_names = template;
+		Ok(())
+	}
 
-				if let Some(prft) = self.prft.get(&rep_id) {
-					let mut data = atom.clone().to_vec();
-					data.extend_from_slice(prft);
-					if let Err(e) = track.data(data.into()) {
-						println!("Error: {}", e);
-						return Err(Error::Crate("moq".to_string(), e.to_string()));
-					}
-				} else if let Err(e) = track.data(atom) {
-					println!("Error: {}", e);
-					return Err(Error::Crate("moq".to_string(), e.to_string()));
-				}
-			}
-			x => {
-				// println!("Other: {x}");
-			}
-		}
+	/// fail on a video group starting at a timestamp no other video rep starts one at, instead of warning
+	pub fn set_strict_alignment(&mut self, strict: bool) {
+		self.alignment.set_strict(strict);
+	}
 
-		Ok(true)
+	/// skip the groups of media tracks nobody is subscribed to, a subscriber gets the next group on
+	pub fn set_lazy_tracks(&mut self, lazy: bool) {
+		self.lazy_tracks = lazy;
 	}
 
-	fn setup(&mut self, moov: &mp4::MoovBox, raw: bytes::Bytes, rep_id: RepID) -> Result<(), Error> {
-		if moov.traks.len() != 1 {
-			println!("Error: multiple tracks in moov");
-			return Err(Error::Crate("mp4".to_string(), "multiple tracks in moov".to_string()));
+	/// fail on a fragment of a trak the moov does not describe, ex. after the encoder switched inputs, instead of
+	/// skipping it with a warning
+	pub fn set_strict_traks(&mut self, strict: bool) {
+		self.strict_traks = strict;
+	}
+
+	/// send the init segment of every rep as a single-object group of its own track, referenced by initTrack
+	///
+	/// The track is named after the media track with [INIT_TRACK_SUFFIX], a new init segment appends a group.
+	/// Otherwise the init segments are inlined as initData, making every catalog version several KB larger.
+	pub fn set_init_tracks(&mut self, init_tracks: bool) {
+		self.init_tracks = init_tracks;
+	}
+
+	/// split the objects larger than `size` bytes into several objects of the same group, [MAX_OBJECT_SIZE] by default
+	///
+	/// The pieces keep the atoms whole where possible, larger atoms are cut.
+	/// Applies to CMAF objects and init tracks, a LOC frame is always a single object.
+	pub fn set_max_object_size(&mut self, size: usize) {
+		self.max_object_size = size;
+	}
+
+	/// take atoms declaring more than `size` bytes for corrupt data, [MAX_ATOM_SIZE] by default
+	///
+	/// The rep skips corrupt data up to the next styp, moof, prft or ftyp, ending its current group.
+	pub fn set_max_atom_size(&mut self, size: usize) {
+		self.max_atom_size = size;
+	}
+
+	/// the base priorities of the audio and the video tracks, [AUDIO_PRIORITY] and [VIDEO_PRIORITY] by default
+	///
+	/// They take precedence over the [GroupOrder]: all groups of a track with a lower base priority are sent first.
+	/// Each is advertised as the `priority` of the selection params of the track.
+	pub fn set_priorities(&mut self, audio: u8, video: u8) {
+		self.priorities = (audio, video);
+	}
+
+	/// publish the catalog on the track `name` instead of [CATALOG_TRACK], before the first rep is set up
+	pub fn set_catalog_track(&mut self, name: &str) -> Result<(), Error> {
+		let mut broadcast = self.broadcast.lock().unwrap_or_else(|e| e.into_inner());
+		if broadcast.catalog_name == name {
+			return Ok(());
 		}
 
-		let Some(settings) = self.settings.get_rep(rep_id) else {
-			println!("Error: missing Settings for rep {}", rep_id);
-			return Err(Error::Missing);
+		let Some(track) = broadcast.tracks.create(name) else {
+			tracing::error!(track_name = name, "failed to create the catalog track");
+			return Err(Error::Transport(moq_transport::serve::ServeError::Cancel));
 		};
-		let track_name = match settings {
-			Setting::Audio(ref a) => a.name.clone(),
-			Setting::Video(ref v) => v.name.clone(),
-		};
-
-		let trak = &moov.traks[0];
-		let id = trak.tkhd.track_id;
-		let timescale = track_timescale(moov, id);
-		let handler = match (&trak.mdia.hdlr.handler_type).try_into() {
-			Ok(h) => h,
-			Err(_) => {
-				println!("Error: cannot convert handler type");
-				return Err(Error::Crate(
-					"mp4".to_string(),
-					"cannot convert handler type".to_string(),
-				));
-			}
-		};
-		let Some(track) = self.broadcast.create(&track_name) else {
-			println!("Error: failed to create catalog track");
-			return Err(Error::Crate(
-				"moq_transport".to_string(),
-				"broadcast closed".to_string(),
-			));
+		let track = match track.groups() {
+			Ok(t) => ArchivingGroupsWriter::new(t, self.archive.clone()),
+			Err(e) => {
+				tracing::error!(error = %e);
+				return Err(Error::Transport(e));
+			}
 		};
-		let track = Track::new(track, handler, timescale);
-		self.tracks.insert(rep_id, track);
 
-		let Some(init) = self.ftyp.get(&rep_id) else {
-			println!("Error: missing ftyp for track {rep_id}");
-			return Err(Error::Crate("mp4".to_string(), "missing ftyp for track".to_string()));
-		};
-		let mut init = init.to_vec();
-		init.extend_from_slice(&raw);
+		let previous = std::mem::replace(&mut broadcast.catalog_name, name.to_string());
+		broadcast.tracks.remove(&previous);
+		broadcast.catalog_broadcast = track;
 
-		let mut catalog_track = moq_catalog::Track::new(&track_name, moq_catalog::Packaging::CMAF);
-		let mut params = moq_catalog::SelectionParams::new();
+		Ok(())
+	}
 
-		let stsd = &trak.mdia.minf.stbl.stsd;
-		if let Some(avc1) = &stsd.avc1 {
-			let profile = avc1.avcc.avc_profile_indication;
-			let constraints = avc1.avcc.profile_compatibility; // Not 100% certain here, but it's 0x00 on my current test video
-			let level = avc1.avcc.avc_level_indication;
+	/// split the catalog into one per media type, ex. `.catalog.video`, each published on a track of that name
+	///
+	/// The catalog track then holds the root catalog, listing the catalogs of the media types instead of tracks.
+	/// The tracks neither audio nor video, ex. the metadata, are listed by `.catalog.data`.
+	pub fn set_hierarchical_catalog(&mut self, hierarchical: bool) {
+		let mut broadcast = self.broadcast.lock().unwrap_or_else(|e| e.into_inner());
+		broadcast.children = hierarchical.then(BTreeMap::new);
+	}
 
-			let width = avc1.width;
-			let height = avc1.height;
+	/// publish the catalogs in the serialization `format`, compact JSON by default
+	pub fn set_catalog_format(&mut self, format: moq_catalog::CatalogFormat) {
+		let mut broadcast = self.broadcast.lock().unwrap_or_else(|e| e.into_inner());
+		broadcast.format = format;
+	}
 
-			let codec = rfc6381_codec::Codec::avc1(profile, constraints, level);
-			let codec_str = codec.to_string();
+	/// write every object of the tracks, including the catalog and the manifest, to `archive` as well
+	pub fn set_archive(&mut self, archive: Archive) {
+		let mut broadcast = self.broadcast.lock().unwrap_or_else(|e| e.into_inner());
+		broadcast.catalog_broadcast.set_archive(Some(archive.clone()));
+		for child in broadcast.children.iter_mut().flat_map(BTreeMap::values_mut) {
+			child.track.set_archive(Some(archive.clone()));
+		}
+		if let Some(manifest) = broadcast.manifest.as_mut() {
+			manifest.track.set_archive(Some(archive.clone()));
+		}
+		if let Some(emsg) = broadcast.emsg.as_mut() {
+			emsg.track.set_archive(Some(archive.clone()));
+		}
+		if let Some(metadata) = broadcast.metadata.as_mut() {
+			metadata.set_archive(Some(archive.clone()));
+		}
+		if let Some(timeline) = broadcast.timeline_track.as_mut() {
+			timeline.track.set_archive(Some(archive.clone()));
+		}
+		drop(broadcast);
 
-			let bitrate = match settings {
-				Setting::Video(v) => v.bitrate,
-				_ => 0,
-			};
-			// let bitrate = if let Setting::Video(s) = settings { s.bitrate } else { 0 };
-
-			params
-				.set_height(height)
-				.set_width(width)
-				.set_codec(&codec_str)
-				.set_bitrate(bitrate);
-
-			if let Err(e) = params.set_mime_type("video/mp4") {
-				println!("Error: {}", e);
-				return Err(Error::Crate("moq_catalog".to_string(), e.to_string()));
-			}
-		} else if let Some(_hev1) = &stsd.hev1 {
-			return Err(Error::Crate("pub".to_string(), "HEVC not yet supported".to_string()));
-		} else if let Some(mp4a) = &stsd.mp4a {
-			let desc = if let Some(d) = &mp4a.esds.as_ref() {
-				&d.es_desc.dec_config
-			} else {
-				println!("Error: missing mp4a description");
-				return Err(Error::Missing);
-			};
+		self.archive = Some(archive);
+	}
 
-			let codec_str = format!("mp4a.{:02x}.{}", desc.object_type_indication, desc.dec_specific.profile);
+	/// record every group and object of the tracks, including the catalog, to `stats`
+	pub fn set_stats(&mut self, stats: Stats) {
+		let mut broadcast = self.broadcast.lock().unwrap_or_else(|e| e.into_inner());
+		broadcast.stats = Some(stats.clone());
+		drop(broadcast);
 
-			params.set_codec(&codec_str).set_sample_rate(mp4a.samplerate.value());
+		self.stats = Some(stats);
+	}
 
-			if let Err(e) = params.set_mime_type("audio/mp4") {
-				println!("Error: {}", e);
-				return Err(Error::Crate("moq_catalog".to_string(), e.to_string()));
-			}
+	/// create the [MPD_TRACK] track, every manifest passed to [Self::publish_manifest] is a group of it
+	pub fn enable_manifest(&mut self) -> Result<(), Error> {
+		let mut broadcast = self.broadcast.lock().unwrap_or_else(|e| e.into_inner());
 
-			let bitrate = core::cmp::max(desc.max_bitrate, desc.avg_bitrate);
-			if bitrate > 0 {
-				params.set_bitrate(bitrate as u64);
+		let Some(track) = broadcast.tracks.create(MPD_TRACK) else {
+			tracing::error!("failed to create the manifest track");
+			return Err(Error::Transport(moq_transport::serve::ServeError::Cancel));
+		};
+		let track = match track.groups() {
+			Ok(t) => ArchivingGroupsWriter::new(t, self.archive.clone()),
+			Err(e) => {
+				tracing::error!(error = %e);
+				return Err(Error::Transport(e));
 			}
-		} else if let Some(_vp09) = &stsd.vp09 {
-			return Err(Error::Crate("pub".to_string(), "VP9 not yet supported".to_string()));
-		} else {
-			return Err(Error::Crate("pub".to_string(), "unknown codec".to_string()));
-		}
+		};
 
-		catalog_track
-			.set_selection_params(params)
-			.set_init_data(&init)
-			.set_label(&track_name);
+		broadcast.manifest = Some(Manifest { track, last: None });
 
-		if let Err(e) = self.catalog.insert_track(catalog_track) {
-			println!("Error: {}", e);
-			return Err(Error::Crate("moq_catalog".to_string(), e.to_string()));
-		}
+		Ok(())
+	}
 
-		log::info!("published catalog");
-		println!("{}", self.catalog);
+	/// create the [EMSG_TRACK] track, every event message of the segments is a single-object group of it
+	///
+	/// Otherwise the event messages are sent in-band, in front of the next moof.
+	pub fn enable_emsg_track(&mut self) -> Result<(), Error> {
+		let mut broadcast = self.broadcast.lock().unwrap_or_else(|e| e.into_inner());
 
-		let buf = match self.catalog.encode() {
-			Ok(b) => b,
+		let Some(track) = broadcast.tracks.create(EMSG_TRACK) else {
+			tracing::error!("failed to create the emsg track");
+			return Err(Error::Transport(moq_transport::serve::ServeError::Cancel));
+		};
+		let track = match track.groups() {
+			Ok(t) => ArchivingGroupsWriter::new(t, self.archive.clone()),
 			Err(e) => {
-				println!("Error: {}", e);
-				return Err(Error::Crate("moq_catalog".to_string(), e.to_string()));
+				tracing::error!(error = %e);
+				return Err(Error::Transport(e));
 			}
 		};
 
-		// Create a single fragment for the segment.
-		match self.catalog_broadcast.append(0) {
-			Ok(mut g) => {
-				if let Err(e) = g.write(buf.into()) {
-					println!("Error: {}", e);
-					return Err(Error::Crate("moq".to_string(), e.to_string()));
-				}
+		broadcast.emsg = Some(EmsgTrack {
+			track,
+			seen: Default::default(),
+		});
+
+		Ok(())
+	}
+
+	/// create the [TIMELINE_TRACK] track, a [TimelineRecord] of every prft of the reps is an object of it
+	///
+	/// A new group is started once the wall clock passed `group_duration` since the first record of the current one.
+	pub fn enable_timeline_track(&mut self, group_duration: std::time::Duration) -> Result<(), Error> {
+		let params = match moq_catalog::SelectionParams::new().with_mime_type(TIMELINE_MIME_TYPE) {
+			Ok(params) => params,
+			Err(e) => {
+				tracing::error!(error = %e);
+				return Err(Error::Catalog(e));
 			}
+		};
+		let catalog_track = moq_catalog::Track::new(TIMELINE_TRACK, moq_catalog::Packaging::LOC)
+			.with_label("timeline")
+			.with_selection_params(params);
+
+		let mut broadcast = self.broadcast.lock().unwrap_or_else(|e| e.into_inner());
+		let Some(track) = broadcast.tracks.create(TIMELINE_TRACK) else {
+			tracing::error!("failed to create the timeline track");
+			return Err(Error::Transport(moq_transport::serve::ServeError::Cancel));
+		};
+		let track = match track.groups() {
+			Ok(t) => ArchivingGroupsWriter::new(t, self.archive.clone()),
 			Err(e) => {
-				println!("Error: {}", e);
-				return Err(Error::Crate("moq".to_string(), e.to_string()));
+				tracing::error!(error = %e);
+				return Err(Error::Transport(e));
 			}
+		};
+		broadcast.timeline_track = Some(TimelineTrack {
+			track,
+			group: None,
+			group_duration,
+		});
+		if let Err(e) = broadcast.catalog.insert_track(catalog_track) {
+			tracing::error!(error = %e);
+			return Err(Error::Catalog(e));
 		}
+		broadcast.publish_catalog()?;
 
 		Ok(())
 	}
 
-	fn get_mut(&mut self, key: RepID) -> &mut bytes::BytesMut {
-		// if key is not present, insert new entry
-		self.buf.entry(key).or_default();
-
-		// return mutable reference
-		self.buf.get_mut(&key).unwrap()
-	}
-}
+	/// create the [metadata::METADATA_TRACK] track, every marker of the JSONL file `path` is a group of it
+	///
+	/// The markers are published once the media passes them, the file is watched for appended ones.
+	/// Must be called within a tokio runtime.
+	pub fn enable_metadata(
+		&mut self,
+		path: std::path::PathBuf,
+		poll_interval: Option<std::time::Duration>,
+	) -> Result<(), Error> {
+		let file = metadata::MetadataFile::new(path, poll_interval)?;
 
-fn next_atom<B: bytes::Buf>(buf: &mut B) -> Result<Option<bytes::Bytes>, Error> {
-	let mut peek = std::io::Cursor::new(buf.chunk());
+		let params = match moq_catalog::SelectionParams::new().with_mime_type(metadata::METADATA_MIME_TYPE) {
+			Ok(params) => params,
+			Err(e) => {
+				tracing::error!(error = %e);
+				return Err(Error::Catalog(e));
+			}
+		};
+		let catalog_track = moq_catalog::Track::new(metadata::METADATA_TRACK, moq_catalog::Packaging::LOC)
+			.with_label("metadata")
+			.with_selection_params(params);
 
-	if peek.remaining() < 8 {
-		if buf.remaining() != buf.chunk().len() {
-			// TODO figure out a way to peek at the first 8 bytes
-			println!("TODO: vectored Buf not yet supported");
-			return Err(Error::Other);
+		let mut broadcast = self.broadcast.lock().unwrap_or_else(|e| e.into_inner());
+		let Some(track) = broadcast.tracks.create(metadata::METADATA_TRACK) else {
+			tracing::error!("failed to create the metadata track");
+			return Err(Error::Transport(moq_transport::serve::ServeError::Cancel));
+		};
+		let track = match track.groups() {
+			Ok(t) => ArchivingGroupsWriter::new(t, self.archive.clone()),
+			Err(e) => {
+				tracing::error!(error = %e);
+				return Err(Error::Transport(e));
+			}
+		};
+		broadcast.metadata = Some(track);
+		if let Err(e) = broadcast.catalog.insert_track(catalog_track) {
+			tracing::error!(error = %e);
+			return Err(Error::Catalog(e));
 		}
+		broadcast.publish_catalog()?;
+		drop(broadcast);
+
+		let weak = Arc::downgrade(&self.broadcast);
+		let publish = move |marker: metadata::Marker| match weak.upgrade() {
+			Some(broadcast) => broadcast
+				.lock()
+				.unwrap_or_else(|e| e.into_inner())
+				.publish_metadata(&marker),
+			None => Ok(()),
+		};
+		let timeline = self.timeline.subscribe();
+		let errors = self.errors_tx.clone();
+		self.metadata = Some(tokio::spawn(async move {
+			if let Err(e) = metadata::run(file, timeline, publish).await {
+				let _ = errors.send(e.context("publishing the metadata"));
+			}
+		}));
 
-		return Ok(None);
+		Ok(())
 	}
 
-	// Convert the first 4 bytes into the size.
-	let size = peek.get_u32();
-	let _type = peek.get_u32();
+	/// write `manifest` as a new single-object group, unless it is unchanged
+	pub fn publish_manifest(&mut self, manifest: bytes::Bytes) -> Result<(), Error> {
+		let mut broadcast = self.broadcast.lock().unwrap_or_else(|e| e.into_inner());
 
-	let size = match size {
-		// Runs until the end of the file.
-		0 => {
-			println!("TODO: unsupported EOF atom");
-			return Err(Error::Other);
+		let Some(current) = broadcast.manifest.as_mut() else {
+			tracing::error!("manifest track not enabled");
+			return Err(Error::Missing);
+		};
+		if current.last.as_ref() == Some(&manifest) {
+			return Ok(());
 		}
 
-		// The next 8 bytes are the extended size to be used instead.
-		1 => {
-			let size_ext = peek.get_u64();
-
-			if size_ext < 16 {
-				println!("impossible extended box size: {}", size_ext);
-				return Err(Error::Other);
+		match current.track.append(0) {
+			Ok(mut group) => {
+				if let Err(e) = group.write(manifest.clone()) {
+					tracing::error!(error = %e);
+					return Err(Error::Transport(e));
+				}
+			}
+			Err(e) => {
+				tracing::error!(error = %e);
+				return Err(Error::Transport(e));
 			}
-			size_ext as usize
-		}
-
-		2..=7 => {
-			println!("impossible box size: {}", size);
-			return Err(Error::Other);
 		}
+		current.last = Some(manifest);
 
-		size => size as usize,
-	};
-
-	if buf.remaining() < size {
-		return Ok(None);
+		Ok(())
 	}
 
-	let atom = buf.copy_to_bytes(size);
-
-	Ok(Some(atom))
-}
+	/// the encoded catalog of the newest version, None until the first track was set up
+	pub fn catalog_snapshot(&self) -> Option<bytes::Bytes> {
+		self.broadcast
+			.lock()
+			.unwrap_or_else(|e| e.into_inner())
+			.snapshot
+			.clone()
+	}
 
-struct Track {
-	// The track we're producing
-	track: moq_transport::serve::GroupsWriter,
+	/// continue with changed `settings`, the reps are matched to the running ones by their track name
+	///
+	/// Running reps keep their track and continue as the rep ID of the new settings, those with a changed setting
+	/// start a new group. The tracks of removed reps are ended and dropped from the catalog,
+	/// added reps are set up once their init segment arrives. Fails on duplicate track names without changing anything.
+	pub async fn reload(&mut self, settings: super::Settings<std::path::PathBuf>) -> Result<(), Error> {
+		let names = settings.track_names(&self.track_names)?;
+		let current = self.settings.track_names(&self.track_names)?;
 
-	// The current segment
-	current: Option<moq_transport::serve::GroupWriter>,
+		// the chunks queued so far belong to the previous settings
+		self.flush().await?;
 
-	// The number of units per second.
-	timescale: u64,
+		self.alignment
+			.restart(std::time::Duration::from_secs_f64(1.0 / settings.fps as f64));
 
-	// The type of track, ex. "vide" or "soun"
-	handler: mp4::TrackType,
-}
+		let mut reps = HashMap::new();
+		for (rep_id, name) in names.iter().enumerate() {
+			let Some(rep) = current
+				.iter()
+				.position(|current| current == name)
+				.and_then(|previous| self.reps.remove(&previous))
+			else {
+				continue;
+			};
 
-impl Track {
-	fn new(track: moq_transport::serve::TrackWriter, handler: mp4::TrackType, timescale: u64) -> Self {
-		Self {
-			track: track.groups().unwrap(),
-			current: None,
-			timescale,
-			handler,
+			let message = Message::Reload(rep_id, Box::new(settings.clone()), self.alignment.clone());
+			if rep.send(message).is_err() {
+				return Err(self.ended(rep_id));
+			}
+			reps.insert(rep_id, rep);
 		}
-	}
 
-	pub fn header(&mut self, raw: bytes::Bytes, fragment: Fragment) -> Result<(), Error> {
-		if let Some(current) = self.current.as_mut() {
-			// Use the existing segment
-			if let Err(e) = current.write(raw) {
-				println!("Error: {}", e);
-				return Err(Error::Crate("moq".to_string(), e.to_string()));
-			}
-			return Ok(());
+		let removed: Vec<_> = current.into_iter().filter(|name| !names.contains(name)).collect();
+		if !removed.is_empty() {
+			tracing::info!(tracks = %removed.join(", "), "removing the tracks");
+			self.broadcast
+				.lock()
+				.unwrap_or_else(|e| e.into_inner())
+				.remove(&removed)?;
 		}
 
-		// Otherwise make a new segment
+		// the tasks of the removed reps end their tracks once their channel is gone
+		self.reps = reps;
+		self.settings = settings;
 
-		// Compute the timestamp in milliseconds.
-		// Overflows after 583 million years, so we're fine.
-		let timestamp: u32 = match fragment.timestamp(self.timescale).as_millis().try_into() {
-			Ok(t) => t,
-			Err(e) => {
-				println!("Error: {}", e);
-				return Err(Error::Crate("moq".to_string(), e.to_string()));
-			}
-		};
+		Ok(())
+	}
 
-		let Some(priority) = u32::MAX.checked_sub(timestamp) else {
-			println!("Error: priority too large");
-			return Err(Error::Crate("moq".to_string(), "priority too large".to_string()));
-		};
+	/// wait until every rep processed the chunks queued so far
+	pub async fn flush(&mut self) -> Result<(), Error> {
+		let mut pending = Vec::new();
+		for (rep_id, rep) in &self.reps {
+			let (tx, rx) = oneshot::channel();
+			let _ = rep.send(Message::Flush(tx));
+			pending.push((*rep_id, rx));
+		}
 
-		// Create a new segment.
-		let mut segment = match self.track.append(priority.into()) {
-			Ok(s) => s,
-			Err(e) => {
-				println!("Error: {}", e);
-				return Err(Error::Crate("moq".to_string(), e.to_string()));
+		for (rep_id, rx) in pending {
+			if rx.await.is_err() {
+				return Err(self.ended(rep_id));
 			}
-		};
-
-		// Write the fragment in it's own object.
-		if let Err(e) = segment.write(raw) {
-			println!("Error: {}", e);
-			return Err(Error::Crate("moq".to_string(), e.to_string()));
 		}
 
-		// Save for the next iteration
-		self.current = Some(segment);
-
 		Ok(())
 	}
 
-	pub fn data(&mut self, raw: bytes::Bytes) -> Result<(), Error> {
-		let Some(segment) = self.current.as_mut() else {
-			println!("Error: missing current fragment");
-			return Err(Error::Crate("moq".to_string(), "missing current fragment".to_string()));
-		};
-		if let Err(e) = segment.write(raw) {
-			println!("Error: {}", e);
-			return Err(Error::Crate("moq".to_string(), e.to_string()));
+	/// resolves with the error of the first rep task that fails
+	pub async fn failed(&mut self) -> Error {
+		match self.errors.recv().await {
+			Some(e) => e,
+			// a sender is kept in self
+			None => std::future::pending().await,
 		}
-
-		Ok(())
 	}
 
-	pub fn end_group(&mut self) {
-		self.current = None;
+	/// end every track and the catalog, subscribers see the tracks finish instead of the session being reset
+	///
+	/// The chunks already queued are published first.
+	pub async fn close(self) {
+		self.shutdown(false).await
 	}
-}
-
-struct Fragment {
-	// The track for this fragment.
-	track: u32,
 
-	// The timestamp of the first sample in this fragment, in timescale units.
-	timestamp: u64,
+	/// end every track like [Self::close], with a last catalog not listing the media tracks anymore
+	///
+	/// Used once the input is finished, the groups already published stay available to late subscribers.
+	pub async fn finish(self) {
+		self.shutdown(true).await
+	}
 
-	// True if this fragment is a keyframe.
-	keyframe: bool,
-}
+	async fn shutdown(self, finished: bool) {
+		if let Some(startup) = self.startup {
+			startup.abort();
+		}
+		if let Some(republish) = self.republish {
+			republish.abort();
+		}
+		if let Some(metadata) = self.metadata {
+			metadata.abort();
+		}
+		drop(self.reps);
+		for task in self.tasks {
+			if let Err(e) = task.await {
+				tracing::debug!(error = %e, "rep task failed");
+			}
+		}
 
-impl Fragment {
-	fn new(moof: mp4::MoofBox) -> Result<Self, Error> {
-		// We can't split the mdat atom, so this is impossible to support
-		if moof.trafs.len() != 1 {
-			println!("Error: multiple tracks per moof atom");
-			return Err(Error::Crate(
-				"mp4".to_string(),
-				"multiple tracks per moof atom".to_string(),
-			));
+		match Arc::try_unwrap(self.broadcast) {
+			Ok(broadcast) => {
+				let mut broadcast = broadcast.into_inner().unwrap_or_else(|e| e.into_inner());
+				if finished {
+					// already logged
+					let _ = broadcast.end();
+				}
+				broadcast.close()
+			}
+			Err(_) => tracing::debug!("catalog still in use"),
 		}
+	}
 
-		let track = moof.trafs[0].tfhd.track_id;
+	/// channel to the task of `rep_id`, spawned on first use
+	fn rep(&mut self, rep_id: RepID) -> &mpsc::UnboundedSender<Message> {
+		if self.startup.is_none() {
+			self.startup = Some(self.start_timeout());
+		}
+		if self.republish.is_none() {
+			self.republish = self.republish_interval.map(|interval| self.start_republish(interval));
+		}
 
-		// Parse the moof to get some timing information to sleep.
-		let timestamp = sample_timestamp(&moof).expect("couldn't find timestamp");
+		let audio_group = self.audio_group();
+		self.reps.entry(rep_id).or_insert_with(|| {
+			let (tx, rx) = mpsc::unbounded_channel();
+			let mut rep = Representation::new(
+				rep_id,
+				self.settings.clone(),
+				self.broadcast.clone(),
+				self.metrics.clone(),
+				self.packaging,
+				self.group_order,
+				self.object_mode,
+			);
+			rep.audio_group = audio_group;
+			rep.group_duration = self.group_duration;
+			rep.discontinuity = self.discontinuity;
+			rep.max_group_age = self.max_group_age;
+			rep.backpressure = self.backpressure.clone();
+			rep.track_names = self.track_names.clone();
+			rep.alignment = Some(self.alignment.clone());
+			rep.archive = self.archive.clone();
+			rep.stats = self.stats.clone();
+			rep.lazy = self.lazy_tracks;
+			rep.strict_traks = self.strict_traks;
+			rep.init_tracks = self.init_tracks;
+			rep.max_object_size = self.max_object_size;
+			rep.max_atom_size = self.max_atom_size;
+			rep.priority = match self.settings.get_rep(rep_id) {
+				Some(Setting::Audio(_)) => self.priorities.0,
+				_ => self.priorities.1,
+			};
+			// audio-only broadcasts are timed by the audio
+			let timed = match self.settings.get_rep(rep_id) {
+				Some(Setting::Video(_)) => true,
+				_ => self.settings.video.is_empty(),
+			};
+			rep.timeline = timed.then(|| self.timeline.clone());
+			self.tasks.push(tokio::spawn(rep.run(rx, self.errors_tx.clone())));
+			tx
+		})
+	}
 
-		// Detect if we should start a new segment.
-		let keyframe = sample_keyframe(&moof);
+	/// publish the initial catalog once [Self::set_catalog_initial_timeout] elapsed, unless all reps were set up
+	fn start_timeout(&self) -> tokio::task::JoinHandle<()> {
+		let broadcast = Arc::downgrade(&self.broadcast);
+		let timeout = self.catalog_timeout;
 
-		Ok(Self {
-			track,
-			timestamp,
-			keyframe,
+		tokio::spawn(async move {
+			tokio::time::sleep(timeout).await;
+			if let Some(broadcast) = broadcast.upgrade() {
+				// already logged
+				let _ = broadcast.lock().unwrap_or_else(|e| e.into_inner()).release();
+			}
 		})
 	}
 
-	// Convert from timescale units to a duration.
-	fn timestamp(&self, timescale: u64) -> std::time::Duration {
-		std::time::Duration::from_millis(1000 * self.timestamp / timescale)
+	/// re-publish the catalog and the init segments every `interval` until the broadcast is gone
+	fn start_republish(&self, interval: std::time::Duration) -> tokio::task::JoinHandle<()> {
+		let broadcast = Arc::downgrade(&self.broadcast);
+		let metrics = self.metrics.clone();
+
+		tokio::spawn(async move {
+			let mut ticks = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+			loop {
+				ticks.tick().await;
+				let Some(broadcast) = broadcast.upgrade() else {
+					return;
+				};
+				let republished = broadcast.lock().unwrap_or_else(|e| e.into_inner()).republish();
+				match republished {
+					Ok(names) => names.iter().for_each(|name| metrics.track(name).republished()),
+					// already logged
+					Err(_) => return,
+				}
+			}
+		})
+	}
+
+	/// the task of `rep_id` is gone, its error was reported on the error channel
+	fn ended(&mut self, rep_id: RepID) -> Error {
+		match self.errors.try_recv() {
+			Ok(e) => e,
+			Err(_) => {
+				tracing::error!(rep_id, "rep task ended");
+				Error::RepEnded(rep_id)
+			}
+		}
 	}
 }
 
-fn sample_timestamp(moof: &mp4::MoofBox) -> Option<u64> {
-	Some(moof.trafs.first()?.tfdt.as_ref()?.base_media_decode_time)
+enum Message {
+	Data(bytes::Bytes),
+	Reset,
+	EndSegment,
+	Flush(oneshot::Sender<()>),
+	/// continue as another rep ID of changed settings
+	Reload(RepID, Box<super::Settings<std::path::PathBuf>>, Alignment),
 }
 
-fn sample_keyframe(moof: &mp4::MoofBox) -> bool {
-	for traf in &moof.trafs {
-		// TODO trak default flags if this is None
-		let default_flags = traf.tfhd.default_sample_flags.unwrap_or_default();
-		let trun = match &traf.trun {
-			Some(t) => t,
-			None => return false,
+/// the broadcast and its catalog, shared by all reps
+struct Broadcast {
+	tracks: moq_transport::serve::TracksWriter,
+
+	/// the name of the catalog track
+	catalog_name: String,
+	catalog_broadcast: ArchivingGroupsWriter,
+	/// all tracks, split into the catalogs of the media types if hierarchical
+	catalog: moq_catalog::MoqCatalog,
+	/// the catalogs of the media types by their track name, None unless hierarchical
+	children: Option<BTreeMap<String, ChildCatalog>>,
+	/// the serialization of the published catalogs
+	format: moq_catalog::CatalogFormat,
+	/// number of catalog versions written
+	catalog_version: u64,
+	/// the group id and priority of the next catalog group, ahead of the version once re-published
+	catalog_group: u64,
+	/// encoded catalog of the latest version
+	snapshot: Option<bytes::Bytes>,
+	/// the reps not set up yet, no catalog is published until none is left or the initial timeout elapsed
+	startup: Option<usize>,
+
+	manifest: Option<Manifest>,
+	emsg: Option<EmsgTrack>,
+	metadata: Option<ArchivingGroupsWriter>,
+	timeline_track: Option<TimelineTrack>,
+	/// the init tracks by the name of the media track they initialize
+	inits: HashMap<String, InitTrack>,
+	/// records the catalog versions
+	stats: Option<Stats>,
+}
+
+/// the track of the catalog of a media type and the version written last
+struct ChildCatalog {
+	track: ArchivingGroupsWriter,
+	version: u64,
+	/// the group id of the next group, ahead of the version once re-published
+	group: u64,
+	last: Option<bytes::Bytes>,
+}
+
+/// the init track of a media track and the init segment written last
+struct InitTrack {
+	track: ArchivingGroupsWriter,
+	last: bytes::Bytes,
+	max_object_size: usize,
+}
+
+impl InitTrack {
+	/// write `init` as a new group, a single object unless it is larger than `max_object_size`
+	fn write(&mut self, init: bytes::Bytes) -> Result<(), Error> {
+		match self.track.append(0) {
+			Ok(mut group) => {
+				for object in crate::atom::split(init.clone(), self.max_object_size) {
+					if let Err(e) = group.write(object) {
+						tracing::error!(error = %e);
+						return Err(Error::Transport(e));
+					}
+				}
+			}
+			Err(e) => {
+				tracing::error!(error = %e);
+				return Err(Error::Transport(e));
+			}
+		}
+		self.last = init;
+
+		Ok(())
+	}
+}
+
+/// the track of the DASH manifest and the version written last
+struct Manifest {
+	track: ArchivingGroupsWriter,
+	last: Option<bytes::Bytes>,
+}
+
+/// the track of the event messages, and the scheme, value and id of the latest ones
+struct EmsgTrack {
+	track: ArchivingGroupsWriter,
+	seen: std::collections::VecDeque<(String, String, u32)>,
+}
+
+/// the [TIMELINE_TRACK] and its current group, with the wall clock of its first record
+struct TimelineTrack {
+	track: ArchivingGroupsWriter,
+	group: Option<(crate::archive::ArchivingGroupWriter, std::time::SystemTime)>,
+	group_duration: std::time::Duration,
+}
+
+/// An object of the [TIMELINE_TRACK], the sample at `media_time` of the rep was produced at `wallclock_rfc3339`.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct TimelineRecord {
+	pub wallclock_rfc3339: String,
+	/// in `timescale` units of the track of the rep
+	pub media_time: u64,
+	pub timescale: u64,
+	pub rep_id: RepID,
+}
+
+impl TimelineRecord {
+	/// the record of `prft` on the track of `rep_id`
+	fn new(prft: &crate::atom::Prft, timescale: u64, rep_id: RepID) -> Self {
+		let wallclock = chrono::DateTime::<chrono::Utc>::from(prft.wallclock);
+		Self {
+			wallclock_rfc3339: wallclock.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+			media_time: prft.media_time,
+			timescale,
+			rep_id,
+		}
+	}
+}
+
+impl Broadcast {
+	/// create the media track of a new rep and advertise it
+	fn insert(&mut self, catalog_track: moq_catalog::Track) -> Result<moq_transport::serve::TrackWriter, Error> {
+		let Some(track) = self.tracks.create(catalog_track.name()) else {
+			tracing::error!("failed to create the catalog track");
+			return Err(Error::Transport(moq_transport::serve::ServeError::Cancel));
 		};
 
-		for i in 0..trun.sample_count {
-			let mut flags = match trun.sample_flags.get(i as usize) {
-				Some(f) => *f,
-				None => default_flags,
+		if let Err(e) = self.catalog.insert_track(catalog_track) {
+			tracing::error!(error = %e);
+			return Err(Error::Catalog(e));
+		}
+
+		if let Some(waiting) = self.startup.as_mut() {
+			*waiting = waiting.saturating_sub(1);
+			if *waiting == 0 {
+				log::info!("all reps set up, publishing the initial catalog");
+				self.startup = None;
+			}
+		}
+		self.publish_catalog()?;
+
+		Ok(track)
+	}
+
+	/// a new writer of the known track `name`, replacing the one created by [Self::insert]
+	fn replace(&mut self, name: &str) -> Result<moq_transport::serve::TrackWriter, Error> {
+		let Some(track) = self.tracks.create(name) else {
+			tracing::error!(track_name = name, "failed to replace the track");
+			return Err(Error::Transport(moq_transport::serve::ServeError::Cancel));
+		};
+
+		Ok(track)
+	}
+
+	/// re-advertise a known track, only if its codec parameters changed
+	fn update(&mut self, catalog_track: moq_catalog::Track) -> Result<(), Error> {
+		let track_name = catalog_track.name().to_string();
+		let Some(current) = self.catalog.track_mut(&track_name) else {
+			tracing::error!(track_name, "track missing in the catalog");
+			return Err(Error::Missing);
+		};
+
+		if current.selection_params() == catalog_track.selection_params() {
+			log::info!("new init segment for {track_name} with unchanged codec parameters");
+			return Ok(());
+		}
+
+		log::info!("codec parameters of {track_name} changed, updating catalog");
+		*current = catalog_track;
+
+		self.publish_catalog()
+	}
+
+	/// write `init` as a new group of the init track of `name`, created on first use
+	///
+	/// The group is a single object, unless `init` is larger than `max_object_size`.
+	fn publish_init(
+		&mut self,
+		name: &str,
+		init: bytes::Bytes,
+		archive: Option<Archive>,
+		max_object_size: usize,
+	) -> Result<(), Error> {
+		let track = match self.inits.entry(name.to_string()) {
+			std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+			std::collections::hash_map::Entry::Vacant(entry) => {
+				let init_track = format!("{name}{INIT_TRACK_SUFFIX}");
+				let Some(track) = self.tracks.create(&init_track) else {
+					tracing::error!(init_track, "failed to create the init track");
+					return Err(Error::Transport(moq_transport::serve::ServeError::Cancel));
+				};
+				match track.groups() {
+					Ok(t) => entry.insert(InitTrack {
+						track: ArchivingGroupsWriter::new(t, archive),
+						last: bytes::Bytes::new(),
+						max_object_size,
+					}),
+					Err(e) => {
+						tracing::error!(error = %e);
+						return Err(Error::Transport(e));
+					}
+				}
+			}
+		};
+
+		track.max_object_size = max_object_size;
+		track.write(init)
+	}
+
+	/// drop the tracks named `names` from the broadcast and the catalog
+	fn remove(&mut self, names: &[String]) -> Result<(), Error> {
+		let mut changed = false;
+		for name in names {
+			self.tracks.remove(name);
+			if self.inits.remove(name).is_some() {
+				self.tracks.remove(&format!("{name}{INIT_TRACK_SUFFIX}"));
+			}
+			changed |= self.catalog.remove_track(name).is_some();
+		}
+
+		match changed {
+			true => self.publish_catalog(),
+			false => Ok(()),
+		}
+	}
+
+	/// advertise the end of the broadcast, a last catalog without any media track
+	///
+	/// The tracks are not removed, late subscribers still get their last group.
+	fn end(&mut self) -> Result<(), Error> {
+		let names: Vec<_> = self
+			.catalog
+			.tracks()
+			.iter()
+			.map(|track| track.name().to_string())
+			.collect();
+		for name in &names {
+			self.catalog.remove_track(name);
+		}
+
+		tracing::info!(
+			tracks = names.len(),
+			"input finished, publishing the final catalog without the tracks"
+		);
+		self.startup = None;
+		self.publish_catalog()
+	}
+
+	fn close(self) {
+		if let Err(e) = self.catalog_broadcast.close(moq_transport::serve::ServeError::Done) {
+			tracing::debug!(error = %e, "catalog already closed");
+		}
+		for (name, child) in self.children.into_iter().flatten() {
+			if let Err(e) = child.track.close(moq_transport::serve::ServeError::Done) {
+				tracing::debug!(catalog = name, error = %e, "catalog already closed");
+			}
+		}
+		if let Some(manifest) = self.manifest {
+			if let Err(e) = manifest.track.close(moq_transport::serve::ServeError::Done) {
+				tracing::debug!(error = %e, "manifest already closed");
+			}
+		}
+		if let Some(emsg) = self.emsg {
+			if let Err(e) = emsg.track.close(moq_transport::serve::ServeError::Done) {
+				tracing::debug!(error = %e, "emsg track already closed");
+			}
+		}
+		if let Some(metadata) = self.metadata {
+			if let Err(e) = metadata.close(moq_transport::serve::ServeError::Done) {
+				tracing::debug!(error = %e, "metadata track already closed");
+			}
+		}
+		if let Some(timeline) = self.timeline_track {
+			if let Err(e) = timeline.track.close(moq_transport::serve::ServeError::Done) {
+				tracing::debug!(error = %e, "timeline track already closed");
+			}
+		}
+		for (name, init) in self.inits {
+			if let Err(e) = init.track.close(moq_transport::serve::ServeError::Done) {
+				tracing::debug!(track = name, error = %e, "init track already closed");
+			}
+		}
+	}
+
+	/// write `atom` as a new single-object group of the emsg track, unless another rep already did
+	///
+	/// Events with the same scheme, value and id are equivalent, every rep carries a copy.
+	fn publish_emsg(&mut self, emsg: &crate::atom::Emsg, atom: bytes::Bytes) -> Result<(), Error> {
+		let Some(current) = self.emsg.as_mut() else {
+			tracing::error!("emsg track not enabled");
+			return Err(Error::Missing);
+		};
+
+		let key = (emsg.scheme_id_uri.clone(), emsg.value.clone(), emsg.id);
+		if current.seen.contains(&key) {
+			return Ok(());
+		}
+		if current.seen.len() == EMSG_SEEN {
+			current.seen.pop_front();
+		}
+		current.seen.push_back(key);
+
+		match current.track.append(0) {
+			Ok(mut group) => {
+				if let Err(e) = group.write(atom) {
+					tracing::error!(error = %e);
+					return Err(Error::Transport(e));
+				}
+			}
+			Err(e) => {
+				tracing::error!(error = %e);
+				return Err(Error::Transport(e));
+			}
+		}
+
+		Ok(())
+	}
+
+	/// write `record` as an object of the timeline track, in a new group once the current one is old enough
+	///
+	/// The records produced `wallclock` since the first one of the current group decide, a clock going back starts one too.
+	fn publish_timeline(&mut self, record: &TimelineRecord, wallclock: std::time::SystemTime) -> Result<(), Error> {
+		let Some(timeline) = self.timeline_track.as_mut() else {
+			tracing::error!("timeline track not enabled");
+			return Err(Error::Missing);
+		};
+
+		let payload = match serde_json::to_vec(record) {
+			Ok(p) => p,
+			Err(e) => {
+				tracing::error!(error = %e);
+				return Err(Error::Json(e));
+			}
+		};
+
+		let current = timeline
+			.group
+			.as_ref()
+			.and_then(|(_, started)| wallclock.duration_since(*started).ok());
+		if current.is_none_or(|age| age >= timeline.group_duration) {
+			let group = match timeline.track.append(0) {
+				Ok(group) => group,
+				Err(e) => {
+					tracing::error!(error = %e);
+					return Err(Error::Transport(e));
+				}
 			};
+			timeline.group = Some((group, wallclock));
+		}
+
+		let Some((group, _)) = timeline.group.as_mut() else {
+			return Err(Error::Missing);
+		};
+		if let Err(e) = group.write(payload.into()) {
+			tracing::error!(error = %e);
+			return Err(Error::Transport(e));
+		}
+
+		Ok(())
+	}
+
+	/// write the payload of `marker` as a new single-object group of the metadata track
+	fn publish_metadata(&mut self, marker: &metadata::Marker) -> Result<(), Error> {
+		let Some(track) = self.metadata.as_mut() else {
+			tracing::error!("metadata track not enabled");
+			return Err(Error::Missing);
+		};
+
+		let payload = match serde_json::to_vec(&marker.payload) {
+			Ok(p) => p,
+			Err(e) => {
+				tracing::error!(error = %e);
+				return Err(Error::Json(e));
+			}
+		};
 
-			if i == 0 && trun.first_sample_flags.is_some() {
-				flags = trun.first_sample_flags.unwrap();
+		match track.append(0) {
+			Ok(mut group) => {
+				if let Err(e) = group.write(payload.into()) {
+					tracing::error!(error = %e);
+					return Err(Error::Transport(e));
+				}
+			}
+			Err(e) => {
+				tracing::error!(error = %e);
+				return Err(Error::Transport(e));
 			}
+		}
 
-			// https://chromium.googlesource.com/chromium/src/media/+/master/formats/mp4/track_run_iterator.cc#177
-			let keyframe = (flags >> 24) & 0x3 == 0x2; // kSampleDependsOnNoOther
-			let non_sync = (flags >> 16) & 0x1 == 0x1; // kSampleIsNonSyncSample
+		Ok(())
+	}
 
-			if keyframe && !non_sync {
-				return true;
+	/// publish the catalog of the reps set up so far, once the initial timeout elapsed
+	fn release(&mut self) -> Result<(), Error> {
+		let Some(waiting) = self.startup.take() else {
+			return Ok(());
+		};
+
+		log::warn!("{waiting} reps not set up in time, publishing the initial catalog without them");
+		match self.catalog.tracks().is_empty() {
+			true => Ok(()),
+			false => self.publish_catalog(),
+		}
+	}
+
+	/// every version is a single-object group holding the full catalog, late subscribers only need the newest
+	///
+	/// Held back during the startup, the first version holds the tracks of all reps.
+	/// If hierarchical, the changed catalogs of the media types are published first and
+	/// the root catalog only once the media types change.
+	fn publish_catalog(&mut self) -> Result<(), Error> {
+		if self.startup.is_some() {
+			return Ok(());
+		}
+
+		let buf = match self.children.is_some() {
+			true => {
+				let root = self.publish_children()?;
+				let buf = encode_catalog(&root, self.format)?;
+				if self.snapshot.as_ref() == Some(&buf) {
+					return Ok(());
+				}
+				buf
 			}
+			false => encode_catalog(&self.catalog, self.format)?,
+		};
+
+		write_catalog(&mut self.catalog_broadcast, self.catalog_group, buf.clone())?;
+		if let Some(stats) = &self.stats {
+			let group = self.catalog_group;
+			stats.group(&self.catalog_name, group, group, None);
+			stats.object(&self.catalog_name, group, group, buf.len(), None);
 		}
+		tracing::info!(version = self.catalog_version, summary = %self.catalog.summary(), "published catalog");
+		tracing::debug!("{}", self.catalog);
+
+		self.catalog_version += 1;
+		self.catalog_group += 1;
+		self.snapshot = Some(buf);
+
+		Ok(())
 	}
 
-	false
+	/// write the newest catalogs and init segments again as new groups, returning the names of their tracks
+	///
+	/// Neither the content nor the version changes, only the group ids advance.
+	/// Nothing is written while the initial catalog is held back.
+	fn republish(&mut self) -> Result<Vec<String>, Error> {
+		let mut names = Vec::new();
+		let Some(snapshot) = self.snapshot.clone() else {
+			return Ok(names);
+		};
+
+		for (name, child) in self.children.iter_mut().flatten() {
+			let Some(last) = child.last.clone() else {
+				continue;
+			};
+			write_catalog(&mut child.track, child.group, last)?;
+			child.group += 1;
+			names.push(name.clone());
+		}
+
+		write_catalog(&mut self.catalog_broadcast, self.catalog_group, snapshot)?;
+		self.catalog_group += 1;
+		names.push(self.catalog_name.clone());
+
+		for (name, init) in self.inits.iter_mut() {
+			init.write(init.last.clone())?;
+			names.push(format!("{name}{INIT_TRACK_SUFFIX}"));
+		}
+
+		log::debug!("re-published {}", names.join(", "));
+		Ok(names)
+	}
+
+	/// publish the catalog of every media type with tracks that changed, the root catalog lists them
+	///
+	/// The tracks neither audio nor video, ex. the metadata or the timeline, are listed by the `data` catalog.
+	fn publish_children(&mut self) -> Result<moq_catalog::MoqCatalog, Error> {
+		let (video, audio) = (self.catalog.video_tracks(), self.catalog.audio_tracks());
+		let data: Vec<_> = self
+			.catalog
+			.tracks()
+			.iter()
+			.filter(|track| !video.contains(track) && !audio.contains(track))
+			.collect();
+		let kinds = [("video", video), ("audio", audio), ("data", data)]
+			.map(|(kind, tracks)| (kind, tracks.into_iter().cloned().collect::<Vec<_>>()));
+
+		let mut root = moq_catalog::MoqCatalog::new();
+		for (kind, tracks) in kinds {
+			let name = format!("{}.{kind}", self.catalog_name);
+			// a published catalog is emptied once its tracks are gone, ex. at the end of the input
+			let published = self
+				.children
+				.as_ref()
+				.is_some_and(|children| children.contains_key(&name));
+			if tracks.is_empty() && !published {
+				continue;
+			}
+
+			// inherits the common track fields
+			let mut catalog = self.catalog.clone();
+			if let Err(e) = catalog.set_tracks(&tracks) {
+				tracing::error!(error = %e);
+				return Err(Error::Catalog(e));
+			}
+			self.publish_child(&name, encode_catalog(&catalog, self.format)?)?;
+			if tracks.is_empty() {
+				continue;
+			}
+
+			if let Err(e) = root.insert_catalog(moq_catalog::Catalog::new(&name)) {
+				tracing::error!(error = %e);
+				return Err(Error::Catalog(e));
+			}
+		}
+
+		Ok(root)
+	}
+
+	/// write `buf` as the next version of the catalog `name`, unless it is unchanged
+	fn publish_child(&mut self, name: &str, buf: bytes::Bytes) -> Result<(), Error> {
+		let archive = self.catalog_broadcast.archive().cloned();
+		let Some(children) = self.children.as_mut() else {
+			tracing::error!("catalog not hierarchical");
+			return Err(Error::Missing);
+		};
+
+		let child = match children.entry(name.to_string()) {
+			std::collections::btree_map::Entry::Occupied(entry) => entry.into_mut(),
+			std::collections::btree_map::Entry::Vacant(entry) => {
+				let Some(track) = self.tracks.create(name) else {
+					tracing::error!(track_name = name, "failed to create the catalog track");
+					return Err(Error::Transport(moq_transport::serve::ServeError::Cancel));
+				};
+				let track = match track.groups() {
+					Ok(t) => ArchivingGroupsWriter::new(t, archive),
+					Err(e) => {
+						tracing::error!(error = %e);
+						return Err(Error::Transport(e));
+					}
+				};
+				entry.insert(ChildCatalog {
+					track,
+					version: 0,
+					group: 0,
+					last: None,
+				})
+			}
+		};
+		if child.last.as_ref() == Some(&buf) {
+			return Ok(());
+		}
+
+		write_catalog(&mut child.track, child.group, buf.clone())?;
+		if let Some(stats) = &self.stats {
+			stats.group(name, child.group, child.group, None);
+			stats.object(name, child.group, child.group, buf.len(), None);
+		}
+		tracing::info!(catalog = name, version = child.version, "published catalog");
+		child.version += 1;
+		child.group += 1;
+		child.last = Some(buf);
+
+		Ok(())
+	}
 }
 
-// Find the timescale for the given track.
-fn track_timescale(moov: &mp4::MoovBox, track_id: u32) -> u64 {
-	let trak = moov
-		.traks
-		.iter()
-		.find(|trak| trak.tkhd.track_id == track_id)
-		.expect("failed to find trak");
+/// write `buf` as the single object of the group `group_id` of the catalog `track`, also its priority
+fn write_catalog(track: &mut ArchivingGroupsWriter, group_id: u64, buf: bytes::Bytes) -> Result<(), Error> {
+	let group = moq_transport::serve::Group {
+		group_id,
+		priority: group_id,
+	};
+	match track.create(group) {
+		Ok(mut g) => {
+			if let Err(e) = g.write(buf) {
+				tracing::error!(error = %e);
+				return Err(Error::Transport(e));
+			}
+		}
+		Err(e) => {
+			tracing::error!(error = %e);
+			return Err(Error::Transport(e));
+		}
+	}
 
-	trak.mdia.mdhd.timescale as u64
+	Ok(())
+}
+
+/// encode `catalog` in `format`, a catalog listing both tracks and catalogs is never published
+fn encode_catalog(
+	catalog: &moq_catalog::MoqCatalog,
+	format: moq_catalog::CatalogFormat,
+) -> Result<bytes::Bytes, Error> {
+	let both = catalog
+		.validate()
+		.into_iter()
+		.find(|e| matches!(e, moq_catalog::Error::BothTracksAndCatalogs));
+	if let Some(e) = both {
+		tracing::error!(error = %e);
+		return Err(Error::Catalog(e));
+	}
+
+	match catalog.encode_with(format) {
+		Ok(b) => Ok(b.into()),
+		Err(e) => {
+			tracing::error!(error = %e);
+			Err(Error::Catalog(e))
+		}
+	}
+}
+
+/// Parses the chunks of a single rep and publishes them on its track.
+struct Representation {
+	rep_id: RepID,
+	settings: super::Settings<std::path::PathBuf>,
+	broadcast: Arc<Mutex<Broadcast>>,
+	metrics: crate::metrics::Metrics,
+	packaging: moq_catalog::Packaging,
+	group_order: GroupOrder,
+	object_mode: ObjectMode,
+	audio_group: Option<std::time::Duration>,
+	group_duration: Option<std::time::Duration>,
+	discontinuity: std::time::Duration,
+	max_group_age: Option<std::time::Duration>,
+	backpressure: Option<Arc<dyn Backpressure>>,
+	track_names: TrackNameTemplate,
+	/// the group starts of video reps are checked against each other
+	alignment: Option<Alignment>,
+	archive: Option<Archive>,
+	stats: Option<Stats>,
+	/// the track skips its groups while nobody is subscribed
+	lazy: bool,
+	/// a fragment of a trak the moov does not describe fails instead of being skipped
+	strict_traks: bool,
+	/// the init segment is sent on its own track instead of inlined in the catalog
+	init_tracks: bool,
+	max_object_size: usize,
+	max_atom_size: usize,
+	/// the base priority of the groups
+	priority: u8,
+	/// advanced to the media time of every fragment, if the rep times the metadata
+	timeline: Option<Arc<watch::Sender<std::time::Duration>>>,
+
+	buf: crate::atom::Chunks,
+	track: Option<Track>,
+
+	/// the bytes of corrupt data skipped so far, while looking for the next fragment
+	resync: Option<usize>,
+
+	/// the fragments of other traks than that of the track are skipped with their mdat
+	skip_mdat: bool,
+	/// the unknown trak warned about last, warned about again once it changes
+	unexpected_trak: Option<u32>,
+
+	/// LOC splits the next mdat into the samples of this moof
+	fragment: Option<Fragment>,
+
+	ftyp: Option<bytes::Bytes>,
+	moov: Option<bytes::Bytes>,
+
+	/// producer reference time of the next moof, sent once in front of it
+	prft: Option<bytes::Bytes>,
+
+	/// event messages sent in-band, in front of the next moof
+	emsg: bytes::BytesMut,
+}
+
+impl Representation {
+	fn new(
+		rep_id: RepID,
+		settings: super::Settings<std::path::PathBuf>,
+		broadcast: Arc<Mutex<Broadcast>>,
+		metrics: crate::metrics::Metrics,
+		packaging: moq_catalog::Packaging,
+		group_order: GroupOrder,
+		object_mode: ObjectMode,
+	) -> Self {
+		Self {
+			rep_id,
+			settings,
+			broadcast,
+			metrics,
+			packaging,
+			group_order,
+			object_mode,
+			audio_group: None,
+			group_duration: None,
+			discontinuity: DISCONTINUITY_THRESHOLD,
+			max_group_age: None,
+			backpressure: None,
+			track_names: Default::default(),
+			alignment: None,
+			archive: None,
+			stats: None,
+			lazy: false,
+			strict_traks: false,
+			init_tracks: false,
+			max_object_size: MAX_OBJECT_SIZE,
+			max_atom_size: MAX_ATOM_SIZE,
+			priority: VIDEO_PRIORITY,
+			timeline: None,
+			buf: Default::default(),
+			track: None,
+			resync: None,
+			skip_mdat: false,
+			unexpected_trak: None,
+			fragment: None,
+			ftyp: None,
+			moov: None,
+			prft: None,
+			emsg: bytes::BytesMut::new(),
+		}
+	}
+
+	/// handle the messages until the [Publisher] is gone, then end the track
+	#[tracing::instrument(name = "rep", skip_all, fields(rep_id = self.rep_id))]
+	async fn run(mut self, mut messages: mpsc::UnboundedReceiver<Message>, errors: mpsc::UnboundedSender<Error>) {
+		while let Some(message) = messages.recv().await {
+			match message {
+				Message::Data(data) => {
+					if let Err(e) = self.publish(data).await {
+						let _ = errors.send(e.context("publishing a chunk"));
+						return;
+					}
+				}
+				Message::Reset => self.reset(),
+				Message::EndSegment => {
+					if let Err(e) = self.end_segment().await {
+						let _ = errors.send(e.context("ending a segment"));
+						return;
+					}
+				}
+				Message::Flush(done) => {
+					let _ = done.send(());
+				}
+				Message::Reload(rep_id, settings, alignment) => {
+					if let Err(e) = self.reload(rep_id, *settings, alignment).await {
+						let _ = errors.send(e.context("reloading the settings"));
+						return;
+					}
+				}
+			}
+		}
+
+		if let Some(track) = self.track {
+			if let Err(e) = track.close().await {
+				log::debug!("track {} already closed: {e}", self.rep_id);
+			}
+		}
+	}
+
+	async fn publish(&mut self, data: bytes::Bytes) -> Result<(), Error> {
+		self.buf.push(data);
+
+		self.parse().await?;
+
+		Ok(())
+	}
+
+	fn reset(&mut self) {
+		self.buf.clear();
+		self.resync = None;
+		self.fragment = None;
+		self.prft = None;
+		self.emsg.clear();
+		if let Some(track) = self.track.as_mut() {
+			track.discard();
+		}
+	}
+
+	/// continue as `rep_id` of changed `settings`, a changed setting of this rep starts a new group
+	async fn reload(
+		&mut self,
+		rep_id: RepID,
+		settings: super::Settings<std::path::PathBuf>,
+		alignment: Alignment,
+	) -> Result<(), Error> {
+		let changed = self.settings.get_rep(self.rep_id) != settings.get_rep(rep_id);
+		let mode = settings.get_rep(rep_id).map(|s| s.mode()).unwrap_or_default();
+		// a new stream mode needs a new writer, the subscribers of the old one are cut off
+		let writer = match &self.track {
+			Some(track) if track.track.mode() != mode => Some(self.broadcast().replace(track.track.name())?),
+			_ => None,
+		};
+		self.rep_id = rep_id;
+		self.settings = settings;
+		self.alignment = Some(alignment);
+
+		let Some(track) = self.track.as_mut() else {
+			return Ok(());
+		};
+		if track.alignment.is_some() {
+			track.alignment = self
+				.alignment
+				.as_ref()
+				.map(|alignment| alignment.rep(rep_id, track.metrics.clone()));
+		}
+
+		if changed {
+			// the catalog entry is refreshed with the next moov, even if the encoder writes the same one
+			self.moov = None;
+			track.end_group().await?;
+		}
+		if let Some(writer) = writer {
+			track.switch(writer, mode, self.archive.clone()).await?;
+		}
+
+		Ok(())
+	}
+
+	async fn end_segment(&mut self) -> Result<(), Error> {
+		match self.track.as_mut() {
+			Some(track) if track.mode == ObjectMode::PerSegment => track.flush().await,
+			_ => Ok(()),
+		}
+	}
+
+	async fn parse(&mut self) -> Result<(), Error> {
+		loop {
+			if self.resync.is_some() && !self.resync() {
+				return Ok(());
+			}
+
+			match self.parse_atom().await {
+				Ok(true) => (),
+				Ok(false) => return Ok(()),
+				// ex. a truncated chunk or chunks out of order, parsing continues at the next fragment
+				Err(e @ (Error::Malformed("atom", _) | Error::Mp4(_))) => self.corrupt(&e),
+				Err(e) => return Err(e),
+			}
+		}
+	}
+
+	/// skip the data up to the next fragment after `error`, ending the current group as it misses fragments
+	fn corrupt(&mut self, error: &Error) {
+		tracing::warn!(rep_id = self.rep_id, %error, "corrupt data, resynchronizing on the next fragment");
+		self.resync = Some(0);
+		self.fragment = None;
+		self.prft = None;
+		self.skip_mdat = false;
+		self.emsg.clear();
+		if let Some(track) = self.track.as_mut() {
+			track.resync();
+		}
+	}
+
+	/// drop the data before the next of the [RESYNC_ATOMS], false while none arrived yet
+	///
+	/// The offsets aligned to 4 bytes from where the corrupt data started are the candidates,
+	/// a candidate is only taken with a size up to the max atom size.
+	fn resync(&mut self) -> bool {
+		let data = self.buf.copy_to_bytes(self.buf.remaining());
+		let (offset, found) = match crate::atom::find_atom(&data, &RESYNC_ATOMS, self.max_atom_size) {
+			Ok(offset) => (offset, true),
+			Err(offset) => (offset, false),
+		};
+		self.buf.push(data.slice(offset..));
+
+		let skipped = self.resync.unwrap_or_default() + offset;
+		if !found {
+			self.resync = Some(skipped);
+			return false;
+		}
+
+		tracing::info!(rep_id = self.rep_id, skipped, "resynchronized after corrupt data");
+		self.resync = None;
+		true
+	}
+
+	/// the broadcast is only ever locked for short, synchronous updates
+	fn broadcast(&self) -> std::sync::MutexGuard<'_, Broadcast> {
+		self.broadcast.lock().unwrap_or_else(|e| e.into_inner())
+	}
+
+	/// the next atom, a prft together with the moof following it once that arrived as well
+	///
+	/// Both are sent as a single object, taken at once they do not have to be copied together.
+	/// An mdat is passed on as is, it is taken as the slices of the chunks it spans.
+	fn next_atom(&mut self) -> anyhow::Result<Option<Rope>> {
+		// the chunks of a rep never end, size 0 atoms are not supported
+		crate::atom::check_header(&self.buf, self.max_atom_size)?;
+		if let Some((size, kind)) = crate::atom::peek_atom(&self.buf, 0, false)? {
+			if &kind == b"prft" {
+				if let Ok(Some((moof, kind))) = crate::atom::peek_atom(&self.buf, size, false) {
+					if &kind == b"moof" {
+						return Ok(Some(self.buf.copy_to_bytes(size + moof).into()));
+					}
+				}
+			}
+
+			// LOC parses the samples out of it
+			if &kind == b"mdat" && self.packaging != moq_catalog::Packaging::LOC {
+				return Ok(Some(self.buf.take_rope(size)));
+			}
+		}
+
+		Ok(crate::atom::next_atom(&mut self.buf, false)?.map(Rope::from))
+	}
+
+	#[tracing::instrument(skip_all, fields(atom_type = tracing::field::Empty, size = tracing::field::Empty))]
+	async fn parse_atom(&mut self) -> Result<bool, Error> {
+		let rope = match self.next_atom() {
+			Ok(Some(rope)) => rope,
+			Ok(None) => return Ok(false),
+			Err(e) => {
+				tracing::error!(error = %e);
+				return Err(Error::Malformed("atom", e.to_string()));
+			}
+		};
+
+		// only an mdat spans several chunks
+		let atom = match rope.parts() {
+			[atom] => atom.clone(),
+			_ => {
+				tracing::Span::current()
+					.record("atom_type", "mdat")
+					.record("size", rope.len());
+				self.mdat(rope).await?;
+				return Ok(true);
+			}
+		};
+
+		let mut reader = std::io::Cursor::new(&atom);
+		let header = match mp4::BoxHeader::read(&mut reader) {
+			Ok(h) => h,
+			Err(e) => {
+				tracing::error!(error = %e);
+				return Err(Error::Mp4(e));
+			}
+		};
+		tracing::Span::current()
+			.record("atom_type", header.name.to_string())
+			.record("size", atom.len());
+
+		match header.name {
+			n if n.to_string() == "prft" => {
+				let size = header.size as usize;
+				if atom.len() == size {
+					self.prft = Some(atom);
+					return Ok(true);
+				}
+
+				// taken together with its moof
+				let moof = atom.slice(size..);
+				let produced = self.produced(&atom[..size])?;
+				self.prft = None;
+				let raw = self.in_band(atom);
+				self.fragment(raw, &moof, produced).await?;
+			}
+			n if n.to_string() == "styp" || n.to_string() == "sidx" => {
+				// the segment type and index of low latency DASH, not needed by the subscribers
+			}
+			n if n.to_string() == "emsg" => self.emsg(atom)?,
+			mp4::BoxType::FtypBox => {
+				// a restarted encoder writes the init segment again
+				self.ftyp = Some(atom);
+			}
+			mp4::BoxType::MoovBox => {
+				if self.moov.as_ref() == Some(&atom) {
+					log::debug!("skipping repeated moov on track {}", self.rep_id);
+					return Ok(true);
+				}
+
+				let moov = match mp4::MoovBox::read_box(&mut reader, header.size) {
+					Ok(m) => m,
+					Err(e) => {
+						tracing::error!(error = %e);
+						return Err(Error::Mp4(e));
+					}
+				};
+
+				match self.track.is_some() {
+					true => self.update(&moov, &atom)?,
+					false => self.setup(&moov, &atom)?,
+				}
+				self.moov = Some(atom);
+			}
+			mp4::BoxType::MoofBox => {
+				// the prft arrived in an earlier chunk than its moof
+				let (produced, raw) = match self.prft.take() {
+					Some(prft) => {
+						let mut raw = bytes::BytesMut::with_capacity(prft.len() + atom.len());
+						raw.extend_from_slice(&prft);
+						raw.extend_from_slice(&atom);
+						(self.produced(&prft)?, raw.freeze())
+					}
+					None => (None, atom.clone()),
+				};
+
+				let raw = self.in_band(raw);
+				self.fragment(raw, &atom, produced).await?;
+			}
+			mp4::BoxType::MdatBox => self.mdat(atom.into()).await?,
+			name => log::debug!("skipping {name} atom on track {}", self.rep_id),
+		}
+
+		Ok(true)
+	}
+
+	async fn mdat(&mut self, atom: Rope) -> Result<(), Error> {
+		if std::mem::take(&mut self.skip_mdat) {
+			return Ok(());
+		}
+
+		let Some(track) = self.track.as_mut() else {
+			tracing::error!("track not available");
+			return Err(Error::Missing);
+		};
+
+		if self.packaging != moq_catalog::Packaging::LOC {
+			return track.data(atom).await;
+		}
+
+		let Some(fragment) = self.fragment.take() else {
+			tracing::error!("mdat without moof");
+			return Err(Error::Malformed("mp4", "mdat without moof".to_string()));
+		};
+
+		for sample in fragment.samples(&atom.to_bytes(), track.defaults)? {
+			track.sample(sample).await?;
+		}
+		Ok(())
+	}
+
+	/// wall clock of a prft, also published on the timeline track if enabled
+	///
+	/// It only feeds the metrics and the timeline, so broken ones are skipped.
+	fn produced(&self, prft: &[u8]) -> Result<Option<std::time::SystemTime>, Error> {
+		let prft = match crate::atom::Prft::parse(prft) {
+			Ok(prft) => prft,
+			Err(e) => {
+				log::warn!("skipping invalid prft on track {}: {e}", self.rep_id);
+				return Ok(None);
+			}
+		};
+
+		if let Some(track) = self.track.as_ref() {
+			let mut broadcast = self.broadcast();
+			if broadcast.timeline_track.is_some() {
+				let record = TimelineRecord::new(&prft, track.timescale, self.rep_id);
+				broadcast.publish_timeline(&record, prft.wallclock)?;
+			}
+		}
+
+		Ok(Some(prft.wallclock))
+	}
+
+	/// publish the event message on the emsg track if enabled, otherwise in front of the next moof
+	fn emsg(&mut self, atom: bytes::Bytes) -> Result<(), Error> {
+		let emsg = match crate::atom::Emsg::parse(&atom) {
+			Ok(emsg) => emsg,
+			Err(e) => {
+				log::warn!("skipping invalid emsg on track {}: {e}", self.rep_id);
+				return Ok(());
+			}
+		};
+		tracing::info!(
+			scheme_id_uri = emsg.scheme_id_uri,
+			value = emsg.value,
+			id = emsg.id,
+			presentation_time = ?emsg.time(),
+			relative = emsg.relative,
+			"event message"
+		);
+
+		let mut broadcast = self.broadcast();
+		if broadcast.emsg.is_some() {
+			return broadcast.publish_emsg(&emsg, atom);
+		}
+		drop(broadcast);
+
+		// LOC objects only hold the samples
+		if self.packaging == moq_catalog::Packaging::LOC {
+			log::warn!(
+				"skipping emsg on LOC track {}, publish the emsg track instead",
+				self.rep_id
+			);
+			return Ok(());
+		}
+
+		self.emsg.extend_from_slice(&atom);
+		Ok(())
+	}
+
+	/// `raw` with the pending event messages in front of it
+	fn in_band(&mut self, raw: bytes::Bytes) -> bytes::Bytes {
+		if self.emsg.is_empty() {
+			return raw;
+		}
+
+		self.emsg.extend_from_slice(&raw);
+		self.emsg.split().freeze()
+	}
+
+	/// the fragment of the atom `moof`, sent as `raw` with the prft produced at `produced` in front of it
+	async fn fragment(
+		&mut self,
+		raw: bytes::Bytes,
+		moof: &[u8],
+		produced: Option<std::time::SystemTime>,
+	) -> Result<(), Error> {
+		let mut reader = std::io::Cursor::new(moof);
+		let header = match mp4::BoxHeader::read(&mut reader) {
+			Ok(h) => h,
+			Err(e) => {
+				tracing::error!(error = %e);
+				return Err(Error::Mp4(e));
+			}
+		};
+		// ex. a fragment of corrupt data, the moof parser may not return
+		if let Err(e) = crate::atom::check_moof(moof, MAX_FRAGMENT_SAMPLES) {
+			tracing::error!(error = %e);
+			return Err(Error::Malformed("atom", e.to_string()));
+		}
+		let moof_box = match mp4::MoofBox::read_box(&mut reader, header.size) {
+			Ok(m) => m,
+			Err(e) => {
+				tracing::error!(error = %e);
+				return Err(Error::Mp4(e));
+			}
+		};
+
+		let Some(track) = self.track.as_mut() else {
+			tracing::error!("track not available");
+			return Err(Error::Missing);
+		};
+		let mut fragment = Fragment::new(moof_box, moof.len(), track.defaults)?;
+
+		if fragment.track != track.trak {
+			let (expected, found) = (track.trak, fragment.track);
+			if track.other_traks.contains(&found) {
+				log::debug!("skipping fragment of trak {found} on track {}", self.rep_id);
+			} else if self.strict_traks {
+				tracing::error!(expected, found, "fragment of an unknown trak");
+				return Err(Error::UnexpectedTrak {
+					rep: self.rep_id,
+					expected,
+					found,
+				});
+			} else if self.unexpected_trak.replace(found) != Some(found) {
+				tracing::warn!(
+					rep_id = self.rep_id,
+					expected,
+					found,
+					"skipping the fragments of an unknown trak"
+				);
+			}
+			self.skip_mdat = true;
+			return Ok(());
+		}
+		self.unexpected_trak = None;
+		track.rebase(&mut fragment);
+
+		if let Some(timeline) = &self.timeline {
+			let timestamp = fragment.timestamp(track.timescale);
+			timeline.send_if_modified(|now| {
+				let later = timestamp > *now;
+				if later {
+					*now = timestamp;
+				}
+				later
+			});
+		}
+
+		if self.packaging == moq_catalog::Packaging::LOC {
+			// the samples are written once their mdat arrived
+			self.fragment = Some(fragment);
+			return Ok(());
+		}
+
+		if track.discontinuity() || track.boundary(fragment.keyframe, fragment.timestamp(track.timescale)) {
+			track.end_group().await?;
+		}
+
+		track.header(raw, fragment).await?;
+		if let Some(wallclock) = produced {
+			track.metrics.produced(wallclock);
+		}
+
+		Ok(())
+	}
+
+	#[tracing::instrument(skip_all, fields(track_name = tracing::field::Empty))]
+	fn setup(&mut self, moov: &mp4::MoovBox, raw: &[u8]) -> Result<(), Error> {
+		let (id, handler) = self.primary_trak(moov)?;
+		let timescale = track_timescale(moov, id);
+
+		let init = self.init_segment(raw)?;
+		let catalog_track = self.catalog_track(&init, id)?;
+		tracing::Span::current().record("track_name", catalog_track.name());
+		let metrics = self.metrics.track(catalog_track.name());
+		let mut broadcast = self.broadcast();
+		// subscribers find the init track once the catalog references it
+		if catalog_track.init_track().is_some() {
+			broadcast.publish_init(catalog_track.name(), init, self.archive.clone(), self.max_object_size)?;
+		}
+		let track = broadcast.insert(catalog_track)?;
+		drop(broadcast);
+		let stream_mode = self.settings.get_rep(self.rep_id).map(|s| s.mode()).unwrap_or_default();
+		let mut track = Track::new(
+			track,
+			handler,
+			timescale,
+			self.group_order,
+			self.object_mode,
+			stream_mode,
+			metrics,
+		)?;
+		track.defaults = SampleDefaults::new(moov, id);
+		(track.trak, track.other_traks) = (id, other_traks(moov, id));
+		track.audio_group = self.audio_group;
+		track.group_duration = self.group_duration;
+		track.discontinuity = self.discontinuity;
+		track.max_group_age = self.max_group_age;
+		track.track.set_backpressure(self.backpressure.clone());
+		track.lazy = self.lazy;
+		track.track.set_archive(self.archive.clone());
+		track.stats = self.stats.clone();
+		track.max_object_size = self.max_object_size;
+		track.priority = self.priority;
+		if handler == mp4::TrackType::Video {
+			track.alignment = self
+				.alignment
+				.as_ref()
+				.map(|alignment| alignment.rep(self.rep_id, track.metrics.clone()));
+		}
+		self.track = Some(track);
+
+		Ok(())
+	}
+
+	/// a restarted encoder writes a new moov for a known rep, only changed codec parameters are re-advertised
+	fn update(&mut self, moov: &mp4::MoovBox, raw: &[u8]) -> Result<(), Error> {
+		let (id, _) = self.primary_trak(moov)?;
+
+		let init = self.init_segment(raw)?;
+		let catalog_track = self.catalog_track(&init, id)?;
+
+		if let Some(track) = self.track.as_mut() {
+			track.timescale = track_timescale(moov, id);
+			track.defaults = SampleDefaults::new(moov, id);
+			(track.trak, track.other_traks) = (id, other_traks(moov, id));
+		}
+
+		let mut broadcast = self.broadcast();
+		if catalog_track.init_track().is_some() {
+			broadcast.publish_init(catalog_track.name(), init, self.archive.clone(), self.max_object_size)?;
+		}
+		broadcast.update(catalog_track)
+	}
+
+	/// the id and handler of the audio or video trak of `moov`, the traks of other handlers are skipped
+	fn primary_trak(&self, moov: &mp4::MoovBox) -> Result<(u32, mp4::TrackType), Error> {
+		let mut media = Vec::new();
+		for trak in &moov.traks {
+			let handler_type = &trak.mdia.hdlr.handler_type;
+			match mp4::TrackType::try_from(handler_type) {
+				Ok(handler @ (mp4::TrackType::Video | mp4::TrackType::Audio)) => {
+					media.push((trak.tkhd.track_id, handler))
+				}
+				_ => log::warn!(
+					"rep {}: skipping trak {} with the unsupported handler {handler_type}",
+					self.rep_id,
+					trak.tkhd.track_id
+				),
+			}
+		}
+
+		let handlers = || {
+			let handlers: Vec<_> = moov
+				.traks
+				.iter()
+				.map(|trak| trak.mdia.hdlr.handler_type.to_string())
+				.collect();
+			handlers.join(", ")
+		};
+		match media.as_slice() {
+			[primary] => Ok(*primary),
+			[] => {
+				let handlers = handlers();
+				tracing::error!(handlers, "no audio or video trak in moov");
+				Err(Error::Malformed(
+					"mp4",
+					format!(
+						"rep {}: no audio or video trak in moov, found [{handlers}]",
+						self.rep_id
+					),
+				))
+			}
+			_ => {
+				let handlers = handlers();
+				tracing::error!(handlers, "multiple audio or video traks in moov");
+				Err(Error::Malformed(
+					"mp4",
+					format!(
+						"rep {}: multiple audio or video traks in moov, found [{handlers}]",
+						self.rep_id
+					),
+				))
+			}
+		}
+	}
+
+	fn track_name(&self) -> Result<String, Error> {
+		let Some(settings) = self.settings.get_rep(self.rep_id) else {
+			tracing::error!("missing settings for the rep");
+			return Err(Error::Missing);
+		};
+		Ok(self.track_names.render(&settings))
+	}
+
+	/// the resolution declared for the rep wins over a disagreeing avc1 sample entry
+	fn set_dimensions(&self, params: &mut moq_catalog::SelectionParams) {
+		if !params.codec().is_some_and(|codec| codec.starts_with("avc1")) {
+			return;
+		}
+		let declared = match self.settings.get_rep(self.rep_id) {
+			Some(Setting::Video(v)) => v.dimensions(),
+			_ => None,
+		};
+		let (Some(declared), Some(width), Some(height)) = (declared, params.width(), params.height()) else {
+			return;
+		};
+
+		if declared != (width, height) {
+			log::warn!(
+				"rep {}: sample entry is {}x{} but {}x{} is declared, using the declared size",
+				self.rep_id,
+				width,
+				height,
+				declared.0,
+				declared.1
+			);
+			params.set_width(declared.0).set_height(declared.1);
+		}
+	}
+
+	/// the init segment of the moov atom `raw`, the ftyp followed by the moov
+	fn init_segment(&self, raw: &[u8]) -> Result<bytes::Bytes, Error> {
+		let Some(ftyp) = &self.ftyp else {
+			tracing::error!("missing ftyp");
+			return Err(Error::Malformed("mp4", "missing ftyp for track".to_string()));
+		};
+		let mut init = bytes::BytesMut::with_capacity(ftyp.len() + raw.len());
+		init.extend_from_slice(ftyp);
+		init.extend_from_slice(raw);
+
+		Ok(init.freeze())
+	}
+
+	/// catalog entry of the trak `id` in the init segment `init`
+	fn catalog_track(&self, init: &[u8], id: u32) -> Result<moq_catalog::Track, Error> {
+		let Some(settings) = self.settings.get_rep(self.rep_id) else {
+			tracing::error!("missing settings for the rep");
+			return Err(Error::Missing);
+		};
+		let track_name = self.track_name()?;
+		let alt_group = match settings {
+			Setting::Audio(_) => AUDIO_ALT_GROUP,
+			Setting::Video(_) => VIDEO_ALT_GROUP,
+		};
+
+		let mut catalog_track = match moq_catalog::Track::from_init_segment_track(&track_name, init, id) {
+			Ok(t) => t,
+			Err(e) => {
+				tracing::error!(error = %e);
+				return Err(Error::Catalog(e));
+			}
+		};
+
+		if let Some(params) = catalog_track.selection_params_mut() {
+			if let Setting::Video(v) = &settings {
+				self.set_dimensions(params);
+				params.set_bitrate(v.bitrate).set_framerate(self.settings.fps);
+			}
+			// only CMAF payloads are in a container, the codec strings are valid WebCodecs strings for either packaging
+			if self.packaging == moq_catalog::Packaging::LOC {
+				params.clear_mime_type();
+			}
+		}
+		// ex. for a codec whose sample entry is not parsed, the catalog could not be encoded
+		if catalog_track
+			.selection_params()
+			.is_some_and(moq_catalog::SelectionParams::is_empty)
+		{
+			log::warn!("rep {}: no selection params found, leaving them out", self.rep_id);
+			catalog_track.clear_selection_params();
+		}
+		if let Some(params) = catalog_track.selection_params_mut() {
+			params.set_priority(self.priority);
+		}
+
+		catalog_track
+			.set_packaging(self.packaging)
+			.set_label(settings.name())
+			.set_alt_group(alt_group);
+		// overrides the namespace of the common track fields
+		if let Some(namespace) = settings.namespace() {
+			catalog_track.set_namespace(namespace);
+		}
+		// base64 grows the init segment by a third, in every catalog version
+		let inline = init.len().div_ceil(3) * 4;
+		if !self.init_tracks && inline > INIT_DATA_LIMIT {
+			log::info!(
+				"rep {}: initData of {inline} bytes too large, publishing the init track",
+				self.rep_id
+			);
+		}
+		if self.init_tracks || inline > INIT_DATA_LIMIT {
+			catalog_track
+				.clear_init_data()
+				.set_init_track(&format!("{track_name}{INIT_TRACK_SUFFIX}"));
+		}
+
+		// subscribers pick the rungs by these, the track is published anyway
+		for inconsistency in catalog_track.validate() {
+			tracing::warn!(%inconsistency);
+		}
+
+		Ok(catalog_track)
+	}
+}
+
+struct Track {
+	// The track we're producing, in the stream mode of its setting
+	track: ModeWriter,
+
+	// The current segment
+	current: Option<ModeGroupWriter>,
+
+	// The number of units per second.
+	timescale: u64,
+
+	// The type of track, ex. "vide" or "soun"
+	handler: mp4::TrackType,
+
+	// The sample defaults of the moov, used when neither tfhd nor trun carry them.
+	defaults: Option<SampleDefaults>,
+
+	// The id of the trak in the moov the fragments are published of, and the ids of its other traks.
+	trak: u32,
+	other_traks: Vec<u32>,
+
+	// How the groups are prioritized, the base priority of the track and the number of groups created so far.
+	order: GroupOrder,
+	priority: u8,
+	sequence: u64,
+
+	// The media time after which an audio group ends, the least a video group covers, and the start of the current group.
+	audio_group: Option<std::time::Duration>,
+	group_duration: Option<std::time::Duration>,
+	group_start: Option<std::time::Duration>,
+
+	// Added to the fragment timestamps, grows whenever a looped input restarts them.
+	offset: u64,
+
+	// The largest gap to the end of the last fragment within a group, and the gap found before the next fragment.
+	discontinuity: std::time::Duration,
+	gap: Option<std::time::Duration>,
+
+	// The rebased timestamp and the duration of the last fragment, in timescale units.
+	last: Option<(u64, u64)>,
+
+	// How the atoms are cut into objects, and the atoms not written yet.
+	mode: ObjectMode,
+	pending: Rope,
+	// Larger objects are split into several.
+	max_object_size: usize,
+
+	// The objects and bytes written to the current group, logged once it ends.
+	objects: u64,
+	bytes: u64,
+
+	metrics: crate::metrics::Recorder,
+
+	// Checks the group starts against the other video reps.
+	alignment: Option<super::alignment::Rep>,
+
+	// Skip the groups while nobody is subscribed, idle until a subscriber gets the next group start.
+	lazy: bool,
+	idle: bool,
+
+	// Abandon the current group once a write to it blocks past the max age, stale until the next group start.
+	max_group_age: Option<std::time::Duration>,
+	opened: Option<std::time::Instant>,
+	stale: bool,
+
+	// Records the groups and objects, with the media time of the last fragment or sample.
+	stats: Option<Stats>,
+	timestamp: Option<std::time::Duration>,
+}
+
+impl Track {
+	fn new(
+		track: moq_transport::serve::TrackWriter,
+		handler: mp4::TrackType,
+		timescale: u64,
+		order: GroupOrder,
+		mode: ObjectMode,
+		stream_mode: StreamMode,
+		metrics: crate::metrics::Recorder,
+	) -> Result<Self, Error> {
+		Ok(Self {
+			track: Self::writer(track, stream_mode)?,
+			current: None,
+			timescale,
+			handler,
+			defaults: None,
+			trak: 0,
+			other_traks: Vec::new(),
+			order,
+			priority: VIDEO_PRIORITY,
+			sequence: 0,
+			audio_group: None,
+			group_duration: None,
+			group_start: None,
+			offset: 0,
+			discontinuity: DISCONTINUITY_THRESHOLD,
+			gap: None,
+			last: None,
+			mode,
+			pending: Rope::default(),
+			max_object_size: MAX_OBJECT_SIZE,
+			objects: 0,
+			bytes: 0,
+			metrics,
+			alignment: None,
+			lazy: false,
+			idle: false,
+			max_group_age: None,
+			opened: None,
+			stale: false,
+			stats: None,
+			timestamp: None,
+		})
+	}
+
+	/// fails if the broadcast already dropped `track`, ex. once it was closed
+	fn writer(track: moq_transport::serve::TrackWriter, mode: StreamMode) -> Result<ModeWriter, Error> {
+		match ModeWriter::new(track, mode) {
+			Ok(writer) => Ok(writer),
+			Err(e) => {
+				tracing::error!(error = %e);
+				Err(Error::Transport(e))
+			}
+		}
+	}
+
+	/// end the current group and continue on a new writer of the track in `mode`
+	async fn switch(
+		&mut self,
+		track: moq_transport::serve::TrackWriter,
+		mode: StreamMode,
+		archive: Option<Archive>,
+	) -> Result<(), Error> {
+		let mut writer = Self::writer(track, mode)?;
+		writer.set_archive(archive);
+
+		writer.set_backpressure(self.track.backpressure());
+
+		self.end_group().await?;
+		let previous = std::mem::replace(&mut self.track, writer);
+		if let Err(e) = previous.close(moq_transport::serve::ServeError::Done) {
+			tracing::debug!(track = self.track.name(), error = %e, "track already closed");
+		}
+		tracing::info!(track = self.track.name(), ?mode, "publishing in the new stream mode");
+
+		Ok(())
+	}
+
+	/// continue after the last fragment if the timestamps jumped back, ex. at the end of a looped input
+	///
+	/// Otherwise the priorities of the groups would go backwards.
+	pub fn rebase(&mut self, fragment: &mut Fragment) {
+		let mut timestamp = fragment.timestamp + self.offset;
+
+		if let Some((last, duration)) = self.last {
+			if timestamp < last {
+				let end = last + duration.max(1);
+				log::info!("timestamps jumped back by {}, rebasing", end - timestamp);
+				self.offset += end - timestamp;
+				timestamp = end;
+			}
+
+			let gap = timescale_duration(timestamp.saturating_sub(last + duration), self.timescale);
+			if gap > self.discontinuity {
+				self.gap = Some(gap);
+			}
+		}
+
+		// without sample durations, the distance to the previous fragment is the best guess
+		let duration = match fragment.duration {
+			0 => self.last.map_or(0, |(last, _)| timestamp - last),
+			duration => duration,
+		};
+
+		self.last = Some((timestamp, duration));
+		fragment.timestamp = timestamp;
+	}
+
+	pub async fn header(&mut self, raw: bytes::Bytes, fragment: Fragment) -> Result<(), Error> {
+		let timestamp = fragment.timestamp(self.timescale);
+		if self.skip(fragment.keyframe) {
+			self.metrics.skipped(raw.len());
+			return Ok(());
+		}
+
+		// Start a new segment unless one is open
+		if self.current.is_none() {
+			self.current = Some(self.group(timestamp)?);
+			self.metrics.group(timestamp);
+		}
+
+		self.metrics.fragment(timestamp);
+		self.timestamp = Some(timestamp);
+		self.object(raw.into()).await
+	}
+
+	pub async fn data(&mut self, raw: Rope) -> Result<(), Error> {
+		// the mdat of a skipped moof
+		if self.idle || self.stale {
+			self.metrics.skipped(raw.len());
+			return Ok(());
+		}
+		self.object(raw).await?;
+
+		match self.mode {
+			ObjectMode::PerChunk => self.flush().await,
+			_ => Ok(()),
+		}
+	}
+
+	/// whether the fragment or sample is skipped, only checked before a new group
+	///
+	/// A lazy track goes idle once nobody is subscribed at a group start,
+	/// and resumes at the next keyframe, or audio fragment, after a subscriber appeared.
+	fn skip(&mut self, keyframe: bool) -> bool {
+		if self.current.is_some() {
+			return false;
+		}
+
+		let start = keyframe || self.handler != mp4::TrackType::Video;
+		if self.stale {
+			if !start {
+				return true;
+			}
+			log::info!("{} caught up, publishing from the next group", self.track.name());
+			self.stale = false;
+		}
+		if !self.lazy {
+			return false;
+		}
+
+		let subscribed = self.track.subscribers() > 0;
+		match (self.idle, subscribed) {
+			(false, true) => false,
+			(false, false) => {
+				log::info!("nobody subscribed to {}, skipping its groups", self.track.name());
+				self.idle = true;
+				true
+			}
+			(true, true) if start => {
+				log::info!("{} subscribed, publishing from the next group", self.track.name());
+				self.idle = false;
+				false
+			}
+			(true, _) => true,
+		}
+	}
+
+	/// wait until the writer takes an object of `size` bytes, at most until the group is older than the max group age
+	///
+	/// A write blocked for longer, ex. by a slow relay, abandons the group,
+	/// the fragments are skipped until the next keyframe, or audio fragment, starts a new group.
+	async fn ready(&mut self, size: usize) {
+		let (Some(max), Some(opened)) = (self.max_group_age, self.opened) else {
+			return self.track.ready(size).await;
+		};
+		// a writer that is ready is never timed out, ex. after the input stalled
+		let remaining = max.saturating_sub(opened.elapsed());
+		if tokio::time::timeout(remaining, self.track.ready(size)).await.is_ok() {
+			return;
+		}
+
+		tracing::warn!(
+			track = self.track.name(),
+			age = ?opened.elapsed(),
+			"group write blocked too long, skipping to the next keyframe"
+		);
+		self.metrics.dropped();
+		self.discard();
+		self.stale = true;
+	}
+
+	/// end the current group after corrupt data, skipping the fragments until the next keyframe, or audio fragment
+	fn resync(&mut self) {
+		self.metrics.resync();
+		self.discard();
+		self.stale = true;
+	}
+
+	/// whether the timestamps jumped ahead before the fragment, which then starts a new group
+	///
+	/// Checked once per fragment, ex. the encoder dropped frames or the input stalled.
+	fn discontinuity(&mut self) -> bool {
+		let Some(gap) = self.gap.take() else {
+			return false;
+		};
+
+		tracing::warn!(track = self.track.name(), ?gap, "discontinuity, starting a new group");
+		self.metrics.discontinuity();
+		true
+	}
+
+	/// whether the fragment or sample at `timestamp` starts a new group
+	///
+	/// Video groups start at keyframes, once the group covers the group duration if set.
+	/// Audio groups start once they cover the configured duration.
+	fn boundary(&self, keyframe: bool, timestamp: std::time::Duration) -> bool {
+		match self.handler {
+			mp4::TrackType::Video => match (self.group_duration, self.group_start) {
+				(Some(duration), Some(start)) => keyframe && timestamp.saturating_sub(start) >= duration,
+				_ => keyframe,
+			},
+			mp4::TrackType::Audio => match (self.audio_group, self.group_start) {
+				(Some(duration), Some(start)) => timestamp.saturating_sub(start) >= duration,
+				_ => false,
+			},
+			_ => false,
+		}
+	}
+
+	/// LOC writes every sample as its own object, video keyframes start a new group
+	pub async fn sample(&mut self, sample: Sample) -> Result<(), Error> {
+		let timestamp = timescale_duration(sample.timestamp, self.timescale);
+		if self.discontinuity() || self.boundary(sample.keyframe, timestamp) {
+			self.end_group().await?;
+		}
+
+		let frame = loc::Frame {
+			timestamp,
+			duration: timescale_duration(sample.duration, self.timescale),
+			keyframe: sample.keyframe,
+			payload: sample.data,
+		}
+		.encode()?;
+
+		if self.skip(sample.keyframe) {
+			self.metrics.skipped(frame.len());
+			return Ok(());
+		}
+
+		if self.current.is_none() {
+			self.current = Some(self.group(timestamp)?);
+			self.metrics.group(timestamp);
+		}
+
+		self.metrics.fragment(timestamp);
+		self.timestamp = Some(timestamp);
+		self.write(frame.into()).await
+	}
+
+	/// write `raw` as its own object, or buffer it until the end of the chunk or segment
+	async fn object(&mut self, raw: Rope) -> Result<(), Error> {
+		match self.mode {
+			ObjectMode::PerAtom => self.write_split(raw).await,
+			ObjectMode::PerChunk | ObjectMode::PerSegment => {
+				self.pending.append(raw);
+				Ok(())
+			}
+		}
+	}
+
+	/// write the buffered atoms as a single object
+	pub async fn flush(&mut self) -> Result<(), Error> {
+		if self.pending.is_empty() {
+			return Ok(());
+		}
+
+		let raw = std::mem::take(&mut self.pending);
+		self.write_split(raw).await
+	}
+
+	/// write `raw` as objects of at most the max object size
+	async fn write_split(&mut self, raw: Rope) -> Result<(), Error> {
+		if raw.len() <= self.max_object_size {
+			return self.write(raw).await;
+		}
+
+		for object in crate::atom::split_rope(raw, self.max_object_size) {
+			self.write(object).await?;
+		}
+		Ok(())
+	}
+
+	/// the slices of `raw` are written as they are, an object spanning several chunks is not copied together
+	async fn write(&mut self, raw: Rope) -> Result<(), Error> {
+		let size = raw.len();
+		if self.current.is_some() {
+			self.ready(size).await;
+		}
+		// the group was abandoned, with this or an earlier object
+		if self.stale {
+			self.metrics.skipped(size);
+			return Ok(());
+		}
+
+		let Some(segment) = self.current.as_mut() else {
+			tracing::error!("missing current fragment");
+			return Err(Error::Malformed("mp4", "object before the first moof".to_string()));
+		};
+
+		if let Err(e) = self.track.write_parts(segment, raw.parts()) {
+			tracing::error!(error = %e);
+			return Err(Error::Transport(e));
+		}
+		self.metrics.object(size);
+		if let Some(stats) = &self.stats {
+			stats.object(
+				self.track.name(),
+				segment.group_id(),
+				segment.priority(),
+				size,
+				self.timestamp,
+			);
+		}
+		self.objects += 1;
+		self.bytes += size as u64;
+
+		Ok(())
+	}
+
+	/// a new group starting at `timestamp`, prioritized by the base priority and the [GroupOrder]
+	fn group(&mut self, timestamp: std::time::Duration) -> Result<ModeGroupWriter, Error> {
+		if let Some(alignment) = &self.alignment {
+			alignment.group(timestamp)?;
+		}
+
+		let priority = self.order.priority(timestamp, self.sequence, self.priority);
+		self.sequence += 1;
+		self.group_start = Some(timestamp);
+		self.opened = Some(std::time::Instant::now());
+		self.objects = 0;
+		self.bytes = 0;
+
+		match self.track.append(priority) {
+			Ok(s) => {
+				if let Some(stats) = &self.stats {
+					stats.group(self.track.name(), s.group_id(), s.priority(), Some(timestamp));
+				}
+				Ok(s)
+			}
+			Err(e) => {
+				tracing::error!(error = %e);
+				Err(Error::Transport(e))
+			}
+		}
+	}
+
+	/// write the buffered atoms, the next fragment starts a new group
+	pub async fn end_group(&mut self) -> Result<(), Error> {
+		self.flush().await?;
+		self.finish();
+		Ok(())
+	}
+
+	/// drop the current group, once all of its objects are written
+	fn finish(&mut self) {
+		let Some(group) = self.current.take() else {
+			return;
+		};
+
+		tracing::info!(
+			track = self.track.name(),
+			group = group.group_id(),
+			priority = group.priority(),
+			objects = self.objects,
+			bytes = self.bytes,
+			"published group"
+		);
+	}
+
+	/// drop the buffered atoms and the current group, ex. after an incomplete chunk
+	pub fn discard(&mut self) {
+		self.pending = Rope::default();
+		self.current = None;
+	}
+
+	/// finish the current group and end the track
+	pub async fn close(mut self) -> Result<(), moq_transport::serve::ServeError> {
+		// a segment cut short by the shutdown is still sent
+		if let Err(e) = self.flush().await {
+			log::debug!("dropping buffered atoms: {e}");
+		}
+		self.finish();
+		self.track.close(moq_transport::serve::ServeError::Done)
+	}
+}
+
+struct Fragment {
+	// The track for this fragment.
+	track: u32,
+
+	// The timestamp of the first sample in this fragment, in timescale units.
+	timestamp: u64,
+
+	// True if this fragment is a keyframe.
+	keyframe: bool,
+
+	// The summed durations of the samples in timescale units, 0 if unknown.
+	duration: u64,
+
+	// The size of the moof atom, the trun data offset is relative to its start.
+	size: usize,
+
+	moof: mp4::MoofBox,
+}
+
+/// the trex values of the moov
+#[derive(Clone, Copy)]
+struct SampleDefaults {
+	duration: u32,
+	size: u32,
+	flags: u32,
+}
+
+impl SampleDefaults {
+	/// the defaults of the trak `id`, the trex of another trak does not apply
+	fn new(moov: &mp4::MoovBox, id: u32) -> Option<Self> {
+		let trex = moov
+			.mvex
+			.as_ref()
+			.map(|mvex| &mvex.trex)
+			.filter(|trex| trex.track_id == id)?;
+		Some(Self {
+			duration: trex.default_sample_duration,
+			size: trex.default_sample_size,
+			flags: trex.default_sample_flags,
+		})
+	}
+}
+
+/// A single frame of a fragment.
+struct Sample {
+	// The presentation timestamp and the duration, in timescale units.
+	timestamp: u64,
+	duration: u64,
+
+	keyframe: bool,
+	data: bytes::Bytes,
+}
+
+impl Fragment {
+	/// `defaults` are the sample defaults of the moov, for the duration of samples without one
+	fn new(moof: mp4::MoofBox, size: usize, defaults: Option<SampleDefaults>) -> Result<Self, Error> {
+		if moof.trafs.is_empty() {
+			tracing::error!("moof without traf");
+			return Err(Error::Malformed("atom", "moof without traf".to_string()));
+		}
+
+		// We can't split the mdat atom, so this is impossible to support
+		if moof.trafs.len() != 1 {
+			tracing::error!(trafs = moof.trafs.len(), "multiple tracks per moof atom");
+			return Err(Error::Malformed("mp4", "multiple tracks per moof atom".to_string()));
+		}
+
+		let track = moof.trafs[0].tfhd.track_id;
+
+		// Parse the moof to get some timing information to sleep.
+		let Some(timestamp) = sample_timestamp(&moof) else {
+			tracing::error!("moof without tfdt");
+			return Err(Error::Malformed("atom", "moof without tfdt".to_string()));
+		};
+
+		// Detect if we should start a new segment.
+		let keyframe = sample_keyframe(&moof);
+		let duration = sample_duration(&moof, defaults);
+
+		Ok(Self {
+			track,
+			timestamp,
+			keyframe,
+			duration,
+			size,
+			moof,
+		})
+	}
+
+	// Convert from timescale units to a duration.
+	fn timestamp(&self, timescale: u64) -> std::time::Duration {
+		std::time::Duration::from_millis(1000 * self.timestamp / timescale)
+	}
+
+	/// split the `mdat` following the moof into its samples
+	fn samples(&self, mdat: &bytes::Bytes, defaults: Option<SampleDefaults>) -> Result<Vec<Sample>, Error> {
+		let traf = &self.moof.trafs[0];
+		let tfhd = &traf.tfhd;
+		let Some(trun) = &traf.trun else {
+			return Ok(Vec::new());
+		};
+
+		if tfhd.base_data_offset.is_some() {
+			tracing::error!("explicit base data offset");
+			return Err(Error::Malformed("mp4", "explicit base data offset".to_string()));
+		}
+
+		// the data offset counts from the start of the moof, directly followed by the mdat
+		let header = match mdat.get(..4) {
+			Some([0, 0, 0, 1]) => 16,
+			_ => 8,
+		};
+		let mut offset = match trun.data_offset {
+			Some(offset) => usize::try_from(offset)
+				.ok()
+				.and_then(|offset| offset.checked_sub(self.size))
+				.unwrap_or_default(),
+			None => header,
+		};
+		if offset < header {
+			tracing::error!("sample data outside of the mdat");
+			return Err(Error::Malformed("mp4", "sample data outside of the mdat".to_string()));
+		}
+
+		let mut timestamp = self.timestamp;
+		let mut samples = Vec::with_capacity(trun.sample_count as usize);
+
+		for i in 0..trun.sample_count as usize {
+			let size = trun
+				.sample_sizes
+				.get(i)
+				.copied()
+				.or(tfhd.default_sample_size)
+				.or(defaults.map(|d| d.size));
+			let Some(size) = size.map(|s| s as usize) else {
+				tracing::error!("missing sample size");
+				return Err(Error::Malformed("mp4", "missing sample size".to_string()));
+			};
+
+			let duration = trun
+				.sample_durations
+				.get(i)
+				.copied()
+				.or(tfhd.default_sample_duration)
+				.or(defaults.map(|d| d.duration))
+				.unwrap_or_default() as u64;
+
+			let flags = match (i, trun.first_sample_flags) {
+				(0, Some(first)) => first,
+				_ => trun
+					.sample_flags
+					.get(i)
+					.copied()
+					.or(tfhd.default_sample_flags)
+					.or(defaults.map(|d| d.flags))
+					.unwrap_or_default(),
+			};
+
+			let Some(data) = mdat.get(offset..offset + size) else {
+				tracing::error!("sample exceeds the mdat");
+				return Err(Error::Malformed("mp4", "sample exceeds the mdat".to_string()));
+			};
+
+			samples.push(Sample {
+				timestamp: timestamp + trun.sample_cts.get(i).copied().unwrap_or_default() as u64,
+				duration,
+				keyframe: is_keyframe(flags),
+				data: mdat.slice_ref(data),
+			});
+
+			offset += size;
+			timestamp += duration;
+		}
+
+		Ok(samples)
+	}
+}
+
+// Convert from timescale units to a duration.
+fn timescale_duration(value: u64, timescale: u64) -> std::time::Duration {
+	std::time::Duration::from_micros(value.saturating_mul(1_000_000) / timescale.max(1))
+}
+
+fn sample_timestamp(moof: &mp4::MoofBox) -> Option<u64> {
+	Some(moof.trafs.first()?.tfdt.as_ref()?.base_media_decode_time)
+}
+
+fn sample_keyframe(moof: &mp4::MoofBox) -> bool {
+	for traf in &moof.trafs {
+		// TODO trak default flags if this is None
+		let default_flags = traf.tfhd.default_sample_flags.unwrap_or_default();
+		let trun = match &traf.trun {
+			Some(t) => t,
+			None => return false,
+		};
+
+		for i in 0..trun.sample_count {
+			let mut flags = match trun.sample_flags.get(i as usize) {
+				Some(f) => *f,
+				None => default_flags,
+			};
+
+			if let (0, Some(first)) = (i, trun.first_sample_flags) {
+				flags = first;
+			}
+
+			if is_keyframe(flags) {
+				return true;
+			}
+		}
+	}
+
+	false
+}
+
+fn is_keyframe(flags: u32) -> bool {
+	// https://chromium.googlesource.com/chromium/src/media/+/master/formats/mp4/track_run_iterator.cc#177
+	let keyframe = (flags >> 24) & 0x3 == 0x2; // kSampleDependsOnNoOther
+	let non_sync = (flags >> 16) & 0x1 == 0x1; // kSampleIsNonSyncSample
+
+	keyframe && !non_sync
+}
+
+/// the summed durations of the samples of the first traf in timescale units, 0 if unknown
+fn sample_duration(moof: &mp4::MoofBox, defaults: Option<SampleDefaults>) -> u64 {
+	let traf = &moof.trafs[0];
+	let Some(trun) = &traf.trun else {
+		return 0;
+	};
+
+	if !trun.sample_durations.is_empty() {
+		return trun.sample_durations.iter().map(|d| *d as u64).sum();
+	}
+
+	let duration = traf.tfhd.default_sample_duration.or(defaults.map(|d| d.duration));
+	trun.sample_count as u64 * duration.unwrap_or_default() as u64
+}
+
+/// the ids of the traks of `moov` besides `track_id`
+fn other_traks(moov: &mp4::MoovBox, track_id: u32) -> Vec<u32> {
+	moov.traks
+		.iter()
+		.map(|trak| trak.tkhd.track_id)
+		.filter(|id| *id != track_id)
+		.collect()
+}
+
+// Find the timescale for the given track.
+fn track_timescale(moov: &mp4::MoovBox, track_id: u32) -> u64 {
+	let trak = moov
+		.traks
+		.iter()
+		.find(|trak| trak.tkhd.track_id == track_id)
+		.expect("failed to find trak");
+
+	trak.mdia.mdhd.timescale as u64
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const SETTINGS: &str = "gop_num=1
+fps=25
+target_segment_duration=2.0
+===AUDIO===
+name,sampling,bitrate
+===VIDEO===
+name,resolution,bitrate,max_rate,buffer_size
+video,1920x1080,6000000,6000000,12000000
+video_low,1280x720,3000000,3000000,6000000
+";
+
+	fn publisher() -> (Publisher, moq_transport::serve::TracksReader) {
+		packaged(Default::default())
+	}
+
+	fn packaged(packaging: moq_catalog::Packaging) -> (Publisher, moq_transport::serve::TracksReader) {
+		configured(packaging, Default::default(), Default::default())
+	}
+
+	fn configured(
+		packaging: moq_catalog::Packaging,
+		group_order: GroupOrder,
+		object_mode: ObjectMode,
+	) -> (Publisher, moq_transport::serve::TracksReader) {
+		with_settings(SETTINGS, packaging, group_order, object_mode)
+	}
+
+	fn with_settings(
+		settings: &str,
+		packaging: moq_catalog::Packaging,
+		group_order: GroupOrder,
+		object_mode: ObjectMode,
+	) -> (Publisher, moq_transport::serve::TracksReader) {
+		let settings = crate::dash::Settings::from_bytes(
+			settings.as_bytes().to_vec(),
+			"input".into(),
+			"output".into(),
+			false,
+			false,
+		)
+		.unwrap();
+		let (writer, _, reader) = moq_transport::serve::Tracks::new("test".to_string()).produce();
+
+		(
+			Publisher::new(
+				writer,
+				settings,
+				Default::default(),
+				packaging,
+				group_order,
+				object_mode,
+			)
+			.unwrap(),
+			reader,
+		)
+	}
+
+	/// publish and wait until the rep task processed the chunk
+	async fn publish(publisher: &mut Publisher, rep_id: RepID, data: &[u8]) -> Result<(), Error> {
+		publisher.publish(rep_id, bytes::Bytes::copy_from_slice(data))?;
+		publisher.flush().await
+	}
+
+	fn current_catalog(publisher: &Publisher) -> moq_catalog::MoqCatalog {
+		publisher.broadcast.lock().unwrap().catalog.clone()
+	}
+
+	/// (group id, last object id) of the latest group of `name`
+	async fn latest_group(reader: &mut moq_transport::serve::TracksReader, name: &str) -> Option<(u64, u64)> {
+		match reader.subscribe(name)?.mode().await.ok()? {
+			moq_transport::serve::TrackReaderMode::Groups(groups) => groups.latest(),
+			_ => None,
+		}
+	}
+
+	fn catalog_track(publisher: &Publisher) -> serde_json::Value {
+		let catalog: serde_json::Value = serde_json::from_slice(&current_catalog(publisher).encode().unwrap()).unwrap();
+		catalog["tracks"][0].clone()
+	}
+
+	#[tokio::test]
+	async fn test_hevc_catalog() {
+		let fixtures: [(&[u8], &str); 2] = [
+			(include_bytes!("../../tests/fixtures/hev1_init.m4s"), "hev1.1.6.L93.B0"),
+			(include_bytes!("../../tests/fixtures/hvc1_init.m4s"), "hvc1.1.6.L93.B0"),
+		];
+
+		for (init, codec) in fixtures {
+			let (mut publisher, _reader) = publisher();
+			publish(&mut publisher, 0, init).await.unwrap();
+
+			let track = catalog_track(&publisher);
+			assert_eq!(track["name"], "video");
+			assert_eq!(track["selectionParams"]["codec"], codec);
+			assert_eq!(track["selectionParams"]["mimeType"], "video/mp4");
+			assert_eq!(track["selectionParams"]["width"], 1920);
+			assert_eq!(track["selectionParams"]["height"], 1080);
+			assert_eq!(track["selectionParams"]["bitrate"], 6_000_000);
+		}
+	}
+
+	#[tokio::test]
+	async fn test_dimensions() {
+		let init = include_bytes!("../../tests/fixtures/avc_2160p_init.m4s");
+		let settings = SETTINGS.replace("1920x1080", "3840x2160");
+		let params = |settings: &str, init: &[u8]| {
+			let settings = settings.to_string();
+			let init = init.to_vec();
+			async move {
+				let (mut publisher, _reader) =
+					with_settings(&settings, Default::default(), Default::default(), Default::default());
+				publish(&mut publisher, 0, &init).await.unwrap();
+				catalog_track(&publisher)["selectionParams"].clone()
+			}
+		};
+
+		// neither wrapped nor truncated
+		let params_2160p = params(&settings, init).await;
+		assert_eq!(params_2160p["width"], 3840);
+		assert_eq!(params_2160p["height"], 2160);
+		assert!(params_2160p.get("displayWidth").is_none());
+
+		// the declared size wins over a disagreeing sample entry
+		let params_1080p = params(SETTINGS, init).await;
+		assert_eq!(params_1080p["width"], 1920);
+		assert_eq!(params_1080p["height"], 1080);
+
+		// anamorphic, the tkhd is the display size
+		let mut anamorphic = init.to_vec();
+		let tkhd = anamorphic.windows(4).position(|w| w == b"tkhd").unwrap() + 4 + 76;
+		anamorphic[tkhd..tkhd + 4].copy_from_slice(&(5120u32 << 16).to_be_bytes());
+		let params_anamorphic = params(&settings, &anamorphic).await;
+		assert_eq!(params_anamorphic["width"], 3840);
+		assert_eq!(params_anamorphic["displayWidth"], 5120);
+		assert_eq!(params_anamorphic["displayHeight"], 2160);
+	}
+
+	#[tokio::test]
+	async fn test_track_names() {
+		let settings = SETTINGS
+			.replace("buffer_size\n", "buffer_size,namespace\n")
+			.replace("12000000\n", "12000000,exp-b\n")
+			.replace("6000000\n", "6000000,\n");
+		let (mut publisher, mut reader) =
+			with_settings(&settings, Default::default(), Default::default(), Default::default());
+		publisher
+			.set_track_name_template("{kind}/{height}p/avc".parse().unwrap())
+			.unwrap();
+
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/avc_init.m4s"))
+			.await
+			.unwrap();
+		publish(&mut publisher, 1, include_bytes!("../../tests/fixtures/avc_init.m4s"))
+			.await
+			.unwrap();
+		assert!(reader.subscribe("video/1080p/avc").is_some());
+
+		let catalog: serde_json::Value =
+			serde_json::from_slice(&current_catalog(&publisher).encode().unwrap()).unwrap();
+		assert_eq!(catalog["commonTrackFields"]["namespace"], "test");
+		let tracks = catalog["tracks"].as_array().unwrap();
+		let track = |name: &str| tracks.iter().find(|t| t["name"] == name).unwrap();
+		assert_eq!(track("video/1080p/avc")["namespace"], "exp-b");
+		assert_eq!(track("video/1080p/avc")["label"], "video");
+		// inherited from the common fields
+		assert!(track("video/720p/avc").get("namespace").is_none());
+
+		// both reps would be named video
+		assert!(matches!(
+			publisher.set_track_name_template("{kind}".parse().unwrap()),
+			Err(Error::Validation(_))
+		));
+	}
+
+	#[tokio::test]
+	async fn test_vp9() {
+		let (mut publisher, mut reader) = publisher();
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/vp09_init.m4s"))
+			.await
+			.unwrap();
+
+		let track = catalog_track(&publisher);
+		assert_eq!(track["selectionParams"]["codec"], "vp09.00.10.08");
+		assert_eq!(track["selectionParams"]["mimeType"], "video/mp4");
+		assert_eq!(track["selectionParams"]["width"], 1280);
+		assert_eq!(track["selectionParams"]["height"], 720);
+		assert_eq!(track["selectionParams"]["bitrate"], 6_000_000);
+
+		// keyframe and delta frame share a group, the next keyframe starts a new one
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/chunk_1.m4s"))
+			.await
+			.unwrap();
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/chunk_2.m4s"))
+			.await
+			.unwrap();
+		assert_eq!(latest_group(&mut reader, "video").await, Some((0, 3)));
+
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/chunk_3.m4s"))
+			.await
+			.unwrap();
+		assert_eq!(latest_group(&mut reader, "video").await, Some((1, 1)));
+	}
+
+	#[tokio::test]
+	async fn test_av1() {
+		let (mut publisher, mut reader) = publisher();
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/av01_init.m4s"))
+			.await
+			.unwrap();
+
+		let track = catalog_track(&publisher);
+		assert_eq!(track["selectionParams"]["codec"], "av01.0.08M.08");
+		assert_eq!(track["selectionParams"]["mimeType"], "video/mp4");
+		assert_eq!(track["selectionParams"]["width"], 1280);
+		assert_eq!(track["selectionParams"]["height"], 720);
+		assert_eq!(track["selectionParams"]["bitrate"], 6_000_000);
+		assert_eq!(track["selectionParams"]["framerate"], 25);
+		assert!(track["initData"].is_string());
+
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/chunk_1.m4s"))
+			.await
+			.unwrap();
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/chunk_2.m4s"))
+			.await
+			.unwrap();
+		assert_eq!(latest_group(&mut reader, "video").await, Some((0, 3)));
+
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/chunk_3.m4s"))
+			.await
+			.unwrap();
+		assert_eq!(latest_group(&mut reader, "video").await, Some((1, 1)));
+	}
+
+	#[tokio::test]
+	async fn test_av1_truncated() {
+		let (mut publisher, _reader) = publisher();
+		let res = publish(
+			&mut publisher,
+			0,
+			include_bytes!("../../tests/fixtures/av01_truncated_init.m4s"),
+		)
+		.await;
+		assert!(matches!(
+			res.unwrap_err().root(),
+			Error::Catalog(moq_catalog::Error::InvalidInitSegment(_))
+		));
+	}
+
+	/// catalog in the newest group of a fresh subscription
+	async fn newest_catalog(reader: &mut moq_transport::serve::TracksReader) -> (u64, bytes::Bytes) {
+		let moq_transport::serve::TrackReaderMode::Groups(mut groups) =
+			reader.subscribe(".catalog").unwrap().mode().await.unwrap()
+		else {
+			panic!("expected groups mode");
+		};
+
+		let mut group = groups.next().await.unwrap().unwrap();
+		(group.group_id, group.read_next().await.unwrap().unwrap())
+	}
+
+	#[tokio::test]
+	async fn test_catalog_versions() {
+		let (mut publisher, mut reader) = publisher();
+		assert_eq!(publisher.catalog_snapshot(), None);
+
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/avc_init.m4s"))
+			.await
+			.unwrap();
+		let (version, first) = newest_catalog(&mut reader).await;
+		assert_eq!(version, 0);
+		assert_eq!(moq_catalog::MoqCatalog::decode(&first).unwrap().tracks().len(), 1);
+
+		// joining mid-stream, the newest group holds the complete catalog on its own
+		publish(&mut publisher, 1, include_bytes!("../../tests/fixtures/vp09_init.m4s"))
+			.await
+			.unwrap();
+		let (version, object) = newest_catalog(&mut reader).await;
+		assert_eq!(version, 1);
+
+		let catalog = moq_catalog::MoqCatalog::decode(&object).unwrap();
+		assert_eq!(catalog.tracks().len(), 2);
+		assert_eq!(catalog.encode().unwrap(), current_catalog(&publisher).encode().unwrap());
+		assert_eq!(publisher.catalog_snapshot(), Some(object));
+		assert_eq!(latest_group(&mut reader, ".catalog").await, Some((1, 0)));
+	}
+
+	#[tokio::test]
+	async fn test_restart() {
+		let (mut publisher, mut reader) = publisher();
+		// only the first rep is published
+		publisher.set_catalog_initial_timeout(std::time::Duration::ZERO);
+		let init = include_bytes!("../../tests/fixtures/avc_init.m4s");
+		let chunk = include_bytes!("../../tests/fixtures/chunk_1.m4s");
+
+		publish(&mut publisher, 0, init).await.unwrap();
+		publish(&mut publisher, 0, chunk).await.unwrap();
+		assert_eq!(latest_group(&mut reader, ".catalog").await, Some((0, 0)));
+
+		// the encoder crashed mid fragment and was restarted with the same parameters
+		publish(&mut publisher, 0, &chunk[..20]).await.unwrap();
+		publisher.reset(0);
+		publish(&mut publisher, 0, init).await.unwrap();
+		publish(&mut publisher, 0, chunk).await.unwrap();
+		assert_eq!(latest_group(&mut reader, ".catalog").await, Some((0, 0)));
+		assert_eq!(latest_group(&mut reader, "video").await, Some((1, 1)));
+
+		// restarted with a different codec
+		publisher.reset(0);
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/vp09_init.m4s"))
+			.await
+			.unwrap();
+		assert_eq!(latest_group(&mut reader, ".catalog").await, Some((1, 0)));
+		assert_eq!(catalog_track(&publisher)["selectionParams"]["codec"], "vp09.00.10.08");
+	}
+
+	#[tokio::test]
+	async fn test_initial_catalog() {
+		let init = include_bytes!("../../tests/fixtures/avc_init.m4s");
+		let names = |publisher: &Publisher| -> Vec<String> {
+			let catalog = moq_catalog::MoqCatalog::decode(&publisher.catalog_snapshot().unwrap()).unwrap();
+			catalog.tracks().iter().map(|track| track.name().to_string()).collect()
+		};
+
+		// a single catalog once both reps are set up
+		let (mut publisher, mut reader) = publisher();
+		publish(&mut publisher, 0, init).await.unwrap();
+		assert_eq!(latest_group(&mut reader, ".catalog").await, None);
+		assert!(publisher.catalog_snapshot().is_none());
+
+		publish(&mut publisher, 1, init).await.unwrap();
+		assert_eq!(latest_group(&mut reader, ".catalog").await, Some((0, 0)));
+		assert_eq!(names(&publisher), ["video", "video_low"]);
+
+		// the second rep never shows up, the catalog is published without it
+		let (mut publisher, mut reader) = self::publisher();
+		publisher.set_catalog_initial_timeout(std::time::Duration::from_millis(200));
+		publish(&mut publisher, 0, init).await.unwrap();
+		assert_eq!(latest_group(&mut reader, ".catalog").await, None);
+
+		tokio::time::timeout(std::time::Duration::from_secs(5), async {
+			while publisher.catalog_snapshot().is_none() {
+				tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+			}
+		})
+		.await
+		.expect("no catalog after the timeout");
+		assert_eq!(latest_group(&mut reader, ".catalog").await, Some((0, 0)));
+		assert_eq!(names(&publisher), ["video"]);
+
+		// a late rep updates it
+		publish(&mut publisher, 1, init).await.unwrap();
+		assert_eq!(latest_group(&mut reader, ".catalog").await, Some((1, 0)));
+		assert_eq!(names(&publisher), ["video", "video_low"]);
+	}
+
+	/// one rung of the ladder dropped, one changed and one added, the reps are matched by their track name
+	#[tokio::test]
+	async fn test_reload() {
+		let (mut publisher, mut reader) = publisher();
+		let init = include_bytes!("../../tests/fixtures/avc_init.m4s");
+		let chunk = include_bytes!("../../tests/fixtures/chunk_1.m4s");
+
+		for rep_id in [0, 1] {
+			publish(&mut publisher, rep_id, init).await.unwrap();
+			publish(&mut publisher, rep_id, chunk).await.unwrap();
+		}
+		let moq_transport::serve::TrackReaderMode::Groups(mut removed) =
+			reader.subscribe("video_low").unwrap().mode().await.unwrap()
+		else {
+			panic!("expected groups mode");
+		};
+
+		let names = |publisher: &Publisher| -> Vec<String> {
+			let catalog = current_catalog(publisher);
+			catalog.tracks().iter().map(|track| track.name().to_string()).collect()
+		};
+		assert_eq!(names(&publisher), ["video", "video_low"]);
+
+		let settings = crate::dash::Settings::from_bytes(
+			SETTINGS
+				.replace("video_low,1280x720", "video_mid,1600x900")
+				.replace("video,1920x1080,6000000", "video,1920x1080,5000000")
+				.into_bytes(),
+			"input".into(),
+			"output".into(),
+			false,
+			false,
+		)
+		.unwrap();
+		publisher.reload(settings).await.unwrap();
+
+		// the removed track is gone from the catalog and ends
+		assert_eq!(names(&publisher), ["video"]);
+		let ended = loop {
+			if let Err(e) = removed.next().await {
+				break e;
+			}
+		};
+		assert!(matches!(ended, moq_transport::serve::ServeError::Done));
+
+		// the restarted encoder writes the same init segment, the changed bitrate is advertised anyway
+		for rep_id in [0, 1] {
+			publisher.reset(rep_id);
+			publish(&mut publisher, rep_id, init).await.unwrap();
+			publish(&mut publisher, rep_id, chunk).await.unwrap();
+		}
+		assert_eq!(names(&publisher), ["video", "video_mid"]);
+		assert_eq!(catalog_track(&publisher)["selectionParams"]["bitrate"], 5000000);
+		assert_eq!(latest_group(&mut reader, "video").await, Some((1, 1)));
+
+		// duplicate track names are rejected without changing anything
+		let settings = crate::dash::Settings::from_bytes(
+			SETTINGS.replace("video_low", "video").into_bytes(),
+			"input".into(),
+			"output".into(),
+			false,
+			false,
+		)
+		.unwrap();
+		assert!(matches!(publisher.reload(settings).await, Err(Error::Validation(_))));
+		assert_eq!(names(&publisher), ["video", "video_mid"]);
+	}
+
+	#[tokio::test]
+	async fn test_groups() {
+		let settings = crate::dash::Settings::from_bytes(
+			SETTINGS
+				.replace("bitrate\n", "bitrate\naudio,48000,128000\n")
+				.into_bytes(),
+			"input".into(),
+			"output".into(),
+			false,
+			false,
+		)
+		.unwrap();
+		let (writer, _, _reader) = moq_transport::serve::Tracks::new("test".to_string()).produce();
+		let mut publisher = Publisher::new(
+			writer,
+			settings,
+			Default::default(),
+			Default::default(),
+			Default::default(),
+			Default::default(),
+		)
+		.unwrap();
+
+		// audio reps come first
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/aac_init.m4s"))
+			.await
+			.unwrap();
+		for rep_id in [1, 2] {
+			publish(
+				&mut publisher,
+				rep_id,
+				include_bytes!("../../tests/fixtures/avc_init.m4s"),
+			)
+			.await
+			.unwrap();
+		}
+
+		let catalog: serde_json::Value =
+			serde_json::from_slice(&current_catalog(&publisher).encode().unwrap()).unwrap();
+		assert_eq!(catalog["commonTrackFields"]["renderGroup"], 1);
+		assert!(catalog["commonTrackFields"].get("altGroup").is_none());
+
+		let tracks = catalog["tracks"].as_array().unwrap();
+		let groups: Vec<_> = tracks
+			.iter()
+			.map(|t| (t["name"].clone(), t["altGroup"].clone()))
+			.collect();
+		assert_eq!(
+			groups,
+			[
+				("audio".into(), 2.into()),
+				("video".into(), 1.into()),
+				("video_low".into(), 1.into())
+			]
+		);
+
+		let audio = &tracks[0]["selectionParams"];
+		assert_eq!(audio["codec"], "mp4a.40.2");
+		assert_eq!(audio["mimeType"], "audio/mp4");
+		assert_eq!(audio["samplerate"], 48000);
+		assert_eq!(audio["channelConfig"], "2");
+		assert_eq!(audio["bitrate"], 128000);
+		assert!(audio.get("framerate").is_none());
+
+		assert_eq!(tracks[1]["selectionParams"]["framerate"], 25);
+		for track in current_catalog(&publisher).tracks() {
+			assert!(track.validate().is_empty(), "{:?}", track.validate());
+		}
+	}
+
+	#[tokio::test]
+	async fn test_close() {
+		let (mut publisher, mut reader) = publisher();
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/avc_init.m4s"))
+			.await
+			.unwrap();
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/chunk_1.m4s"))
+			.await
+			.unwrap();
+
+		let mut tracks = Vec::new();
+		for name in [".catalog", "video"] {
+			match reader.subscribe(name).unwrap().mode().await.unwrap() {
+				moq_transport::serve::TrackReaderMode::Groups(groups) => tracks.push(groups),
+				_ => panic!("expected groups mode for {name}"),
+			}
+		}
+
+		tracks[0].next().await.unwrap().unwrap();
+		let mut group = tracks[1].next().await.unwrap().unwrap();
+		publisher.close().await;
+
+		// the open group is finished, then the tracks end instead of being cancelled
+		while group.read_next().await.unwrap().is_some() {}
+		for groups in &mut tracks {
+			assert!(matches!(
+				groups.next().await,
+				Err(moq_transport::serve::ServeError::Done)
+			));
+		}
+	}
+
+	#[tokio::test]
+	async fn test_metadata() {
+		let dir = crate::testing::temp_dir("publisher-metadata");
+		let path = dir.join("markers.jsonl");
+		let markers = [(1000, "ad"), (80, "chapter 2"), (0, "intro"), (40, "chapter 1")];
+		let lines: String = markers
+			.iter()
+			.map(|(at_ms, title)| {
+				format!(
+					"{}\n",
+					serde_json::json!({ "at_ms": at_ms, "payload": { "title": title } })
+				)
+			})
+			.collect();
+		std::fs::write(&path, lines).unwrap();
+
+		let (mut publisher, mut reader) = publisher();
+		publisher.set_catalog_initial_timeout(std::time::Duration::ZERO);
+		publisher.enable_metadata(path, None).unwrap();
+
+		// the newest marker published, once the publisher caught up with the media time
+		async fn latest(reader: &mut moq_transport::serve::TracksReader, group: u64) -> serde_json::Value {
+			tokio::time::timeout(std::time::Duration::from_secs(5), async {
+				while latest_group(reader, metadata::METADATA_TRACK).await != Some((group, 0)) {
+					tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+				}
+			})
+			.await
+			.expect("marker not published");
+			serde_json::from_slice(&latest_object(reader, metadata::METADATA_TRACK).await).unwrap()
+		}
+
+		// markers at the start of the media are due right away
+		assert_eq!(latest(&mut reader, 0).await["title"], "intro");
+
+		// the fragment at 200ms passes both chapters, in order of their time
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/avc_init.m4s"))
+			.await
+			.unwrap();
+		publish(&mut publisher, 0, &fragment(2560, true, &[100])).await.unwrap();
+		assert_eq!(latest(&mut reader, 2).await["title"], "chapter 2");
+		tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+		assert_eq!(latest_group(&mut reader, metadata::METADATA_TRACK).await, Some((2, 0)));
+
+		// advertised next to the media
+		let catalog = current_catalog(&publisher);
+		let track = catalog.track(metadata::METADATA_TRACK).unwrap();
+		assert_eq!(track.packaging(), moq_catalog::Packaging::LOC);
+		assert_eq!(
+			track.selection_params().unwrap().mime_type(),
+			Some(metadata::METADATA_MIME_TYPE)
+		);
+
+		let _ = std::fs::remove_dir_all(&dir);
+	}
+
+	#[tokio::test]
+	async fn test_finish() {
+		let (mut publisher, mut reader) = publisher();
+		publisher.set_catalog_initial_timeout(std::time::Duration::ZERO);
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/avc_init.m4s"))
+			.await
+			.unwrap();
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/chunk_1.m4s"))
+			.await
+			.unwrap();
+
+		let moq_transport::serve::TrackReaderMode::Groups(mut video) =
+			reader.subscribe("video").unwrap().mode().await.unwrap()
+		else {
+			panic!("expected groups mode");
+		};
+		video.next().await.unwrap().unwrap();
+		publisher.finish().await;
+
+		// the last catalog lists no tracks, the media tracks ended without being removed
+		let catalog = moq_catalog::MoqCatalog::decode(&latest_object(&mut reader, ".catalog").await).unwrap();
+		assert!(catalog.tracks().is_empty());
+		assert_eq!(latest_group(&mut reader, ".catalog").await, Some((1, 0)));
+		assert!(matches!(
+			video.next().await,
+			Err(moq_transport::serve::ServeError::Done)
+		));
+		assert_eq!(latest_group(&mut reader, "video").await, Some((0, 1)));
+	}
+
+	/// moof and mdat of 40ms samples at `timestamp` (12800 timescale), the first sample is a keyframe if `keyframe`
+	fn fragment(timestamp: u64, keyframe: bool, sizes: &[u32]) -> Vec<u8> {
+		let trun_size = 24 + 8 * sizes.len() as u32;
+		let traf_size = 8 + 16 + 20 + trun_size;
+		let moof_size = 8 + 16 + traf_size;
+
+		let mut moof = Vec::new();
+		moof.extend_from_slice(&moof_size.to_be_bytes());
+		moof.extend_from_slice(b"moof");
+		moof.extend_from_slice(&[0, 0, 0, 16, b'm', b'f', b'h', b'd', 0, 0, 0, 0, 0, 0, 0, 1]);
+		moof.extend_from_slice(&traf_size.to_be_bytes());
+		moof.extend_from_slice(b"traf");
+		// default-base-is-moof, track 1
+		moof.extend_from_slice(&[0, 0, 0, 16, b't', b'f', b'h', b'd', 0, 2, 0, 0, 0, 0, 0, 1]);
+		moof.extend_from_slice(&[0, 0, 0, 20, b't', b'f', b'd', b't', 1, 0, 0, 0]);
+		moof.extend_from_slice(&timestamp.to_be_bytes());
+		moof.extend_from_slice(&trun_size.to_be_bytes());
+		// data offset, first sample flags, sample durations and sizes
+		moof.extend_from_slice(&[b't', b'r', b'u', b'n', 0, 0, 0x03, 0x05]);
+		moof.extend_from_slice(&(sizes.len() as u32).to_be_bytes());
+		moof.extend_from_slice(&(moof_size + 8).to_be_bytes());
+		moof.extend_from_slice(
+			&match keyframe {
+				true => 0x0200_0000u32,
+				false => 0x0101_0000,
+			}
+			.to_be_bytes(),
+		);
+		for size in sizes {
+			moof.extend_from_slice(&512u32.to_be_bytes());
+			moof.extend_from_slice(&size.to_be_bytes());
+		}
+
+		let data: Vec<u8> = sizes
+			.iter()
+			.enumerate()
+			.flat_map(|(i, size)| std::iter::repeat_n(i as u8, *size as usize))
+			.collect();
+		moof.extend_from_slice(&(8 + data.len() as u32).to_be_bytes());
+		moof.extend_from_slice(b"mdat");
+		moof.extend_from_slice(&data);
+
+		moof
+	}
+
+	/// collects the output of a subscriber installed in a test
+	#[derive(Clone, Default)]
+	struct Logs(std::sync::Arc<Mutex<Vec<u8>>>);
+
+	impl std::io::Write for Logs {
+		fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+			self.0.lock().unwrap().extend_from_slice(buf);
+			Ok(buf.len())
+		}
+
+		fn flush(&mut self) -> std::io::Result<()> {
+			Ok(())
+		}
+	}
+
+	#[tokio::test]
+	async fn test_group_events() {
+		let logs = Logs::default();
+		let writer = logs.clone();
+		let subscriber = tracing_subscriber::fmt()
+			.json()
+			.flatten_event(true)
+			.with_current_span(true)
+			.with_writer(move || writer.clone())
+			.finish();
+		let _guard = tracing::subscriber::set_default(subscriber);
+
+		let (mut publisher, _reader) = publisher();
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/avc_init.m4s"))
+			.await
+			.unwrap();
+		let first = fragment(0, true, &[100, 20]);
+		publish(&mut publisher, 0, &first).await.unwrap();
+		publish(&mut publisher, 0, &fragment(25_600, true, &[50]))
+			.await
+			.unwrap();
+
+		let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+		let groups: Vec<serde_json::Value> = logs
+			.lines()
+			.map(|line| serde_json::from_str(line).unwrap())
+			.filter(|event: &serde_json::Value| event["message"] == "published group")
+			.collect();
+
+		// the moof and the mdat of the first fragment, ended by the next keyframe
+		assert_eq!(groups.len(), 1, "{logs}");
+		assert_eq!(groups[0]["track"], "video");
+		assert_eq!(groups[0]["group"], 0);
+		assert_eq!(groups[0]["objects"], 2);
+		assert_eq!(groups[0]["bytes"], first.len());
+		assert_eq!(groups[0]["spans"][0]["rep_id"], 0);
+	}
+
+	const AUDIO_SETTINGS: &str = "gop_num=1
+fps=25
+target_segment_duration=2.0
+===AUDIO===
+name,sampling,bitrate
+audio,48000,128000
+===VIDEO===
+name,resolution,bitrate,max_rate,buffer_size
+";
+
+	#[tokio::test]
+	async fn test_audio_only() {
+		let (mut publisher, mut reader) = with_settings(
+			AUDIO_SETTINGS,
+			Default::default(),
+			Default::default(),
+			Default::default(),
+		);
+		// the fragments below are a single sample a second apart
+		publisher.set_discontinuity_threshold(std::time::Duration::from_secs(2));
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/aac_init.m4s"))
+			.await
+			.unwrap();
+
+		let catalog: serde_json::Value =
+			serde_json::from_slice(&current_catalog(&publisher).encode().unwrap()).unwrap();
+		assert_eq!(catalog["tracks"].as_array().unwrap().len(), 1);
+		assert_eq!(catalog["tracks"][0]["name"], "audio");
+		assert_eq!(catalog["tracks"][0]["altGroup"], AUDIO_ALT_GROUP);
+
+		// without keyframes to split on, a group ends after the segment duration, 2s rounded to 94 AAC frames
+		let segments = [(0, (0, 1)), (48_000, (0, 3)), (96_000, (0, 5)), (97_000, (1, 1))];
+		for (timestamp, latest) in segments {
+			publish(&mut publisher, 0, &fragment(timestamp, true, &[10]))
+				.await
+				.unwrap();
+			assert_eq!(latest_group(&mut reader, "audio").await, Some(latest));
+		}
+	}
+
+	#[tokio::test]
+	async fn test_group_duration() {
+		let (mut publisher, mut reader) = publisher();
+		publisher.set_group_duration(std::time::Duration::from_secs(3));
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/avc_init.m4s"))
+			.await
+			.unwrap();
+
+		// 1s GOPs of two fragments, the keyframe at 3s is the first to end a group
+		for second in 0..3 {
+			for (timestamp, keyframe) in [(second * 12800, true), (second * 12800 + 6400, false)] {
+				publish(&mut publisher, 0, &fragment(timestamp, keyframe, &[10]))
+					.await
+					.unwrap();
+			}
+		}
+		assert_eq!(latest_group(&mut reader, "video").await, Some((0, 11)));
+
+		publish(&mut publisher, 0, &fragment(3 * 12800, true, &[10]))
+			.await
+			.unwrap();
+		assert_eq!(latest_group(&mut reader, "video").await, Some((1, 1)));
+
+		// by default every keyframe starts a group
+		let (mut publisher, mut reader) = self::publisher();
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/avc_init.m4s"))
+			.await
+			.unwrap();
+		for timestamp in [0, 6400, 12800] {
+			publish(&mut publisher, 0, &fragment(timestamp, timestamp % 12800 == 0, &[10]))
+				.await
+				.unwrap();
+		}
+		assert_eq!(latest_group(&mut reader, "video").await, Some((1, 1)));
+	}
+
+	/// the value of the metric `name` of the first track, or of the track given by the labels within `name`
+	fn counter(publisher: &Publisher, name: &str) -> u64 {
+		let encoded = publisher.metrics.encode();
+		let line = encoded
+			.lines()
+			.find(|l| l.strip_prefix(name).is_some_and(|rest| rest.starts_with(['{', ' '])))
+			.unwrap();
+		line.rsplit_once(' ').unwrap().1.parse().unwrap()
+	}
+
+	#[tokio::test]
+	async fn test_discontinuity() {
+		let (mut publisher, mut reader) = publisher();
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/avc_init.m4s"))
+			.await
+			.unwrap();
+
+		// a 40ms sample every 0.5s, the gaps of 460ms are tolerated
+		for timestamp in [0, 6400, 12800, 19200] {
+			publish(&mut publisher, 0, &fragment(timestamp, timestamp == 0, &[10]))
+				.await
+				.unwrap();
+		}
+		assert_eq!(latest_group(&mut reader, "video").await, Some((0, 7)));
+		assert_eq!(counter(&publisher, "moq_pub_discontinuities_total"), 0);
+
+		// 2s of frames missing, the delta fragment ends the group
+		publish(&mut publisher, 0, &fragment(51200, false, &[10]))
+			.await
+			.unwrap();
+		assert_eq!(latest_group(&mut reader, "video").await, Some((1, 1)));
+		assert_eq!(counter(&publisher, "moq_pub_discontinuities_total"), 1);
+
+		// continues in the new group
+		publish(&mut publisher, 0, &fragment(51712, false, &[10]))
+			.await
+			.unwrap();
+		assert_eq!(latest_group(&mut reader, "video").await, Some((1, 3)));
+		assert_eq!(counter(&publisher, "moq_pub_discontinuities_total"), 1);
+	}
+
+	/// blocks the writes while closed, ex. a relay that stopped reading
+	struct Gate(watch::Sender<bool>);
+
+	impl Backpressure for Gate {
+		fn ready(&self, _: &str, _: usize) -> futures::future::BoxFuture<'static, ()> {
+			let mut open = self.0.subscribe();
+			Box::pin(async move {
+				let _ = open.wait_for(|open| *open).await;
+			})
+		}
+	}
+
+	#[tokio::test]
+	async fn test_max_group_age() {
+		let (mut publisher, mut reader) = publisher();
+		let gate = Arc::new(Gate(watch::channel(true).0));
+		publisher.set_max_group_age(std::time::Duration::from_millis(100));
+		publisher.set_backpressure(gate.clone());
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/avc_init.m4s"))
+			.await
+			.unwrap();
+
+		publish(&mut publisher, 0, &fragment(0, true, &[10])).await.unwrap();
+		publish(&mut publisher, 0, &fragment(512, false, &[10])).await.unwrap();
+		assert_eq!(latest_group(&mut reader, "video").await, Some((0, 3)));
+
+		// a stalled input is no reason to drop the group
+		tokio::time::sleep(std::time::Duration::from_millis(150)).await;
+		publish(&mut publisher, 0, &fragment(1024, false, &[10])).await.unwrap();
+		assert_eq!(latest_group(&mut reader, "video").await, Some((0, 5)));
+		assert_eq!(counter(&publisher, "moq_pub_dropped_groups_total"), 0);
+
+		// the writer blocks, the group is abandoned and the fragments are skipped until the keyframe
+		gate.0.send_replace(false);
+		publish(&mut publisher, 0, &fragment(1536, false, &[10])).await.unwrap();
+		publish(&mut publisher, 0, &fragment(2048, false, &[10])).await.unwrap();
+		assert_eq!(latest_group(&mut reader, "video").await, Some((0, 5)));
+		assert_eq!(counter(&publisher, "moq_pub_dropped_groups_total"), 1);
+
+		// the group of the keyframe is abandoned as well while the writer still blocks
+		let started = std::time::Instant::now();
+		publish(&mut publisher, 0, &fragment(2560, true, &[10])).await.unwrap();
+		assert!(started.elapsed() >= std::time::Duration::from_millis(100));
+		assert_eq!(counter(&publisher, "moq_pub_dropped_groups_total"), 2);
+
+		gate.0.send_replace(true);
+		publish(&mut publisher, 0, &fragment(3072, true, &[10])).await.unwrap();
+		publish(&mut publisher, 0, &fragment(3584, false, &[10])).await.unwrap();
+		assert_eq!(latest_group(&mut reader, "video").await, Some((2, 3)));
+		assert_eq!(counter(&publisher, "moq_pub_dropped_groups_total"), 2);
+	}
+
+	#[tokio::test]
+	async fn test_resync() {
+		let (mut publisher, mut reader) = publisher();
+		publisher.set_max_atom_size(1 << 10);
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/avc_init.m4s"))
+			.await
+			.unwrap();
+		publish(&mut publisher, 0, &fragment(0, true, &[10])).await.unwrap();
+		assert_eq!(latest_group(&mut reader, "video").await, Some((0, 1)));
+
+		// garbage ends the group, the fragments are skipped until the next keyframe
+		publish(&mut publisher, 0, &[0xff; 12]).await.unwrap();
+		publish(&mut publisher, 0, &fragment(512, false, &[10])).await.unwrap();
+		assert_eq!(counter(&publisher, "moq_pub_resyncs_total"), 1);
+		publish(&mut publisher, 0, &fragment(1024, true, &[10])).await.unwrap();
+		publish(&mut publisher, 0, &fragment(1536, false, &[10])).await.unwrap();
+		assert_eq!(latest_group(&mut reader, "video").await, Some((1, 3)));
+
+		// an mdat larger than the max atom size
+		publish(&mut publisher, 0, &fragment(2048, false, &[2 << 10]))
+			.await
+			.unwrap();
+		assert_eq!(counter(&publisher, "moq_pub_resyncs_total"), 2);
+
+		// a candidate split across chunks
+		let next = fragment(2560, true, &[10]);
+		publish(&mut publisher, 0, &[&[0; 4][..], &next[..6]].concat())
+			.await
+			.unwrap();
+		publish(&mut publisher, 0, &next[6..]).await.unwrap();
+		assert_eq!(latest_group(&mut reader, "video").await, Some((2, 1)));
+		assert_eq!(counter(&publisher, "moq_pub_resyncs_total"), 2);
+	}
+
+	/// deterministic pseudo-random numbers, xorshift
+	fn random(state: &mut u64) -> u64 {
+		*state ^= *state << 13;
+		*state ^= *state >> 7;
+		*state ^= *state << 17;
+		*state
+	}
+
+	#[tokio::test]
+	async fn test_resync_fuzz() {
+		let mut state = 0x9e37_79b9_7f4a_7c15;
+
+		for run in 0..60 {
+			let (mut publisher, mut reader) = publisher();
+			publisher.set_max_atom_size(1 << 16);
+			publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/avc_init.m4s"))
+				.await
+				.unwrap();
+			publish(&mut publisher, 0, &fragment(0, true, &[12])).await.unwrap();
+
+			let clean = fragment(512, false, &[40, 20]);
+			// the atoms start at offsets aligned to 4 bytes
+			let cut = 4 * (1 + random(&mut state) as usize % (clean.len() / 4 - 1));
+			let corrupt = match run % 3 {
+				// random bytes between the fragments
+				0 => (0..4 * (1 + random(&mut state) % 16))
+					.map(|_| random(&mut state) as u8)
+					.collect(),
+				// a truncated fragment
+				1 => clean[..cut].to_vec(),
+				// the chunks of the fragment out of order
+				_ => [&clean[cut..], &clean[..cut]].concat(),
+			};
+			publish(&mut publisher, 0, &corrupt).await.unwrap();
+
+			// in chunks of any size, ex. written while being read
+			let keyframe = fragment(4096, true, &[12]);
+			let after = fragment(4608, false, &[12]);
+			let mut data = [
+				fragment(1024, true, &[32]),
+				fragment(1536, false, &[32]),
+				keyframe.clone(),
+				after.clone(),
+			]
+			.concat();
+			while !data.is_empty() {
+				let len = data.len().min(1 + random(&mut state) as usize % 100);
+				let rest = data.split_off(len);
+				publish(&mut publisher, 0, &data).await.unwrap();
+				data = rest;
+			}
+
+			// publishing resumed at the latest, with the last keyframe
+			let moq_transport::serve::TrackReaderMode::Groups(mut groups) =
+				reader.subscribe("video").unwrap().mode().await.unwrap()
+			else {
+				panic!("expected groups mode");
+			};
+			let mut group = groups.next().await.unwrap().unwrap();
+			let mut objects = Vec::new();
+			for _ in 0..=group.latest() {
+				objects.extend(group.read_next().await.unwrap().unwrap());
+			}
+			assert!(
+				objects.ends_with(&[keyframe.clone(), after.clone()].concat()),
+				"run {run}, corrupt {corrupt:?}"
+			);
+		}
+	}
+
+	/// `init` with a copy of its trak appended to the moov, as the trak `id` with the handler `handler`
+	fn with_trak(init: &[u8], id: u32, handler: &[u8; 4]) -> Vec<u8> {
+		let size = |buf: &[u8], at: usize| u32::from_be_bytes(buf[at..at + 4].try_into().unwrap()) as usize;
+		let find = |buf: &[u8], name: &[u8]| buf.windows(4).position(|w| w == name).unwrap() - 4;
+
+		let moov = find(init, b"moov");
+		let start = moov + find(&init[moov + 8..], b"trak") + 8;
+		let mut trak = init[start..start + size(init, start)].to_vec();
+
+		// tkhd version 0, the track id follows the creation and modification times
+		let tkhd = find(&trak, b"tkhd");
+		trak[tkhd + 20..tkhd + 24].copy_from_slice(&id.to_be_bytes());
+		let hdlr = find(&trak, b"hdlr");
+		trak[hdlr + 16..hdlr + 20].copy_from_slice(handler);
+
+		let mut out = init.to_vec();
+		let end = moov + size(init, moov);
+		out.splice(end..end, trak.iter().copied());
+		let grown = (size(init, moov) + trak.len()) as u32;
+		out[moov..moov + 4].copy_from_slice(&grown.to_be_bytes());
+		out
+	}
+
+	#[tokio::test]
+	async fn test_unsupported_trak() {
+		let (mut publisher, mut reader) = publisher();
+		let init = with_trak(include_bytes!("../../tests/fixtures/avc_init.m4s"), 2, b"meta");
+		publish(&mut publisher, 0, &init).await.unwrap();
+
+		let track = catalog_track(&publisher);
+		assert_eq!(track["name"], "video");
+		assert_eq!(track["selectionParams"]["codec"], "avc1.64001f");
+
+		// the fragments of the data trak are skipped with their mdat
+		publish(&mut publisher, 0, &fragment(0, true, &[10])).await.unwrap();
+		let mut data = fragment(512, true, &[10]);
+		data[47] = 2;
+		publish(&mut publisher, 0, &data).await.unwrap();
+		assert_eq!(latest_group(&mut reader, "video").await, Some((0, 1)));
+
+		publish(&mut publisher, 0, &fragment(512, false, &[10])).await.unwrap();
+		assert_eq!(latest_group(&mut reader, "video").await, Some((0, 3)));
+	}
+
+	#[tokio::test]
+	async fn test_unexpected_trak() {
+		let (mut publisher, mut reader) = publisher();
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/avc_init.m4s"))
+			.await
+			.unwrap();
+		publish(&mut publisher, 0, &fragment(0, true, &[10])).await.unwrap();
+
+		// a trak the moov does not describe is skipped with its mdat
+		let mut data = fragment(512, false, &[10]);
+		data[47] = 9;
+		publish(&mut publisher, 0, &data).await.unwrap();
+		assert_eq!(latest_group(&mut reader, "video").await, Some((0, 1)));
+		publish(&mut publisher, 0, &fragment(512, false, &[10])).await.unwrap();
+		assert_eq!(latest_group(&mut reader, "video").await, Some((0, 3)));
+
+		let (mut strict, _reader) = self::publisher();
+		strict.set_strict_traks(true);
+		publish(&mut strict, 0, include_bytes!("../../tests/fixtures/avc_init.m4s"))
+			.await
+			.unwrap();
+		let err = publish(&mut strict, 0, &data).await.unwrap_err();
+		assert!(err
+			.to_string()
+			.contains("fragment of rep 0 is of trak 9, the init segment describes trak 1"));
+	}
+
+	#[test]
+	fn test_fragment() {
+		let parse = |data: &[u8]| {
+			let mut reader = std::io::Cursor::new(data);
+			let header = mp4::BoxHeader::read(&mut reader).unwrap();
+			let moof = mp4::MoofBox::read_box(&mut reader, header.size).unwrap();
+			Fragment::new(moof, header.size as usize, None)
+		};
+
+		let parsed = parse(&fragment(1024, true, &[10, 20, 30])).unwrap();
+		assert_eq!(parsed.track, 1);
+		assert_eq!(parsed.timestamp, 1024);
+		assert_eq!(parsed.duration, 3 * 512);
+		assert!(parsed.keyframe);
+
+		let mut data = fragment(1024, true, &[10]);
+		data[47] = 2;
+		assert_eq!(parse(&data).unwrap().track, 2);
+
+		// the tfdt replaced by a free box of the same size
+		data[52..56].copy_from_slice(b"free");
+		let err = parse(&data).err().unwrap();
+		assert_eq!(err.to_string(), "malformed atom: moof without tfdt");
+	}
+
+	#[tokio::test]
+	async fn test_multiple_traks() {
+		let (mut publisher, _reader) = publisher();
+		let init = with_trak(include_bytes!("../../tests/fixtures/avc_init.m4s"), 2, b"vide");
+		let err = publish(&mut publisher, 0, &init).await.unwrap_err();
+		assert!(err
+			.to_string()
+			.contains("rep 0: multiple audio or video traks in moov, found [vide, vide]"));
+	}
+
+	#[tokio::test]
+	async fn test_loc() {
+		let (mut publisher, mut reader) = packaged(moq_catalog::Packaging::LOC);
+
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/avc_init.m4s"))
+			.await
+			.unwrap();
+		let track = catalog_track(&publisher);
+		assert_eq!(track["packaging"], "loc");
+		assert_eq!(track["selectionParams"]["codec"], "avc1.64001f");
+		assert!(track["selectionParams"].get("mimeType").is_none());
+
+		let moq_transport::serve::TrackReaderMode::Groups(mut groups) =
+			reader.subscribe("video").unwrap().mode().await.unwrap()
+		else {
+			panic!("expected groups mode");
+		};
+
+		// a GOP of 5 samples, split over two fragments
+		publish(&mut publisher, 0, &fragment(25600, true, &[10, 20, 30]))
+			.await
+			.unwrap();
+		publish(&mut publisher, 0, &fragment(27136, false, &[5, 5]))
+			.await
+			.unwrap();
+		assert_eq!(latest_group(&mut reader, "video").await, Some((0, 4)));
+
+		let mut group = groups.next().await.unwrap().unwrap();
+		let mut frames = Vec::new();
+		for _ in 0..5 {
+			let object = group.read_next().await.unwrap().unwrap();
+			frames.push(crate::dash::LocFrame::decode(object).unwrap());
+		}
+		assert_eq!(
+			frames.iter().map(|f| f.payload.len()).collect::<Vec<_>>(),
+			[10, 20, 30, 5, 5]
+		);
+		assert_eq!(frames[1].payload, vec![1; 20]);
+		assert_eq!(
+			frames.iter().map(|f| f.keyframe).collect::<Vec<_>>(),
+			[true, false, false, false, false]
+		);
+		assert_eq!(frames[0].timestamp, std::time::Duration::from_secs(2));
+		assert_eq!(frames[4].timestamp, std::time::Duration::from_millis(2160));
+		assert_eq!(frames[4].duration, std::time::Duration::from_millis(40));
+
+		// the fixture keyframe starts the next group with its single sample
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/chunk_1.m4s"))
+			.await
+			.unwrap();
+		assert_eq!(latest_group(&mut reader, "video").await, Some((1, 0)));
+	}
+
+	/// prft of track 1 produced `secs` after the UNIX epoch
+	fn prft(secs: u32) -> Vec<u8> {
+		let ntp = secs + 2_208_988_800;
+		[
+			&[0, 0, 0, 32][..],
+			b"prft",
+			&[1, 0, 0, 0, 0, 0, 0, 1],
+			&ntp.to_be_bytes(),
+			&[0; 12],
+		]
+		.concat()
+	}
+
+	#[tokio::test]
+	async fn test_prft() {
+		let (mut publisher, mut reader) = publisher();
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/avc_init.m4s"))
+			.await
+			.unwrap();
+
+		let moq_transport::serve::TrackReaderMode::Groups(mut groups) =
+			reader.subscribe("video").unwrap().mode().await.unwrap()
+		else {
+			panic!("expected groups mode");
+		};
+
+		let chunk_1 = include_bytes!("../../tests/fixtures/chunk_1.m4s");
+		let chunk_2 = include_bytes!("../../tests/fixtures/chunk_2.m4s");
+		publish(&mut publisher, 0, &[&prft(1)[..], chunk_1].concat())
+			.await
+			.unwrap();
+
+		// produced in 1970
+		let encoded = publisher.metrics.encode();
+		let line = encoded
+			.lines()
+			.find(|l| l.starts_with("moq_pub_publish_latency_seconds{"))
+			.unwrap();
+		let latency = line.rsplit_once(' ').unwrap().1.parse::<f64>().unwrap();
+		assert!(latency > 1_000_000_000.0, "{line}");
+
+		publish(&mut publisher, 0, chunk_2).await.unwrap();
+		publish(&mut publisher, 0, &prft(2)).await.unwrap();
+
+		let mut group = groups.next().await.unwrap().unwrap();
+		let mut objects = Vec::new();
+		for _ in 0..4 {
+			objects.push(group.read_next().await.unwrap().unwrap());
+		}
+
+		// the prft is sent once, in front of the moof it belongs to
+		assert_eq!(objects[0], [&prft(1)[..], &chunk_1[..96]].concat());
+		assert_eq!(objects[1], chunk_1[96..]);
+		assert_eq!(objects[2], chunk_2[..96]);
+		assert_eq!(objects[3], chunk_2[96..]);
+
+		// a prft without its fragment is not sent
+		assert_eq!(latest_group(&mut reader, "video").await, Some((0, 3)));
+
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/chunk_3.m4s"))
+			.await
+			.unwrap();
+		let mut group = groups.next().await.unwrap().unwrap();
+		let object = group.read_next().await.unwrap().unwrap();
+		assert_eq!(&object[4..8], b"prft");
+	}
+
+	/// prft of track 1 produced `millis` after 2024-01-01T00:00:00Z, version 1 has a 64 bit media time
+	fn prft_at(version: u8, millis: u64, media_time: u64) -> Vec<u8> {
+		let ntp = ((1_704_067_200 + 2_208_988_800 + millis / 1000) << 32) | (((millis % 1000) << 32) / 1000);
+		let media_time = match version {
+			0 => (media_time as u32).to_be_bytes().to_vec(),
+			_ => media_time.to_be_bytes().to_vec(),
+		};
+		let size = 24 + media_time.len() as u32;
+		[
+			&size.to_be_bytes()[..],
+			b"prft",
+			&[version, 0, 0, 0, 0, 0, 0, 1],
+			&ntp.to_be_bytes(),
+			&media_time,
+		]
+		.concat()
+	}
+
+	#[tokio::test]
+	async fn test_timeline_track() {
+		let (mut publisher, mut reader) = publisher();
+		publisher.set_catalog_initial_timeout(std::time::Duration::ZERO);
+		publisher
+			.enable_timeline_track(std::time::Duration::from_secs(2))
+			.unwrap();
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/avc_init.m4s"))
+			.await
+			.unwrap();
+
+		let moq_transport::serve::TrackReaderMode::Groups(mut groups) =
+			reader.subscribe(TIMELINE_TRACK).unwrap().mode().await.unwrap()
+		else {
+			panic!("expected groups mode");
+		};
+
+		// a 64 bit media time
+		let prft = prft_at(1, 500, 1 << 33);
+		assert_eq!(prft.len(), 32);
+		publish(&mut publisher, 0, &[&prft[..], &fragment(0, true, &[100])].concat())
+			.await
+			.unwrap();
+		let mut group = groups.next().await.unwrap().unwrap();
+		let record: serde_json::Value = serde_json::from_slice(&group.read_next().await.unwrap().unwrap()).unwrap();
+		assert_eq!(
+			record,
+			serde_json::json!({
+				"wallclock_rfc3339": "2024-01-01T00:00:00.500Z",
+				"media_time": 1u64 << 33,
+				"timescale": 12800,
+				"rep_id": 0,
+			})
+		);
+
+		// a 32 bit one, in the group started 1.5s earlier
+		let prft = prft_at(0, 2000, 12800);
+		assert_eq!(prft.len(), 28);
+		publish(&mut publisher, 0, &[&prft[..], &fragment(512, false, &[100])].concat())
+			.await
+			.unwrap();
+		let record: TimelineRecord = serde_json::from_slice(&group.read_next().await.unwrap().unwrap()).unwrap();
+		assert_eq!(
+			record,
+			TimelineRecord {
+				wallclock_rfc3339: "2024-01-01T00:00:02.000Z".to_string(),
+				media_time: 12800,
+				timescale: 12800,
+				rep_id: 0,
+			}
+		);
+		assert_eq!(latest_group(&mut reader, TIMELINE_TRACK).await, Some((0, 1)));
+
+		// a new group every 2s of wall clock, or once the clock goes back
+		let cadence = [
+			(2500, (1, 0)),
+			(4000, (1, 1)),
+			(4500, (2, 0)),
+			(1000, (3, 0)),
+			(2000, (3, 1)),
+		];
+		for (i, (millis, expected)) in cadence.into_iter().enumerate() {
+			let ts = 1024 + 512 * i as u64;
+			publish(
+				&mut publisher,
+				0,
+				&[&prft_at(0, millis, ts)[..], &fragment(ts, false, &[100])].concat(),
+			)
+			.await
+			.unwrap();
+			assert_eq!(
+				latest_group(&mut reader, TIMELINE_TRACK).await,
+				Some(expected),
+				"{millis}ms"
+			);
+		}
+
+		// advertised next to the media
+		let catalog = current_catalog(&publisher);
+		let track = catalog.track(TIMELINE_TRACK).unwrap();
+		assert_eq!(track.packaging(), moq_catalog::Packaging::LOC);
+		assert_eq!(track.selection_params().unwrap().mime_type(), Some(TIMELINE_MIME_TYPE));
+	}
+
+	#[tokio::test]
+	async fn test_emsg() {
+		// styp, sidx and emsg in front of the moof and mdat of chunk_1
+		let segment = include_bytes!("../../tests/fixtures/emsg_chunk.m4s");
+		let (emsg, chunk) = (&segment[68..144], &segment[144..]);
+		assert_eq!(&emsg[4..8], b"emsg");
+
+		let (mut publisher, mut reader) = publisher();
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/avc_init.m4s"))
+			.await
+			.unwrap();
+		let moq_transport::serve::TrackReaderMode::Groups(mut groups) =
+			reader.subscribe("video").unwrap().mode().await.unwrap()
+		else {
+			panic!("expected groups mode");
+		};
+		publish(&mut publisher, 0, segment).await.unwrap();
+
+		// sent in-band with the next moof, styp and sidx are dropped
+		let mut group = groups.next().await.unwrap().unwrap();
+		assert_eq!(group.read_next().await.unwrap().unwrap(), [emsg, &chunk[..96]].concat());
+		assert_eq!(group.read_next().await.unwrap().unwrap(), chunk[96..]);
+		assert!(reader.subscribe(EMSG_TRACK).is_none());
+
+		// or on a track of its own, once for all reps
+		let (mut publisher, mut reader) = self::publisher();
+		publisher.enable_emsg_track().unwrap();
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/avc_init.m4s"))
+			.await
+			.unwrap();
+		let moq_transport::serve::TrackReaderMode::Groups(mut groups) =
+			reader.subscribe("video").unwrap().mode().await.unwrap()
+		else {
+			panic!("expected groups mode");
+		};
+		let moq_transport::serve::TrackReaderMode::Groups(mut events) =
+			reader.subscribe(EMSG_TRACK).unwrap().mode().await.unwrap()
+		else {
+			panic!("expected groups mode");
+		};
+		publish(&mut publisher, 0, segment).await.unwrap();
+		publish(&mut publisher, 0, emsg).await.unwrap();
+
+		let mut event = events.next().await.unwrap().unwrap();
+		assert_eq!(event.read_next().await.unwrap().unwrap(), emsg);
+		assert_eq!(latest_group(&mut reader, EMSG_TRACK).await, Some((0, 0)));
+
+		let mut group = groups.next().await.unwrap().unwrap();
+		assert_eq!(group.read_next().await.unwrap().unwrap(), chunk[..96]);
+	}
+
+	/// the encoded object of the latest group of `name`
+	async fn latest_object(reader: &mut moq_transport::serve::TracksReader, name: &str) -> bytes::Bytes {
+		let moq_transport::serve::TrackReaderMode::Groups(mut groups) =
+			reader.subscribe(name).unwrap().mode().await.unwrap()
+		else {
+			panic!("expected groups mode");
+		};
+		let mut group = groups.next().await.unwrap().unwrap();
+		group.read_next().await.unwrap().unwrap()
+	}
+
+	#[tokio::test]
+	async fn test_hierarchical_catalog() {
+		let settings = SETTINGS.replace("bitrate\n", "bitrate\naudio,48000,128000\n");
+		let (mut publisher, mut reader) =
+			with_settings(&settings, Default::default(), Default::default(), Default::default());
+		publisher.set_hierarchical_catalog(true);
+		publisher.set_catalog_initial_timeout(std::time::Duration::ZERO);
+
+		// audio reps come first
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/aac_init.m4s"))
+			.await
+			.unwrap();
+		for rep_id in [1, 2] {
+			publish(
+				&mut publisher,
+				rep_id,
+				include_bytes!("../../tests/fixtures/avc_init.m4s"),
+			)
+			.await
+			.unwrap();
+		}
+
+		// the root lists the catalogs instead of tracks, it only changed once the video arrived
+		let root = moq_catalog::MoqCatalog::decode(&latest_object(&mut reader, ".catalog").await).unwrap();
+		let children: Vec<_> = root.catalogs().iter().map(|catalog| catalog.name()).collect();
+		assert_eq!(children, [".catalog.video", ".catalog.audio"]);
+		assert!(!serde_json::from_slice::<serde_json::Value>(&root.encode().unwrap())
+			.unwrap()
+			.as_object()
+			.unwrap()
+			.contains_key("tracks"));
+		assert_eq!(latest_group(&mut reader, ".catalog").await, Some((1, 0)));
+		assert_eq!(latest_group(&mut reader, ".catalog.video").await, Some((1, 0)));
+		assert_eq!(latest_group(&mut reader, ".catalog.audio").await, Some((0, 0)));
+
+		// the catalogs of the media types hold all tracks
+		let mut tracks = Vec::new();
+		for child in children {
+			let catalog = moq_catalog::MoqCatalog::decode(&latest_object(&mut reader, child).await).unwrap();
+			assert!(catalog.catalogs().is_empty());
+			assert!(catalog.common_track_fields().is_some());
+			tracks.extend(catalog.tracks().iter().map(|track| track.name().to_string()));
+		}
+		assert_eq!(tracks, ["video", "video_low", "audio"]);
+
+		let mut all: Vec<_> = current_catalog(&publisher)
+			.tracks()
+			.iter()
+			.map(|track| track.name().to_string())
+			.collect();
+		all.sort();
+		tracks.sort();
+		assert_eq!(tracks, all);
+	}
+
+	#[tokio::test]
+	async fn test_hierarchical_data_tracks() {
+		let dir = crate::testing::temp_dir("publisher-data");
+		let path = dir.join("markers.jsonl");
+		std::fs::write(&path, "").unwrap();
+
+		let (mut publisher, mut reader) = publisher();
+		publisher.set_hierarchical_catalog(true);
+		publisher.set_catalog_initial_timeout(std::time::Duration::ZERO);
+		publisher.enable_metadata(path, None).unwrap();
+		publisher.enable_timeline_track(TIMELINE_GROUP_DURATION).unwrap();
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/avc_init.m4s"))
+			.await
+			.unwrap();
+
+		// neither audio nor video, listed by a catalog of their own
+		let root = moq_catalog::MoqCatalog::decode(&latest_object(&mut reader, ".catalog").await).unwrap();
+		let children: Vec<_> = root.catalogs().iter().map(|catalog| catalog.name()).collect();
+		assert_eq!(children, [".catalog.video", ".catalog.data"]);
+
+		let data = moq_catalog::MoqCatalog::decode(&latest_object(&mut reader, ".catalog.data").await).unwrap();
+		let names: Vec<_> = data.tracks().iter().map(|track| track.name()).collect();
+		assert_eq!(names, [metadata::METADATA_TRACK, TIMELINE_TRACK]);
+
+		let video = moq_catalog::MoqCatalog::decode(&latest_object(&mut reader, ".catalog.video").await).unwrap();
+		let names: Vec<_> = video.tracks().iter().map(|track| track.name()).collect();
+		assert_eq!(names, ["video"]);
+
+		publisher.close().await;
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[tokio::test]
+	async fn test_catalog_track() {
+		let (mut publisher, mut reader) = publisher();
+		publisher.set_catalog_track("catalog").unwrap();
+		publisher.set_catalog_initial_timeout(std::time::Duration::ZERO);
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/avc_init.m4s"))
+			.await
+			.unwrap();
+
+		assert_eq!(latest_group(&mut reader, "catalog").await, Some((0, 0)));
+		assert!(reader.subscribe(CATALOG_TRACK).is_none());
+
+		// a catalog listing both tracks and catalogs is never published
+		let mut broadcast = publisher.broadcast.lock().unwrap();
+		let mut both: moq_catalog::MoqCatalog = serde_json::from_value(serde_json::json!({
+			"version": "1",
+			"streamingFormat": "1",
+			"streamingFormatVersion": "1",
+			"tracks": [],
+			"catalogs": [{ "streamingFormat": "1", "streamingFormatVersion": "1", "name": "sports" }],
+		}))
+		.unwrap();
+		std::mem::swap(&mut broadcast.catalog, &mut both);
+		assert!(matches!(
+			broadcast.publish_catalog(),
+			Err(Error::Catalog(moq_catalog::Error::BothTracksAndCatalogs))
+		));
+	}
+
+	#[tokio::test]
+	async fn test_init_tracks() {
+		let init = include_bytes!("../../tests/fixtures/avc_init.m4s");
+
+		let (mut publisher, _reader) = publisher();
+		publish(&mut publisher, 0, init).await.unwrap();
+		let inline = current_catalog(&publisher).encode().unwrap();
+
+		let (mut publisher, mut reader) = self::publisher();
+		publisher.set_init_tracks(true);
+		publish(&mut publisher, 0, init).await.unwrap();
+		let catalog = current_catalog(&publisher);
+		assert!(catalog.encode().unwrap().len() < inline.len() - init.len());
+
+		// referenced by the media track, not a track of the catalog itself
+		let track = catalog_track(&publisher);
+		assert_eq!(track["initTrack"], "video_init");
+		assert!(track.get("initData").is_none());
+		assert_eq!(catalog.tracks().len(), 1);
+
+		let moq_transport::serve::TrackReaderMode::Groups(mut groups) =
+			reader.subscribe("video_init").unwrap().mode().await.unwrap()
+		else {
+			panic!("expected groups mode");
+		};
+		let mut group = groups.next().await.unwrap().unwrap();
+		assert_eq!(group.read_next().await.unwrap().unwrap(), init[..]);
+
+		// a restarted encoder appends its init segment
+		let restarted = include_bytes!("../../tests/fixtures/hev1_init.m4s");
+		publish(&mut publisher, 0, restarted).await.unwrap();
+		let mut group = groups.next().await.unwrap().unwrap();
+		assert_eq!(group.group_id, 1);
+		assert_eq!(group.read_next().await.unwrap().unwrap(), restarted[..]);
+		assert_eq!(catalog_track(&publisher)["initTrack"], "video_init");
+	}
+
+	#[tokio::test]
+	async fn test_republish() {
+		let init = include_bytes!("../../tests/fixtures/avc_init.m4s");
+		let (mut publisher, mut reader) = publisher();
+		publisher.set_catalog_initial_timeout(std::time::Duration::ZERO);
+		publisher.set_init_tracks(true);
+		publisher.set_republish_interval(std::time::Duration::from_millis(100));
+		publish(&mut publisher, 0, init).await.unwrap();
+
+		let subscribe = |reader: &mut moq_transport::serve::TracksReader, name: &str| {
+			let track = reader.subscribe(name).unwrap();
+			async move {
+				match track.mode().await.unwrap() {
+					moq_transport::serve::TrackReaderMode::Groups(groups) => groups,
+					_ => panic!("expected groups mode"),
+				}
+			}
+		};
+		let mut catalogs = subscribe(&mut reader, ".catalog").await;
+		let mut inits = subscribe(&mut reader, "video_init").await;
+
+		// the first group and two intervals of re-published ones, the newest group every time
+		let mut watcher = moq_catalog::CatalogWatcher::new();
+		let mut previous: Option<bytes::Bytes> = None;
+		for group_id in 0..3 {
+			let next = tokio::time::timeout(std::time::Duration::from_secs(5), catalogs.next());
+			let mut group = next.await.expect("no catalog re-published").unwrap().unwrap();
+			assert_eq!(group.group_id, group_id);
+			let object = group.read_next().await.unwrap().unwrap();
+			if let Some(previous) = &previous {
+				assert_eq!(&object, previous);
+			}
+
+			// only the first one is news to a subscriber
+			assert_eq!(watcher.update(&object).unwrap().is_empty(), group_id > 0);
+			previous = Some(object);
+
+			let mut group = inits.next().await.unwrap().unwrap();
+			assert_eq!(group.group_id, group_id);
+			assert_eq!(group.read_next().await.unwrap().unwrap(), init[..]);
+		}
+		assert_eq!(publisher.catalog_snapshot(), previous);
+
+		for track in [".catalog", "video_init"] {
+			let name = format!("moq_pub_republished_groups_total{{track=\"{track}\"}}");
+			assert!(counter(&publisher, &name) >= 2, "{track}");
+		}
+
+		// a change is the next version, in the next group
+		publish(&mut publisher, 1, init).await.unwrap();
+		let mut group = catalogs.next().await.unwrap().unwrap();
+		let object = group.read_next().await.unwrap().unwrap();
+		assert!(group.group_id >= 3);
+		assert_eq!(moq_catalog::MoqCatalog::decode(&object).unwrap().tracks().len(), 2);
+	}
+
+	/// the objects of the group, once it is finished
+	async fn objects(group: &mut moq_transport::serve::GroupReader) -> Vec<bytes::Bytes> {
+		let mut objects = Vec::new();
+		while let Some(object) = group.read_next().await.unwrap() {
+			objects.push(object);
+		}
+		objects
+	}
+
+	#[tokio::test]
+	async fn test_max_object_size() {
+		let (mut publisher, mut reader) = self::publisher();
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/avc_init.m4s"))
+			.await
+			.unwrap();
+		let moq_transport::serve::TrackReaderMode::Groups(mut groups) =
+			reader.subscribe("video").unwrap().mode().await.unwrap()
+		else {
+			panic!("expected groups mode");
+		};
+
+		// the moof stays whole, the 3 MiB mdat is cut
+		let chunk = fragment(0, true, &[3 << 20]);
+		publish(&mut publisher, 0, &chunk).await.unwrap();
+		let mut group = groups.next().await.unwrap().unwrap();
+		publish(&mut publisher, 0, &fragment(12800, true, &[10])).await.unwrap();
+
+		let objects = objects(&mut group).await;
+		let sizes: Vec<_> = objects.iter().map(bytes::Bytes::len).collect();
+		assert_eq!(sizes, [chunk.len() - (3 << 20) - 8, 1 << 20, 1 << 20, 1 << 20, 8]);
+		assert_eq!(objects.concat(), chunk);
+	}
+
+	#[tokio::test]
+	async fn test_large_init() {
+		// a moov with 3 MiB of extradata, skipped by the parser
+		let init = include_bytes!("../../tests/fixtures/avc_init.m4s");
+		let (ftyp, moov) = init.split_at(24);
+		let mut large = ftyp.to_vec();
+		large.extend_from_slice(&(moov.len() as u32 + (3 << 20) + 8).to_be_bytes());
+		large.extend_from_slice(&moov[4..]);
+		large.extend_from_slice(&((3 << 20) + 8u32).to_be_bytes());
+		large.extend_from_slice(b"free");
+		large.resize(large.len() + (3 << 20), 0);
+
+		let (mut publisher, mut reader) = self::publisher();
+		publish(&mut publisher, 0, &large).await.unwrap();
+
+		// too large to inline in the catalog
+		let track = catalog_track(&publisher);
+		assert_eq!(track["initTrack"], "video_init");
+		assert!(track.get("initData").is_none());
+
+		let moq_transport::serve::TrackReaderMode::Groups(mut groups) =
+			reader.subscribe("video_init").unwrap().mode().await.unwrap()
+		else {
+			panic!("expected groups mode");
+		};
+		let mut group = groups.next().await.unwrap().unwrap();
+		let objects = objects(&mut group).await;
+		assert_eq!(objects.len(), 5);
+		assert_eq!(objects[0], ftyp);
+		assert!(objects.iter().all(|object| object.len() <= MAX_OBJECT_SIZE));
+		assert_eq!(objects.concat(), large);
+	}
+
+	/// priorities of the groups started by keyframes at `millis`
+	async fn priorities(group_order: GroupOrder, millis: &[u64]) -> Vec<u64> {
+		let (mut publisher, mut reader) = configured(Default::default(), group_order, Default::default());
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/avc_init.m4s"))
+			.await
+			.unwrap();
+
+		let moq_transport::serve::TrackReaderMode::Groups(mut groups) =
+			reader.subscribe("video").unwrap().mode().await.unwrap()
+		else {
+			panic!("expected groups mode");
+		};
+
+		let mut priorities = Vec::new();
+		for millis in millis {
+			publish(&mut publisher, 0, &fragment(millis * 128 / 10, true, &[10]))
+				.await
+				.unwrap();
+			priorities.push(groups.next().await.unwrap().unwrap().priority);
+		}
+
+		priorities
+	}
+
+	#[tokio::test]
+	async fn test_group_order() {
+		// past the u32::MAX milliseconds that used to kill the publisher after 49 days
+		let millis = [u32::MAX as u64 + 2000, u32::MAX as u64 + 4000, u32::MAX as u64 + 6000];
+		let wrapped = millis.map(|millis| millis & MAX_ORDER_PRIORITY);
+
+		// above the base priority of the video tracks
+		let video = ((u8::MAX - VIDEO_PRIORITY) as u64) << BASE_PRIORITY_SHIFT;
+
+		let newest = priorities(GroupOrder::NewestFirst, &millis).await;
+		assert_eq!(newest, wrapped.map(|millis| video + millis));
+		assert!(newest.windows(2).all(|w| w[0] < w[1]));
+
+		let oldest = priorities(GroupOrder::OldestFirst, &millis).await;
+		assert_eq!(oldest[1], video + MAX_ORDER_PRIORITY - wrapped[1]);
+		assert!(oldest.windows(2).all(|w| w[0] > w[1]));
+		assert_eq!(
+			priorities(GroupOrder::Sequential, &millis).await,
+			[0, 1, 2].map(|sequence| video + MAX_ORDER_PRIORITY - sequence)
+		);
+
+		assert_eq!("sequential".parse::<GroupOrder>().unwrap(), GroupOrder::Sequential);
+		assert!("newest".parse::<GroupOrder>().is_err());
+	}
+
+	/// the QUIC stream priority the transport sets for a group, higher is sent first
+	fn stream(priority: u64) -> i32 {
+		priority as i32
+	}
+
+	#[test]
+	fn test_base_priority() {
+		// from the epoch to past the largest media time a group order can tell apart
+		let millis = [0, 1, 20, 1_999, 2_000, u32::MAX as u64, MAX_ORDER_PRIORITY, u64::MAX];
+		for order in [GroupOrder::NewestFirst, GroupOrder::OldestFirst, GroupOrder::Sequential] {
+			for (sequence, audio) in millis.iter().enumerate() {
+				for video in millis {
+					let priority = |millis: u64, base| {
+						order.priority(std::time::Duration::from_millis(millis), sequence as u64, base)
+					};
+					let (audio, video) = (priority(*audio, AUDIO_PRIORITY), priority(video, VIDEO_PRIORITY));
+					assert!(
+						stream(audio) > stream(video),
+						"{order:?}: audio {audio} after video {video}"
+					);
+				}
+			}
+		}
+
+		// the groups of a track in flight at once keep their order on the streams, also past the wrap of the order
+		for start in [0, 1 << 23, MAX_ORDER_PRIORITY - 2_000, u32::MAX as u64] {
+			let newest = |millis| {
+				let priority =
+					GroupOrder::NewestFirst.priority(std::time::Duration::from_millis(millis), 0, VIDEO_PRIORITY);
+				stream(priority)
+			};
+			for (older, newer) in [(start, start + 1), (start + 2_000, start + 4_000)] {
+				if newer & MAX_ORDER_PRIORITY > older & MAX_ORDER_PRIORITY {
+					assert!(newest(newer) > newest(older), "{older} sent before {newer}");
+				}
+			}
+		}
+
+		// the group order applies within a base priority
+		let newest = |millis| GroupOrder::NewestFirst.priority(std::time::Duration::from_millis(millis), 0, 3);
+		assert!(newest(2_000) > newest(1_000));
+		assert_eq!(newest(0) >> BASE_PRIORITY_SHIFT, (u8::MAX - 3) as u64);
+
+		// the whole range is a positive stream priority
+		assert_eq!(
+			GroupOrder::OldestFirst.priority(Default::default(), 0, 0),
+			i32::MAX as u64
+		);
+		assert_eq!(GroupOrder::NewestFirst.priority(Default::default(), 0, u8::MAX), 0);
+	}
+
+	#[tokio::test]
+	async fn test_priorities() {
+		let settings = SETTINGS.replace("name,sampling,bitrate", "name,sampling,bitrate\naudio,44100,128000");
+		let (mut publisher, mut reader) =
+			with_settings(&settings, Default::default(), Default::default(), Default::default());
+		publisher.set_priorities(5, 2);
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/aac_init.m4s"))
+			.await
+			.unwrap();
+		publish(&mut publisher, 1, include_bytes!("../../tests/fixtures/avc_init.m4s"))
+			.await
+			.unwrap();
+
+		// advertised in the catalog
+		let catalog: serde_json::Value =
+			serde_json::from_slice(&current_catalog(&publisher).encode().unwrap()).unwrap();
+		let priorities: Vec<_> = catalog["tracks"]
+			.as_array()
+			.unwrap()
+			.iter()
+			.map(|track| (track["name"].clone(), track["selectionParams"]["priority"].clone()))
+			.collect();
+		assert_eq!(priorities, [("audio".into(), 5.into()), ("video".into(), 2.into())]);
+
+		// the groups of the video come first now
+		let moq_transport::serve::TrackReaderMode::Groups(mut groups) =
+			reader.subscribe("video").unwrap().mode().await.unwrap()
+		else {
+			panic!("expected groups mode");
+		};
+		publish(&mut publisher, 1, &fragment(0, true, &[10])).await.unwrap();
+		let priority = groups.next().await.unwrap().unwrap().priority;
+		assert_eq!(priority, ((u8::MAX - 2) as u64) << BASE_PRIORITY_SHIFT);
+	}
+
+	/// publish the fixture chunks as one segment, then read `counts` objects from the video groups
+	async fn segment_objects(
+		publisher: &mut Publisher,
+		reader: &mut moq_transport::serve::TracksReader,
+		counts: &[usize],
+	) -> Vec<Vec<bytes::Bytes>> {
+		let moq_transport::serve::TrackReaderMode::Groups(mut groups) =
+			reader.subscribe("video").unwrap().mode().await.unwrap()
+		else {
+			panic!("expected groups mode");
+		};
+
+		// the keyframes start a group each, taken before the next one replaces it
+		let mut started = Vec::new();
+		for (chunk, keyframe) in [
+			(&include_bytes!("../../tests/fixtures/chunk_1.m4s")[..], true),
+			(include_bytes!("../../tests/fixtures/chunk_2.m4s"), false),
+			(include_bytes!("../../tests/fixtures/chunk_3.m4s"), true),
+		] {
+			publish(publisher, 0, chunk).await.unwrap();
+			if keyframe {
+				started.push(groups.next().await.unwrap().unwrap());
+			}
+		}
+		publisher.end_segment(0).unwrap();
+		publisher.flush().await.unwrap();
+
+		let mut objects = Vec::new();
+		for (mut group, count) in started.into_iter().zip(counts) {
+			let mut group_objects = Vec::new();
+			for _ in 0..*count {
+				group_objects.push(group.read_next().await.unwrap().unwrap());
+			}
+			objects.push(group_objects);
+		}
+
+		objects
+	}
+
+	#[tokio::test]
+	async fn test_object_mode() {
+		let chunk_1 = include_bytes!("../../tests/fixtures/chunk_1.m4s");
+		let chunk_2 = include_bytes!("../../tests/fixtures/chunk_2.m4s");
+		let chunk_3 = include_bytes!("../../tests/fixtures/chunk_3.m4s");
+
+		// one object per moof, holding the whole chunk
+		let (mut publisher, mut reader) = configured(Default::default(), Default::default(), ObjectMode::PerChunk);
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/avc_init.m4s"))
+			.await
+			.unwrap();
+		let objects = segment_objects(&mut publisher, &mut reader, &[2, 1]).await;
+		assert_eq!(objects[0], [&chunk_1[..], &chunk_2[..]]);
+		assert_eq!(objects[1], [&chunk_3[..]]);
+		assert_eq!(latest_group(&mut reader, "video").await, Some((1, 0)));
+
+		// the first segment is cut by the keyframe, the second by its end
+		let (mut publisher, mut reader) = configured(Default::default(), Default::default(), ObjectMode::PerSegment);
+		publish(&mut publisher, 0, include_bytes!("../../tests/fixtures/avc_init.m4s"))
+			.await
+			.unwrap();
+		let objects = segment_objects(&mut publisher, &mut reader, &[1, 1]).await;
+		assert_eq!(objects[0], [[&chunk_1[..], &chunk_2[..]].concat()]);
+		assert_eq!(objects[1], [&chunk_3[..]]);
+		assert_eq!(latest_group(&mut reader, "video").await, Some((1, 0)));
+
+		assert_eq!("per-chunk".parse::<ObjectMode>().unwrap(), ObjectMode::PerChunk);
+		assert!("chunk".parse::<ObjectMode>().is_err());
+	}
+
+	#[tokio::test]
+	async fn test_stream_mode() {
+		let settings = SETTINGS
+			.replace("buffer_size\n", "buffer_size,mode\n")
+			.replace("12000000\n", "12000000,objects\n")
+			.replace("6000000\n", "6000000,\n");
+		let (mut publisher, mut reader) = with_settings(
+			&settings,
+			moq_catalog::Packaging::LOC,
+			Default::default(),
+			Default::default(),
+		);
+		for rep_id in [0, 1] {
+			publish(
+				&mut publisher,
+				rep_id,
+				include_bytes!("../../tests/fixtures/avc_init.m4s"),
+			)
+			.await
+			.unwrap();
+			publish(&mut publisher, rep_id, &fragment(25600, true, &[10, 20, 30]))
+				.await
+				.unwrap();
+		}
+
+		let moq_transport::serve::TrackReaderMode::Objects(objects) =
+			reader.subscribe("video").unwrap().mode().await.unwrap()
+		else {
+			panic!("expected objects mode");
+		};
+		assert_eq!(objects.latest(), Some((0, 2)));
+		// groups without a mode
+		assert_eq!(latest_group(&mut reader, "video_low").await, Some((0, 2)));
+
+		// a changed mode continues on a new track
+		let settings = crate::dash::Settings::from_bytes(
+			settings.replace(",objects\n", ",datagrams\n").into_bytes(),
+			"input".into(),
+			"output".into(),
+			false,
+			false,
+		)
+		.unwrap();
+		publisher.reload(settings).await.unwrap();
+		publish(&mut publisher, 0, &fragment(27136, false, &[5, 5]))
+			.await
+			.unwrap();
+
+		let moq_transport::serve::TrackReaderMode::Datagrams(datagrams) =
+			reader.subscribe("video").unwrap().mode().await.unwrap()
+		else {
+			panic!("expected datagrams mode");
+		};
+		assert_eq!(datagrams.latest(), Some((0, 1)));
+	}
+
+	#[test]
+	fn test_track_closed() {
+		// the broadcast dropped the track before the rep started writing it
+		let (writer, reader) = moq_transport::serve::Track::new("test".to_string(), "video".to_string()).produce();
+		drop(reader);
+
+		let track = Track::new(
+			writer,
+			mp4::TrackType::Video,
+			90000,
+			Default::default(),
+			Default::default(),
+			StreamMode::Objects,
+			crate::metrics::Metrics::default().track("video"),
+		);
+		assert!(matches!(
+			track,
+			Err(Error::Transport(moq_transport::serve::ServeError::Cancel))
+		));
+	}
+
+	#[tokio::test]
+	async fn test_alignment() {
+		let (mut publisher, _reader) = publisher();
+		publisher.set_strict_alignment(true);
+		for rep_id in [0, 1] {
+			publish(
+				&mut publisher,
+				rep_id,
+				include_bytes!("../../tests/fixtures/avc_init.m4s"),
+			)
+			.await
+			.unwrap();
+		}
+
+		// the keyframe of video_low at 4s is a frame late, at 25 fps
+		for (rep_id, millis) in [(0, 0), (1, 0), (0, 2000), (1, 2000), (0, 4000)] {
+			publish(&mut publisher, rep_id, &fragment(millis * 128 / 10, true, &[10]))
+				.await
+				.unwrap();
+		}
+		let err = publish(&mut publisher, 1, &fragment(4040 * 128 / 10, true, &[10]))
+			.await
+			.unwrap_err();
+		assert!(matches!(err.root(), Error::Misaligned(0, t) if t.as_millis() == 4000));
+	}
+
+	/// a backlog on one rep does not delay the others, the order within a rep is kept
+	#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+	async fn test_independent_reps() {
+		const BACKLOG: usize = 20_000;
+
+		let ladder: String = (0..6)
+			.map(|i| format!("video_{i},1280x720,3000000,3000000,6000000\n"))
+			.collect();
+		let settings = crate::dash::Settings::from_bytes(
+			SETTINGS
+				.replace("video,1920x1080,6000000,6000000,12000000\n", &ladder)
+				.replace("video_low,1280x720,3000000,3000000,6000000\n", "")
+				.into_bytes(),
+			"input".into(),
+			"output".into(),
+			true,
+			false,
+		)
+		.unwrap();
+		let (writer, _, mut reader) = moq_transport::serve::Tracks::new("test".to_string()).produce();
+		let mut publisher = Publisher::new(
+			writer,
+			settings,
+			Default::default(),
+			Default::default(),
+			Default::default(),
+			Default::default(),
+		)
+		.unwrap();
+
+		let init = include_bytes!("../../tests/fixtures/avc_init.m4s");
+		let chunk = include_bytes!("../../tests/fixtures/chunk_1.m4s");
+		for rep_id in 0..6 {
+			publish(&mut publisher, rep_id, init).await.unwrap();
+		}
+
+		let moq_transport::serve::TrackReaderMode::Groups(mut groups) =
+			reader.subscribe("video_5").unwrap().mode().await.unwrap()
+		else {
+			panic!("expected groups mode");
+		};
+
+		// every fragment starts with a keyframe and thus a group
+		publisher.publish(0, chunk.repeat(BACKLOG).into()).unwrap();
+		publisher.publish(5, bytes::Bytes::from_static(chunk)).unwrap();
+
+		// video_5 gets its group while video_0 is still working through its backlog
+		groups.next().await.unwrap().unwrap();
+		let backlog = latest_group(&mut reader, "video_0").await;
+		assert!(backlog < Some((BACKLOG as u64 - 1, 1)), "{backlog:?}");
+
+		publisher.flush().await.unwrap();
+		assert_eq!(
+			latest_group(&mut reader, "video_0").await,
+			Some((BACKLOG as u64 - 1, 1))
+		);
+	}
 }
@@ -0,0 +1,151 @@
+use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
+use std::time;
+
+use super::{connect, ConnectOptions, Error};
+
+/// the backoff stops doubling here
+const MAX_BACKOFF: time::Duration = time::Duration::from_secs(30);
+
+/// time the relay gets on shutdown to receive the end of the tracks and the unannounce
+pub(crate) const GRACE_PERIOD: time::Duration = time::Duration::from_secs(2);
+
+/// how often and how fast the relay is re-dialed after the session dropped
+#[derive(Debug, Clone)]
+pub struct Reconnect {
+	/// consecutive failed attempts before giving up, 0 to never reconnect
+	pub max_attempts: u32,
+	/// delay before the first attempt, doubled with every failed one
+	pub backoff: time::Duration,
+}
+
+impl Default for Reconnect {
+	fn default() -> Self {
+		Self {
+			max_attempts: 5,
+			backoff: time::Duration::from_secs(1),
+		}
+	}
+}
+
+impl Reconnect {
+	/// delay before the `attempt`-th consecutive attempt, starting at 1
+	pub fn delay(&self, attempt: u32) -> time::Duration {
+		let factor = 1u32.checked_shl(attempt.saturating_sub(1)).unwrap_or(u32::MAX);
+		self.backoff.saturating_mul(factor).min(MAX_BACKOFF.max(self.backoff))
+	}
+}
+
+/// Keep `reader` announced on the relay of `options`, re-dialing with backoff whenever the session drops.
+///
+/// The tracks outlive the sessions, subscribers of a new session continue from the newest group.
+/// Fails if the first connection fails, or once `reconnect.max_attempts` consecutive attempts did.
+/// Once `shutdown` is notified the end of the tracks is served and the broadcast unannounced.
+pub async fn announce(
+	options: &ConnectOptions,
+	reader: moq_transport::serve::TracksReader,
+	reconnect: &Reconnect,
+	shutdown: &tokio::sync::Notify,
+) -> Result<(), Error> {
+	announce_all(options, vec![reader], reconnect, shutdown).await
+}
+
+/// Like [announce], for several broadcasts on a single session, each under the namespace of its reader.
+///
+/// The namespace of `options` is not used. Once the relay ends any of the announces, the session is re-dialed.
+pub async fn announce_all(
+	options: &ConnectOptions,
+	readers: Vec<moq_transport::serve::TracksReader>,
+	reconnect: &Reconnect,
+	shutdown: &tokio::sync::Notify,
+) -> Result<(), Error> {
+	let mut connected = false;
+	let mut attempt = 0;
+
+	loop {
+		let res = tokio::select! {
+			res = connect(options) => res,
+			_ = shutdown.notified() => return Ok(()),
+		};
+
+		let err = match res {
+			Ok((session, publisher)) => {
+				if connected {
+					log::info!("reconnected to relay: url={}", options.url);
+				}
+				connected = true;
+				attempt = 0;
+
+				let mut session = Box::pin(session.run().fuse());
+				let mut announces: FuturesUnordered<_> = readers
+					.iter()
+					.map(|reader| {
+						let mut publisher = publisher.clone();
+						let reader = reader.clone();
+						async move { publisher.announce(reader).await }
+					})
+					.collect();
+				let mut announce = Box::pin(async move { announces.next().await.unwrap_or(Ok(())) }.fuse());
+
+				let res = tokio::select! {
+					res = &mut session => res,
+					res = &mut announce => res,
+					_ = shutdown.notified() => {
+						// serve the end of the tracks, then unannounce, neither may hang on a stuck relay
+						let flush = async {
+							tokio::select! {
+								_ = &mut session => (),
+								_ = &mut announce => (),
+							}
+						};
+						let _ = tokio::time::timeout(GRACE_PERIOD / 2, flush).await;
+
+						drop(announce);
+						let _ = tokio::time::timeout(GRACE_PERIOD / 2, &mut session).await;
+						return Ok(());
+					}
+				};
+
+				match res {
+					Ok(()) => Error::Transport(moq_transport::serve::ServeError::Done),
+					Err(e) => Error::Session(e),
+				}
+			}
+			Err(e) if !connected => return Err(e),
+			Err(e) => e,
+		};
+
+		attempt += 1;
+		if attempt > reconnect.max_attempts {
+			tracing::error!(error = %err, attempts = reconnect.max_attempts, "relay unreachable, giving up");
+			return Err(err);
+		}
+
+		let delay = reconnect.delay(attempt);
+		log::warn!(
+			"lost the relay: {err}, reconnecting ({attempt}/{}) in {delay:?}",
+			reconnect.max_attempts
+		);
+
+		tokio::select! {
+			_ = tokio::time::sleep(delay) => (),
+			_ = shutdown.notified() => return Ok(()),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_delay() {
+		let reconnect = Reconnect {
+			max_attempts: 10,
+			backoff: time::Duration::from_millis(500),
+		};
+
+		let delays: Vec<_> = (1..=8).map(|a| reconnect.delay(a).as_millis()).collect();
+		assert_eq!(delays, [500, 1000, 2000, 4000, 8000, 16000, 30000, 30000]);
+		assert_eq!(reconnect.delay(100), MAX_BACKOFF);
+	}
+}
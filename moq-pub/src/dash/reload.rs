@@ -0,0 +1,144 @@
+use notify::Watcher;
+use std::{path, time};
+use tokio::sync::{mpsc, oneshot};
+
+use super::{helper, Error, Settings};
+
+/// the settings file is read once no event arrived for this long, editors write in several steps
+const DEBOUNCE: time::Duration = time::Duration::from_millis(500);
+
+/// changed settings and where the result of applying them is sent
+pub(crate) type Request = (Settings<path::PathBuf>, oneshot::Sender<Result<(), Error>>);
+
+/// Hands changed settings to a running [super::DashPublisher].
+#[derive(Clone)]
+pub struct Reloader {
+	requests: mpsc::UnboundedSender<Request>,
+}
+
+impl Reloader {
+	pub(crate) fn new(requests: mpsc::UnboundedSender<Request>) -> Self {
+		Self { requests }
+	}
+
+	/// continue publishing with `settings`, once the segments written so far are published
+	///
+	/// Resolves once the publisher applied them, the publisher has to be running.
+	pub async fn reload(&self, settings: Settings<path::PathBuf>) -> Result<(), Error> {
+		let (tx, rx) = oneshot::channel();
+		if self.requests.send((settings, tx)).is_err() {
+			tracing::error!("publisher gone");
+			return Err(Error::Missing);
+		}
+
+		match rx.await {
+			Ok(res) => res,
+			Err(_) => {
+				tracing::error!("publisher gone");
+				Err(Error::Missing)
+			}
+		}
+	}
+}
+
+/// Watches a settings file and yields its valid, changed versions.
+pub struct SettingsWatcher {
+	path: path::PathBuf,
+	current: Settings<path::PathBuf>,
+	events: helper::Events,
+	_watcher: Box<dyn Watcher + Send>,
+}
+
+impl SettingsWatcher {
+	/// watch `path`, the settings `current` were read from, polling every `poll_interval` instead of using inotify
+	///
+	/// The directory is watched, as editors often replace the file instead of writing to it.
+	pub fn new(
+		current: Settings<path::PathBuf>,
+		path: path::PathBuf,
+		poll_interval: Option<time::Duration>,
+	) -> Result<Self, Error> {
+		let (watcher, events) = helper::watch_file(&path, poll_interval)?;
+
+		Ok(Self {
+			path,
+			current,
+			events,
+			_watcher: watcher,
+		})
+	}
+
+	/// the next version of the settings file differing from the current one
+	///
+	/// Invalid versions are logged and skipped, the file is read once it was left alone for [DEBOUNCE].
+	pub async fn next(&mut self) -> Result<Settings<path::PathBuf>, Error> {
+		loop {
+			self.changed().await?;
+			while let Ok(res) = tokio::time::timeout(DEBOUNCE, self.changed()).await {
+				res?;
+			}
+
+			let settings = match self.current.reload(&self.path) {
+				Ok(s) => s,
+				Err(e) => {
+					tracing::warn!(path = %self.path.display(), error = %e, "ignoring the invalid settings");
+					continue;
+				}
+			};
+			if settings.file() == self.current.file() {
+				continue;
+			}
+
+			tracing::info!(path = %self.path.display(), "settings changed");
+			self.current = settings.clone();
+			return Ok(settings);
+		}
+	}
+
+	/// wait for an event modifying the settings file
+	async fn changed(&mut self) -> Result<(), Error> {
+		helper::changed(&mut self.events, &self.path).await
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const SETTINGS: &str = "gop_num=1
+fps=25
+target_segment_duration=2.0
+===AUDIO===
+name,sampling,bitrate
+===VIDEO===
+name,resolution,bitrate,max_rate,buffer_size
+video,1280x720,3000000,3000000,6000000
+";
+
+	#[tokio::test]
+	async fn test_next() {
+		let dir = crate::testing::temp_dir("reload");
+		let path = dir.join("settings.txt");
+		std::fs::write(&path, SETTINGS).unwrap();
+
+		let current = Settings::new(path.clone(), "input".into(), "output".into(), true, false).unwrap();
+		let mut watcher = SettingsWatcher::new(current, path.clone(), None).unwrap();
+
+		// unchanged, invalid, then changed in two writes
+		std::fs::write(&path, SETTINGS).unwrap();
+		tokio::time::sleep(DEBOUNCE * 2).await;
+		std::fs::write(&path, SETTINGS.replace("fps=25", "fps=0")).unwrap();
+		tokio::time::sleep(DEBOUNCE * 2).await;
+		std::fs::write(&path, SETTINGS.replace("3000000,", "2000000,")).unwrap();
+		std::fs::write(&path, SETTINGS.replace("3000000,", "1000000,")).unwrap();
+
+		let settings = tokio::time::timeout(time::Duration::from_secs(10), watcher.next())
+			.await
+			.unwrap()
+			.unwrap();
+		assert_eq!(settings.video[0].bitrate, 1000000);
+		assert_eq!(settings.video[0].max_rate, 1000000);
+
+		let _ = std::fs::remove_dir_all(&dir);
+	}
+}
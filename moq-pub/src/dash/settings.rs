@@ -1,9 +1,320 @@
 use bytes::Buf;
 
 use super::{helper, Error};
+use crate::mode::StreamMode;
+use moq_catalog::AAC_SAMPLING_RATES;
 
 const INPUT_DEFAULT: &str = "/dev/video0";
 
+/// the ffmpeg input of the laptop camera, which `--input /dev/video0` stands for on every platform
+#[cfg(not(any(target_os = "macos", windows)))]
+const CAMERA: &str = "/dev/video0";
+#[cfg(target_os = "macos")]
+const CAMERA: &str = "0";
+/// the names `ffmpeg -list_devices true -f dshow -i dummy` lists on most laptops
+#[cfg(windows)]
+const CAMERA: &str = "video=Integrated Camera";
+
+/// URL schemes of live network inputs
+const NETWORK_SCHEMES: [&str; 4] = ["rtmp", "srt", "udp", "rtsp"];
+
+/// samples of an AAC frame, the audio is segmented at their boundaries
+const AAC_FRAME: u64 = 1024;
+
+/// The contents of a JSON or YAML settings file.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SettingsFile {
+	pub gop_num: u64,
+	pub fps: u64,
+	pub target_segment_duration: f64,
+	#[serde(default)]
+	pub audio: Vec<AudioSetting>,
+	/// empty for audio-only broadcasts
+	#[serde(default)]
+	pub video: Vec<VideoSetting>,
+	#[serde(flatten)]
+	pub extra: ExtraArgs,
+}
+
+impl SettingsFile {
+	/// the contents of a CSV settings file, the format [Settings::from_bytes] reads
+	pub fn to_csv(&self) -> Result<String, Error> {
+		let mut csv = format!(
+			"gop_num={}\nfps={}\ntarget_segment_duration={:?}\n",
+			self.gop_num, self.fps, self.target_segment_duration
+		);
+		for (key, args) in self.extra.sections() {
+			if args.is_empty() {
+				continue;
+			}
+			// the values are split at whitespace when read
+			if let Some(arg) = args
+				.iter()
+				.find(|arg| arg.is_empty() || arg.contains(char::is_whitespace))
+			{
+				tracing::error!(key, arg, "argument cannot be written to a CSV settings file");
+				return Err(Error::InvalidSettings(format!(
+					"the argument {arg:?} of {key} is empty or contains whitespace, use a JSON or YAML settings file"
+				)));
+			}
+			csv.push_str(&format!("{key}={}\n", args.join(" ")));
+		}
+		csv.push_str("===AUDIO===\n");
+		csv.push_str(&write_csv(&self.audio, "name,sampling,bitrate")?);
+		csv.push_str("===VIDEO===\n");
+		csv.push_str(&write_csv(
+			&self.video,
+			"name,resolution,bitrate,max_rate,buffer_size,codec",
+		)?);
+		Ok(csv)
+	}
+
+	/// write the settings as a CSV settings file to `path`
+	pub fn save<P>(&self, path: P) -> Result<(), Error>
+	where
+		P: AsRef<std::path::Path>,
+	{
+		if let Err(e) = std::fs::write(path, self.to_csv()?) {
+			tracing::error!(error = %e);
+			return Err(Error::Io(e));
+		};
+		Ok(())
+	}
+}
+
+/// A bitrate ladder generated from its top rung, `<W>x<H>@<kbps>`, instead of written in a settings file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ladder {
+	pub width: u32,
+	pub height: u32,
+	/// of the top rung, in kbit/s
+	pub bitrate: u64,
+}
+
+impl Ladder {
+	/// the most rungs, each with half the pixels of the one above
+	const RUNGS: i32 = 5;
+	/// the bitrate of a rung relative to the one above
+	const BITRATE_SCALE: f64 = 0.6;
+	/// lower rungs are dropped
+	const MIN_HEIGHT: u32 = 180;
+	const MIN_BITRATE: u64 = 150;
+
+	/// the settings of the ladder, with a single 128 kbit/s audio rep
+	pub fn file(&self) -> SettingsFile {
+		let video = (0..Self::RUNGS)
+			.filter_map(|rung| {
+				// halving the pixels scales both sides by 1/sqrt(2), the encoder needs even sizes
+				let scale = 0.5f64.powf(rung as f64 / 2.0);
+				let even = |side: u32| ((side as f64 * scale / 2.0).round() as u32) * 2;
+				let (width, height) = (even(self.width), even(self.height));
+				let kbps = (self.bitrate as f64 * Self::BITRATE_SCALE.powi(rung)).round() as u64;
+				if height < Self::MIN_HEIGHT || kbps < Self::MIN_BITRATE {
+					return None;
+				}
+
+				Some(VideoSetting {
+					name: format!("{height}p"),
+					resolution: format!("{width}x{height}"),
+					bitrate: kbps * 1000,
+					max_rate: kbps * 1000,
+					buffer_size: kbps * 2000,
+					codec: default_video_codec(),
+					namespace: None,
+					mode: None,
+				})
+			})
+			.collect();
+
+		SettingsFile {
+			gop_num: 1,
+			fps: 25,
+			target_segment_duration: 2.0,
+			audio: vec![AudioSetting {
+				name: "audio".to_string(),
+				sampling_rate: 48000,
+				bitrate: 128000,
+				namespace: None,
+				mode: None,
+			}],
+			video,
+			extra: ExtraArgs::default(),
+		}
+	}
+}
+
+impl std::str::FromStr for Ladder {
+	type Err = Error;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let parsed = s.split_once('@').and_then(|(resolution, bitrate)| {
+			let (width, height) = resolution.split_once('x')?;
+			Some(Self {
+				width: width.parse().ok()?,
+				height: height.parse().ok()?,
+				bitrate: bitrate.parse().ok()?,
+			})
+		});
+		let Some(ladder) = parsed else {
+			tracing::error!(ladder = s, "expected <W>x<H>@<kbps>");
+			return Err(Error::Malformed("ladder", s.to_string()));
+		};
+
+		if ladder.width == 0 || ladder.height < Self::MIN_HEIGHT || ladder.bitrate < Self::MIN_BITRATE {
+			tracing::error!(ladder = s, "top rung below 180p or 150 kbit/s");
+			return Err(Error::InvalidSettings(format!(
+				"the top rung of the ladder {s} is below {}p or {} kbit/s",
+				Self::MIN_HEIGHT,
+				Self::MIN_BITRATE
+			)));
+		}
+
+		Ok(ladder)
+	}
+}
+
+/// Arguments passed through to ffmpeg, winning over the generated flags they repeat.
+///
+/// A flag replaces the earlier ones of the same name, including those for a subset of its streams,
+/// ex. `-c:v h264_nvenc` replaces `-c:v:0 libx264`. Only the flags that may be repeated, like `-map`, are kept.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ExtraArgs {
+	/// before the `-i` of every input
+	#[serde(rename = "extra_input_args", default, skip_serializing_if = "Vec::is_empty")]
+	pub input: Vec<String>,
+	/// after the flags of every video rep, `{index}` is replaced by the index of the rep among the video reps
+	#[serde(rename = "extra_video_args", default, skip_serializing_if = "Vec::is_empty")]
+	pub video: Vec<String>,
+	/// after the flags of every audio rep, `{index}` is replaced like for the video reps
+	#[serde(rename = "extra_audio_args", default, skip_serializing_if = "Vec::is_empty")]
+	pub audio: Vec<String>,
+	/// after the flags of the DASH muxer
+	#[serde(rename = "extra_output_args", default, skip_serializing_if = "Vec::is_empty")]
+	pub output: Vec<String>,
+}
+
+impl ExtraArgs {
+	pub fn is_empty(&self) -> bool {
+		self.sections().iter().all(|(_, args)| args.is_empty())
+	}
+
+	/// append the arguments of `other`, which win over these
+	pub fn extend(&mut self, other: &Self) {
+		self.input.extend_from_slice(&other.input);
+		self.video.extend_from_slice(&other.video);
+		self.audio.extend_from_slice(&other.audio);
+		self.output.extend_from_slice(&other.output);
+	}
+
+	/// the arguments by their key in a settings file
+	fn sections(&self) -> [(&'static str, &Vec<String>); 4] {
+		[
+			("extra_input_args", &self.input),
+			("extra_video_args", &self.video),
+			("extra_audio_args", &self.audio),
+			("extra_output_args", &self.output),
+		]
+	}
+
+	fn section_mut(&mut self, key: &str) -> Option<&mut Vec<String>> {
+		match key {
+			"extra_input_args" => Some(&mut self.input),
+			"extra_video_args" => Some(&mut self.video),
+			"extra_audio_args" => Some(&mut self.audio),
+			"extra_output_args" => Some(&mut self.output),
+			_ => None,
+		}
+	}
+}
+
+/// format of a settings file, detected from its extension
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+	/// key=value pairs followed by the `===AUDIO===` and `===VIDEO===` CSV sections
+	Csv,
+	Json,
+	Yaml,
+}
+
+impl Format {
+	pub fn from_path<P>(path: P) -> Self
+	where
+		P: AsRef<std::path::Path>,
+	{
+		match path.as_ref().extension().and_then(|ext| ext.to_str()) {
+			Some("json") => Self::Json,
+			Some("yaml" | "yml") => Self::Yaml,
+			_ => Self::Csv,
+		}
+	}
+}
+
+/// what ffmpeg reads from, detected from the `--input` string
+#[derive(Debug, Clone, PartialEq)]
+pub enum Input {
+	/// the laptop camera and microphone, ALSA and video4linux2, avfoundation on macOS or DirectShow on Windows
+	Webcam,
+	/// a file, read at its native frame rate
+	File(std::path::PathBuf),
+	/// a live stream of one of the [NETWORK_SCHEMES], ex. `srt://:9000?mode=listener`
+	Network(String),
+}
+
+impl Input {
+	/// whether the input is a live stream, it is paced by the sender and cannot be looped
+	pub fn is_live(&self) -> bool {
+		matches!(self, Self::Network(_))
+	}
+
+	/// the demuxer of a network input, only set for the protocols carrying arbitrary containers
+	fn format(&self) -> Option<&'static str> {
+		match self {
+			Self::Network(url) if url.starts_with("srt://") || url.starts_with("udp://") => Some("mpegts"),
+			_ => None,
+		}
+	}
+}
+
+impl From<&str> for Input {
+	fn from(input: &str) -> Self {
+		if input == INPUT_DEFAULT {
+			return Self::Webcam;
+		}
+
+		match input.split_once("://") {
+			Some((scheme, _)) if NETWORK_SCHEMES.contains(&scheme.to_ascii_lowercase().as_str()) => {
+				Self::Network(input.to_string())
+			}
+			_ => Self::File(input.into()),
+		}
+	}
+}
+
+impl From<String> for Input {
+	fn from(input: String) -> Self {
+		input.as_str().into()
+	}
+}
+
+impl From<std::path::PathBuf> for Input {
+	fn from(input: std::path::PathBuf) -> Self {
+		match input.to_str() {
+			Some(s) => s.into(),
+			None => Self::File(input),
+		}
+	}
+}
+
+impl std::fmt::Display for Input {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Webcam => write!(f, "{INPUT_DEFAULT}"),
+			Self::File(path) => write!(f, "{}", path.display()),
+			Self::Network(url) => write!(f, "{url}"),
+		}
+	}
+}
+
 #[derive(Debug, Clone)]
 pub struct Settings<P>
 where
@@ -14,7 +325,11 @@ where
 	pub target_segment_duration: f64,
 	pub audio: Vec<AudioSetting>,
 	pub video: Vec<VideoSetting>,
-	input: P,
+	/// the arguments passed through by the settings file
+	pub extra: ExtraArgs,
+	/// the arguments passed through on the command line, kept on reload
+	overrides: ExtraArgs,
+	input: Input,
 	output: P,
 	no_audio: bool,
 	looping: bool,
@@ -24,31 +339,105 @@ impl<P> Settings<P>
 where
 	P: AsRef<std::path::Path>,
 {
-	pub fn new(settings_file: P, input: P, output: P, no_audio: bool, looping: bool) -> Result<Self, Error> {
+	/// read the settings file, its format is detected from the extension (`.json`, `.yaml`/`.yml`, CSV otherwise)
+	pub fn new(settings_file: P, input: Input, output: P, no_audio: bool, looping: bool) -> Result<Self, Error> {
+		let format = Format::from_path(&settings_file);
+
 		let buf = match std::fs::read(settings_file) {
 			Ok(b) => b,
 			Err(e) => {
-				println!("Error: {}", e);
-				return Err(Error::Crate("fs".to_string(), e.to_string()));
+				tracing::error!(error = %e);
+				return Err(Error::Io(e).context("reading the settings file"));
 			}
 		};
 
+		Self::parse(buf, format, input, output, no_audio, looping)
+	}
+
+	/// parse the settings from the contents of a settings file in `format`
+	pub fn parse(
+		buf: Vec<u8>,
+		format: Format,
+		input: Input,
+		output: P,
+		no_audio: bool,
+		looping: bool,
+	) -> Result<Self, Error> {
+		let file = match format {
+			Format::Csv => return Self::from_bytes(buf, input, output, no_audio, looping),
+			Format::Json => serde_json::from_slice(&buf).map_err(Error::Json),
+			Format::Yaml => serde_yaml::from_slice(&buf).map_err(Error::Yaml),
+		};
+
+		match file {
+			Ok(file) => Self::from_file(file, input, output, no_audio, looping),
+			Err(e) => {
+				tracing::error!(error = %e);
+				Err(e)
+			}
+		}
+	}
+
+	/// parse the settings from the contents of a CSV settings file
+	pub fn from_bytes(buf: Vec<u8>, input: Input, output: P, no_audio: bool, looping: bool) -> Result<Self, Error> {
 		let (key_pairs, csv_vec) = helper::split_vec_once(buf, "===AUDIO===\n".as_bytes());
 
 		let (audio, video) = helper::split_vec_once(csv_vec, b"===VIDEO===\n");
 
-		let (gop_num, fps, target_segment_duration) = Self::parse_key_pairs(&key_pairs)?;
+		let (gop_num, fps, target_segment_duration, extra) = Self::parse_key_pairs(&key_pairs)?;
 
-		let video = VideoSetting::vec_from_bytes(&video)?;
+		// line numbers of the section headers, counted from 1 and skipping the separators
+		let audio_line = lines(&key_pairs) + 2;
+		let video_line = audio_line + lines(&audio) + 1;
 
-		let audio = AudioSetting::vec_from_bytes(&audio)?;
+		let audio = read_csv(&audio, audio_line)?;
 
-		Ok(Self {
+		let video = read_csv(&video, video_line)?;
+
+		let file = SettingsFile {
 			gop_num,
 			fps,
 			target_segment_duration,
 			audio,
 			video,
+			extra,
+		};
+
+		Self::from_file(file, input, output, no_audio, looping)
+	}
+
+	/// create the settings from a deserialized settings file
+	pub fn from_file(
+		file: SettingsFile,
+		input: Input,
+		output: P,
+		no_audio: bool,
+		looping: bool,
+	) -> Result<Self, Error> {
+		if file.fps == 0 {
+			tracing::error!("fps must be greater than 0");
+			return Err(Error::InvalidSettings("fps must be greater than 0".to_string()));
+		}
+
+		if file.video.is_empty() && (file.audio.is_empty() || no_audio) {
+			tracing::error!("at least one representation is required");
+			return Err(Error::InvalidSettings(
+				"at least one representation is required".to_string(),
+			));
+		}
+
+		if looping && input.is_live() {
+			log::warn!("{input} is a live input, it is not looped");
+		}
+
+		Ok(Self {
+			gop_num: file.gop_num,
+			fps: file.fps,
+			target_segment_duration: file.target_segment_duration,
+			audio: file.audio,
+			video: file.video,
+			extra: file.extra,
+			overrides: ExtraArgs::default(),
 			input,
 			output,
 			no_audio,
@@ -56,144 +445,214 @@ where
 		})
 	}
 
-	pub fn to_args(&self) -> Result<Vec<String>, Error> {
-		let mut args = Vec::new();
+	/// read `settings_file` again, keeping the input, the output and the flags, fails if it is invalid
+	pub fn reload<F>(&self, settings_file: F) -> Result<Self, Error>
+	where
+		F: AsRef<std::path::Path>,
+		P: Clone,
+	{
+		let format = Format::from_path(&settings_file);
 
-		let segment_duration = format!("{:.3}", self.parse_segment_duration());
+		let buf = match std::fs::read(settings_file) {
+			Ok(b) => b,
+			Err(e) => {
+				tracing::error!(error = %e);
+				return Err(Error::Io(e).context("reading the settings file"));
+			}
+		};
 
-		let mut input_args = vec!["-fflags", "+genpts", "-re"];
+		let settings = Self::parse(
+			buf,
+			format,
+			self.input.clone(),
+			self.output.clone(),
+			self.no_audio,
+			self.looping,
+		)?
+		.with_extra_args(self.overrides.clone());
+		settings.validate()?;
+
+		Ok(settings)
+	}
+
+	/// pass `extra` through to ffmpeg after the arguments of the settings file, winning over them
+	pub fn with_extra_args(mut self, extra: ExtraArgs) -> Self {
+		self.overrides = extra;
+		self
+	}
 
-		if self.looping {
-			input_args.append(&mut vec!["-stream_loop", "-1"]);
+	/// the settings as they would be written to a settings file
+	pub fn file(&self) -> SettingsFile {
+		SettingsFile {
+			gop_num: self.gop_num,
+			fps: self.fps,
+			target_segment_duration: self.target_segment_duration,
+			audio: self.audio.clone(),
+			video: self.video.clone(),
+			extra: self.extra.clone(),
 		}
+	}
 
-		let Some(input) = self.input.as_ref().to_str() else {
-			println!("Error: input path is not a valid string");
-			return Err(Error::FailedToConvert);
-		};
+	/// the directory ffmpeg writes the segments to
+	pub fn output(&self) -> &std::path::Path {
+		self.output.as_ref()
+	}
 
-		let fps = format!("{}", self.fps);
-		if input == INPUT_DEFAULT {
-			input_args.append(&mut vec![
-				"-f",
-				"alsa",
-				"-ac",
-				"2",
-				"-thread_queue_size",
-				"512",
-				"-i",
-				"default",
-				"-f",
-				"video4linux2",
-				"-s",
-				"1280x720",
-				"-r",
-				&fps,
-				"-i",
-				input,
-			]);
-		} else {
-			input_args.append(&mut vec!["-i", input]);
+	/// the arguments of the ffmpeg call
+	pub fn to_args(&self) -> Result<Vec<String>, Error> {
+		Ok(self.ffmpeg_args()?.to_args())
+	}
+
+	/// the arguments of the ffmpeg call, grouped by the inputs, the reps and the output flags
+	pub fn ffmpeg_args(&self) -> Result<FfmpegArgs, Error> {
+		let (units, per_second, reason) = self.segment_duration();
+		let effective = units as f64 / per_second as f64;
+		if (effective - self.target_segment_duration).abs() > 0.001 {
+			tracing::info!(
+				requested = self.target_segment_duration,
+				effective,
+				"adjusted the segment duration to {reason} at {} fps",
+				self.fps
+			);
 		}
+		let segment_duration = format!("{effective:.3}");
 
-		args.append(&mut input_args);
+		let mut global = vec!["-fflags", "+genpts"];
 
-		let mut args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+		// a live input arrives in real time already
+		if !self.input.is_live() {
+			global.push("-re");
 
-		args.append(&mut self.audio());
-		args.append(&mut self.qualities()?);
+			if self.looping {
+				global.extend(["-stream_loop", "-1"]);
+			}
+		}
 
-		let gop = format!(
-			"{}",
-			(self.gop_num as f64 * self.fps as f64 * self.parse_segment_duration()) as u64
-		);
+		let fps = format!("{}", self.fps);
+		let mut inputs = Vec::new();
+		match &self.input {
+			Input::Webcam => {
+				let [microphone, camera] = webcam(&fps);
+				inputs.push(microphone);
+				if !self.video.is_empty() {
+					inputs.push(camera);
+				}
+			}
+			Input::File(path) => {
+				let Some(path) = path.to_str() else {
+					tracing::error!(input = %path.display(), "input path is not a valid string");
+					return Err(Error::FailedToConvert);
+				};
+				inputs.push(vec!["-i", path]);
+			}
+			Input::Network(url) => {
+				let mut input = Vec::new();
+				if let Some(format) = self.input.format() {
+					input.extend(["-f", format]);
+				}
+				input.extend(["-i", url]);
+				inputs.push(input);
+			}
+		}
 
-		let output = self.output.as_ref().join("source.mpd");
-		let output_args = vec![
-			"-f",
-			"dash",
-			"-dash_segment_type",
-			"mp4",
-			"-preset",
-			"ultrafast",
-			"-sc_threshold",
-			"0",
-			"-r",
-			&fps,
-			"-keyint_min",
-			&gop,
-			"-g",
-			&gop,
-			"-aspect",
-			"16:9",
-			"-c:v",
-			"libx264",
-			"-pix_fmt",
-			"yuv420p",
-			"-color_primaries",
-			"bt709",
-			"-color_trc",
-			"bt709",
-			"-colorspace",
-			"bt709",
-			"-tune",
-			"zerolatency",
-			"-x264-params",
-			"sliced-threads=0:nal-hrd=cbr",
-			"-seg_duration",
-			&segment_duration,
-			"-adaptation_sets",
-			"id=0,streams=v id=1,streams=a",
-			"-use_timeline",
-			"1",
-			"-streaming",
-			"1",
-			"-window_size",
-			"3",
-			"-extra_window_size",
-			"0",
-			"-frag_type",
-			"every_frame",
-			"-utc_timing_url",
-			"https://time.akamai.com/?iso",
-			"-write_prft",
-			"1",
-			"-flags",
-			"+global_header",
-			"-metadata",
-			"title=MoQ",
-			"-ldash",
-			"1",
-			"-init_seg_name",
-			"source_init_rep_$RepresentationID$.$ext$",
-			"-media_seg_name",
-			"source_chunk_$Number%05d$_rep_$RepresentationID$.$ext$",
-			output.to_str().unwrap(),
-		];
-
-		let mut output_args = output_args.iter().map(|a| a.to_string()).collect();
-
-		args.append(&mut output_args);
+		let mut reps = self.audio();
+		reps.extend(self.qualities()?);
 
-		Ok(args)
-	}
+		// the segments hold whole video frames
+		let gop = format!("{}", (self.gop_num as f64 * self.fps as f64 * effective).round() as u64);
+
+		// only the streams present are declared, ex. just the audio set of an audio-only broadcast
+		let mut sets = Vec::new();
+		if !self.video.is_empty() {
+			sets.push("streams=v");
+		}
+		if self.has_audio() {
+			sets.push("streams=a");
+		}
+		let adaptation_sets = sets
+			.iter()
+			.enumerate()
+			.map(|(id, streams)| format!("id={id},{streams}"))
+			.collect::<Vec<_>>()
+			.join(" ");
+
+		let mut output = vec![["-f", "dash"], ["-dash_segment_type", "mp4"]];
+
+		if !self.video.is_empty() {
+			output.extend([
+				["-preset", "ultrafast"],
+				["-sc_threshold", "0"],
+				["-r", &fps],
+				["-keyint_min", &gop],
+				["-g", &gop],
+				["-aspect", "16:9"],
+				["-pix_fmt", "yuv420p"],
+				["-color_primaries", "bt709"],
+				["-color_trc", "bt709"],
+				["-colorspace", "bt709"],
+				["-tune", "zerolatency"],
+				["-x264-params", "sliced-threads=0:nal-hrd=cbr"],
+			]);
+		}
 
-	fn qualities(&self) -> Result<Vec<String>, Error> {
-		let Some(input) = self.input.as_ref().to_str() else {
-			println!("Error: input path is not a valid string");
+		output.extend([
+			["-seg_duration", &segment_duration],
+			["-adaptation_sets", &adaptation_sets],
+			["-use_timeline", "1"],
+			["-streaming", "1"],
+			["-window_size", "3"],
+			["-extra_window_size", "0"],
+			["-frag_type", "every_frame"],
+			["-utc_timing_url", "https://time.akamai.com/?iso"],
+			["-write_prft", "1"],
+			["-flags", "+global_header"],
+			["-metadata", "title=MoQ"],
+			["-ldash", "1"],
+			["-init_seg_name", "source_init_rep_$RepresentationID$.$ext$"],
+			[
+				"-media_seg_name",
+				"source_chunk_$Number%05d$_rep_$RepresentationID$.$ext$",
+			],
+		]);
+
+		let manifest = self.output.as_ref().join("source.mpd");
+		let Some(manifest) = helper::path_to_string(&manifest) else {
+			tracing::error!(output = %manifest.display(), "output path is not a valid string");
 			return Err(Error::FailedToConvert);
 		};
 
+		let strings = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+		let mut args = FfmpegArgs {
+			global: strings(&global),
+			inputs: inputs.iter().map(|input| strings(input)).collect(),
+			reps,
+			output: output.iter().map(|flag| strings(flag)).collect(),
+			manifest,
+		};
+
+		let mut extra = self.extra.clone();
+		extra.extend(&self.overrides);
+		let audio = match self.has_audio() {
+			true => self.audio.len(),
+			false => 0,
+		};
+		args.pass_through(&extra, audio);
+
+		Ok(args)
+	}
+
+	/// the flags of every video rep
+	fn qualities(&self) -> Result<Vec<Vec<String>>, Error> {
 		let mut args = Vec::new();
 
 		for (i, rep) in self.video.iter().enumerate() {
-			let map = if self.no_audio || self.audio.is_empty() || input != INPUT_DEFAULT {
+			let map = if self.no_audio || self.audio.is_empty() || self.input != Input::Webcam {
 				"0:v:0".to_string()
 			} else {
 				"1:v:0".to_string()
 			};
 
-			let mut arg = vec![
+			args.push(vec![
 				"-map".to_string(),
 				map,
 				format!("-s:v:{i}"),
@@ -204,23 +663,29 @@ where
 				format!("{}", rep.max_rate),
 				format!("-bufsize:v:{i}"),
 				format!("{}", rep.buffer_size),
-			];
-
-			args.append(&mut arg);
+				format!("-c:v:{i}"),
+				rep.codec.clone(),
+			]);
 		}
 
 		Ok(args)
 	}
 
-	fn audio(&self) -> Vec<String> {
-		if self.no_audio || self.audio.is_empty() {
-			return vec!["-an".to_string()];
+	/// whether the audio representations are encoded
+	fn has_audio(&self) -> bool {
+		!self.no_audio && !self.audio.is_empty()
+	}
+
+	/// the flags of every audio rep, or disabling the audio
+	fn audio(&self) -> Vec<Vec<String>> {
+		if !self.has_audio() {
+			return vec![vec!["-an".to_string()]];
 		}
 
 		let mut args = Vec::new();
 
 		for (i, rep) in self.audio.iter().enumerate() {
-			let mut arg = vec![
+			args.push(vec![
 				"-map".to_string(),
 				"0:a:0".to_string(),
 				format!("-c:a:{i}"),
@@ -229,62 +694,105 @@ where
 				format!("{}", rep.bitrate),
 				format!("-ar:{i}"),
 				format!("{}", rep.sampling_rate),
-			];
-			args.append(&mut arg);
+			]);
 		}
 
 		args
 	}
 
-	fn parse_segment_duration(&self) -> f64 {
-		let greatest_common_divider = |x: u64, y: u64| {
-			let mut y = y;
-			let mut x = x;
-			while y != 0 {
-				let t = y;
-				y = x % y;
-				x = t;
-			}
-			x
-		};
+	/// the segment duration ffmpeg is asked for, in seconds, the target rounded to whole frames
+	///
+	/// The segments hold whole video frames, and whole AAC frames too if a duration of both is within one AAC frame
+	/// of the target. Without video they hold whole AAC frames, without audio just whole video frames.
+	pub fn effective_segment_duration(&self) -> f64 {
+		let (units, per_second, _) = self.segment_duration();
+		units as f64 / per_second as f64
+	}
 
-		let sampling_rate = if !self.audio.is_empty() {
-			self.audio[0].sampling_rate
-		} else {
-			128_000
+	/// the effective segment duration in 1/`per_second` units of a second, with what it is a multiple of
+	fn segment_duration(&self) -> (u64, u64, &'static str) {
+		let fps = self.fps.max(1);
+		let sampling_rate = match self.has_audio() {
+			true => self.audio[0].sampling_rate.max(1),
+			false => 1,
 		};
+		// both frame durations are whole units
+		let per_second = fps * sampling_rate;
+		let target = (self.target_segment_duration * per_second as f64).round() as u64;
+		let nearest = |frame: u64| ((target + frame / 2) / frame).max(1) * frame;
+
+		let video = sampling_rate;
+		if !self.has_audio() {
+			return (nearest(video), per_second, "whole video frames");
+		}
 
-		let divider = greatest_common_divider(1024 * self.fps, sampling_rate);
-		let base = 1024_f64 / divider as f64;
-		let multiplier = (self.target_segment_duration / base) as u64;
+		let audio = AAC_FRAME * fps;
+		if self.video.is_empty() {
+			return (nearest(audio), per_second, "whole AAC frames");
+		}
 
-		base * multiplier as f64
+		let both = nearest(video / gcd(video, audio) * audio);
+		match both.abs_diff(target) <= audio {
+			true => (both, per_second, "whole video and AAC frames"),
+			false => (
+				nearest(video),
+				per_second,
+				"whole video frames, no duration of whole AAC frames is close",
+			),
+		}
 	}
 
-	fn parse_key_pairs(key_pairs: &[u8]) -> Result<(u64, u64, f64), Error> {
-		let re = match regex::Regex::new(r" +#.+\n") {
-			Ok(r) => r,
-			Err(e) => {
-				println!("Regex: {}", e);
-				return Err(Error::Crate("regex".to_string(), e.to_string()));
-			}
-		};
+	fn parse_key_pairs(key_pairs: &[u8]) -> Result<(u64, u64, f64, ExtraArgs), Error> {
 		let key_pairs = match String::from_utf8(key_pairs.to_vec()) {
 			Ok(v) => v,
 			Err(e) => {
-				println!("Error: {}", e);
-				return Err(Error::Crate("String".to_string(), e.to_string()));
+				tracing::error!(error = %e);
+				return Err(Error::InvalidSettings(e.to_string()));
 			}
 		};
-		let str = re.replace_all(&key_pairs, "\n").to_string();
 
-		let key_pairs = str.as_bytes().to_vec();
+		let mut gop_num = None;
+		let mut fps = None;
+		let mut target_segment_duration = None;
+		let mut extra = ExtraArgs::default();
+
+		for (i, line) in key_pairs.lines().enumerate() {
+			// strip trailing comments
+			let line = line.split(" #").next().unwrap_or_default().trim();
+			if line.is_empty() {
+				continue;
+			}
+
+			let Some((key, value)) = line.split_once('=') else {
+				tracing::error!(line = i + 1, "expected key=value");
+				return Err(Error::InvalidSetting {
+					line: i + 1,
+					field: line.to_string(),
+					error: "expected key=value".to_string(),
+				});
+			};
+			let key = key.trim();
+
+			match key {
+				"gop_num" => gop_num = Some(parse_value(i + 1, key, value)?),
+				"fps" => fps = Some(parse_value(i + 1, key, value)?),
+				"target_segment_duration" => target_segment_duration = Some(parse_value(i + 1, key, value)?),
+				_ => match extra.section_mut(key) {
+					// the arguments are separated by whitespace
+					Some(args) => *args = value.split_whitespace().map(String::from).collect(),
+					None => log::warn!("ignoring unknown setting {} in line {}", key, i + 1),
+				},
+			}
+		}
 
-		let (gop_num, key_pairs) = Self::parse_u64(key_pairs)?;
-		let (fps, key_pairs) = Self::parse_u64(key_pairs)?;
-		let (target_segment_duration, _) = Self::parse_f64(key_pairs)?;
+		let (Some(gop_num), Some(fps), Some(target_segment_duration)) = (gop_num, fps, target_segment_duration) else {
+			tracing::error!("gop_num, fps and target_segment_duration are required");
+			return Err(Error::InvalidSettings(
+				"gop_num, fps and target_segment_duration are required".to_string(),
+			));
+		};
 
-		Ok((gop_num, fps, target_segment_duration))
+		Ok((gop_num, fps, target_segment_duration, extra))
 	}
 
 	pub fn get_rep(&self, index: usize) -> Option<Setting> {
@@ -304,167 +812,1201 @@ where
 		self.audio.len() + self.video.len()
 	}
 
-	fn parse_u64(buf: Vec<u8>) -> Result<(u64, Vec<u8>), Error> {
-		let (data, buf) = helper::split_vec_once(buf.to_vec(), b"\n");
+	/// check the settings for values ffmpeg cannot encode, returns every violation
+	///
+	/// A bitrate equal to the max rate is allowed, as the encoder runs in CBR mode.
+	pub fn validate(&self) -> Result<(), Error> {
+		let mut violations = Vec::new();
 
-		let (_, data) = helper::split_vec_once(data, b"=");
-		let str = match String::from_utf8(data) {
-			Ok(s) => s,
-			Err(e) => {
-				println!("Error: {}", e);
-				return Err(Error::Crate("string".to_string(), e.to_string()));
+		if self.target_segment_duration.is_nan() || self.target_segment_duration <= 0.0 {
+			violations.push(Violation::SegmentDuration(self.target_segment_duration));
+		}
+
+		for rep in &self.video {
+			let dimensions = rep
+				.resolution
+				.split_once('x')
+				.and_then(|(w, h)| Some((w.parse::<u64>().ok()?, h.parse::<u64>().ok()?)));
+			if !dimensions.is_some_and(|(w, h)| w > 0 && h > 0 && w % 2 == 0 && h % 2 == 0) {
+				violations.push(Violation::Resolution(rep.name.clone(), rep.resolution.clone()));
 			}
-		};
 
-		let num = str.parse::<u64>().unwrap_or_default();
+			if rep.bitrate == 0 || rep.bitrate > rep.max_rate || rep.max_rate > rep.buffer_size {
+				violations.push(Violation::Bitrate {
+					name: rep.name.clone(),
+					bitrate: rep.bitrate,
+					max_rate: rep.max_rate,
+					buffer_size: rep.buffer_size,
+				});
+			}
+		}
 
-		Ok((num, buf))
+		for rep in &self.audio {
+			let supported = u32::try_from(rep.sampling_rate).is_ok_and(|rate| AAC_SAMPLING_RATES.contains(&rate));
+			if !supported {
+				violations.push(Violation::SamplingRate(rep.name.clone(), rep.sampling_rate));
+			}
+		}
+
+		let mut names = std::collections::HashSet::new();
+		let mut duplicates = std::collections::HashSet::new();
+		for name in self
+			.audio
+			.iter()
+			.map(|rep| &rep.name)
+			.chain(self.video.iter().map(|rep| &rep.name))
+		{
+			// report every duplicate once
+			if !names.insert(name) && duplicates.insert(name) {
+				violations.push(Violation::DuplicateName(name.clone()));
+			}
+		}
+
+		if !violations.is_empty() {
+			for violation in &violations {
+				tracing::error!(%violation);
+			}
+			return Err(Error::Validation(violations));
+		}
+
+		Ok(())
 	}
 
-	fn parse_f64(buf: Vec<u8>) -> Result<(f64, Vec<u8>), Error> {
-		let (data, buf) = helper::split_vec_once(buf.to_vec(), b"\n");
+	/// the track name of every rep in rep ID order, fails with [Error::Validation] on duplicates
+	///
+	/// The tracks share the broadcast, so a name must be unique even if the reps are in different namespaces.
+	pub fn track_names(&self, template: &TrackNameTemplate) -> Result<Vec<String>, Error> {
+		let names: Vec<_> = (0..self.rep_len())
+			.filter_map(|rep_id| self.get_rep(rep_id))
+			.map(|setting| template.render(&setting))
+			.collect();
+
+		let mut unique = std::collections::HashSet::new();
+		let mut violations = Vec::new();
+		for name in &names {
+			let violation = Violation::DuplicateTrackName(name.clone());
+			if !unique.insert(name) && !violations.contains(&violation) {
+				violations.push(violation);
+			}
+		}
 
-		let (_, data) = helper::split_vec_once(data, b"=");
-		let str = match String::from_utf8(data) {
-			Ok(s) => s,
-			Err(e) => {
-				println!("Error: {}", e);
-				return Err(Error::Crate("string".to_string(), e.to_string()));
+		if !violations.is_empty() {
+			for violation in &violations {
+				tracing::error!(%violation);
 			}
+			return Err(Error::Validation(violations));
+		}
+
+		Ok(names)
+	}
+
+	/// write the ffmpeg call as a shell script to `path`
+	pub fn save(&self, path: P) -> Result<(), Error> {
+		let script = format!("#!/bin/bash\n\n{}\n", self.ffmpeg_args()?);
+
+		if let Err(e) = std::fs::write(path, script) {
+			tracing::error!(error = %e);
+			return Err(Error::Io(e));
 		};
+		Ok(())
+	}
+}
 
-		let num = str.parse::<f64>().unwrap_or_default();
+/// the inputs of the laptop microphone and camera at `fps`, the microphone first
+#[cfg(not(any(target_os = "macos", windows)))]
+fn webcam(fps: &str) -> [Vec<&str>; 2] {
+	[
+		vec!["-f", "alsa", "-ac", "2", "-thread_queue_size", "512", "-i", "default"],
+		vec!["-f", "video4linux2", "-s", "1280x720", "-r", fps, "-i", CAMERA],
+	]
+}
+
+#[cfg(target_os = "macos")]
+fn webcam(fps: &str) -> [Vec<&str>; 2] {
+	[
+		vec!["-f", "avfoundation", "-thread_queue_size", "512", "-i", ":0"],
+		vec![
+			"-f",
+			"avfoundation",
+			"-video_size",
+			"1280x720",
+			"-framerate",
+			fps,
+			"-i",
+			CAMERA,
+		],
+	]
+}
+
+#[cfg(windows)]
+fn webcam(fps: &str) -> [Vec<&str>; 2] {
+	[
+		vec![
+			"-f",
+			"dshow",
+			"-thread_queue_size",
+			"512",
+			"-i",
+			"audio=Microphone Array",
+		],
+		vec![
+			"-f",
+			"dshow",
+			"-video_size",
+			"1280x720",
+			"-framerate",
+			fps,
+			"-i",
+			CAMERA,
+		],
+	]
+}
 
-		Ok((num, buf))
+/// The arguments of an ffmpeg call by [Settings], in the order they are passed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FfmpegArgs {
+	/// flags applying to all inputs, ex. the pacing
+	pub global: Vec<String>,
+	/// the flags of every input, each ending with its `-i`
+	pub inputs: Vec<Vec<String>>,
+	/// the stream mapping and encoder flags of every rep, audio first
+	pub reps: Vec<Vec<String>>,
+	/// the flag and value pairs of the DASH muxer
+	pub output: Vec<Vec<String>>,
+	/// path of the manifest ffmpeg writes, the last argument
+	pub manifest: String,
+}
+
+impl FfmpegArgs {
+	fn lines(&self) -> impl Iterator<Item = &[String]> {
+		std::iter::once(self.global.as_slice())
+			.chain(self.inputs.iter().map(Vec::as_slice))
+			.chain(self.reps.iter().map(Vec::as_slice))
+			.chain(self.output.iter().map(Vec::as_slice))
+			.chain(std::iter::once(std::slice::from_ref(&self.manifest)))
 	}
 
-	pub fn save(&self, path: P) -> Result<(), Error> {
-		let args = self.to_args()?;
-		let mut buf = b"#!/bin/bash\n\n".to_vec();
-
-		let mut ffmpeg = b"ffmpeg".to_vec();
-		buf.append(&mut ffmpeg);
-
-		// check if there is a webcam input (double -f -i inputs)
-		let f_pos = args.iter().position(|arg| arg == "-f").unwrap();
-		let i_pos = args.iter().position(|arg| arg == "-i").unwrap();
-		let args = if f_pos < i_pos {
-			// when there if a format before input, append all flags until first -f
-			let (input, args) = args.split_at(args.iter().position(|arg| arg == "-f").unwrap_or_default());
-			helper::append_shell(&mut buf, input);
-			args
-		} else {
-			// do nothing otherwise
-			&args
+	pub fn to_args(&self) -> Vec<String> {
+		self.lines().flatten().cloned().collect()
+	}
+
+	/// append `extra`, removing the flags it replaces; the reps after the first `audio` ones are video
+	fn pass_through(&mut self, extra: &ExtraArgs, audio: usize) {
+		// the global flags apply to the inputs, ex. -stream_loop
+		let input = last_wins(flags(&extra.input));
+		self.global = without(flags(&self.global), &input).concat();
+		for line in &mut self.inputs {
+			let mut flags = without(flags(line), &input);
+			let at = flags.iter().rposition(|flag| flag[0] == "-i").unwrap_or(flags.len());
+			flags.splice(at..at, input.iter().cloned());
+			*line = flags.concat();
+		}
+
+		let indexed = |args: &[String], index: usize| {
+			let args: Vec<_> = args
+				.iter()
+				.map(|arg| arg.replace("{index}", &index.to_string()))
+				.collect();
+			flags(&args)
 		};
+		let mut lines: Vec<_> = (0..audio)
+			.map(|rep| (rep, indexed(&extra.audio, rep)))
+			.chain((audio..self.reps.len()).map(|rep| (rep, indexed(&extra.video, rep - audio))))
+			.collect();
+		lines.push((self.reps.len(), flags(&extra.output)));
+
+		// the flags of the reps and the muxer are options of the same output
+		for (rep, extra) in lines {
+			if extra.is_empty() {
+				continue;
+			}
+			let extra = last_wins(extra);
+
+			for line in &mut self.reps {
+				*line = without(flags(line), &extra).concat();
+			}
+			self.output = without(std::mem::take(&mut self.output), &extra);
+
+			match self.reps.get_mut(rep) {
+				Some(line) => line.extend(extra.concat()),
+				None => self.output.extend(extra),
+			}
+		}
+	}
+}
+
+/// the flags that may be given more than once, they are never replaced
+const REPEATABLE: [&str; 4] = ["-map", "-metadata", "-i", "-filter_complex"];
+
+/// `args` split into the flags, each followed by its values until the next flag
+fn flags<S>(args: &[S]) -> Vec<Vec<String>>
+where
+	S: AsRef<str>,
+{
+	let mut flags: Vec<Vec<String>> = Vec::new();
+	for arg in args {
+		let arg = arg.as_ref();
+		// negative numbers are values, ex. -stream_loop -1
+		let is_flag = arg.starts_with('-') && arg.parse::<f64>().is_err();
+		match flags.last_mut() {
+			Some(flag) if !is_flag => flag.push(arg.to_string()),
+			_ => flags.push(vec![arg.to_string()]),
+		}
+	}
+	flags
+}
+
+/// the name of `flag` with its stream specifier, the aliases of `-c` resolved, ex. `-vcodec` is `-c:v`
+fn flag_name(flag: &str) -> String {
+	match flag.split_once(':') {
+		Some(("-codec", streams)) => format!("-c:{streams}"),
+		None if flag == "-codec" => "-c".to_string(),
+		None if flag == "-vcodec" => "-c:v".to_string(),
+		None if flag == "-acodec" => "-c:a".to_string(),
+		_ => flag.to_string(),
+	}
+}
+
+/// `flags` without those replaced by a later one
+fn last_wins(flags: Vec<Vec<String>>) -> Vec<Vec<String>> {
+	let mut kept: Vec<Vec<String>> = Vec::new();
+	for flag in flags.into_iter().rev() {
+		if !kept.iter().any(|later| replaces(later, &flag)) {
+			kept.push(flag);
+		}
+	}
+	kept.reverse();
+	kept
+}
+
+/// `flags` without those replaced by any of `extra`
+fn without(flags: Vec<Vec<String>>, extra: &[Vec<String>]) -> Vec<Vec<String>> {
+	flags
+		.into_iter()
+		.filter(|flag| !extra.iter().any(|extra| replaces(extra, flag)))
+		.collect()
+}
+
+/// whether `flag` replaces `other`, a flag of the same name or for a subset of its streams
+fn replaces(flag: &[String], other: &[String]) -> bool {
+	let (flag, other) = (flag_name(&flag[0]), flag_name(&other[0]));
+	let repeatable = REPEATABLE.contains(&flag.split(':').next().unwrap_or_default());
+	!repeatable && (other == flag || other.starts_with(&format!("{flag}:")))
+}
+
+impl std::fmt::Display for FfmpegArgs {
+	/// the shell command, an input, rep or output flag per line, every argument quoted if needed
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "ffmpeg")?;
+		for line in self.lines() {
+			let line: Vec<_> = line.iter().map(|arg| helper::shell_quote(arg)).collect();
+			write!(f, " \\\n\t{}", line.join(" "))?;
+		}
+		Ok(())
+	}
+}
 
-		// find the first input flags
-		let (input, args) = args.split_at(args.iter().position(|arg| arg == "-i").unwrap_or_default() + 2);
-		helper::append_shell(&mut buf, input);
+/// number of lines in `buf`
+fn lines(buf: &[u8]) -> usize {
+	buf.iter().filter(|b| **b == b'\n').count()
+}
 
-		// try to find the second input flag, if found append
-		let (input, args) = args.split_at(args.iter().position(|arg| arg == "-map").unwrap_or_default());
-		if !input.is_empty() {
-			helper::append_shell(&mut buf, input);
+/// parse the value of `key` in `line`
+fn parse_value<T>(line: usize, key: &str, value: &str) -> Result<T, Error>
+where
+	T: std::str::FromStr,
+	T::Err: std::fmt::Display,
+{
+	match value.trim().parse() {
+		Ok(v) => Ok(v),
+		Err(e) => {
+			tracing::error!(key, line, error = %e, "invalid setting");
+			Err(Error::InvalidSetting {
+				line,
+				field: key.to_string(),
+				error: e.to_string(),
+			})
 		}
+	}
+}
 
-		// find all audio flags, append in chunks of 8
-		let (input, args) = args.split_at(args.iter().position(|arg| arg == "-s:v:0").unwrap_or_default() - 2);
-		let chunks = input.chunks(8);
-		for chunk in chunks {
-			helper::append_shell(&mut buf, chunk);
+/// deserialize a CSV section starting in `first_line` of the settings file
+/// the CSV section of `records`, with `header` if there are none
+fn write_csv<T>(records: &[T], header: &str) -> Result<String, Error>
+where
+	T: serde::Serialize,
+{
+	if records.is_empty() {
+		return Ok(format!("{header}\n"));
+	}
+
+	let mut writer = csv::Writer::from_writer(Vec::new());
+	for record in records {
+		if let Err(e) = writer.serialize(record) {
+			tracing::error!(error = %e);
+			return Err(Error::Csv(e));
 		}
+	}
 
-		// find all video flags, append in chunks of 10
-		let (streams, args) = args.split_at(args.iter().position(|arg| arg == "-f").unwrap_or_default());
-		let chunks = streams.chunks(10);
-		for chunk in chunks {
-			helper::append_shell(&mut buf, chunk);
+	let buf = match writer.into_inner() {
+		Ok(b) => b,
+		Err(e) => {
+			tracing::error!(error = %e);
+			return Err(Error::Io(e.into_error()));
 		}
+	};
+	Ok(String::from_utf8_lossy(&buf).into_owned())
+}
 
-		// append the rest in chunks of 2
-		let chunks = args.chunks(2);
-		for chunk in chunks {
-			helper::append_shell(&mut buf, chunk);
+fn read_csv<T>(buf: &[u8], first_line: usize) -> Result<Vec<T>, Error>
+where
+	T: serde::de::DeserializeOwned,
+{
+	let mut reader = csv::ReaderBuilder::new()
+		.has_headers(true)
+		.delimiter(b',')
+		.comment(Some(b'#'))
+		.trim(csv::Trim::All)
+		.from_reader(buf.reader());
+
+	let headers = match reader.headers() {
+		Ok(h) => h.clone(),
+		Err(e) => {
+			tracing::error!(error = %e);
+			return Err(Error::Csv(e));
 		}
+	};
 
-		if let Err(e) = std::fs::write(path, buf) {
-			println!("Error: {}", e);
-			return Err(Error::Crate("fs".to_string(), e.to_string()));
+	let mut vec = Vec::new();
+	for res in reader.deserialize() {
+		let res = match res {
+			Ok(r) => r,
+			Err(e) => {
+				tracing::error!(error = %e);
+				let line = e
+					.position()
+					.map_or(first_line, |pos| first_line + pos.line() as usize - 1);
+				let (field, error) = match e.kind() {
+					csv::ErrorKind::Deserialize { err, .. } => (
+						err.field()
+							.and_then(|i| headers.get(i as usize))
+							.unwrap_or_default()
+							.to_string(),
+						err.kind().to_string(),
+					),
+					_ => (String::new(), e.to_string()),
+				};
+				return Err(Error::InvalidSetting { line, field, error });
+			}
 		};
-		Ok(())
+		vec.push(res);
 	}
+
+	Ok(vec)
+}
+
+/// a single reason the settings cannot be encoded
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum Violation {
+	#[error("resolution {1} of {0} is not WxH with positive, even dimensions")]
+	Resolution(String, String),
+
+	#[error("{name} requires 0 < bitrate <= max_rate <= buffer_size, got {bitrate}, {max_rate}, {buffer_size}")]
+	Bitrate {
+		name: String,
+		bitrate: u64,
+		max_rate: u64,
+		buffer_size: u64,
+	},
+
+	#[error("sampling rate {1} of {0} is not supported by AAC")]
+	SamplingRate(String, u64),
+
+	#[error("representation name {0} is not unique")]
+	DuplicateName(String),
+
+	#[error("track name {0} is not unique")]
+	DuplicateTrackName(String),
+
+	#[error("target_segment_duration must be greater than 0, got {0}")]
+	SegmentDuration(f64),
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum Setting {
 	Audio(AudioSetting),
 	Video(VideoSetting),
 }
 
-#[derive(Debug, Clone, serde::Deserialize)]
+impl Setting {
+	pub fn name(&self) -> &str {
+		match self {
+			Self::Audio(a) => &a.name,
+			Self::Video(v) => &v.name,
+		}
+	}
+
+	/// namespace of the track in the catalog, None to inherit the one of the broadcast
+	pub fn namespace(&self) -> Option<&str> {
+		match self {
+			Self::Audio(a) => a.namespace.as_deref(),
+			Self::Video(v) => v.namespace.as_deref(),
+		}
+	}
+
+	/// how the objects of the track are sent, groups unless the setting names a mode
+	pub fn mode(&self) -> StreamMode {
+		match self {
+			Self::Audio(a) => a.mode,
+			Self::Video(v) => v.mode,
+		}
+		.unwrap_or_default()
+	}
+}
+
+/// A track name derived from the properties of a rep, ex. `{kind}/{height}p/{name}`.
+///
+/// The placeholders are `{name}`, `{kind}` (`audio` or `video`), `{bitrate}` and `{height}`, empty for audio.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackNameTemplate(Vec<Placeholder>);
+
+#[derive(Debug, Clone, PartialEq)]
+enum Placeholder {
+	Text(String),
+	Name,
+	Kind,
+	Bitrate,
+	Height,
+}
+
+impl TrackNameTemplate {
+	/// the track name of `setting`
+	pub fn render(&self, setting: &Setting) -> String {
+		let mut name = String::new();
+		for part in &self.0 {
+			match (part, setting) {
+				(Placeholder::Text(text), _) => name.push_str(text),
+				(Placeholder::Name, _) => name.push_str(setting.name()),
+				(Placeholder::Kind, Setting::Audio(_)) => name.push_str("audio"),
+				(Placeholder::Kind, Setting::Video(_)) => name.push_str("video"),
+				(Placeholder::Bitrate, Setting::Audio(a)) => name.push_str(&a.bitrate.to_string()),
+				(Placeholder::Bitrate, Setting::Video(v)) => name.push_str(&v.bitrate.to_string()),
+				(Placeholder::Height, Setting::Audio(_)) => (),
+				(Placeholder::Height, Setting::Video(v)) => {
+					if let Some((_, height)) = v.dimensions() {
+						name.push_str(&height.to_string());
+					}
+				}
+			}
+		}
+		name
+	}
+}
+
+impl Default for TrackNameTemplate {
+	fn default() -> Self {
+		Self(vec![Placeholder::Name])
+	}
+}
+
+impl std::str::FromStr for TrackNameTemplate {
+	type Err = Error;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mut parts = Vec::new();
+		let mut rest = s;
+
+		while let Some(start) = rest.find('{') {
+			if start > 0 {
+				parts.push(Placeholder::Text(rest[..start].to_string()));
+			}
+
+			let Some(end) = rest[start..].find('}') else {
+				tracing::error!(template = s, "unclosed placeholder");
+				return Err(Error::Malformed("track name template", s.to_string()));
+			};
+			let placeholder = &rest[start + 1..start + end];
+			parts.push(match placeholder {
+				"name" => Placeholder::Name,
+				"kind" => Placeholder::Kind,
+				"bitrate" => Placeholder::Bitrate,
+				"height" => Placeholder::Height,
+				_ => {
+					tracing::error!(template = s, placeholder, "unknown placeholder");
+					return Err(Error::Unknown("placeholder", placeholder.to_string()));
+				}
+			});
+
+			rest = &rest[start + end + 1..];
+		}
+		if !rest.is_empty() {
+			parts.push(Placeholder::Text(rest.to_string()));
+		}
+
+		Ok(Self(parts))
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct VideoSetting {
 	pub name: String,
 	pub resolution: String,
 	pub bitrate: u64,
 	pub max_rate: u64,
 	pub buffer_size: u64,
+	/// ffmpeg encoder, optional column
+	#[serde(default = "default_video_codec")]
+	pub codec: String,
+	/// catalog namespace of the track, optional column
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub namespace: Option<String>,
+	/// how the objects of the track are sent, optional column
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub mode: Option<StreamMode>,
+}
+
+fn default_video_codec() -> String {
+	"libx264".to_string()
 }
 
 impl VideoSetting {
 	pub fn vec_from_bytes(buf: &[u8]) -> Result<Vec<Self>, Error> {
-		let mut vec = Vec::new();
-		let mut reader = csv::ReaderBuilder::new()
-			.has_headers(true)
-			.delimiter(b',')
-			.comment(Some(b'#'))
-			.trim(csv::Trim::All)
-			.from_reader(buf.reader());
-
-		for res in reader.deserialize() {
-			let res = match res {
-				Ok(r) => r,
-				Err(e) => {
-					println!("Error: {}", e);
-					return Err(Error::Crate("csv".to_string(), e.to_string()));
-				}
-			};
-			vec.push(res);
-		}
+		read_csv(buf, 1)
+	}
 
-		Ok(vec)
+	/// width and height of the resolution, None if it is not WxH
+	pub fn dimensions(&self) -> Option<(u16, u16)> {
+		let (width, height) = self.resolution.split_once('x')?;
+		Some((width.parse().ok()?, height.parse().ok()?))
 	}
 }
 
-#[derive(Debug, Clone, serde::Deserialize)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct AudioSetting {
 	pub name: String,
 	#[serde(rename = "sampling")]
 	pub sampling_rate: u64,
 	pub bitrate: u64,
+	/// catalog namespace of the track, optional column
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub namespace: Option<String>,
+	/// how the objects of the track are sent, optional column
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub mode: Option<StreamMode>,
 }
 
 impl AudioSetting {
 	pub fn vec_from_bytes(buf: &[u8]) -> Result<Vec<Self>, Error> {
-		let mut vec = Vec::new();
-		// let mut reader = csv::Reader::from_reader(buf.as_slice().reader());
-		let mut reader = csv::ReaderBuilder::new()
-			.has_headers(true)
-			.delimiter(b',')
-			.comment(Some(b'#'))
-			.trim(csv::Trim::All)
-			.from_reader(buf.reader());
-
-		for res in reader.deserialize() {
-			let res = match res {
-				Ok(r) => r,
-				Err(e) => {
-					println!("Error: {}", e);
-					return Err(Error::Crate("csv".to_string(), e.to_string()));
+		read_csv(buf, 1)
+	}
+}
+
+fn gcd(mut x: u64, mut y: u64) -> u64 {
+	while y != 0 {
+		(x, y) = (y, x % y);
+	}
+	x
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const CSV: &str = "gop_num=1
+fps=25 # frames per second
+target_segment_duration=2.0
+===AUDIO===
+name,sampling,bitrate
+audio,48000,128000
+===VIDEO===
+name,resolution,bitrate,max_rate,buffer_size,codec
+720p,1280x720,3000000,3000000,6000000,libx264
+360p,640x360,1000000,1000000,2000000,libx265
+";
+
+	const JSON: &str = r#"{
+	"gop_num": 1,
+	"fps": 25,
+	"target_segment_duration": 2.0,
+	"audio": [{ "name": "audio", "sampling": 48000, "bitrate": 128000 }],
+	"video": [
+		{ "name": "720p", "resolution": "1280x720", "bitrate": 3000000, "max_rate": 3000000, "buffer_size": 6000000 },
+		{ "name": "360p", "resolution": "640x360", "bitrate": 1000000, "max_rate": 1000000, "buffer_size": 2000000, "codec": "libx265" }
+	]
+}"#;
+
+	const YAML: &str = "gop_num: 1
+fps: 25
+target_segment_duration: 2.0
+audio:
+  - name: audio
+    sampling: 48000
+    bitrate: 128000
+video:
+  - name: 720p
+    resolution: 1280x720
+    bitrate: 3000000
+    max_rate: 3000000
+    buffer_size: 6000000
+  - name: 360p
+    resolution: 640x360
+    bitrate: 1000000
+    max_rate: 1000000
+    buffer_size: 2000000
+    codec: libx265
+";
+
+	fn parse(buf: &str, format: Format) -> Result<Settings<std::path::PathBuf>, Error> {
+		Settings::parse(
+			buf.as_bytes().to_vec(),
+			format,
+			"input.mp4".into(),
+			"output".into(),
+			false,
+			false,
+		)
+	}
+
+	#[test]
+	fn test_formats() {
+		let csv = parse(CSV, Format::Csv).unwrap();
+		let json = parse(JSON, Format::Json).unwrap();
+		let yaml = parse(YAML, Format::Yaml).unwrap();
+
+		assert_eq!(csv.file(), json.file());
+		assert_eq!(csv.file(), yaml.file());
+		assert_eq!(csv.to_args().unwrap(), json.to_args().unwrap());
+		assert_eq!(csv.to_args().unwrap(), yaml.to_args().unwrap());
+
+		assert_eq!(Format::from_path("settings.json"), Format::Json);
+		assert_eq!(Format::from_path("settings.yml"), Format::Yaml);
+		assert_eq!(Format::from_path("settings.csv"), Format::Csv);
+	}
+
+	/// the value following `flag` in the ffmpeg arguments
+	fn arg<'a>(args: &'a [String], flag: &str) -> &'a str {
+		let index = args.iter().position(|arg| arg == flag).unwrap();
+		&args[index + 1]
+	}
+
+	#[test]
+	fn test_effective_segment_duration() {
+		let mut settings = parse(CSV, Format::Csv).unwrap();
+
+		// 2s are 50 frames at 25 fps, but 93.75 AAC frames at 48 kHz, the nearest 1.92s of both are too far off
+		assert_eq!(settings.effective_segment_duration(), 2.0);
+		let args = settings.to_args().unwrap();
+		assert_eq!(arg(&args, "-seg_duration"), "2.000");
+		assert_eq!(arg(&args, "-g"), "50");
+
+		// 1.92s are 48 frames and 90 AAC frames
+		settings.target_segment_duration = 1.9;
+		assert_eq!(settings.effective_segment_duration(), 1.92);
+		assert_eq!(arg(&settings.to_args().unwrap(), "-g"), "48");
+
+		// no sampling rate to align to, it used to be the audio bitrate
+		settings.audio.clear();
+		settings.target_segment_duration = 2.0;
+		assert_eq!(settings.effective_segment_duration(), 2.0);
+		settings.fps = 30;
+		settings.target_segment_duration = 1.99;
+		assert_eq!(settings.effective_segment_duration(), 2.0);
+
+		// audio only, 94 AAC frames
+		let mut settings = parse(CSV, Format::Csv).unwrap();
+		settings.video.clear();
+		assert_eq!(settings.effective_segment_duration(), 94.0 * 1024.0 / 48000.0);
+	}
+
+	/// every duration is whole video frames and within an AAC frame of the target, or half a video frame at low rates
+	#[test]
+	fn test_effective_segment_duration_properties() {
+		let mut settings = parse(CSV, Format::Csv).unwrap();
+		for fps in 1..=60 {
+			for sampling_rate in AAC_SAMPLING_RATES {
+				for i in 1..=97 {
+					let target = i as f64 * 0.1037;
+					settings.fps = fps;
+					settings.audio[0].sampling_rate = sampling_rate as u64;
+					settings.target_segment_duration = target;
+
+					let effective = settings.effective_segment_duration();
+					let context = format!("{target}s at {fps} fps and {sampling_rate} Hz: {effective}s");
+
+					let (units, per_second, _) = settings.segment_duration();
+					assert_eq!(units % (per_second / fps), 0, "{context}");
+					let frames = effective * fps as f64;
+					assert!((frames - frames.round()).abs() < 1e-6, "{context}");
+
+					if target * (fps as f64) < 1.0 {
+						assert_eq!(frames.round(), 1.0, "{context}");
+						continue;
+					}
+					let aac_frame = AAC_FRAME as f64 / sampling_rate as f64;
+					let tolerance = aac_frame.max(0.5 / fps as f64) + 1e-9;
+					assert!((effective - target).abs() <= tolerance, "{context}");
+					if fps >= 24 && sampling_rate <= 48000 {
+						assert!((effective - target).abs() <= aac_frame + 1e-9, "{context}");
+					}
 				}
-			};
-			vec.push(res);
+			}
+		}
+	}
+
+	#[test]
+	fn test_round_trip() {
+		let settings = parse(CSV, Format::Csv).unwrap();
+
+		let json = serde_json::to_string(&settings.file()).unwrap();
+		let yaml = serde_yaml::to_string(&settings.file()).unwrap();
+
+		assert_eq!(
+			parse(&json, Format::Json).unwrap().to_args().unwrap(),
+			settings.to_args().unwrap()
+		);
+		assert_eq!(
+			parse(&yaml, Format::Yaml).unwrap().to_args().unwrap(),
+			settings.to_args().unwrap()
+		);
+	}
+
+	#[test]
+	fn test_ladder() {
+		let rungs = |ladder: &str| -> Vec<(String, u64)> {
+			let file = ladder.parse::<Ladder>().unwrap().file();
+			assert_eq!(file.audio.len(), 1);
+			file.video
+				.into_iter()
+				.map(|rep| {
+					assert_eq!(rep.name, format!("{}p", rep.dimensions().unwrap().1));
+					assert_eq!((rep.max_rate, rep.buffer_size), (rep.bitrate, 2 * rep.bitrate));
+					(rep.resolution, rep.bitrate / 1000)
+				})
+				.collect()
+		};
+		let expected = |rungs: &[(&str, u64)]| -> Vec<(String, u64)> {
+			rungs
+				.iter()
+				.map(|(resolution, kbps)| (resolution.to_string(), *kbps))
+				.collect()
+		};
+
+		assert_eq!(
+			rungs("1920x1080@6000"),
+			expected(&[
+				("1920x1080", 6000),
+				("1358x764", 3600),
+				("960x540", 2160),
+				("678x382", 1296),
+				("480x270", 778)
+			])
+		);
+		assert_eq!(
+			rungs("3840x2160@16000"),
+			expected(&[
+				("3840x2160", 16000),
+				("2716x1528", 9600),
+				("1920x1080", 5760),
+				("1358x764", 3456),
+				("960x540", 2074)
+			])
+		);
+		assert_eq!(
+			rungs("1280x720@3000"),
+			expected(&[
+				("1280x720", 3000),
+				("906x510", 1800),
+				("640x360", 1080),
+				("452x254", 648),
+				("320x180", 389)
+			])
+		);
+		// below 180p or 150 kbit/s
+		assert_eq!(rungs("640x360@400"), expected(&[("640x360", 400), ("452x254", 240)]));
+
+		// the same settings as read from the saved CSV
+		let file = "1920x1080@6000".parse::<Ladder>().unwrap().file();
+		let csv = Settings::<std::path::PathBuf>::from_bytes(
+			file.to_csv().unwrap().into_bytes(),
+			"input.mp4".into(),
+			"output".into(),
+			false,
+			false,
+		)
+		.unwrap();
+		assert_eq!(csv.file(), file);
+
+		for invalid in [
+			"1920x1080",
+			"1920@6000",
+			"1920x1080@6k",
+			"1920x1080@100",
+			"320x144@1000",
+		] {
+			assert!(invalid.parse::<Ladder>().is_err(), "{invalid}");
 		}
+	}
+
+	#[test]
+	fn test_invalid() {
+		let err = parse(&CSV.replace("fps=25", "fps=2S"), Format::Csv).unwrap_err();
+		assert!(matches!(err, Error::InvalidSetting { line: 2, ref field, .. } if field == "fps"));
+
+		let err = parse(&CSV.replace("640x360,1000000", "640x360,1OOOOOO"), Format::Csv).unwrap_err();
+		assert!(matches!(err, Error::InvalidSetting { line: 10, ref field, .. } if field == "bitrate"));
+
+		let err = parse(&CSV.replace("fps=25", "fps=0"), Format::Csv).unwrap_err();
+		assert!(matches!(err, Error::InvalidSettings(_)));
+
+		let err = parse(&JSON.replace("\"fps\": 25", "\"fps\": 0"), Format::Json).unwrap_err();
+		assert!(matches!(err, Error::InvalidSettings(_)));
+
+		let err = parse(
+			"gop_num: 1\nfps: 25\ntarget_segment_duration: 2.0\nvideo: []\n",
+			Format::Yaml,
+		)
+		.unwrap_err();
+		assert!(matches!(err, Error::InvalidSettings(_)));
+
+		let err = parse("gop_num: 1\nfps: twenty\n", Format::Yaml).unwrap_err();
+		assert!(err.to_string().contains("line 2"));
+	}
+
+	/// the input flags before the first `-map`, of the [CSV] settings reading from `input`
+	fn input_args(input: &str, looping: bool) -> Vec<String> {
+		let settings = Settings::<std::path::PathBuf>::from_bytes(
+			CSV.as_bytes().to_vec(),
+			input.into(),
+			"output".into(),
+			false,
+			looping,
+		)
+		.unwrap();
+		let args = settings.to_args().unwrap();
+		let map = args.iter().position(|arg| arg == "-map").unwrap();
+		args[..map].to_vec()
+	}
+
+	#[test]
+	fn test_inputs() {
+		assert_eq!(
+			input_args("input.mp4", true),
+			["-fflags", "+genpts", "-re", "-stream_loop", "-1", "-i", "input.mp4"]
+		);
+
+		let webcam = input_args("/dev/video0", false);
+		assert_eq!(webcam[..3], ["-fflags", "+genpts", "-re"]);
+		assert_eq!(webcam.iter().filter(|arg| *arg == "-i").count(), 2);
+		assert!(webcam.ends_with(&["-i".to_string(), CAMERA.to_string()]));
+
+		// live inputs are neither paced nor looped, the containers of srt and udp are not probed for
+		for (url, format) in [
+			("srt://:9000?mode=listener", Some("mpegts")),
+			("udp://239.0.0.1:1234?pkt_size=1316&fifo_size=50000", Some("mpegts")),
+			("rtmp://localhost/live/stream", None),
+			("RTSP://camera.local:554/stream", None),
+		] {
+			let mut expected = vec!["-fflags", "+genpts"];
+			expected.extend(format.map(|f| ["-f", f]).into_iter().flatten());
+			expected.extend(["-i", url]);
+			assert_eq!(input_args(url, true), expected, "{url}");
+		}
+
+		assert_eq!(Input::from("file://input.mp4"), Input::File("file://input.mp4".into()));
+
+		// the query of the URL is quoted in the script
+		let settings = Settings::<std::path::PathBuf>::from_bytes(
+			CSV.as_bytes().to_vec(),
+			"udp://239.0.0.1:1234?pkt_size=1316&fifo_size=50000".into(),
+			"output".into(),
+			false,
+			false,
+		)
+		.unwrap();
+		let path = crate::testing::temp_dir("settings-network").join("ffmpeg.sh");
+		settings.save(path.clone()).unwrap();
+		let script = std::fs::read_to_string(&path).unwrap();
+		let _ = std::fs::remove_file(&path);
+		assert!(script.contains("-f mpegts -i 'udp://239.0.0.1:1234?pkt_size=1316&fifo_size=50000'"));
+		assert!(script.contains("-adaptation_sets 'id=0,streams=v id=1,streams=a'"));
+	}
+
+	const AUDIO_ONLY: &str = "gop_num=1
+fps=25
+target_segment_duration=2.0
+===AUDIO===
+name,sampling,bitrate
+audio,48000,128000
+===VIDEO===
+name,resolution,bitrate,max_rate,buffer_size
+";
+
+	#[test]
+	fn test_audio_only() {
+		let settings = parse(AUDIO_ONLY, Format::Csv).unwrap();
+		let yaml = "gop_num: 1
+fps: 25
+target_segment_duration: 2.0
+audio:
+  - { name: audio, sampling: 48000, bitrate: 128000 }
+";
+		assert_eq!(settings.file(), parse(yaml, Format::Yaml).unwrap().file());
+
+		let args = settings.to_args().unwrap();
+		assert!(!args.iter().any(|arg| arg.contains(":v") || arg == "-x264-params"));
+		let sets = args.iter().position(|arg| arg == "-adaptation_sets").unwrap();
+		assert_eq!(args[sets + 1], "id=0,streams=a");
+
+		// the script of the ffmpeg call is written without video flags
+		let path = crate::testing::temp_dir("settings-audio-only").join("ffmpeg.sh");
+		settings.save(path.clone()).unwrap();
+		let script = std::fs::read_to_string(&path).unwrap();
+		let _ = std::fs::remove_file(&path);
+		assert!(script.contains("-c:a:0"));
+
+		// without the audio, nothing is left to encode
+		let res = Settings::<std::path::PathBuf>::parse(
+			AUDIO_ONLY.as_bytes().to_vec(),
+			Format::Csv,
+			"input.mp4".into(),
+			"output".into(),
+			true,
+			false,
+		);
+		assert!(matches!(res, Err(Error::InvalidSettings(_))));
+	}
+
+	/// the arguments bash passes to ffmpeg when running the saved script of `settings`
+	#[cfg(unix)]
+	fn script_args(settings: &Settings<std::path::PathBuf>, name: &str) -> Vec<String> {
+		let path = crate::testing::temp_dir(&format!("settings-script-{name}")).join("ffmpeg.sh");
+		settings.save(path.clone()).unwrap();
+		let output = std::process::Command::new("bash")
+			.arg("-c")
+			.arg(r#"ffmpeg() { printf '%s\0' "$@"; }; . "$0""#)
+			.arg(&path)
+			.output()
+			.unwrap();
+		let _ = std::fs::remove_file(&path);
+
+		assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+		String::from_utf8(output.stdout)
+			.unwrap()
+			.split_terminator('\0')
+			.map(String::from)
+			.collect()
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn test_script() {
+		let single = "gop_num=1
+fps=25
+target_segment_duration=2.0
+===AUDIO===
+name,sampling,bitrate
+===VIDEO===
+name,resolution,bitrate,max_rate,buffer_size
+720p,1280x720,3000000,3000000,6000000
+";
+
+		for (name, csv, input, output) in [
+			("webcam", CSV, "/dev/video0", "output"),
+			("file", CSV, "my input's.mp4", "out dir/$HOME"),
+			("audio-only", AUDIO_ONLY, "input.mp4", "output"),
+			("single", single, "input.mp4", "output"),
+			("network", CSV, "srt://:9000?mode=listener", "output"),
+		] {
+			let settings = Settings::<std::path::PathBuf>::from_bytes(
+				csv.as_bytes().to_vec(),
+				input.into(),
+				output.into(),
+				false,
+				true,
+			)
+			.unwrap();
+			let args = settings.ffmpeg_args().unwrap();
+			assert_eq!(script_args(&settings, name), args.to_args(), "{name}");
+
+			// an input, rep or output flag per line
+			let lines = 2 + args.inputs.len() + args.reps.len() + args.output.len() + 1;
+			assert_eq!(args.to_string().lines().count(), lines, "{name}");
+		}
+
+		let settings = parse(CSV, Format::Csv).unwrap();
+		let args = settings.ffmpeg_args().unwrap();
+		assert_eq!(args.inputs, [["-i", "input.mp4"]]);
+		assert_eq!(args.reps.len(), 3);
+		assert_eq!(args.reps[0][..2], ["-map", "0:a:0"]);
+		assert_eq!(args.manifest, "output/source.mpd");
+
+		let webcam = Settings::<std::path::PathBuf>::from_bytes(
+			CSV.as_bytes().to_vec(),
+			"/dev/video0".into(),
+			"output".into(),
+			false,
+			false,
+		)
+		.unwrap();
+		let args = webcam.ffmpeg_args().unwrap();
+		assert_eq!(args.inputs.len(), 2);
+		assert_eq!(args.reps[1][..2], ["-map", "1:v:0"]);
+		assert!(args.to_string().contains("'source_init_rep_$RepresentationID$.$ext$'"));
+	}
+
+	#[test]
+	fn test_extra_args() {
+		let strings = |args: &str| args.split(' ').map(String::from).collect::<Vec<_>>();
+		let json = JSON.replace(
+			"\"target_segment_duration\": 2.0,",
+			r#""target_segment_duration": 2.0,
+	"extra_input_args": ["-stream_loop", "3", "-thread_queue_size", "1024"],
+	"extra_video_args": ["-c:v:{index}", "h264_nvenc", "-preset", "p4"],
+	"extra_audio_args": ["-b:a:{index}", "96000"],
+	"extra_output_args": ["-window_size", "5", "-metadata", "artist=MoQ"],"#,
+		);
+		let settings = Settings::<std::path::PathBuf>::parse(
+			json.into_bytes(),
+			Format::Json,
+			"input.mp4".into(),
+			"output".into(),
+			false,
+			true,
+		)
+		.unwrap();
+		let args = settings.ffmpeg_args().unwrap();
+
+		// the input flags are placed before the -i, replacing the global loop
+		assert_eq!(args.global, ["-fflags", "+genpts", "-re"]);
+		assert_eq!(
+			args.inputs,
+			[strings("-stream_loop 3 -thread_queue_size 1024 -i input.mp4")]
+		);
+
+		// the reps are indexed by kind, the generated encoder, bitrate and preset are replaced
+		assert_eq!(args.reps[0], strings("-map 0:a:0 -c:a:0 aac -ar:0 48000 -b:a:0 96000"));
+		assert!(args.reps[1].ends_with(&strings("-c:v:0 h264_nvenc")));
+		assert!(args.reps[2].ends_with(&strings("-c:v:1 h264_nvenc -preset p4")));
+		let all = args.to_args();
+		assert!(!all
+			.iter()
+			.any(|arg| ["libx264", "libx265", "ultrafast"].contains(&arg.as_str())));
+		assert_eq!(all.iter().filter(|arg| *arg == "-preset").count(), 1);
+
+		// the output flags follow those of the muxer, a repeatable flag is kept
+		assert!(!args.output.contains(&strings("-window_size 3")));
+		assert_eq!(
+			args.output[args.output.len() - 2..],
+			[strings("-window_size 5"), strings("-metadata artist=MoQ")]
+		);
+		assert!(args.output.contains(&strings("-metadata title=MoQ")));
+
+		// the command line wins over the settings file, last value wins
+		let settings = settings.with_extra_args(ExtraArgs {
+			video: strings("-vcodec hevc_nvenc -preset p1"),
+			output: strings("-window_size 4 -window_size 6"),
+			..Default::default()
+		});
+		let args = settings.ffmpeg_args().unwrap();
+		let all = args.to_args();
+		assert!(!all.iter().any(|arg| ["h264_nvenc", "p4"].contains(&arg.as_str())));
+		assert!(all.ends_with(&strings("-window_size 6 output/source.mpd")));
+		assert!(args.reps[2].ends_with(&strings("-vcodec hevc_nvenc -preset p1")));
+		assert_eq!(all.iter().filter(|arg| *arg == "-window_size").count(), 1);
+
+		// the script passes the same arguments
+		#[cfg(unix)]
+		assert_eq!(script_args(&settings, "extra"), all);
+		assert!(args.to_string().contains("\t-map 0:v:0 -s:v:1 640x360"));
+
+		// CSV settings files separate the arguments by whitespace
+		let csv = settings.file().to_csv().unwrap();
+		assert!(csv.contains("extra_video_args=-c:v:{index} h264_nvenc -preset p4\n"));
+		assert_eq!(parse(&csv, Format::Csv).unwrap().file(), settings.file());
+
+		let mut file = settings.file();
+		file.extra.output = vec!["-metadata".to_string(), "title=My Stream".to_string()];
+		assert!(matches!(file.to_csv(), Err(Error::InvalidSettings(_))));
+	}
+
+	#[test]
+	fn test_validate() {
+		// a valid multi-rung ladder, including CBR rungs
+		parse(CSV, Format::Csv).unwrap().validate().unwrap();
+
+		let violations = |csv: &str| match parse(csv, Format::Csv).unwrap().validate() {
+			Err(Error::Validation(violations)) => violations,
+			res => panic!("expected violations, got {:?}", res),
+		};
+
+		for resolution in ["1280x", "x720", "0x720", "1281x720", "1280*720"] {
+			assert_eq!(
+				violations(&CSV.replace("1280x720", resolution)),
+				[Violation::Resolution("720p".to_string(), resolution.to_string())]
+			);
+		}
+
+		for rates in [
+			"0,3000000,6000000",
+			"3000001,3000000,6000000",
+			"3000000,6000001,6000000",
+		] {
+			assert!(matches!(
+				violations(&CSV.replace("3000000,3000000,6000000", rates))[..],
+				[Violation::Bitrate { ref name, .. }] if name == "720p"
+			));
+		}
+
+		assert_eq!(
+			violations(&CSV.replace("audio,48000", "audio,48001")),
+			[Violation::SamplingRate("audio".to_string(), 48001)]
+		);
+
+		assert_eq!(
+			violations(&CSV.replace("360p", "720p").replace("audio,", "720p,")),
+			[Violation::DuplicateName("720p".to_string())]
+		);
+
+		assert_eq!(
+			violations(&CSV.replace("target_segment_duration=2.0", "target_segment_duration=0")),
+			[Violation::SegmentDuration(0.0)]
+		);
+
+		// every violation is reported, not just the first
+		let csv = CSV.replace("1280x720", "1280x").replace("audio,48000", "audio,1");
+		assert_eq!(violations(&csv).len(), 2);
+	}
+
+	#[test]
+	fn test_track_names() {
+		let csv = CSV
+			.replace("buffer_size,codec", "buffer_size,codec,namespace")
+			.replace("libx264", "libx264,exp-b")
+			.replace("libx265", "libx265,");
+		let settings = parse(&csv, Format::Csv).unwrap();
+		assert_eq!(settings.video[0].namespace.as_deref(), Some("exp-b"));
+		assert_eq!(settings.video[1].namespace, None);
+		assert_eq!(settings.audio[0].namespace, None);
+
+		let names = |template: &str| settings.track_names(&template.parse().unwrap());
+		assert_eq!(names("{name}").unwrap(), ["audio", "720p", "360p"]);
+		assert_eq!(
+			names("{kind}/{height}p/{bitrate}").unwrap(),
+			["audio/p/128000", "video/720p/3000000", "video/360p/1000000"]
+		);
 
-		Ok(vec)
+		assert!(matches!(
+			names("{kind}").unwrap_err(),
+			Error::Validation(ref v) if v == &[Violation::DuplicateTrackName("video".to_string())]
+		));
+
+		assert!(matches!(
+			"{kind}/{codec}".parse::<TrackNameTemplate>(),
+			Err(Error::Unknown("placeholder", ref p)) if p == "codec"
+		));
+		assert!(matches!(
+			"{kind".parse::<TrackNameTemplate>(),
+			Err(Error::Malformed("track name template", _))
+		));
 	}
 }
@@ -1,64 +1,317 @@
 use notify::Watcher;
 use notify::{
-	event::{AccessKind::Close, AccessMode::Write, CreateKind::File, ModifyKind::Data},
+	event::{
+		AccessKind::Close,
+		AccessMode::Write,
+		CreateKind::{self, File},
+		DataChange, MetadataKind,
+		ModifyKind::{self, Data, Metadata, Name},
+		RenameMode,
+	},
 	EventKind::{Access, Create, Modify},
 };
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use tokio::io::{AsyncReadExt, AsyncSeekExt};
 
+use super::error::ResultExt;
 use super::helper;
 use super::Error;
 
+/// how long the data events of a file are coalesced into a single read by default
+pub const DEBOUNCE: std::time::Duration = std::time::Duration::from_millis(10);
+/// the most read from a file at once by default, larger changes are published in several reads
+pub const MAX_READ: usize = 8 << 20;
+/// how many completely read segments are remembered, late events for them publish nothing twice
+const FINISHED: usize = 64;
+/// how long a segment gets no data events before it is complete, where closes are not reported
+pub const QUIESCENCE: std::time::Duration = std::time::Duration::from_secs(1);
+
 pub struct MoqWatcher {
-	store: HashMap<String, usize>,
+	/// the segments being read, by their name without the `.tmp` suffix
+	store: HashMap<String, Offset>,
+	/// the segments read completely, the newest last
+	finished: VecDeque<(String, Offset)>,
 	publisher: super::Publisher,
 	re: regex::Regex,
+	poll_interval: Option<std::time::Duration>,
+	/// publish the manifest instead of ignoring it
+	publish_mpd: bool,
+	/// settings to continue with, applied in between two events
+	reloads: tokio::sync::mpsc::UnboundedReceiver<super::reload::Request>,
+	reloads_tx: tokio::sync::mpsc::UnboundedSender<super::reload::Request>,
+	/// window the data events of a file are coalesced in, zero reads on every event
+	debounce: std::time::Duration,
+	/// the files with data events not read yet, in the order of their first event
+	pending: Vec<std::path::PathBuf>,
+	/// when the pending files are read
+	deadline: Option<tokio::time::Instant>,
+	max_read: usize,
+	/// segments without data events for this long are complete, None relies on closes and renames
+	quiescence: Option<std::time::Duration>,
+	/// the path and the last data event of the segments being written, by their name without the `.tmp` suffix
+	quiet: HashMap<String, (std::path::PathBuf, tokio::time::Instant)>,
+}
+
+/// how far a file has been read, the inode detects files replaced under the same name
+#[derive(Debug, Default)]
+struct Offset {
+	position: usize,
+	inode: Option<u64>,
+	/// when the file was last written, as of the last read
+	modified: Option<std::time::SystemTime>,
+	/// kept open in between two reads, positioned at `position`
+	file: Option<tokio::fs::File>,
+	/// the number of reads so far
+	reads: usize,
 }
 
 impl MoqWatcher {
+	/// polls the directory every `poll_interval` instead of relying on inotify, ex. for network filesystems
 	pub fn new(
 		broadcast: moq_transport::serve::TracksWriter,
 		settings: super::Settings<std::path::PathBuf>,
+		poll_interval: Option<std::time::Duration>,
+		metrics: crate::metrics::Metrics,
+		packaging: moq_catalog::Packaging,
+		group_order: super::GroupOrder,
+		object_mode: super::ObjectMode,
 	) -> Result<Self, Error> {
 		let re = match regex::Regex::new(r"rep_(?<rep>\d+)\.m4s") {
 			Ok(r) => r,
 			Err(e) => {
-				println!("Error: {}", e);
-				return Err(Error::Crate("regex".to_string(), e.to_string()));
+				tracing::error!(error = %e);
+				return Err(Error::Regex(e));
 			}
 		};
+		let (reloads_tx, reloads) = tokio::sync::mpsc::unbounded_channel();
 		Ok(Self {
 			store: HashMap::new(),
-			publisher: super::Publisher::new(broadcast, settings)?,
+			finished: VecDeque::new(),
+			publisher: super::Publisher::new(broadcast, settings, metrics, packaging, group_order, object_mode)?,
 			re,
+			poll_interval,
+			publish_mpd: false,
+			reloads,
+			reloads_tx,
+			debounce: DEBOUNCE,
+			pending: Vec::new(),
+			deadline: None,
+			max_read: MAX_READ,
+			// only inotify reports closes
+			quiescence: (!cfg!(target_os = "linux")).then_some(QUIESCENCE),
+			quiet: HashMap::new(),
 		})
 	}
 
+	/// coalesce the data events of a file within `debounce` into a single read, zero reads on every event
+	pub fn set_debounce(&mut self, debounce: std::time::Duration) {
+		self.debounce = debounce;
+	}
+
+	/// a segment without data events for `quiescence` is complete, None waits for its close or rename
+	///
+	/// Defaults to [QUIESCENCE] on the platforms not reporting closes, ex. FSEvents on macOS or Windows.
+	pub fn set_quiescence(&mut self, quiescence: Option<std::time::Duration>) {
+		self.quiescence = quiescence;
+	}
+
+	/// read at most `max_read` bytes at once, larger changes of a file are published in several reads
+	pub fn set_max_read(&mut self, max_read: usize) {
+		self.max_read = max_read.max(1);
+	}
+
+	/// publish the catalog on the track `name`
+	pub fn set_catalog_track(&mut self, name: &str) -> Result<(), Error> {
+		self.publisher.set_catalog_track(name)
+	}
+
+	/// publish a catalog per media type, listed by the root catalog
+	pub fn set_hierarchical_catalog(&mut self, hierarchical: bool) {
+		self.publisher.set_hierarchical_catalog(hierarchical);
+	}
+
+	/// publish the catalogs in the serialization `format`
+	pub fn set_catalog_format(&mut self, format: moq_catalog::CatalogFormat) {
+		self.publisher.set_catalog_format(format);
+	}
+
+	/// publish every version of the manifest on its own track
+	pub fn publish_mpd(&mut self) -> Result<(), Error> {
+		self.publisher.enable_manifest()?;
+		self.publish_mpd = true;
+		Ok(())
+	}
+
+	/// publish the event messages of the segments on their own track instead of in-band
+	pub fn publish_emsg_track(&mut self) -> Result<(), Error> {
+		self.publisher.enable_emsg_track()
+	}
+
+	/// publish the wall clock and media time of every prft on their own track, a new group every `group_duration`
+	pub fn publish_timeline_track(&mut self, group_duration: std::time::Duration) -> Result<(), Error> {
+		self.publisher.enable_timeline_track(group_duration)
+	}
+
+	/// publish the markers of the JSONL file `path` on their own track as the media reaches them
+	pub fn publish_metadata(
+		&mut self,
+		path: std::path::PathBuf,
+		poll_interval: Option<std::time::Duration>,
+	) -> Result<(), Error> {
+		self.publisher.enable_metadata(path, poll_interval)
+	}
+
+	/// send the init segments on their own tracks instead of inlining them in the catalog
+	pub fn set_init_tracks(&mut self, init_tracks: bool) {
+		self.publisher.set_init_tracks(init_tracks);
+	}
+
+	/// end the groups of the audio tracks every `duration` of media
+	pub fn set_audio_group_duration(&mut self, duration: std::time::Duration) {
+		self.publisher.set_audio_group_duration(duration);
+	}
+
+	/// end the groups once they cover `duration` of media, at keyframes for video
+	pub fn set_group_duration(&mut self, duration: std::time::Duration) {
+		self.publisher.set_group_duration(duration);
+	}
+
+	/// wait at most `timeout` for the tracks of all reps before the first catalog
+	pub fn set_catalog_initial_timeout(&mut self, timeout: std::time::Duration) {
+		self.publisher.set_catalog_initial_timeout(timeout);
+	}
+
+	/// write the newest catalog and init segments again every `interval`, for late subscribers
+	pub fn set_republish_interval(&mut self, interval: std::time::Duration) {
+		self.publisher.set_republish_interval(interval);
+	}
+
+	/// start a new group at gaps in the timestamps larger than `threshold`
+	pub fn set_discontinuity_threshold(&mut self, threshold: std::time::Duration) {
+		self.publisher.set_discontinuity_threshold(threshold);
+	}
+
+	/// abandon groups whose writes block longer than `age`, skipping to the next keyframe
+	pub fn set_max_group_age(&mut self, age: std::time::Duration) {
+		self.publisher.set_max_group_age(age);
+	}
+
+	/// fail on misaligned video groups instead of warning
+	pub fn set_strict_alignment(&mut self, strict: bool) {
+		self.publisher.set_strict_alignment(strict);
+	}
+
+	/// fail on a fragment of a trak the init segment does not describe, instead of skipping it
+	pub fn set_strict_traks(&mut self, strict: bool) {
+		self.publisher.set_strict_traks(strict);
+	}
+
+	/// skip the groups of media tracks nobody is subscribed to
+	pub fn set_lazy_tracks(&mut self, lazy: bool) {
+		self.publisher.set_lazy_tracks(lazy);
+	}
+
+	/// split the objects larger than `size` bytes into several objects of the same group
+	pub fn set_max_object_size(&mut self, size: usize) {
+		self.publisher.set_max_object_size(size);
+	}
+
+	/// take atoms declaring more than `size` bytes for corrupt data, skipped up to the next fragment
+	pub fn set_max_atom_size(&mut self, size: usize) {
+		self.publisher.set_max_atom_size(size);
+	}
+
+	/// the base priorities of the audio and the video tracks, taking precedence over the group order
+	pub fn set_priorities(&mut self, audio: u8, video: u8) {
+		self.publisher.set_priorities(audio, video);
+	}
+
+	/// write every published object to `archive` as well
+	pub fn set_archive(&mut self, archive: crate::archive::Archive) {
+		self.publisher.set_archive(archive);
+	}
+
+	/// record every published group and object to `stats`
+	pub fn set_stats(&mut self, stats: crate::stats::Stats) {
+		self.publisher.set_stats(stats);
+	}
+
+	/// derive the track names from the rep settings, fails on duplicate names
+	pub fn set_track_name_template(&mut self, template: super::TrackNameTemplate) -> Result<(), Error> {
+		self.publisher.set_track_name_template(template)
+	}
+
+	/// hands changed settings to the running watcher
+	pub fn reloader(&self) -> super::Reloader {
+		super::Reloader::new(self.reloads_tx.clone())
+	}
+
 	pub async fn run<P>(&mut self, target: P) -> Result<(), Error>
 	where
 		P: AsRef<std::path::Path>,
 	{
-		let (tx, rx) = std::sync::mpsc::channel();
+		let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
 
-		let mut watcher = match notify::recommended_watcher(tx) {
+		let handler = move |event| {
+			let _ = tx.send(event);
+		};
+		let watcher: notify::Result<Box<dyn Watcher + Send>> = match self.poll_interval {
+			Some(interval) => {
+				let config = notify::Config::default().with_poll_interval(interval);
+				notify::PollWatcher::new(handler, config).map(|w| Box::new(w) as _)
+			}
+			None => notify::recommended_watcher(handler).map(|w| Box::new(w) as _),
+		};
+		let mut watcher = match watcher {
 			Ok(w) => w,
 			Err(e) => {
-				println!("Error: {}", e);
-				return Err(Error::Crate("notify".to_string(), e.to_string()));
+				tracing::error!(error = %e);
+				return Err(Error::Notify(e));
 			}
 		};
 
 		if let Err(e) = watcher.watch(target.as_ref(), notify::RecursiveMode::NonRecursive) {
-			println!("Error: {}", e);
-			return Err(Error::Crate("notify".to_string(), e.to_string()));
+			tracing::error!(error = %e);
+			return Err(Error::Notify(e));
 		}
 
-		for event in rx {
+		// segments written before the watch was registered never produce events
+		self.scan(target.as_ref()).await?;
+
+		loop {
+			// a failed rep would otherwise only be noticed on its next chunk
+			// pending events are handled before a reload, they belong to the previous settings
+			let deadline = self.deadline;
+			let settled = self.settled();
+			let event = tokio::select! {
+				biased;
+				e = self.publisher.failed() => return Err(e),
+				// ahead of the events, a steady stream of them must not delay the reads
+				_ = tokio::time::sleep_until(deadline.unwrap_or_else(tokio::time::Instant::now)), if deadline.is_some() => {
+					self.read_pending().await?;
+					continue;
+				}
+				_ = tokio::time::sleep_until(settled.unwrap_or_else(tokio::time::Instant::now)), if settled.is_some() => {
+					self.settle().await?;
+					continue;
+				}
+				event = rx.recv() => event,
+				Some((settings, done)) = self.reloads.recv() => {
+					self.read_pending().await?;
+					let _ = done.send(self.publisher.reload(settings).await);
+					continue;
+				}
+			};
+			let Some(event) = event else {
+				self.read_pending().await?;
+				break;
+			};
+
 			let event = match event {
 				Ok(e) => e,
 				Err(e) => {
-					println!("Error: {}", e);
-					return Err(Error::Crate("notify".to_string(), e.to_string()));
+					tracing::error!(error = %e);
+					return Err(Error::Notify(e));
 				}
 			};
 
@@ -67,144 +320,542 @@ impl MoqWatcher {
 		Ok(())
 	}
 
+	/// publish the segments already in `target`, init segments first and then the chunks in order
+	///
+	/// Events for these files queued in the meantime continue from the stored offsets.
+	async fn scan(&mut self, target: &std::path::Path) -> Result<(), Error> {
+		let entries = match std::fs::read_dir(target) {
+			Ok(e) => e,
+			Err(e) => {
+				tracing::error!(error = %e);
+				return Err(Error::Io(e));
+			}
+		};
+
+		let mut segments = Vec::new();
+		for entry in entries {
+			let path = match entry {
+				Ok(e) => e.path(),
+				Err(e) => {
+					tracing::error!(error = %e);
+					return Err(Error::Io(e));
+				}
+			};
+			let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+				continue;
+			};
+			if !self.re.is_match(name) {
+				continue;
+			}
+
+			// init segments have no chunk number and sort first
+			let chunk = name
+				.split("_chunk_")
+				.nth(1)
+				.and_then(|rest| rest.split('_').next())
+				.and_then(|number| number.parse::<u64>().ok());
+			let rep_id = self.parse_path(&path)?;
+
+			segments.push((chunk, rep_id, path));
+		}
+		segments.sort();
+
+		for (_, _, path) in segments {
+			log::info!("publishing existing segment {}", path.display());
+			self.handle(notify::Event::new(Create(File)).add_path(path.clone()))
+				.await?;
+			self.handle(notify::Event::new(Modify(Data(DataChange::Any))).add_path(path))
+				.await?;
+		}
+
+		// surface broken existing segments before going live
+		self.read_pending().await?;
+		self.publisher.flush().await
+	}
+
+	/// the encoded catalog of the newest version
+	pub fn catalog_snapshot(&self) -> Option<bytes::Bytes> {
+		self.publisher.catalog_snapshot()
+	}
+
+	/// end all published tracks
+	pub async fn close(self) {
+		self.publisher.close().await;
+	}
+
+	/// publish the rest of the segments still open, then end all tracks with a last catalog without them
+	///
+	/// The encoder is done, the events of its last writes may never be handled by [Self::run].
+	pub async fn finish(mut self) -> Result<(), Error> {
+		let res = self.drain().await;
+		self.publisher.finish().await;
+		res
+	}
+
+	/// read the files with deferred events and the segments not closed yet to their end
+	async fn drain(&mut self) -> Result<(), Error> {
+		self.read_pending().await?;
+
+		let mut open: Vec<_> = self.store.keys().map(std::path::PathBuf::from).collect();
+		open.sort();
+		for path in open {
+			self.complete(&[path]).await?;
+		}
+
+		self.publisher.flush().await
+	}
+
+	/// when the next segment is quiet for the quiescence window
+	fn settled(&self) -> Option<tokio::time::Instant> {
+		let quiescence = self.quiescence?;
+		self.quiet.values().map(|(_, last)| *last + quiescence).min()
+	}
+
+	/// complete the segments without data events for the quiescence window, the encoder moved on
+	async fn settle(&mut self) -> Result<(), Error> {
+		let Some(quiescence) = self.quiescence else {
+			return Ok(());
+		};
+		self.read_pending().await?;
+
+		let now = tokio::time::Instant::now();
+		let mut quiet: Vec<_> = self
+			.quiet
+			.values()
+			.filter(|(_, last)| *last + quiescence <= now)
+			.map(|(path, _)| path.clone())
+			.collect();
+		quiet.sort();
+		for path in quiet {
+			log::debug!("no writes to {} for {quiescence:?}, it is complete", path.display());
+			self.complete(&[path]).await?;
+		}
+
+		Ok(())
+	}
+
+	/// read the rest of a segment, nothing more is written to it
+	async fn complete(&mut self, paths: &[std::path::PathBuf]) -> Result<(), Error> {
+		self.send_chunk(paths).await?;
+		self.end_segment(paths)?;
+		self.delete(paths).await
+	}
+
+	/// the last data event of a segment, it is not quiet yet
+	fn touch(&mut self, paths: &[std::path::PathBuf]) {
+		if self.quiescence.is_none() {
+			return;
+		}
+		for path in paths {
+			if let Some(name) = helper::path_to_string(path) {
+				let key = helper::clean_path(&name).to_string();
+				self.quiet.insert(key, (path.clone(), tokio::time::Instant::now()));
+			}
+		}
+	}
+
+	/// the events of FSEvents and Windows as the ones of inotify, which are handled below
+	fn portable(&self, mut event: notify::Event) -> notify::Event {
+		event.kind = match event.kind {
+			// Windows reports writes without their kind
+			Modify(ModifyKind::Any) => Modify(Data(DataChange::Any)),
+			// FSEvents reports both ends of a rename on their own, ffmpeg renames the tmp file
+			Modify(Name(RenameMode::Any)) if self.is_tmp(&event.paths) => Modify(Name(RenameMode::From)),
+			Modify(Name(RenameMode::Any)) => Modify(Name(RenameMode::To)),
+			kind => kind,
+		};
+		event
+	}
+
 	async fn handle(&mut self, event: notify::Event) -> Result<(), Error> {
+		let event = self.portable(event);
 		if self.is_mpd(&event) {
-			return Ok(());
+			return match self.publish_mpd {
+				true => self.handle_mpd(&event).await,
+				false => Ok(()),
+			};
+		}
+		// the other events rely on everything written before them being read
+		if !matches!(event.kind, Modify(Data(_))) {
+			self.read_pending().await?;
+		}
+		if matches!(event.kind, Create(_) | Modify(Data(_))) {
+			self.touch(&event.paths);
 		}
 		match event.kind {
 			Create(File) => {
 				// watch segment files in chunks
 				self.insert(&event.paths).await?;
 			}
-			Modify(Data(_)) => {
+			Modify(Data(_)) if self.debounce.is_zero() => {
 				// new chunk has been written, send to publisher
 				self.send_chunk(&event.paths).await?;
 			}
+			Modify(Data(_)) => {
+				// ffmpeg writes a chunk in several writes, read them at once
+				self.defer(&event.paths)?;
+			}
 			Access(Close(Write)) => {
 				// file is finished, make sure to really have everything
 				self.send_chunk(&event.paths).await?;
+				self.end_segment(&event.paths)?;
 
 				self.delete(&event.paths).await?;
 			}
+			Modify(Name(RenameMode::To)) => {
+				// the source of the rename is the tmp file ffmpeg wrote to
+				let Some(to) = event.paths.first() else {
+					tracing::error!(paths = ?event.paths, "invalid num of paths");
+					return Err(Error::InvalidPathNum(1, 0));
+				};
+				let mut from = to.clone().into_os_string();
+				from.push(".tmp");
+
+				self.rename(&[from.into(), to.clone()]).await?;
+			}
+			Modify(Name(RenameMode::Both)) => {
+				self.rename(&event.paths).await?;
+			}
+			// the poll watcher neither knows file kinds nor closes and renames
+			Create(CreateKind::Any) => self.poll_create(&event.paths).await?,
+			Modify(Metadata(MetadataKind::WriteTime)) if self.is_tmp(&event.paths) => {
+				self.send_chunk(&event.paths).await?;
+			}
 			_ => (),
 		}
 		Ok(())
 	}
 
+	/// the manifest is always rewritten completely, it is published once closed or renamed to its final name
+	async fn handle_mpd(&mut self, event: &notify::Event) -> Result<(), Error> {
+		let path = match event.kind {
+			Access(Close(Write)) | Modify(Name(RenameMode::To)) => event.paths.first(),
+			Modify(Name(RenameMode::Both)) => event.paths.get(1),
+			// the poll watcher neither knows closes nor renames
+			Create(CreateKind::Any) | Modify(Metadata(MetadataKind::WriteTime)) => event.paths.first(),
+			_ => None,
+		};
+		// the tmp file is complete once renamed
+		let Some(path) = path.filter(|path| !self.is_tmp(std::slice::from_ref(path))) else {
+			return Ok(());
+		};
+
+		let manifest = match tokio::fs::read(path).await {
+			Ok(m) => m,
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+				log::debug!("{} replaced before it was read", path.display());
+				return Ok(());
+			}
+			Err(e) => {
+				tracing::error!(error = %e);
+				return Err(Error::Io(e));
+			}
+		};
+
+		self.publisher.publish_manifest(manifest.into())
+	}
+
+	/// a tmp file was renamed to its final name, publish what was written since the last read
+	///
+	/// Both share the offset of the segment, without one it was already completely published on close.
+	async fn rename(&mut self, paths: &[std::path::PathBuf]) -> Result<(), Error> {
+		if paths.len() != 2 {
+			tracing::error!(?paths, "invalid num of paths");
+			return Err(Error::InvalidPathNum(2, paths.len()));
+		}
+
+		let (Some(from), Some(to)) = (helper::path_to_string(&paths[0]), helper::path_to_string(&paths[1])) else {
+			tracing::error!(?paths, "could not convert path to string");
+			return Err(Error::FailedToConvert);
+		};
+
+		if !self.store.contains_key(helper::clean_path(&from)) {
+			log::debug!("{from} renamed to {to} after it was closed");
+			return Ok(());
+		}
+
+		self.send_chunk(&paths[1..]).await?;
+		self.end_segment(&paths[1..])?;
+		self.delete(&paths[1..]).await?;
+
+		Ok(())
+	}
+
+	/// tmp files are read as they grow, a final file replaces its tmp file or was written in between two polls
+	async fn poll_create(&mut self, paths: &[std::path::PathBuf]) -> Result<(), Error> {
+		if self.is_tmp(paths) {
+			self.insert(paths).await?;
+			return self.send_chunk(paths).await;
+		}
+
+		let Some(to) = paths.first() else {
+			tracing::error!(?paths, "invalid num of paths");
+			return Err(Error::InvalidPathNum(1, 0));
+		};
+		let mut from = to.clone().into_os_string();
+		from.push(".tmp");
+
+		match helper::path_to_string(to) {
+			Some(to) if self.store.contains_key(&to) => self.rename(&[from.into(), to.into()]).await,
+			_ => {
+				self.send_chunk(paths).await?;
+				self.end_segment(paths)?;
+				self.delete(paths).await
+			}
+		}
+	}
+
+	/// read `paths` once the debounce window passed, or before the next other event
+	fn defer(&mut self, paths: &[std::path::PathBuf]) -> Result<(), Error> {
+		if paths.len() != 1 {
+			tracing::error!(?paths, "invalid num of paths");
+			return Err(Error::InvalidPathNum(1, paths.len()));
+		}
+
+		if self.pending.is_empty() {
+			self.deadline = Some(tokio::time::Instant::now() + self.debounce);
+		}
+		if !self.pending.contains(&paths[0]) {
+			self.pending.push(paths[0].clone());
+		}
+
+		Ok(())
+	}
+
+	/// read the files with deferred data events
+	async fn read_pending(&mut self) -> Result<(), Error> {
+		self.deadline = None;
+		for path in std::mem::take(&mut self.pending) {
+			self.send_chunk(&[path]).await?;
+		}
+		Ok(())
+	}
+
+	#[tracing::instrument(skip_all, fields(path = ?paths))]
 	async fn send_chunk(&mut self, paths: &[std::path::PathBuf]) -> Result<(), Error> {
 		if paths.len() != 1 {
-			println!("Error: invalid num of paths");
+			tracing::error!(?paths, "invalid num of paths");
 			return Err(Error::InvalidPathNum(1, paths.len()));
 		}
 
 		let path = &paths[0];
-		let chunk = self.read_chunk(&path).await?;
+		loop {
+			let chunk = self.read_chunk(&path).await.context("reading a chunk")?;
 
-		if chunk.is_empty() {
-			return Ok(());
+			if chunk.is_empty() {
+				return Ok(());
+			}
+
+			// a shorter read reached the end of the file
+			let end = chunk.len() < self.max_read;
+
+			let rep_id = self.parse_path(path)?;
+			self.publisher.publish(rep_id, chunk)?;
+
+			if end {
+				return Ok(());
+			}
 		}
+	}
 
-		let rep_id = self.parse_path(path)?;
-		self.publisher.publish(rep_id, &chunk)?;
+	/// the segment file is complete, nothing more is read from it
+	fn end_segment(&mut self, paths: &[std::path::PathBuf]) -> Result<(), Error> {
+		let Some(path) = paths.first() else {
+			tracing::error!(?paths, "invalid num of paths");
+			return Err(Error::InvalidPathNum(1, 0));
+		};
 
-		Ok(())
+		let rep_id = self.parse_path(path)?;
+		self.publisher.end_segment(rep_id)
 	}
 
-	async fn read_chunk<P>(&mut self, path: P) -> Result<Vec<u8>, Error>
+	async fn read_chunk<P>(&mut self, path: P) -> Result<bytes::Bytes, Error>
 	where
 		P: AsRef<std::path::Path>,
 	{
-		let Some(path) = helper::path_to_string(path) else {
-			println!("Error: could not convert path to string");
+		let Some(path) = helper::path_to_string(&path) else {
+			tracing::error!(path = %path.as_ref().display(), "could not convert path to string");
 			return Err(Error::FailedToConvert);
 		};
 
-		let offset = self.get(&path).await;
-
-		let mut fp = match tokio::fs::File::open(&path).await {
-			Ok(f) => f,
+		// the tmp file may have been renamed since the event
+		let metadata = match tokio::fs::metadata(&path).await {
+			Ok(m) => m,
 			Err(e) => {
 				if e.kind() != std::io::ErrorKind::NotFound {
-					println!("Error: missing file");
-					return Err(Error::Crate("tokio::fs".to_string(), e.to_string()));
+					tracing::error!(path, error = %e, "missing file");
+					return Err(Error::Io(e));
 				}
-				match tokio::fs::File::open(path.replace(".tmp", "")).await {
-					Ok(f) => f,
+				match tokio::fs::metadata(helper::counterpart(&path)).await {
+					Ok(m) => m,
 					Err(e) => {
-						println!("Error: missing file");
-						return Err(Error::Crate("tokio::fs".to_string(), e.to_string()));
+						tracing::error!(path, error = %e, "missing file");
+						return Err(Error::Io(e));
 					}
 				}
 			}
 		};
+		let size = metadata.len() as usize;
+		let inode = helper::inode(&metadata);
+		let modified = metadata.modified().ok();
+
+		// events were missed and the file was truncated or replaced, the stored offset is meaningless
+		let key = helper::clean_path(&path).to_string();
+		let mut offset = match self.store.remove(&key) {
+			Some(offset) => offset,
+			None => self.reopen(&key, inode, modified),
+		};
+		if size < offset.position || (offset.inode.is_some() && offset.inode != inode) {
+			log::warn!("{path} was truncated or replaced, resyncing from the start");
+			let rep_id = self.parse_path(&path)?;
+			self.publisher.reset(rep_id);
+			offset.position = 0;
+			offset.file = None;
+		}
+		offset.inode = inode;
+		offset.modified = modified;
 
-		if let Err(e) = fp.seek(std::io::SeekFrom::Start(offset as u64)).await {
-			println!("Error: {}", e);
-			return Err(Error::Crate("tokio::fs".to_string(), e.to_string()));
+		let len = (size - offset.position).min(self.max_read);
+		if len == 0 {
+			self.store.insert(key, offset);
+			return Ok(bytes::Bytes::new());
 		}
 
-		let size = match fp.metadata().await {
-			Ok(m) => m.len() as usize,
-			Err(e) => {
-				println!("Error: {}", e);
-				return Err(Error::Crate("tokio::fs".to_string(), e.to_string()));
+		let mut fp = match offset.file.take() {
+			Some(f) => f,
+			None => self.open(&path, offset.position).await?,
+		};
+
+		// read into uninitialized memory, frozen without copying it
+		let mut chunk = bytes::BytesMut::with_capacity(len);
+		while chunk.len() < len {
+			match (&mut fp).take((len - chunk.len()) as u64).read_buf(&mut chunk).await {
+				Ok(0) => {
+					let e = std::io::Error::from(std::io::ErrorKind::UnexpectedEof);
+					tracing::error!(error = %e);
+					return Err(Error::Io(e));
+				}
+				Ok(_) => (),
+				Err(e) => {
+					tracing::error!(error = %e);
+					return Err(Error::Io(e));
+				}
 			}
+		}
+
+		offset.position += len;
+		offset.file = Some(fp);
+		offset.reads += 1;
+		self.store.insert(key, offset);
+
+		Ok(chunk.freeze())
+	}
+
+	/// the offset of the segment `key` read completely before, if it is still the same, unchanged file
+	///
+	/// Late events for a closed or renamed segment then publish nothing, instead of all its bytes again.
+	fn reopen(&mut self, key: &str, inode: Option<u64>, modified: Option<std::time::SystemTime>) -> Offset {
+		let Some(index) = self.finished.iter().position(|(name, _)| name == key) else {
+			return Offset::default();
 		};
 
-		let mut chunk = vec![0u8; size - offset];
-		let read = match fp.read_exact(&mut chunk).await {
-			Ok(r) => r,
+		match self.finished.remove(index) {
+			Some((_, offset)) if offset.inode.is_some() && offset.inode == inode && offset.modified == modified => {
+				offset
+			}
+			// a new file under the same name or rewritten in place, ex. after ffmpeg restarted
+			_ => Offset::default(),
+		}
+	}
+
+	/// open `path`, or the file it was renamed to, at `position`
+	async fn open(&self, path: &str, position: usize) -> Result<tokio::fs::File, Error> {
+		let mut fp = match tokio::fs::File::open(path).await {
+			Ok(f) => f,
 			Err(e) => {
-				println!("Error: {}", e);
-				return Err(Error::Crate("tokio::fs".to_string(), e.to_string()));
+				if e.kind() != std::io::ErrorKind::NotFound {
+					tracing::error!(path, error = %e, "missing file");
+					return Err(Error::Io(e));
+				}
+				match tokio::fs::File::open(helper::counterpart(path)).await {
+					Ok(f) => f,
+					Err(e) => {
+						tracing::error!(path, error = %e, "missing file");
+						return Err(Error::Io(e));
+					}
+				}
 			}
 		};
 
-		assert_eq!(read, size - offset);
-
-		self.set(&path, size).await;
+		if let Err(e) = fp.seek(std::io::SeekFrom::Start(position as u64)).await {
+			tracing::error!(error = %e);
+			return Err(Error::Io(e));
+		}
 
-		Ok(chunk)
+		Ok(fp)
 	}
 
 	async fn insert(&mut self, paths: &[std::path::PathBuf]) -> Result<(), Error> {
 		if paths.len() != 1 {
-			println!("Error: invalid num of paths");
+			tracing::error!(?paths, "invalid num of paths");
 			return Err(Error::InvalidPathNum(1, paths.len()));
 		}
 
 		let path = &paths[0];
 		let Some(path) = helper::path_to_string(path) else {
-			println!("Error: could not convert path to string");
+			tracing::error!(path = %path.display(), "could not convert path to string");
 			return Err(Error::FailedToConvert);
 		};
 
-		if !path.ends_with(".m4s.tmp") {
+		// already picked up by the initial scan
+		if !path.ends_with(".m4s.tmp") || self.store.contains_key(helper::clean_path(&path)) {
 			return Ok(());
 		}
 
-		self.set(&path, 0).await;
+		// a new init segment means the encoder (re)started, leftovers of the previous run are useless
+		if path.contains("_init_") {
+			let rep_id = self.parse_path(path.replace(".tmp", ""))?;
+			self.publisher.reset(rep_id);
+		}
+
+		self.set(&path, Offset::default()).await;
 
 		Ok(())
 	}
 
 	async fn delete(&mut self, paths: &[std::path::PathBuf]) -> Result<(), Error> {
 		if paths.len() != 1 {
-			println!("Error: invalid num of paths");
+			tracing::error!(?paths, "invalid num of paths");
 			return Err(Error::InvalidPathNum(1, paths.len()));
 		}
 
 		let path = &paths[0];
 		let Some(path) = helper::path_to_string(path) else {
-			println!("Error: could not convert path to string");
+			tracing::error!(path = %path.display(), "could not convert path to string");
 			return Err(Error::FailedToConvert);
 		};
 
-		self.store.remove(&path);
+		// closes the file, the offset is kept for late events
+		let key = helper::clean_path(&path);
+		self.quiet.remove(key);
+		if let Some(mut offset) = self.store.remove(key) {
+			log::debug!("{path} read in {} reads", offset.reads);
+			offset.file = None;
+			if self.finished.len() == FINISHED {
+				self.finished.pop_front();
+			}
+			self.finished.push_back((key.to_string(), offset));
+		}
 
 		Ok(())
 	}
 
+	fn is_tmp(&self, paths: &[std::path::PathBuf]) -> bool {
+		paths
+			.iter()
+			.all(|path| path.extension().is_some_and(|ext| ext == "tmp"))
+	}
+
 	fn is_mpd(&self, event: &notify::Event) -> bool {
 		for path in &event.paths {
 			let Some(path) = helper::path_to_string(path) else {
@@ -221,15 +872,15 @@ impl MoqWatcher {
 	where
 		P: AsRef<std::path::Path>,
 	{
-		let Some(path) = helper::path_to_string(path) else {
-			println!("Error: could not convert path to string");
+		let Some(path) = helper::path_to_string(&path) else {
+			tracing::error!(path = %path.as_ref().display(), "could not convert path to string");
 			return Err(Error::FailedToConvert);
 		};
 
 		let matches = match self.re.captures(&path) {
 			Some(m) => m,
 			None => {
-				println!("Error: missing rep id in path");
+				tracing::error!(path, "missing rep id in path");
 				return Err(Error::Missing);
 			}
 		};
@@ -237,7 +888,7 @@ impl MoqWatcher {
 		let rep_id = match matches["rep"].parse() {
 			Ok(r) => r,
 			Err(_) => {
-				println!("Error: failed to parse {} to usize", &matches["rep"]);
+				tracing::error!(path, rep = &matches["rep"], "failed to parse the rep id");
 				return Err(Error::FailedToConvert);
 			}
 		};
@@ -245,12 +896,488 @@ impl MoqWatcher {
 		Ok(rep_id)
 	}
 
-	async fn get(&self, key: &str) -> usize {
-		let value = self.store.get(key);
-		*value.unwrap_or(&0)
+	/// start reading the segment of `path` at `offset`, replacing what is known about it
+	async fn set(&mut self, path: &str, offset: Offset) {
+		let key = helper::clean_path(path);
+		self.finished.retain(|(name, _)| name != key);
+		self.store.insert(key.to_string(), offset);
 	}
+}
 
-	async fn set(&mut self, key: &str, offset: usize) {
-		self.store.insert(key.to_string(), offset);
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing::temp_dir;
+	use std::path;
+
+	const SETTINGS: &str = "gop_num=1
+fps=25
+target_segment_duration=2.0
+===AUDIO===
+name,sampling,bitrate
+===VIDEO===
+name,resolution,bitrate,max_rate,buffer_size
+video,1280x720,3000000,3000000,6000000
+";
+
+	fn watcher() -> (MoqWatcher, moq_transport::serve::TracksReader) {
+		let settings = super::super::Settings::from_bytes(
+			SETTINGS.as_bytes().to_vec(),
+			"input".into(),
+			"output".into(),
+			true,
+			false,
+		)
+		.unwrap();
+		let (writer, _, reader) = moq_transport::serve::Tracks::new("test".to_string()).produce();
+
+		let mut watcher = MoqWatcher::new(
+			writer,
+			settings,
+			None,
+			Default::default(),
+			Default::default(),
+			Default::default(),
+			Default::default(),
+		)
+		.unwrap();
+		// every data event is read immediately
+		watcher.set_debounce(std::time::Duration::ZERO);
+
+		(watcher, reader)
+	}
+
+	fn event(kind: notify::EventKind, paths: &[&path::Path]) -> notify::Event {
+		paths.iter().fold(notify::Event::new(kind), |event, path| {
+			event.add_path(path.to_path_buf())
+		})
+	}
+
+	/// (group id, last object id) of the latest group of the video track
+	async fn latest_group(reader: &mut moq_transport::serve::TracksReader) -> Option<(u64, u64)> {
+		match reader.subscribe("video")?.mode().await.ok()? {
+			moq_transport::serve::TrackReaderMode::Groups(groups) => groups.latest(),
+			_ => None,
+		}
+	}
+
+	/// every rewrite of the manifest is a group, a tmp file is only published once renamed
+	#[tokio::test]
+	async fn test_mpd() {
+		let dir = temp_dir("watcher-mpd");
+		let (mut watcher, mut reader) = watcher();
+		watcher.publish_mpd().unwrap();
+
+		let moq_transport::serve::TrackReaderMode::Groups(mut groups) =
+			reader.subscribe(".mpd").unwrap().mode().await.unwrap()
+		else {
+			panic!("expected groups mode");
+		};
+
+		let mpd = dir.join("source.mpd");
+		let tmp = dir.join("source.mpd.tmp");
+		for i in 0..3 {
+			std::fs::write(&tmp, format!("<MPD version=\"{i}\"/>")).unwrap();
+			watcher.handle(event(Create(File), &[&tmp])).await.unwrap();
+			watcher.handle(event(Access(Close(Write)), &[&tmp])).await.unwrap();
+
+			std::fs::rename(&tmp, &mpd).unwrap();
+			watcher
+				.handle(event(Modify(Name(RenameMode::Both)), &[&tmp, &mpd]))
+				.await
+				.unwrap();
+		}
+
+		// written in place and closed, but unchanged
+		watcher.handle(event(Access(Close(Write)), &[&mpd])).await.unwrap();
+
+		let mut group = groups.next().await.unwrap().unwrap();
+		assert_eq!(group.group_id, 2);
+		assert_eq!(group.read_next().await.unwrap().unwrap(), std::fs::read(&mpd).unwrap());
+
+		let _ = std::fs::remove_dir_all(&dir);
+
+		// skipped by default
+		let (mut skipping, mut reader) = self::watcher();
+		skipping.handle(event(Access(Close(Write)), &[&mpd])).await.unwrap();
+		assert!(reader.subscribe(".mpd").is_none());
+	}
+
+	/// ffmpeg writes `<name>.tmp` and renames it, the close may or may not be reported before the rename
+	#[tokio::test]
+	async fn test_rename() {
+		let dir = temp_dir("watcher-rename");
+		let (mut watcher, mut reader) = watcher();
+
+		let segments: [(&[u8], &str); 3] = [
+			(
+				include_bytes!("../../tests/fixtures/avc_init.m4s"),
+				"source_init_rep_0.m4s",
+			),
+			(
+				include_bytes!("../../tests/fixtures/chunk_1.m4s"),
+				"source_chunk_00001_rep_0.m4s",
+			),
+			(
+				include_bytes!("../../tests/fixtures/chunk_2.m4s"),
+				"source_chunk_00002_rep_0.m4s",
+			),
+		];
+
+		for (i, (segment, name)) in segments.into_iter().enumerate() {
+			let to = dir.join(name);
+			let from = dir.join(format!("{name}.tmp"));
+
+			// only half of the segment is written before the first read
+			let (head, _) = segment.split_at(segment.len() / 2);
+			std::fs::write(&from, head).unwrap();
+			watcher.handle(event(Create(File), &[&from])).await.unwrap();
+			watcher
+				.handle(event(Modify(Data(DataChange::Any)), &[&from]))
+				.await
+				.unwrap();
+
+			std::fs::write(&from, segment).unwrap();
+			match i {
+				// rename reported as separate from and to events, without a close
+				0 => {
+					std::fs::rename(&from, &to).unwrap();
+					watcher
+						.handle(event(Modify(Name(RenameMode::From)), &[&from]))
+						.await
+						.unwrap();
+					watcher
+						.handle(event(Modify(Name(RenameMode::To)), &[&to]))
+						.await
+						.unwrap();
+				}
+				// rename reported as a single event, without a close
+				1 => {
+					std::fs::rename(&from, &to).unwrap();
+					watcher
+						.handle(event(Modify(Name(RenameMode::Both)), &[&from, &to]))
+						.await
+						.unwrap();
+				}
+				// close before the rename, nothing must be published twice
+				_ => {
+					watcher.handle(event(Access(Close(Write)), &[&from])).await.unwrap();
+					std::fs::rename(&from, &to).unwrap();
+					watcher
+						.handle(event(Modify(Name(RenameMode::Both)), &[&from, &to]))
+						.await
+						.unwrap();
+				}
+			}
+
+			assert!(watcher.store.is_empty());
+			watcher.publisher.flush().await.unwrap();
+		}
+
+		let _ = std::fs::remove_dir_all(&dir);
+
+		// moof and mdat of both chunks in a single group
+		assert_eq!(latest_group(&mut reader).await, Some((0, 3)));
+	}
+
+	/// FSEvents and Windows neither report closes nor renames as a single event, segments written in place go quiet
+	#[tokio::test]
+	async fn test_portable_events() {
+		let dir = temp_dir("watcher-portable");
+		let quiescence = std::time::Duration::from_millis(200);
+
+		for platform in ["macos", "windows", "in place"] {
+			let (mut watcher, mut reader) = watcher();
+			watcher.set_quiescence(Some(quiescence));
+
+			for (segment, name) in [
+				(
+					&include_bytes!("../../tests/fixtures/avc_init.m4s")[..],
+					"source_init_rep_0.m4s",
+				),
+				(
+					include_bytes!("../../tests/fixtures/chunk_1.m4s"),
+					"source_chunk_00001_rep_0.m4s",
+				),
+				(
+					include_bytes!("../../tests/fixtures/chunk_2.m4s"),
+					"source_chunk_00002_rep_0.m4s",
+				),
+			] {
+				let to = dir.join(name);
+				let from = match platform {
+					"in place" => to.clone(),
+					_ => dir.join(format!("{name}.tmp")),
+				};
+				let (head, _) = segment.split_at(segment.len() / 2);
+
+				std::fs::write(&from, head).unwrap();
+				let (create, modify) = match platform {
+					"windows" => (Create(CreateKind::Any), Modify(ModifyKind::Any)),
+					_ => (Create(File), Modify(Data(DataChange::Content))),
+				};
+				watcher.handle(event(create, &[&from])).await.unwrap();
+				watcher.handle(event(modify, &[&from])).await.unwrap();
+				std::fs::write(&from, segment).unwrap();
+				watcher.handle(event(modify, &[&from])).await.unwrap();
+
+				match platform {
+					"macos" => {
+						std::fs::rename(&from, &to).unwrap();
+						for path in [&from, &to] {
+							watcher
+								.handle(event(Modify(Name(RenameMode::Any)), &[path]))
+								.await
+								.unwrap();
+						}
+					}
+					"windows" => {
+						std::fs::rename(&from, &to).unwrap();
+						watcher
+							.handle(event(Modify(Name(RenameMode::From)), &[&from]))
+							.await
+							.unwrap();
+						watcher
+							.handle(event(Modify(Name(RenameMode::To)), &[&to]))
+							.await
+							.unwrap();
+					}
+					_ => {
+						// still written to until no more events arrive
+						watcher.settle().await.unwrap();
+						assert!(!watcher.store.is_empty());
+						tokio::time::sleep(quiescence).await;
+						watcher.settle().await.unwrap();
+					}
+				}
+
+				assert!(watcher.store.is_empty(), "{platform}: {name} not complete");
+				assert!(watcher.quiet.is_empty());
+				watcher.publisher.flush().await.unwrap();
+			}
+
+			// moof and mdat of both chunks in a single group
+			assert_eq!(latest_group(&mut reader).await, Some((0, 3)), "{platform}");
+			for entry in std::fs::read_dir(&dir).unwrap() {
+				std::fs::remove_file(entry.unwrap().path()).unwrap();
+			}
+		}
+
+		let _ = std::fs::remove_dir_all(&dir);
+	}
+
+	/// events were missed while the tmp file was truncated or replaced, the read starts over
+	#[tokio::test]
+	async fn test_resync() {
+		let dir = temp_dir("watcher-resync");
+		let (mut watcher, mut reader) = watcher();
+
+		let init = dir.join("source_init_rep_0.m4s.tmp");
+		std::fs::write(&init, include_bytes!("../../tests/fixtures/avc_init.m4s")).unwrap();
+		watcher.handle(event(Create(File), &[&init])).await.unwrap();
+		watcher.handle(event(Access(Close(Write)), &[&init])).await.unwrap();
+
+		let chunk_1 = include_bytes!("../../tests/fixtures/chunk_1.m4s");
+		let chunk_2 = include_bytes!("../../tests/fixtures/chunk_2.m4s");
+		let chunk_3 = include_bytes!("../../tests/fixtures/chunk_3.m4s");
+
+		// a complete fragment followed by an incomplete one
+		let tmp = dir.join("source_chunk_00001_rep_0.m4s.tmp");
+		std::fs::write(&tmp, [&chunk_1[..], &chunk_2[..20]].concat()).unwrap();
+		watcher.handle(event(Create(File), &[&tmp])).await.unwrap();
+		watcher
+			.handle(event(Modify(Data(DataChange::Any)), &[&tmp]))
+			.await
+			.unwrap();
+		watcher.publisher.flush().await.unwrap();
+		assert_eq!(latest_group(&mut reader).await, Some((0, 1)));
+
+		// truncated below the offset, the incomplete fragment is dropped
+		std::fs::write(&tmp, chunk_3).unwrap();
+		watcher
+			.handle(event(Modify(Data(DataChange::Any)), &[&tmp]))
+			.await
+			.unwrap();
+		watcher.publisher.flush().await.unwrap();
+		assert_eq!(latest_group(&mut reader).await, Some((1, 1)));
+
+		// replaced by a larger file, the offset would be valid but belongs to the old file
+		let replacement = dir.join("replacement");
+		std::fs::write(&replacement, [&chunk_3[..], &chunk_2[..]].concat()).unwrap();
+		std::fs::rename(&replacement, &tmp).unwrap();
+		watcher
+			.handle(event(Modify(Data(DataChange::Any)), &[&tmp]))
+			.await
+			.unwrap();
+		watcher.publisher.flush().await.unwrap();
+		assert_eq!(latest_group(&mut reader).await, Some((2, 3)));
+
+		let _ = std::fs::remove_dir_all(&dir);
+	}
+
+	/// a burst of writes is read at once, in reads of at most the max read size, and published the same
+	#[tokio::test]
+	async fn test_debounce() {
+		let dir = temp_dir("watcher-debounce");
+		let chunk = [
+			&include_bytes!("../../tests/fixtures/chunk_1.m4s")[..],
+			&include_bytes!("../../tests/fixtures/chunk_2.m4s")[..],
+		]
+		.concat();
+		let writes = chunk.chunks(64).count();
+
+		let mut published = Vec::new();
+		for (debounce, max_read, reads) in [
+			(std::time::Duration::ZERO, MAX_READ, writes),
+			(DEBOUNCE, MAX_READ, 1),
+			(DEBOUNCE, 100, chunk.len().div_ceil(100)),
+		] {
+			let (mut watcher, mut reader) = watcher();
+			watcher.set_debounce(debounce);
+			watcher.set_max_read(max_read);
+
+			let init = dir.join("source_init_rep_0.m4s.tmp");
+			std::fs::write(&init, include_bytes!("../../tests/fixtures/avc_init.m4s")).unwrap();
+			watcher.handle(event(Create(File), &[&init])).await.unwrap();
+			watcher.handle(event(Access(Close(Write)), &[&init])).await.unwrap();
+			watcher.publisher.flush().await.unwrap();
+			let moq_transport::serve::TrackReaderMode::Groups(mut groups) =
+				reader.subscribe("video").unwrap().mode().await.unwrap()
+			else {
+				panic!("expected groups mode");
+			};
+
+			let tmp = dir.join("source_chunk_00001_rep_0.m4s.tmp");
+			std::fs::write(&tmp, b"").unwrap();
+			watcher.handle(event(Create(File), &[&tmp])).await.unwrap();
+			let mut file = std::fs::OpenOptions::new().append(true).open(&tmp).unwrap();
+			for write in chunk.chunks(64) {
+				std::io::Write::write_all(&mut file, write).unwrap();
+				watcher
+					.handle(event(Modify(Data(DataChange::Any)), &[&tmp]))
+					.await
+					.unwrap();
+			}
+			watcher.read_pending().await.unwrap();
+			assert_eq!(watcher.store[helper::clean_path(tmp.to_str().unwrap())].reads, reads);
+
+			watcher.handle(event(Access(Close(Write)), &[&tmp])).await.unwrap();
+			assert!(watcher.store.is_empty());
+			watcher.publisher.flush().await.unwrap();
+			watcher.close().await;
+
+			let mut objects = Vec::new();
+			let mut group = groups.next().await.unwrap().unwrap();
+			while let Ok(Some(object)) = group.read_next().await {
+				objects.push(object);
+			}
+			published.push(objects);
+		}
+
+		let _ = std::fs::remove_dir_all(&dir);
+
+		// moof and mdat of both chunks
+		assert_eq!(published[0].len(), 4);
+		assert_eq!(published[0].concat(), chunk);
+		assert!(published.iter().all(|objects| *objects == published[0]));
+	}
+
+	/// events for the tmp file and the final segment share one offset, no byte is published twice
+	#[tokio::test]
+	async fn test_canonical_offsets() {
+		let dir = temp_dir("watcher-canonical");
+		let (mut watcher, mut reader) = watcher();
+
+		let init = dir.join("source_init_rep_0.m4s.tmp");
+		std::fs::write(&init, include_bytes!("../../tests/fixtures/avc_init.m4s")).unwrap();
+		watcher.handle(event(Create(File), &[&init])).await.unwrap();
+		watcher.handle(event(Access(Close(Write)), &[&init])).await.unwrap();
+		watcher.publisher.flush().await.unwrap();
+		let moq_transport::serve::TrackReaderMode::Groups(mut groups) =
+			reader.subscribe("video").unwrap().mode().await.unwrap()
+		else {
+			panic!("expected groups mode");
+		};
+
+		let chunk_1 = include_bytes!("../../tests/fixtures/chunk_1.m4s");
+		let chunk_2 = include_bytes!("../../tests/fixtures/chunk_2.m4s");
+		let tmp = dir.join("source_chunk_00001_rep_0.m4s.tmp");
+		let segment = dir.join("source_chunk_00001_rep_0.m4s");
+
+		// the first chunk read from the tmp file, the second one only once renamed
+		std::fs::write(&tmp, chunk_1).unwrap();
+		watcher.handle(event(Create(File), &[&tmp])).await.unwrap();
+		watcher
+			.handle(event(Modify(Data(DataChange::Any)), &[&tmp]))
+			.await
+			.unwrap();
+		std::fs::write(&tmp, [&chunk_1[..], &chunk_2[..]].concat()).unwrap();
+		std::fs::rename(&tmp, &segment).unwrap();
+		watcher
+			.handle(event(Modify(Name(RenameMode::To)), &[&segment]))
+			.await
+			.unwrap();
+
+		// late events for the final segment
+		watcher
+			.handle(event(Modify(Data(DataChange::Any)), &[&segment]))
+			.await
+			.unwrap();
+		watcher.handle(event(Access(Close(Write)), &[&segment])).await.unwrap();
+		assert!(watcher.store.is_empty());
+		watcher.publisher.flush().await.unwrap();
+		watcher.close().await;
+
+		let mut objects = Vec::new();
+		let mut group = groups.next().await.unwrap().unwrap();
+		while let Ok(Some(object)) = group.read_next().await {
+			objects.push(object);
+		}
+
+		let _ = std::fs::remove_dir_all(&dir);
+
+		assert_eq!(objects.concat(), [&chunk_1[..], &chunk_2[..]].concat());
+	}
+
+	/// the encoder finished, the rest of a segment without events is still published
+	#[tokio::test]
+	async fn test_finish() {
+		let dir = temp_dir("watcher-finish");
+		let (mut watcher, mut reader) = watcher();
+
+		let init = dir.join("source_init_rep_0.m4s.tmp");
+		std::fs::write(&init, include_bytes!("../../tests/fixtures/avc_init.m4s")).unwrap();
+		watcher.handle(event(Create(File), &[&init])).await.unwrap();
+		watcher.handle(event(Access(Close(Write)), &[&init])).await.unwrap();
+
+		let chunk_1 = include_bytes!("../../tests/fixtures/chunk_1.m4s");
+		let chunk_2 = include_bytes!("../../tests/fixtures/chunk_2.m4s");
+		let tmp = dir.join("source_chunk_00001_rep_0.m4s.tmp");
+		std::fs::write(&tmp, chunk_1).unwrap();
+		watcher.handle(event(Create(File), &[&tmp])).await.unwrap();
+		watcher
+			.handle(event(Modify(Data(DataChange::Any)), &[&tmp]))
+			.await
+			.unwrap();
+
+		// the last write and the close are never reported
+		std::fs::write(&tmp, [&chunk_1[..], &chunk_2[..]].concat()).unwrap();
+		watcher.finish().await.unwrap();
+
+		let _ = std::fs::remove_dir_all(&dir);
+
+		assert_eq!(latest_group(&mut reader).await, Some((0, 3)));
+	}
+
+	#[tokio::test]
+	async fn test_rename_paths() {
+		let (mut watcher, _reader) = watcher();
+		let res = watcher
+			.handle(event(
+				Modify(Name(RenameMode::Both)),
+				&[path::Path::new("source_chunk_00001_rep_0.m4s")],
+			))
+			.await;
+		assert!(matches!(res, Err(Error::InvalidPathNum(2, 1))));
 	}
 }
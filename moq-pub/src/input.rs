@@ -0,0 +1,362 @@
+use std::{path, time};
+
+use anyhow::Context;
+use bytes::BytesMut;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::Media;
+
+/// how often a regular file is checked for growth once its end was reached
+const POLL_INTERVAL: time::Duration = time::Duration::from_millis(100);
+
+/// the most bytes buffered without a complete atom by default, more is not a fragmented MP4 stream
+pub const MAX_BUFFER: usize = 64 << 20;
+
+/// What happens once the end of the input is reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum OnEof {
+	/// publish the rest and stop
+	#[default]
+	Exit,
+	/// keep reading: a regular file once it grows, a named pipe once it is opened by the next writer
+	Wait,
+}
+
+/// The fMP4 stream of the original publisher: stdin, a regular file or a named pipe.
+pub struct Input {
+	source: Source,
+	on_eof: OnEof,
+	max_buffer: usize,
+	/// nothing is read before this wall-clock time
+	start: Option<time::SystemTime>,
+}
+
+enum Source {
+	Reader(Box<dyn AsyncRead + Send + Unpin>),
+	File {
+		path: path::PathBuf,
+		file: tokio::fs::File,
+		/// a regular file grows, anything else is reopened
+		regular: bool,
+	},
+}
+
+impl Input {
+	pub fn stdin() -> Self {
+		Self::reader(tokio::io::stdin())
+	}
+
+	/// read from `reader` until its end, ex. stdin
+	pub fn reader<R>(reader: R) -> Self
+	where
+		R: AsyncRead + Send + Unpin + 'static,
+	{
+		Self {
+			source: Source::Reader(Box::new(reader)),
+			on_eof: OnEof::Exit,
+			max_buffer: MAX_BUFFER,
+			start: None,
+		}
+	}
+
+	/// read from the file or named pipe at `path`, waits for it to be created if missing
+	pub async fn open<P>(path: P, on_eof: OnEof) -> anyhow::Result<Self>
+	where
+		P: AsRef<path::Path>,
+	{
+		let path = path.as_ref().to_path_buf();
+		wait_for(&path).await?;
+
+		let file = open(&path).await?;
+		let regular = file.metadata().await.context("failed to stat input")?.is_file();
+
+		Ok(Self {
+			source: Source::File { path, file, regular },
+			on_eof,
+			max_buffer: MAX_BUFFER,
+			start: None,
+		})
+	}
+
+	/// fail once more than `max_buffer` bytes could not be parsed, instead of buffering an unparsable stream forever
+	pub fn max_buffer(mut self, max_buffer: usize) -> Self {
+		self.max_buffer = max_buffer;
+		self
+	}
+
+	/// start reading at the wall-clock time `at`, the tracks are announced meanwhile
+	pub fn start_at(mut self, at: time::SystemTime) -> Self {
+		self.start = Some(at);
+		self
+	}
+
+	/// append the next bytes to `buf`, 0 once the input ended
+	pub async fn read_buf(&mut self, buf: &mut BytesMut) -> anyhow::Result<usize> {
+		loop {
+			let (path, file, regular) = match &mut self.source {
+				Source::Reader(reader) => return reader.read_buf(buf).await.context("failed to read from stdin"),
+				Source::File { path, file, regular } => (path, file, *regular),
+			};
+
+			let read = file
+				.read_buf(buf)
+				.await
+				.with_context(|| format!("failed to read from {}", path.display()))?;
+			if read > 0 || self.on_eof == OnEof::Exit {
+				return Ok(read);
+			}
+
+			match regular {
+				true => tokio::time::sleep(POLL_INTERVAL).await,
+				// the writer closed the pipe, blocks until the next one opens it
+				false => *file = open(path).await?,
+			}
+		}
+	}
+}
+
+/// parse `input` until it ends, then end the tracks
+pub async fn publish(mut media: Media, mut input: Input, pace: bool) -> anyhow::Result<()> {
+	if let Some(at) = input.start {
+		crate::schedule::start(at).await;
+	}
+
+	let mut buf = BytesMut::new();
+
+	loop {
+		let read = input.read_buf(&mut buf).await?;
+		if read == 0 {
+			let res = media.finish(&mut buf).context("failed to parse media");
+			media.close();
+			return res;
+		}
+
+		match pace {
+			true => media.parse_paced(&mut buf).await,
+			false => media.parse(&mut buf),
+		}
+		.context("failed to parse media")?;
+
+		anyhow::ensure!(
+			buf.len() <= input.max_buffer,
+			"{} bytes buffered without a complete atom, more than the limit of {} bytes: the input is probably not fragmented MP4",
+			buf.len(),
+			input.max_buffer
+		);
+	}
+}
+
+async fn open(path: &path::Path) -> anyhow::Result<tokio::fs::File> {
+	tokio::fs::File::open(path)
+		.await
+		.with_context(|| format!("failed to open {}", path.display()))
+}
+
+/// returns once `path` exists, watching its directory for it to be created
+#[cfg(feature = "dash")]
+async fn wait_for(path: &path::Path) -> anyhow::Result<()> {
+	use notify::Watcher;
+
+	let dir = match path.parent() {
+		Some(dir) if !dir.as_os_str().is_empty() => dir,
+		_ => path::Path::new("."),
+	};
+
+	let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+	let mut watcher = notify::recommended_watcher(move |event| {
+		let _ = tx.send(event);
+	})
+	.context("failed to watch for the input")?;
+	watcher
+		.watch(dir, notify::RecursiveMode::NonRecursive)
+		.with_context(|| format!("failed to watch {}", dir.display()))?;
+
+	// checked once watching, a file created in between is not missed
+	while !path.exists() {
+		log::info!("waiting for {} to be created", path.display());
+		match rx.recv().await {
+			Some(Ok(_)) => (),
+			Some(Err(e)) => return Err(e).context("failed to watch for the input"),
+			None => anyhow::bail!("stopped watching for {}", path.display()),
+		}
+	}
+
+	Ok(())
+}
+
+/// returns once `path` exists, polled without the watcher of the dash feature
+#[cfg(not(feature = "dash"))]
+async fn wait_for(path: &path::Path) -> anyhow::Result<()> {
+	if !path.exists() {
+		log::info!("waiting for {} to be created", path.display());
+	}
+	while !path.exists() {
+		tokio::time::sleep(POLL_INTERVAL).await;
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing::temp_dir;
+	use moq_transport::serve;
+
+	/// init segment and two fragments of a single video track
+	fn stream() -> Vec<u8> {
+		[
+			&include_bytes!("../tests/fixtures/avc_init.m4s")[..],
+			include_bytes!("../tests/fixtures/chunk_1.m4s"),
+			include_bytes!("../tests/fixtures/chunk_2.m4s"),
+		]
+		.concat()
+	}
+
+	/// publish `input` and return the moof and mdat atoms of both fragments
+	async fn published(input: Input) -> Vec<bytes::Bytes> {
+		let (writer, _, mut reader) = serve::Tracks::new("test".to_string()).produce();
+		let media = Media::new(writer, None, Vec::new()).unwrap();
+		let handle = tokio::spawn(publish(media, input, false));
+
+		let objects = tokio::time::timeout(time::Duration::from_secs(5), async {
+			let track = loop {
+				if let Some(track) = reader.subscribe("video0") {
+					break track;
+				}
+				tokio::time::sleep(time::Duration::from_millis(10)).await;
+			};
+			let serve::TrackReaderMode::Groups(mut groups) = track.mode().await.unwrap() else {
+				panic!("expected groups mode");
+			};
+			let mut group = groups.next().await.unwrap().unwrap();
+
+			let mut objects = Vec::new();
+			while objects.len() < 4 {
+				objects.push(group.read_next().await.unwrap().unwrap());
+			}
+			objects
+		})
+		.await;
+
+		handle.abort();
+		objects.expect("timed out waiting for the fragments")
+	}
+
+	#[tokio::test]
+	async fn test_growing_file() {
+		let dir = temp_dir("input-file");
+		let path = dir.join("input.mp4");
+		let stream = stream();
+		let expected = published(Input::reader(std::io::Cursor::new(stream.clone()))).await;
+
+		// created after the publisher started, then written in two parts
+		let (head, tail) = stream.split_at(stream.len() / 2);
+		let writer = {
+			let path = path.clone();
+			let (head, tail) = (head.to_vec(), tail.to_vec());
+			tokio::spawn(async move {
+				tokio::time::sleep(time::Duration::from_millis(50)).await;
+				std::fs::write(&path, head).unwrap();
+				tokio::time::sleep(POLL_INTERVAL * 2).await;
+
+				let mut file = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+				std::io::Write::write_all(&mut file, &tail).unwrap();
+			})
+		};
+
+		let input = Input::open(&path, OnEof::Wait).await.unwrap();
+		assert_eq!(published(input).await, expected);
+		writer.await.unwrap();
+
+		let _ = std::fs::remove_dir_all(&dir);
+	}
+
+	#[tokio::test]
+	async fn test_eof() {
+		let (writer, _, mut reader) = serve::Tracks::new("test".to_string()).produce();
+		let media = Media::new(writer, None, Vec::new()).unwrap();
+		let input = Input::reader(std::io::Cursor::new(stream()));
+
+		// returns instead of polling the ended input, the tracks end
+		tokio::time::timeout(time::Duration::from_secs(5), publish(media, input, false))
+			.await
+			.expect("publishing did not return at the end of the input")
+			.unwrap();
+		for name in [".catalog", "video0"] {
+			let serve::TrackReaderMode::Groups(mut groups) = reader.subscribe(name).unwrap().mode().await.unwrap()
+			else {
+				panic!("expected groups mode");
+			};
+			groups.next().await.unwrap().unwrap();
+			assert!(matches!(groups.next().await, Err(serve::ServeError::Done)));
+		}
+	}
+
+	#[tokio::test]
+	async fn test_max_buffer() {
+		let (writer, _, _reader) = serve::Tracks::new("test".to_string()).produce();
+		let media = Media::new(writer, None, Vec::new()).unwrap();
+
+		// an endless stream of an atom that never completes
+		let input = Input::reader(tokio::io::repeat(0)).max_buffer(1 << 20);
+		let res = tokio::time::timeout(time::Duration::from_secs(5), publish(media, input, false))
+			.await
+			.expect("publishing did not stop at the limit");
+		assert!(res.unwrap_err().to_string().contains("not fragmented MP4"));
+	}
+
+	#[tokio::test]
+	async fn test_start_at() {
+		let (writer, _, mut reader) = serve::Tracks::new("test".to_string()).produce();
+		let media = Media::new(writer, None, Vec::new()).unwrap();
+		let at = time::SystemTime::now() + time::Duration::from_secs(2);
+		let input = Input::reader(std::io::Cursor::new(stream())).start_at(at);
+		let handle = tokio::spawn(publish(media, input, false));
+
+		// the first group is not published before the start
+		let track = loop {
+			if let Some(track) = reader.subscribe("video0") {
+				break track;
+			}
+			tokio::time::sleep(time::Duration::from_millis(10)).await;
+		};
+		let serve::TrackReaderMode::Groups(mut groups) = track.mode().await.unwrap() else {
+			panic!("expected groups mode");
+		};
+		groups.next().await.unwrap().unwrap();
+		assert!(time::SystemTime::now() >= at);
+		handle.await.unwrap().unwrap();
+	}
+
+	#[cfg(unix)]
+	#[tokio::test]
+	async fn test_fifo() {
+		let dir = temp_dir("input-fifo");
+		let path = dir.join("input.fifo");
+		let status = std::process::Command::new("mkfifo").arg(&path).status().unwrap();
+		assert!(status.success());
+
+		let stream = stream();
+		let expected = published(Input::reader(std::io::Cursor::new(stream.clone()))).await;
+
+		// a single writer, the publisher stops once it closes the pipe
+		let (head, tail) = stream.split_at(stream.len() / 2);
+		let writer = {
+			let path = path.clone();
+			let (head, tail) = (head.to_vec(), tail.to_vec());
+			std::thread::spawn(move || {
+				let mut fifo = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+				std::io::Write::write_all(&mut fifo, &head).unwrap();
+				std::thread::sleep(time::Duration::from_millis(50));
+				std::io::Write::write_all(&mut fifo, &tail).unwrap();
+			})
+		};
+
+		let input = Input::open(&path, OnEof::Exit).await.unwrap();
+		assert_eq!(published(input).await, expected);
+		writer.join().unwrap();
+
+		let _ = std::fs::remove_dir_all(&dir);
+	}
+}
@@ -1,2 +1,18 @@
+pub mod archive;
+mod atom;
+mod client;
+#[cfg(feature = "cli")]
+pub mod config;
+#[cfg(feature = "dash")]
+pub mod dash;
+pub mod input;
 mod media;
+pub mod metrics;
+pub mod mode;
+pub mod schedule;
+pub mod stats;
+pub mod sub;
+#[cfg(test)]
+mod testing;
+pub use client::*;
 pub use media::*;
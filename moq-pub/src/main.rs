@@ -1,21 +1,59 @@
-use bytes::BytesMut;
 use std::{net, path};
 use url::Url;
 
 use anyhow::Context;
-use clap::{Args, Parser, Subcommand};
-use tokio::io::AsyncReadExt;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use tokio::io::AsyncWrite;
 
 use moq_native::quic;
-use moq_pub::Media;
-use moq_transport::{serve, session::Publisher};
+use moq_pub::input::{self, Input, OnEof};
+use moq_pub::{archive::Archive, config, dash, metrics::Metrics, mode::StreamMode, stats::Stats, sub, Media};
+use moq_transport::serve;
 
-mod dash;
+/// every flag can be set by the environment variable of its long name with this prefix, ex. MOQ_PUB_URL
+const ENV_PREFIX: &str = "MOQ_PUB";
 
 #[derive(Parser)]
 pub struct Cli {
 	#[command(subcommand)]
 	pub(crate) command: Commands,
+
+	/// Read the flags from this TOML file by their long names, ex. tls-cert = ["cert.pem"], at the top level
+	/// or in a [run], [dash] or [sub] table. The MOQ_PUB_* environment, ex. MOQ_PUB_URL, and the flags override it.
+	#[arg(long, global = true)]
+	pub config: Option<path::PathBuf>,
+
+	/// Write the logs as plain text or as one JSON object per line
+	#[arg(long, global = true, value_enum, default_value_t = LogFormat::Text)]
+	pub log_format: LogFormat,
+
+	/// The log level, overridden by RUST_LOG
+	#[arg(long, global = true, default_value = "info")]
+	pub log_level: tracing::Level,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum LogFormat {
+	Text,
+	Json,
+}
+
+impl Cli {
+	/// install the tracing subscriber, the `log` records of the dependencies are forwarded to it
+	fn init_logging(&self) {
+		// Quinn is far too chatty below WARN.
+		let filter = tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+			tracing_subscriber::EnvFilter::new(format!("{},quinn=warn,quinn_proto=warn", self.log_level))
+		});
+
+		let builder = tracing_subscriber::fmt()
+			.with_env_filter(filter)
+			.with_writer(std::io::stderr);
+		match self.log_format {
+			LogFormat::Text => builder.init(),
+			LogFormat::Json => builder.json().flatten_event(true).with_current_span(true).init(),
+		}
+	}
 }
 #[derive(Subcommand)]
 enum Commands {
@@ -23,91 +61,430 @@ enum Commands {
 	Run(Original),
 
 	/// Dash fMP4 Publisher
-	Dash(Dash),
+	Dash(Box<Dash>),
+
+	/// Subscribe to a catalog track and write it as fMP4
+	Sub(Sub),
 }
 
 #[derive(Args, Clone)]
-struct Original {
+struct ReconnectArgs {
+	/// How often the relay is re-dialed after the session dropped before giving up, 0 to exit instead
+	#[arg(long, default_value = "5")]
+	pub reconnect_attempts: u32,
+
+	/// Milliseconds to wait before re-dialing the relay, doubled with every failed attempt
+	#[arg(long, default_value = "1000")]
+	pub reconnect_backoff: u64,
+}
+
+impl ReconnectArgs {
+	fn reconnect(&self) -> dash::Reconnect {
+		dash::Reconnect {
+			max_attempts: self.reconnect_attempts,
+			backoff: std::time::Duration::from_millis(self.reconnect_backoff),
+		}
+	}
+}
+
+#[derive(Args, Clone)]
+struct StartArgs {
+	/// Announce right away but start the input at this RFC 3339 time, ex. 2024-05-01T12:00:00Z
+	#[arg(long, conflicts_with = "start_delay")]
+	pub start_at: Option<chrono::DateTime<chrono::FixedOffset>>,
+
+	/// Announce right away but start the input after the given seconds
+	#[arg(long)]
+	pub start_delay: Option<u64>,
+}
+
+impl StartArgs {
+	/// the wall-clock time the input is started at, right away if not given
+	fn start(&self) -> Option<std::time::SystemTime> {
+		match (self.start_at, self.start_delay) {
+			(Some(at), _) => Some(at.into()),
+			(None, Some(delay)) => Some(std::time::SystemTime::now() + std::time::Duration::from_secs(delay)),
+			(None, None) => None,
+		}
+	}
+}
+
+#[derive(Args, Clone)]
+struct ConnectArgs {
 	/// Listen for UDP packets on the given address.
 	#[arg(long, default_value = "[::]:0")]
 	pub bind: net::SocketAddr,
 
-	/// Advertise this frame rate in the catalog (informational)
-	// TODO auto-detect this from the input when not provided
-	#[arg(long, default_value = "24")]
-	pub fps: u8,
+	/// Connect to the given URL starting with https://
+	#[arg()]
+	pub url: Url,
+
+	/// Close the connection after the given milliseconds without traffic, 0 to never time out
+	#[arg(long, default_value = "10000")]
+	pub idle_timeout: u64,
+
+	/// Send a keep-alive after the given milliseconds without traffic, 0 to disable
+	#[arg(long, default_value = "4000")]
+	pub keep_alive_interval: u64,
+
+	/// The QUIC congestion controller
+	#[arg(long, value_enum, default_value_t = quic::CongestionControl::Bbr)]
+	pub congestion_control: quic::CongestionControl,
+
+	/// The initial congestion window in bytes, the default of the congestion controller if not given
+	#[arg(long)]
+	pub initial_window: Option<u64>,
+
+	/// The TLS configuration.
+	#[command(flatten)]
+	pub tls: moq_native::tls::Args,
+}
+
+impl ConnectArgs {
+	fn transport(&self) -> quic::Transport {
+		let millis = |millis| (millis > 0).then(|| std::time::Duration::from_millis(millis));
+		quic::Transport {
+			idle_timeout: millis(self.idle_timeout),
+			keep_alive_interval: millis(self.keep_alive_interval),
+			congestion_control: self.congestion_control,
+			initial_window: self.initial_window,
+		}
+	}
+
+	fn options(self, namespace: String) -> dash::ConnectOptions {
+		dash::ConnectOptions {
+			transport: self.transport(),
+			tls: self.tls,
+			url: self.url,
+			bind: self.bind,
+			namespace,
+		}
+	}
+}
+
+#[derive(Args, Clone)]
+struct Original {
+	/// Advertise this frame rate in the catalog instead of the one detected from the input
+	#[arg(long)]
+	pub fps: Option<u8>,
 
-	/// Advertise this bit rate in the catalog (informational)
-	// TODO auto-detect this from the input when not provided
+	/// Advertise these bit rates (per track) in the catalog instead of the ones measured from the input
 	#[arg(short, long, num_args = 1.., value_delimiter = ',')]
 	pub bitrate: Vec<u32>,
 
-	/// Connect to the given URL starting with https://
-	#[arg()]
-	pub url: Url,
+	#[command(flatten)]
+	pub connect: ConnectArgs,
 
 	/// The name of the broadcast
 	#[arg(long)]
 	pub name: String,
 
-	/// The TLS configuration.
+	/// Pace the fragments by their timestamps, for reading a file instead of a live encoder
+	#[arg(long)]
+	pub pace: bool,
+
+	/// Read the fMP4 stream from this file or named pipe instead of stdin, waits for it to be created
+	#[arg(long)]
+	pub input: Option<path::PathBuf>,
+
+	/// At the end of the --input file stop (exit) or wait for it to grow or the pipe to be reopened (wait)
+	#[arg(long, default_value = "exit")]
+	pub on_eof: OnEof,
+
+	/// Seconds to stay announced once the input ended, late subscribers still get the last groups
+	#[arg(long, default_value = "0")]
+	pub linger: u64,
+
+	/// Stop once this many bytes could not be parsed, ex. when the input is not fragmented MP4
+	#[arg(long, default_value_t = input::MAX_BUFFER)]
+	pub max_buffer: usize,
+
+	/// Serve Prometheus metrics of the published tracks on the given address
+	#[arg(long)]
+	pub metrics_bind: Option<net::SocketAddr>,
+
+	/// Also write every published object below the given directory, as <track>/<group>/<object>.bin
+	#[arg(long)]
+	pub archive: Option<path::PathBuf>,
+
+	/// Send the audio objects on a stream per group, a stream per object or as datagrams
+	#[arg(long, default_value = "groups")]
+	pub audio_mode: StreamMode,
+
 	#[command(flatten)]
-	pub tls: moq_native::tls::Args,
+	pub reconnect: ReconnectArgs,
+
+	#[command(flatten)]
+	pub start: StartArgs,
 }
 
 #[derive(Args, Clone)]
 struct Dash {
-	/// The path to ffmpeg input, default is integrated laptop camera (Linux: /dev/video0)
+	/// The ffmpeg input: a file, a live rtmp://, srt://, udp:// or rtsp:// URL, or /dev/video0 for the laptop camera (avfoundation on macOS, DirectShow on Windows)
 	#[arg(short, long, default_value = "/dev/video0")]
-	pub input: path::PathBuf,
+	pub input: dash::Input,
 
 	/// The path to DASH Manifest output file (.mpd)
-	#[arg(short, long)]
-	pub output: path::PathBuf,
+	#[arg(short, long, required_unless_present = "broadcasts")]
+	pub output: Option<path::PathBuf>,
 
-	/// The path to the Settings file
+	/// The path to the Settings file, CSV sections or .json/.yaml, changes are applied while running
 	#[arg(short = 's', long = "settings", default_value = "../media/settings.csv")]
 	pub settings_file: path::PathBuf,
 
+	/// Generate the ladder from its top rung instead of reading --settings, ex. 1920x1080@6000 (kbit/s).
+	/// It is printed and saved as ladder.csv next to --output, where changes are applied like to --settings.
+	#[arg(long, value_name = "WxH@KBPS", conflicts_with_all = ["settings_file", "broadcasts"])]
+	pub auto_ladder: Option<dash::Ladder>,
+
 	/// The name of the broadcast
+	#[arg(long, required_unless_present = "broadcasts")]
+	pub name: Option<String>,
+
+	/// Publish the broadcasts listed in this YAML or JSON file on a single session, instead of --name,
+	/// --input, --settings, --output and --no-audio. The other options apply to all of them.
+	#[arg(long, conflicts_with_all = ["name", "output"])]
+	pub broadcasts: Option<path::PathBuf>,
+
+	/// Stop all broadcasts of --broadcasts once one fails, instead of leaving the others running
 	#[arg(long)]
-	pub name: String,
+	pub fail_fast: bool,
 
 	/// Set to not publish audio
 	#[arg(long)]
 	pub no_audio: bool,
 
+	/// Validate the settings, print the ffmpeg command and the planned tracks, then exit without publishing
+	#[arg(long)]
+	pub dry_run: bool,
+
+	/// Loop the input forever, the timestamps restarting with every loop are rebased by the publisher
 	#[arg(long = "loop")]
 	pub looping: bool,
 
-	/// Listen for UDP packets on the given address.
-	#[arg(long, default_value = "[::]:0")]
-	pub bind: net::SocketAddr,
+	/// Arguments passed to ffmpeg before the -i of every input, ex. "-rtsp_transport tcp".
+	/// They follow those of the settings file and replace the generated flags of the same name, like the other --extra-*-args.
+	#[arg(long, value_name = "ARGS", allow_hyphen_values = true, value_delimiter = ' ')]
+	pub extra_input_args: Vec<String>,
 
-	/// Connect to the given URL starting with https://
-	#[arg()]
-	pub url: Url,
+	/// Arguments passed to ffmpeg after the flags of every video rep, {index} is its index, ex. "-c:v:{index} h264_nvenc"
+	#[arg(long, value_name = "ARGS", allow_hyphen_values = true, value_delimiter = ' ')]
+	pub extra_video_args: Vec<String>,
+
+	/// Arguments passed to ffmpeg after the flags of every audio rep, {index} is its index
+	#[arg(long, value_name = "ARGS", allow_hyphen_values = true, value_delimiter = ' ')]
+	pub extra_audio_args: Vec<String>,
+
+	/// Arguments passed to ffmpeg after the flags of the DASH muxer, ex. "-window_size 5"
+	#[arg(long, value_name = "ARGS", allow_hyphen_values = true, value_delimiter = ' ')]
+	pub extra_output_args: Vec<String>,
+
+	/// Seconds to stay connected once ffmpeg finished a finite input, late subscribers still get the last groups
+	#[arg(long, default_value = "0")]
+	pub linger: u64,
+
+	/// How often a crashed ffmpeg is restarted before giving up
+	#[arg(long, default_value = "3")]
+	pub max_restarts: u32,
+
+	/// Milliseconds to wait before restarting a crashed ffmpeg
+	#[arg(long, default_value = "1000")]
+	pub restart_backoff: u64,
+
+	/// Fail with the output of ffmpeg if it writes no segment within the given milliseconds of its start
+	#[arg(long, default_value_t = dash::STARTUP_TIMEOUT.as_millis() as u64)]
+	pub startup_timeout: u64,
 
-	/// The TLS configuration.
 	#[command(flatten)]
-	pub tls: moq_native::tls::Args,
+	pub reconnect: ReconnectArgs,
+
+	#[command(flatten)]
+	pub start: StartArgs,
+
+	/// Poll the output every given milliseconds instead of using inotify, ex. on network filesystems
+	#[arg(long)]
+	pub poll_interval: Option<u64>,
+
+	/// Read a segment once per given milliseconds of writes to it, 0 reads on every write
+	#[arg(long, default_value = "10")]
+	pub debounce: u64,
+
+	/// Treat a segment without writes for the given milliseconds as complete, 0 waits for its close or rename [default: 1000 on macOS and Windows, 0 on Linux]
+	#[arg(long)]
+	pub quiescence: Option<u64>,
+
+	/// Read at most the given bytes of a segment at once
+	#[arg(long, default_value = "8388608")]
+	pub max_read: usize,
+
+	/// Serve Prometheus metrics of the published tracks on the given address
+	#[arg(long)]
+	pub metrics_bind: Option<net::SocketAddr>,
+
+	/// Also write every published object below the given directory, as <track>/<group>/<object>.bin
+	#[arg(long)]
+	pub archive: Option<path::PathBuf>,
+
+	/// Append a JSONL record of every group created and object written to this file, for correlating network traces
+	#[arg(long)]
+	pub stats_file: Option<path::PathBuf>,
+
+	/// Move the --stats-file to <file>.1 once it would grow past the given bytes
+	#[arg(long, requires = "stats_file")]
+	pub stats_max_size: Option<u64>,
+
+	/// Publish CMAF fragments (cmaf) or a LOC object per frame (loc)
+	#[arg(long, default_value = "cmaf")]
+	pub packaging: moq_catalog::Packaging,
+
+	/// Which groups the relay sends first: newest-first, oldest-first or sequential
+	#[arg(long, default_value = "newest-first")]
+	pub group_order: dash::GroupOrder,
+
+	/// Write every atom (per-atom), CMAF chunk (per-chunk) or segment (per-segment) as an object
+	#[arg(long, default_value = "per-atom")]
+	pub object_mode: dash::ObjectMode,
+
+	/// Publish the DASH manifest on the .mpd track
+	#[arg(long)]
+	pub publish_mpd: bool,
+
+	/// Publish the event messages (emsg) of the segments on the .emsg track, instead of in front of the next moof
+	#[arg(long)]
+	pub emsg_track: bool,
+
+	/// Publish the markers of this JSONL file, lines of {"at_ms": .., "payload": {..}}, on the .metadata track
+	/// once the media reaches them. Lines appended while running are published as well.
+	#[arg(long)]
+	pub metadata: Option<path::PathBuf>,
+
+	/// Publish the wall clock and media time of every prft of the segments on the .timeline track, as JSON records
+	#[arg(long)]
+	pub timeline_track: bool,
+
+	/// Start a new group of the .timeline track every given milliseconds of wall clock
+	#[arg(long, default_value_t = dash::TIMELINE_GROUP_DURATION.as_millis() as u64)]
+	pub timeline_group_duration: u64,
+
+	/// Publish the init segment of every representation on a <track>_init track, instead of inlined in the catalog
+	#[arg(long)]
+	pub init_tracks: bool,
+
+	/// End the groups of the audio tracks every given milliseconds of media, every segment if there is no video
+	#[arg(long)]
+	pub audio_group_duration: Option<u64>,
+
+	/// End the groups once they cover the given milliseconds of media, at the next keyframe for video, instead of every GOP
+	#[arg(long)]
+	pub group_duration: Option<u64>,
+
+	/// Start a new group once the timestamps jump ahead of the previous fragment by more than the given milliseconds
+	#[arg(long, default_value_t = 500)]
+	pub discontinuity_threshold_ms: u64,
+
+	/// Abandon a group whose writes are still blocked after the given milliseconds and skip to the next keyframe, bounding the latency when the relay falls behind
+	#[arg(long)]
+	pub max_group_age_ms: Option<u64>,
+
+	/// Milliseconds to wait for the init segments of all representations before publishing the first catalog, 0 to not wait
+	#[arg(long, default_value = "5000")]
+	pub catalog_initial_timeout: u64,
+
+	/// Write the catalog and the init tracks again every given milliseconds, unchanged, so subscribers joining through
+	/// a relay that only keeps the newest groups find them
+	#[arg(long)]
+	pub init_republish_interval: Option<u64>,
+
+	/// The name of the catalog track
+	#[arg(long, default_value = dash::CATALOG_TRACK)]
+	pub catalog_track: String,
+
+	/// Publish a catalog per media type on <catalog track>.video, .audio and .data for the other tracks, listed by the
+	/// root catalog
+	#[arg(long)]
+	pub hierarchical_catalog: bool,
+
+	/// Serialize the catalogs as json (pretty), json-compact or cbor, the smallest
+	#[arg(long, default_value = "json-compact")]
+	pub catalog_format: moq_catalog::CatalogFormat,
+
+	/// Name the tracks after the rep settings, with the placeholders {name}, {kind}, {bitrate} and {height}
+	#[arg(long, default_value = "{name}")]
+	pub track_name_template: dash::TrackNameTemplate,
+
+	/// Stop once a video representation starts a group at a timestamp no other one does, instead of warning
+	#[arg(long)]
+	pub strict_alignment: bool,
+
+	/// Stop on a fragment of a trak the init segment does not describe, ex. after ffmpeg switched inputs, instead of
+	/// skipping it with a warning
+	#[arg(long)]
+	pub strict: bool,
+
+	/// Stop writing the groups of a representation while nobody is subscribed to it, resuming at the next keyframe
+	#[arg(long)]
+	pub lazy_tracks: bool,
+
+	/// Split objects, ex. a large mdat or init segment, into several objects of this many bytes within the group
+	#[arg(long, default_value_t = dash::MAX_OBJECT_SIZE)]
+	pub max_object_size: usize,
+
+	/// Atoms declaring a larger size are taken for corrupt segment data, skipped up to the next fragment
+	#[arg(long, default_value_t = dash::MAX_ATOM_SIZE)]
+	pub max_atom_size: usize,
+
+	/// Base priority of the audio tracks, lower is sent first. It takes precedence over --group-order
+	/// and is advertised as the priority in the selectionParams of the catalog tracks.
+	#[arg(long, default_value_t = dash::AUDIO_PRIORITY)]
+	pub audio_priority: u8,
+
+	/// Base priority of the video tracks, by default after the audio
+	#[arg(long, default_value_t = dash::VIDEO_PRIORITY)]
+	pub video_priority: u8,
+
+	#[command(flatten)]
+	pub connect: ConnectArgs,
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-	env_logger::init();
+#[derive(Args, Clone)]
+struct Sub {
+	#[command(flatten)]
+	pub connect: ConnectArgs,
 
-	// Disable tracing so we don't get a bunch of Quinn spam.
-	let tracer = tracing_subscriber::FmtSubscriber::builder()
-		.with_max_level(tracing::Level::WARN)
-		.finish();
-	tracing::subscriber::set_global_default(tracer).unwrap();
+	/// The name of the broadcast
+	#[arg(long)]
+	pub namespace: String,
 
-	let cli = Cli::parse();
+	/// The name of the track to play, default is the one with the highest bitrate
+	#[arg(long)]
+	pub name: Option<String>,
+
+	/// The name of the catalog track, a root catalog is followed to the catalogs it lists
+	#[arg(long, default_value = dash::CATALOG_TRACK)]
+	pub catalog_track: String,
+
+	/// The file to write the fMP4 stream to, - for stdout
+	#[arg(default_value = "-")]
+	pub output: path::PathBuf,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+	let (cli, resolved) = match config::parse_from::<Cli, _, _>(ENV_PREFIX, std::env::args_os()) {
+		Ok(parsed) => parsed,
+		// ex. --help or a missing flag, printed by clap
+		Err(e) => match e.downcast::<clap::Error>() {
+			Ok(e) => e.exit(),
+			Err(e) => return Err(e),
+		},
+	};
+	cli.init_logging();
+	log::info!("effective config:\n{resolved}");
 
 	match cli.command {
 		Commands::Run(args) => run_orignal(args).await.unwrap(),
-		Commands::Dash(args) => run_dash(args).await.unwrap(),
+		Commands::Dash(args) => run_dash(*args).await.unwrap(),
+		Commands::Sub(args) => run_sub(args).await.unwrap(),
 	}
 
 	Ok(())
@@ -116,45 +493,337 @@ async fn main() -> anyhow::Result<()> {
 async fn run_orignal(cli: Original) -> anyhow::Result<()> {
 	let (writer, _, reader) = serve::Tracks::new(cli.name).produce();
 	let bitrates = cli.bitrate.clone();
-	let media = Media::new(writer, bitrates)?;
-
-	let tls = cli.tls.load()?;
-
-	let quic = quic::Endpoint::new(moq_native::quic::Config {
-		bind: cli.bind,
-		tls: tls.clone(),
-	})?;
+	let metrics = Metrics::default();
+	let mut media = Media::new(writer, cli.fps, bitrates)?
+		.with_metrics(metrics.clone())
+		.with_audio_mode(cli.audio_mode);
+	let archive = cli.archive.map(Archive::new);
+	if let Some(archive) = archive.clone() {
+		media = media.with_archive(archive);
+	}
 
-	log::info!("connecting to relay: url={}", cli.url);
-	let session = quic.client.connect(&cli.url).await?;
+	let start = cli.start.start();
+	let mut input = match &cli.input {
+		Some(path) => Input::open(path, cli.on_eof).await?,
+		None => Input::stdin(),
+	}
+	.max_buffer(cli.max_buffer);
+	if let Some(at) = start {
+		input = input.start_at(at);
+	}
 
-	let (session, mut publisher) = Publisher::connect(session)
-		.await
-		.context("failed to create MoQ Transport publisher")?;
+	let options = cli.connect.options(reader.namespace.clone());
+	let reconnect = cli.reconnect.reconnect();
+	let shutdown = tokio::sync::Notify::new();
+
+	// the tracks ended with the input, the broadcast stays announced meanwhile
+	let linger = std::time::Duration::from_secs(cli.linger);
+	let media = async {
+		input::publish(media, input, cli.pace).await.context("media error")?;
+		if !linger.is_zero() {
+			log::info!("input ended, lingering for {linger:?}");
+			tokio::time::sleep(linger).await;
+		}
+		anyhow::Ok(())
+	};
 
 	tokio::select! {
-		res = session.run() => res.context("session error")?,
-		res = run_media(media) => res.context("media error")?,
-		res = publisher.announce(reader) => res.context("publisher error")?,
+		res = dash::announce(&options, reader, &reconnect, &shutdown) => res.context("relay error")?,
+		res = media => res?,
+		res = run_metrics(metrics, cli.metrics_bind) => res.context("metrics error")?,
+	}
+
+	if let Some(archive) = archive {
+		archive.flush().await;
 	}
 
 	Ok(())
 }
 
-async fn run_media(mut media: Media) -> anyhow::Result<()> {
-	let mut input = tokio::io::stdin();
-	let mut buf = BytesMut::new();
+/// serve the metrics if an address is given, never returns otherwise
+async fn run_metrics(metrics: Metrics, bind: Option<net::SocketAddr>) -> anyhow::Result<()> {
+	match bind {
+		Some(bind) => metrics.serve(bind).await,
+		None => std::future::pending().await,
+	}
+}
 
-	loop {
-		input.read_buf(&mut buf).await.context("failed to read from stdin")?;
-		media.parse(&mut buf).context("failed to parse media")?;
+/// print what `broadcast` would run and publish, fails if the settings are invalid
+fn dry_run(
+	cli: &Dash,
+	broadcast: &dash::BroadcastConfig,
+	settings: &dash::Settings<path::PathBuf>,
+) -> anyhow::Result<()> {
+	settings.validate()?;
+	let names = settings.track_names(&cli.track_name_template)?;
+
+	println!("{}\n", settings.ffmpeg_args()?);
+	println!("broadcast {}:", broadcast.name);
+	match cli.hierarchical_catalog {
+		true => {
+			println!("  {}  root catalog", cli.catalog_track);
+			for kind in ["video", "audio"] {
+				println!("  {}.{kind}  {kind} catalog, {:?}", cli.catalog_track, cli.packaging);
+			}
+			// the tracks neither audio nor video
+			if cli.metadata.is_some() || cli.timeline_track {
+				println!("  {}.data  data catalog", cli.catalog_track);
+			}
+		}
+		false => println!("  {}  catalog, {:?}", cli.catalog_track, cli.packaging),
+	}
+	for (rep_id, name) in names.iter().enumerate() {
+		let Some(setting) = settings.get_rep(rep_id) else {
+			continue;
+		};
+		let rep = match &setting {
+			dash::Setting::Audio(_) if broadcast.no_audio => continue,
+			dash::Setting::Audio(audio) => format!("audio {} Hz, {} bps", audio.sampling_rate, audio.bitrate),
+			dash::Setting::Video(video) => {
+				format!("video {}, {} bps, {}", video.resolution, video.bitrate, video.codec)
+			}
+		};
+		println!("  {name}  rep {rep_id}, {rep}, {:?}", setting.mode());
+		if cli.init_tracks {
+			println!("  {name}{}  init segment", dash::INIT_TRACK_SUFFIX);
+		}
+	}
+	if cli.publish_mpd {
+		println!("  .mpd  manifest");
 	}
+	if cli.emsg_track {
+		println!("  .emsg  event messages");
+	}
+	if let Some(path) = &cli.metadata {
+		println!("  {}  timed metadata of {}", dash::METADATA_TRACK, path.display());
+	}
+	if cli.timeline_track {
+		println!("  {}  wall clock of the prfts", dash::TIMELINE_TRACK);
+	}
+
+	Ok(())
+}
+
+/// the pipeline of `broadcast` with the options of `cli`
+fn dash(
+	cli: &Dash,
+	broadcast: dash::BroadcastConfig,
+	settings: dash::Settings<path::PathBuf>,
+	metrics: Metrics,
+	stats: Option<Stats>,
+	start: Option<std::time::SystemTime>,
+) -> anyhow::Result<dash::Dash> {
+	let mut dash = dash::Dash::new(
+		settings,
+		broadcast.output,
+		cli.connect.clone().options(broadcast.name),
+		dash::Restart {
+			max_restarts: cli.max_restarts,
+			backoff: std::time::Duration::from_millis(cli.restart_backoff),
+		},
+	)?
+	.reconnect(cli.reconnect.reconnect())
+	.startup_timeout(std::time::Duration::from_millis(cli.startup_timeout));
+	if let Some(interval) = cli.poll_interval {
+		dash = dash.poll_interval(std::time::Duration::from_millis(interval));
+	}
+	if let Some(quiescence) = cli.quiescence {
+		dash = dash.quiescence(std::time::Duration::from_millis(quiescence));
+	}
+	dash = dash
+		.debounce(std::time::Duration::from_millis(cli.debounce))
+		.max_read(cli.max_read);
+	if let Some(duration) = cli.audio_group_duration {
+		dash = dash.audio_group_duration(std::time::Duration::from_millis(duration));
+	}
+	if let Some(duration) = cli.group_duration {
+		dash = dash.group_duration(std::time::Duration::from_millis(duration));
+	}
+	if let Some(age) = cli.max_group_age_ms {
+		dash = dash.max_group_age(std::time::Duration::from_millis(age));
+	}
+	if let Some(interval) = cli.init_republish_interval {
+		dash = dash.republish_interval(std::time::Duration::from_millis(interval));
+	}
+	dash = dash
+		.discontinuity_threshold(std::time::Duration::from_millis(cli.discontinuity_threshold_ms))
+		.catalog_initial_timeout(std::time::Duration::from_millis(cli.catalog_initial_timeout))
+		.catalog_track(&cli.catalog_track)
+		.hierarchical_catalog(cli.hierarchical_catalog)
+		.catalog_format(cli.catalog_format)
+		.metrics(metrics)
+		.packaging(cli.packaging)
+		.group_order(cli.group_order)
+		.object_mode(cli.object_mode)
+		.publish_mpd(cli.publish_mpd)
+		.emsg_track(cli.emsg_track)
+		.init_tracks(cli.init_tracks)
+		.track_name_template(cli.track_name_template.clone())
+		.strict_alignment(cli.strict_alignment)
+		.strict_traks(cli.strict)
+		.lazy_tracks(cli.lazy_tracks)
+		.max_object_size(cli.max_object_size)
+		.max_atom_size(cli.max_atom_size)
+		.priorities(cli.audio_priority, cli.video_priority)
+		.linger(std::time::Duration::from_secs(cli.linger))
+		.watch_settings(broadcast.settings);
+
+	if let Some(dir) = &cli.archive {
+		dash = dash.archive(Archive::new(dir.clone()));
+	}
+	if let Some(path) = &cli.metadata {
+		dash = dash.metadata(path.clone());
+	}
+	if cli.timeline_track {
+		dash = dash.timeline_track(std::time::Duration::from_millis(cli.timeline_group_duration));
+	}
+	if let Some(stats) = stats {
+		dash = dash.stats(stats);
+	}
+	if let Some(at) = start {
+		dash = dash.start_at(at);
+	}
+
+	Ok(dash)
 }
 
 async fn run_dash(cli: Dash) -> anyhow::Result<()> {
-	let dash = dash::Dash::new(cli)?;
+	let mut broadcasts = match &cli.broadcasts {
+		Some(path) => dash::BroadcastConfig::load(path)?,
+		None => vec![dash::BroadcastConfig {
+			name: cli.name.clone().context("missing --name")?,
+			input: cli.input.clone(),
+			settings: cli.settings_file.clone(),
+			output: cli.output.clone().context("missing --output")?,
+			no_audio: cli.no_audio,
+		}],
+	};
+
+	let ladder = match &cli.auto_ladder {
+		Some(ladder) => {
+			let file = ladder.file();
+			println!("{}", file.to_csv()?);
+			Some(file)
+		}
+		None => None,
+	};
+
+	let extra = dash::ExtraArgs {
+		input: cli.extra_input_args.clone(),
+		video: cli.extra_video_args.clone(),
+		audio: cli.extra_audio_args.clone(),
+		output: cli.extra_output_args.clone(),
+	};
+
+	let mut settings = Vec::new();
+	for broadcast in &mut broadcasts {
+		if let Some(file) = &ladder {
+			// read back like a settings file, for reproducibility and to apply changes to it
+			broadcast.settings = broadcast.output.with_file_name("ladder.csv");
+			if !cli.dry_run {
+				file.save(&broadcast.settings)?;
+				log::info!("saved the ladder to {}", broadcast.settings.display());
+			}
+			settings.push(
+				dash::Settings::from_file(
+					file.clone(),
+					broadcast.input.clone(),
+					broadcast.output.clone(),
+					broadcast.no_audio,
+					cli.looping,
+				)?
+				.with_extra_args(extra.clone()),
+			);
+			continue;
+		}
+
+		settings.push(
+			dash::Settings::new(
+				broadcast.settings.clone(),
+				broadcast.input.clone(),
+				broadcast.output.clone(),
+				broadcast.no_audio,
+				cli.looping,
+			)?
+			.with_extra_args(extra.clone()),
+		);
+	}
 
-	dash.run().await?;
+	if cli.dry_run {
+		for (broadcast, settings) in broadcasts.iter().zip(&settings) {
+			dry_run(&cli, broadcast, settings)?;
+		}
+		return Ok(());
+	}
+
+	let metrics = Metrics::default();
+	// resolved once, the broadcasts start together
+	let start = cli.start.start();
+	// one file for all broadcasts
+	let stats = cli.stats_file.as_ref().map(|path| Stats::new(path, cli.stats_max_size));
+	let mut dashes = Vec::new();
+	for (broadcast, settings) in broadcasts.into_iter().zip(settings) {
+		// next to the output, named after the broadcast if there are several
+		let script = match cli.broadcasts {
+			Some(_) => format!("dash_{}.sh", broadcast.name),
+			None => "dash.sh".to_string(),
+		};
+		settings.save(broadcast.output.with_file_name(script))?;
+
+		dashes.push(dash(&cli, broadcast, settings, metrics.clone(), stats.clone(), start)?);
+	}
+
+	let run = async {
+		match cli.broadcasts {
+			Some(_) => {
+				// the session is shared, only its namespace is unused
+				let mut broadcasts = dash::Broadcasts::new(cli.connect.clone().options(String::new()))
+					.reconnect(cli.reconnect.reconnect())
+					.fail_fast(cli.fail_fast);
+				for dash in dashes {
+					broadcasts = broadcasts.broadcast(dash);
+				}
+				broadcasts.run().await
+			}
+			None => dashes.remove(0).run().await,
+		}
+	};
+
+	tokio::select! {
+		res = run => res?,
+		res = run_metrics(metrics, cli.metrics_bind) => res.context("metrics error")?,
+	}
+
+	Ok(())
+}
+
+async fn run_sub(cli: Sub) -> anyhow::Result<()> {
+	let output: Box<dyn AsyncWrite + Unpin> = match cli.output.to_str() {
+		Some("-") => Box::new(tokio::io::stdout()),
+		_ => Box::new(tokio::fs::File::create(&cli.output).await?),
+	};
+
+	let selection = match cli.name {
+		Some(name) => sub::Selection::Name(name),
+		None => sub::Selection::HighestBitrate,
+	};
+
+	let (session, subscriber) = moq_pub::connect_subscriber(
+		&cli.connect.url,
+		cli.connect.bind,
+		&cli.connect.tls,
+		cli.connect.transport(),
+	)
+	.await?;
+
+	let mut player = sub::Player::new(sub::Remote::new(subscriber, cli.namespace), output, selection)
+		.catalog_track(&cli.catalog_track);
+
+	tokio::select! {
+		res = session.run() => res.context("session error")?,
+		res = player.run() => res.context("player error")?,
+		res = tokio::signal::ctrl_c() => res.context("failed to listen for SIGINT")?,
+	}
 
 	Ok(())
 }
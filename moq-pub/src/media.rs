@@ -1,14 +1,35 @@
 use anyhow::{self, Context};
 use bytes::{Buf, Bytes};
-use moq_transport::serve::{GroupWriter, GroupsWriter, TrackWriter, TracksWriter};
+use moq_transport::serve::{TrackWriter, TracksWriter};
+
+use crate::archive::{Archive, ArchivingGroupsWriter};
+use crate::mode::{ModeGroupWriter, ModeWriter, StreamMode};
 use mp4::{self, ReadBox, TrackType};
-use std::cmp::max;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::io::Cursor;
 use std::time;
 
+use crate::atom::next_atom;
+
 const LABEL: &str = "Dash MoQ";
 
+/// track of the catalog, unless another name is set
+pub const CATALOG_TRACK: &str = ".catalog";
+
+/// audio and video are rendered together, the tracks of each kind are alternatives of each other
+const RENDER_GROUP: usize = 1;
+const VIDEO_ALT_GROUP: usize = 1;
+const AUDIO_ALT_GROUP: usize = 2;
+
+/// media time the framerate and bitrate are measured over
+const MEASUREMENT_WINDOW: time::Duration = time::Duration::from_secs(10);
+
+/// media time needed before a measurement is advertised
+const MEASUREMENT_MIN: time::Duration = time::Duration::from_secs(1);
+
+/// relative difference between the advertised and measured values that triggers a catalog update
+const MAX_DRIFT: f64 = 0.2;
+
 pub struct Media {
 	// Tracks based on their track ID.
 	tracks: HashMap<u32, Track>,
@@ -17,27 +38,46 @@ pub struct Media {
 	broadcast: TracksWriter,
 
 	// The catalog and its track
-	catalog_pub: moq_transport::serve::GroupsWriter,
+	catalog_pub: ArchivingGroupsWriter,
 	catalog: moq_catalog::MoqCatalog,
+	published: Option<moq_catalog::MoqCatalog>,
 
 	// The ftyp and moov atoms at the start of the file.
 	ftyp: Option<Bytes>,
 	moov: Option<mp4::MoovBox>,
+
+	// The producer reference time of the next moof, sent once in front of it.
 	prft: Option<Bytes>,
 
 	// The current track name
 	current: Option<u32>,
 
+	// Delays fragments in parse_paced
+	pacer: Pacer,
+
+	// Overrides for the detected framerate and per track bitrates
+	fps: Option<u8>,
 	bitrates: Vec<u32>,
+
+	// Counters of the published tracks
+	metrics: crate::metrics::Metrics,
+
+	// Receives a copy of every object, if archiving
+	archive: Option<Archive>,
+
+	// How the objects of the audio tracks are sent, the video tracks always use groups
+	audio_mode: StreamMode,
 }
 
 impl Media {
-	pub fn new(mut broadcast: TracksWriter, bitrates: Vec<u32>) -> anyhow::Result<Self> {
-		let catalog_pub = broadcast.create(".catalog").context("broadcast closed")?.groups()?;
+	/// `fps` and `bitrates` (by track ID - 1) override the values measured from the input
+	pub fn new(mut broadcast: TracksWriter, fps: Option<u8>, bitrates: Vec<u32>) -> anyhow::Result<Self> {
+		let catalog_pub = broadcast.create(CATALOG_TRACK).context("broadcast closed")?.groups()?;
+		let catalog_pub = ArchivingGroupsWriter::new(catalog_pub, None);
 		let mut catalog = moq_catalog::MoqCatalog::new();
 
 		let mut csf = moq_catalog::CommonStructFields::new("", moq_catalog::Packaging::CMAF);
-		csf.set_alt_group(1)
+		csf.set_render_group(RENDER_GROUP)
 			.set_label(LABEL)
 			.set_namespace(&broadcast.namespace);
 
@@ -48,27 +88,104 @@ impl Media {
 			broadcast,
 			catalog_pub,
 			catalog,
+			published: None,
 			ftyp: None,
 			moov: None,
 			prft: None,
 			current: None,
+			pacer: Pacer::default(),
+			fps,
 			bitrates,
+			metrics: Default::default(),
+			archive: None,
+			audio_mode: StreamMode::default(),
 		})
 	}
 
+	/// write every object, including the catalog, to `archive` as well, must be set before the input is parsed
+	pub fn with_archive(mut self, archive: Archive) -> Self {
+		self.catalog_pub.set_archive(Some(archive.clone()));
+		self.archive = Some(archive);
+		self
+	}
+
+	/// send the objects of the audio tracks in `mode`, must be set before the input is parsed
+	pub fn with_audio_mode(mut self, mode: StreamMode) -> Self {
+		self.audio_mode = mode;
+		self
+	}
+
+	/// record the published tracks in `metrics`, must be set before the input is parsed
+	pub fn with_metrics(mut self, metrics: crate::metrics::Metrics) -> Self {
+		self.metrics = metrics;
+		self
+	}
+
 	// Parse the input buffer, reading any full atoms we can find.
 	// Keep appending more data and calling parse.
 	pub fn parse<B: Buf>(&mut self, buf: &mut B) -> anyhow::Result<()> {
-		while self.parse_atom(buf)? {}
+		while self.parse_atom(buf, false)? {}
+		Ok(())
+	}
+
+	// Parse the rest of the input once it ended, an atom with size 0 extends until here.
+	pub fn finish<B: Buf>(&mut self, buf: &mut B) -> anyhow::Result<()> {
+		while self.parse_atom(buf, true)? {}
+		anyhow::ensure!(!buf.has_remaining(), "trailing data at end of input");
+		Ok(())
+	}
+
+	// End every track and the catalog, subscribers see them finish instead of the session being reset.
+	pub fn close(self) {
+		for (_, track) in self.tracks {
+			track.close();
+		}
+		if let Err(e) = self.catalog_pub.close(moq_transport::serve::ServeError::Done) {
+			log::debug!("catalog already closed: {e}");
+		}
+	}
+
+	// Like parse, but delays each fragment so the broadcast approximates real-time.
+	// Used when reading a file instead of a live encoder.
+	pub async fn parse_paced<B: Buf>(&mut self, buf: &mut B) -> anyhow::Result<()> {
+		while let Some(atom) = next_atom(buf, false)? {
+			if let Some(timestamp) = self.fragment_time(&atom)? {
+				let delay = self.pacer.delay(timestamp, time::Instant::now());
+				tokio::time::sleep(delay).await;
+			}
+
+			self.process_atom(atom)?;
+		}
 		Ok(())
 	}
 
-	fn parse_atom<B: Buf>(&mut self, buf: &mut B) -> anyhow::Result<bool> {
-		let atom = match next_atom(buf)? {
+	fn parse_atom<B: Buf>(&mut self, buf: &mut B, eof: bool) -> anyhow::Result<bool> {
+		let atom = match next_atom(buf, eof)? {
 			Some(atom) => atom,
 			None => return Ok(false),
 		};
 
+		self.process_atom(atom)?;
+
+		Ok(true)
+	}
+
+	// The media time of a moof atom, None for any other atom.
+	fn fragment_time(&self, atom: &Bytes) -> anyhow::Result<Option<time::Duration>> {
+		let mut reader = Cursor::new(atom);
+		let header = mp4::BoxHeader::read(&mut reader)?;
+		if header.name != mp4::BoxType::MoofBox {
+			return Ok(None);
+		}
+
+		let moof = mp4::MoofBox::read_box(&mut reader, header.size)?;
+		let fragment = Fragment::new(moof)?;
+		let track = self.tracks.get(&fragment.track).context("failed to find track")?;
+
+		Ok(Some(fragment.timestamp(track.timescale)))
+	}
+
+	fn process_atom(&mut self, atom: Bytes) -> anyhow::Result<()> {
 		let mut reader = Cursor::new(&atom);
 		let header = mp4::BoxHeader::read(&mut reader)?;
 
@@ -119,7 +236,8 @@ impl Media {
 				// Get the track for this moof.
 				let track = self.tracks.get_mut(&fragment.track).context("failed to find track")?;
 
-				if fragment.keyframe {
+				// Every audio sample is a keyframe, only video starts new groups.
+				if fragment.keyframe && track.handler == TrackType::Video {
 					track.end_group();
 				}
 
@@ -127,22 +245,40 @@ impl Media {
 				anyhow::ensure!(self.current.is_none(), "multiple moof atoms");
 				self.current.replace(fragment.track);
 
+				track.measurement.fragment(fragment.timestamp, fragment.samples);
+
+				let prft = self.prft.take();
+				let produced = match prft.as_deref().map(crate::atom::Prft::parse) {
+					Some(Ok(prft)) => Some(prft.wallclock),
+					Some(Err(e)) => {
+						log::warn!("skipping invalid prft: {e}");
+						None
+					}
+					None => None,
+				};
+				let atom = match prft {
+					Some(prft) => [prft, atom].concat().into(),
+					None => atom,
+				};
+
 				// Publish the moof header, creating a new segment if it's a keyframe.
+				let id = fragment.track;
 				track.header(atom, fragment).context("failed to publish moof")?;
+				if let Some(wallclock) = produced {
+					track.metrics.produced(wallclock);
+				}
+
+				self.update_catalog(id)?;
 			}
 			mp4::BoxType::MdatBox => {
 				// Get the track ID from the previous moof.
 				let track = self.current.take().context("missing moof")?;
 				let track = self.tracks.get_mut(&track).context("failed to find track")?;
 
+				track.measurement.data(atom.len());
+
 				// Publish the mdat atom.
-				if let Some(prft) = self.prft.clone() {
-					let mut data = atom.clone().to_vec();
-					data.extend_from_slice(&prft);
-					track.data(data.into()).context("failed to publish prft")?;
-				} else {
-					track.data(atom).context("failed to publish mdat")?;
-				}
+				track.data(atom).context("failed to publish mdat")?;
 			}
 
 			_ => {
@@ -150,21 +286,32 @@ impl Media {
 			}
 		}
 
-		Ok(true)
+		Ok(())
 	}
 
 	fn setup(&mut self, moov: &mp4::MoovBox, raw: Bytes) -> anyhow::Result<()> {
-		// Create a track for each track in the moov
+		// Create a track for each track in the moov, named by kind and index, ex. video0 and audio0
+		let mut kinds: HashMap<&str, usize> = HashMap::new();
 		for trak in &moov.traks {
 			let id = trak.tkhd.track_id;
-			let name = format!("{LABEL} {}", id);
+			anyhow::ensure!(!self.tracks.contains_key(&id), "duplicate track ID {id}");
 
 			let timescale = track_timescale(moov, id);
-			let handler = (&trak.mdia.hdlr.handler_type).try_into()?;
+			let handler: TrackType = (&trak.mdia.hdlr.handler_type).try_into()?;
+
+			let kind = track_kind(handler);
+			let index = kinds.entry(kind).or_default();
+			let name = format!("{kind}{index}");
+			*index += 1;
 
 			// Store the track publisher in a map so we can update it later.
 			let track = self.broadcast.create(&name).context("broadcast closed")?;
-			let track = Track::new(track, handler, timescale);
+			let mode = match handler {
+				TrackType::Audio => self.audio_mode,
+				_ => StreamMode::default(),
+			};
+			let mut track = Track::new(track, &name, handler, timescale, mode, self.metrics.track(&name))?;
+			track.track.set_archive(self.archive.clone());
 			self.tracks.insert(id, track);
 		}
 
@@ -172,168 +319,160 @@ impl Media {
 		let mut init = self.ftyp.clone().context("missing ftyp")?.to_vec();
 		init.extend_from_slice(&raw);
 
-		// Add the init to CSF Init Data
-		if let Some(csf) = self.catalog.common_track_fields_mut() {
-			csf.set_init_data(&init);
-		}
-
 		// Produce the catalog
 		for trak in &moov.traks {
-			let rep_id = format!("{LABEL} {}", trak.tkhd.track_id);
-			let mut track = moq_catalog::Track::new(&rep_id, moq_catalog::Packaging::CMAF);
-			let mut params = moq_catalog::SelectionParams::new();
-
-			let stsd = &trak.mdia.minf.stbl.stsd;
-			if let Some(avc1) = &stsd.avc1 {
-				let profile = avc1.avcc.avc_profile_indication;
-				let constraints = avc1.avcc.profile_compatibility; // Not 100% certain here, but it's 0x00 on my current test video
-				let level = avc1.avcc.avc_level_indication;
-
-				let width = avc1.width;
-				let height = avc1.height;
-
-				let codec = rfc6381_codec::Codec::avc1(profile, constraints, level);
-				let codec_str = codec.to_string();
-
-				let index = trak.tkhd.track_id as usize - 1;
-				let bitrate = self.bitrates[index] as u64;
-
-				params
-					.set_height(height)
-					.set_width(width)
-					.set_codec(&codec_str)
-					.set_bitrate(bitrate)
-					.set_mime_type("video/mp4")?;
-			} else if let Some(_hev1) = &stsd.hev1 {
-				// TODO https://github.com/gpac/mp4box.js/blob/325741b592d910297bf609bc7c400fc76101077b/src/box-codecs.js#L106
-				anyhow::bail!("HEVC not yet supported")
-			} else if let Some(mp4a) = &stsd.mp4a {
-				let desc = &mp4a
-					.esds
-					.as_ref()
-					.context("missing esds box for MP4a")?
-					.es_desc
-					.dec_config;
-				let codec_str = format!("mp4a.{:02x}.{}", desc.object_type_indication, desc.dec_specific.profile);
-
-				params
-					.set_codec(&codec_str)
-					.set_mime_type("audio/mp4")?
-					.set_sample_rate(mp4a.samplerate.value());
-
-				let bitrate = max(desc.max_bitrate, desc.avg_bitrate);
-				if bitrate > 0 {
-					params.set_bitrate(bitrate as u64);
-				}
-			} else if let Some(vp09) = &stsd.vp09 {
-				// https://github.com/gpac/mp4box.js/blob/325741b592d910297bf609bc7c400fc76101077b/src/box-codecs.js#L238
-				// let vpcc = &vp09.vpcc;
-				// let codec_str = format!("vp09.0.{:02x}.{:02x}.{:02x}", vpcc.profile, vpcc.level, vpcc.bit_depth);
-
-				// track["kind"] = json!("video");
-				// track["codec"] = json!(codec_str);
-				// track["width"] = json!(vp09.width); // no idea if this needs to be multiplied
-				// track["height"] = json!(vp09.height); // no idea if this needs to be multiplied
-				let _ = vp09;
-
-				// TODO Test if this actually works; I'm just guessing based on mp4box.js
-				anyhow::bail!("VP9 not yet supported")
-			} else {
-				// TODO add av01 support: https://github.com/gpac/mp4box.js/blob/325741b592d910297bf609bc7c400fc76101077b/src/box-codecs.js#L251
-				anyhow::bail!("unknown codec for track: {}", trak.tkhd.track_id);
+			let (name, handler) = self
+				.tracks
+				.get(&trak.tkhd.track_id)
+				.map(|track| (track.name.clone(), track.handler))
+				.context("failed to find track")?;
+			let mut track = moq_catalog::Track::from_init_segment_track(&name, &init, trak.tkhd.track_id)?;
+			let params = track.selection_params_mut().context("missing selection params")?;
+
+			// Prefer the overrides, otherwise use what the moov tells us until the fragments are measured
+			let index = trak.tkhd.track_id as usize - 1;
+			if let Some(bitrate) = self.bitrates.get(index) {
+				params.set_bitrate(*bitrate as u64);
+			}
+
+			// the override wins over the framerate of the stts
+			if let (TrackType::Video, Some(fps)) = (handler, self.fps) {
+				params.set_framerate(fps as u64);
 			}
 
-			track.set_selection_params(params);
+			let alt_group = match handler {
+				TrackType::Audio => AUDIO_ALT_GROUP,
+				_ => VIDEO_ALT_GROUP,
+			};
+			track.set_alt_group(alt_group);
 
-			// tracks.push(track);
 			self.catalog.insert_track(track)?;
 		}
 
-		log::info!("published catalog");
-		println!("{}", self.catalog);
+		self.publish_catalog()
+	}
 
-		let buf = self.catalog.encode()?;
+	/// advertise the measured framerate and bitrate of a track, unless overridden or within [MAX_DRIFT]
+	fn update_catalog(&mut self, id: u32) -> anyhow::Result<()> {
+		let track = self.tracks.get(&id).context("failed to find track")?;
 
-		// Create a single fragment for the segment.
-		self.catalog_pub.append(0)?.write(buf.into())?;
+		let framerate = match (track.handler, self.fps) {
+			(TrackType::Video, None) => track.measurement.framerate().map(|fps| fps.round() as u64),
+			_ => None,
+		};
+		let bitrate = match self.bitrates.get(id as usize - 1) {
+			Some(_) => None,
+			None => track.measurement.bitrate(),
+		};
 
-		Ok(())
-	}
-}
+		let params = self
+			.catalog
+			.track_mut(&track.name)
+			.and_then(|track| track.selection_params_mut())
+			.context("failed to find catalog track")?;
 
-// Find the next full atom in the buffer.
-// TODO return the amount of data still needed in Err?
-fn next_atom<B: Buf>(buf: &mut B) -> anyhow::Result<Option<Bytes>> {
-	let mut peek = Cursor::new(buf.chunk());
+		let mut changed = false;
+		if let Some(framerate) = framerate.filter(|fps| drifted(params.framerate(), *fps)) {
+			params.set_framerate(framerate);
+			changed = true;
+		}
+		if let Some(bitrate) = bitrate.filter(|bitrate| drifted(params.bitrate(), *bitrate)) {
+			params.set_bitrate(bitrate);
+			changed = true;
+		}
 
-	if peek.remaining() < 8 {
-		if buf.remaining() != buf.chunk().len() {
-			// TODO figure out a way to peek at the first 8 bytes
-			anyhow::bail!("TODO: vectored Buf not yet supported");
+		if changed {
+			self.publish_catalog()?;
 		}
 
-		return Ok(None);
+		Ok(())
 	}
 
-	// Convert the first 4 bytes into the size.
-	let size = peek.get_u32();
-	let _type = peek.get_u32();
-
-	let size = match size {
-		// Runs until the end of the file.
-		0 => anyhow::bail!("TODO: unsupported EOF atom"),
-
-		// The next 8 bytes are the extended size to be used instead.
-		1 => {
-			let size_ext = peek.get_u64();
-			anyhow::ensure!(size_ext >= 16, "impossible extended box size: {}", size_ext);
-			size_ext as usize
-		}
-
-		2..=7 => {
-			anyhow::bail!("impossible box size: {}", size)
-		}
+	/// write the full catalog in the first group, afterwards only the delta to the previous one
+	fn publish_catalog(&mut self) -> anyhow::Result<()> {
+		let buf = match &self.published {
+			Some(previous) => self.catalog.encode_delta(previous)?,
+			None => self.catalog.encode()?,
+		};
+		self.published = Some(self.catalog.clone());
 
-		size => size as usize,
-	};
+		// Create a single fragment for the segment.
+		self.catalog_pub.append(0)?.write(buf.into())?;
+		log::info!("published catalog: {}", self.catalog.summary());
+		log::debug!("{}", self.catalog);
 
-	if buf.remaining() < size {
-		return Ok(None);
+		Ok(())
 	}
+}
 
-	let atom = buf.copy_to_bytes(size);
+/// prefix of the track names, ex. video for video0
+fn track_kind(handler: TrackType) -> &'static str {
+	match handler {
+		TrackType::Video => "video",
+		TrackType::Audio => "audio",
+		TrackType::Subtitle => "subtitle",
+	}
+}
 
-	Ok(Some(atom))
+/// true if `measured` differs by more than [MAX_DRIFT] from the `advertised` value
+fn drifted(advertised: Option<u64>, measured: u64) -> bool {
+	match advertised {
+		Some(advertised) => (measured as f64 - advertised as f64).abs() > advertised as f64 * MAX_DRIFT,
+		None => measured > 0,
+	}
 }
 
 struct Track {
 	// The track we're producing
-	track: GroupsWriter,
+	track: ModeWriter,
+
+	// The name of the track, also used in the catalog
+	name: String,
 
 	// The current segment
-	current: Option<GroupWriter>,
+	current: Option<ModeGroupWriter>,
 
 	// The number of units per second.
 	timescale: u64,
 
 	// The type of track, ex. "vide" or "soun"
 	handler: TrackType,
+
+	// The framerate and bitrate of the recent fragments
+	measurement: Measurement,
+
+	// Counters exposed as metrics
+	metrics: crate::metrics::Recorder,
 }
 
 impl Track {
-	fn new(track: TrackWriter, handler: TrackType, timescale: u64) -> Self {
-		Self {
-			track: track.groups().unwrap(),
+	fn new(
+		track: TrackWriter,
+		name: &str,
+		handler: TrackType,
+		timescale: u64,
+		mode: StreamMode,
+		metrics: crate::metrics::Recorder,
+	) -> anyhow::Result<Self> {
+		Ok(Self {
+			track: ModeWriter::new(track, mode).context("broadcast closed")?,
+			name: name.to_string(),
 			current: None,
 			timescale,
 			handler,
-		}
+			measurement: Measurement::new(timescale),
+			metrics,
+		})
 	}
 
 	pub fn header(&mut self, raw: Bytes, fragment: Fragment) -> anyhow::Result<()> {
+		let size = raw.len();
+
 		if let Some(current) = self.current.as_mut() {
 			// Use the existing segment
-			current.write(raw)?;
+			self.track.write(current, raw)?;
+			self.metrics.fragment(fragment.timestamp(self.timescale));
+			self.metrics.object(size);
 			return Ok(());
 		}
 
@@ -353,17 +492,24 @@ impl Track {
 		let mut segment = self.track.append(priority)?;
 
 		// Write the fragment in it's own object.
-		segment.write(raw)?;
+		self.track.write(&mut segment, raw)?;
 
 		// Save for the next iteration
 		self.current = Some(segment);
 
+		let timestamp = fragment.timestamp(self.timescale);
+		self.metrics.group(timestamp);
+		self.metrics.fragment(timestamp);
+		self.metrics.object(size);
+
 		Ok(())
 	}
 
 	pub fn data(&mut self, raw: Bytes) -> anyhow::Result<()> {
 		let segment = self.current.as_mut().context("missing current fragment")?;
-		segment.write(raw)?;
+		let size = raw.len();
+		self.track.write(segment, raw)?;
+		self.metrics.object(size);
 
 		Ok(())
 	}
@@ -371,6 +517,14 @@ impl Track {
 	pub fn end_group(&mut self) {
 		self.current = None;
 	}
+
+	// Finish the current group and end the track.
+	fn close(mut self) {
+		self.end_group();
+		if let Err(e) = self.track.close(moq_transport::serve::ServeError::Done) {
+			log::debug!("track {} already closed: {e}", self.name);
+		}
+	}
 }
 
 struct Fragment {
@@ -382,6 +536,9 @@ struct Fragment {
 
 	// True if this fragment is a keyframe.
 	keyframe: bool,
+
+	// The number of samples in this fragment.
+	samples: u32,
 }
 
 impl Fragment {
@@ -396,10 +553,13 @@ impl Fragment {
 		// Detect if we should start a new segment.
 		let keyframe = sample_keyframe(&moof);
 
+		let samples = moof.trafs[0].trun.as_ref().map_or(0, |trun| trun.sample_count);
+
 		Ok(Self {
 			track,
 			timestamp,
 			keyframe,
+			samples,
 		})
 	}
 
@@ -409,6 +569,106 @@ impl Fragment {
 	}
 }
 
+// Timestamps further than this from the expected media time are treated as a discontinuity, ex. looped input.
+const MAX_DISCONTINUITY: time::Duration = time::Duration::from_secs(10);
+
+// Maps media time to wall-clock time, anchored at the first fragment.
+#[derive(Default)]
+struct Pacer {
+	// (wall-clock, media time) of the anchor fragment
+	anchor: Option<(time::Instant, time::Duration)>,
+}
+
+impl Pacer {
+	// How long to wait before publishing a fragment starting at media time `timestamp`.
+	fn delay(&mut self, timestamp: time::Duration, now: time::Instant) -> time::Duration {
+		let Some((wall, media)) = self.anchor else {
+			self.anchor = Some((now, timestamp));
+			return time::Duration::ZERO;
+		};
+
+		let target = match timestamp.checked_sub(media) {
+			Some(elapsed) => wall + elapsed,
+			// other tracks may be slightly behind the anchor
+			None => wall.checked_sub(media - timestamp).unwrap_or(wall),
+		};
+
+		let delay = target.saturating_duration_since(now);
+		let late = now.saturating_duration_since(target);
+
+		if delay > MAX_DISCONTINUITY || late > MAX_DISCONTINUITY {
+			log::info!("timestamp discontinuity at {:?}, re-anchoring", timestamp);
+			self.anchor = Some((now, timestamp));
+			return time::Duration::ZERO;
+		}
+
+		delay
+	}
+}
+
+// Sliding window over the recent fragments of a track.
+struct Measurement {
+	timescale: u64,
+
+	// (timestamp, samples, bytes) of each fragment, the duration of the last one is not known yet
+	fragments: VecDeque<(u64, u32, usize)>,
+}
+
+impl Measurement {
+	fn new(timescale: u64) -> Self {
+		Self {
+			timescale,
+			fragments: VecDeque::new(),
+		}
+	}
+
+	fn fragment(&mut self, timestamp: u64, samples: u32) {
+		self.fragments.push_back((timestamp, samples, 0));
+
+		// Drop the oldest fragments while the remaining ones still span the window.
+		let window = MEASUREMENT_WINDOW.as_secs() * self.timescale;
+		while self.fragments.len() > 2 && timestamp.saturating_sub(self.fragments[1].0) >= window {
+			self.fragments.pop_front();
+		}
+	}
+
+	fn data(&mut self, size: usize) {
+		if let Some((_, _, bytes)) = self.fragments.back_mut() {
+			*bytes += size;
+		}
+	}
+
+	// Media time covered by the complete fragments, None if too short to be meaningful.
+	fn duration(&self) -> Option<u64> {
+		let first = self.fragments.front()?.0;
+		let last = self.fragments.back()?.0;
+		let duration = last.checked_sub(first)?;
+
+		match duration >= MEASUREMENT_MIN.as_secs() * self.timescale {
+			true => Some(duration),
+			false => None,
+		}
+	}
+
+	// Average frames per second, so variable framerates report their mean.
+	fn framerate(&self) -> Option<f64> {
+		let duration = self.duration()?;
+		let samples: u64 = self.complete().map(|(_, samples, _)| *samples as u64).sum();
+		Some(samples as f64 * self.timescale as f64 / duration as f64)
+	}
+
+	// Average bits per second.
+	fn bitrate(&self) -> Option<u64> {
+		let duration = self.duration()?;
+		let bytes: u64 = self.complete().map(|(_, _, bytes)| *bytes as u64).sum();
+		Some(bytes * 8 * self.timescale / duration)
+	}
+
+	fn complete(&self) -> impl Iterator<Item = &(u64, u32, usize)> {
+		self.fragments.iter().take(self.fragments.len().saturating_sub(1))
+	}
+}
+
 fn sample_timestamp(moof: &mp4::MoofBox) -> Option<u64> {
 	Some(moof.trafs.first()?.tfdt.as_ref()?.base_media_decode_time)
 }
@@ -428,8 +688,8 @@ fn sample_keyframe(moof: &mp4::MoofBox) -> bool {
 				None => default_flags,
 			};
 
-			if i == 0 && trun.first_sample_flags.is_some() {
-				flags = trun.first_sample_flags.unwrap();
+			if let (0, Some(first)) = (i, trun.first_sample_flags) {
+				flags = first;
 			}
 
 			// https://chromium.googlesource.com/chromium/src/media/+/master/formats/mp4/track_run_iterator.cc#177
@@ -455,3 +715,178 @@ fn track_timescale(moov: &mp4::MoovBox, track_id: u32) -> u64 {
 
 	trak.mdia.mdhd.timescale as u64
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_av1_catalog() {
+		let (writer, _, _reader) = moq_transport::serve::Tracks::new("test".to_string()).produce();
+		let mut media = Media::new(writer, None, vec![2_000_000]).unwrap();
+
+		let mut buf = bytes::BytesMut::from(&include_bytes!("../tests/fixtures/av01_init.m4s")[..]);
+		media.parse(&mut buf).unwrap();
+
+		let catalog: serde_json::Value = serde_json::from_slice(&media.catalog.encode().unwrap()).unwrap();
+		let params = &catalog["tracks"][0]["selectionParams"];
+		assert_eq!(params["codec"], "av01.0.08M.08");
+		assert_eq!(params["width"], 1280);
+		assert_eq!(params["height"], 720);
+		assert_eq!(params["bitrate"], 2_000_000);
+	}
+
+	#[test]
+	fn test_closed_broadcast() {
+		let (writer, _, reader) = moq_transport::serve::Tracks::new("test".to_string()).produce();
+		let mut media = Media::new(writer, None, Vec::new()).unwrap();
+		drop(reader);
+
+		// an error instead of a panic
+		let mut buf = bytes::BytesMut::from(&include_bytes!("../tests/fixtures/av01_init.m4s")[..]);
+		assert!(media.parse(&mut buf).is_err());
+	}
+
+	fn atom(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+		let mut atom = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+		atom.extend_from_slice(kind);
+		atom.extend_from_slice(body);
+		atom
+	}
+
+	/// moof+mdat with a single keyframe sample of `size` bytes on track 1
+	fn segment(decode_time: u64, size: u32) -> Vec<u8> {
+		track_segment(1, decode_time, size, true)
+	}
+
+	/// moof+mdat with a single sample of `size` bytes on `track`
+	fn track_segment(track: u8, decode_time: u64, size: u32, keyframe: bool) -> Vec<u8> {
+		let tfhd = atom(b"tfhd", &[0, 2, 0, 0, 0, 0, 0, track]);
+		let tfdt = atom(b"tfdt", &[&[1, 0, 0, 0], &decode_time.to_be_bytes()[..]].concat());
+		let mfhd = atom(b"mfhd", &[0; 8]);
+		let moof_len = 8 + mfhd.len() + 8 + tfhd.len() + tfdt.len() + 28;
+
+		let trun = [
+			&[0, 0, 2, 5][..],
+			&1u32.to_be_bytes(),
+			&(moof_len as u32 + 8).to_be_bytes(),
+			&if keyframe { 0x02000000u32 } else { 0x01010000 }.to_be_bytes(),
+			&size.to_be_bytes(),
+		]
+		.concat();
+		let traf = atom(b"traf", &[tfhd, tfdt, atom(b"trun", &trun)].concat());
+		let moof = atom(b"moof", &[mfhd, traf].concat());
+
+		[moof, atom(b"mdat", &vec![0; size as usize])].concat()
+	}
+
+	#[test]
+	fn test_measurement() {
+		let (writer, _, _reader) = moq_transport::serve::Tracks::new("test".to_string()).produce();
+		let mut media = Media::new(writer, None, Vec::new()).unwrap();
+
+		let mut buf = bytes::BytesMut::from(&include_bytes!("../tests/fixtures/avc_init.m4s")[..]);
+		media.parse(&mut buf).unwrap();
+
+		let params = |media: &Media| {
+			let catalog: serde_json::Value = serde_json::from_slice(&media.catalog.encode().unwrap()).unwrap();
+			catalog["tracks"][0]["selectionParams"].clone()
+		};
+		assert!(params(&media)["framerate"].is_null());
+		assert!(params(&media)["bitrate"].is_null());
+
+		// 12800 timescale, alternating 20ms and 60ms frames average to 25 fps, 5000 byte mdat atoms to 1 Mbit/s
+		let mut time = 0;
+		for i in 0..60 {
+			buf.extend_from_slice(&segment(time, 4992));
+			time += if i % 2 == 0 { 256 } else { 768 };
+		}
+		media.parse(&mut buf).unwrap();
+
+		assert_eq!(params(&media)["framerate"], 25);
+		assert_eq!(params(&media)["bitrate"], 1_000_000);
+
+		// small changes are not advertised
+		for i in 0..300 {
+			buf.extend_from_slice(&segment(time, 5492));
+			time += if i % 2 == 0 { 256 } else { 768 };
+		}
+		media.parse(&mut buf).unwrap();
+		assert_eq!(params(&media)["bitrate"], 1_000_000);
+
+		// the bitrate doubled, it is re-advertised once it drifted far enough from the old one
+		for _ in 0..300 {
+			buf.extend_from_slice(&segment(time, 9992));
+			time += 512;
+		}
+		media.parse(&mut buf).unwrap();
+		assert_eq!(params(&media)["framerate"], 25);
+		let bitrate = params(&media)["bitrate"].as_u64().unwrap();
+		assert!((1_600_000..=2_400_000).contains(&bitrate), "bitrate {bitrate}");
+	}
+
+	/// (group id, last object id) of the latest group of `name`
+	fn latest(reader: &mut moq_transport::serve::TracksReader, name: &str) -> Option<(u64, u64)> {
+		let track = reader.subscribe(name)?;
+		match futures::executor::block_on(track.mode()).ok()? {
+			moq_transport::serve::TrackReaderMode::Groups(groups) => groups.latest(),
+			_ => None,
+		}
+	}
+
+	#[test]
+	fn test_multiple_traks() {
+		let (writer, _, mut reader) = moq_transport::serve::Tracks::new("test".to_string()).produce();
+		let mut media = Media::new(writer, None, Vec::new()).unwrap();
+
+		// the avc and aac init segments muxed into one moov with track IDs 1 and 2
+		let mut buf = bytes::BytesMut::from(&include_bytes!("../tests/fixtures/av_init.m4s")[..]);
+		media.parse(&mut buf).unwrap();
+
+		let catalog: serde_json::Value = serde_json::from_slice(&media.catalog.encode().unwrap()).unwrap();
+		let tracks = catalog["tracks"].as_array().unwrap();
+		assert_eq!(tracks.len(), 2);
+		assert_eq!(tracks[0]["name"], "video0");
+		assert_eq!(tracks[0]["selectionParams"]["codec"], "avc1.64001f");
+		assert_eq!(tracks[0]["altGroup"], VIDEO_ALT_GROUP);
+		assert_eq!(tracks[1]["name"], "audio0");
+		assert_eq!(tracks[1]["selectionParams"]["codec"], "mp4a.40.2");
+		assert_eq!(tracks[1]["altGroup"], AUDIO_ALT_GROUP);
+
+		// two GOPs of two video frames, interleaved with audio frames
+		let mut time = 0;
+		for i in 0..4 {
+			buf.extend_from_slice(&track_segment(1, time, 100, i % 2 == 0));
+			buf.extend_from_slice(&track_segment(2, time, 10, true));
+			time += 512;
+		}
+		media.parse(&mut buf).unwrap();
+
+		assert_eq!(latest(&mut reader, "video0"), Some((1, 3)));
+		// audio keyframes do not split the group
+		assert_eq!(latest(&mut reader, "audio0"), Some((0, 7)));
+	}
+
+	#[test]
+	fn test_pacer() {
+		let mut pacer = Pacer::default();
+		let start = time::Instant::now();
+		let secs = time::Duration::from_secs;
+
+		// the first fragment anchors
+		assert_eq!(pacer.delay(secs(100), start), secs(0));
+		assert_eq!(pacer.delay(secs(102), start), secs(2));
+		assert_eq!(pacer.delay(secs(104), start + secs(3)), secs(1));
+
+		// behind schedule, publish immediately
+		assert_eq!(pacer.delay(secs(105), start + secs(8)), secs(0));
+
+		// looped input jumps back to 0, re-anchor instead of waiting
+		assert_eq!(pacer.delay(secs(0), start + secs(9)), secs(0));
+		assert_eq!(pacer.delay(secs(2), start + secs(9)), secs(2));
+
+		// a jump far ahead re-anchors as well
+		assert_eq!(pacer.delay(secs(3600), start + secs(10)), secs(0));
+		assert_eq!(pacer.delay(secs(3601), start + secs(10)), secs(1));
+	}
+}
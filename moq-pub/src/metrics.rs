@@ -0,0 +1,331 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time;
+
+#[cfg(feature = "metrics-server")]
+use axum::{extract::State, http::header, response::IntoResponse, routing::get, Router};
+
+/// Counters of the published tracks, served in the Prometheus text format.
+///
+/// Cloning is cheap, all clones share the same counters.
+#[derive(Clone, Default)]
+pub struct Metrics {
+	tracks: Arc<Mutex<BTreeMap<String, TrackMetrics>>>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct TrackMetrics {
+	groups: u64,
+	objects: u64,
+	bytes: u64,
+
+	/// media timestamp of the first fragment in the current group
+	group_start: Option<time::Duration>,
+	/// media time covered by the current group
+	group_duration: time::Duration,
+
+	/// wall clock and media timestamp of the first fragment, later fragments are compared against it
+	anchor: Option<(time::Instant, time::Duration)>,
+	/// how far the last fragment was written behind its media timestamp, negative when ahead
+	latency: f64,
+
+	/// groups starting at a media timestamp no other video track starts a group at
+	misaligned: u64,
+
+	/// bytes parsed while nobody was subscribed, never written
+	skipped: u64,
+
+	/// groups started early as the timestamps jumped ahead
+	discontinuities: u64,
+
+	/// groups abandoned as they stayed open too long, the fragments until the next keyframe were skipped
+	dropped: u64,
+
+	/// times corrupt data was skipped up to the next fragment
+	resyncs: u64,
+
+	/// unchanged groups written again by the periodic re-publish, ex. of the catalog
+	republished: u64,
+}
+
+/// a metric exposed for every track
+struct Family {
+	name: &'static str,
+	kind: &'static str,
+	help: &'static str,
+	value: fn(&TrackMetrics) -> String,
+}
+
+const FAMILIES: [Family; 11] = [
+	Family {
+		name: "moq_pub_groups_total",
+		kind: "counter",
+		help: "Groups created",
+		value: |t| t.groups.to_string(),
+	},
+	Family {
+		name: "moq_pub_objects_total",
+		kind: "counter",
+		help: "Objects written",
+		value: |t| t.objects.to_string(),
+	},
+	Family {
+		name: "moq_pub_bytes_total",
+		kind: "counter",
+		help: "Bytes written",
+		value: |t| t.bytes.to_string(),
+	},
+	Family {
+		name: "moq_pub_group_duration_seconds",
+		kind: "gauge",
+		help: "Media time covered by the current group",
+		value: |t| t.group_duration.as_secs_f64().to_string(),
+	},
+	Family {
+		name: "moq_pub_publish_latency_seconds",
+		kind: "gauge",
+		help: "Delay between the media timestamp of the last fragment and the wall clock it was written at",
+		value: |t| t.latency.to_string(),
+	},
+	Family {
+		name: "moq_pub_misaligned_groups_total",
+		kind: "counter",
+		help: "Groups starting at a media timestamp no other video track starts a group at",
+		value: |t| t.misaligned.to_string(),
+	},
+	Family {
+		name: "moq_pub_skipped_bytes_total",
+		kind: "counter",
+		help: "Bytes not written as nobody was subscribed to the track",
+		value: |t| t.skipped.to_string(),
+	},
+	Family {
+		name: "moq_pub_discontinuities_total",
+		kind: "counter",
+		help: "Groups started early as the fragment timestamps jumped ahead of the previous fragment",
+		value: |t| t.discontinuities.to_string(),
+	},
+	Family {
+		name: "moq_pub_dropped_groups_total",
+		kind: "counter",
+		help: "Groups abandoned as they stayed open longer than the maximum group age",
+		value: |t| t.dropped.to_string(),
+	},
+	Family {
+		name: "moq_pub_resyncs_total",
+		kind: "counter",
+		help: "Times corrupt segment data was skipped up to the next fragment, ending the current group",
+		value: |t| t.resyncs.to_string(),
+	},
+	Family {
+		name: "moq_pub_republished_groups_total",
+		kind: "counter",
+		help: "Groups written again unchanged so late subscribers find them, ex. of the catalog or an init track",
+		value: |t| t.republished.to_string(),
+	},
+];
+
+impl Metrics {
+	/// recorder for the track `name`, registered immediately so the track shows up with zero counters
+	pub fn track(&self, name: &str) -> Recorder {
+		self.lock().entry(name.to_string()).or_default();
+
+		Recorder {
+			name: name.to_string(),
+			metrics: self.clone(),
+		}
+	}
+
+	/// all counters in the Prometheus text exposition format
+	pub fn encode(&self) -> String {
+		let tracks = self.lock();
+
+		let mut out = String::new();
+		for family in FAMILIES {
+			let _ = writeln!(out, "# HELP {} {}", family.name, family.help);
+			let _ = writeln!(out, "# TYPE {} {}", family.name, family.kind);
+			for (track, metrics) in tracks.iter() {
+				let _ = writeln!(
+					out,
+					"{}{{track=\"{}\"}} {}",
+					family.name,
+					escape(track),
+					(family.value)(metrics)
+				);
+			}
+		}
+
+		out
+	}
+
+	/// serve the metrics on `/metrics` until the listener fails
+	#[cfg(feature = "metrics-server")]
+	pub async fn serve(self, bind: std::net::SocketAddr) -> anyhow::Result<()> {
+		let listener = std::net::TcpListener::bind(bind)?;
+		self.serve_listener(listener).await
+	}
+
+	/// like [Self::serve], on an already bound listener, ex. to learn the port of `[::]:0`
+	#[cfg(feature = "metrics-server")]
+	pub async fn serve_listener(self, listener: std::net::TcpListener) -> anyhow::Result<()> {
+		listener.set_nonblocking(true)?;
+		log::info!("serving metrics on {}", listener.local_addr()?);
+
+		let app = Router::new().route("/metrics", get(serve_metrics)).with_state(self);
+		axum::Server::from_tcp(listener)?.serve(app.into_make_service()).await?;
+
+		Ok(())
+	}
+
+	fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, TrackMetrics>> {
+		self.tracks.lock().unwrap_or_else(|e| e.into_inner())
+	}
+}
+
+#[cfg(feature = "metrics-server")]
+async fn serve_metrics(State(metrics): State<Metrics>) -> impl IntoResponse {
+	([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], metrics.encode())
+}
+
+/// label values escape backslashes, quotes and newlines
+fn escape(value: &str) -> String {
+	value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Updates the counters of a single track.
+#[derive(Clone)]
+pub struct Recorder {
+	name: String,
+	metrics: Metrics,
+}
+
+impl Recorder {
+	/// a new group was started with the fragment at `timestamp`
+	pub fn group(&self, timestamp: time::Duration) {
+		self.update(|track| {
+			track.groups += 1;
+			track.group_start = Some(timestamp);
+			track.group_duration = time::Duration::ZERO;
+		});
+	}
+
+	/// a fragment with the media `timestamp` is written now
+	pub fn fragment(&self, timestamp: time::Duration) {
+		let now = time::Instant::now();
+
+		self.update(|track| {
+			if let Some(start) = track.group_start {
+				track.group_duration = timestamp.saturating_sub(start);
+			}
+
+			let (wall, media) = *track.anchor.get_or_insert((now, timestamp));
+			let elapsed = now.duration_since(wall).as_secs_f64();
+			let played = timestamp.as_secs_f64() - media.as_secs_f64();
+			track.latency = elapsed - played;
+		});
+	}
+
+	/// the fragment written last was produced at `wallclock`, ex. from its prft
+	///
+	/// Replaces the latency estimated from the media timestamps with the actual delay since it was produced.
+	pub fn produced(&self, wallclock: time::SystemTime) {
+		let latency = match time::SystemTime::now().duration_since(wallclock) {
+			Ok(behind) => behind.as_secs_f64(),
+			Err(e) => -e.duration().as_secs_f64(),
+		};
+
+		self.update(|track| track.latency = latency);
+	}
+
+	/// an object of `size` bytes was written
+	pub fn object(&self, size: usize) {
+		self.update(|track| {
+			track.objects += 1;
+			track.bytes += size as u64;
+		});
+	}
+
+	/// a group of the track was found misaligned with the other video tracks
+	pub fn misaligned(&self) {
+		self.update(|track| track.misaligned += 1);
+	}
+
+	/// `size` bytes were parsed but not written, nobody was subscribed
+	pub fn skipped(&self, size: usize) {
+		self.update(|track| track.skipped += size as u64);
+	}
+
+	/// a group was started early at a gap in the timestamps
+	pub fn discontinuity(&self) {
+		self.update(|track| track.discontinuities += 1);
+	}
+
+	/// the current group was abandoned, it stayed open too long
+	pub fn dropped(&self) {
+		self.update(|track| track.dropped += 1);
+	}
+
+	/// corrupt data was skipped, the current group was ended
+	pub fn resync(&self) {
+		self.update(|track| track.resyncs += 1);
+	}
+
+	/// the newest group was written again unchanged
+	pub fn republished(&self) {
+		self.update(|track| track.republished += 1);
+	}
+
+	fn update<F: FnOnce(&mut TrackMetrics)>(&self, f: F) {
+		f(self.metrics.lock().entry(self.name.clone()).or_default());
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_encode() {
+		let metrics = Metrics::default();
+		let video = metrics.track("video \"hd\"");
+		metrics.track("audio");
+
+		video.group(time::Duration::from_secs(2));
+		video.fragment(time::Duration::from_secs(2));
+		video.object(100);
+		video.fragment(time::Duration::from_millis(2500));
+		video.object(20);
+
+		let encoded = metrics.encode();
+		let lines: Vec<_> = encoded.lines().filter(|l| !l.starts_with('#')).collect();
+		assert_eq!(
+			&lines[..8],
+			[
+				"moq_pub_groups_total{track=\"audio\"} 0",
+				"moq_pub_groups_total{track=\"video \\\"hd\\\"\"} 1",
+				"moq_pub_objects_total{track=\"audio\"} 0",
+				"moq_pub_objects_total{track=\"video \\\"hd\\\"\"} 2",
+				"moq_pub_bytes_total{track=\"audio\"} 0",
+				"moq_pub_bytes_total{track=\"video \\\"hd\\\"\"} 120",
+				"moq_pub_group_duration_seconds{track=\"audio\"} 0",
+				"moq_pub_group_duration_seconds{track=\"video \\\"hd\\\"\"} 0.5",
+			]
+		);
+		assert!(encoded.contains("# TYPE moq_pub_publish_latency_seconds gauge"));
+
+		// written faster than real time
+		let latency = lines[9].rsplit_once(' ').unwrap().1.parse::<f64>().unwrap();
+		assert!(latency < 0.0);
+
+		// a prft tells the actual production time
+		video.produced(time::SystemTime::now() - time::Duration::from_secs(3));
+		let encoded = metrics.encode();
+		let line = encoded
+			.lines()
+			.rfind(|l| l.starts_with("moq_pub_publish_latency_seconds"))
+			.unwrap();
+		let latency = line.rsplit_once(' ').unwrap().1.parse::<f64>().unwrap();
+		assert!((3.0..4.0).contains(&latency), "{line}");
+	}
+}
@@ -0,0 +1,256 @@
+use moq_transport::serve::{self, ServeError};
+
+use crate::archive::{Archive, ArchivingGroupWriter, ArchivingGroupsWriter, GroupArchive};
+
+/// How the objects of a track are sent to the relay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamMode {
+	/// a stream per group, its objects arrive in order
+	#[default]
+	Groups,
+	/// a stream per object, a late object does not hold up the rest of its group
+	Objects,
+	/// a datagram per object, never retransmitted, every object has to fit into a single datagram
+	Datagrams,
+}
+
+/// Holds back the writes of a track until the sink takes more, ex. a slow relay connection.
+pub trait Backpressure: Send + Sync {
+	/// resolves once an object of `size` bytes may be written to the track `name`
+	fn ready(&self, name: &str, size: usize) -> futures::future::BoxFuture<'static, ()>;
+}
+
+/// A track written in its [StreamMode], the objects are archived like the ones of an [ArchivingGroupsWriter].
+pub struct ModeWriter {
+	name: String,
+	mode: Mode,
+	subscribers: serve::Subscribers,
+	archive: Option<Archive>,
+	backpressure: Option<std::sync::Arc<dyn Backpressure>>,
+	/// the id of the next group, the groups mode counts them itself
+	next: u64,
+}
+
+enum Mode {
+	Groups(ArchivingGroupsWriter),
+	Objects(serve::ObjectsWriter),
+	Datagrams(serve::DatagramsWriter),
+}
+
+impl ModeWriter {
+	/// write `track` in `mode`, fails if the track was already dropped by the broadcast, ex. once it was closed
+	pub fn new(track: serve::TrackWriter, mode: StreamMode) -> Result<Self, ServeError> {
+		let name = track.name.clone();
+		let subscribers = track.subscribers();
+		let mode = match mode {
+			StreamMode::Groups => Mode::Groups(ArchivingGroupsWriter::new(track.groups()?, None)),
+			StreamMode::Objects => Mode::Objects(track.objects()?),
+			StreamMode::Datagrams => Mode::Datagrams(track.datagrams()?),
+		};
+
+		Ok(Self {
+			name,
+			mode,
+			subscribers,
+			archive: None,
+			backpressure: None,
+			next: 0,
+		})
+	}
+
+	pub fn name(&self) -> &str {
+		&self.name
+	}
+
+	pub fn mode(&self) -> StreamMode {
+		match self.mode {
+			Mode::Groups(_) => StreamMode::Groups,
+			Mode::Objects(_) => StreamMode::Objects,
+			Mode::Datagrams(_) => StreamMode::Datagrams,
+		}
+	}
+
+	/// the number of subscriptions currently served from the track
+	pub fn subscribers(&self) -> usize {
+		self.subscribers.count()
+	}
+
+	/// archive the groups created from now on, None to stop
+	pub fn set_archive(&mut self, archive: Option<Archive>) {
+		if let Mode::Groups(groups) = &mut self.mode {
+			groups.set_archive(archive.clone());
+		}
+		self.archive = archive;
+	}
+
+	/// wait for `backpressure` before every object, None to write right away
+	pub fn set_backpressure(&mut self, backpressure: Option<std::sync::Arc<dyn Backpressure>>) {
+		self.backpressure = backpressure;
+	}
+
+	pub fn backpressure(&self) -> Option<std::sync::Arc<dyn Backpressure>> {
+		self.backpressure.clone()
+	}
+
+	/// resolves once an object of `size` bytes may be written
+	pub async fn ready(&self, size: usize) {
+		if let Some(backpressure) = &self.backpressure {
+			backpressure.ready(&self.name, size).await;
+		}
+	}
+
+	/// start the next group, its objects are sent with `priority`
+	pub fn append(&mut self, priority: u64) -> Result<ModeGroupWriter, ServeError> {
+		if let Mode::Groups(groups) = &mut self.mode {
+			let group = groups.append(priority)?;
+			return Ok(ModeGroupWriter {
+				group_id: group.group_id,
+				priority,
+				next: 0,
+				group: Some(group),
+				archive: None,
+			});
+		}
+
+		let group_id = self.next;
+		self.next += 1;
+
+		Ok(ModeGroupWriter {
+			group_id,
+			priority,
+			next: 0,
+			group: None,
+			archive: self
+				.archive
+				.as_ref()
+				.map(|archive| archive.group(&self.name, group_id, priority)),
+		})
+	}
+
+	/// write the object made of `parts` as the next object of `group`, without copying them in the groups mode
+	pub fn write_parts(&mut self, group: &mut ModeGroupWriter, parts: &[bytes::Bytes]) -> Result<(), ServeError> {
+		match &mut group.group {
+			Some(stream) if parts.len() > 1 => {
+				stream.write_parts(parts)?;
+				group.next += 1;
+				Ok(())
+			}
+			// the objects and datagrams carry a single payload
+			_ => self.write(group, crate::atom::concat(parts)),
+		}
+	}
+
+	/// write `payload` as the next object of `group`
+	pub fn write(&mut self, group: &mut ModeGroupWriter, payload: bytes::Bytes) -> Result<(), ServeError> {
+		if let Some(stream) = &mut group.group {
+			stream.write(payload)?;
+			group.next += 1;
+			return Ok(());
+		}
+
+		if let Some(archive) = &mut group.archive {
+			archive.object(payload.clone());
+		}
+
+		match &mut self.mode {
+			Mode::Objects(objects) => {
+				let object = serve::Object {
+					group_id: group.group_id,
+					object_id: group.next,
+					priority: group.priority,
+				};
+				objects.write(object, payload)?;
+			}
+			Mode::Datagrams(datagrams) => datagrams.write(serve::Datagram {
+				group_id: group.group_id,
+				object_id: group.next,
+				priority: group.priority,
+				payload,
+			})?,
+			// the groups of this mode have a stream, `group` belongs to another track
+			Mode::Groups(_) => return Err(ServeError::Mode),
+		}
+		group.next += 1;
+
+		Ok(())
+	}
+
+	pub fn close(self, err: ServeError) -> Result<(), ServeError> {
+		match self.mode {
+			Mode::Groups(groups) => groups.close(err),
+			Mode::Objects(objects) => objects.close(err),
+			Mode::Datagrams(datagrams) => datagrams.close(err),
+		}
+	}
+}
+
+/// A group of a [ModeWriter], its objects are written with [ModeWriter::write].
+pub struct ModeGroupWriter {
+	group_id: u64,
+	priority: u64,
+	/// the id of the next object
+	next: u64,
+	/// the stream of the group, in the groups mode
+	group: Option<ArchivingGroupWriter>,
+	/// the archived objects of the other modes
+	archive: Option<GroupArchive>,
+}
+
+impl ModeGroupWriter {
+	pub fn group_id(&self) -> u64 {
+		self.group_id
+	}
+
+	pub fn priority(&self) -> u64 {
+		self.priority
+	}
+
+	/// the number of objects written so far
+	pub fn len(&self) -> usize {
+		self.next as usize
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.next == 0
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[tokio::test]
+	async fn test_modes() {
+		for mode in [StreamMode::Groups, StreamMode::Objects, StreamMode::Datagrams] {
+			let (writer, reader) = serve::Track::new("test".to_string(), "audio".to_string()).produce();
+			let mut track = ModeWriter::new(writer, mode).unwrap();
+			assert_eq!(track.mode(), mode);
+
+			let mut group = track.append(3).unwrap();
+			track.write(&mut group, "first".into()).unwrap();
+			track.write(&mut group, "second".into()).unwrap();
+			let next = track.append(2).unwrap();
+			assert_eq!((group.group_id(), group.len()), (0, 2));
+			assert_eq!((next.group_id(), next.priority()), (1, 2));
+
+			let mode = match reader.mode().await.unwrap() {
+				serve::TrackReaderMode::Groups(_) => StreamMode::Groups,
+				serve::TrackReaderMode::Objects(_) => StreamMode::Objects,
+				serve::TrackReaderMode::Datagrams(_) => StreamMode::Datagrams,
+				serve::TrackReaderMode::Stream(_) => unreachable!(),
+			};
+			assert_eq!(track.mode(), mode);
+		}
+	}
+
+	#[test]
+	fn test_closed() {
+		// the broadcast dropped the track, ex. once it was closed
+		for mode in [StreamMode::Groups, StreamMode::Objects, StreamMode::Datagrams] {
+			let (writer, reader) = serve::Track::new("test".to_string(), "audio".to_string()).produce();
+			drop(reader);
+			assert!(matches!(ModeWriter::new(writer, mode), Err(ServeError::Cancel)));
+		}
+	}
+}
@@ -0,0 +1,37 @@
+use std::time;
+
+/// the last seconds before the start, logged every second
+pub const COUNTDOWN: time::Duration = time::Duration::from_secs(10);
+
+/// returns at the wall-clock time `at`, right away with a warning if it already passed
+pub async fn start(at: time::SystemTime) {
+	let remaining = match at.duration_since(time::SystemTime::now()) {
+		Ok(remaining) => remaining,
+		Err(e) => {
+			log::warn!("the start time passed {:?} ago, starting now", e.duration());
+			return;
+		}
+	};
+	log::info!("starting in {remaining:?}");
+
+	// the monotonic clock, the wall clock may jump meanwhile
+	let deadline = tokio::time::Instant::now() + remaining;
+	loop {
+		let left = deadline.saturating_duration_since(tokio::time::Instant::now());
+		if left.is_zero() {
+			break;
+		}
+
+		let secs = left.as_secs() + u64::from(left.subsec_nanos() > 0);
+		let until = match left > COUNTDOWN {
+			true => left - COUNTDOWN,
+			false => {
+				log::info!("starting in {secs}s");
+				left - time::Duration::from_secs(secs - 1)
+			}
+		};
+		tokio::time::sleep(until).await;
+	}
+
+	log::info!("starting now");
+}
@@ -0,0 +1,226 @@
+// the groups and objects are only recorded by the dash pipeline
+#![cfg_attr(not(feature = "dash"), allow(dead_code))]
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::{path, time};
+
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{mpsc, oneshot};
+
+/// the events buffered for the writer, more are dropped
+pub const CAPACITY: usize = 4096;
+
+/// how often the buffered events are written to the file
+pub const FLUSH_INTERVAL: time::Duration = time::Duration::from_secs(1);
+
+/// Appends a JSONL record to a file for every group created and object written.
+///
+/// The file is written on a separate task, publishing never waits for it:
+/// once [CAPACITY] events are queued, the next ones are dropped and counted instead.
+/// The first failure, ex. a full disk, is logged and disables the stats.
+#[derive(Clone)]
+pub struct Stats {
+	records: mpsc::Sender<Record>,
+	dropped: Arc<AtomicU64>,
+}
+
+enum Record {
+	Event(Event),
+	Flush(oneshot::Sender<()>),
+}
+
+/// A line of the stats file.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Event {
+	/// milliseconds since the unix epoch
+	pub ts: u64,
+	pub track: String,
+	pub group_seq: u64,
+	pub priority: u64,
+	/// the size of the object, 0 for a group
+	pub bytes: u64,
+	/// the media time of the group start or object, None for the catalog
+	pub media_ts_ms: Option<u64>,
+	pub kind: Kind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+	Group,
+	Object,
+}
+
+impl Stats {
+	/// append to `path`, moved to `<path>.1` once it would grow past `max_size` if given; must be called within a tokio runtime
+	pub fn new<P>(path: P, max_size: Option<u64>) -> Self
+	where
+		P: AsRef<path::Path>,
+	{
+		let path = path.as_ref().to_path_buf();
+		let (records, rx) = mpsc::channel(CAPACITY);
+		let dropped = Arc::new(AtomicU64::new(0));
+
+		let counter = dropped.clone();
+		tokio::spawn(async move {
+			if let Err(e) = run(&path, max_size, rx, counter).await {
+				log::error!("writing the stats to {} failed, disabling them: {e}", path.display());
+			}
+		});
+
+		Self { records, dropped }
+	}
+
+	/// the events dropped so far because the writer fell behind
+	pub fn dropped(&self) -> u64 {
+		self.dropped.load(Ordering::Relaxed)
+	}
+
+	/// wait until everything recorded so far is written, returns immediately if the stats are disabled
+	pub async fn flush(&self) {
+		let (tx, rx) = oneshot::channel();
+		if self.records.send(Record::Flush(tx)).await.is_ok() {
+			let _ = rx.await;
+		}
+	}
+
+	/// record the group `group_seq` of `track`, created for the media time `media`
+	pub(crate) fn group(&self, track: &str, group_seq: u64, priority: u64, media: Option<time::Duration>) {
+		self.record(Event {
+			ts: now(),
+			track: track.to_string(),
+			group_seq,
+			priority,
+			bytes: 0,
+			media_ts_ms: media.map(|media| media.as_millis() as u64),
+			kind: Kind::Group,
+		});
+	}
+
+	/// record an object of `bytes` written to the group `group_seq` of `track`
+	pub(crate) fn object(
+		&self,
+		track: &str,
+		group_seq: u64,
+		priority: u64,
+		bytes: usize,
+		media: Option<time::Duration>,
+	) {
+		self.record(Event {
+			ts: now(),
+			track: track.to_string(),
+			group_seq,
+			priority,
+			bytes: bytes as u64,
+			media_ts_ms: media.map(|media| media.as_millis() as u64),
+			kind: Kind::Object,
+		});
+	}
+
+	/// never blocks, a disabled writer dropped the receiver and the events are discarded
+	fn record(&self, event: Event) {
+		if let Err(mpsc::error::TrySendError::Full(_)) = self.records.try_send(Record::Event(event)) {
+			self.dropped.fetch_add(1, Ordering::Relaxed);
+		}
+	}
+}
+
+async fn run(
+	path: &path::Path,
+	max_size: Option<u64>,
+	mut records: mpsc::Receiver<Record>,
+	dropped: Arc<AtomicU64>,
+) -> std::io::Result<()> {
+	let mut file = tokio::io::BufWriter::new(open(path).await?);
+	let mut size = file.get_ref().metadata().await?.len();
+	let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+	let mut reported = 0;
+
+	loop {
+		let record = tokio::select! {
+			record = records.recv() => record,
+			_ = interval.tick() => {
+				file.flush().await?;
+
+				let dropped = dropped.load(Ordering::Relaxed);
+				if dropped > reported {
+					log::warn!("dropped {} stats events, the file is written too slowly", dropped - reported);
+					reported = dropped;
+				}
+				continue;
+			}
+		};
+
+		match record {
+			Some(Record::Event(event)) => {
+				let mut line = serde_json::to_vec(&event)?;
+				line.push(b'\n');
+
+				if max_size.is_some_and(|max| size > 0 && size + line.len() as u64 > max) {
+					file.flush().await?;
+					let rotated = path.with_extension(match path.extension() {
+						Some(ext) => format!("{}.1", ext.to_string_lossy()),
+						None => "1".to_string(),
+					});
+					tokio::fs::rename(path, rotated).await?;
+					file = tokio::io::BufWriter::new(open(path).await?);
+					size = 0;
+				}
+
+				file.write_all(&line).await?;
+				size += line.len() as u64;
+			}
+			Some(Record::Flush(done)) => {
+				file.flush().await?;
+				let _ = done.send(());
+			}
+			None => return file.flush().await,
+		}
+	}
+}
+
+async fn open(path: &path::Path) -> std::io::Result<tokio::fs::File> {
+	if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+		tokio::fs::create_dir_all(dir).await?;
+	}
+	tokio::fs::OpenOptions::new().create(true).append(true).open(path).await
+}
+
+/// milliseconds since the unix epoch
+fn now() -> u64 {
+	time::SystemTime::now()
+		.duration_since(time::UNIX_EPOCH)
+		.unwrap_or_default()
+		.as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[tokio::test]
+	async fn test_rotate() {
+		let dir = crate::testing::temp_dir("stats");
+		let path = dir.join("stats.jsonl");
+
+		let stats = Stats::new(&path, Some(300));
+		for object in 0..4 {
+			stats.object("video", 0, 0, 100, Some(time::Duration::from_millis(object * 40)));
+		}
+		stats.flush().await;
+
+		// whole lines are moved, the newest ones are in the file
+		let lines = |path: &path::Path| -> Vec<Event> {
+			let buf = std::fs::read_to_string(path).unwrap();
+			buf.lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+		};
+		let (rotated, current) = (lines(&dir.join("stats.jsonl.1")), lines(&path));
+		assert_eq!(rotated.len() + current.len(), 4);
+		assert!(!current.is_empty());
+		assert_eq!(current.last().unwrap().media_ts_ms, Some(120));
+		assert_eq!(stats.dropped(), 0);
+
+		let _ = std::fs::remove_dir_all(&dir);
+	}
+}
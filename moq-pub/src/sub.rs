@@ -0,0 +1,214 @@
+use anyhow::Context;
+use moq_catalog::{CatalogFormat, CatalogWatcher, MoqCatalog};
+use moq_transport::serve::{GroupsReader, TrackReader, TrackReaderMode, Tracks, TracksReader, TracksWriter};
+use moq_transport::session::Subscriber;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+/// which track of the catalog is played
+#[derive(Debug, Clone, PartialEq)]
+pub enum Selection {
+	/// the track with this name, waits for a catalog listing it
+	Name(String),
+	/// the track with the highest bitrate in its selectionParams
+	HighestBitrate,
+}
+
+impl Selection {
+	fn select<'a>(&self, catalog: &'a MoqCatalog) -> Option<&'a moq_catalog::Track> {
+		match self {
+			Self::Name(name) => catalog.track(name),
+			Self::HighestBitrate => catalog
+				.tracks()
+				.iter()
+				.max_by_key(|track| track.selection_params().and_then(|params| params.bitrate())),
+		}
+	}
+}
+
+/// Where the tracks of a broadcast are subscribed from.
+pub trait Source {
+	fn subscribe(&mut self, name: &str) -> anyhow::Result<TrackReader>;
+}
+
+/// tracks published in the same process, ex. by a [crate::dash::DashPublisher]
+impl Source for TracksReader {
+	fn subscribe(&mut self, name: &str) -> anyhow::Result<TrackReader> {
+		TracksReader::subscribe(self, name).context(format!("no track {name}"))
+	}
+}
+
+/// tracks subscribed from a relay
+pub struct Remote {
+	subscriber: Subscriber,
+	writer: TracksWriter,
+	reader: TracksReader,
+}
+
+impl Remote {
+	pub fn new(subscriber: Subscriber, namespace: String) -> Self {
+		let (writer, _, reader) = Tracks::new(namespace).produce();
+		Self {
+			subscriber,
+			writer,
+			reader,
+		}
+	}
+}
+
+impl Source for Remote {
+	fn subscribe(&mut self, name: &str) -> anyhow::Result<TrackReader> {
+		let track = self
+			.writer
+			.create(name)
+			.context(format!("failed to create track {name}"))?;
+
+		let mut subscriber = self.subscriber.clone();
+		let track_name = name.to_string();
+		tokio::task::spawn(async move {
+			if let Err(err) = subscriber.subscribe(track).await {
+				log::warn!("failed to subscribe to {track_name}: {err:?}");
+			}
+		});
+
+		self.reader.subscribe(name).context(format!("no track {name}"))
+	}
+}
+
+/// Plays a track of a broadcast by writing its init segment followed by its media segments.
+pub struct Player<S, O> {
+	source: S,
+	output: O,
+	selection: Selection,
+	catalog_track: String,
+}
+
+impl<S: Source, O: AsyncWrite + Unpin> Player<S, O> {
+	pub fn new(source: S, output: O, selection: Selection) -> Self {
+		Self {
+			source,
+			output,
+			selection,
+			catalog_track: crate::CATALOG_TRACK.to_string(),
+		}
+	}
+
+	/// read the catalog from the track `name` instead of `.catalog`
+	pub fn catalog_track(mut self, name: &str) -> Self {
+		self.catalog_track = name.to_string();
+		self
+	}
+
+	/// select a track from the catalog and write it to the output until the track ends
+	pub async fn run(&mut self) -> anyhow::Result<()> {
+		let track = self.select().await?;
+		log::info!("playing track {}", track.name());
+
+		let mut groups = groups(self.source.subscribe(track.name())?).await?;
+
+		while let Some(mut group) = groups.next().await? {
+			while let Some(object) = group.read_next().await? {
+				self.output.write_all(&object).await?;
+			}
+			self.output.flush().await?;
+		}
+
+		Ok(())
+	}
+
+	/// read catalogs until one contains a matching track and write its init segment
+	async fn select(&mut self) -> anyhow::Result<moq_catalog::Track> {
+		let mut catalogs = groups(self.source.subscribe(&self.catalog_track)?).await?;
+		let mut watcher = CatalogWatcher::new();
+
+		loop {
+			let mut group = catalogs
+				.next()
+				.await?
+				.context("catalog ended without a matching track")?;
+			let Some(object) = group.read_next().await? else {
+				continue;
+			};
+
+			// the latest catalog wins, deltas are applied to the one before
+			watcher.update(&object)?;
+			let Some(current) = watcher.catalog().cloned() else {
+				continue;
+			};
+			// a root catalog lists the catalogs of the media types instead of tracks
+			let current = match current.catalogs().is_empty() {
+				true => current,
+				false => self.assemble(&current).await?,
+			};
+
+			if let Some(track) = self.selection.select(&current) {
+				let init = match (track.init_data()?, track.init_track()) {
+					(Some(init), _) => init,
+					(None, Some(init_track)) => self.init(init_track).await?,
+					(None, None) => current
+						.common_track_fields()
+						.map(|csf| csf.init_data())
+						.transpose()?
+						.flatten()
+						.context(format!("no initData for track {}", track.name()))?,
+				};
+				self.output.write_all(&init).await?;
+
+				return Ok(track.clone());
+			}
+
+			log::info!("no track matching {:?} in the catalog yet", self.selection);
+		}
+	}
+
+	/// a single catalog with the tracks of all catalogs listed by `root`, in their order
+	async fn assemble(&mut self, root: &MoqCatalog) -> anyhow::Result<MoqCatalog> {
+		let mut assembled: Option<MoqCatalog> = None;
+		let mut tracks = Vec::new();
+		for child in root.catalogs() {
+			let mut groups = groups(self.source.subscribe(child.name())?).await?;
+			let mut group = groups
+				.next()
+				.await?
+				.context(format!("catalog {} ended", child.name()))?;
+			let object = group
+				.read_next()
+				.await?
+				.context(format!("empty group on catalog {}", child.name()))?;
+
+			let catalog = decode(&object)?;
+			tracks.extend_from_slice(catalog.tracks());
+			// the common track fields are the same in all of them
+			assembled.get_or_insert(catalog);
+		}
+
+		let mut assembled = assembled.context("root catalog lists no catalogs")?;
+		assembled.set_tracks(&tracks)?;
+		Ok(assembled)
+	}
+
+	/// the init segment in the latest group of `init_track`, split into several objects if large
+	async fn init(&mut self, init_track: &str) -> anyhow::Result<Vec<u8>> {
+		let mut groups = groups(self.source.subscribe(init_track)?).await?;
+		let mut group = groups.next().await?.context(format!("init track {init_track} ended"))?;
+		let mut init = Vec::new();
+		while let Some(object) = group.read_next().await? {
+			init.extend_from_slice(&object);
+		}
+		anyhow::ensure!(!init.is_empty(), "empty group on init track {init_track}");
+
+		Ok(init)
+	}
+}
+
+async fn groups(track: TrackReader) -> anyhow::Result<GroupsReader> {
+	match track.mode().await? {
+		TrackReaderMode::Groups(groups) => Ok(groups),
+		_ => anyhow::bail!("expected groups mode for {}", track.name),
+	}
+}
+
+/// decode a catalog in any [CatalogFormat], sniffed from its first byte
+fn decode(buf: &[u8]) -> anyhow::Result<MoqCatalog> {
+	let format = CatalogFormat::sniff(buf).context("catalog is neither JSON nor CBOR")?;
+	Ok(MoqCatalog::decode_with(buf, format)?)
+}
@@ -0,0 +1,9 @@
+//! Helpers shared by the unit tests of the modules.
+
+/// fresh, empty directory below the system temp dir
+pub(crate) fn temp_dir(name: &str) -> std::path::PathBuf {
+	let dir = std::env::temp_dir().join(format!("moq-pub-{}-{}", name, std::process::id()));
+	let _ = std::fs::remove_dir_all(&dir);
+	std::fs::create_dir_all(&dir).unwrap();
+	dir
+}
@@ -0,0 +1,97 @@
+#![cfg(feature = "dash")]
+
+mod common;
+
+use std::time;
+
+use common::{fixture, groups, next_group, temp_dir, Relay, Subscriber, SETTINGS, TIMEOUT};
+use moq_pub::dash::{self, DashPublisher, Settings};
+use moq_transport::serve;
+
+#[tokio::test]
+async fn announces_broadcasts_on_one_session() {
+	let dir = temp_dir("broadcasts");
+	let settings_file = dir.join("settings.csv");
+	std::fs::write(&settings_file, SETTINGS).unwrap();
+
+	let relay = Relay::start(&dir);
+
+	let namespaces = ["camera1", "camera2"];
+	let mut publishers = Vec::new();
+	let mut readers = Vec::new();
+	for namespace in namespaces {
+		let output = dir.join(namespace);
+		std::fs::create_dir_all(&output).unwrap();
+		let settings = Settings::new(
+			settings_file.clone(),
+			dir.join("input.mp4").into(),
+			output.clone(),
+			true,
+			false,
+		)
+		.unwrap();
+
+		let (mut publisher, reader): (DashPublisher, _) = DashPublisher::builder()
+			.output(&output)
+			.settings(settings)
+			.namespace(namespace)
+			.build()
+			.unwrap();
+		publishers.push(tokio::spawn(async move { publisher.run().await }));
+		readers.push(reader);
+	}
+
+	// give the watchers time to register
+	tokio::time::sleep(time::Duration::from_millis(100)).await;
+	for namespace in namespaces {
+		let output = dir.join(namespace);
+		std::fs::copy(fixture("avc_init.m4s"), output.join("source_init_rep_0.m4s")).unwrap();
+		std::fs::copy(fixture("chunk_1.m4s"), output.join("source_chunk_00001_rep_0.m4s")).unwrap();
+		std::fs::copy(fixture("chunk_2.m4s"), output.join("source_chunk_00002_rep_0.m4s")).unwrap();
+	}
+
+	// the subscriber follows right after the announce, unknown tracks are not found
+	let published = |reader: &serve::TracksReader| reader.tracks().iter().any(|track| track.name == "720p");
+	tokio::time::timeout(TIMEOUT, async {
+		while !readers.iter().all(published) {
+			tokio::time::sleep(time::Duration::from_millis(10)).await;
+		}
+	})
+	.await
+	.expect("timed out waiting for the init segments");
+
+	let options = relay.options("");
+	let announce = tokio::spawn(async move {
+		let shutdown = tokio::sync::Notify::new();
+		dash::announce_all(&options, readers, &Default::default(), &shutdown).await
+	});
+
+	let mut subscriber = Subscriber::connect(&relay).await;
+	let mut groups_of = Vec::new();
+	for namespace in namespaces {
+		relay.announced(namespace).await;
+		groups_of.push(groups(subscriber.subscribe(namespace, "720p")).await);
+	}
+
+	// both were announced on a single session
+	let connections = relay.connections.list();
+	assert_eq!(connections.len(), 2);
+	let publisher = connections
+		.iter()
+		.find(|connection| connection.role == moq_relay::Role::Publisher)
+		.unwrap();
+	assert_eq!(publisher.namespaces, namespaces);
+
+	let keyframe = std::fs::read(fixture("chunk_1.m4s")).unwrap();
+	for groups in &mut groups_of {
+		let (_, group) = next_group(groups, 2).await;
+		assert_eq!(group[0], keyframe[..96]);
+		assert_eq!(&group[1][4..8], b"mdat");
+	}
+
+	for publisher in publishers {
+		publisher.abort();
+	}
+	announce.abort();
+	let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,299 @@
+//! The relay of moq-relay and a minimal subscriber, running in-process on random localhost ports.
+//!
+//! Every test crate uses a subset of the helpers.
+#![allow(dead_code)]
+
+use std::{net, path, time};
+
+use moq_pub::dash;
+use moq_transport::serve;
+
+/// how long the helpers wait for the relay, the publisher or the subscriber
+pub const TIMEOUT: time::Duration = time::Duration::from_secs(10);
+
+/// a ladder of a single 720p rep
+pub const SETTINGS: &str = "gop_num=1
+fps=25
+target_segment_duration=2.0
+===AUDIO===
+name,sampling,bitrate
+===VIDEO===
+name,resolution,bitrate,max_rate,buffer_size
+720p,1280x720,3000000,3000000,6000000
+";
+
+/// pre-generated segments of the 720p rep and the name the ffmpeg dash muxer would give them
+pub const SEGMENTS: [(&str, &str); 3] = [
+	("avc_init.m4s", "source_init_rep_0.m4s"),
+	("chunk_1.m4s", "source_chunk_00001_rep_0.m4s"),
+	("chunk_2.m4s", "source_chunk_00002_rep_0.m4s"),
+];
+
+pub fn fixture(name: &str) -> path::PathBuf {
+	path::Path::new(env!("CARGO_MANIFEST_DIR"))
+		.join("tests/fixtures")
+		.join(name)
+}
+
+/// fresh, empty directory below the system temp dir
+pub fn temp_dir(name: &str) -> path::PathBuf {
+	let dir = std::env::temp_dir().join(format!("moq-pub-{}-{}", name, std::process::id()));
+	let _ = std::fs::remove_dir_all(&dir);
+	std::fs::create_dir_all(&dir).unwrap();
+	dir
+}
+
+/// A relay accepting publishers and subscribers, aborted once dropped.
+pub struct Relay {
+	addr: net::SocketAddr,
+	cert: path::PathBuf,
+	key: path::PathBuf,
+	locals: moq_relay::Locals,
+	pub connections: moq_relay::Connections,
+	task: tokio::task::JoinHandle<anyhow::Result<()>>,
+	web: Option<tokio::task::JoinHandle<anyhow::Result<()>>>,
+}
+
+impl Relay {
+	/// listen on a random localhost port, with a self-signed certificate generated in `dir`
+	pub fn start(dir: &path::Path) -> Self {
+		let generated = rcgen::generate_simple_self_signed(["localhost".to_string(), "127.0.0.1".to_string()]).unwrap();
+		let cert = dir.join("relay.crt");
+		let key = dir.join("relay.key");
+		std::fs::write(&cert, generated.serialize_pem().unwrap()).unwrap();
+		std::fs::write(&key, generated.serialize_private_key_pem()).unwrap();
+
+		let relay = listen("127.0.0.1:0".parse().unwrap(), &cert, &key).unwrap();
+		Self {
+			addr: relay.local_addr().unwrap(),
+			cert,
+			key,
+			locals: relay.locals(),
+			connections: relay.connections(),
+			task: tokio::spawn(relay.run()),
+			web: None,
+		}
+	}
+
+	/// serve the web routes of the relay over plain HTTP on a random localhost port, the limiter is never applied
+	pub async fn web(&mut self) -> net::SocketAddr {
+		// the port of the web server is not known otherwise
+		let bind = net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+		let web = moq_relay::Web::new(moq_relay::WebConfig {
+			public_bind: "127.0.0.1:0".parse().unwrap(),
+			http_bind: Some(bind),
+			admin_bind: None,
+			admin_token: None,
+			tls: server_tls(&self.cert, &self.key).load().unwrap(),
+			limit_interfaces: Some(Vec::new()),
+			locals: self.locals.clone(),
+			connections: self.connections.clone(),
+			limiter_log: None,
+			prefix: String::new(),
+			allow_origins: Vec::new(),
+		});
+		self.web = Some(tokio::spawn(web.run()));
+
+		tokio::time::timeout(TIMEOUT, async {
+			while tokio::net::TcpStream::connect(bind).await.is_err() {
+				tokio::time::sleep(time::Duration::from_millis(10)).await;
+			}
+		})
+		.await
+		.expect("the web server did not start");
+		bind
+	}
+
+	/// drop every session and listen again on the same address, the announced broadcasts are gone
+	pub async fn restart(&mut self) {
+		self.task.abort();
+		let _ = (&mut self.task).await;
+
+		// the closing connections keep the port busy for a moment
+		let relay = tokio::time::timeout(TIMEOUT, async {
+			loop {
+				match listen(self.addr, &self.cert, &self.key) {
+					Ok(relay) => break relay,
+					Err(_) => tokio::time::sleep(time::Duration::from_millis(10)).await,
+				}
+			}
+		})
+		.await
+		.expect("the address stayed in use");
+
+		self.locals = relay.locals();
+		self.connections = relay.connections();
+		self.task = tokio::spawn(relay.run());
+	}
+
+	/// client configuration verifying the generated certificate
+	pub fn tls(&self) -> moq_native::tls::Args {
+		moq_native::tls::Args {
+			cert: Vec::new(),
+			key: Vec::new(),
+			root: vec![self.cert.clone()],
+			disable_verify: false,
+		}
+	}
+
+	pub fn url(&self) -> url::Url {
+		format!("moqt://{}", self.addr).parse().unwrap()
+	}
+
+	/// how a publisher announces `namespace` to the relay
+	pub fn options(&self, namespace: &str) -> dash::ConnectOptions {
+		dash::ConnectOptions {
+			tls: self.tls(),
+			url: self.url(),
+			bind: "127.0.0.1:0".parse().unwrap(),
+			namespace: namespace.to_string(),
+			transport: Default::default(),
+		}
+	}
+
+	/// wait until a publisher announced `namespace`, subscriptions before are not found
+	pub async fn announced(&self, namespace: &str) {
+		tokio::time::timeout(TIMEOUT, async {
+			while self.locals.get(namespace).is_none() {
+				tokio::time::sleep(time::Duration::from_millis(10)).await;
+			}
+		})
+		.await
+		.unwrap_or_else(|_| panic!("{namespace} was not announced"));
+	}
+
+	/// wait until the publisher of `namespace` unannounced it
+	pub async fn unannounced(&self, namespace: &str) {
+		tokio::time::timeout(TIMEOUT, async {
+			while self.locals.get(namespace).is_some() {
+				tokio::time::sleep(time::Duration::from_millis(10)).await;
+			}
+		})
+		.await
+		.unwrap_or_else(|_| panic!("{namespace} is still announced"));
+	}
+}
+
+/// the generated certificate served by the relay
+fn server_tls(cert: &path::Path, key: &path::Path) -> moq_native::tls::Args {
+	moq_native::tls::Args {
+		cert: vec![cert.to_path_buf()],
+		key: vec![key.to_path_buf()],
+		root: vec![cert.to_path_buf()],
+		disable_verify: false,
+	}
+}
+
+fn listen(bind: net::SocketAddr, cert: &path::Path, key: &path::Path) -> anyhow::Result<moq_relay::Relay> {
+	moq_relay::Relay::new(moq_relay::RelayConfig {
+		bind,
+		tls: server_tls(cert, key).load()?,
+		announce: None,
+		api: None,
+		node: None,
+	})
+}
+
+impl Drop for Relay {
+	fn drop(&mut self) {
+		self.task.abort();
+		if let Some(web) = &self.web {
+			web.abort();
+		}
+	}
+}
+
+/// announce `reader` to the relay until aborted
+pub fn announce(relay: &Relay, reader: serve::TracksReader) -> tokio::task::JoinHandle<Result<(), dash::Error>> {
+	let options = relay.options(&reader.namespace);
+	tokio::spawn(async move {
+		let shutdown = tokio::sync::Notify::new();
+		dash::announce(&options, reader, &Default::default(), &shutdown).await
+	})
+}
+
+/// A subscriber session with the relay, closed once dropped.
+pub struct Subscriber {
+	subscriber: moq_transport::session::Subscriber,
+	tasks: Vec<tokio::task::JoinHandle<()>>,
+}
+
+impl Subscriber {
+	pub async fn connect(relay: &Relay) -> Self {
+		let endpoint = moq_native::quic::Endpoint::new(moq_native::quic::Config {
+			bind: "127.0.0.1:0".parse().unwrap(),
+			tls: relay.tls().load().unwrap(),
+			transport: Default::default(),
+		})
+		.unwrap();
+
+		let session = endpoint.client.connect(&relay.url()).await.unwrap();
+		let (session, subscriber) = moq_transport::session::Subscriber::connect(session).await.unwrap();
+		let session = tokio::spawn(async move {
+			if let Err(e) = session.run().await {
+				log::debug!("subscriber session ended: {e}");
+			}
+		});
+
+		Self {
+			subscriber,
+			tasks: vec![session],
+		}
+	}
+
+	/// subscribe to the track `name`, its mode is known once the first group arrived
+	pub fn subscribe(&mut self, namespace: &str, name: &str) -> serve::TrackReader {
+		let (writer, reader) = serve::Track::new(namespace.to_string(), name.to_string()).produce();
+		let mut subscriber = self.subscriber.clone();
+		self.tasks.push(tokio::spawn(async move {
+			if let Err(e) = subscriber.subscribe(writer).await {
+				log::debug!("subscription ended: {e}");
+			}
+		}));
+
+		reader
+	}
+
+	/// the newest catalog of `namespace`
+	pub async fn catalog(&mut self, namespace: &str) -> moq_catalog::MoqCatalog {
+		let mut groups = groups(self.subscribe(namespace, ".catalog")).await;
+		let (_, objects) = next_group(&mut groups, 1).await;
+		moq_catalog::MoqCatalog::decode(&objects[0]).unwrap()
+	}
+}
+
+impl Drop for Subscriber {
+	fn drop(&mut self) {
+		for task in &self.tasks {
+			task.abort();
+		}
+	}
+}
+
+/// the groups of `track`, once the first one arrived
+pub async fn groups(track: serve::TrackReader) -> serve::GroupsReader {
+	match tokio::time::timeout(TIMEOUT, track.mode()).await {
+		Ok(Ok(serve::TrackReaderMode::Groups(groups))) => groups,
+		Ok(Ok(_)) => panic!("{} is not sent in groups", track.name),
+		Ok(Err(e)) => panic!("subscribing to {} failed: {e}", track.name),
+		Err(_) => panic!("no group of {} arrived", track.name),
+	}
+}
+
+/// the id and the first `count` objects of the next group
+///
+/// Only the newest group is returned, older ones that were not read in time are skipped.
+pub async fn next_group(groups: &mut serve::GroupsReader, count: usize) -> (u64, Vec<bytes::Bytes>) {
+	let read = async {
+		let mut group = groups.next().await.unwrap().expect("track ended");
+		let mut objects = Vec::new();
+		while objects.len() < count {
+			objects.push(group.read_next().await.unwrap().expect("group ended"));
+		}
+		(group.group_id, objects)
+	};
+
+	tokio::time::timeout(TIMEOUT, read)
+		.await
+		.expect("timed out waiting for a group")
+}
@@ -0,0 +1,161 @@
+#![cfg(feature = "dash")]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use futures::FutureExt;
+use moq_pub::dash::{Publisher, Settings};
+use moq_transport::serve::TrackReaderMode;
+
+/// counts the bytes allocated, every copy of the published data needs a new allocation
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+	unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+		ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+		System.alloc(layout)
+	}
+
+	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+		System.dealloc(ptr, layout)
+	}
+
+	unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+		ALLOCATED.fetch_add(new_size.saturating_sub(layout.size()), Ordering::Relaxed);
+		System.realloc(ptr, layout, new_size)
+	}
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const SETTINGS: &str = "gop_num=1
+fps=25
+target_segment_duration=2.0
+===AUDIO===
+name,sampling,bitrate
+===VIDEO===
+name,resolution,bitrate,max_rate,buffer_size
+2160p,3840x2160,20000000,20000000,40000000
+";
+
+/// 25 fragments of prft, moof and a 100 kB mdat
+fn fragments() -> Vec<u8> {
+	let chunk = include_bytes!("fixtures/chunk_1.m4s");
+	let (moof, _) = chunk.split_at(96);
+	let mdat = [&(100_008u32.to_be_bytes())[..], b"mdat", &[0; 100_000]].concat();
+	let prft = [
+		&[0, 0, 0, 32][..],
+		b"prft",
+		&[1, 0, 0, 0, 0, 0, 0, 1],
+		&[0xe0; 4],
+		&[0; 12],
+	]
+	.concat();
+
+	[prft, moof.to_vec(), mdat].concat().repeat(25)
+}
+
+/// a publisher of the single 2160p track, with its init segment published
+async fn publisher() -> (Publisher, moq_transport::serve::TracksReader) {
+	let settings = Settings::from_bytes(
+		SETTINGS.as_bytes().to_vec(),
+		"input".into(),
+		"output".into(),
+		true,
+		false,
+	)
+	.unwrap();
+	let (writer, _, reader) = moq_transport::serve::Tracks::new("test".to_string()).produce();
+	let mut publisher = Publisher::new(
+		writer,
+		settings,
+		Default::default(),
+		Default::default(),
+		Default::default(),
+		Default::default(),
+	)
+	.unwrap();
+
+	let init = include_bytes!("fixtures/avc_init.m4s");
+	publisher.publish(0, bytes::Bytes::from_static(init)).unwrap();
+	publisher.flush().await.unwrap();
+
+	(publisher, reader)
+}
+
+/// bytes allocated per published byte, with the stream handed over in reads of `read` bytes
+async fn allocated(read: usize) -> f64 {
+	let (mut publisher, mut reader) = publisher().await;
+	let stream = fragments();
+	let chunks: Vec<_> = stream.chunks(read).map(bytes::Bytes::copy_from_slice).collect();
+
+	let before = ALLOCATED.load(Ordering::Relaxed);
+	for chunk in chunks {
+		publisher.publish(0, chunk).unwrap();
+	}
+	publisher.flush().await.unwrap();
+	let allocated = ALLOCATED.load(Ordering::Relaxed) - before;
+
+	if let Ok(e) = tokio::time::timeout(std::time::Duration::from_millis(10), publisher.failed()).await {
+		panic!("{e:?}");
+	}
+	// a group per keyframe, with the prft and moof as the first object and the mdat as the second
+	let TrackReaderMode::Groups(groups) = reader.subscribe("2160p").unwrap().mode().await.unwrap() else {
+		panic!("expected groups mode");
+	};
+	assert_eq!(groups.latest(), Some((24, 1)));
+
+	allocated as f64 / stream.len() as f64
+}
+
+/// the watcher hands over whole fragments, or reads cutting through them
+#[tokio::test(flavor = "current_thread")]
+async fn publishes_without_copying() {
+	// every atom within a single read is sent as is
+	let whole = allocated(usize::MAX).await;
+	assert!(whole < 0.1, "{whole} bytes allocated per published byte");
+
+	// the atoms spanning several reads are written as the slices of the reads
+	let split = allocated(64 * 1024).await;
+	assert!(split < 0.1, "{split} bytes allocated per published byte");
+}
+
+/// the objects of every group, in order, with the stream handed over in reads of `read` bytes
+///
+/// A read is shorter than a fragment, the groups are taken as they start.
+async fn published(read: usize) -> Vec<u8> {
+	let (mut publisher, mut reader) = publisher().await;
+	let TrackReaderMode::Groups(mut groups) = reader.subscribe("2160p").unwrap().mode().await.unwrap() else {
+		panic!("expected groups mode");
+	};
+
+	let mut started = Vec::new();
+	for chunk in fragments().chunks(read) {
+		publisher.publish(0, bytes::Bytes::copy_from_slice(chunk)).unwrap();
+		publisher.flush().await.unwrap();
+		if let Some(group) = groups.next().now_or_never() {
+			started.push(group.unwrap().unwrap());
+		}
+	}
+	publisher.close().await;
+
+	let mut published = Vec::new();
+	for mut group in started {
+		while let Some(object) = group.read_next().await.unwrap() {
+			published.extend_from_slice(&object);
+		}
+	}
+	published
+}
+
+/// the slices written for the atoms spanning several reads make up the same bytes
+#[tokio::test(flavor = "current_thread")]
+async fn publishes_identical_bytes() {
+	let stream = fragments();
+	for read in [100_000, 64 * 1024, 1000, 7] {
+		assert!(published(read).await == stream, "reads of {read} bytes");
+	}
+}
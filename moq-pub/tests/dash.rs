@@ -0,0 +1,496 @@
+#![cfg(feature = "dash")]
+
+mod common;
+
+use std::{path, time};
+
+use common::{fixture, temp_dir, SEGMENTS, SETTINGS};
+use moq_pub::archive::Archive;
+use moq_pub::dash::{DashPublisher, DashPublisherBuilder, Settings, SettingsWatcher};
+use moq_pub::stats::{Event, Kind, Stats};
+use moq_transport::serve::{TrackReaderMode, TracksReader};
+
+/// wait for the latest group of `name` and return all of its objects
+async fn latest_group(reader: &mut TracksReader, name: &str) -> Vec<bytes::Bytes> {
+	let track = loop {
+		if let Some(track) = reader.subscribe(name) {
+			break track;
+		}
+		tokio::time::sleep(time::Duration::from_millis(10)).await;
+	};
+
+	let TrackReaderMode::Groups(mut groups) = track.mode().await.unwrap() else {
+		panic!("expected groups mode for {name}");
+	};
+
+	let mut group = groups.next().await.unwrap().expect("track closed");
+
+	let mut objects = Vec::new();
+	while objects.len() < 4 {
+		match group.read_next().await.unwrap() {
+			Some(object) => objects.push(object),
+			None => break,
+		}
+	}
+	objects
+}
+
+/// publisher watching `dir`/output
+fn publisher(dir: &path::Path) -> (DashPublisher, TracksReader) {
+	builder(dir).build().unwrap()
+}
+
+fn builder(dir: &path::Path) -> DashPublisherBuilder {
+	let output = dir.join("output");
+	std::fs::create_dir_all(&output).unwrap();
+
+	let settings_file = dir.join("settings.csv");
+	std::fs::write(&settings_file, SETTINGS).unwrap();
+	let settings = Settings::new(settings_file, dir.join("input.mp4").into(), output.clone(), true, false).unwrap();
+
+	DashPublisher::builder()
+		.output(&output)
+		.settings(settings)
+		.namespace("test")
+}
+
+#[tokio::test]
+async fn publishes_segments_from_disk() {
+	let dir = temp_dir("dash");
+	let output = dir.join("output");
+	let (mut publisher, mut reader) = publisher(&dir);
+
+	let handle = tokio::spawn(async move { publisher.run().await });
+
+	// give the watcher time to register
+	tokio::time::sleep(time::Duration::from_millis(100)).await;
+
+	for (source, name) in SEGMENTS {
+		std::fs::copy(fixture(source), output.join(name)).unwrap();
+	}
+
+	let result = tokio::time::timeout(time::Duration::from_secs(5), async {
+		let catalog = latest_group(&mut reader, ".catalog").await;
+		let media = latest_group(&mut reader, "720p").await;
+		(catalog, media)
+	})
+	.await;
+
+	handle.abort();
+	let _ = std::fs::remove_dir_all(&dir);
+
+	let (catalog, media) = result.expect("timed out waiting for the published tracks");
+
+	let catalog: serde_json::Value = serde_json::from_slice(&catalog[0]).unwrap();
+	let track = &catalog["tracks"][0];
+	assert_eq!(track["name"], "720p");
+	assert_eq!(track["selectionParams"]["codec"], "avc1.64001f");
+	assert_eq!(track["selectionParams"]["mimeType"], "video/mp4");
+	assert_eq!(track["selectionParams"]["width"], 1280);
+	assert_eq!(track["selectionParams"]["height"], 720);
+	assert_eq!(track["selectionParams"]["bitrate"], 3_000_000);
+
+	// both fragments belong to the group started by the keyframe
+	assert_eq!(media.len(), 4);
+	assert_eq!(&media[0][4..8], b"moof");
+	assert_eq!(&media[1][4..8], b"mdat");
+	assert_eq!(&media[2][4..8], b"moof");
+	assert_eq!(&media[3][4..8], b"mdat");
+}
+
+#[tokio::test]
+async fn publishes_segments_written_before_start() {
+	let dir = temp_dir("dash-existing");
+	let output = dir.join("output");
+	let (mut publisher, mut reader) = publisher(&dir);
+
+	// the encoder was faster than the watcher
+	for (source, name) in &SEGMENTS[..2] {
+		std::fs::copy(fixture(source), output.join(name)).unwrap();
+	}
+
+	let handle = tokio::spawn(async move { publisher.run().await });
+
+	let result = tokio::time::timeout(time::Duration::from_secs(5), async {
+		let catalog = latest_group(&mut reader, ".catalog").await;
+
+		// the live segment continues the group of the existing one
+		let (source, name) = SEGMENTS[2];
+		std::fs::copy(fixture(source), output.join(name)).unwrap();
+
+		let media = latest_group(&mut reader, "720p").await;
+		(catalog, media)
+	})
+	.await;
+
+	handle.abort();
+	let _ = std::fs::remove_dir_all(&dir);
+
+	let (catalog, media) = result.expect("timed out waiting for the existing segments");
+
+	let catalog: serde_json::Value = serde_json::from_slice(&catalog[0]).unwrap();
+	assert_eq!(catalog["tracks"][0]["name"], "720p");
+
+	// chunk 1 was published once and chunk 2 appended to its group
+	let chunks: Vec<Vec<u8>> = ["chunk_1.m4s", "chunk_2.m4s"]
+		.iter()
+		.map(|source| std::fs::read(fixture(source)).unwrap())
+		.collect();
+	assert_eq!(media.len(), 4);
+	assert_eq!([&media[0][..], &media[1][..]].concat(), chunks[0]);
+	assert_eq!([&media[2][..], &media[3][..]].concat(), chunks[1]);
+}
+
+#[tokio::test]
+async fn publishes_segments_when_polling() {
+	let dir = temp_dir("dash-poll");
+	let output = dir.join("output");
+	let (mut publisher, mut reader) = builder(&dir)
+		.poll_interval(time::Duration::from_millis(20))
+		.build()
+		.unwrap();
+
+	let handle = tokio::spawn(async move { publisher.run().await });
+
+	// give the watcher time to register
+	tokio::time::sleep(time::Duration::from_millis(100)).await;
+
+	// written like the ffmpeg dash muxer: into a tmp file in parts, then renamed
+	for (source, name) in SEGMENTS {
+		let segment = std::fs::read(fixture(source)).unwrap();
+		let tmp = output.join(format!("{name}.tmp"));
+
+		std::fs::write(&tmp, &segment[..segment.len() / 2]).unwrap();
+		tokio::time::sleep(time::Duration::from_millis(100)).await;
+		std::fs::write(&tmp, &segment).unwrap();
+		std::fs::rename(&tmp, output.join(name)).unwrap();
+		tokio::time::sleep(time::Duration::from_millis(100)).await;
+	}
+
+	let result = tokio::time::timeout(time::Duration::from_secs(5), latest_group(&mut reader, "720p")).await;
+
+	handle.abort();
+	let _ = std::fs::remove_dir_all(&dir);
+
+	let media = result.expect("timed out waiting for the polled segments");
+
+	let chunks: Vec<Vec<u8>> = ["chunk_1.m4s", "chunk_2.m4s"]
+		.iter()
+		.map(|source| std::fs::read(fixture(source)).unwrap())
+		.collect();
+	assert_eq!(media.len(), 4);
+	assert_eq!([&media[0][..], &media[1][..]].concat(), chunks[0]);
+	assert_eq!([&media[2][..], &media[3][..]].concat(), chunks[1]);
+}
+
+/// plain HTTP/1.0 GET of the metrics endpoint
+#[cfg(feature = "metrics-server")]
+async fn scrape(addr: std::net::SocketAddr) -> String {
+	use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+	let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+	stream
+		.write_all(b"GET /metrics HTTP/1.0\r\nHost: localhost\r\n\r\n")
+		.await
+		.unwrap();
+
+	let mut response = String::new();
+	stream.read_to_string(&mut response).await.unwrap();
+	response
+}
+
+#[cfg(feature = "metrics-server")]
+#[tokio::test]
+async fn serves_metrics() {
+	let dir = temp_dir("dash-metrics");
+	let output = dir.join("output");
+	let metrics = moq_pub::metrics::Metrics::default();
+	let (mut publisher, mut reader) = builder(&dir).metrics(metrics.clone()).build().unwrap();
+
+	let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+	let addr = listener.local_addr().unwrap();
+	let server = tokio::spawn(metrics.serve_listener(listener));
+
+	let handle = tokio::spawn(async move { publisher.run().await });
+
+	// give the watcher time to register
+	tokio::time::sleep(time::Duration::from_millis(100)).await;
+
+	for (source, name) in SEGMENTS {
+		std::fs::copy(fixture(source), output.join(name)).unwrap();
+	}
+
+	let result = tokio::time::timeout(time::Duration::from_secs(5), async {
+		let media = latest_group(&mut reader, "720p").await;
+		(media, scrape(addr).await)
+	})
+	.await;
+
+	handle.abort();
+	server.abort();
+	let _ = std::fs::remove_dir_all(&dir);
+
+	let (media, response) = result.expect("timed out waiting for the metrics");
+	assert_eq!(media.len(), 4);
+	assert!(response.starts_with("HTTP/1.0 200 OK"));
+
+	let value = |name: &str| -> f64 {
+		let line = format!("{name}{{track=\"720p\"}} ");
+		let value = response.lines().find_map(|l| l.strip_prefix(&line));
+		value.unwrap_or_else(|| panic!("missing {name}")).parse().unwrap()
+	};
+	assert_eq!(value("moq_pub_groups_total"), 1.0);
+	assert_eq!(value("moq_pub_objects_total"), 4.0);
+	let bytes: usize = ["chunk_1.m4s", "chunk_2.m4s"]
+		.iter()
+		.map(|source| std::fs::metadata(fixture(source)).unwrap().len() as usize)
+		.sum();
+	assert_eq!(value("moq_pub_bytes_total"), bytes as f64);
+	assert!(value("moq_pub_group_duration_seconds") > 0.0);
+	value("moq_pub_publish_latency_seconds");
+}
+
+#[tokio::test]
+async fn rebases_looped_input() {
+	let dir = temp_dir("dash-loop");
+	let output = dir.join("output");
+	let (mut publisher, mut reader) = publisher(&dir);
+
+	let handle = tokio::spawn(async move { publisher.run().await });
+
+	// give the watcher time to register
+	tokio::time::sleep(time::Duration::from_millis(100)).await;
+
+	std::fs::copy(fixture("avc_init.m4s"), output.join("source_init_rep_0.m4s")).unwrap();
+
+	// the input is played twice, the timestamps restart at 0 with the second play of chunk 1
+	let played = ["chunk_1.m4s", "chunk_2.m4s", "chunk_3.m4s"].repeat(2);
+
+	let result = tokio::time::timeout(time::Duration::from_secs(5), async {
+		let track = loop {
+			if let Some(track) = reader.subscribe("720p") {
+				break track;
+			}
+			tokio::time::sleep(time::Duration::from_millis(10)).await;
+		};
+		let TrackReaderMode::Groups(mut groups) = track.mode().await.unwrap() else {
+			panic!("expected groups mode");
+		};
+
+		let mut priorities = Vec::new();
+		for (number, source) in played.iter().enumerate() {
+			let name = format!("source_chunk_{:05}_rep_0.m4s", number + 1);
+			std::fs::copy(fixture(source), output.join(name)).unwrap();
+
+			// chunk 2 continues the group of chunk 1
+			if *source != "chunk_2.m4s" {
+				priorities.push(groups.next().await.unwrap().unwrap().priority);
+			}
+		}
+		priorities
+	})
+	.await;
+
+	handle.abort();
+	let _ = std::fs::remove_dir_all(&dir);
+
+	let priorities = result.expect("timed out waiting for the looped segments");
+
+	// newer groups always come first, the higher priority is sent first, also across the loop
+	assert_eq!(priorities.len(), 4);
+	assert!(priorities.windows(2).all(|w| w[0] < w[1]), "{priorities:?}");
+}
+
+/// wait for a catalog version listing exactly the tracks `names`
+async fn catalog_names(reader: &mut TracksReader, names: &[&str]) {
+	let TrackReaderMode::Groups(mut groups) = reader.subscribe(".catalog").unwrap().mode().await.unwrap() else {
+		panic!("expected groups mode for the catalog");
+	};
+
+	loop {
+		let mut group = groups.next().await.unwrap().expect("catalog closed");
+		let catalog: serde_json::Value = serde_json::from_slice(&group.read_next().await.unwrap().unwrap()).unwrap();
+		let current: Vec<_> = catalog["tracks"]
+			.as_array()
+			.unwrap()
+			.iter()
+			.map(|track| track["name"].as_str().unwrap().to_string())
+			.collect();
+		if current == names {
+			return;
+		}
+	}
+}
+
+#[tokio::test]
+async fn applies_changed_settings() {
+	let dir = temp_dir("dash-reload");
+	let output = dir.join("output");
+	std::fs::create_dir_all(&output).unwrap();
+
+	let ladder = SETTINGS.to_string() + "360p,640x360,1000000,1000000,2000000\n";
+	let settings_file = dir.join("settings.csv");
+	std::fs::write(&settings_file, &ladder).unwrap();
+	let settings = Settings::new(
+		settings_file.clone(),
+		dir.join("input.mp4").into(),
+		output.clone(),
+		true,
+		false,
+	)
+	.unwrap();
+
+	let (mut publisher, mut reader) = DashPublisher::builder()
+		.output(&output)
+		.settings(settings.clone())
+		.namespace("test")
+		.build()
+		.unwrap();
+	let reloader = publisher.reloader();
+	let mut changes = SettingsWatcher::new(settings, settings_file.clone(), None).unwrap();
+
+	let handle = tokio::spawn(async move { publisher.run().await });
+
+	// give the watcher time to register
+	tokio::time::sleep(time::Duration::from_millis(100)).await;
+
+	let init = fixture("avc_init.m4s");
+	let result = tokio::time::timeout(time::Duration::from_secs(10), async {
+		for rep_id in [0, 1] {
+			std::fs::copy(&init, output.join(format!("source_init_rep_{rep_id}.m4s"))).unwrap();
+		}
+		catalog_names(&mut reader, &["720p", "360p"]).await;
+
+		// 720p keeps rep 0, the new rung takes over rep 1 once the restarted encoder writes it
+		std::fs::write(&settings_file, ladder.replace("360p,640x360", "1080p,1920x1080")).unwrap();
+		let settings = changes.next().await.unwrap();
+		reloader.reload(settings).await.unwrap();
+		catalog_names(&mut reader, &["720p"]).await;
+
+		std::fs::copy(&init, output.join("source_init_rep_1.m4s")).unwrap();
+		catalog_names(&mut reader, &["720p", "1080p"]).await;
+	})
+	.await;
+
+	handle.abort();
+	let _ = std::fs::remove_dir_all(&dir);
+
+	result.expect("timed out waiting for the changed catalog");
+}
+
+#[tokio::test]
+async fn archives_published_objects() {
+	let dir = temp_dir("dash-archive");
+	let output = dir.join("output");
+	let archive = Archive::new(dir.join("archive"));
+	let (mut publisher, mut reader) = builder(&dir).archive(archive.clone()).build().unwrap();
+
+	for (source, name) in SEGMENTS {
+		std::fs::copy(fixture(source), output.join(name)).unwrap();
+	}
+
+	let media = tokio::time::timeout(time::Duration::from_secs(5), async {
+		tokio::select! {
+			res = publisher.run() => panic!("publisher ended: {res:?}"),
+			media = latest_group(&mut reader, "720p") => media,
+		}
+	})
+	.await
+	.expect("timed out waiting for the published tracks");
+	assert_eq!(media.len(), 4);
+
+	// the open group ends with the tracks
+	publisher.close().await;
+	archive.flush().await;
+
+	let group = dir.join("archive/720p/0");
+	let mut archived = std::fs::read(fixture("avc_init.m4s")).unwrap();
+	for object in 0..4 {
+		archived.extend(std::fs::read(group.join(format!("{object}.bin"))).unwrap());
+	}
+	let source: Vec<u8> = SEGMENTS
+		.iter()
+		.flat_map(|(source, _)| std::fs::read(fixture(source)).unwrap())
+		.collect();
+	assert_eq!(archived, source);
+
+	let index: serde_json::Value = serde_json::from_slice(&std::fs::read(group.join("index.json")).unwrap()).unwrap();
+	assert_eq!(index["track"], "720p");
+	assert_eq!(index["objects"].as_array().unwrap().len(), 4);
+
+	let catalog: serde_json::Value =
+		serde_json::from_slice(&std::fs::read(dir.join("archive/.catalog/0/0.bin")).unwrap()).unwrap();
+	assert_eq!(catalog["tracks"][0]["name"], "720p");
+
+	let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn records_stats() {
+	let dir = temp_dir("dash-stats");
+	let output = dir.join("output");
+	let stats = Stats::new(dir.join("stats.jsonl"), None);
+	let (mut publisher, mut reader) = builder(&dir).stats(stats.clone()).build().unwrap();
+
+	// the keyframe of the third chunk starts the second group
+	let segments = SEGMENTS
+		.into_iter()
+		.chain([("chunk_3.m4s", "source_chunk_00003_rep_0.m4s")]);
+	for (source, name) in segments {
+		std::fs::copy(fixture(source), output.join(name)).unwrap();
+	}
+
+	tokio::time::timeout(time::Duration::from_secs(5), async {
+		let second = async {
+			let track = loop {
+				if let Some(track) = reader.subscribe("720p") {
+					break track;
+				}
+				tokio::time::sleep(time::Duration::from_millis(10)).await;
+			};
+			let TrackReaderMode::Groups(mut groups) = track.mode().await.unwrap() else {
+				panic!("expected groups mode");
+			};
+			while groups.next().await.unwrap().expect("track closed").group_id < 1 {}
+		};
+		tokio::select! {
+			res = publisher.run() => panic!("publisher ended: {res:?}"),
+			_ = second => (),
+		}
+	})
+	.await
+	.expect("timed out waiting for the second group");
+
+	publisher.close().await;
+	stats.flush().await;
+
+	let events: Vec<Event> = std::fs::read_to_string(dir.join("stats.jsonl"))
+		.unwrap()
+		.lines()
+		.map(|line| serde_json::from_str(line).unwrap())
+		.collect();
+
+	for track in [".catalog", "720p"] {
+		let groups: Vec<_> = events
+			.iter()
+			.filter(|event| event.track == track && event.kind == Kind::Group)
+			.map(|event| event.group_seq)
+			.collect();
+		assert!(!groups.is_empty(), "no groups of {track}");
+		assert!(
+			groups.iter().enumerate().all(|(i, seq)| *seq == i as u64),
+			"{track}: {groups:?}"
+		);
+	}
+
+	// the objects follow their group, the media ones with a media time
+	let media: Vec<_> = events.iter().filter(|event| event.track == "720p").collect();
+	assert_eq!(media[0].kind, Kind::Group);
+	assert!(media.iter().all(|event| event.media_ts_ms.is_some()));
+	let objects = media.iter().filter(|event| event.kind == Kind::Object);
+	assert_eq!(objects.clone().count(), 6);
+	assert!(objects.clone().all(|event| event.bytes > 0));
+	assert_eq!(stats.dropped(), 0);
+
+	let _ = std::fs::remove_dir_all(&dir);
+}
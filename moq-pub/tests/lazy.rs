@@ -0,0 +1,101 @@
+#![cfg(feature = "dash")]
+
+mod common;
+
+use std::{path, time};
+
+use common::{announce, fixture, groups, next_group, temp_dir, Relay, Subscriber, SETTINGS, TIMEOUT};
+use moq_pub::dash::{DashPublisher, Settings};
+use moq_pub::metrics::Metrics;
+
+/// the `metric` of the 720p track
+fn metric(metrics: &Metrics, metric: &str) -> u64 {
+	let prefix = format!("{metric}{{track=\"720p\"}} ");
+	metrics
+		.encode()
+		.lines()
+		.find_map(|line| line.strip_prefix(&prefix)?.parse().ok())
+		.unwrap_or_default()
+}
+
+/// copy the fixture `chunk` as the segment `number` of rep 0
+fn segment(output: &path::Path, chunk: &str, number: usize) {
+	let name = format!("source_chunk_{number:05}_rep_0.m4s");
+	std::fs::copy(fixture(chunk), output.join(name)).unwrap();
+}
+
+#[tokio::test]
+async fn skips_tracks_without_subscribers() {
+	let dir = temp_dir("lazy");
+	let output = dir.join("output");
+	std::fs::create_dir_all(&output).unwrap();
+	let settings_file = dir.join("settings.csv");
+	std::fs::write(&settings_file, SETTINGS).unwrap();
+	let settings = Settings::new(settings_file, dir.join("input.mp4").into(), output.clone(), true, false).unwrap();
+
+	let relay = Relay::start(&dir);
+
+	let metrics = Metrics::default();
+	let (mut publisher, reader): (DashPublisher, _) = DashPublisher::builder()
+		.output(&output)
+		.settings(settings)
+		.namespace("test")
+		.metrics(metrics.clone())
+		.lazy_tracks(true)
+		.build()
+		.unwrap();
+	let publisher = tokio::spawn(async move { publisher.run().await });
+
+	let announce = announce(&relay, reader);
+	relay.announced("test").await;
+
+	// give the watcher time to register
+	tokio::time::sleep(time::Duration::from_millis(100)).await;
+	std::fs::copy(fixture("avc_init.m4s"), output.join("source_init_rep_0.m4s")).unwrap();
+	segment(&output, "chunk_1.m4s", 1);
+	segment(&output, "chunk_2.m4s", 2);
+
+	let chunks = 2 * std::fs::metadata(fixture("chunk_1.m4s")).unwrap().len();
+	let result = tokio::time::timeout(TIMEOUT, async {
+		// both fragments are parsed, nothing is written without a subscriber
+		while metric(&metrics, "moq_pub_skipped_bytes_total") < chunks {
+			tokio::time::sleep(time::Duration::from_millis(10)).await;
+		}
+		let written = metric(&metrics, "moq_pub_bytes_total");
+		let mut subscriber = Subscriber::connect(&relay).await;
+		let track = subscriber.subscribe("test", "720p");
+		let group = async { next_group(&mut groups(track).await, 2).await.1 };
+		tokio::pin!(group);
+
+		// a delta fragment never starts the group of a late subscriber
+		tokio::time::sleep(time::Duration::from_millis(100)).await;
+		segment(&output, "chunk_2.m4s", 3);
+
+		let mut number = 4;
+		let group = loop {
+			segment(&output, "chunk_1.m4s", number);
+			number += 1;
+
+			tokio::select! {
+				group = &mut group => break group,
+				_ = tokio::time::sleep(time::Duration::from_millis(200)) => (),
+			}
+		};
+		(written, group)
+	})
+	.await;
+
+	publisher.abort();
+	announce.abort();
+	let _ = std::fs::remove_dir_all(&dir);
+
+	let (written, group) = result.expect("timed out waiting for the late subscriber");
+	assert_eq!(written, 0);
+	assert!(metric(&metrics, "moq_pub_bytes_total") > 0);
+
+	// the group starts with the keyframe fragment
+	let keyframe = std::fs::read(fixture("chunk_1.m4s")).unwrap();
+	assert_eq!(&group[0][4..8], b"moof");
+	assert_eq!(group[0], keyframe[..96]);
+	assert_eq!(&group[1][4..8], b"mdat");
+}
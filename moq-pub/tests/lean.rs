@@ -0,0 +1,17 @@
+use std::process::Command;
+
+/// the library builds without the dash pipeline and the binary, ex. only to publish an fMP4 stream with Media
+#[test]
+fn builds_without_default_features() {
+	let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+	let output = Command::new(cargo)
+		.args(["check", "--lib", "--no-default-features", "--manifest-path"])
+		.arg(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml"))
+		// the build directory of the running tests is locked
+		.env("CARGO_TARGET_DIR", concat!(env!("CARGO_TARGET_TMPDIR"), "/lean"))
+		.env("RUSTFLAGS", "-D warnings")
+		.output()
+		.unwrap();
+
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}
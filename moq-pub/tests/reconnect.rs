@@ -0,0 +1,56 @@
+#![cfg(feature = "dash")]
+
+mod common;
+
+use std::time;
+
+use common::{groups, next_group, temp_dir, Relay, Subscriber, TIMEOUT};
+use moq_pub::dash;
+use moq_transport::serve;
+
+/// the first object of the newest `video` group, once the publisher announced `test`
+async fn latest(relay: &Relay) -> bytes::Bytes {
+	relay.announced("test").await;
+	let mut subscriber = Subscriber::connect(relay).await;
+	let mut groups = groups(subscriber.subscribe("test", "video")).await;
+	next_group(&mut groups, 1).await.1.remove(0)
+}
+
+#[tokio::test]
+async fn reconnects_after_relay_restart() {
+	let dir = temp_dir("reconnect");
+	let mut relay = Relay::start(&dir);
+
+	let (mut writer, _, reader) = serve::Tracks::new("test".to_string()).produce();
+	let mut video = writer.create("video").unwrap().groups().unwrap();
+	video.append(0).unwrap().write("before".into()).unwrap();
+
+	let options = relay.options("test");
+	let reconnect = dash::Reconnect {
+		max_attempts: 20,
+		backoff: time::Duration::from_millis(100),
+	};
+	let shutdown = tokio::sync::Notify::new();
+	let announce = dash::announce(&options, reader, &reconnect, &shutdown);
+	tokio::pin!(announce);
+
+	tokio::select! {
+		res = &mut announce => panic!("announce ended: {res:?}"),
+		object = latest(&relay) => assert_eq!(object, "before"),
+	}
+
+	// the tracks keep going while the relay is gone
+	relay.restart().await;
+	video.append(0).unwrap().write("after".into()).unwrap();
+
+	tokio::select! {
+		res = &mut announce => panic!("announce ended: {res:?}"),
+		object = latest(&relay) => assert_eq!(object, "after"),
+	}
+
+	// a shutdown ends the announce instead of reconnecting
+	shutdown.notify_one();
+	tokio::time::timeout(TIMEOUT, announce).await.unwrap().unwrap();
+
+	let _ = std::fs::remove_dir_all(&dir);
+}
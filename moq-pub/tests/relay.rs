@@ -0,0 +1,332 @@
+#![cfg(feature = "dash")]
+
+mod common;
+
+use bytes::Bytes;
+use common::{announce, fixture, groups, next_group, temp_dir, Relay, Subscriber, SEGMENTS, SETTINGS, TIMEOUT};
+use moq_pub::dash::{self, DashPublisher, Settings};
+use moq_transport::serve::{self, ServeError};
+
+/// the moof and mdat of every chunk, one object per atom
+fn atoms(chunks: &[&str]) -> Vec<Bytes> {
+	chunks
+		.iter()
+		.flat_map(|chunk| {
+			let chunk = Bytes::from(std::fs::read(fixture(chunk)).unwrap());
+			[chunk.slice(..96), chunk.slice(96..)]
+		})
+		.collect()
+}
+
+#[tokio::test]
+async fn publishes_media_through_relay() {
+	let dir = temp_dir("relay-media");
+	let relay = Relay::start(&dir);
+
+	let (writer, _, reader) = serve::Tracks::new("media".to_string()).produce();
+	let mut media = moq_pub::Media::new(writer, None, Vec::new()).unwrap();
+	let mut parse = |name: &str| media.parse(&mut Bytes::from(std::fs::read(fixture(name)).unwrap()));
+
+	// the tracks exist before anybody subscribes
+	parse("avc_init.m4s").unwrap();
+	let announce = announce(&relay, reader);
+	relay.announced("media").await;
+
+	let mut subscriber = Subscriber::connect(&relay).await;
+	let catalog = subscriber.catalog("media").await;
+	let track = catalog.tracks()[0].name().to_string();
+	let track = subscriber.subscribe("media", &track);
+
+	// the keyframe of chunk_1 and the delta frame of chunk_2 form a group, chunk_3 starts the next
+	parse("chunk_1.m4s").unwrap();
+	parse("chunk_2.m4s").unwrap();
+	let mut groups = groups(track).await;
+	assert_eq!(
+		next_group(&mut groups, 4).await,
+		(0, atoms(&["chunk_1.m4s", "chunk_2.m4s"]))
+	);
+
+	parse("chunk_3.m4s").unwrap();
+	assert_eq!(next_group(&mut groups, 2).await, (1, atoms(&["chunk_3.m4s"])));
+
+	announce.abort();
+	let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn lists_publisher_connection() {
+	let dir = temp_dir("relay-connections");
+	let relay = Relay::start(&dir);
+
+	let (writer, _, reader) = serve::Tracks::new("media".to_string()).produce();
+	let mut media = moq_pub::Media::new(writer, None, Vec::new()).unwrap();
+	media
+		.parse(&mut Bytes::from(std::fs::read(fixture("avc_init.m4s")).unwrap()))
+		.unwrap();
+	let announce = announce(&relay, reader);
+	relay.announced("media").await;
+
+	let connections = relay.connections.list();
+	assert_eq!(connections.len(), 1);
+	let connection = &connections[0];
+	assert_eq!(connection.role, moq_relay::Role::Publisher);
+	assert_eq!(connection.namespaces, ["media"]);
+	assert!(connection.peer_addr.ip().is_loopback());
+	assert!(connection.rtt_ms > 0.0);
+	assert!(connection.cwnd > 0);
+	assert!(connection.bytes_sent > 0 && connection.bytes_recv > 0);
+	assert_eq!(relay.connections.get(connection.id).unwrap().id, connection.id);
+
+	// the entry is removed once the session closes
+	announce.abort();
+	tokio::time::timeout(TIMEOUT, async {
+		while !relay.connections.list().is_empty() {
+			tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+		}
+	})
+	.await
+	.expect("the connection is still listed");
+
+	let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn publishes_dash_segments_through_relay() {
+	let dir = temp_dir("relay-dash");
+	let relay = Relay::start(&dir);
+
+	let output = dir.join("output");
+	std::fs::create_dir_all(&output).unwrap();
+	let settings_file = dir.join("settings.csv");
+	std::fs::write(&settings_file, SETTINGS).unwrap();
+	let settings = Settings::new(settings_file, dir.join("input.mp4").into(), output.clone(), true, false).unwrap();
+
+	let (mut publisher, reader): (DashPublisher, _) = DashPublisher::builder()
+		.output(&output)
+		.settings(settings)
+		.namespace("dash")
+		.build()
+		.unwrap();
+	let publisher = tokio::spawn(async move { publisher.run().await });
+
+	// the pre-recorded segments, as ffmpeg names them
+	let segment = |fixture_name: &str, name: &str| std::fs::copy(fixture(fixture_name), output.join(name)).unwrap();
+
+	// give the watcher time to register, the tracks exist before anybody subscribes
+	tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+	segment("avc_init.m4s", "source_init_rep_0.m4s");
+	tokio::time::timeout(TIMEOUT, async {
+		while !reader.tracks().iter().any(|track| track.name == "720p") {
+			tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+		}
+	})
+	.await
+	.expect("timed out waiting for the init segment");
+
+	let announce = announce(&relay, reader);
+	relay.announced("dash").await;
+
+	let mut subscriber = Subscriber::connect(&relay).await;
+	let catalog = subscriber.catalog("dash").await;
+	let names: Vec<_> = catalog.tracks().iter().map(|track| track.name()).collect();
+	assert_eq!(names, ["720p"]);
+	let track = subscriber.subscribe("dash", "720p");
+
+	segment("chunk_1.m4s", "source_chunk_00001_rep_0.m4s");
+	segment("chunk_2.m4s", "source_chunk_00002_rep_0.m4s");
+	let mut groups = groups(track).await;
+	assert_eq!(
+		next_group(&mut groups, 4).await,
+		(0, atoms(&["chunk_1.m4s", "chunk_2.m4s"]))
+	);
+
+	segment("chunk_3.m4s", "source_chunk_00003_rep_0.m4s");
+	assert_eq!(next_group(&mut groups, 2).await, (1, atoms(&["chunk_3.m4s"])));
+
+	announce.abort();
+	publisher.abort();
+	let _ = std::fs::remove_dir_all(&dir);
+}
+
+/// plain HTTP/1.0 GET of `path` on the web server of the relay, the status and the body
+async fn get(addr: std::net::SocketAddr, path: &str) -> (u16, String) {
+	use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+	let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+	let request = format!("GET {path} HTTP/1.0\r\nHost: localhost\r\n\r\n");
+	stream.write_all(request.as_bytes()).await.unwrap();
+
+	let mut response = String::new();
+	stream.read_to_string(&mut response).await.unwrap();
+	let (head, body) = response.split_once("\r\n\r\n").unwrap();
+	let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+	(status, body.to_string())
+}
+
+#[tokio::test]
+async fn serves_cbor_catalog_over_http() {
+	let dir = temp_dir("relay-cbor-catalog");
+	let mut relay = Relay::start(&dir);
+	let web = relay.web().await;
+
+	let output = dir.join("output");
+	std::fs::create_dir_all(&output).unwrap();
+	let settings_file = dir.join("settings.csv");
+	std::fs::write(&settings_file, SETTINGS).unwrap();
+	let settings = Settings::new(settings_file, dir.join("input.mp4").into(), output.clone(), true, false).unwrap();
+
+	let (mut publisher, reader): (DashPublisher, _) = DashPublisher::builder()
+		.output(&output)
+		.settings(settings)
+		.namespace("cbor")
+		.catalog_format(moq_catalog::CatalogFormat::Cbor)
+		.build()
+		.unwrap();
+	let publisher = tokio::spawn(async move { publisher.run().await });
+
+	// give the watcher time to register
+	tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+	for (source, name) in SEGMENTS {
+		std::fs::copy(fixture(source), output.join(name)).unwrap();
+	}
+	tokio::time::timeout(TIMEOUT, async {
+		while !reader.tracks().iter().any(|track| track.name == "720p") {
+			tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+		}
+	})
+	.await
+	.expect("timed out waiting for the init segment");
+
+	let announce = announce(&relay, reader);
+	relay.announced("cbor").await;
+
+	// subscribed from the publisher by the relay, and served as JSON
+	let (status, body) = get(web, "/catalog/cbor").await;
+	assert_eq!(status, 200, "{body}");
+	let catalog = moq_catalog::MoqCatalog::decode(body.as_bytes()).unwrap();
+	let names: Vec<_> = catalog.tracks().iter().map(|track| track.name()).collect();
+	assert_eq!(names, ["720p"]);
+
+	announce.abort();
+	publisher.abort();
+	let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn ends_tracks_and_unannounces_on_shutdown() {
+	let dir = temp_dir("relay-shutdown");
+	let relay = Relay::start(&dir);
+
+	let output = dir.join("output");
+	std::fs::create_dir_all(&output).unwrap();
+	let settings_file = dir.join("settings.csv");
+	std::fs::write(&settings_file, SETTINGS).unwrap();
+	let settings = Settings::new(settings_file, dir.join("input.mp4").into(), output.clone(), true, false).unwrap();
+
+	let (mut publisher, reader): (DashPublisher, _) = DashPublisher::builder()
+		.output(&output)
+		.settings(settings)
+		.namespace("shutdown")
+		.build()
+		.unwrap();
+	let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+	let publisher = tokio::spawn(async move {
+		tokio::select! {
+			res = publisher.run() => res.unwrap(),
+			_ = stopped => (),
+		}
+		publisher.close().await;
+	});
+
+	// give the watcher time to register, the tracks exist before anybody subscribes
+	tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+	for (source, name) in SEGMENTS {
+		std::fs::copy(fixture(source), output.join(name)).unwrap();
+	}
+	tokio::time::timeout(TIMEOUT, async {
+		while !reader.tracks().iter().any(|track| track.name == "720p") {
+			tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+		}
+	})
+	.await
+	.expect("timed out waiting for the init segment");
+
+	// the teardown of Dash::run, the tracks end before the session is closed
+	let shutdown = std::sync::Arc::new(tokio::sync::Notify::new());
+	let options = relay.options("shutdown");
+	let announce = tokio::spawn({
+		let shutdown = shutdown.clone();
+		async move { dash::announce(&options, reader, &Default::default(), &shutdown).await }
+	});
+	relay.announced("shutdown").await;
+
+	let mut subscriber = Subscriber::connect(&relay).await;
+	let mut catalog = groups(subscriber.subscribe("shutdown", ".catalog")).await;
+	let mut media = groups(subscriber.subscribe("shutdown", "720p")).await;
+	next_group(&mut catalog, 1).await;
+	next_group(&mut media, 4).await;
+
+	stop.send(()).unwrap();
+	publisher.await.unwrap();
+
+	// the subscribers see the code of Done while the session is still up, not a reset
+	for groups in [&mut catalog, &mut media] {
+		let end = tokio::time::timeout(TIMEOUT, async {
+			loop {
+				match groups.next().await {
+					Ok(Some(_)) => continue,
+					res => break res.map(|_| ()),
+				}
+			}
+		})
+		.await
+		.expect("the track did not end");
+		assert_eq!(end, Err(ServeError::Closed(ServeError::Done.code())));
+	}
+
+	shutdown.notify_one();
+	tokio::time::timeout(TIMEOUT, announce).await.unwrap().unwrap().unwrap();
+	relay.unannounced("shutdown").await;
+
+	let _ = std::fs::remove_dir_all(&dir);
+}
+
+/// ffmpeg exiting right away, ex. for a missing capture device, ends the run with its output
+#[cfg(unix)]
+#[tokio::test]
+async fn fails_when_ffmpeg_fails_to_start() {
+	use std::os::unix::fs::PermissionsExt;
+
+	let dir = temp_dir("relay-ffmpeg-startup");
+	let relay = Relay::start(&dir);
+
+	let ffmpeg = dir.join("ffmpeg");
+	std::fs::write(
+		&ffmpeg,
+		"#!/bin/sh\necho '[video4linux2] /dev/video0: No such file or directory' >&2\nexit 1\n",
+	)
+	.unwrap();
+	std::fs::set_permissions(&ffmpeg, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+	let output = dir.join("output");
+	let settings = Settings::from_bytes(
+		SETTINGS.as_bytes().to_vec(),
+		"/dev/video0".into(),
+		output.clone(),
+		true,
+		false,
+	)
+	.unwrap();
+	let dash = dash::Dash::new(settings, output, relay.options("startup"), Default::default())
+		.unwrap()
+		.ffmpeg(ffmpeg);
+
+	let err = tokio::time::timeout(TIMEOUT, dash.run()).await.unwrap().unwrap_err();
+	assert!(matches!(err.root(), dash::Error::FfmpegStartup { .. }), "{err}");
+	assert!(
+		err.to_string().contains("/dev/video0: No such file or directory"),
+		"{err}"
+	);
+
+	let _ = std::fs::remove_dir_all(&dir);
+}
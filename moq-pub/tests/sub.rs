@@ -0,0 +1,273 @@
+#![cfg(feature = "dash")]
+
+mod common;
+
+use std::time;
+
+use common::{announce, fixture, temp_dir, Relay, SEGMENTS, SETTINGS, TIMEOUT};
+use moq_pub::dash::{DashPublisher, DashPublisherBuilder, Settings};
+use moq_pub::sub::{Player, Remote, Selection};
+use moq_transport::serve::TrackReaderMode;
+use tokio::io::AsyncReadExt;
+
+const AUDIO_SETTINGS: &str = "gop_num=1
+fps=25
+target_segment_duration=2.0
+===AUDIO===
+name,sampling,bitrate
+audio,48000,128000
+===VIDEO===
+name,resolution,bitrate,max_rate,buffer_size
+";
+
+/// two fragments a second apart, both in the first group of 2s
+const AUDIO_SEGMENTS: [(&str, &str); 3] = [
+	("aac_init.m4s", "source_init_rep_0.m4s"),
+	("aac_chunk_1.m4s", "source_chunk_00001_rep_0.m4s"),
+	("aac_chunk_2.m4s", "source_chunk_00002_rep_0.m4s"),
+];
+
+/// the player output of a published DASH stream is the init segment followed by the fragments
+///
+/// Returns the catalog the player selected from.
+async fn play(
+	name: &str,
+	settings: &str,
+	segments: &[(&str, &str)],
+	selection: Selection,
+	configure: fn(DashPublisherBuilder) -> DashPublisherBuilder,
+) -> serde_json::Value {
+	let dir = temp_dir(name);
+	let output = dir.join("output");
+	std::fs::create_dir_all(&output).unwrap();
+
+	let settings_file = dir.join("settings.csv");
+	std::fs::write(&settings_file, settings).unwrap();
+	let settings = Settings::new(
+		settings_file,
+		dir.join("input.mp4").into(),
+		output.clone(),
+		false,
+		false,
+	)
+	.unwrap();
+
+	let builder = DashPublisher::builder()
+		.output(&output)
+		.settings(settings)
+		.namespace("test")
+		// the audio chunks are a second apart, played as one group
+		.discontinuity_threshold(time::Duration::from_secs(2));
+	let (mut publisher, reader) = configure(builder).build().unwrap();
+
+	let handle = tokio::spawn(async move { publisher.run().await });
+
+	// give the watcher time to register
+	tokio::time::sleep(time::Duration::from_millis(100)).await;
+
+	let mut catalog = reader.clone();
+	let (writer, mut played) = tokio::io::duplex(1 << 16);
+	let player = tokio::spawn(async move { Player::new(reader, writer, selection).run().await });
+
+	let mut expected = Vec::new();
+	for (source, name) in segments {
+		let segment = std::fs::read(fixture(source)).unwrap();
+		expected.extend_from_slice(&segment);
+		std::fs::write(output.join(name), segment).unwrap();
+	}
+
+	let mut buf = vec![0; expected.len()];
+	let result = tokio::time::timeout(time::Duration::from_secs(5), async {
+		played.read_exact(&mut buf).await.unwrap();
+
+		let TrackReaderMode::Groups(mut groups) = catalog.subscribe(".catalog").unwrap().mode().await.unwrap() else {
+			panic!("expected groups mode for the catalog");
+		};
+		let mut group = groups.next().await.unwrap().unwrap();
+		group.read_next().await.unwrap().unwrap()
+	})
+	.await;
+
+	handle.abort();
+	player.abort();
+	let _ = std::fs::remove_dir_all(&dir);
+
+	let catalog = result.expect("timed out waiting for the played stream");
+	assert_eq!(buf, expected);
+
+	// published in any catalog format
+	let format = moq_catalog::CatalogFormat::sniff(&catalog).unwrap();
+	let catalog = moq_catalog::MoqCatalog::decode_with(&catalog, format).unwrap();
+	serde_json::from_slice(&catalog.encode().unwrap()).unwrap()
+}
+
+#[tokio::test]
+async fn plays_track_by_name() {
+	play(
+		"sub-name",
+		SETTINGS,
+		&SEGMENTS,
+		Selection::Name("720p".to_string()),
+		|b| b,
+	)
+	.await;
+}
+
+#[tokio::test]
+async fn plays_highest_bitrate() {
+	play("sub-bitrate", SETTINGS, &SEGMENTS, Selection::HighestBitrate, |b| b).await;
+}
+
+#[tokio::test]
+async fn plays_audio_only() {
+	let catalog = play(
+		"sub-audio",
+		AUDIO_SETTINGS,
+		&AUDIO_SEGMENTS,
+		Selection::HighestBitrate,
+		|b| b,
+	)
+	.await;
+
+	let tracks = catalog["tracks"].as_array().unwrap();
+	assert_eq!(tracks.len(), 1);
+	assert_eq!(tracks[0]["name"], "audio");
+	assert_eq!(tracks[0]["selectionParams"]["mimeType"], "audio/mp4");
+	assert!(tracks[0]["selectionParams"]["codec"]
+		.as_str()
+		.unwrap()
+		.starts_with("mp4a."));
+}
+
+#[tokio::test]
+async fn plays_init_track() {
+	let catalog = play("sub-init-track", SETTINGS, &SEGMENTS, Selection::HighestBitrate, |b| {
+		b.init_tracks(true)
+	})
+	.await;
+
+	let tracks = catalog["tracks"].as_array().unwrap();
+	assert_eq!(tracks.len(), 1);
+	assert_eq!(tracks[0]["initTrack"], "720p_init");
+}
+
+#[tokio::test]
+async fn plays_hierarchical_catalog() {
+	let catalog = play(
+		"sub-hierarchical",
+		SETTINGS,
+		&SEGMENTS,
+		Selection::Name("720p".to_string()),
+		|b| b.hierarchical_catalog(true),
+	)
+	.await;
+
+	// the player found the track in the catalog of the video tracks
+	assert!(catalog.get("tracks").is_none());
+	assert_eq!(catalog["catalogs"][0]["name"], ".catalog.video");
+}
+
+#[tokio::test]
+async fn plays_cbor_catalog() {
+	let catalog = play(
+		"sub-cbor",
+		SETTINGS,
+		&SEGMENTS,
+		Selection::Name("720p".to_string()),
+		|b| {
+			b.hierarchical_catalog(true)
+				.catalog_format(moq_catalog::CatalogFormat::Cbor)
+		},
+	)
+	.await;
+
+	// the root and the catalog of the video tracks were sniffed as CBOR
+	assert_eq!(catalog["catalogs"][0]["name"], ".catalog.video");
+}
+
+#[tokio::test]
+async fn plays_through_relay() {
+	let dir = temp_dir("sub-relay");
+	let relay = Relay::start(&dir);
+
+	let output = dir.join("output");
+	std::fs::create_dir_all(&output).unwrap();
+	let settings_file = dir.join("settings.csv");
+	std::fs::write(&settings_file, SETTINGS).unwrap();
+	let settings = Settings::new(settings_file, dir.join("input.mp4").into(), output.clone(), true, false).unwrap();
+
+	let (mut publisher, reader) = DashPublisher::builder()
+		.output(&output)
+		.settings(settings)
+		.namespace("test")
+		.build()
+		.unwrap();
+	let publisher = tokio::spawn(async move { publisher.run().await });
+
+	// give the watcher time to register, the catalog lists the track before anybody subscribes
+	tokio::time::sleep(time::Duration::from_millis(100)).await;
+	std::fs::copy(fixture("avc_init.m4s"), output.join("source_init_rep_0.m4s")).unwrap();
+	tokio::time::timeout(TIMEOUT, async {
+		while !reader.tracks().iter().any(|track| track.name == "720p") {
+			tokio::time::sleep(time::Duration::from_millis(10)).await;
+		}
+	})
+	.await
+	.expect("timed out waiting for the init segment");
+
+	let announce = announce(&relay, reader);
+	relay.announced("test").await;
+
+	let (session, subscriber) = moq_pub::connect_subscriber(
+		&relay.url(),
+		"127.0.0.1:0".parse().unwrap(),
+		&relay.tls(),
+		Default::default(),
+	)
+	.await
+	.unwrap();
+	let session = tokio::spawn(session.run());
+	let (writer, mut played) = tokio::io::duplex(1 << 16);
+	let mut player = Player::new(
+		Remote::new(subscriber, "test".to_string()),
+		writer,
+		Selection::HighestBitrate,
+	);
+	let player = tokio::spawn(async move { player.run().await });
+
+	let init = std::fs::read(fixture("avc_init.m4s")).unwrap();
+	let chunk = std::fs::read(fixture("chunk_1.m4s")).unwrap();
+	let result = tokio::time::timeout(TIMEOUT, async {
+		let mut played_init = vec![0; init.len()];
+		played.read_exact(&mut played_init).await.unwrap();
+
+		// every keyframe chunk starts a group, the first one after the subscription is played
+		let mut played_chunk = vec![0; chunk.len()];
+		let read = played.read_exact(&mut played_chunk);
+		tokio::pin!(read);
+		for number in 1.. {
+			let name = format!("source_chunk_{number:05}_rep_0.m4s");
+			std::fs::copy(fixture("chunk_1.m4s"), output.join(name)).unwrap();
+
+			tokio::select! {
+				res = &mut read => {
+					res.unwrap();
+					break;
+				}
+				_ = tokio::time::sleep(time::Duration::from_millis(200)) => (),
+			}
+		}
+		(played_init, played_chunk)
+	})
+	.await;
+
+	publisher.abort();
+	announce.abort();
+	player.abort();
+	session.abort();
+	let _ = std::fs::remove_dir_all(&dir);
+
+	let (played_init, played_chunk) = result.expect("timed out waiting for the played stream");
+	assert_eq!(played_init, init);
+	assert_eq!(played_chunk, chunk);
+}
@@ -0,0 +1,189 @@
+use std::collections::{BTreeSet, HashMap};
+use std::net;
+use std::sync::{Arc, Mutex};
+
+use moq_transport::session::Streams;
+
+/// The QUIC connections accepted by the relay, while their session runs.
+///
+/// Only a snapshot of each connection is kept, the session task owning it refreshes the statistics.
+#[derive(Clone, Default)]
+pub struct Connections {
+	lookup: Arc<Mutex<HashMap<usize, Entry>>>,
+}
+
+struct Entry {
+	peer_addr: net::SocketAddr,
+	stats: Stats,
+	role: Role,
+	streams: Streams,
+	namespaces: BTreeSet<String>,
+}
+
+/// the statistics of a connection listed in [ConnectionInfo]
+struct Stats {
+	rtt_ms: f64,
+	cwnd: u64,
+	bytes_sent: u64,
+	bytes_recv: u64,
+	lost_packets: u64,
+}
+
+impl Stats {
+	fn of(conn: &quinn::Connection) -> Self {
+		let stats = conn.stats();
+
+		Self {
+			rtt_ms: stats.path.rtt.as_secs_f64() * 1000.0,
+			cwnd: stats.path.cwnd,
+			bytes_sent: stats.udp_tx.bytes,
+			bytes_recv: stats.udp_rx.bytes,
+			lost_packets: stats.path.lost_packets,
+		}
+	}
+}
+
+/// What the peer does on the session.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+	Publisher,
+	Subscriber,
+	Both,
+}
+
+/// The state of a connection, as served by `/connections`, the statistics are refreshed every second.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct ConnectionInfo {
+	pub id: usize,
+	pub peer_addr: net::SocketAddr,
+	pub role: Role,
+	pub rtt_ms: f64,
+	/// congestion window in bytes
+	pub cwnd: u64,
+	/// UDP payload bytes, including the QUIC overhead
+	pub bytes_sent: u64,
+	pub bytes_recv: u64,
+	pub lost_packets: u64,
+	/// the data streams currently open in either direction
+	pub open_streams: usize,
+	/// the namespaces the peer announced
+	pub namespaces: Vec<String>,
+}
+
+impl Connections {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// list the connection until the registration is dropped, with its statistics as of now
+	pub fn register(&self, conn: &quinn::Connection, role: Role, streams: Streams) -> ConnectionRegistration {
+		let id = conn.stable_id();
+		let entry = Entry {
+			peer_addr: conn.remote_address(),
+			stats: Stats::of(conn),
+			role,
+			streams,
+			namespaces: BTreeSet::new(),
+		};
+		self.lookup.lock().unwrap().insert(id, entry);
+
+		ConnectionRegistration {
+			handle: ConnectionHandle {
+				connections: self.clone(),
+				id,
+			},
+		}
+	}
+
+	pub fn get(&self, id: usize) -> Option<ConnectionInfo> {
+		self.lookup.lock().unwrap().get(&id).map(|entry| entry.info(id))
+	}
+
+	/// every connection, sorted by id
+	pub fn list(&self) -> Vec<ConnectionInfo> {
+		let mut list: Vec<_> = self
+			.lookup
+			.lock()
+			.unwrap()
+			.iter()
+			.map(|(id, entry)| entry.info(*id))
+			.collect();
+		list.sort_by_key(|info| info.id);
+		list
+	}
+}
+
+impl Entry {
+	fn info(&self, id: usize) -> ConnectionInfo {
+		ConnectionInfo {
+			id,
+			peer_addr: self.peer_addr,
+			role: self.role,
+			rtt_ms: self.stats.rtt_ms,
+			cwnd: self.stats.cwnd,
+			bytes_sent: self.stats.bytes_sent,
+			bytes_recv: self.stats.bytes_recv,
+			lost_packets: self.stats.lost_packets,
+			open_streams: self.streams.open(),
+			namespaces: self.namespaces.iter().cloned().collect(),
+		}
+	}
+}
+
+pub struct ConnectionRegistration {
+	handle: ConnectionHandle,
+}
+
+impl ConnectionRegistration {
+	pub fn handle(&self) -> ConnectionHandle {
+		self.handle.clone()
+	}
+
+	/// replace the listed statistics with the current ones of `conn`
+	pub fn update(&self, conn: &quinn::Connection) {
+		if let Some(entry) = self.handle.connections.lookup.lock().unwrap().get_mut(&self.handle.id) {
+			entry.stats = Stats::of(conn);
+		}
+	}
+}
+
+impl Drop for ConnectionRegistration {
+	fn drop(&mut self) {
+		self.handle.connections.lookup.lock().unwrap().remove(&self.handle.id);
+	}
+}
+
+/// Records what happens on a listed connection.
+#[derive(Clone)]
+pub struct ConnectionHandle {
+	connections: Connections,
+	id: usize,
+}
+
+impl ConnectionHandle {
+	/// list the namespace on the connection until the registration is dropped
+	pub fn announce(&self, namespace: &str) -> NamespaceRegistration {
+		if let Some(entry) = self.connections.lookup.lock().unwrap().get_mut(&self.id) {
+			entry.namespaces.insert(namespace.to_string());
+		}
+
+		NamespaceRegistration {
+			handle: self.clone(),
+			namespace: namespace.to_string(),
+		}
+	}
+}
+
+pub struct NamespaceRegistration {
+	handle: ConnectionHandle,
+	namespace: String,
+}
+
+impl Drop for NamespaceRegistration {
+	fn drop(&mut self) {
+		if let Some(entry) = self.handle.connections.lookup.lock().unwrap().get_mut(&self.handle.id) {
+			entry.namespaces.remove(&self.namespace);
+		}
+	}
+}
@@ -5,7 +5,7 @@ use moq_transport::{
 	session::{Announced, SessionError, Subscriber},
 };
 
-use crate::{Api, Locals, Producer};
+use crate::{Api, ConnectionHandle, Locals, Producer};
 
 #[derive(Clone)]
 pub struct Consumer {
@@ -13,15 +13,23 @@ pub struct Consumer {
 	locals: Locals,
 	api: Option<Api>,
 	forward: Option<Producer>, // Forward all announcements to this subscriber
+	connection: Option<ConnectionHandle>,
 }
 
 impl Consumer {
-	pub fn new(remote: Subscriber, locals: Locals, api: Option<Api>, forward: Option<Producer>) -> Self {
+	pub fn new(
+		remote: Subscriber,
+		locals: Locals,
+		api: Option<Api>,
+		forward: Option<Producer>,
+		connection: Option<ConnectionHandle>,
+	) -> Self {
 		Self {
 			remote,
 			locals,
 			api,
 			forward,
+			connection,
 		}
 	}
 
@@ -60,6 +68,7 @@ impl Consumer {
 
 		// Register the local tracks, unregister on drop
 		let _register = self.locals.register(reader.clone()).await?;
+		let _listed = self.connection.as_ref().map(|conn| conn.announce(&reader.namespace));
 
 		announce.ok()?;
 
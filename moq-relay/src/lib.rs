@@ -0,0 +1,20 @@
+mod api;
+mod connection;
+mod consumer;
+mod limiter;
+mod local;
+mod producer;
+mod relay;
+mod remote;
+mod session;
+mod web;
+
+pub use api::*;
+pub use connection::*;
+pub use consumer::*;
+pub use local::*;
+pub use producer::*;
+pub use relay::*;
+pub use remote::*;
+pub use session::*;
+pub use web::*;
@@ -1,43 +1,467 @@
-use std::{process::Command, sync::Arc};
+use std::{
+	collections::{BTreeMap, VecDeque},
+	io::Write,
+	path::PathBuf,
+	process::Command,
+	sync::Arc,
+	time::SystemTime,
+};
 
 use anyhow::Context;
 use serde::{Deserialize, Serialize};
 use tokio::{
 	sync::RwLock,
 	task::JoinHandle,
-	time::{sleep, Duration},
+	time::{sleep, sleep_until, Duration, Instant},
 };
 
-fn default_trajectory_mode() -> String {
-	"cascade".to_string()
+/// Applies and removes the bandwidth limit of a network interface.
+pub trait TrafficShaper: std::fmt::Debug + Send + Sync {
+	fn apply(
+		&self,
+		interface: &str,
+		rate_kbit: u32,
+		latency_ms: u32,
+		impairment: Impairment,
+		direction: Direction,
+	) -> anyhow::Result<()>;
+	/// remove the limit applied in `direction`
+	fn clear(&self, interface: &str, direction: Direction) -> anyhow::Result<()>;
+}
+
+/// The wall clock a scheduled trajectory waits for.
+pub trait WallClock: std::fmt::Debug + Send + Sync {
+	fn now(&self) -> SystemTime;
+}
+
+/// the time of the system
+#[derive(Debug)]
+pub struct SystemClock;
+
+impl WallClock for SystemClock {
+	fn now(&self) -> SystemTime {
+		SystemTime::now()
+	}
+}
+
+/// `start` advanced by the tokio clock, which tests can pause, minus the time it was set back by
+#[cfg(test)]
+#[derive(Debug)]
+pub struct MockClock {
+	start: SystemTime,
+	instant: Instant,
+	behind: std::sync::Mutex<Duration>,
+}
+
+#[cfg(test)]
+impl MockClock {
+	pub fn new(start: SystemTime) -> Self {
+		Self {
+			start,
+			instant: Instant::now(),
+			behind: Default::default(),
+		}
+	}
+
+	/// turn the clock back by `by`, like a correction of the system time
+	pub fn set_back(&self, by: Duration) {
+		*self.behind.lock().unwrap() += by;
+	}
+}
+
+#[cfg(test)]
+impl WallClock for MockClock {
+	fn now(&self) -> SystemTime {
+		self.start + self.instant.elapsed() - *self.behind.lock().unwrap()
+	}
+}
+
+/// Which traffic of the interfaces is limited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+	/// the traffic the relay sends, ex. toward the subscribers
+	#[default]
+	Egress,
+	/// the traffic the relay receives, ex. from the publishers, shaped on an ifb device
+	Ingress,
+	Both,
+}
+
+impl Direction {
+	fn egress(&self) -> bool {
+		matches!(self, Self::Egress | Self::Both)
+	}
+
+	fn ingress(&self) -> bool {
+		matches!(self, Self::Ingress | Self::Both)
+	}
+
+	fn is_egress(&self) -> bool {
+		*self == Self::Egress
+	}
+}
+
+/// limits with a netem qdisc using `tc`
+#[derive(Debug)]
+pub struct TcShaper;
+
+impl TcShaper {
+	pub fn new() -> anyhow::Result<Self> {
+		if std::env::consts::OS != "linux" {
+			anyhow::bail!("tc only supported on linux");
+		}
+		Ok(Self)
+	}
+
+	/// the ifb device the received traffic of `interface` is redirected to, within the 15 characters of a device name
+	pub fn ifb_device(interface: &str) -> String {
+		let mut device = format!("ifb-{interface}");
+		device.truncate(15);
+		device
+	}
+
+	/// the tc and ip commands limiting `interface`, each starting with the program
+	///
+	/// Egress is limited by a root netem qdisc. Ingress is redirected to an ifb device, whose egress is limited instead.
+	pub fn apply_commands(
+		interface: &str,
+		rate_kbit: u32,
+		latency_ms: u32,
+		impairment: Impairment,
+		direction: Direction,
+	) -> Vec<Vec<String>> {
+		// if this doesnÄt work use the original args from Björn:
+		// "qdisc", "add", "dev", interface, "root", "tbf", "rate", &bandwidth, "latency", &latency, "burst", "1540"
+		let netem = |device: &str| {
+			let mut args = command(&["tc", "qdisc", "add", "dev", device, "root", "netem", "delay"]);
+			args.push(format!("{latency_ms}ms"));
+			args.extend(impairment.netem_args());
+			args.extend(["rate".to_string(), format!("{rate_kbit}kbit")]);
+			args
+		};
+
+		let mut commands = Vec::new();
+		if direction.egress() {
+			commands.push(netem(interface));
+		}
+		if direction.ingress() {
+			let ifb = Self::ifb_device(interface);
+			commands.extend([
+				command(&["ip", "link", "add", &ifb, "type", "ifb"]),
+				command(&["ip", "link", "set", "dev", &ifb, "up"]),
+				command(&["tc", "qdisc", "add", "dev", interface, "handle", "ffff:", "ingress"]),
+				command(&[
+					"tc", "filter", "add", "dev", interface, "parent", "ffff:", "protocol", "all", "u32", "match",
+					"u32", "0", "0", "action", "mirred", "egress", "redirect", "dev", &ifb,
+				]),
+				netem(&ifb),
+			]);
+		}
+		commands
+	}
+
+	/// the tc and ip commands removing the limit of `interface` applied in `direction`
+	///
+	/// Deleting the ingress qdisc removes the redirect, deleting the ifb device its netem qdisc.
+	pub fn clear_commands(interface: &str, direction: Direction) -> Vec<Vec<String>> {
+		let mut commands = Vec::new();
+		if direction.egress() {
+			commands.push(command(&["tc", "qdisc", "delete", "dev", interface, "root"]));
+		}
+		if direction.ingress() {
+			commands.extend([
+				command(&["tc", "qdisc", "delete", "dev", interface, "ingress"]),
+				command(&["ip", "link", "delete", &Self::ifb_device(interface)]),
+			]);
+		}
+		commands
+	}
+
+	/// run the program of `command`, a non-zero exit status fails with its stderr
+	fn run(command: &[String]) -> anyhow::Result<()> {
+		let output = Command::new(&command[0])
+			.args(&command[1..])
+			.output()
+			.with_context(|| format!("failed to run {}", command[0]))?;
+		if !output.status.success() {
+			anyhow::bail!(
+				"{} failed ({}): {}",
+				command.join(" "),
+				output.status,
+				String::from_utf8_lossy(&output.stderr).trim()
+			);
+		}
+		Ok(())
+	}
+}
+
+fn command(args: &[&str]) -> Vec<String> {
+	args.iter().map(|arg| arg.to_string()).collect()
+}
+
+impl TrafficShaper for TcShaper {
+	fn apply(
+		&self,
+		interface: &str,
+		rate_kbit: u32,
+		latency_ms: u32,
+		impairment: Impairment,
+		direction: Direction,
+	) -> anyhow::Result<()> {
+		for command in Self::apply_commands(interface, rate_kbit, latency_ms, impairment, direction) {
+			Self::run(&command).context("failed adding qdisc")?;
+		}
+		Ok(())
+	}
+
+	fn clear(&self, interface: &str, direction: Direction) -> anyhow::Result<()> {
+		for command in Self::clear_commands(interface, direction) {
+			match Self::run(&command) {
+				// there was no limit to remove
+				Err(e) if format!("{e}").contains("handle of zero") => {}
+				res => res.context("failed deleting qdiscs")?,
+			}
+		}
+		Ok(())
+	}
+}
+
+/// records the calls and the commands [TcShaper] would run instead of limiting, fails every call if `fail` is set
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct MockShaper {
+	pub calls: std::sync::Mutex<Vec<ShaperCall>>,
+	pub commands: std::sync::Mutex<Vec<String>>,
+	pub fail: bool,
+}
+
+#[cfg(test)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShaperCall {
+	Apply(String, u32, u32, Impairment, Direction),
+	Clear(String, Direction),
+}
+
+#[cfg(test)]
+impl MockShaper {
+	fn record(&self, call: ShaperCall, commands: Vec<Vec<String>>) -> anyhow::Result<()> {
+		self.calls.lock().unwrap().push(call);
+		anyhow::ensure!(!self.fail, "mock failure");
+		self.commands
+			.lock()
+			.unwrap()
+			.extend(commands.iter().map(|command| command.join(" ")));
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+impl TrafficShaper for MockShaper {
+	fn apply(
+		&self,
+		interface: &str,
+		rate_kbit: u32,
+		latency_ms: u32,
+		impairment: Impairment,
+		direction: Direction,
+	) -> anyhow::Result<()> {
+		self.record(
+			ShaperCall::Apply(interface.to_string(), rate_kbit, latency_ms, impairment, direction),
+			TcShaper::apply_commands(interface, rate_kbit, latency_ms, impairment, direction),
+		)
+	}
+
+	fn clear(&self, interface: &str, direction: Direction) -> anyhow::Result<()> {
+		self.record(
+			ShaperCall::Clear(interface.to_string(), direction),
+			TcShaper::clear_commands(interface, direction),
+		)
+	}
 }
 
 #[derive(Debug)]
 pub struct Limiter {
 	current_limit: Option<u32>,
+	current_latency: Option<u32>,
+	current_impairment: Impairment,
+	current_direction: Option<Direction>,
+	/// the direction the qdiscs were added in, removed by the next [delete_all_qdiscs]
+	applied: Option<Direction>,
 	default_latency: u32,
 	network_interfaces: Vec<String>,
+	shaper: Arc<dyn TrafficShaper>,
+	clock: Arc<dyn WallClock>,
 	running_handle: Option<JoinHandle<anyhow::Result<()>>>,
+	/// when the running trajectory starts, until it did
+	scheduled: Option<SystemTime>,
+	step_index: Option<usize>,
+	/// the latest applied steps, aborts and resets, oldest first
+	log: VecDeque<LogEntry>,
+	/// JSONL file every log entry is appended to
+	log_file: Option<PathBuf>,
 }
 
-impl Limiter {
-	pub fn new(default_latency: Option<u32>) -> anyhow::Result<Self> {
-		if std::env::consts::OS != "linux" {
-			anyhow::bail!("tc only supported on linux");
+/// how many entries of the executed schedule are kept in memory
+const LOG_CAPACITY: usize = 1000;
+
+/// the tokio clock may drift from the wall clock, a scheduled trajectory checks the wall clock again this often
+const CLOCK_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// An entry of the executed schedule, as reported by `GET /trajectory/log`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogEntry {
+	/// rfc3339 wallclock time the entry took effect
+	pub start: String,
+	pub event: LogEvent,
+	/// the limit of a step
+	pub limit_kbps: Option<u32>,
+	/// the latency of a step, with the default applied
+	pub latency_ms: Option<u32>,
+	/// how long a step is held, 0 until it is removed
+	pub duration_ms: Option<u32>,
+	/// rfc3339 wallclock time a scheduled trajectory starts at
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub scheduled_at: Option<String>,
+	/// how long a scheduled trajectory waits for its start
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub remaining_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogEvent {
+	/// a step was applied
+	Step,
+	/// the limit was removed while running
+	Abort,
+	/// the trajectory ended and the limit was removed
+	Reset,
+	/// a trajectory waits for its start time
+	Scheduled,
+	/// the scheduled trajectory was cancelled before it started
+	Cancel,
+}
+
+impl LogEntry {
+	fn new(event: LogEvent) -> Self {
+		Self {
+			start: crate::web::rfc3339(SystemTime::now()),
+			event,
+			limit_kbps: None,
+			latency_ms: None,
+			duration_ms: None,
+			scheduled_at: None,
+			remaining_ms: None,
 		}
+	}
+}
+
+/// the limit currently applied, as reported by `GET /bandwidth`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LimiterStatus {
+	pub active: bool,
+	pub limit_kbps: Option<u32>,
+	pub latency_ms: u32,
+	pub trajectory_running: bool,
+	pub step_index: Option<usize>,
+	pub impairment: Impairment,
+	pub direction: Option<Direction>,
+	/// rfc3339 wallclock time the scheduled trajectory starts at
+	pub scheduled_at: Option<String>,
+	/// how long until the scheduled trajectory starts
+	pub starts_in_ms: Option<u64>,
+}
 
-		let network_interfaces = Self::get_interfaces()?;
+impl Limiter {
+	/// limits `interfaces`, all but the loopback interface if not given
+	pub fn new(
+		default_latency: Option<u32>,
+		shaper: Arc<dyn TrafficShaper>,
+		interfaces: Option<Vec<String>>,
+	) -> anyhow::Result<Self> {
+		let network_interfaces = match interfaces {
+			Some(interfaces) => interfaces,
+			None => Self::get_interfaces()?,
+		};
 
 		let default_latency = default_latency.unwrap_or(50);
 
 		Ok(Self {
 			current_limit: None,
+			current_latency: None,
+			current_impairment: Impairment::default(),
+			current_direction: None,
+			applied: None,
 			default_latency,
 			network_interfaces,
+			shaper,
+			clock: Arc::new(SystemClock),
 			running_handle: None,
+			scheduled: None,
+			step_index: None,
+			log: VecDeque::new(),
+			log_file: None,
 		})
 	}
 
+	/// wait for `clock` instead of the system time before starting a scheduled trajectory
+	#[cfg(test)]
+	pub fn set_clock(&mut self, clock: Arc<dyn WallClock>) {
+		self.clock = clock;
+	}
+
+	/// append every log entry to the JSONL file at `path` as well
+	pub fn set_log_file(&mut self, path: PathBuf) {
+		self.log_file = Some(path);
+	}
+
+	/// the latest entries of the executed schedule, newest last
+	pub fn log(&self) -> Vec<LogEntry> {
+		self.log.iter().cloned().collect()
+	}
+
+	/// a failing log file is reported, limiting goes on
+	fn record(&mut self, entry: LogEntry) {
+		if let Some(path) = &self.log_file {
+			if let Err(e) = append_log(path, &entry) {
+				log::warn!("Limiter: failed to write {}: {e:#}", path.display());
+			}
+		}
+
+		if self.log.len() == LOG_CAPACITY {
+			self.log.pop_front();
+		}
+		self.log.push_back(entry);
+	}
+
+	pub fn status(&self) -> LimiterStatus {
+		LimiterStatus {
+			active: self.current_limit.is_some(),
+			limit_kbps: self.current_limit,
+			latency_ms: self.current_latency.unwrap_or(self.default_latency),
+			trajectory_running: self.scheduled.is_none()
+				&& self.running_handle.as_ref().is_some_and(|h| !h.is_finished()),
+			step_index: self.step_index,
+			impairment: self.current_impairment,
+			direction: self.current_direction,
+			scheduled_at: self.scheduled.map(crate::web::rfc3339),
+			starts_in_ms: self.scheduled.map(|at| {
+				let remaining = at.duration_since(self.clock.now()).unwrap_or_default();
+				remaining.as_millis() as u64
+			}),
+		}
+	}
+
+	/// forget the applied limit, after the qdiscs were removed
+	fn clear(&mut self) {
+		self.current_limit = None;
+		self.current_latency = None;
+		self.current_impairment = Impairment::default();
+		self.current_direction = None;
+		self.step_index = None;
+	}
+
 	pub fn set_handle(&mut self, handle: JoinHandle<anyhow::Result<()>>) {
 		if let Some(current) = self.running_handle.replace(handle) {
 			current.abort();
@@ -48,6 +472,7 @@ impl Limiter {
 		if let Some(current) = self.running_handle.take() {
 			current.abort();
 		}
+		self.scheduled = None;
 	}
 
 	fn get_interfaces() -> anyhow::Result<Vec<String>> {
@@ -60,23 +485,128 @@ impl Limiter {
 	}
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+fn append_log(path: &std::path::Path, entry: &LogEntry) -> anyhow::Result<()> {
+	let mut line = serde_json::to_vec(entry)?;
+	line.push(b'\n');
+
+	let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+	file.write_all(&line)?;
+	Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Trajectory {
 	pub limit: u32,
 	pub duration: u32,
 	pub latency: u32,
+	/// loss, jitter and reordering of this step, none by default
+	#[serde(flatten)]
+	pub impairment: Impairment,
+	#[serde(default, skip_serializing_if = "Direction::is_egress")]
+	pub direction: Direction,
+}
+
+/// Packet impairments emulated by netem on top of the limit, unset ones are disabled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Impairment {
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub loss_percent: Option<f32>,
+	/// random variation of the latency
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub jitter_ms: Option<u32>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub reorder_percent: Option<f32>,
+}
+
+impl Impairment {
+	/// percentages must be between 0 and 100
+	pub fn validate(&self) -> anyhow::Result<()> {
+		for (name, percent) in [
+			("loss_percent", self.loss_percent),
+			("reorder_percent", self.reorder_percent),
+		] {
+			if let Some(percent) = percent {
+				if !(0.0..=100.0).contains(&percent) {
+					anyhow::bail!("{name} must be between 0 and 100");
+				}
+			}
+		}
+		Ok(())
+	}
+
+	/// netem arguments following the delay
+	fn netem_args(&self) -> Vec<String> {
+		let mut args = Vec::new();
+		if let Some(jitter) = self.jitter_ms {
+			args.push(format!("{jitter}ms"));
+		}
+		if let Some(loss) = self.loss_percent {
+			args.extend(["loss".to_string(), format!("{loss}%")]);
+		}
+		if let Some(reorder) = self.reorder_percent {
+			args.extend(["reorder".to_string(), format!("{reorder}%")]);
+		}
+		args
+	}
+}
+
+/// the full netem configuration, as posted to `/impairment`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Netem {
+	pub limit: u32,
+	/// the default latency if 0
+	#[serde(default)]
+	pub latency: u32,
+	#[serde(flatten)]
+	pub impairment: Impairment,
+	#[serde(default)]
+	pub direction: Direction,
+}
+
+/// a step held until it is removed
+impl From<Netem> for Trajectory {
+	fn from(netem: Netem) -> Self {
+		Self {
+			limit: netem.limit,
+			duration: 0,
+			latency: netem.latency,
+			impairment: netem.impairment,
+			direction: netem.direction,
+		}
+	}
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TrajectoryQuery {
 	#[serde(default)]
 	pub looping: bool,
-	#[serde(default = "default_trajectory_mode")]
-	pub mode: String,
+	/// name of a built-in or stored profile to run instead of the posted trajectory
+	#[serde(default)]
+	pub mode: Option<String>,
+	/// rfc3339 wallclock time to start at, right away if not given or already passed
+	#[serde(default)]
+	pub start_at: Option<String>,
 }
 
-pub async fn set_bandwidth(limiter: Arc<RwLock<Limiter>>, limit: i64, latency: i64) -> anyhow::Result<()> {
+/// the query of `/bandwidth/set`, egress by default
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BandwidthQuery {
+	#[serde(default)]
+	pub direction: Direction,
+}
+
+pub async fn set_bandwidth(
+	limiter: Arc<RwLock<Limiter>>,
+	limit: i64,
+	latency: i64,
+	direction: Direction,
+) -> anyhow::Result<()> {
 	if limit < 0 {
+		{
+			let mut lock = limiter.write().await;
+			lock.clear();
+			lock.record(LogEntry::new(LogEvent::Abort));
+		}
 		_ = delete_all_qdiscs(&limiter).await;
 		return Ok(());
 	}
@@ -88,65 +618,141 @@ pub async fn set_bandwidth(limiter: Arc<RwLock<Limiter>>, limit: i64, latency: i
 		limit: limit as u32,
 		duration: 0,
 		latency,
+		impairment: Impairment::default(),
+		direction,
 	};
-	set_trajectory(limiter, vec![trajectory], None).await?;
+	set_trajectory(limiter, vec![trajectory], false, None).await?;
 	Ok(())
 }
 
+/// apply `netem` until it is removed, replaces a running trajectory
+pub async fn set_impairment(limiter: Arc<RwLock<Limiter>>, netem: Netem) -> anyhow::Result<()> {
+	let trajectory = vec![netem.into()];
+	validate_trajectory(&trajectory)?;
+
+	limiter.write().await.abort();
+	set_trajectory(limiter, trajectory, false, None).await
+}
+
 pub async fn unset_bandwidth(limiter: Arc<RwLock<Limiter>>) -> anyhow::Result<()> {
 	log::debug!("Limiter: aborting...");
 	let l1 = limiter.clone();
 	{
 		let mut lock = l1.write().await;
 		lock.abort();
+		lock.clear();
+		lock.record(LogEntry::new(LogEvent::Abort));
 	}
 	log::debug!("Limiter: aborted");
 	delete_all_qdiscs(&limiter).await
 }
 
-pub async fn set_trajectory(
-	limiter: Arc<RwLock<Limiter>>,
+/// abort the trajectory waiting for its start time, unlike [unset_bandwidth] the applied limit is kept
+pub async fn cancel_trajectory(limiter: Arc<RwLock<Limiter>>) -> anyhow::Result<()> {
+	let mut lock = limiter.write().await;
+	if lock.scheduled.is_none() {
+		anyhow::bail!("no trajectory scheduled");
+	}
+	lock.abort();
+	lock.record(LogEntry::new(LogEvent::Cancel));
+	log::debug!("Limiter: cancelled the scheduled trajectory");
+	Ok(())
+}
+
+/// names of the compiled-in profiles, custom profiles cannot replace them
+pub const BUILTIN_PROFILES: [&str; 2] = ["cascade", "4g"];
+
+/// limits above 100 Gbit/s are surely a typo
+const MAX_LIMIT: u32 = 100_000_000;
+
+/// the compiled-in profile `name`
+fn builtin_profile(name: &str) -> Option<anyhow::Result<Vec<Trajectory>>> {
+	let buf: &[u8] = match name {
+		"cascade" => include_bytes!("cascade.json"),
+		"4g" => include_bytes!("4g_trajectory.json"),
+		_ => return None,
+	};
+	Some(serde_json::from_slice(buf).context("invalid built-in profile"))
+}
+
+/// the trajectory to run: the profile named by `mode`, otherwise the given `trajectory`
+pub fn load_trajectory(
 	trajectory: Vec<Trajectory>,
-	query: Option<TrajectoryQuery>,
-) -> anyhow::Result<()> {
-	let (looping, mode) = match query {
-		Some(q) => (q.looping, q.mode),
-		None => (false, "-".to_string()),
+	mode: Option<&str>,
+	profiles: &BTreeMap<String, Vec<Trajectory>>,
+) -> anyhow::Result<Vec<Trajectory>> {
+	let trajectory = match mode {
+		None => trajectory,
+		Some(name) => match (builtin_profile(name), profiles.get(name)) {
+			(Some(builtin), _) => builtin?,
+			(None, Some(profile)) => profile.clone(),
+			(None, None) => anyhow::bail!("unknown profile {name}"),
+		},
 	};
 
-	let trajectory = match mode.as_str() {
-		"cascade" => {
-			let buf = include_bytes!("cascade.json");
-			serde_json::from_slice(buf)?
+	validate_trajectory(&trajectory)?;
+
+	Ok(trajectory)
+}
+
+/// a trajectory must not be empty, limit the bandwidth, impair within bounds and only hold the limit forever in the last step
+pub fn validate_trajectory(trajectory: &[Trajectory]) -> anyhow::Result<()> {
+	if trajectory.is_empty() {
+		anyhow::bail!("cannot set empty trajectory");
+	}
+
+	for (index, step) in trajectory.iter().enumerate() {
+		if step.limit == 0 || step.limit > MAX_LIMIT {
+			anyhow::bail!("step {index}: limit must be between 1 and {MAX_LIMIT} kbit/s");
 		}
-		"4g" => {
-			let buf = include_bytes!("4g_trajectory.json");
-			serde_json::from_slice(buf)?
+		if step.duration == 0 && index + 1 != trajectory.len() {
+			anyhow::bail!("step {index}: only the last step may last forever");
 		}
-		_ => trajectory,
-	};
+		step.impairment.validate().with_context(|| format!("step {index}"))?;
+	}
+
+	Ok(())
+}
 
+/// run `trajectory`, once the wall clock reached `start_at` if given
+pub async fn set_trajectory(
+	limiter: Arc<RwLock<Limiter>>,
+	trajectory: Vec<Trajectory>,
+	looping: bool,
+	start_at: Option<SystemTime>,
+) -> anyhow::Result<()> {
 	if trajectory.is_empty() {
 		anyhow::bail!("cannot set empty trajectory");
 	}
 
+	if let Some(start_at) = schedule(&limiter, start_at).await {
+		wait_until(&limiter, start_at).await;
+		limiter.write().await.scheduled = None;
+	}
+
 	log::debug!("Limiter: limiting bandwidth...");
 
 	loop {
-		for step in &trajectory {
+		for (index, step) in trajectory.iter().enumerate() {
 			let limiter = limiter.clone();
 			let bandwidth = format!("{}kbit", step.limit);
 			let latency = match step.latency {
-				0 => format!("{}ms", limiter.read().await.default_latency),
-				l => format!("{l}ms"),
+				0 => limiter.read().await.default_latency,
+				l => l,
 			};
 
 			{
 				let mut lock = limiter.write().await;
 				lock.current_limit.replace(step.limit);
+				lock.current_latency.replace(latency);
+				lock.current_impairment = step.impairment;
+				lock.current_direction.replace(step.direction);
+				lock.step_index.replace(index);
 			}
 
 			_ = delete_all_qdiscs(&limiter).await;
+			// a partially applied limit is removed as well
+			limiter.write().await.applied.replace(step.direction);
 
 			if step.duration == 0 {
 				log::debug!("Limiter: limiting to {bandwidth} for eternity (or until reset)");
@@ -154,16 +760,24 @@ pub async fn set_trajectory(
 				log::debug!("Limiter: limiting to {bandwidth} for {}ms", step.duration);
 			}
 
-			for interface in &limiter.read().await.network_interfaces {
-				Command::new("tc")
-					// if this doesnÄt work use the original args from Björn:
-					// "qdisc", "add", "dev", interface, "root", "tbf", "rate", &bandwidth, "latency", &latency, "burst", "1540"
-					.args([
-						"qdisc", "add", "dev", interface, "root", "netem", "delay", &latency, "rate", &bandwidth,
-					])
-					.output()
-					.context("failed adding qdisc")?;
+			let entry = LogEntry {
+				limit_kbps: Some(step.limit),
+				latency_ms: Some(latency),
+				duration_ms: Some(step.duration),
+				..LogEntry::new(LogEvent::Step)
+			};
+			let applied = {
+				let lock = limiter.read().await;
+				lock.network_interfaces.iter().try_for_each(|interface| {
+					lock.shaper
+						.apply(interface, step.limit, latency, step.impairment, step.direction)
+				})
+			};
+			if let Err(e) = applied {
+				limiter.write().await.clear();
+				return Err(e);
 			}
+			limiter.write().await.record(entry);
 
 			if step.duration == 0 {
 				return Ok(());
@@ -178,8 +792,11 @@ pub async fn set_trajectory(
 	}
 
 	{
+		// this task is the running one, aborting it would cancel the cleanup
 		let mut lock = limiter.write().await;
-		lock.abort();
+		lock.running_handle.take();
+		lock.clear();
+		lock.record(LogEntry::new(LogEvent::Reset));
 	}
 
 	_ = delete_all_qdiscs(&limiter).await;
@@ -189,15 +806,417 @@ pub async fn set_trajectory(
 	Ok(())
 }
 
+/// the time to wait for before running a trajectory starting at `start_at`, none if it starts right away
+async fn schedule(limiter: &Arc<RwLock<Limiter>>, start_at: Option<SystemTime>) -> Option<SystemTime> {
+	let mut lock = limiter.write().await;
+	lock.scheduled = None;
+	let start_at = start_at?;
+
+	let remaining = match start_at.duration_since(lock.clock.now()) {
+		Ok(remaining) => remaining,
+		Err(e) => {
+			log::warn!(
+				"Limiter: start time {} passed {}ms ago, starting now",
+				crate::web::rfc3339(start_at),
+				e.duration().as_millis()
+			);
+			return None;
+		}
+	};
+
+	log::debug!("Limiter: starting in {}ms", remaining.as_millis());
+	lock.scheduled = Some(start_at);
+	lock.record(LogEntry {
+		scheduled_at: Some(crate::web::rfc3339(start_at)),
+		remaining_ms: Some(remaining.as_millis() as u64),
+		..LogEntry::new(LogEvent::Scheduled)
+	});
+
+	Some(start_at)
+}
+
+/// sleep until the wall clock of the limiter reached `start_at`, checking it every [CLOCK_CHECK_INTERVAL]
+async fn wait_until(limiter: &Arc<RwLock<Limiter>>, start_at: SystemTime) {
+	loop {
+		let now = limiter.read().await.clock.now();
+		let remaining = start_at.duration_since(now).unwrap_or_default();
+		if remaining.is_zero() {
+			return;
+		}
+		sleep_until(Instant::now() + remaining.min(CLOCK_CHECK_INTERVAL)).await;
+	}
+}
+
+/// remove the qdiscs of the applied direction, the egress ones if nothing was applied
 async fn delete_all_qdiscs(limiter: &Arc<RwLock<Limiter>>) -> anyhow::Result<()> {
-	for interface in &limiter.read().await.network_interfaces {
-		Command::new("tc")
-			.args(["qdisc", "delete", "dev", interface, "root"])
-			.output()
-			.context("failed deleting qdiscs")?;
+	let mut lock = limiter.write().await;
+	let direction = lock.applied.unwrap_or_default();
+	for interface in &lock.network_interfaces {
+		lock.shaper.clear(interface, direction)?;
 	}
+	lock.applied = None;
 
 	log::debug!("Limiter: removed all limits");
 
 	Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn limiter(shaper: Arc<MockShaper>) -> Arc<RwLock<Limiter>> {
+		let interfaces = vec!["eth0".to_string(), "eth1".to_string()];
+		Arc::new(RwLock::new(Limiter::new(None, shaper, Some(interfaces)).unwrap()))
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn test_cascade() {
+		let shaper = Arc::new(MockShaper::default());
+		let trajectory = load_trajectory(Vec::new(), Some("cascade"), &BTreeMap::new()).unwrap();
+
+		set_trajectory(limiter(shaper.clone()), trajectory.clone(), false, None)
+			.await
+			.unwrap();
+
+		let clear = || ["eth0", "eth1"].map(|i| ShaperCall::Clear(i.to_string(), Direction::Egress));
+		let mut expected = Vec::new();
+		for step in &trajectory {
+			expected.extend(clear());
+			expected.extend(
+				["eth0", "eth1"].map(|i| {
+					ShaperCall::Apply(i.to_string(), step.limit, step.latency, step.impairment, step.direction)
+				}),
+			);
+		}
+		expected.extend(clear());
+
+		assert_eq!(trajectory.len(), 8);
+		assert_eq!(*shaper.calls.lock().unwrap(), expected);
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn test_log() {
+		let path = std::env::temp_dir().join(format!("moq-relay-limiter-{}.jsonl", std::process::id()));
+		let _ = std::fs::remove_file(&path);
+
+		let limiter = limiter(Arc::new(MockShaper::default()));
+		limiter.write().await.set_log_file(path.clone());
+
+		let trajectory = vec![
+			step(1000, 500),
+			Trajectory {
+				latency: 20,
+				..step(500, 1000)
+			},
+			step(2000, 500),
+		];
+		set_trajectory(limiter.clone(), trajectory, false, None).await.unwrap();
+		set_bandwidth(limiter.clone(), 300, 10, Direction::Egress)
+			.await
+			.unwrap();
+		unset_bandwidth(limiter.clone()).await.unwrap();
+
+		let log = limiter.read().await.log();
+		let steps: Vec<_> = log
+			.iter()
+			.map(|entry| (entry.event, entry.limit_kbps, entry.latency_ms, entry.duration_ms))
+			.collect();
+		assert_eq!(
+			steps,
+			[
+				(LogEvent::Step, Some(1000), Some(50), Some(500)),
+				(LogEvent::Step, Some(500), Some(20), Some(1000)),
+				(LogEvent::Step, Some(2000), Some(50), Some(500)),
+				(LogEvent::Reset, None, None, None),
+				(LogEvent::Step, Some(300), Some(10), Some(0)),
+				(LogEvent::Abort, None, None, None),
+			]
+		);
+		assert!(log
+			.iter()
+			.all(|entry| chrono::DateTime::parse_from_rfc3339(&entry.start).is_ok()));
+
+		// the file holds the same entries, one per line
+		let file: Vec<LogEntry> = std::fs::read_to_string(&path)
+			.unwrap()
+			.lines()
+			.map(|line| serde_json::from_str(line).unwrap())
+			.collect();
+		assert_eq!(file, log);
+
+		let _ = std::fs::remove_file(&path);
+	}
+
+	/// a limiter whose wall clock follows the paused tokio clock, starting at the returned time
+	fn scheduling(shaper: Arc<MockShaper>) -> (Arc<RwLock<Limiter>>, Arc<MockClock>, SystemTime) {
+		let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+		let clock = Arc::new(MockClock::new(start));
+		let limiter = limiter(shaper);
+		limiter.try_write().unwrap().set_clock(clock.clone());
+		(limiter, clock, start)
+	}
+
+	/// run `trajectory` in the background like `POST /trajectory`, replacing the running one
+	async fn spawn(limiter: &Arc<RwLock<Limiter>>, trajectory: Vec<Trajectory>, start_at: SystemTime) {
+		let handle = tokio::spawn(set_trajectory(limiter.clone(), trajectory, false, Some(start_at)));
+		limiter.write().await.set_handle(handle);
+		tokio::task::yield_now().await;
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn test_schedule() {
+		let shaper = Arc::new(MockShaper::default());
+		let (limiter, clock, start) = scheduling(shaper.clone());
+
+		let start_at = start + Duration::from_secs(10);
+		spawn(&limiter, vec![step(1000, 0)], start_at).await;
+		sleep(Duration::from_secs(4)).await;
+
+		let status = limiter.read().await.status();
+		assert_eq!(status.scheduled_at, Some(crate::web::rfc3339(start_at)));
+		assert_eq!(status.starts_in_ms, Some(6000));
+		assert!(!status.active && !status.trajectory_running);
+		assert!(shaper.calls.lock().unwrap().is_empty());
+
+		let log = limiter.read().await.log();
+		assert_eq!(log[0].event, LogEvent::Scheduled);
+		assert_eq!(log[0].remaining_ms, Some(10_000));
+
+		// the wall clock is corrected by 2s, the start follows it
+		clock.set_back(Duration::from_secs(2));
+		sleep(Duration::from_millis(7500)).await;
+		assert!(!limiter.read().await.status().active);
+		sleep(Duration::from_secs(1)).await;
+
+		let status = limiter.read().await.status();
+		assert!(status.active);
+		assert_eq!(status.limit_kbps, Some(1000));
+		assert_eq!(status.scheduled_at, None);
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn test_schedule_past() {
+		let shaper = Arc::new(MockShaper::default());
+		let (limiter, _, start) = scheduling(shaper.clone());
+
+		// starts right away
+		let start_at = start - Duration::from_secs(1);
+		set_trajectory(limiter.clone(), vec![step(1000, 0)], false, Some(start_at))
+			.await
+			.unwrap();
+		let status = limiter.read().await.status();
+		assert_eq!(status.limit_kbps, Some(1000));
+		assert_eq!(status.scheduled_at, None);
+		assert!(limiter.read().await.log().iter().all(|e| e.event == LogEvent::Step));
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn test_schedule_replace() {
+		let shaper = Arc::new(MockShaper::default());
+		let (limiter, _, start) = scheduling(shaper.clone());
+
+		spawn(&limiter, vec![step(1000, 0)], start + Duration::from_secs(10)).await;
+		spawn(&limiter, vec![step(2000, 0)], start + Duration::from_secs(20)).await;
+		assert_eq!(
+			limiter.read().await.status().scheduled_at,
+			Some(crate::web::rfc3339(start + Duration::from_secs(20)))
+		);
+
+		sleep(Duration::from_secs(15)).await;
+		assert!(shaper.calls.lock().unwrap().is_empty());
+		sleep(Duration::from_secs(10)).await;
+
+		let applied: Vec<_> = shaper
+			.calls
+			.lock()
+			.unwrap()
+			.iter()
+			.filter_map(|call| match call {
+				ShaperCall::Apply(_, limit, ..) => Some(*limit),
+				_ => None,
+			})
+			.collect();
+		assert_eq!(applied, [2000, 2000]);
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn test_cancel() {
+		let shaper = Arc::new(MockShaper::default());
+		let (limiter, _, start) = scheduling(shaper.clone());
+		assert!(cancel_trajectory(limiter.clone()).await.is_err());
+
+		set_bandwidth(limiter.clone(), 300, 10, Direction::Egress)
+			.await
+			.unwrap();
+		spawn(&limiter, vec![step(1000, 0)], start + Duration::from_secs(10)).await;
+		cancel_trajectory(limiter.clone()).await.unwrap();
+		let calls = shaper.calls.lock().unwrap().len();
+
+		// the applied limit is kept, the cancelled trajectory never starts
+		sleep(Duration::from_secs(20)).await;
+		let status = limiter.read().await.status();
+		assert_eq!(status.limit_kbps, Some(300));
+		assert_eq!(status.scheduled_at, None);
+		assert!(!status.trajectory_running);
+		assert_eq!(shaper.calls.lock().unwrap().len(), calls);
+		assert_eq!(limiter.read().await.log().last().unwrap().event, LogEvent::Cancel);
+
+		assert_eq!(
+			format!("{}", cancel_trajectory(limiter).await.unwrap_err()),
+			"no trajectory scheduled"
+		);
+	}
+
+	#[tokio::test]
+	async fn test_failure() {
+		let shaper = Arc::new(MockShaper {
+			fail: true,
+			..Default::default()
+		});
+
+		let limiter = limiter(shaper.clone());
+		let err = set_bandwidth(limiter.clone(), 1000, 0, Direction::Egress)
+			.await
+			.unwrap_err();
+		assert_eq!(format!("{err}"), "mock failure");
+
+		// the limit is tried on the first interface only, the default latency is used
+		assert_eq!(
+			shaper.calls.lock().unwrap().last(),
+			Some(&ShaperCall::Apply(
+				"eth0".to_string(),
+				1000,
+				50,
+				Impairment::default(),
+				Direction::Egress
+			))
+		);
+		assert!(!limiter.read().await.status().active);
+	}
+
+	fn step(limit: u32, duration: u32) -> Trajectory {
+		Trajectory {
+			limit,
+			duration,
+			latency: 0,
+			impairment: Impairment::default(),
+			direction: Direction::Egress,
+		}
+	}
+
+	#[test]
+	fn test_load_trajectory() {
+		let profiles = BTreeMap::from([("slow".to_string(), vec![step(500, 1000), step(100, 0)])]);
+		let body = vec![step(1000, 0)];
+
+		// the body is used without a mode, it used to be replaced by the default cascade
+		assert_eq!(load_trajectory(body.clone(), None, &profiles).unwrap(), body);
+
+		// a requested profile wins over the body
+		assert_eq!(
+			load_trajectory(body.clone(), Some("slow"), &profiles).unwrap(),
+			profiles["slow"]
+		);
+		assert_eq!(
+			load_trajectory(body.clone(), Some("cascade"), &profiles).unwrap().len(),
+			8
+		);
+
+		assert!(load_trajectory(body, Some("missing"), &profiles).is_err());
+		assert!(load_trajectory(Vec::new(), None, &profiles).is_err());
+	}
+
+	#[test]
+	fn test_validate_trajectory() {
+		assert!(validate_trajectory(&[step(1000, 500), step(2000, 0)]).is_ok());
+		assert!(validate_trajectory(&[]).is_err());
+		assert!(validate_trajectory(&[step(0, 500)]).is_err());
+		assert!(validate_trajectory(&[step(MAX_LIMIT + 1, 500)]).is_err());
+		assert!(validate_trajectory(&[step(1000, 0), step(2000, 500)]).is_err());
+
+		let impaired = |loss_percent, reorder_percent| Trajectory {
+			impairment: Impairment {
+				loss_percent,
+				jitter_ms: Some(10),
+				reorder_percent,
+			},
+			..step(1000, 0)
+		};
+		assert!(validate_trajectory(&[impaired(Some(100.0), Some(0.0))]).is_ok());
+		assert!(validate_trajectory(&[impaired(Some(100.5), None)]).is_err());
+		assert!(validate_trajectory(&[impaired(None, Some(-1.0))]).is_err());
+		assert!(validate_trajectory(&[impaired(Some(f32::NAN), None)]).is_err());
+	}
+
+	#[tokio::test]
+	async fn test_impairment() {
+		let shaper = Arc::new(MockShaper::default());
+		let limiter = limiter(shaper.clone());
+		let netem: Netem =
+			serde_json::from_str(r#"{ "limit": 2000, "loss_percent": 1.5, "jitter_ms": 20, "reorder_percent": 25 }"#)
+				.unwrap();
+
+		set_impairment(limiter.clone(), netem.clone()).await.unwrap();
+		assert_eq!(
+			shaper.calls.lock().unwrap().last(),
+			Some(&ShaperCall::Apply(
+				"eth1".to_string(),
+				2000,
+				50,
+				netem.impairment,
+				Direction::Egress
+			))
+		);
+		assert_eq!(limiter.read().await.status().impairment, netem.impairment);
+		assert_eq!(
+			netem.impairment.netem_args(),
+			["20ms", "loss", "1.5%", "reorder", "25%"]
+		);
+
+		unset_bandwidth(limiter.clone()).await.unwrap();
+		assert_eq!(limiter.read().await.status().impairment, Impairment::default());
+	}
+
+	/// the commands of limiting eth0 to 1000 kbit in `direction` and removing the limit again
+	async fn commands(direction: Direction) -> Vec<String> {
+		let shaper = Arc::new(MockShaper::default());
+		let interfaces = Some(vec!["eth0".to_string()]);
+		let limiter = Arc::new(RwLock::new(Limiter::new(None, shaper.clone(), interfaces).unwrap()));
+
+		set_bandwidth(limiter.clone(), 1000, 20, direction).await.unwrap();
+		assert_eq!(limiter.read().await.status().direction, Some(direction));
+		unset_bandwidth(limiter.clone()).await.unwrap();
+		assert_eq!(limiter.read().await.status().direction, None);
+
+		let commands = shaper.commands.lock().unwrap().clone();
+		commands
+	}
+
+	#[tokio::test]
+	async fn test_directions() {
+		// nothing was applied before, the egress qdisc is removed just in case
+		let clear = "tc qdisc delete dev eth0 root";
+		let egress = "tc qdisc add dev eth0 root netem delay 20ms rate 1000kbit";
+		let ingress = [
+			"ip link add ifb-eth0 type ifb",
+			"ip link set dev ifb-eth0 up",
+			"tc qdisc add dev eth0 handle ffff: ingress",
+			"tc filter add dev eth0 parent ffff: protocol all u32 match u32 0 0 action mirred egress redirect dev ifb-eth0",
+			"tc qdisc add dev ifb-eth0 root netem delay 20ms rate 1000kbit",
+		];
+		let teardown = ["tc qdisc delete dev eth0 ingress", "ip link delete ifb-eth0"];
+
+		assert_eq!(commands(Direction::Egress).await, [clear, egress, clear]);
+		assert_eq!(
+			commands(Direction::Ingress).await,
+			[&[clear][..], &ingress, &teardown].concat()
+		);
+		assert_eq!(
+			commands(Direction::Both).await,
+			[&[clear, egress][..], &ingress, &[clear], &teardown].concat()
+		);
+
+		assert_eq!(TcShaper::ifb_device("enp0s31f6-long"), "ifb-enp0s31f6-l");
+	}
+}
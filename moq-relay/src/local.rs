@@ -2,12 +2,23 @@ use std::collections::hash_map;
 use std::collections::HashMap;
 
 use std::sync::{Arc, Mutex};
+use std::time;
 
 use moq_transport::serve::{ServeError, TracksReader};
 
 #[derive(Clone)]
 pub struct Locals {
-	lookup: Arc<Mutex<HashMap<String, TracksReader>>>,
+	lookup: Arc<Mutex<HashMap<String, Local>>>,
+}
+
+/// A broadcast announced to this relay.
+#[derive(Clone)]
+pub struct Local {
+	pub tracks: TracksReader,
+	/// when the broadcast was announced
+	pub since: time::SystemTime,
+	/// the last complete catalog served per catalog track, dropped with the announce
+	pub catalogs: Arc<Mutex<HashMap<String, bytes::Bytes>>>,
 }
 
 impl Default for Locals {
@@ -25,8 +36,13 @@ impl Locals {
 
 	pub async fn register(&mut self, tracks: TracksReader) -> anyhow::Result<Registration> {
 		let namespace = tracks.namespace.clone();
+		let local = Local {
+			tracks,
+			since: time::SystemTime::now(),
+			catalogs: Default::default(),
+		};
 		match self.lookup.lock().unwrap().entry(namespace.clone()) {
-			hash_map::Entry::Vacant(entry) => entry.insert(tracks),
+			hash_map::Entry::Vacant(entry) => entry.insert(local),
 			hash_map::Entry::Occupied(_) => return Err(ServeError::Duplicate.into()),
 		};
 
@@ -39,8 +55,19 @@ impl Locals {
 	}
 
 	pub fn route(&self, namespace: &str) -> Option<TracksReader> {
+		self.get(namespace).map(|local| local.tracks)
+	}
+
+	pub fn get(&self, namespace: &str) -> Option<Local> {
 		self.lookup.lock().unwrap().get(namespace).cloned()
 	}
+
+	/// every announced broadcast, sorted by namespace
+	pub fn list(&self) -> Vec<Local> {
+		let mut list: Vec<_> = self.lookup.lock().unwrap().values().cloned().collect();
+		list.sort_by(|a, b| a.tracks.namespace.cmp(&b.tracks.namespace));
+		list
+	}
 }
 
 pub struct Registration {
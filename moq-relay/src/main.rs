@@ -1,23 +1,5 @@
 use clap::Parser;
-
-mod api;
-mod consumer;
-mod limiter;
-mod local;
-mod producer;
-mod relay;
-mod remote;
-mod session;
-mod web;
-
-pub use api::*;
-pub use consumer::*;
-pub use local::*;
-pub use producer::*;
-pub use relay::*;
-pub use remote::*;
-pub use session::*;
-pub use web::*;
+use moq_relay::*;
 
 use std::net;
 use url::Url;
@@ -51,6 +33,38 @@ pub struct Cli {
 	/// This hosts a HTTPS web server via TCP to serve the fingerprint of the certificate.
 	#[arg(long)]
 	pub dev: bool,
+
+	/// Also serve the development web server over plain HTTP on this address.
+	/// Clients can fetch the fingerprint without validating the certificate first.
+	#[arg(long)]
+	pub web_http_bind: Option<net::SocketAddr>,
+
+	/// Serve the bandwidth limiter and connection routes of the development web server on this address only,
+	/// ex. an internal admin port. By default they are served next to the fingerprint.
+	#[arg(long)]
+	pub web_admin_bind: Option<net::SocketAddr>,
+
+	/// Require this bearer token on the bandwidth limiter and connection routes of the development web server.
+	#[arg(long)]
+	pub admin_token: Option<String>,
+
+	/// Serve the routes of the development web server below this path, ex. `/moq`, instead of the root.
+	#[arg(long, default_value = "")]
+	pub web_prefix: String,
+
+	/// Only allow this origin to make requests to the development web server, with credentials.
+	/// Can be repeated, by default any origin is allowed without credentials.
+	#[arg(long)]
+	pub web_allow_origin: Vec<axum::http::HeaderValue>,
+
+	/// Only apply the bandwidth limits of the development web server to these interfaces.
+	/// By default every interface but the loopback one is limited.
+	#[arg(long, value_delimiter = ',')]
+	pub limit_interfaces: Option<Vec<String>>,
+
+	/// Append every step the bandwidth limiter applies, aborts and resets to this JSONL file.
+	#[arg(long)]
+	pub limiter_log: Option<std::path::PathBuf>,
 }
 
 #[tokio::main]
@@ -79,15 +93,55 @@ async fn main() -> anyhow::Result<()> {
 		announce: cli.announce,
 	})?;
 
+	let mut web_server = None;
 	if cli.dev {
 		// Create a web server too.
-		// Currently this only contains the certificate fingerprint (for development only).
-		let web = Web::new(WebConfig { bind: cli.bind, tls });
-
-		tokio::spawn(async move {
-			web.run().await.expect("failed to run web server");
+		// This serves the certificate fingerprint, the announced broadcasts and the bandwidth limiter (for development only).
+		let server = Web::new(WebConfig {
+			public_bind: cli.bind,
+			http_bind: cli.web_http_bind,
+			admin_bind: cli.web_admin_bind,
+			admin_token: cli.admin_token,
+			tls,
+			limit_interfaces: cli.limit_interfaces,
+			locals: relay.locals(),
+			connections: relay.connections(),
+			limiter_log: cli.limiter_log,
+			prefix: cli.web_prefix,
+			allow_origins: cli.web_allow_origin,
 		});
+
+		let handle = server.shutdown_handle();
+		web_server = Some((handle, tokio::spawn(server.run())));
+	}
+
+	let res = tokio::select! {
+		res = relay.run() => res,
+		res = terminate() => res,
+	};
+
+	// stop accepting requests and let the open ones finish
+	if let Some((handle, task)) = web_server {
+		handle.graceful_shutdown(Some(SHUTDOWN_TIMEOUT));
+		match task.await {
+			Ok(Err(e)) => log::warn!("web server failed: {e:#}"),
+			Err(e) => log::warn!("web server panicked: {e}"),
+			Ok(Ok(())) => log::info!("web server stopped"),
+		}
+	}
+
+	res
+}
+
+/// resolves on SIGINT or SIGTERM
+async fn terminate() -> anyhow::Result<()> {
+	let mut sigterm = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+
+	tokio::select! {
+		res = tokio::signal::ctrl_c() => res?,
+		_ = sigterm.recv() => {},
 	}
 
-	relay.run().await
+	log::info!("shutting down");
+	Ok(())
 }
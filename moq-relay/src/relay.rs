@@ -1,4 +1,4 @@
-use std::net;
+use std::{net, time};
 
 use anyhow::Context;
 
@@ -6,7 +6,10 @@ use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
 use moq_native::quic;
 use url::Url;
 
-use crate::{Api, Consumer, Locals, Producer, Remotes, RemotesConsumer, RemotesProducer, Session};
+use crate::{Api, Connections, Consumer, Locals, Producer, Remotes, RemotesConsumer, RemotesProducer, Role, Session};
+
+/// how often the statistics listed in [Connections] are refreshed
+const STATS_INTERVAL: time::Duration = time::Duration::from_secs(1);
 
 pub struct RelayConfig {
 	/// Listen on this address
@@ -30,6 +33,7 @@ pub struct Relay {
 	quic: quic::Endpoint,
 	announce: Option<Url>,
 	locals: Locals,
+	connections: Connections,
 	api: Option<Api>,
 	remotes: Option<(RemotesProducer, RemotesConsumer)>,
 }
@@ -40,6 +44,7 @@ impl Relay {
 		let quic = quic::Endpoint::new(quic::Config {
 			bind: config.bind,
 			tls: config.tls,
+			transport: Default::default(),
 		})?;
 
 		let api = if let (Some(url), Some(node)) = (config.api, config.node) {
@@ -64,10 +69,30 @@ impl Relay {
 			announce: config.announce,
 			api,
 			locals,
+			connections: Connections::new(),
 			remotes,
 		})
 	}
 
+	/// the address the QUIC server listens on, ex. to learn the port of `[::]:0`
+	pub fn local_addr(&self) -> anyhow::Result<net::SocketAddr> {
+		self.quic
+			.server
+			.as_ref()
+			.context("missing TLS certificate")?
+			.local_addr()
+	}
+
+	/// the broadcasts announced to this relay
+	pub fn locals(&self) -> Locals {
+		self.locals.clone()
+	}
+
+	/// the sessions accepted by this relay
+	pub fn connections(&self) -> Connections {
+		self.connections.clone()
+	}
+
 	pub async fn run(self) -> anyhow::Result<()> {
 		let mut tasks = FuturesUnordered::new();
 
@@ -92,7 +117,7 @@ impl Relay {
 			let session = Session {
 				session,
 				producer: Some(Producer::new(publisher, self.locals.clone(), remotes.clone())),
-				consumer: Some(Consumer::new(subscriber, self.locals.clone(), None, None)),
+				consumer: Some(Consumer::new(subscriber, self.locals.clone(), None, None, None)),
 			};
 
 			let forward = session.producer.clone();
@@ -109,10 +134,11 @@ impl Relay {
 
 		loop {
 			tokio::select! {
-				res = server.accept() => {
-					let conn = res.context("failed to accept QUIC connection")?;
+				res = server.accept_connection() => {
+					let (conn, quic) = res.context("failed to accept QUIC connection")?;
 
 					let locals = self.locals.clone();
+					let connections = self.connections.clone();
 					let remotes = remotes.clone();
 					let forward = forward.clone();
 					let api = self.api.clone();
@@ -126,14 +152,35 @@ impl Relay {
 							}
 						};
 
+						// the peer subscribes to our publisher and publishes to our subscriber
+						let role = match (&publisher, &subscriber) {
+							(Some(_), Some(_)) => Role::Both,
+							(Some(_), None) => Role::Subscriber,
+							_ => Role::Publisher,
+						};
+						let registration = connections.register(&quic, role, session.streams());
+						let connection = registration.handle();
+
 						let session = Session {
 							session,
 							producer: publisher.map(|publisher| Producer::new(publisher, locals.clone(), remotes)),
-							consumer: subscriber.map(|subscriber| Consumer::new(subscriber, locals, api, forward)),
+							consumer: subscriber.map(|subscriber| Consumer::new(subscriber, locals, api, forward, Some(connection))),
+						};
+
+						// the connection stays owned by this task, the registry only gets its statistics
+						let stats = async {
+							let mut interval = tokio::time::interval(STATS_INTERVAL);
+							loop {
+								interval.tick().await;
+								registration.update(&quic);
+							}
 						};
 
-						if let Err(err) = session.run().await {
-							log::warn!("failed to run MoQ session: {}", err);
+						tokio::select! {
+							res = session.run() => if let Err(err) = res {
+								log::warn!("failed to run MoQ session: {}", err);
+							},
+							_ = stats => (),
 						}
 
 						Ok(())
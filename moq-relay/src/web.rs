@@ -1,21 +1,53 @@
-use std::{net, sync::Arc};
+use std::{collections::BTreeMap, net, sync::Arc, time};
 
 use crate::limiter::*;
+use crate::{Connections, Local, Locals};
 
 use axum::{
-	extract::{Path, Query, State},
-	http::Method,
-	response::IntoResponse,
-	routing::{get, post},
+	body::Bytes,
+	extract::{rejection::JsonRejection, Path, Query, State},
+	http::{header, HeaderValue, Method, Request, StatusCode},
+	middleware::{self, Next},
+	response::{IntoResponse, Response},
+	routing::{get, post, put},
 	Json, Router,
 };
 use axum_server::tls_rustls::RustlsAcceptor;
+use futures::FutureExt;
+use moq_transport::serve::{ServeError, TrackReader, TrackReaderMode};
 use tokio::sync::RwLock;
-use tower_http::cors::{Any, CorsLayer};
+use tower_http::cors::{AllowHeaders, AllowOrigin, Any, CorsLayer};
+
+/// track the publishers write their catalog to, unless requested with `?track=`
+const CATALOG_TRACK: &str = ".catalog";
+
+/// how long a request waits for the publisher to serve the catalog
+const CATALOG_TIMEOUT: time::Duration = time::Duration::from_secs(2);
+
+/// how long the open connections may take to finish their requests once the web server shuts down
+pub const SHUTDOWN_TIMEOUT: time::Duration = time::Duration::from_secs(5);
 
 pub struct WebConfig {
-	pub bind: net::SocketAddr,
+	pub public_bind: net::SocketAddr,
+	/// serves the public routes over plain HTTP, for clients that cannot validate the certificate yet
+	pub http_bind: Option<net::SocketAddr>,
+	/// serves the limiter and connection routes, which the public listeners omit then
+	pub admin_bind: Option<net::SocketAddr>,
+	/// bearer token the limiter and connection routes require, on whichever listener serves them
+	pub admin_token: Option<String>,
 	pub tls: moq_native::tls::Config,
+	/// interfaces the bandwidth limiter applies to, all but the loopback interface if not given
+	pub limit_interfaces: Option<Vec<String>>,
+	/// the broadcasts announced to the relay
+	pub locals: Locals,
+	/// the sessions accepted by the relay
+	pub connections: Connections,
+	/// JSONL file the executed limiter schedule is appended to
+	pub limiter_log: Option<std::path::PathBuf>,
+	/// path all routes are nested under, ex. `/moq`, at the root if empty
+	pub prefix: String,
+	/// origins allowed to make requests with credentials, any origin without credentials if empty
+	pub allow_origins: Vec<HeaderValue>,
 }
 
 // Run a HTTP server using Axum
@@ -23,58 +55,422 @@ pub struct WebConfig {
 pub struct Web {
 	app: Router,
 	server: axum_server::Server<RustlsAcceptor>,
+	http: Option<axum_server::Server>,
+	admin: Option<(Router, axum_server::Server<RustlsAcceptor>)>,
+	handle: axum_server::Handle,
+}
+
+/// The routes served by a listener.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Routes {
+	/// the fingerprint, broadcast, limiter and connection routes
+	All,
+	/// all but the limiter and connection routes
+	Public,
+	/// only the limiter and connection routes
+	Admin,
 }
 
 struct Store {
-	fingerprint: String,
+	fingerprints: Vec<moq_native::tls::Fingerprint>,
 	limiter: Arc<RwLock<Limiter>>,
+	/// custom trajectory profiles uploaded at runtime
+	profiles: BTreeMap<String, Vec<Trajectory>>,
+	locals: Locals,
+	connections: Connections,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct CatalogQuery {
+	/// the catalog track, ex. `.catalog.video` of a hierarchical catalog
+	#[serde(default)]
+	track: Option<String>,
 }
 
 impl Web {
 	pub fn new(config: WebConfig) -> Self {
-		// Get the first certificate's fingerprint.
-		// TODO serve all of them so we can support multiple signature algorithms.
-		let fingerprint = config.tls.fingerprints.first().expect("missing certificate").clone();
+		assert!(!config.tls.fingerprints.is_empty(), "missing certificate");
+		let fingerprints = config.tls.fingerprints.clone();
 
 		let mut tls = config.tls.server.expect("missing server configuration");
 		tls.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
 		let tls = axum_server::tls_rustls::RustlsConfig::from_config(Arc::new(tls));
 
+		let mut limiter = Limiter::new(None, Arc::new(TcShaper::new().unwrap()), config.limit_interfaces).unwrap();
+		if let Some(path) = config.limiter_log {
+			limiter.set_log_file(path);
+		}
+
 		let store = Arc::new(RwLock::new(Store {
-			fingerprint,
-			limiter: Arc::new(RwLock::new(Limiter::new(None).unwrap())),
+			fingerprints,
+			limiter: Arc::new(RwLock::new(limiter)),
+			profiles: BTreeMap::new(),
+			locals: config.locals,
+			connections: config.connections,
 		}));
 
-		let app = Router::new()
-			.route("/fingerprint", get(serve_fingerprint))
-			.route("/bandwidth/set/:kbps/:latency", post(post_set_bandwidth))
-			.route("/bandwidth/remove", post(post_remove_bandwidth))
-			.route("/trajectory", post(post_trajectory))
-			.layer(
-				CorsLayer::new()
-					.allow_origin(Any)
-					.allow_methods([Method::GET, Method::POST])
-					.allow_headers(Any),
-			)
-			.with_state(store);
+		let token = config.admin_token.as_deref();
+		let routes = match config.admin_bind {
+			Some(_) => Routes::Public,
+			None => Routes::All,
+		};
+		let app = router(store.clone(), routes, token, &config.prefix, &config.allow_origins);
+
+		// all listeners shut down together
+		let handle = axum_server::Handle::new();
+		let server = axum_server::bind_rustls(config.public_bind, tls.clone()).handle(handle.clone());
+		let http = config
+			.http_bind
+			.map(|bind| axum_server::bind(bind).handle(handle.clone()));
+		let admin = config.admin_bind.map(|bind| {
+			let admin = router(store, Routes::Admin, token, &config.prefix, &config.allow_origins);
+			(admin, axum_server::bind_rustls(bind, tls).handle(handle.clone()))
+		});
 
-		let server = axum_server::bind_rustls(config.bind, tls);
+		Self {
+			app,
+			server,
+			http,
+			admin,
+			handle,
+		}
+	}
 
-		Self { app, server }
+	/// stops the listeners, ex. `graceful_shutdown(Some(SHUTDOWN_TIMEOUT))` ends [Self::run] once the requests drained
+	pub fn shutdown_handle(&self) -> axum_server::Handle {
+		self.handle.clone()
 	}
 
 	pub async fn run(self) -> anyhow::Result<()> {
-		self.server.serve(self.app.into_make_service()).await?;
+		let https = self.server.serve(self.app.clone().into_make_service());
+		let http = async {
+			match self.http {
+				Some(http) => http.serve(self.app.into_make_service()).await,
+				None => Ok(()),
+			}
+		};
+		let admin = async {
+			match self.admin {
+				Some((admin, server)) => server.serve(admin.into_make_service()).await,
+				None => Ok(()),
+			}
+		};
+
+		tokio::try_join!(https, http, admin)?;
 		Ok(())
 	}
 }
 
+/// the `routes` nested under `prefix`, with CORS for `allow_origins`
+///
+/// The limiter and connection routes require the bearer `token` if given.
+fn router(
+	store: Arc<RwLock<Store>>,
+	routes: Routes,
+	token: Option<&str>,
+	prefix: &str,
+	allow_origins: &[HeaderValue],
+) -> Router {
+	let public = Router::new()
+		.route("/fingerprint", get(serve_fingerprint))
+		.route("/fingerprints", get(serve_fingerprints))
+		.route("/broadcasts", get(serve_broadcasts))
+		.route("/broadcasts/:namespace", get(serve_broadcast))
+		.route("/catalog/:namespace", get(serve_catalog));
+
+	let mut admin = Router::new()
+		.route("/connections", get(serve_connections))
+		.route("/connections/:id", get(serve_connection))
+		.route("/bandwidth", get(serve_bandwidth))
+		.route("/bandwidth/set/:kbps/:latency", post(post_set_bandwidth))
+		.route("/bandwidth/remove", post(post_remove_bandwidth))
+		.route("/trajectory", post(post_trajectory))
+		.route("/trajectory/cancel", post(post_cancel_trajectory))
+		.route("/trajectory/log", get(serve_trajectory_log))
+		.route("/trajectory/profiles", get(serve_profiles))
+		.route("/trajectory/profiles/:name", put(put_profile))
+		.route("/impairment", post(post_impairment));
+	if let Some(token) = token {
+		admin = admin.route_layer(middleware::from_fn_with_state(Arc::<str>::from(token), authorize));
+	}
+
+	let routes = match routes {
+		Routes::All => public.merge(admin),
+		Routes::Public => public,
+		Routes::Admin => admin,
+	}
+	.with_state(store);
+
+	let prefix = prefix.trim_matches('/');
+	let app = match prefix.is_empty() {
+		true => routes,
+		false => Router::new().nest(&format!("/{prefix}"), routes),
+	};
+
+	// credentials cannot be combined with wildcards
+	let cors = CorsLayer::new().allow_methods([Method::GET, Method::POST]);
+	let cors = match allow_origins.is_empty() {
+		true => cors.allow_origin(Any).allow_headers(Any),
+		false => cors
+			.allow_origin(AllowOrigin::list(allow_origins.iter().cloned()))
+			.allow_headers(AllowHeaders::mirror_request())
+			.allow_credentials(true),
+	};
+
+	app.layer(cors)
+}
+
+/// rejects the requests without the bearer token `token`
+async fn authorize<B>(State(token): State<Arc<str>>, request: Request<B>, next: Next<B>) -> Response {
+	let bearer = request
+		.headers()
+		.get(header::AUTHORIZATION)
+		.and_then(|value| value.to_str().ok())
+		.and_then(|value| value.strip_prefix("Bearer "));
+	let authorized = bearer.is_some_and(|bearer| {
+		ring::constant_time::verify_slices_are_equal(bearer.as_bytes(), token.as_bytes()).is_ok()
+	});
+	if authorized {
+		return next.run(request).await;
+	}
+
+	let mut response = error(StatusCode::UNAUTHORIZED, "missing or invalid bearer token".to_string());
+	response
+		.headers_mut()
+		.insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+	response
+}
+
+/// the fingerprint of the first certificate
 async fn serve_fingerprint(State(store): State<Arc<RwLock<Store>>>) -> impl IntoResponse {
-	store.read().await.fingerprint.clone()
+	store.read().await.fingerprints[0].sha256.clone()
 }
 
+/// the fingerprints of every certificate, so clients can pick the signature algorithm they support
+async fn serve_fingerprints(State(store): State<Arc<RwLock<Store>>>) -> impl IntoResponse {
+	let fingerprints: Vec<_> = store
+		.read()
+		.await
+		.fingerprints
+		.iter()
+		.map(|fingerprint| {
+			serde_json::json!({
+				"algorithm": fingerprint.algorithm,
+				"sha256": fingerprint.sha256,
+			})
+		})
+		.collect();
+
+	Json(fingerprints)
+}
+
+/// the QUIC stats of every session
+async fn serve_connections(State(store): State<Arc<RwLock<Store>>>) -> impl IntoResponse {
+	Json(store.read().await.connections.list())
+}
+
+/// the QUIC stats of the session `id`, as listed by `/connections`
+async fn serve_connection(State(store): State<Arc<RwLock<Store>>>, Path(id): Path<usize>) -> Response {
+	match store.read().await.connections.get(id) {
+		Some(info) => Json(info).into_response(),
+		None => error(StatusCode::NOT_FOUND, format!("connection {id} not found")),
+	}
+}
+
+/// announced namespaces with the names of their tracks
+async fn serve_broadcasts(State(store): State<Arc<RwLock<Store>>>) -> impl IntoResponse {
+	let locals = store.read().await.locals.clone();
+
+	let broadcasts: Vec<_> = locals
+		.list()
+		.into_iter()
+		.map(|local| {
+			let mut tracks: Vec<_> = local.tracks.tracks().iter().map(|track| track.name.clone()).collect();
+			tracks.sort();
+
+			serde_json::json!({
+				"namespace": local.tracks.namespace,
+				"tracks": tracks,
+				"since": rfc3339(local.since),
+			})
+		})
+		.collect();
+
+	Json(broadcasts)
+}
+
+/// a single announced namespace with the latest group and object of every track, 404 if not announced
+///
+/// The relay only knows the tracks that were subscribed to so far,
+/// the latest group and object are null until the publisher answered the subscription.
+async fn serve_broadcast(Path(namespace): Path<String>, State(store): State<Arc<RwLock<Store>>>) -> Response {
+	let locals = store.read().await.locals.clone();
+
+	let Some(Local { tracks, since, .. }) = locals.get(&namespace) else {
+		let error = format!("{namespace} is not announced");
+		return (StatusCode::NOT_FOUND, Json(serde_json::json!({ "error": error }))).into_response();
+	};
+
+	let mut tracks: Vec<_> = tracks
+		.tracks()
+		.into_iter()
+		.map(|track| {
+			// the mode is only set once the publisher answered, never wait for it
+			let latest = track
+				.mode()
+				.now_or_never()
+				.and_then(Result::ok)
+				.and_then(|mode| mode.latest());
+			(track.name.clone(), latest)
+		})
+		.collect();
+	tracks.sort();
+
+	let tracks: Vec<_> = tracks
+		.into_iter()
+		.map(|(name, latest)| {
+			serde_json::json!({
+				"name": name,
+				"latest_group": latest.map(|(group, _)| group),
+				"latest_object": latest.map(|(_, object)| object),
+			})
+		})
+		.collect();
+
+	Json(serde_json::json!({
+		"namespace": namespace,
+		"tracks": tracks,
+		"since": rfc3339(since),
+	}))
+	.into_response()
+}
+
+/// the newest catalog published on the `.catalog` track of an announced namespace, or on the `track` of the query
+///
+/// The catalog is subscribed from the publisher on the first request and cached by the relay like any other track.
+/// While a new catalog is still arriving, the last complete one is served until the namespace is unannounced.
+/// A CBOR catalog, ex. of `moq-pub --catalog-format cbor`, is served as JSON as well.
+async fn serve_catalog(
+	Path(namespace): Path<String>,
+	Query(query): Query<CatalogQuery>,
+	State(store): State<Arc<RwLock<Store>>>,
+) -> Response {
+	let locals = store.read().await.locals.clone();
+	let name = query.track.as_deref().unwrap_or(CATALOG_TRACK);
+
+	let Some(Local {
+		mut tracks, catalogs, ..
+	}) = locals.get(&namespace)
+	else {
+		return error(StatusCode::NOT_FOUND, format!("{namespace} is not announced"));
+	};
+	let Some(track) = tracks.subscribe(name) else {
+		return error(
+			StatusCode::NOT_FOUND,
+			format!("{namespace} has no catalog track {name}"),
+		);
+	};
+
+	let cached = catalogs.lock().unwrap().get(name).cloned();
+
+	let newest = newest_object(track);
+	let res = match cached {
+		Some(cached) => newest.now_or_never().unwrap_or(Ok(cached)),
+		None => match tokio::time::timeout(CATALOG_TIMEOUT, newest).await {
+			Ok(res) => res,
+			Err(_) => {
+				let message = format!("no catalog of {namespace} within {CATALOG_TIMEOUT:?}");
+				return error(StatusCode::GATEWAY_TIMEOUT, message);
+			}
+		},
+	};
+
+	let catalog = match res {
+		Ok(catalog) => catalog,
+		Err(e) => {
+			let message = format!("{namespace} has no catalog track {name}: {e}");
+			return error(StatusCode::NOT_FOUND, message);
+		}
+	};
+	let catalog = match catalog_json(&catalog) {
+		Ok(catalog) => catalog,
+		Err(e) => return error(StatusCode::BAD_GATEWAY, format!("catalog of {namespace} {e}")),
+	};
+
+	catalogs.lock().unwrap().insert(name.to_string(), catalog.clone());
+
+	([(header::CONTENT_TYPE, "application/json")], catalog).into_response()
+}
+
+/// the encoded catalog as JSON, a CBOR one is transcoded
+fn catalog_json(catalog: &Bytes) -> Result<Bytes, String> {
+	match moq_catalog::CatalogFormat::sniff(catalog) {
+		Some(moq_catalog::CatalogFormat::Cbor) => {
+			let value: ciborium::Value =
+				ciborium::from_reader(&catalog[..]).map_err(|e| format!("is not CBOR: {e}"))?;
+			let json = serde_json::to_vec(&value).map_err(|e| format!("has no JSON form: {e}"))?;
+			Ok(json.into())
+		}
+		_ => match serde_json::from_slice::<serde_json::Value>(catalog) {
+			Ok(_) => Ok(catalog.clone()),
+			Err(e) => Err(format!("is not JSON: {e}")),
+		},
+	}
+}
+
+/// the first object of the newest group of `track`, once it is complete
+async fn newest_object(track: TrackReader) -> Result<Bytes, ServeError> {
+	let TrackReaderMode::Groups(mut groups) = track.mode().await? else {
+		return Err(ServeError::Mode);
+	};
+
+	let mut group = groups.next().await?.ok_or(ServeError::NotFound)?;
+	group.read_next().await?.ok_or(ServeError::NotFound)
+}
+
+/// `{ "error": ... }` with `status`
+fn error(status: StatusCode, error: String) -> Response {
+	(status, Json(serde_json::json!({ "error": error }))).into_response()
+}
+
+pub(crate) fn rfc3339(time: time::SystemTime) -> String {
+	chrono::DateTime::<chrono::Utc>::from(time).to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+}
+
+/// the limit currently applied
+async fn serve_bandwidth(State(store): State<Arc<RwLock<Store>>>) -> impl IntoResponse {
+	let limiter = {
+		let lock = store.read().await;
+		lock.limiter.clone()
+	};
+
+	let status = limiter.read().await.status();
+	Json(status)
+}
+
+/// the executed limiter schedule, newest last
+async fn serve_trajectory_log(State(store): State<Arc<RwLock<Store>>>) -> impl IntoResponse {
+	let limiter = {
+		let lock = store.read().await;
+		lock.limiter.clone()
+	};
+
+	let log = limiter.read().await.log();
+	Json(log)
+}
+
+/// the limiter status on success, otherwise `{ "error": ... }`
+async fn respond(limiter: Arc<RwLock<Limiter>>, res: anyhow::Result<()>, error: StatusCode) -> Response {
+	match res {
+		Ok(_) => Json(limiter.read().await.status()).into_response(),
+		Err(e) => (error, Json(serde_json::json!({ "error": format!("{e:#}") }))).into_response(),
+	}
+}
+
+/// limit the egress by default, `?direction=ingress` or `both` to limit the received traffic
 async fn post_set_bandwidth(
 	Path((kbps, latency)): Path<(i64, i64)>,
+	Query(query): Query<BandwidthQuery>,
 	State(store): State<Arc<RwLock<Store>>>,
 ) -> impl IntoResponse {
 	let limiter = {
@@ -82,10 +478,8 @@ async fn post_set_bandwidth(
 		lock.limiter.clone()
 	};
 
-	match set_bandwidth(limiter, kbps, latency).await {
-		Ok(_) => "ok",
-		Err(_) => "failed",
-	}
+	let res = set_bandwidth(limiter.clone(), kbps, latency, query.direction).await;
+	respond(limiter, res, StatusCode::INTERNAL_SERVER_ERROR).await
 }
 
 async fn post_remove_bandwidth(State(store): State<Arc<RwLock<Store>>>) -> impl IntoResponse {
@@ -94,8 +488,8 @@ async fn post_remove_bandwidth(State(store): State<Arc<RwLock<Store>>>) -> impl
 		lock.limiter.clone()
 	};
 
-	_ = unset_bandwidth(limiter).await;
-	"ok"
+	let res = unset_bandwidth(limiter.clone()).await;
+	respond(limiter, res, StatusCode::INTERNAL_SERVER_ERROR).await
 }
 
 async fn post_trajectory(
@@ -103,16 +497,718 @@ async fn post_trajectory(
 	Query(query): Query<TrajectoryQuery>,
 	Json(trajectory): Json<Vec<Trajectory>>,
 ) -> impl IntoResponse {
+	let (limiter, trajectory) = {
+		let lock = store.read().await;
+		let trajectory = load_trajectory(trajectory, query.mode.as_deref(), &lock.profiles);
+		(lock.limiter.clone(), trajectory)
+	};
+
+	let trajectory = match trajectory {
+		Ok(t) => t,
+		Err(e) => return respond(limiter, Err(e), StatusCode::BAD_REQUEST).await,
+	};
+	let start_at = match query.start_at.as_deref().map(chrono::DateTime::parse_from_rfc3339) {
+		None => None,
+		Some(Ok(at)) => Some(time::SystemTime::from(at)),
+		Some(Err(e)) => {
+			let e = anyhow::anyhow!("invalid start_at: {e}");
+			return respond(limiter, Err(e), StatusCode::BAD_REQUEST).await;
+		}
+	};
+
+	// replaces the running or scheduled trajectory
+	let l1 = limiter.clone();
+	let handle = tokio::spawn(set_trajectory(l1, trajectory, query.looping, start_at));
+
+	{
+		let mut lock = limiter.write().await;
+		lock.set_handle(handle);
+	}
+
+	respond(limiter, Ok(()), StatusCode::OK).await
+}
+
+/// abort the trajectory waiting for its start time, keeping the applied limit, 409 if none is scheduled
+async fn post_cancel_trajectory(State(store): State<Arc<RwLock<Store>>>) -> Response {
 	let limiter = {
 		let lock = store.read().await;
 		lock.limiter.clone()
 	};
 
-	let l1 = limiter.clone();
-	let handle = tokio::spawn(set_trajectory(l1, trajectory, Some(query)));
+	let res = cancel_trajectory(limiter.clone()).await;
+	respond(limiter, res, StatusCode::CONFLICT).await
+}
+
+/// apply the limit with loss, jitter and reordering until removed, invalid parameters fail with 400
+async fn post_impairment(
+	State(store): State<Arc<RwLock<Store>>>,
+	netem: Result<Json<Netem>, JsonRejection>,
+) -> Response {
+	let limiter = {
+		let lock = store.read().await;
+		lock.limiter.clone()
+	};
+
+	// negative values do not even deserialize
+	let netem = match netem {
+		Ok(Json(netem)) => netem,
+		Err(e) => return respond(limiter, Err(anyhow::anyhow!(e.body_text())), StatusCode::BAD_REQUEST).await,
+	};
+	if let Err(e) = validate_trajectory(&[netem.clone().into()]) {
+		return respond(limiter, Err(e), StatusCode::BAD_REQUEST).await;
+	}
 
-	let mut lock = limiter.write().await;
-	lock.set_handle(handle);
+	let res = set_impairment(limiter.clone(), netem).await;
+	respond(limiter, res, StatusCode::INTERNAL_SERVER_ERROR).await
+}
+
+/// the custom profiles by name
+async fn serve_profiles(State(store): State<Arc<RwLock<Store>>>) -> impl IntoResponse {
+	Json(store.read().await.profiles.clone())
+}
+
+async fn put_profile(
+	Path(name): Path<String>,
+	State(store): State<Arc<RwLock<Store>>>,
+	Json(trajectory): Json<Vec<Trajectory>>,
+) -> Response {
+	let res = match BUILTIN_PROFILES.contains(&name.as_str()) {
+		true => Err(anyhow::anyhow!("cannot replace the built-in profile {name}")),
+		false => validate_trajectory(&trajectory),
+	};
+	if let Err(e) = res {
+		return (
+			StatusCode::BAD_REQUEST,
+			Json(serde_json::json!({ "error": format!("{e:#}") })),
+		)
+			.into_response();
+	}
+
+	log::info!("stored trajectory profile {name}");
+	store.write().await.profiles.insert(name, trajectory.clone());
+
+	Json(trajectory).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn store(shaper: MockShaper) -> Arc<RwLock<Store>> {
+		let interfaces = Some(vec!["eth0".to_string()]);
+		Arc::new(RwLock::new(Store {
+			fingerprints: Vec::new(),
+			limiter: Arc::new(RwLock::new(Limiter::new(None, Arc::new(shaper), interfaces).unwrap())),
+			profiles: BTreeMap::new(),
+			locals: Locals::new(),
+			connections: Connections::new(),
+		}))
+	}
+
+	async fn json(response: Response) -> (StatusCode, serde_json::Value) {
+		let status = response.status();
+		let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+		(status, serde_json::from_slice(&body).unwrap())
+	}
+
+	#[tokio::test]
+	async fn test_status() {
+		let response = serve_bandwidth(State(store(MockShaper::default())))
+			.await
+			.into_response();
+		assert_eq!(
+			json(response).await,
+			(
+				StatusCode::OK,
+				serde_json::json!({
+					"active": false,
+					"limit_kbps": null,
+					"latency_ms": 50,
+					"trajectory_running": false,
+					"step_index": null,
+					"impairment": {},
+					"direction": null,
+					"scheduled_at": null,
+					"starts_in_ms": null,
+				})
+			)
+		);
+	}
+
+	#[tokio::test]
+	async fn test_trajectory_log() {
+		let store = store(MockShaper::default());
+		post_remove_bandwidth(State(store.clone())).await;
+
+		let response = serve_trajectory_log(State(store)).await.into_response();
+		let (status, body) = json(response).await;
+		assert_eq!(status, StatusCode::OK);
+		assert_eq!(body.as_array().unwrap().len(), 1);
+		assert_eq!(body[0]["event"], "abort");
+		assert_eq!(body[0]["limit_kbps"], serde_json::Value::Null);
+	}
+
+	#[tokio::test]
+	async fn test_empty_trajectory() {
+		let store = store(MockShaper::default());
+		let query = TrajectoryQuery {
+			looping: false,
+			mode: None,
+			start_at: None,
+		};
+
+		let response = post_trajectory(State(store.clone()), Query(query), Json(Vec::new()))
+			.await
+			.into_response();
+		let (status, body) = json(response).await;
+		assert_eq!(status, StatusCode::BAD_REQUEST);
+		assert_eq!(body["error"], "cannot set empty trajectory");
+
+		let limiter = store.read().await.limiter.clone();
+		assert!(!limiter.read().await.status().trajectory_running);
+	}
+
+	#[tokio::test]
+	async fn test_scheduled_trajectory() {
+		let store = store(MockShaper::default());
+		let trajectory = || serde_json::from_value(serde_json::json!([step(1000, 0)])).unwrap();
+		let start_at = chrono::Utc::now() + std::time::Duration::from_secs(3600);
+		let query = TrajectoryQuery {
+			looping: false,
+			mode: None,
+			start_at: Some(start_at.to_rfc3339()),
+		};
+		let response = post_trajectory(State(store.clone()), Query(query), Json(trajectory()))
+			.await
+			.into_response();
+		assert_eq!(response.status(), StatusCode::OK);
+
+		// shown as scheduled once the task is waiting
+		let limiter = store.read().await.limiter.clone();
+		let status = tokio::time::timeout(std::time::Duration::from_secs(1), async {
+			loop {
+				let status = limiter.read().await.status();
+				if status.scheduled_at.is_some() {
+					break status;
+				}
+				tokio::task::yield_now().await;
+			}
+		})
+		.await
+		.unwrap();
+		assert_eq!(status.scheduled_at, Some(rfc3339(start_at.into())));
+		assert!(status.starts_in_ms.unwrap() > 3_500_000);
+		assert!(!status.active && !status.trajectory_running);
+
+		let (status, body) = json(post_cancel_trajectory(State(store.clone())).await).await;
+		assert_eq!(status, StatusCode::OK);
+		assert_eq!(body["scheduled_at"], serde_json::Value::Null);
+
+		// nothing left to cancel
+		let (status, body) = json(post_cancel_trajectory(State(store.clone())).await).await;
+		assert_eq!(status, StatusCode::CONFLICT);
+		assert_eq!(body["error"], "no trajectory scheduled");
+
+		let query = TrajectoryQuery {
+			looping: false,
+			mode: None,
+			start_at: Some("tomorrow".to_string()),
+		};
+		let response = post_trajectory(State(store), Query(query), Json(trajectory()))
+			.await
+			.into_response();
+		let (status, body) = json(response).await;
+		assert_eq!(status, StatusCode::BAD_REQUEST);
+		assert!(
+			body["error"].as_str().unwrap().starts_with("invalid start_at"),
+			"{body}"
+		);
+	}
+
+	#[tokio::test]
+	async fn test_set_bandwidth() {
+		let store = store(MockShaper::default());
+		let response = post_set_bandwidth(Path((1000, 20)), Query(Default::default()), State(store.clone()))
+			.await
+			.into_response();
+		let (status, body) = json(response).await;
+		assert_eq!(status, StatusCode::OK);
+		assert_eq!(body["active"], true);
+		assert_eq!(body["limit_kbps"], 1000);
+		assert_eq!(body["latency_ms"], 20);
+		assert_eq!(body["step_index"], 0);
+		assert_eq!(body["direction"], "egress");
+
+		let query = BandwidthQuery {
+			direction: Direction::Ingress,
+		};
+		let response = post_set_bandwidth(Path((500, 20)), Query(query), State(store.clone()))
+			.await
+			.into_response();
+		assert_eq!(json(response).await.1["direction"], "ingress");
+
+		let response = post_remove_bandwidth(State(store)).await.into_response();
+		let (status, body) = json(response).await;
+		assert_eq!(status, StatusCode::OK);
+		assert_eq!(body["active"], false);
+	}
+
+	#[tokio::test]
+	async fn test_shaper_failure() {
+		let store = store(MockShaper {
+			fail: true,
+			..Default::default()
+		});
+		let response = post_set_bandwidth(Path((1000, 20)), Query(Default::default()), State(store))
+			.await
+			.into_response();
+		let (status, body) = json(response).await;
+		assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+		assert_eq!(body["error"], "mock failure");
+	}
+
+	#[tokio::test]
+	async fn test_impairment() {
+		let store = store(MockShaper::default());
+		let netem = serde_json::json!({ "limit": 1000, "latency": 20, "loss_percent": 2.5, "jitter_ms": 5 });
+
+		let response = post_impairment(State(store.clone()), Ok(Json(serde_json::from_value(netem).unwrap()))).await;
+		let (status, body) = json(response).await;
+		assert_eq!(status, StatusCode::OK);
+		assert_eq!(body["limit_kbps"], 1000);
+		assert_eq!(
+			body["impairment"],
+			serde_json::json!({ "loss_percent": 2.5, "jitter_ms": 5 })
+		);
+
+		let netem = serde_json::json!({ "limit": 1000, "loss_percent": 101 });
+		let response = post_impairment(State(store.clone()), Ok(Json(serde_json::from_value(netem).unwrap()))).await;
+		let (status, body) = json(response).await;
+		assert_eq!(status, StatusCode::BAD_REQUEST);
+		assert_eq!(body["error"], "step 0: loss_percent must be between 0 and 100");
+
+		// the previous impairment is still active
+		let response = serve_bandwidth(State(store)).await.into_response();
+		assert_eq!(json(response).await.1["impairment"]["jitter_ms"], 5);
+	}
+
+	#[tokio::test]
+	async fn test_broadcasts() {
+		let store = store(MockShaper::default());
+		let mut locals = store.read().await.locals.clone();
+
+		let response = serve_broadcasts(State(store.clone())).await.into_response();
+		assert_eq!(json(response).await, (StatusCode::OK, serde_json::json!([])));
+
+		// registered like an announce by a publisher session
+		let (_, mut request, mut reader) = moq_transport::serve::Tracks::new("live".to_string()).produce();
+		let registration = locals.register(reader.clone()).await.unwrap();
+
+		// one track is subscribed and served, the other is still pending
+		reader.subscribe("video").unwrap();
+		reader.subscribe("audio").unwrap();
+		let mut groups = request.next().await.unwrap().groups().unwrap();
+		let mut group = groups.append(0).unwrap();
+		group.write(b"frame".to_vec().into()).unwrap();
+
+		let response = serve_broadcasts(State(store.clone())).await.into_response();
+		let (status, body) = json(response).await;
+		assert_eq!(status, StatusCode::OK);
+		assert_eq!(body[0]["namespace"], "live");
+		assert_eq!(body[0]["tracks"], serde_json::json!(["audio", "video"]));
+		let since = chrono::DateTime::parse_from_rfc3339(body[0]["since"].as_str().unwrap()).unwrap();
+		assert!((chrono::Utc::now() - since.with_timezone(&chrono::Utc)).num_seconds() < 5);
+
+		let response = serve_broadcast(Path("live".to_string()), State(store.clone())).await;
+		let (status, body) = json(response).await;
+		assert_eq!(status, StatusCode::OK);
+		assert_eq!(
+			body["tracks"],
+			serde_json::json!([
+				{ "name": "audio", "latest_group": null, "latest_object": null },
+				{ "name": "video", "latest_group": 0, "latest_object": 0 },
+			])
+		);
+
+		// gone once the publisher disconnects
+		drop(registration);
+		let response = serve_broadcasts(State(store.clone())).await.into_response();
+		assert_eq!(json(response).await, (StatusCode::OK, serde_json::json!([])));
+
+		let response = serve_broadcast(Path("live".to_string()), State(store)).await;
+		assert_eq!(response.status(), StatusCode::NOT_FOUND);
+	}
+
+	async fn catalog(store: &Arc<RwLock<Store>>, namespace: &str, track: Option<&str>) -> Response {
+		let query = CatalogQuery {
+			track: track.map(str::to_string),
+		};
+		serve_catalog(Path(namespace.to_string()), Query(query), State(store.clone())).await
+	}
+
+	#[tokio::test]
+	async fn test_catalog() {
+		let store = store(MockShaper::default());
+		let mut locals = store.read().await.locals.clone();
+
+		let response = catalog(&store, "live", None).await;
+		assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+		// registered like an announce by a publisher session, which serves the catalog once subscribed
+		let (_, mut request, reader) = moq_transport::serve::Tracks::new("live".to_string()).produce();
+		let _registration = locals.register(reader).await.unwrap();
+		let serve = tokio::spawn(async move {
+			let track = request.next().await.unwrap();
+			assert_eq!(track.name, CATALOG_TRACK);
+			let mut groups = track.groups().unwrap();
+			groups.append(0).unwrap().write(r#"{"version":1}"#.into()).unwrap();
+			groups
+		});
 
-	"ok"
+		let response = catalog(&store, "live", None).await;
+		assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+		assert_eq!(
+			json(response).await,
+			(StatusCode::OK, serde_json::json!({ "version": 1 }))
+		);
+
+		// a catalog still arriving is not served yet
+		let mut groups = serve.await.unwrap();
+		let mut object = groups.append(0).unwrap().create(13).unwrap();
+		object.write(r#"{"vers"#.into()).unwrap();
+		let response = catalog(&store, "live", None).await;
+		assert_eq!(json(response).await.1, serde_json::json!({ "version": 1 }));
+
+		object.write(r#"ion":2}"#.into()).unwrap();
+		drop(object);
+		let response = catalog(&store, "live", None).await;
+		assert_eq!(json(response).await.1, serde_json::json!({ "version": 2 }));
+
+		groups.append(0).unwrap().write("not json".into()).unwrap();
+		let response = catalog(&store, "live", None).await;
+		assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+
+		// the publisher of another namespace has no catalog track
+		let (_, mut request, reader) = moq_transport::serve::Tracks::new("raw".to_string()).produce();
+		let _registration = locals.register(reader).await.unwrap();
+		tokio::spawn(async move {
+			let track = request.next().await.unwrap();
+			track.close(ServeError::NotFound).unwrap();
+		});
+
+		let response = catalog(&store, "raw", None).await;
+		let (status, body) = json(response).await;
+		assert_eq!(status, StatusCode::NOT_FOUND);
+		assert_eq!(body["error"], "raw has no catalog track .catalog: not found");
+	}
+
+	#[tokio::test]
+	async fn test_catalog_track() {
+		let store = store(MockShaper::default());
+		let mut locals = store.read().await.locals.clone();
+
+		let (_, mut request, reader) = moq_transport::serve::Tracks::new("live".to_string()).produce();
+		let registration = locals.register(reader).await.unwrap();
+		let serve = tokio::spawn(async move {
+			let track = request.next().await.unwrap();
+			assert_eq!(track.name, ".catalog.video");
+			let mut groups = track.groups().unwrap();
+			groups.append(0).unwrap().write(r#"{"version":1}"#.into()).unwrap();
+			groups
+		});
+
+		let response = catalog(&store, "live", Some(".catalog.video")).await;
+		assert_eq!(
+			json(response).await,
+			(StatusCode::OK, serde_json::json!({ "version": 1 }))
+		);
+		let _groups = serve.await.unwrap();
+
+		// the cached catalogs are dropped with the announce
+		let cached = locals.get("live").unwrap().catalogs;
+		assert_eq!(cached.lock().unwrap().len(), 1);
+		drop(registration);
+		assert_eq!(Arc::strong_count(&cached), 1);
+
+		let (_, _, reader) = moq_transport::serve::Tracks::new("live".to_string()).produce();
+		let _registration = locals.register(reader).await.unwrap();
+		assert!(locals.get("live").unwrap().catalogs.lock().unwrap().is_empty());
+	}
+
+	fn fixture(name: &str) -> std::path::PathBuf {
+		std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+			.join("tests/fixtures")
+			.join(name)
+	}
+
+	/// status and body of `GET path`, the connection is closed afterwards
+	async fn get<S>(mut stream: S, path: &str) -> (u16, String)
+	where
+		S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+	{
+		use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+		let request = format!("GET {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+		stream.write_all(request.as_bytes()).await.unwrap();
+
+		let mut response = String::new();
+		stream.read_to_string(&mut response).await.unwrap();
+		let (head, body) = response.split_once("\r\n\r\n").unwrap();
+		let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+		(status, body.to_string())
+	}
+
+	#[tokio::test]
+	async fn test_fingerprints() {
+		let tls = moq_native::tls::Args {
+			cert: vec![fixture("localhost.crt"), fixture("localhost_rsa.crt")],
+			key: vec![fixture("localhost.key"), fixture("localhost_rsa.key")],
+			root: vec![fixture("localhost.crt")],
+			disable_verify: true,
+		}
+		.load()
+		.unwrap();
+
+		let mut web = Web::new(WebConfig {
+			public_bind: "127.0.0.1:0".parse().unwrap(),
+			http_bind: Some("127.0.0.1:0".parse().unwrap()),
+			admin_bind: None,
+			admin_token: None,
+			tls: tls.clone(),
+			limit_interfaces: Some(vec!["eth0".to_string()]),
+			locals: Locals::new(),
+			connections: Connections::new(),
+			limiter_log: None,
+			prefix: String::new(),
+			allow_origins: Vec::new(),
+		});
+		let (https, http) = (axum_server::Handle::new(), axum_server::Handle::new());
+		web.server = web.server.handle(https.clone());
+		web.http = web.http.map(|server| server.handle(http.clone()));
+		let server = tokio::spawn(web.run());
+		let (https, http) = (https.listening().await.unwrap(), http.listening().await.unwrap());
+
+		let connector = tokio_rustls::TlsConnector::from(Arc::new(tls.client));
+		let connect = || async {
+			let stream = tokio::net::TcpStream::connect(https).await.unwrap();
+			let name = rustls::ServerName::try_from("localhost").unwrap();
+			connector.connect(name, stream).await.unwrap()
+		};
+
+		// the first certificate for backwards compatibility
+		let (status, body) = get(connect().await, "/fingerprint").await;
+		assert_eq!((status, body.as_str()), (200, tls.fingerprints[0].sha256.as_str()));
+
+		let expected = serde_json::json!([
+			{ "algorithm": "ecdsa", "sha256": tls.fingerprints[0].sha256 },
+			{ "algorithm": "rsa", "sha256": tls.fingerprints[1].sha256 },
+		]);
+		assert_eq!(tls.fingerprints[0].sha256.len(), 64);
+		assert_ne!(tls.fingerprints[0].sha256, tls.fingerprints[1].sha256);
+
+		// the same routes with and without TLS
+		let (status, body) = get(connect().await, "/fingerprints").await;
+		assert_eq!(status, 200);
+		assert_eq!(serde_json::from_str::<serde_json::Value>(&body).unwrap(), expected);
+
+		let (status, body) = get(tokio::net::TcpStream::connect(http).await.unwrap(), "/fingerprints").await;
+		assert_eq!(status, 200);
+		assert_eq!(serde_json::from_str::<serde_json::Value>(&body).unwrap(), expected);
+
+		let (status, body) = get(tokio::net::TcpStream::connect(http).await.unwrap(), "/fingerprint").await;
+		assert_eq!((status, body.as_str()), (200, tls.fingerprints[0].sha256.as_str()));
+
+		server.abort();
+	}
+
+	/// status and CORS origin of `GET path` from `origin`
+	async fn get_from(router: Router, path: &str, origin: &str) -> (StatusCode, Option<String>) {
+		use tower::ServiceExt;
+
+		let request = axum::http::Request::get(path)
+			.header(header::ORIGIN, origin)
+			.body(axum::body::Body::empty())
+			.unwrap();
+		let response = router.oneshot(request).await.unwrap();
+		let allowed = response
+			.headers()
+			.get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+			.map(|origin| origin.to_str().unwrap().to_string());
+		(response.status(), allowed)
+	}
+
+	#[tokio::test]
+	async fn test_prefix() {
+		let origin = "https://player.example";
+		let root = || router(store(MockShaper::default()), Routes::All, None, "", &[]);
+		assert_eq!(
+			get_from(root(), "/bandwidth", origin).await,
+			(StatusCode::OK, Some("*".to_string()))
+		);
+		assert_eq!(
+			get_from(root(), "/moq/bandwidth", origin).await.0,
+			StatusCode::NOT_FOUND
+		);
+
+		// a trailing slash is ignored, only the listed origins are allowed
+		let origins = [HeaderValue::from_static(origin)];
+		let nested = || router(store(MockShaper::default()), Routes::All, None, "/moq/", &origins);
+		assert_eq!(
+			get_from(nested(), "/moq/bandwidth", origin).await,
+			(StatusCode::OK, Some(origin.to_string()))
+		);
+		assert_eq!(get_from(nested(), "/moq/broadcasts", origin).await.0, StatusCode::OK);
+		assert_eq!(get_from(nested(), "/bandwidth", origin).await.0, StatusCode::NOT_FOUND);
+		assert_eq!(
+			get_from(nested(), "/moq/bandwidth", "https://other.example").await,
+			(StatusCode::OK, None)
+		);
+	}
+
+	/// status of `GET path`, with the bearer `token` if given
+	async fn get_with(router: Router, path: &str, token: Option<&str>) -> StatusCode {
+		use tower::ServiceExt;
+
+		let mut request = axum::http::Request::get(path);
+		if let Some(token) = token {
+			request = request.header(header::AUTHORIZATION, format!("Bearer {token}"));
+		}
+		let request = request.body(axum::body::Body::empty()).unwrap();
+		router.oneshot(request).await.unwrap().status()
+	}
+
+	#[tokio::test]
+	async fn test_admin() {
+		let public = || router(store(MockShaper::default()), Routes::Public, Some("secret"), "", &[]);
+		assert_eq!(get_with(public(), "/broadcasts", None).await, StatusCode::OK);
+		assert_eq!(get_with(public(), "/bandwidth", None).await, StatusCode::NOT_FOUND);
+		assert_eq!(
+			get_with(public(), "/bandwidth", Some("secret")).await,
+			StatusCode::NOT_FOUND
+		);
+
+		let admin = || router(store(MockShaper::default()), Routes::Admin, Some("secret"), "", &[]);
+		assert_eq!(get_with(admin(), "/bandwidth", None).await, StatusCode::UNAUTHORIZED);
+		assert_eq!(
+			get_with(admin(), "/bandwidth", Some("wrong")).await,
+			StatusCode::UNAUTHORIZED
+		);
+		assert_eq!(get_with(admin(), "/bandwidth", Some("secret")).await, StatusCode::OK);
+		assert_eq!(
+			get_with(admin(), "/trajectory/log", None).await,
+			StatusCode::UNAUTHORIZED
+		);
+		assert_eq!(
+			get_with(admin(), "/broadcasts", Some("secret")).await,
+			StatusCode::NOT_FOUND
+		);
+
+		// the sessions are listed by the relay, none here
+		assert_eq!(get_with(admin(), "/connections", None).await, StatusCode::UNAUTHORIZED);
+		assert_eq!(get_with(admin(), "/connections", Some("secret")).await, StatusCode::OK);
+		assert_eq!(
+			get_with(admin(), "/connections/1", Some("secret")).await,
+			StatusCode::NOT_FOUND
+		);
+		assert_eq!(
+			get_with(public(), "/connections", Some("secret")).await,
+			StatusCode::NOT_FOUND
+		);
+	}
+
+	#[tokio::test]
+	async fn test_shutdown() {
+		let tls = moq_native::tls::Args {
+			cert: vec![fixture("localhost.crt")],
+			key: vec![fixture("localhost.key")],
+			root: Vec::new(),
+			disable_verify: true,
+		}
+		.load()
+		.unwrap();
+
+		let web = Web::new(WebConfig {
+			public_bind: "127.0.0.1:0".parse().unwrap(),
+			http_bind: None,
+			admin_bind: None,
+			admin_token: None,
+			tls,
+			limit_interfaces: Some(vec!["eth0".to_string()]),
+			locals: Locals::new(),
+			connections: Connections::new(),
+			limiter_log: None,
+			prefix: "/moq".to_string(),
+			allow_origins: Vec::new(),
+		});
+		let handle = web.shutdown_handle();
+		let server = tokio::spawn(web.run());
+		let bind = handle.listening().await.unwrap();
+
+		handle.graceful_shutdown(Some(time::Duration::from_secs(1)));
+		let res = tokio::time::timeout(time::Duration::from_secs(5), server).await;
+		res.expect("run did not resolve").unwrap().unwrap();
+
+		assert!(tokio::net::TcpStream::connect(bind).await.is_err());
+	}
+
+	fn step(limit: u32, duration: u32) -> serde_json::Value {
+		serde_json::json!({ "limit": limit, "duration": duration, "latency": 10 })
+	}
+
+	#[tokio::test]
+	async fn test_profiles() {
+		let store = store(MockShaper::default());
+		let profile: Vec<Trajectory> = serde_json::from_value(serde_json::json!([step(500, 0)])).unwrap();
+
+		let response = put_profile(Path("slow".to_string()), State(store.clone()), Json(profile.clone())).await;
+		assert_eq!(response.status(), StatusCode::OK);
+
+		// built-in names are reserved and invalid profiles rejected
+		let response = put_profile(Path("cascade".to_string()), State(store.clone()), Json(profile.clone())).await;
+		assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+		let response = put_profile(Path("empty".to_string()), State(store.clone()), Json(Vec::new())).await;
+		assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+		let response = serve_profiles(State(store.clone())).await.into_response();
+		assert_eq!(
+			json(response).await,
+			(StatusCode::OK, serde_json::json!({ "slow": [step(500, 0)] }))
+		);
+
+		// the stored profile is resolved by name
+		let query = TrajectoryQuery {
+			looping: false,
+			mode: Some("slow".to_string()),
+			start_at: None,
+		};
+		let body: Vec<Trajectory> = serde_json::from_value(serde_json::json!([step(1000, 0)])).unwrap();
+		let response = post_trajectory(State(store.clone()), Query(query), Json(body))
+			.await
+			.into_response();
+		assert_eq!(response.status(), StatusCode::OK);
+
+		let limiter = store.read().await.limiter.clone();
+		let status = tokio::time::timeout(std::time::Duration::from_secs(1), async {
+			loop {
+				let status = limiter.read().await.status();
+				if status.active {
+					break status;
+				}
+				tokio::task::yield_now().await;
+			}
+		})
+		.await
+		.unwrap();
+		assert_eq!(status.limit_kbps, Some(500));
+
+		let query = TrajectoryQuery {
+			looping: false,
+			mode: Some("missing".to_string()),
+			start_at: None,
+		};
+		let response = post_trajectory(State(store), Query(query), Json(Vec::new()))
+			.await
+			.into_response();
+		assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+	}
 }
@@ -24,7 +24,11 @@ async fn main() -> anyhow::Result<()> {
 
 	let tls = config.tls.load()?;
 
-	let quic = quic::Endpoint::new(quic::Config { bind: config.bind, tls })?;
+	let quic = quic::Endpoint::new(quic::Config {
+		bind: config.bind,
+		tls,
+		transport: Default::default(),
+	})?;
 
 	let session = quic.client.connect(&config.url).await?;
 
@@ -19,7 +19,13 @@ use super::{
 	ObjectsWriter, ServeError, Stream, StreamReader, StreamWriter,
 };
 use paste::paste;
-use std::{ops::Deref, sync::Arc};
+use std::{
+	ops::Deref,
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc,
+	},
+};
 
 /// Static information about a track.
 #[derive(Debug, Clone, PartialEq)]
@@ -47,6 +53,7 @@ impl Track {
 struct TrackState {
 	mode: Option<TrackReaderMode>,
 	closed: Result<(), ServeError>,
+	subscribers: Subscribers,
 }
 
 impl Default for TrackState {
@@ -54,10 +61,35 @@ impl Default for TrackState {
 		Self {
 			mode: None,
 			closed: Ok(()),
+			subscribers: Subscribers::default(),
 		}
 	}
 }
 
+/// The number of subscriptions currently served from a track, see [TrackWriter::subscribers].
+#[derive(Clone, Debug, Default)]
+pub struct Subscribers {
+	count: Arc<AtomicUsize>,
+}
+
+impl Subscribers {
+	pub fn count(&self) -> usize {
+		self.count.load(Ordering::Relaxed)
+	}
+}
+
+/// Counts towards the [Subscribers] of a track until dropped, see [TrackReader::subscription].
+#[derive(Debug)]
+pub struct Subscription {
+	count: Arc<AtomicUsize>,
+}
+
+impl Drop for Subscription {
+	fn drop(&mut self) {
+		self.count.fetch_sub(1, Ordering::Relaxed);
+	}
+}
+
 /// Creates new streams for a track.
 pub struct TrackWriter {
 	state: State<TrackState>,
@@ -115,6 +147,11 @@ impl TrackWriter {
 		Ok(writer)
 	}
 
+	/// The subscriptions served from the track, still counted once the writer picked a mode.
+	pub fn subscribers(&self) -> Subscribers {
+		self.state.lock().subscribers.clone()
+	}
+
 	/// Close the track with an error.
 	pub fn close(self, err: ServeError) -> Result<(), ServeError> {
 		let state = self.state.lock();
@@ -185,6 +222,13 @@ impl TrackReader {
 			.await;
 		}
 	}
+
+	/// Count as a subscriber of the track until the [Subscription] is dropped.
+	pub fn subscription(&self) -> Subscription {
+		let count = self.state.lock().subscribers.count.clone();
+		count.fetch_add(1, Ordering::Relaxed);
+		Subscription { count }
+	}
 }
 
 impl Deref for TrackReader {
@@ -163,6 +163,11 @@ impl TracksReader {
 
 		Some(track.1.clone())
 	}
+
+	/// The tracks created or requested so far, without requesting any.
+	pub fn tracks(&self) -> Vec<TrackReader> {
+		self.state.lock().tracks.values().cloned().collect()
+	}
 }
 
 impl Deref for TracksReader {
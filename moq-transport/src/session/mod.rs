@@ -3,6 +3,7 @@ mod announced;
 mod error;
 mod publisher;
 mod reader;
+mod streams;
 mod subscribe;
 mod subscribed;
 mod subscriber;
@@ -12,6 +13,7 @@ pub use announce::*;
 pub use announced::*;
 pub use error::*;
 pub use publisher::*;
+pub use streams::*;
 pub use subscribe::*;
 pub use subscribed::*;
 pub use subscriber::*;
@@ -36,6 +38,8 @@ pub struct Session {
 	subscriber: Option<Subscriber>,
 
 	outgoing: Queue<Message>,
+
+	streams: Streams,
 }
 
 impl Session {
@@ -46,9 +50,10 @@ impl Session {
 		role: setup::Role,
 	) -> (Self, Option<Publisher>, Option<Subscriber>) {
 		let outgoing = Queue::default().split();
+		let streams = Streams::default();
 		let publisher = role
 			.is_publisher()
-			.then(|| Publisher::new(outgoing.0.clone(), webtransport.clone()));
+			.then(|| Publisher::new(outgoing.0.clone(), webtransport.clone(), streams.clone()));
 		let subscriber = role.is_subscriber().then(|| Subscriber::new(outgoing.0));
 
 		let session = Self {
@@ -58,6 +63,7 @@ impl Session {
 			publisher: publisher.clone(),
 			subscriber: subscriber.clone(),
 			outgoing: outgoing.1,
+			streams,
 		};
 
 		(session, publisher, subscriber)
@@ -160,11 +166,16 @@ impl Session {
 		Ok(Session::new(session, sender, recver, role))
 	}
 
+	/// the data streams open on this session, usable after [Self::run] consumed it
+	pub fn streams(&self) -> Streams {
+		self.streams.clone()
+	}
+
 	pub async fn run(self) -> Result<(), SessionError> {
 		tokio::select! {
 			res = Self::run_recv(self.recver, self.publisher, self.subscriber.clone()) => res,
 			res = Self::run_send(self.sender, self.outgoing) => res,
-			res = Self::run_streams(self.webtransport.clone(), self.subscriber.clone()) => res,
+			res = Self::run_streams(self.webtransport.clone(), self.subscriber.clone(), self.streams) => res,
 			res = Self::run_datagrams(self.webtransport, self.subscriber) => res,
 		}
 	}
@@ -217,6 +228,7 @@ impl Session {
 	async fn run_streams(
 		mut webtransport: web_transport::Session,
 		subscriber: Option<Subscriber>,
+		streams: Streams,
 	) -> Result<(), SessionError> {
 		let mut tasks = FuturesUnordered::new();
 
@@ -225,8 +237,10 @@ impl Session {
 				res = webtransport.accept_uni() => {
 					let stream = res?;
 					let subscriber = subscriber.clone().ok_or(SessionError::RoleViolation)?;
+					let open = streams.start();
 
 					tasks.push(async move {
+						let _open = open;
 						if let Err(err) = Subscriber::recv_stream(subscriber, stream).await {
 							log::warn!("failed to serve stream: {}", err);
 						};
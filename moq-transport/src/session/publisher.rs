@@ -13,7 +13,7 @@ use crate::{
 
 use crate::watch::Queue;
 
-use super::{Announce, AnnounceRecv, Session, SessionError, Subscribed, SubscribedRecv};
+use super::{Announce, AnnounceRecv, Session, SessionError, StreamGuard, Streams, Subscribed, SubscribedRecv};
 
 // TODO remove Clone.
 #[derive(Clone)]
@@ -25,16 +25,19 @@ pub struct Publisher {
 	unknown: Queue<Subscribed>,
 
 	outgoing: Queue<Message>,
+
+	streams: Streams,
 }
 
 impl Publisher {
-	pub(crate) fn new(outgoing: Queue<Message>, webtransport: web_transport::Session) -> Self {
+	pub(crate) fn new(outgoing: Queue<Message>, webtransport: web_transport::Session, streams: Streams) -> Self {
 		Self {
 			webtransport,
 			announces: Default::default(),
 			subscribed: Default::default(),
 			unknown: Default::default(),
 			outgoing,
+			streams,
 		}
 	}
 
@@ -203,8 +206,10 @@ impl Publisher {
 		self.announces.lock().unwrap().remove(namespace);
 	}
 
-	pub(super) async fn open_uni(&mut self) -> Result<web_transport::SendStream, SessionError> {
-		Ok(self.webtransport.open_uni().await?)
+	/// the stream is counted as open until the guard is dropped
+	pub(super) async fn open_uni(&mut self) -> Result<(web_transport::SendStream, StreamGuard), SessionError> {
+		let stream = self.webtransport.open_uni().await?;
+		Ok((stream, self.streams.start()))
 	}
 
 	pub(super) async fn send_datagram(&mut self, data: bytes::Bytes) -> Result<(), SessionError> {
@@ -0,0 +1,31 @@
+use std::sync::{
+	atomic::{AtomicUsize, Ordering},
+	Arc,
+};
+
+/// Counts the data streams of a session that are currently open, in both directions.
+#[derive(Clone, Default)]
+pub struct Streams {
+	open: Arc<AtomicUsize>,
+}
+
+impl Streams {
+	/// the streams opened or accepted and not yet finished
+	pub fn open(&self) -> usize {
+		self.open.load(Ordering::Relaxed)
+	}
+
+	/// count a stream until the returned guard is dropped
+	pub(super) fn start(&self) -> StreamGuard {
+		self.open.fetch_add(1, Ordering::Relaxed);
+		StreamGuard(self.clone())
+	}
+}
+
+pub(super) struct StreamGuard(Streams);
+
+impl Drop for StreamGuard {
+	fn drop(&mut self) {
+		self.0.open.fetch_sub(1, Ordering::Relaxed);
+	}
+}
@@ -78,6 +78,9 @@ impl Subscribed {
 	}
 
 	async fn serve_inner(&mut self, track: serve::TrackReader) -> Result<(), SessionError> {
+		// the writer may skip the track while nobody is subscribed
+		let _subscription = track.subscription();
+
 		let latest = track.latest();
 		self.state.lock_mut().ok_or(ServeError::Cancel)?.max = latest;
 
@@ -159,7 +162,7 @@ impl Drop for Subscribed {
 
 impl Subscribed {
 	async fn serve_track(&mut self, mut track: serve::StreamReader) -> Result<(), SessionError> {
-		let mut stream = self.publisher.open_uni().await?;
+		let (mut stream, _open) = self.publisher.open_uni().await?;
 
 		// TODO figure out u32 vs u64 priority
 		stream.set_priority(track.priority as i32);
@@ -247,7 +250,7 @@ impl Subscribed {
 		mut publisher: Publisher,
 		state: State<SubscribedState>,
 	) -> Result<(), SessionError> {
-		let mut stream = publisher.open_uni().await?;
+		let (mut stream, _open) = publisher.open_uni().await?;
 
 		// TODO figure out u32 vs u64 priority
 		stream.set_priority(group.priority as i32);
@@ -332,7 +335,7 @@ impl Subscribed {
 			.ok_or(ServeError::Done)?
 			.update_max(object.group_id, object.object_id)?;
 
-		let mut stream = publisher.open_uni().await?;
+		let (mut stream, _open) = publisher.open_uni().await?;
 
 		// TODO figure out u32 vs u64 priority
 		stream.set_priority(object.priority as i32);